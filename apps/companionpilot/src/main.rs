@@ -1,18 +1,62 @@
-use std::sync::Arc;
+use std::{collections::HashSet, net::SocketAddr, path::Path, sync::Arc};
 
+use axum::serve::ListenerExt;
 use companionpilot_core::{
+    alerts::{
+        AdminAlert, AdminAlerts, AlertPolicy, AlertSink, ChannelAlertSink, WebhookAlertSink,
+        run_alert_dispatcher,
+    },
+    ambient::{AmbientContext, parse_ambient_topics},
+    background_tasks::BackgroundTasks,
+    builder::CompanionPilot,
+    calendar::CalendarFeeds,
+    capabilities::DeploymentInfo,
+    concurrency::ConversationSequencing,
     config::AppConfig,
-    discord_bot,
-    http::{self, AppState},
-    memory::{InMemoryMemoryStore, MemoryStore, PostgresMemoryStore},
-    model::{MockModelProvider, ModelProvider, OpenRouterProvider},
-    orchestrator::DefaultChatOrchestrator,
-    safety::SafetyPolicy,
+    context_prefetch::DEFAULT_PREFETCH_TTL,
+    context_window::ContextWindows,
+    coordination::{InstanceCoordinator, RedisCoordinator},
+    date_greetings::DateGreetingConfig,
+    discord_bot::{self, DiscordBotOptions},
+    doctor::{self, CheckStatus},
+    events::EventMappings,
+    fact_backfill::{FactBackfillConfig, start_fact_backfill_job},
+    guild_settings::{ActivationRules, CitationStyle, GuildSettings},
+    http::apply_http_config,
+    in_flight::InFlightRecovery,
+    memory::{
+        InMemoryMemoryStore, MemorySnapshot, MemoryStore, PostgresMemoryStore,
+        start_hard_delete_job,
+    },
+    memory_review::MemoryReviewConfig,
+    model::{
+        GenerationParams, MockModelProvider, ModelProvider, OpenRouterProvider,
+        SeededMockModelProvider,
+    },
+    model_probe::ModelProbeConfig,
+    mqtt::{mqtt_options, run_mqtt_subscriber},
+    orchestrator::OrchestratorConfig,
+    outbox::start_outbox_job,
+    personas::PersonaRegistry,
+    prompt_rollout::RolloutPolicy,
+    proxy::split_list,
+    quotas::parse_tool_quotas,
+    redaction::{Redactor, parse_redaction_kinds},
+    routing::ModelRoutes,
+    tls::{ReloadingCert, TlsListener},
+    tool_macros::ToolMacroRegistry,
+    tool_output::parse_tool_output_budgets,
+    tool_stats::ToolStatsConfig,
     tools::{
-        CurrentDateTimeTool, SpotifyPlayingStatusTool, TavilyWebSearchTool, ToolExecutor,
-        ToolRegistry,
+        BraveSearchProvider, ChatHistorySearchTool, CrossEncoderReranker, CurrentDateTimeTool,
+        DEFAULT_EMBEDDING_URL, DEFAULT_OCR_URL, DeepResearchTool, EmbeddingReranker,
+        MockToolExecutor, OcrBackend, OcrImageTool, RerankMode, Reranker, ResearchBudget,
+        SearchMode, SearxngSearchProvider, SerpApiSearchProvider, SpotifyPlayingStatusTool,
+        TavilySearchProvider, ToolExecutor, ToolRegistry, WebSearchProvider, WebSearchTool,
     },
+    user_keys::UserKeyVault,
     voice::{VoiceManager, VoiceRuntimeConfig},
+    widgets::WidgetRegistry,
 };
 use tokio::net::TcpListener;
 use tracing::{info, warn};
@@ -23,31 +67,126 @@ async fn main() -> anyhow::Result<()> {
     init_tracing();
 
     let config = AppConfig::from_env()?;
+    if let Err(report) = config.validate() {
+        for problem in &report.problems {
+            warn!(
+                setting = problem.setting,
+                "configuration problem: {}", problem.message
+            );
+        }
+    }
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(command) = args.first() {
+        return run_command(&config, command, &args[1..]).await;
+    }
 
     let model = build_model_provider(&config);
     let memory = build_memory_store(&config).await?;
     let voice = build_voice_manager(&config);
-    let tools = build_tools(&config, voice.clone());
-
-    let memory_for_dashboard = memory.clone();
-    let orchestrator = Arc::new(DefaultChatOrchestrator::new(
-        model,
-        memory,
-        tools,
-        SafetyPolicy::default(),
-    ));
-    if let Some(voice_manager) = &voice {
-        voice_manager.set_orchestrator(orchestrator.clone()).await;
-        voice_manager.start_idle_reaper();
+    let tools = build_tools(&config, model.clone(), memory.clone(), voice.clone());
+
+    start_hard_delete_job(memory.clone());
+    start_outbox_job(memory.clone());
+    let (admin_alerts, discord_alert_receiver) = start_admin_alerts(&config);
+    let personas = load_personas(&config);
+    let mut builder = CompanionPilot::builder()
+        .model(model.clone())
+        .memory(memory)
+        .tools(tools)
+        .settings(build_orchestrator_config(&config))
+        .personas(personas.clone())
+        .tool_macros(load_tool_macros(&config))
+        .widgets(load_widgets(&config, &personas))
+        .event_mappings(load_event_mappings(&config))
+        .admin_alerts(admin_alerts);
+    if let Some(simulated_tools) = load_simulated_tools(&config) {
+        builder = builder.simulated_tools(simulated_tools);
+    }
+    if let Some(voice_manager) = voice {
+        builder = builder.voice(voice_manager);
+    }
+    if let Some(coordinator) = build_coordinator(&config).await {
+        builder = builder.coordinator(coordinator);
+    }
+    let mut background_tools = config
+        .tools
+        .background_tools
+        .split(',')
+        .map(str::trim)
+        .filter(|tool_name| !tool_name.is_empty())
+        .map(ToOwned::to_owned)
+        .collect::<HashSet<_>>();
+    // Research takes minutes, far past any inline tool timeout.
+    background_tools.insert("deep_research".to_owned());
+    let (background_tasks, background_results) = BackgroundTasks::channel(
+        background_tools,
+        std::time::Duration::from_secs(config.tools.background_timeout_secs),
+    );
+    builder = builder.background_tasks(background_tasks);
+    if let Some(secret) = &config.model.user_key_secret {
+        builder = builder.user_keys(Arc::new(UserKeyVault::from_secret(secret)));
+    }
+    if let Some(secret) = &config.http.calendar_secret {
+        builder = builder.calendar_feeds(Arc::new(CalendarFeeds::new(
+            secret,
+            config.http.public_url.as_deref(),
+        )));
+    }
+    if let Some(redactor) = build_redactor(&config) {
+        builder = builder.redactor(redactor);
+    }
+    if let Some(ambient) = start_mqtt_subscriber(&config) {
+        builder = builder.ambient_context(ambient);
+    }
+    if config.model.probe_interval_mins > 0 {
+        builder = builder.model_probe(ModelProbeConfig {
+            interval: std::time::Duration::from_secs(config.model.probe_interval_mins * 60),
+            p95_alert: std::time::Duration::from_millis(config.model.probe_p95_alert_ms),
+            ..ModelProbeConfig::default()
+        });
+    }
+    builder = builder.prompt_rollouts(RolloutPolicy {
+        percent: config.model.prompt_rollout_percent.min(100) as u8,
+        window: std::time::Duration::from_secs(config.model.prompt_rollout_window_mins * 60),
+        min_turns: config.model.prompt_rollout_min_turns,
+        max_fallback_increase: config.model.prompt_rollout_max_fallback_increase.into(),
+        max_feedback_drop: config.model.prompt_rollout_max_feedback_drop.into(),
+    });
+    let companion = builder.build().await;
+    if let Some(backfill) = build_fact_backfill_config(&config) {
+        start_fact_backfill_job(model, companion.orchestrator.clone(), backfill);
     }
 
-    if let Some(discord_token) = config.discord_token.clone() {
-        let discord_orchestrator = orchestrator.clone();
-        let discord_voice = voice.clone();
+    if let Some(discord_token) = config.discord.token.clone() {
+        let discord_orchestrator = companion.orchestrator.clone();
+        let discord_voice = companion.voice.clone();
+        let options = DiscordBotOptions {
+            debounce_window: std::time::Duration::from_millis(config.discord.debounce_ms),
+            admin_channel_id: config.discord.admin_channel_id,
+            admin_alerts: discord_alert_receiver,
+            personas: companion.personas.clone(),
+            memory_review: build_memory_review_config(&config),
+            date_greetings: config.discord.date_greetings.then(|| DateGreetingConfig {
+                hour_utc: config.discord.date_greetings_hour_utc.min(23) as u32,
+                channel_id: config.discord.date_greetings_channel_id,
+            }),
+            message_content_intent: config.discord.message_content,
+            presence_interval: (config.discord.presence_secs > 0)
+                .then(|| std::time::Duration::from_secs(config.discord.presence_secs)),
+            shard: config.discord.shard_id.zip(config.discord.shard_count),
+            typing_prefetch: config.discord.typing_prefetch,
+            background_results: Some(background_results),
+            in_flight_recovery: in_flight_recovery(&config),
+        };
         tokio::spawn(async move {
-            if let Err(error) =
-                discord_bot::start_discord_bot(discord_token, discord_orchestrator, discord_voice)
-                    .await
+            if let Err(error) = discord_bot::start_discord_bot(
+                discord_token,
+                discord_orchestrator,
+                discord_voice,
+                options,
+            )
+            .await
             {
                 warn!(?error, "Discord bot stopped with error");
             }
@@ -56,19 +195,86 @@ async fn main() -> anyhow::Result<()> {
         warn!("DISCORD_TOKEN is not set; Discord bot is disabled");
     }
 
-    if config.redis_url.is_none() {
-        warn!("REDIS_URL is not configured; using stateless in-process cache only");
+    let app = apply_http_config(companion.router(), &config.http)
+        .into_make_service_with_connect_info::<SocketAddr>();
+    if let (Some(cert_path), Some(key_path)) =
+        (&config.http.tls_cert_path, &config.http.tls_key_path)
+    {
+        let cert = Arc::new(ReloadingCert::load(cert_path, key_path)?);
+        if config.http.tls_reload_secs > 0 {
+            cert.clone()
+                .spawn_reload(std::time::Duration::from_secs(config.http.tls_reload_secs));
+        }
+        // `tap_io` only adapts the listener so client addresses reach handlers.
+        let listener = TlsListener::bind(config.http_bind, cert)
+            .await?
+            .tap_io(|_| {});
+        info!("CompanionPilot HTTPS API listening on {}", config.http_bind);
+        axum::serve(listener, app).await?;
+    } else {
+        let listener = TcpListener::bind(config.http_bind).await?;
+        info!("CompanionPilot HTTP API listening on {}", config.http_bind);
+        axum::serve(listener, app).await?;
     }
+    Ok(())
+}
 
-    let app = http::router(AppState {
-        orchestrator,
-        memory: memory_for_dashboard,
-    });
-    let listener = TcpListener::bind(config.http_bind).await?;
-    info!("CompanionPilot HTTP API listening on {}", config.http_bind);
+async fn run_command(config: &AppConfig, command: &str, args: &[String]) -> anyhow::Result<()> {
+    match command {
+        "snapshot" => {
+            let out = flag_value(args, "--out").ok_or_else(|| {
+                anyhow::anyhow!("usage: companionpilot snapshot --out <file.json>")
+            })?;
+            let memory = build_memory_store(config).await?;
+            let snapshot = MemorySnapshot::capture(memory.as_ref()).await?;
+            std::fs::write(out, serde_json::to_vec_pretty(&snapshot)?)?;
+            info!(users = snapshot.users.len(), path = %out, "memory snapshot written");
+            Ok(())
+        }
+        "restore" => {
+            let input = flag_value(args, "--in")
+                .ok_or_else(|| anyhow::anyhow!("usage: companionpilot restore --in <file.json>"))?;
+            let snapshot: MemorySnapshot = serde_json::from_slice(&std::fs::read(input)?)?;
+            let memory = build_memory_store(config).await?;
+            let summary = snapshot.restore(memory.as_ref()).await?;
+            info!(
+                users = summary.users,
+                facts = summary.facts,
+                sessions = summary.sessions,
+                messages = summary.messages,
+                tool_calls = summary.tool_calls,
+                planner_decisions = summary.planner_decisions,
+                path = %input,
+                "memory snapshot restored"
+            );
+            Ok(())
+        }
+        "doctor" => {
+            let results = doctor::run_checks(config).await;
+            for result in &results {
+                println!("{result}");
+            }
+            let failed = results
+                .iter()
+                .filter(|result| result.status == CheckStatus::Fail)
+                .count();
+            if failed > 0 {
+                return Err(anyhow::anyhow!("{failed} check(s) failed"));
+            }
+            println!("all configured dependencies are reachable");
+            Ok(())
+        }
+        other => Err(anyhow::anyhow!(
+            "unknown command `{other}`; valid commands are snapshot|restore|doctor"
+        )),
+    }
+}
 
-    axum::serve(listener, app).await?;
-    Ok(())
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
 }
 
 fn init_tracing() {
@@ -79,41 +285,41 @@ fn init_tracing() {
 }
 
 fn build_model_provider(config: &AppConfig) -> Arc<dyn ModelProvider> {
-    let provider = config.model_provider.to_lowercase();
+    let provider = config.model.provider.to_lowercase();
     match provider.as_str() {
         "openrouter" => {
-            if let Some(api_key) = config.openrouter_api_key.clone() {
-                info!(model = %config.openrouter_model, "using OpenRouter model provider");
+            if let Some(api_key) = config.model.openrouter_api_key.clone() {
+                info!(model = %config.model.openrouter_model, "using OpenRouter model provider");
                 Arc::new(OpenRouterProvider::new(
                     api_key,
-                    config.openrouter_model.clone(),
-                    config.openrouter_referer.clone(),
-                    config.openrouter_title.clone(),
+                    config.model.openrouter_model.clone(),
+                    config.model.openrouter_referer.clone(),
+                    config.model.openrouter_title.clone(),
                 ))
             } else {
                 warn!("MODEL_PROVIDER=openrouter but OPENROUTER_API_KEY is missing; using mock");
-                Arc::new(MockModelProvider)
+                mock_model_provider(config)
             }
         }
         "mock" => {
             warn!("MODEL_PROVIDER=mock; using mock model provider");
-            Arc::new(MockModelProvider)
+            mock_model_provider(config)
         }
         "auto" => {
-            if let Some(api_key) = config.openrouter_api_key.clone() {
+            if let Some(api_key) = config.model.openrouter_api_key.clone() {
                 info!(
-                    model = %config.openrouter_model,
+                    model = %config.model.openrouter_model,
                     "using OpenRouter model provider (auto mode)"
                 );
                 Arc::new(OpenRouterProvider::new(
                     api_key,
-                    config.openrouter_model.clone(),
-                    config.openrouter_referer.clone(),
-                    config.openrouter_title.clone(),
+                    config.model.openrouter_model.clone(),
+                    config.model.openrouter_referer.clone(),
+                    config.model.openrouter_title.clone(),
                 ))
             } else {
                 warn!("No OPENROUTER_API_KEY configured; using mock model provider");
-                Arc::new(MockModelProvider)
+                mock_model_provider(config)
             }
         }
         other => {
@@ -121,22 +327,384 @@ fn build_model_provider(config: &AppConfig) -> Arc<dyn ModelProvider> {
                 provider = %other,
                 "unknown MODEL_PROVIDER value; valid values are auto|openrouter|mock; falling back to auto"
             );
-            if let Some(api_key) = config.openrouter_api_key.clone() {
+            if let Some(api_key) = config.model.openrouter_api_key.clone() {
                 Arc::new(OpenRouterProvider::new(
                     api_key,
-                    config.openrouter_model.clone(),
-                    config.openrouter_referer.clone(),
-                    config.openrouter_title.clone(),
+                    config.model.openrouter_model.clone(),
+                    config.model.openrouter_referer.clone(),
+                    config.model.openrouter_title.clone(),
                 ))
             } else {
-                Arc::new(MockModelProvider)
+                mock_model_provider(config)
             }
         }
     }
 }
 
+fn mock_model_provider(config: &AppConfig) -> Arc<dyn ModelProvider> {
+    match config.model.mock_seed {
+        Some(seed) => {
+            info!(seed, "mock model replies are seeded");
+            Arc::new(SeededMockModelProvider::new(seed))
+        }
+        None => Arc::new(MockModelProvider),
+    }
+}
+
+/// Starts the admin alert dispatcher. Returns the sender for alert producers
+/// and, when a Discord admin channel is configured, the queue the Discord
+/// adapter posts from.
+fn start_admin_alerts(
+    config: &AppConfig,
+) -> (
+    AdminAlerts,
+    Option<tokio::sync::mpsc::UnboundedReceiver<AdminAlert>>,
+) {
+    let (admin_alerts, receiver) = AdminAlerts::channel();
+    let mut sinks: Vec<Box<dyn AlertSink>> = Vec::new();
+    let mut discord_receiver = None;
+    if config.discord.token.is_some() && config.discord.admin_channel_id.is_some() {
+        let (sink, receiver) = ChannelAlertSink::channel();
+        sinks.push(Box::new(sink));
+        discord_receiver = Some(receiver);
+    }
+    if let Some(url) = &config.alerts.webhook_url {
+        sinks.push(Box::new(WebhookAlertSink::new(url.clone())));
+    }
+
+    if sinks.is_empty() {
+        info!("no admin alert channel or webhook configured; alerts are logged only");
+    }
+    let policy = AlertPolicy {
+        dedup_window: std::time::Duration::from_secs(config.alerts.dedup_secs),
+        max_per_window: (config.alerts.max_per_hour as usize).max(1),
+        rate_window: std::time::Duration::from_secs(3600),
+    };
+    tokio::spawn(run_alert_dispatcher(receiver, policy, sinks));
+
+    (admin_alerts, discord_receiver)
+}
+
+fn load_personas(config: &AppConfig) -> Arc<PersonaRegistry> {
+    let registry = Arc::new(PersonaRegistry::default());
+    if let Some(dir) = &config.personas_dir
+        && let Err(error) = registry.load_dir(Path::new(dir))
+    {
+        warn!(?error, dir, "failed to read PERSONAS_DIR");
+    }
+    registry
+}
+
+fn load_widgets(config: &AppConfig, personas: &PersonaRegistry) -> Arc<WidgetRegistry> {
+    let widgets = WidgetRegistry::parse(&config.http.chat_widgets).unwrap_or_else(|error| {
+        warn!(%error, "failed to parse CHAT_WIDGETS; widgets are disabled");
+        WidgetRegistry::default()
+    });
+    for widget in widgets.iter() {
+        if let Some(persona) = &widget.persona
+            && personas.get(persona).is_none()
+        {
+            warn!(widget = %widget.name, %persona, "widget persona is not loaded");
+        }
+    }
+    Arc::new(widgets)
+}
+
+fn load_simulated_tools(config: &AppConfig) -> Option<Arc<MockToolExecutor>> {
+    let path = config.tools.simulation_script.as_ref()?;
+    match std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|raw| MockToolExecutor::from_json(&raw))
+    {
+        Ok(tools) => Some(Arc::new(tools)),
+        Err(error) => {
+            warn!(?error, path, "failed to load TOOL_SIMULATION_SCRIPT");
+            None
+        }
+    }
+}
+
+fn load_tool_macros(config: &AppConfig) -> Arc<ToolMacroRegistry> {
+    let Some(path) = &config.tools.macros_path else {
+        return Arc::default();
+    };
+    match ToolMacroRegistry::load(Path::new(path)) {
+        Ok(macros) => Arc::new(macros),
+        Err(error) => {
+            warn!(
+                ?error,
+                path, "failed to load TOOL_MACROS_PATH; macros are disabled"
+            );
+            Arc::default()
+        }
+    }
+}
+
+fn load_event_mappings(config: &AppConfig) -> Arc<EventMappings> {
+    let Some(path) = &config.memory.event_mappings_path else {
+        return Arc::default();
+    };
+    match EventMappings::load(Path::new(path)) {
+        Ok(mappings) => Arc::new(mappings),
+        Err(error) => {
+            warn!(
+                ?error,
+                path, "failed to load EVENT_MAPPINGS_PATH; events are rejected"
+            );
+            Arc::default()
+        }
+    }
+}
+
+fn build_memory_review_config(config: &AppConfig) -> Option<MemoryReviewConfig> {
+    const DAY_SECS: u64 = 24 * 60 * 60;
+    if config.memory.review_interval_hours == 0 {
+        return None;
+    }
+    Some(MemoryReviewConfig {
+        interval: std::time::Duration::from_secs(config.memory.review_interval_hours * 60 * 60),
+        stale_after: std::time::Duration::from_secs(config.memory.review_stale_days * DAY_SECS),
+        low_confidence: config.memory.review_min_confidence,
+        cooldown: std::time::Duration::from_secs(config.memory.review_cooldown_days * DAY_SECS),
+        max_facts: config.memory.review_max_facts as usize,
+    })
+}
+
+fn build_fact_backfill_config(config: &AppConfig) -> Option<FactBackfillConfig> {
+    if !config.memory.fact_backfill {
+        return None;
+    }
+    Some(FactBackfillConfig {
+        hour_utc: config.memory.fact_backfill_hour_utc.min(23) as u32,
+        max_messages_per_user: config.memory.fact_backfill_max_messages as usize,
+        approval_queue: config.memory.fact_approval_queue,
+        ..FactBackfillConfig::default()
+    })
+}
+
+fn in_flight_recovery(config: &AppConfig) -> InFlightRecovery {
+    InFlightRecovery::parse(&config.in_flight_recovery).unwrap_or_else(|| {
+        warn!(
+            value = %config.in_flight_recovery,
+            "unknown IN_FLIGHT_RECOVERY; expected off, notify or retry"
+        );
+        InFlightRecovery::default()
+    })
+}
+
+fn context_windows(config: &AppConfig) -> ContextWindows {
+    ContextWindows::parse(
+        &config.memory.context_recent_messages,
+        &config.memory.context_facts,
+    )
+    .unwrap_or_else(|error| {
+        warn!(%error, "invalid CONTEXT_RECENT_MESSAGES or CONTEXT_FACTS; using defaults");
+        ContextWindows::default()
+    })
+}
+
+/// Mirrors the choices of [`build_model_provider`], [`build_memory_store`]
+/// and [`build_voice_manager`].
+fn deployment_info(config: &AppConfig) -> DeploymentInfo {
+    let mock_model = config.model.provider.eq_ignore_ascii_case("mock")
+        || config.model.openrouter_api_key.is_none();
+    DeploymentInfo {
+        model: if mock_model {
+            "mock".to_owned()
+        } else {
+            config.model.openrouter_model.clone()
+        },
+        memory_backend: if config.memory.database_url.is_some() {
+            "postgres".to_owned()
+        } else {
+            "in_memory".to_owned()
+        },
+        voice: config.voice.enabled && config.voice.openai_api_key.is_some(),
+    }
+}
+
+fn build_orchestrator_config(config: &AppConfig) -> OrchestratorConfig {
+    let defaults = OrchestratorConfig::default();
+    let mut tool_timeout_overrides = defaults.tool_timeout_overrides;
+    tool_timeout_overrides.extend(OrchestratorConfig::parse_tool_timeout_overrides(
+        &config.tools.timeout_overrides,
+    ));
+
+    let conversation_sequencing = ConversationSequencing::parse(&config.conversation_sequencing)
+        .unwrap_or_else(|| {
+            warn!(
+                value = %config.conversation_sequencing,
+                "unknown CONVERSATION_SEQUENCING; expected off, queue or merge"
+            );
+            ConversationSequencing::default()
+        });
+
+    let citation_style = CitationStyle::parse(&config.guild_defaults.citation_style)
+        .unwrap_or_else(|| {
+            warn!(
+                value = %config.guild_defaults.citation_style,
+                "unknown CITATION_STYLE; expected off, footnotes or compact"
+            );
+            CitationStyle::default()
+        });
+
+    OrchestratorConfig {
+        fact_dedup_threshold: (config.memory.fact_dedup_threshold > 0.0)
+            .then_some(config.memory.fact_dedup_threshold.min(1.0)),
+        fact_decay_half_life_days: config.memory.fact_decay_half_life_days.max(0.0),
+        fact_approval_queue: config.memory.fact_approval_queue,
+        cross_channel_turns: config.memory.cross_channel_context_turns as usize,
+        context_windows: context_windows(config),
+        deployment: deployment_info(config),
+        session_gap: (config.memory.session_gap_minutes > 0).then(|| {
+            std::time::Duration::from_secs(config.memory.session_gap_minutes.saturating_mul(60))
+        }),
+        session_titles: config.memory.session_titles,
+        session_scoped_context: config.memory.session_scoped_context,
+        pinned_context_tokens: config.memory.pinned_context_tokens as usize,
+        simulate_tools: config.tools.simulation,
+        shadow_tools: config
+            .tools
+            .shadow_tools
+            .split(',')
+            .map(str::trim)
+            .filter(|tool_name| !tool_name.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        now_playing_users: config
+            .tools
+            .spotify_context_users
+            .split(',')
+            .map(str::trim)
+            .filter(|user_id| !user_id.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        now_playing_ttl: std::time::Duration::from_secs(config.tools.spotify_context_ttl_secs),
+        ambient_context_users: config
+            .mqtt
+            .context_users
+            .split(',')
+            .map(str::trim)
+            .filter(|user_id| !user_id.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        planner_json_retries: config.model.planner_json_retries.min(3) as usize,
+        planner_examples: config.model.planner_few_shot_examples.min(8) as usize,
+        small_talk_routing: config.model.small_talk_routing,
+        speculative_synthesis: config.model.speculative_synthesis,
+        thoughts_log: config.model.thoughts_log,
+        model_handoff_turns: config.model.handoff_turns.min(20) as u32,
+        context_prefetch_ttl: if config.discord.typing_prefetch {
+            DEFAULT_PREFETCH_TTL
+        } else {
+            std::time::Duration::ZERO
+        },
+        tool_timeout: std::time::Duration::from_millis(config.tools.timeout_ms.max(1)),
+        tool_timeout_overrides,
+        tool_quotas: parse_tool_quotas(&config.tools.daily_quotas),
+        tool_output_max_chars: config.tools.output_max_chars as usize,
+        tool_output_budgets: parse_tool_output_budgets(&config.tools.output_budgets),
+        tool_round_budget: (config.tools.round_budget_ms > 0)
+            .then(|| std::time::Duration::from_millis(config.tools.round_budget_ms)),
+        max_concurrent_orchestrations: (config.max_concurrent_orchestrations > 0)
+            .then_some(config.max_concurrent_orchestrations as usize),
+        max_queued_orchestrations: config.max_queued_orchestrations as usize,
+        user_rate_limit: (config.user_rate_limit > 0)
+            .then(|| config.user_rate_limit.min(u32::MAX as u64) as u32),
+        user_rate_window: std::time::Duration::from_secs(config.user_rate_limit_window_secs.max(1)),
+        conversation_sequencing,
+        in_flight_tracking: in_flight_recovery(config) != InFlightRecovery::Off,
+        conversation_lock_ttl: std::time::Duration::from_secs(config.memory.conversation_lock_secs),
+        tool_stats: ToolStatsConfig {
+            window: (config.tools.stats_window as usize).max(1),
+            min_samples: config.tools.failure_alert_min_calls as usize,
+            failure_rate_threshold: config.tools.failure_alert_threshold.clamp(0.0, 1.0),
+        },
+        generation: GenerationParams {
+            model: None,
+            temperature: config.model.temperature,
+            top_p: config.model.top_p,
+            max_tokens: config.model.max_tokens,
+            stop: config.model.stop.clone(),
+            api_key: None,
+        },
+        model_routes: ModelRoutes {
+            simple: config.model.route_simple.clone(),
+            complex: config.model.route_complex.clone(),
+            code: config.model.route_code.clone(),
+            creative: config.model.route_creative.clone(),
+        },
+        guild_defaults: GuildSettings {
+            activation: ActivationRules {
+                channel_ids: Vec::new(),
+                require_mention: config.guild_defaults.require_mention,
+            },
+            persona: config.guild_defaults.persona.clone(),
+            memory_consent_default: config.guild_defaults.memory_consent,
+            language: config.guild_defaults.reply_language.clone(),
+            citation_style,
+            replay_missed_mentions: config.guild_defaults.replay_missed_mentions,
+            ..GuildSettings::default()
+        },
+        guild_settings_cache_ttl: std::time::Duration::from_secs(config.guild_defaults.cache_secs),
+    }
+}
+
+fn start_mqtt_subscriber(config: &AppConfig) -> Option<Arc<AmbientContext>> {
+    let broker_url = config.mqtt.broker_url.as_deref()?;
+    let topics = match parse_ambient_topics(&config.mqtt.topics) {
+        Ok(topics) if !topics.is_empty() => topics,
+        Ok(_) => {
+            warn!("MQTT_TOPICS is empty; ambient context is disabled");
+            return None;
+        }
+        Err(error) => {
+            warn!(%error, "failed to parse MQTT_TOPICS; ambient context is disabled");
+            return None;
+        }
+    };
+    let options = match mqtt_options(broker_url, &config.mqtt.client_id) {
+        Ok(options) => options,
+        Err(error) => {
+            warn!(
+                ?error,
+                "invalid MQTT_BROKER_URL; ambient context is disabled"
+            );
+            return None;
+        }
+    };
+    let ambient = Arc::new(AmbientContext::new(
+        topics,
+        std::time::Duration::from_secs(config.mqtt.stale_secs),
+    ));
+    tokio::spawn(run_mqtt_subscriber(options, ambient.clone()));
+    Some(ambient)
+}
+
+/// Redis coordination for running several replicas; without it each instance
+/// assumes it is the only one.
+async fn build_coordinator(config: &AppConfig) -> Option<Arc<dyn InstanceCoordinator>> {
+    let Some(redis_url) = &config.memory.redis_url else {
+        warn!("REDIS_URL is not configured; running without cross-instance coordination");
+        return None;
+    };
+    match RedisCoordinator::connect(redis_url, &config.memory.redis_key_prefix).await {
+        Ok(coordinator) => {
+            info!("Connected to Redis for instance coordination");
+            Some(Arc::new(coordinator))
+        }
+        Err(error) => {
+            warn!(
+                ?error,
+                "failed to connect to Redis; running without coordination"
+            );
+            None
+        }
+    }
+}
+
 async fn build_memory_store(config: &AppConfig) -> anyhow::Result<Arc<dyn MemoryStore>> {
-    if let Some(database_url) = &config.database_url {
+    if let Some(database_url) = &config.memory.database_url {
         let store = PostgresMemoryStore::connect(database_url).await?;
         info!("Connected to Postgres memory store");
         Ok(Arc::new(store))
@@ -146,35 +714,147 @@ async fn build_memory_store(config: &AppConfig) -> anyhow::Result<Arc<dyn Memory
     }
 }
 
-fn build_tools(config: &AppConfig, voice: Option<Arc<VoiceManager>>) -> Arc<dyn ToolExecutor> {
-    let web_search = config
-        .tavily_api_key
-        .as_ref()
-        .map(|key| TavilyWebSearchTool::new(key.clone()));
-
-    if web_search.is_none() {
-        warn!("TAVILY_API_KEY not set; planner-selected web_search calls will fail");
+fn build_tools(
+    config: &AppConfig,
+    model: Arc<dyn ModelProvider>,
+    memory: Arc<dyn MemoryStore>,
+    voice: Option<Arc<VoiceManager>>,
+) -> Arc<dyn ToolExecutor> {
+    let web_search = build_web_search(config);
+    match &web_search {
+        Some(web_search) => info!(providers = ?web_search.provider_names(), "web search enabled"),
+        None => {
+            warn!("no web search provider configured; planner-selected web_search calls will fail")
+        }
     }
 
+    let deep_research = web_search.clone().map(|web_search| {
+        DeepResearchTool::new(
+            model,
+            Arc::new(ToolRegistry {
+                web_search: Some(web_search),
+                ..ToolRegistry::default()
+            }),
+            ResearchBudget {
+                max_rounds: config.tools.deep_research_max_rounds as usize,
+                max_searches: config.tools.deep_research_max_searches as usize,
+                pages_per_search: config.tools.deep_research_pages_per_search as usize,
+                ..ResearchBudget::default()
+            },
+        )
+    });
+
     Arc::new(ToolRegistry {
         current_datetime: CurrentDateTimeTool,
         spotify_playing_status: SpotifyPlayingStatusTool::default(),
         web_search,
+        deep_research,
+        chat_history: Some(ChatHistorySearchTool::new(memory)),
+        ocr_image: build_ocr_image(config),
         voice,
     })
 }
 
+fn build_ocr_image(config: &AppConfig) -> Option<OcrImageTool> {
+    let tools = &config.tools;
+    let backend = match tools.ocr_backend.trim().to_ascii_lowercase().as_str() {
+        "vision" => OcrBackend::Vision {
+            url: tools
+                .ocr_url
+                .clone()
+                .unwrap_or_else(|| DEFAULT_OCR_URL.to_owned()),
+            api_key: tools.ocr_api_key.clone().or_else(|| {
+                tools
+                    .ocr_url
+                    .is_none()
+                    .then(|| config.model.openrouter_api_key.clone())
+                    .flatten()
+            }),
+            model: tools.ocr_model.clone(),
+        },
+        "tesseract" => OcrBackend::Tesseract {
+            path: tools.tesseract_path.clone(),
+        },
+        _ => return None,
+    };
+    info!(?backend, "ocr_image enabled");
+    Some(OcrImageTool::new(backend))
+}
+
+fn build_web_search(config: &AppConfig) -> Option<WebSearchTool> {
+    let tools = &config.tools;
+    let providers = tools
+        .web_search_provider_names()
+        .into_iter()
+        .filter_map(|name| -> Option<Arc<dyn WebSearchProvider>> {
+            match name {
+                "tavily" => Some(Arc::new(TavilySearchProvider::new(
+                    tools.tavily_api_key.clone()?,
+                ))),
+                "brave" => Some(Arc::new(BraveSearchProvider::new(
+                    tools.brave_search_api_key.clone()?,
+                ))),
+                "serpapi" => Some(Arc::new(SerpApiSearchProvider::new(
+                    tools.serpapi_api_key.clone()?,
+                ))),
+                "searxng" => Some(Arc::new(SearxngSearchProvider::new(
+                    tools.searxng_url.as_deref()?,
+                ))),
+                _ => None,
+            }
+        })
+        .collect();
+    let mode = SearchMode::parse(&tools.web_search_mode).unwrap_or_default();
+    let web_search = WebSearchTool::new(providers, mode)?;
+    let api_key = tools.rerank_api_key.clone();
+    let reranker: Arc<dyn Reranker> = match RerankMode::parse(&tools.web_search_rerank) {
+        Some(RerankMode::Embedding) => Arc::new(EmbeddingReranker::new(
+            tools.rerank_url.as_deref().unwrap_or(DEFAULT_EMBEDDING_URL),
+            api_key,
+            &tools.rerank_model,
+        )),
+        Some(RerankMode::CrossEncoder) => match &tools.rerank_url {
+            Some(url) => Arc::new(CrossEncoderReranker::new(url, api_key)),
+            None => return Some(web_search),
+        },
+        Some(RerankMode::Off) | None => return Some(web_search),
+    };
+    info!(mode = %tools.web_search_rerank, "web search reranking enabled");
+    let web_search = web_search.with_reranker(reranker);
+    Some(match build_redactor(config) {
+        Some(redactor) => web_search.with_redactor(redactor),
+        None => web_search,
+    })
+}
+
+fn build_redactor(config: &AppConfig) -> Option<Arc<Redactor>> {
+    let kinds = match parse_redaction_kinds(&config.memory.redact_pii) {
+        Ok(kinds) => kinds,
+        Err(error) => {
+            warn!(%error, "invalid REDACT_PII; personal data is not masked");
+            Vec::new()
+        }
+    };
+    let split = |raw: &str| split_list(raw).map(ToOwned::to_owned).collect::<Vec<_>>();
+    let redactor = Redactor::new(
+        &kinds,
+        &split(&config.memory.redact_words),
+        &split(&config.memory.redaction_allowlist),
+    );
+    (!redactor.is_empty()).then(|| Arc::new(redactor))
+}
+
 fn build_voice_manager(config: &AppConfig) -> Option<Arc<VoiceManager>> {
-    if !config.voice_enabled {
+    if !config.voice.enabled {
         return None;
     }
 
-    let Some(openai_api_key) = config.openai_api_key.clone() else {
+    let Some(openai_api_key) = config.voice.openai_api_key.clone() else {
         warn!("VOICE_ENABLED is true but OPENAI_API_KEY is missing; voice is disabled");
         return None;
     };
 
-    let allowlist = VoiceRuntimeConfig::parse_allowlist(&config.voice_allowlist);
+    let allowlist = VoiceRuntimeConfig::parse_allowlist(&config.voice.allowlist);
     if allowlist.is_empty() {
         warn!(
             "VOICE_ENABLED is true but VOICE_ALLOWLIST has no valid guild:channel entries; voice tools will fail until configured"
@@ -183,13 +863,21 @@ fn build_voice_manager(config: &AppConfig) -> Option<Arc<VoiceManager>> {
 
     Some(VoiceManager::new(VoiceRuntimeConfig {
         openai_api_key,
-        stt_model: config.openai_stt_model.clone(),
-        tts_model: config.openai_tts_model.clone(),
-        tts_voice: config.openai_tts_voice.clone(),
+        stt_model: config.voice.stt_model.clone(),
+        tts_model: config.voice.tts_model.clone(),
+        tts_voice: config.voice.tts_voice.clone(),
         allowlist,
-        idle_timeout: std::time::Duration::from_secs(config.voice_idle_timeout_sec),
-        default_chunk_gap: std::time::Duration::from_millis(config.voice_chunk_gap_ms),
-        default_listen_window: std::time::Duration::from_millis(config.voice_listen_window_ms),
-        default_max_turn: std::time::Duration::from_millis(config.voice_max_turn_ms),
+        idle_timeout: std::time::Duration::from_secs(config.voice.idle_timeout_sec),
+        default_chunk_gap: std::time::Duration::from_millis(config.voice.chunk_gap_ms),
+        default_listen_window: std::time::Duration::from_millis(config.voice.listen_window_ms),
+        default_max_turn: std::time::Duration::from_millis(config.voice.max_turn_ms),
+        tts_cache_dir: config
+            .voice
+            .tts_cache_dir
+            .as_ref()
+            .map(std::path::PathBuf::from),
+        tts_cache_max_bytes: config.voice.tts_cache_max_mb.saturating_mul(1024 * 1024),
+        barge_in: config.voice.barge_in,
+        auto_join_user_ids: VoiceRuntimeConfig::parse_user_ids(&config.voice.auto_join_users),
     }))
 }
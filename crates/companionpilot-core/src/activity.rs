@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Users who sent a message within this window count as active.
+pub const ACTIVE_USER_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Live counters for the bot's presence: turns being processed right now and
+/// users seen recently.
+#[derive(Debug, Default)]
+pub struct ActivityTracker {
+    in_flight: Arc<AtomicUsize>,
+    last_seen: Mutex<HashMap<String, Instant>>,
+}
+
+/// Decrements the in-flight counter when the turn ends, however it ends.
+#[derive(Debug)]
+pub struct TurnActivity {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for TurnActivity {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ActivitySnapshot {
+    pub in_flight: usize,
+    pub active_users: usize,
+}
+
+impl ActivityTracker {
+    /// Marks a turn for `user_id` as started; hold the guard until it ends.
+    pub fn begin_turn(&self, user_id: &str) -> TurnActivity {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        lock(&self.last_seen).insert(user_id.to_owned(), Instant::now());
+        TurnActivity {
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    pub fn snapshot(&self) -> ActivitySnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn snapshot_at(&self, now: Instant) -> ActivitySnapshot {
+        let mut last_seen = lock(&self.last_seen);
+        last_seen.retain(|_, seen| now.duration_since(*seen) < ACTIVE_USER_WINDOW);
+        ActivitySnapshot {
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            active_users: last_seen.len(),
+        }
+    }
+}
+
+/// What the bot shows as its status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Presence {
+    Thinking,
+    /// "Listening to N users".
+    Listening(usize),
+    /// Idle; shows the persona replies are written as.
+    Persona(String),
+}
+
+impl Presence {
+    pub fn from_snapshot(snapshot: ActivitySnapshot, persona: Option<&str>) -> Self {
+        if snapshot.in_flight > 0 {
+            Self::Thinking
+        } else if snapshot.active_users > 0 {
+            Self::Listening(snapshot.active_users)
+        } else {
+            Self::Persona(persona.unwrap_or("CompanionPilot").to_owned())
+        }
+    }
+
+    pub fn text(&self) -> String {
+        match self {
+            Self::Thinking => "Thinking…".to_owned(),
+            Self::Listening(1) => "1 user".to_owned(),
+            Self::Listening(users) => format!("{users} users"),
+            Self::Persona(name) => format!("Chatting as {name}"),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presence_follows_turns_and_recent_users() {
+        let tracker = ActivityTracker::default();
+        assert_eq!(
+            Presence::from_snapshot(tracker.snapshot(), Some("pirate")),
+            Presence::Persona("pirate".to_owned())
+        );
+
+        let turn = tracker.begin_turn("u1");
+        assert_eq!(
+            Presence::from_snapshot(tracker.snapshot(), None),
+            Presence::Thinking
+        );
+        drop(turn);
+        assert_eq!(
+            Presence::from_snapshot(tracker.snapshot(), None),
+            Presence::Listening(1)
+        );
+
+        let later = Instant::now() + ACTIVE_USER_WINDOW;
+        assert_eq!(tracker.snapshot_at(later).active_users, 0);
+    }
+}
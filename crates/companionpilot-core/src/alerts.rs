@@ -0,0 +1,243 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// An operational event worth an operator's attention.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminAlert {
+    /// Stable category, e.g. `tool_failure_rate`.
+    pub kind: &'static str,
+    /// Identity used for deduplication within a kind, e.g. the tool name.
+    pub key: String,
+    pub message: String,
+}
+
+impl AdminAlert {
+    pub fn render(&self) -> String {
+        format!("**[{}]** {}", self.kind, self.message)
+    }
+}
+
+/// Sending half of the admin alert queue. Cheap to clone; sending never blocks
+/// and is a no-op once the receiving side is gone.
+#[derive(Debug, Clone)]
+pub struct AdminAlerts {
+    sender: mpsc::UnboundedSender<AdminAlert>,
+}
+
+impl AdminAlerts {
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<AdminAlert>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { sender }, receiver)
+    }
+
+    pub fn send(&self, kind: &'static str, message: impl Into<String>) {
+        self.send_keyed(kind, kind, message);
+    }
+
+    pub fn send_keyed(
+        &self,
+        kind: &'static str,
+        key: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        let _ = self.sender.send(AdminAlert {
+            kind,
+            key: key.into(),
+            message: message.into(),
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertPolicy {
+    /// Repeats of the same kind and key within this window are dropped.
+    pub dedup_window: Duration,
+    /// At most this many alerts are delivered per `rate_window`.
+    pub max_per_window: usize,
+    pub rate_window: Duration,
+}
+
+impl Default for AlertPolicy {
+    fn default() -> Self {
+        Self {
+            dedup_window: Duration::from_secs(600),
+            max_per_window: 20,
+            rate_window: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Decides which alerts get delivered so a flapping dependency cannot spam
+/// the admin channel.
+pub struct AlertGate {
+    policy: AlertPolicy,
+    last_sent: HashMap<(&'static str, String), Instant>,
+    delivered: VecDeque<Instant>,
+    suppressed: u64,
+}
+
+impl AlertGate {
+    pub fn new(policy: AlertPolicy) -> Self {
+        Self {
+            policy,
+            last_sent: HashMap::new(),
+            delivered: VecDeque::new(),
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the alert to deliver (annotated with the number of alerts
+    /// dropped by rate limiting since the last delivery), or `None`.
+    pub fn admit(&mut self, mut alert: AdminAlert, now: Instant) -> Option<AdminAlert> {
+        let dedup_key = (alert.kind, alert.key.clone());
+        if let Some(last_sent) = self.last_sent.get(&dedup_key)
+            && now.duration_since(*last_sent) < self.policy.dedup_window
+        {
+            return None;
+        }
+
+        while let Some(oldest) = self.delivered.front() {
+            if now.duration_since(*oldest) < self.policy.rate_window {
+                break;
+            }
+            self.delivered.pop_front();
+        }
+        if self.delivered.len() >= self.policy.max_per_window {
+            self.suppressed += 1;
+            return None;
+        }
+
+        self.last_sent.insert(dedup_key, now);
+        self.last_sent
+            .retain(|_, sent_at| now.duration_since(*sent_at) < self.policy.dedup_window);
+        self.delivered.push_back(now);
+        if self.suppressed > 0 {
+            alert.message = format!(
+                "{} ({} earlier alerts suppressed by rate limit)",
+                alert.message, self.suppressed
+            );
+            self.suppressed = 0;
+        }
+        Some(alert)
+    }
+}
+
+/// Destination for delivered alerts.
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn deliver(&self, alert: &AdminAlert) -> anyhow::Result<()>;
+}
+
+/// Posts alerts to a Discord-compatible webhook (`{"content": ...}`).
+pub struct WebhookAlertSink {
+    client: Client,
+    url: String,
+}
+
+impl WebhookAlertSink {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for WebhookAlertSink {
+    async fn deliver(&self, alert: &AdminAlert) -> anyhow::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(&json!({ "content": alert.render() }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Hands alerts to another task, e.g. the Discord adapter posting to the
+/// admin channel.
+pub struct ChannelAlertSink {
+    sender: mpsc::UnboundedSender<AdminAlert>,
+}
+
+impl ChannelAlertSink {
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<AdminAlert>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { sender }, receiver)
+    }
+}
+
+#[async_trait]
+impl AlertSink for ChannelAlertSink {
+    async fn deliver(&self, alert: &AdminAlert) -> anyhow::Result<()> {
+        self.sender
+            .send(alert.clone())
+            .map_err(|_| anyhow::anyhow!("alert channel receiver is gone"))
+    }
+}
+
+/// Drains the alert queue through the gate into every sink until all senders
+/// are dropped.
+pub async fn run_alert_dispatcher(
+    mut receiver: mpsc::UnboundedReceiver<AdminAlert>,
+    policy: AlertPolicy,
+    sinks: Vec<Box<dyn AlertSink>>,
+) {
+    let mut gate = AlertGate::new(policy);
+    while let Some(alert) = receiver.recv().await {
+        let Some(alert) = gate.admit(alert, Instant::now()) else {
+            continue;
+        };
+        for sink in &sinks {
+            if let Err(error) = sink.deliver(&alert).await {
+                warn!(?error, kind = alert.kind, "failed to deliver admin alert");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{AdminAlert, AlertGate, AlertPolicy};
+
+    fn alert(key: &str) -> AdminAlert {
+        AdminAlert {
+            kind: "tool_failure_rate",
+            key: key.to_owned(),
+            message: format!("{key} is failing"),
+        }
+    }
+
+    #[test]
+    fn deduplicates_and_rate_limits() {
+        let mut gate = AlertGate::new(AlertPolicy {
+            dedup_window: Duration::from_secs(60),
+            max_per_window: 2,
+            rate_window: Duration::from_secs(600),
+        });
+        let start = Instant::now();
+
+        assert!(gate.admit(alert("web_search"), start).is_some());
+        assert!(gate.admit(alert("web_search"), start).is_none());
+        assert!(gate.admit(alert("ocr"), start).is_some());
+        assert!(gate.admit(alert("spotify"), start).is_none());
+
+        let later = start + Duration::from_secs(601);
+        let delivered = gate
+            .admit(alert("spotify"), later)
+            .expect("rate window has passed");
+        assert!(delivered.message.contains("1 earlier alerts suppressed"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Longest reading kept from one message; sensors rarely need more.
+const MAX_READING_CHARS: usize = 120;
+
+/// An MQTT topic filter (`+` and `#` wildcards allowed) and how its readings
+/// are labelled in the prompt, e.g. `home/livingroom/temperature` as
+/// `living room temperature`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmbientTopic {
+    pub filter: String,
+    pub label: String,
+}
+
+/// Parses comma-separated `filter=label` pairs; a bare filter is labelled
+/// with its own text.
+pub fn parse_ambient_topics(raw: &str) -> Result<Vec<AmbientTopic>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (filter, label) = entry
+                .split_once('=')
+                .map_or((entry, entry), |(filter, label)| {
+                    (filter.trim(), label.trim())
+                });
+            if !valid_filter(filter) {
+                return Err(format!("`{filter}` is not a valid MQTT topic filter"));
+            }
+            Ok(AmbientTopic {
+                filter: filter.to_owned(),
+                label: if label.is_empty() { filter } else { label }.to_owned(),
+            })
+        })
+        .collect()
+}
+
+fn valid_filter(filter: &str) -> bool {
+    let levels = filter.split('/').collect::<Vec<_>>();
+    !filter.is_empty()
+        && levels
+            .iter()
+            .enumerate()
+            .all(|(index, level)| match *level {
+                "#" => index == levels.len() - 1,
+                "+" => true,
+                _ => !level.contains(['+', '#']),
+            })
+}
+
+/// Whether `topic` matches `filter` under MQTT wildcard rules.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic_level)) if level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Latest readings from subscribed home/IoT topics (room temperature, who is
+/// home), shown to the model as live context. Readings older than
+/// `stale_after` are left out rather than presented as current.
+#[derive(Debug)]
+pub struct AmbientContext {
+    topics: Vec<AmbientTopic>,
+    stale_after: Duration,
+    readings: Mutex<BTreeMap<String, (Instant, String)>>,
+}
+
+impl AmbientContext {
+    pub fn new(topics: Vec<AmbientTopic>, stale_after: Duration) -> Self {
+        Self {
+            topics,
+            stale_after,
+            readings: Mutex::default(),
+        }
+    }
+
+    pub fn topics(&self) -> &[AmbientTopic] {
+        &self.topics
+    }
+
+    /// Stores a message's payload under its topic's label; returns `false`
+    /// for topics no filter matches. An empty payload clears the reading.
+    pub fn record(&self, topic: &str, payload: &[u8]) -> bool {
+        let Some(label) = self.label_for(topic) else {
+            return false;
+        };
+        let value = String::from_utf8_lossy(payload);
+        let value = value.trim();
+        let mut readings = self.lock();
+        if value.is_empty() {
+            readings.remove(&label);
+        } else {
+            let value = value.chars().take(MAX_READING_CHARS).collect::<String>();
+            readings.insert(label, (Instant::now(), value));
+        }
+        true
+    }
+
+    /// `label: value` lines for the fresh readings, sorted by label.
+    pub fn lines(&self) -> Vec<String> {
+        self.lock()
+            .iter()
+            .filter(|(_, (received_at, _))| received_at.elapsed() < self.stale_after)
+            .map(|(label, (_, value))| format!("{label}: {value}"))
+            .collect()
+    }
+
+    /// The first matching topic's label; wildcard matches add the matched
+    /// topic so `home/+/temperature` readings stay apart per room.
+    fn label_for(&self, topic: &str) -> Option<String> {
+        let matched = self
+            .topics
+            .iter()
+            .find(|ambient| topic_matches(&ambient.filter, topic))?;
+        Some(if matched.filter.contains(['+', '#']) {
+            format!("{} ({topic})", matched.label)
+        } else {
+            matched.label.clone()
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, (Instant, String)>> {
+        self.readings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readings_are_labelled_by_matching_topic() {
+        let topics = parse_ambient_topics(
+            "home/livingroom/temperature=living room temperature, home/presence/+=home",
+        )
+        .expect("valid topics");
+        assert!(parse_ambient_topics("home/#/temperature").is_err());
+        assert!(topic_matches("home/#", "home/a/b"));
+        assert!(!topic_matches("home/+", "home/a/b"));
+
+        let ambient = AmbientContext::new(topics, Duration::from_secs(60));
+        assert!(ambient.record("home/livingroom/temperature", b" 21.5 "));
+        assert!(ambient.record("home/presence/petr", b"home"));
+        assert!(!ambient.record("garden/humidity", b"40"));
+        assert_eq!(
+            ambient.lines(),
+            vec![
+                "home (home/presence/petr): home".to_owned(),
+                "living room temperature: 21.5".to_owned(),
+            ]
+        );
+
+        ambient.record("home/presence/petr", b"");
+        assert_eq!(ambient.lines().len(), 1);
+
+        let stale = AmbientContext::new(ambient.topics().to_vec(), Duration::ZERO);
+        stale.record("home/livingroom/temperature", b"22");
+        assert!(stale.lines().is_empty());
+    }
+}
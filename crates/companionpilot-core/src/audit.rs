@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::types::AuditLogRecord;
+
+/// Request header naming the admin behind a dashboard/API change.
+pub const ACTOR_HEADER: &str = "x-cp-actor";
+
+/// Actor recorded when a request does not identify itself.
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Filters for `GET /api/admin/audit`; every field is optional.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditLogQuery {
+    #[serde(default)]
+    pub actor: Option<String>,
+    #[serde(default)]
+    pub action: Option<String>,
+    /// Exact target, e.g. `user:123` or `guild:456`.
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default = "default_audit_limit")]
+    pub limit: usize,
+}
+
+impl Default for AuditLogQuery {
+    fn default() -> Self {
+        Self {
+            actor: None,
+            action: None,
+            target: None,
+            since: None,
+            limit: default_audit_limit(),
+        }
+    }
+}
+
+fn default_audit_limit() -> usize {
+    200
+}
+
+impl AuditLogQuery {
+    pub fn matches(&self, record: &AuditLogRecord) -> bool {
+        self.actor
+            .as_ref()
+            .is_none_or(|actor| *actor == record.actor)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| *action == record.action)
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|target| *target == record.target)
+            && self.since.is_none_or(|since| record.timestamp >= since)
+    }
+}
+
+pub fn user_target(user_id: &str) -> String {
+    format!("user:{user_id}")
+}
+
+pub fn guild_target(guild_id: &str) -> String {
+    format!("guild:{guild_id}")
+}
+
+pub fn persona_target(name: &str) -> String {
+    format!("persona:{name}")
+}
+
+pub fn command_target(name: &str) -> String {
+    format!("command:{name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{AuditLogQuery, user_target};
+    use crate::types::AuditLogRecord;
+
+    #[test]
+    fn query_filters_by_every_given_field() {
+        let now = Utc::now();
+        let record = AuditLogRecord {
+            actor: "petr".to_owned(),
+            action: "fact.delete".to_owned(),
+            target: user_target("42"),
+            before_json: Some(r#"{"key":"city"}"#.to_owned()),
+            after_json: None,
+            timestamp: now,
+        };
+
+        assert!(AuditLogQuery::default().matches(&record));
+        assert!(
+            AuditLogQuery {
+                actor: Some("petr".to_owned()),
+                target: Some("user:42".to_owned()),
+                since: Some(now - Duration::hours(1)),
+                ..AuditLogQuery::default()
+            }
+            .matches(&record)
+        );
+        assert!(
+            !AuditLogQuery {
+                action: Some("facts.clear".to_owned()),
+                ..AuditLogQuery::default()
+            }
+            .matches(&record)
+        );
+        assert!(
+            !AuditLogQuery {
+                since: Some(now + Duration::seconds(1)),
+                ..AuditLogQuery::default()
+            }
+            .matches(&record)
+        );
+    }
+}
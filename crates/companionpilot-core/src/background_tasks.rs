@@ -0,0 +1,236 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    memory::MemoryStore,
+    redaction::Redactor,
+    tools::{ToolExecutor, ToolResult},
+    types::{
+        BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord, ChatRole, MessageCtx,
+        Modality,
+    },
+};
+
+/// How long a background tool may run unless configured otherwise.
+pub const DEFAULT_BACKGROUND_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+tokio::task_local! {
+    /// The task a background tool call runs for, so the tool can report
+    /// progress without knowing about background tasks.
+    static CURRENT_TASK: ProgressReporter;
+}
+
+#[derive(Clone)]
+struct ProgressReporter {
+    task: BackgroundTaskRecord,
+    memory: Arc<dyn MemoryStore>,
+    redactor: Option<Arc<Redactor>>,
+    background: BackgroundTasks,
+}
+
+/// `text` as it may be stored.
+fn redacted<'a>(redactor: &Option<Arc<Redactor>>, text: &'a str) -> std::borrow::Cow<'a, str> {
+    match redactor {
+        Some(redactor) => redactor.redact(text),
+        None => text.into(),
+    }
+}
+
+/// Stores a progress note on the current background task and passes it on
+/// for delivery; a no-op when the tool is not running as a background task.
+pub async fn report_progress(text: &str) {
+    let Ok(reporter) = CURRENT_TASK.try_with(ProgressReporter::clone) else {
+        return;
+    };
+    let now = Utc::now();
+    if let Err(error) = reporter
+        .memory
+        .update_background_task(
+            &reporter.task.id,
+            BackgroundTaskStatus::Running,
+            Some(&redacted(&reporter.redactor, text)),
+            now,
+        )
+        .await
+    {
+        warn!(?error, task_id = %reporter.task.id, "failed to store background task progress");
+    }
+    reporter.background.complete(BackgroundTaskRecord {
+        result_text: Some(text.to_owned()),
+        updated_at: now,
+        ..reporter.task
+    });
+}
+
+/// Tools that take minutes run detached from the turn: the planner's call
+/// answers at once with a "started" note, and the finished task is sent to
+/// the receiving half (e.g. the Discord adapter) to post as a follow-up.
+/// Cheap to clone; sending is a no-op once the receiver is gone.
+#[derive(Debug, Clone)]
+pub struct BackgroundTasks {
+    tools: Arc<HashSet<String>>,
+    timeout: Duration,
+    completed: mpsc::UnboundedSender<BackgroundTaskRecord>,
+}
+
+impl BackgroundTasks {
+    pub fn channel(
+        tools: HashSet<String>,
+        timeout: Duration,
+    ) -> (Self, mpsc::UnboundedReceiver<BackgroundTaskRecord>) {
+        let (completed, receiver) = mpsc::unbounded_channel();
+        (
+            Self {
+                tools: Arc::new(tools),
+                timeout,
+                completed,
+            },
+            receiver,
+        )
+    }
+
+    /// Longest a task may run; also how long a replica's claim on it lasts.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Whether calls to `tool_name` run in the background.
+    pub fn handles(&self, tool_name: &str) -> bool {
+        self.tools.contains(tool_name)
+    }
+
+    /// Hands a finished task, or a still running one with a progress note,
+    /// to whoever delivers the follow-up.
+    pub fn complete(&self, task: BackgroundTaskRecord) {
+        let _ = self.completed.send(task);
+    }
+}
+
+/// What the planner's tool call returns once its task is started.
+pub fn task_started_result(task: &BackgroundTaskRecord) -> ToolResult {
+    ToolResult {
+        text: format!(
+            "Started background task {} for `{}`. It can take a few minutes. Tell the user you are working on it and that the result will be posted here when it is ready; do not make up the result.",
+            task.id, task.tool_name
+        ),
+        citations: Vec::new(),
+        data: Some(serde_json::json!({
+            "background_task_id": task.id,
+            "status": task.status,
+        })),
+    }
+}
+
+/// Runs the task's tool to completion, stores the outcome (also as an
+/// assistant message, so later turns can refer to it) and passes the task on
+/// for delivery.
+pub async fn run_background_task<T>(
+    tools: Arc<T>,
+    memory: Arc<dyn MemoryStore>,
+    redactor: Option<Arc<Redactor>>,
+    background: BackgroundTasks,
+    mut task: BackgroundTaskRecord,
+) where
+    T: ToolExecutor + ?Sized,
+{
+    info!(task_id = %task.id, tool_name = %task.tool_name, "background task started");
+    let ctx = MessageCtx {
+        message_id: format!("task-{}", task.id),
+        user_id: task.user_id.clone(),
+        guild_id: task.guild_id.clone(),
+        channel_id: task.channel_id.clone(),
+        content: String::new(),
+        timestamp: task.created_at,
+        attachments: Vec::new(),
+    };
+    let reporter = ProgressReporter {
+        task: task.clone(),
+        memory: memory.clone(),
+        redactor: redactor.clone(),
+        background: background.clone(),
+    };
+    let outcome = CURRENT_TASK
+        .scope(
+            reporter,
+            tokio::time::timeout(
+                background.timeout,
+                tools.execute(&task.tool_name, task.args.clone(), &ctx),
+            ),
+        )
+        .await;
+    let (status, result_text) = match outcome {
+        Ok(Ok(result)) => (BackgroundTaskStatus::Done, result_with_sources(result)),
+        Ok(Err(error)) => (BackgroundTaskStatus::Failed, error.to_string()),
+        Err(_) => (
+            BackgroundTaskStatus::Failed,
+            format!("timed out after {} seconds", background.timeout.as_secs()),
+        ),
+    };
+    let now = Utc::now();
+    let stored_text = redacted(&redactor, &result_text);
+    if let Err(error) = memory
+        .update_background_task(&task.id, status, Some(&stored_text), now)
+        .await
+    {
+        warn!(?error, task_id = %task.id, "failed to store background task result");
+    }
+    if status == BackgroundTaskStatus::Done
+        && let Err(error) = memory
+            .record_chat_message(ChatMessageRecord {
+                id: ctx.message_id.clone(),
+                user_id: task.user_id.clone(),
+                guild_id: task.guild_id.clone(),
+                channel_id: task.channel_id.clone(),
+                role: ChatRole::Assistant,
+                content: stored_text.clone().into_owned(),
+                timestamp: now,
+                modality: Modality::Text,
+                session_id: None,
+                pinned: false,
+                source_message_id: None,
+            })
+            .await
+    {
+        warn!(?error, task_id = %task.id, "failed to record background task result");
+    }
+    info!(
+        task_id = %task.id,
+        tool_name = %task.tool_name,
+        status = status.as_str(),
+        "background task finished"
+    );
+    task.status = status;
+    task.result_text = Some(result_text);
+    task.updated_at = now;
+    background.complete(task);
+}
+
+fn result_with_sources(result: ToolResult) -> String {
+    if result.citations.is_empty() {
+        return result.text;
+    }
+    let sources = result
+        .citations
+        .iter()
+        .map(|citation| format!("- {citation}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n\nSources:\n{sources}", result.text)
+}
+
+/// The follow-up message for a finished task, or the progress note of a
+/// running one.
+pub fn render_task_followup(task: &BackgroundTaskRecord) -> String {
+    let result = task.result_text.as_deref().unwrap_or_default();
+    match task.status {
+        BackgroundTaskStatus::Running => format!("-# {result}"),
+        BackgroundTaskStatus::Failed => format!(
+            "Sorry, the `{}` task I was working on failed: {result}",
+            task.tool_name
+        ),
+        _ => result.to_owned(),
+    }
+}
@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+#[cfg(feature = "http")]
+use axum::Router;
+use tracing::warn;
+
+#[cfg(feature = "http")]
+use crate::http::{self, AppState};
+#[cfg(feature = "voice")]
+use crate::voice::VoiceManager;
+use crate::{
+    alerts::AdminAlerts,
+    ambient::AmbientContext,
+    background_tasks::BackgroundTasks,
+    calendar::CalendarFeeds,
+    coordination::InstanceCoordinator,
+    events::EventMappings,
+    memory::{InMemoryMemoryStore, MemoryStore},
+    model::{MockModelProvider, ModelProvider},
+    model_probe::{ModelLatencyProbe, ModelProbeConfig, run_model_probe},
+    orchestrator::{ChatOrchestrator, DefaultChatOrchestrator, OrchestratorConfig},
+    personas::PersonaRegistry,
+    prompt_rollout::{PromptRollouts, RolloutPolicy, run_prompt_rollouts},
+    redaction::Redactor,
+    reply_filters::ReplyPipelines,
+    safety::SafetyPolicy,
+    tool_macros::ToolMacroRegistry,
+    tools::{MockToolExecutor, ToolExecutor, ToolRegistry},
+    user_keys::UserKeyVault,
+    widgets::WidgetRegistry,
+};
+
+/// Wires an orchestrator and HTTP router for embedding CompanionPilot in
+/// another application. Unset parts fall back to the mock model, in-memory
+/// store and an empty tool registry.
+#[derive(Default)]
+pub struct CompanionPilotBuilder {
+    model: Option<Arc<dyn ModelProvider>>,
+    memory: Option<Arc<dyn MemoryStore>>,
+    tools: Option<Arc<dyn ToolExecutor>>,
+    safety: SafetyPolicy,
+    config: OrchestratorConfig,
+    personas: Arc<PersonaRegistry>,
+    admin_alerts: Option<AdminAlerts>,
+    simulated_tools: Option<Arc<MockToolExecutor>>,
+    tool_macros: Arc<ToolMacroRegistry>,
+    widgets: Arc<WidgetRegistry>,
+    event_mappings: Arc<EventMappings>,
+    coordinator: Option<Arc<dyn InstanceCoordinator>>,
+    model_probe: Option<ModelProbeConfig>,
+    reply_pipelines: Option<Arc<ReplyPipelines>>,
+    ambient: Option<Arc<AmbientContext>>,
+    background_tasks: Option<BackgroundTasks>,
+    redactor: Option<Arc<Redactor>>,
+    user_keys: Option<Arc<UserKeyVault>>,
+    calendar_feeds: Option<Arc<CalendarFeeds>>,
+    prompt_rollouts: Option<RolloutPolicy>,
+    #[cfg(feature = "voice")]
+    voice: Option<Arc<VoiceManager>>,
+}
+
+impl CompanionPilotBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn model(mut self, model: Arc<dyn ModelProvider>) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn memory(mut self, memory: Arc<dyn MemoryStore>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    pub fn tools(mut self, tools: Arc<dyn ToolExecutor>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    pub fn safety(mut self, safety: SafetyPolicy) -> Self {
+        self.safety = safety;
+        self
+    }
+
+    /// Orchestrator settings, including defaults for guilds without their own.
+    pub fn settings(mut self, config: OrchestratorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn personas(mut self, personas: Arc<PersonaRegistry>) -> Self {
+        self.personas = personas;
+        self
+    }
+
+    pub fn admin_alerts(mut self, admin_alerts: AdminAlerts) -> Self {
+        self.admin_alerts = Some(admin_alerts);
+        self
+    }
+
+    /// Canned tool outputs used when simulation mode is on, globally or per
+    /// turn.
+    pub fn simulated_tools(mut self, simulated_tools: Arc<MockToolExecutor>) -> Self {
+        self.simulated_tools = Some(simulated_tools);
+        self
+    }
+
+    /// Named tool chains the planner may request as one call.
+    pub fn tool_macros(mut self, tool_macros: Arc<ToolMacroRegistry>) -> Self {
+        self.tool_macros = tool_macros;
+        self
+    }
+
+    /// Public chat widgets served by the router.
+    pub fn widgets(mut self, widgets: Arc<WidgetRegistry>) -> Self {
+        self.widgets = widgets;
+        self
+    }
+
+    /// How events pushed to `POST /api/events` are written to memory.
+    pub fn event_mappings(mut self, event_mappings: Arc<EventMappings>) -> Self {
+        self.event_mappings = event_mappings;
+        self
+    }
+
+    /// Home/IoT readings added to context for the configured users; the
+    /// caller keeps it updated, e.g. from an MQTT subscriber.
+    pub fn ambient_context(mut self, ambient: Arc<AmbientContext>) -> Self {
+        self.ambient = Some(ambient);
+        self
+    }
+
+    /// Long-running tools run detached from the turn; finished tasks go to
+    /// the receiver paired with `background_tasks`.
+    pub fn background_tasks(mut self, background_tasks: BackgroundTasks) -> Self {
+        self.background_tasks = Some(background_tasks);
+        self
+    }
+
+    /// Personal data masking applied to logs before they are stored.
+    pub fn redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Lets users register their own model keys, stored encrypted.
+    pub fn user_keys(mut self, user_keys: Arc<UserKeyVault>) -> Self {
+        self.user_keys = Some(user_keys);
+        self
+    }
+
+    /// Serves users' birthdays and anniversaries as token-protected iCal
+    /// feeds.
+    pub fn calendar_feeds(mut self, calendar_feeds: Arc<CalendarFeeds>) -> Self {
+        self.calendar_feeds = Some(calendar_feeds);
+        self
+    }
+
+    /// Rolls persona updates out to part of the traffic first, promoting or
+    /// rolling them back under `policy`.
+    pub fn prompt_rollouts(mut self, policy: RolloutPolicy) -> Self {
+        self.prompt_rollouts = Some(policy);
+        self
+    }
+
+    /// Locks shared with other replicas of this deployment.
+    pub fn coordinator(mut self, coordinator: Arc<dyn InstanceCoordinator>) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+
+    /// Probes the model on a schedule to keep it warm and track its latency.
+    pub fn model_probe(mut self, config: ModelProbeConfig) -> Self {
+        self.model_probe = Some(config);
+        self
+    }
+
+    /// Reply post-processing for Discord, HTTP and voice, replacing the
+    /// default filter chains.
+    pub fn reply_pipelines(mut self, reply_pipelines: ReplyPipelines) -> Self {
+        self.reply_pipelines = Some(Arc::new(reply_pipelines));
+        self
+    }
+
+    /// Voice manager that replies through the built orchestrator.
+    #[cfg(feature = "voice")]
+    pub fn voice(mut self, voice: Arc<VoiceManager>) -> Self {
+        self.voice = Some(voice);
+        self
+    }
+
+    /// Builds the orchestrator and, when voice is configured, connects the
+    /// voice manager to it and starts its idle reaper.
+    pub async fn build(self) -> CompanionPilot {
+        let memory = self
+            .memory
+            .unwrap_or_else(|| Arc::new(InMemoryMemoryStore::default()));
+        let model = self.model.unwrap_or_else(|| Arc::new(MockModelProvider));
+        let mut orchestrator = DefaultChatOrchestrator::new(
+            model.clone(),
+            memory.clone(),
+            self.tools
+                .unwrap_or_else(|| Arc::new(ToolRegistry::default())),
+            self.safety,
+        )
+        .with_config(self.config)
+        .with_personas(self.personas.clone())
+        .with_tool_macros(self.tool_macros);
+        if let Some(config) = self.model_probe {
+            let probe = Arc::new(ModelLatencyProbe::new(config));
+            orchestrator = orchestrator.with_model_probe(probe.clone());
+            tokio::spawn(run_model_probe(probe, model, self.admin_alerts.clone()));
+        }
+        if let Some(admin_alerts) = self.admin_alerts {
+            orchestrator = orchestrator.with_admin_alerts(admin_alerts);
+        }
+        if let Some(simulated_tools) = self.simulated_tools {
+            orchestrator = orchestrator.with_simulated_tools(simulated_tools);
+        }
+        if let Some(coordinator) = self.coordinator {
+            orchestrator = orchestrator.with_coordinator(coordinator);
+        }
+        if let Some(reply_pipelines) = self.reply_pipelines {
+            orchestrator = orchestrator.with_reply_pipelines(reply_pipelines);
+        }
+        if let Some(ambient) = self.ambient {
+            orchestrator = orchestrator.with_ambient_context(ambient);
+        }
+        if let Some(redactor) = self.redactor {
+            orchestrator = orchestrator.with_redactor(redactor);
+        }
+        if let Some(user_keys) = self.user_keys {
+            orchestrator = orchestrator.with_user_keys(user_keys);
+        }
+        if let Some(calendar_feeds) = self.calendar_feeds {
+            orchestrator = orchestrator.with_calendar_feeds(calendar_feeds);
+        }
+        let prompt_rollouts = self
+            .prompt_rollouts
+            .map(|policy| Arc::new(PromptRollouts::new(policy)));
+        if let Some(rollouts) = &prompt_rollouts {
+            orchestrator = orchestrator.with_prompt_rollouts(rollouts.clone());
+        }
+        let resume_tasks = self.background_tasks.is_some();
+        if let Some(background_tasks) = self.background_tasks {
+            orchestrator = orchestrator.with_background_tasks(background_tasks);
+        }
+        let orchestrator = Arc::new(orchestrator);
+        if resume_tasks {
+            let orchestrator = orchestrator.clone();
+            tokio::spawn(async move {
+                if let Err(error) = orchestrator.resume_background_tasks().await {
+                    warn!(?error, "failed to resume background tasks");
+                }
+            });
+        }
+        let orchestrator: Arc<dyn ChatOrchestrator> = orchestrator;
+        if let Some(rollouts) = prompt_rollouts {
+            tokio::spawn(run_prompt_rollouts(
+                rollouts,
+                self.personas.clone(),
+                orchestrator.clone(),
+            ));
+        }
+
+        #[cfg(feature = "voice")]
+        if let Some(voice) = &self.voice {
+            voice.set_orchestrator(orchestrator.clone()).await;
+            voice.start_idle_reaper();
+        }
+
+        CompanionPilot {
+            orchestrator,
+            memory,
+            personas: self.personas,
+            widgets: self.widgets,
+            event_mappings: self.event_mappings,
+            #[cfg(feature = "voice")]
+            voice: self.voice,
+        }
+    }
+}
+
+/// A wired CompanionPilot instance.
+pub struct CompanionPilot {
+    pub orchestrator: Arc<dyn ChatOrchestrator>,
+    pub memory: Arc<dyn MemoryStore>,
+    pub personas: Arc<PersonaRegistry>,
+    pub widgets: Arc<WidgetRegistry>,
+    pub event_mappings: Arc<EventMappings>,
+    #[cfg(feature = "voice")]
+    pub voice: Option<Arc<VoiceManager>>,
+}
+
+impl CompanionPilot {
+    pub fn builder() -> CompanionPilotBuilder {
+        CompanionPilotBuilder::new()
+    }
+
+    /// The chat API and dashboard routes served by the bundled binary.
+    #[cfg(feature = "http")]
+    pub fn router(&self) -> Router {
+        http::router(AppState {
+            orchestrator: self.orchestrator.clone(),
+            memory: self.memory.clone(),
+            personas: self.personas.clone(),
+            widgets: self.widgets.clone(),
+            event_mappings: self.event_mappings.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::CompanionPilot;
+    use crate::types::MessageCtx;
+
+    #[tokio::test]
+    async fn default_build_answers_and_records_history() {
+        let companion = CompanionPilot::builder().build().await;
+        #[cfg(feature = "http")]
+        let _router = companion.router();
+
+        companion
+            .orchestrator
+            .handle_message(MessageCtx {
+                message_id: "1".into(),
+                user_id: "u1".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "hello there".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("handle message should succeed");
+
+        let history = companion
+            .memory
+            .list_chat_messages("u1", 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 2);
+    }
+}
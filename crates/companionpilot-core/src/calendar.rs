@@ -0,0 +1,243 @@
+use std::fmt;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::{
+    date_facts::{DateFact, DateFactKind},
+    memory::MemoryStore,
+};
+
+const FACT_SCAN_LIMIT: usize = 1_000;
+/// Longest content line before it is folded, in octets (RFC 5545 3.1).
+const MAX_LINE_OCTETS: usize = 75;
+/// Random bytes in a feed salt.
+const SALT_BYTES: usize = 16;
+
+/// Read-only iCal feeds of users' birthdays and anniversaries, the dates
+/// the greeting scheduler watches, for subscribing from a phone calendar.
+/// Calendar apps cannot send headers, so each feed URL carries a token: an
+/// HMAC of the user id and their feed salt under the server secret. A new
+/// salt revokes one user's links; changing the secret revokes every
+/// subscription.
+pub struct CalendarFeeds {
+    key: hmac::Key,
+    /// Where the API is reachable from outside, for building feed links.
+    public_url: Option<String>,
+}
+
+impl fmt::Debug for CalendarFeeds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CalendarFeeds")
+            .field("public_url", &self.public_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CalendarFeeds {
+    pub fn new(secret: &str, public_url: Option<&str>) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            public_url: public_url.map(|url| url.trim().trim_end_matches('/').to_owned()),
+        }
+    }
+
+    /// The token in the user's feed URL, for their current `salt`.
+    pub fn token(&self, user_id: &str, salt: &str) -> String {
+        URL_SAFE_NO_PAD.encode(hmac::sign(&self.key, &feed_message(user_id, salt)))
+    }
+
+    pub fn verify(&self, user_id: &str, salt: &str, token: &str) -> bool {
+        URL_SAFE_NO_PAD
+            .decode(token.trim())
+            .is_ok_and(|tag| hmac::verify(&self.key, &feed_message(user_id, salt), &tag).is_ok())
+    }
+
+    /// Feed path with its token, relative to the API root.
+    pub fn feed_path(&self, user_id: &str, salt: &str) -> String {
+        format!(
+            "/api/users/{user_id}/calendar.ics?token={}",
+            self.token(user_id, salt)
+        )
+    }
+
+    /// The full subscription URL, when the public URL is configured.
+    pub fn feed_url(&self, user_id: &str, salt: &str) -> Option<String> {
+        let public_url = self.public_url.as_ref()?;
+        Some(format!("{public_url}{}", self.feed_path(user_id, salt)))
+    }
+}
+
+fn feed_message(user_id: &str, salt: &str) -> Vec<u8> {
+    format!("calendar:{user_id}:{salt}").into_bytes()
+}
+
+/// The user's current feed salt; empty until their link is first rotated.
+pub async fn feed_salt(store: &dyn MemoryStore, user_id: &str) -> anyhow::Result<String> {
+    Ok(store
+        .get_calendar_feed_salt(user_id)
+        .await?
+        .unwrap_or_default())
+}
+
+/// Gives the user a new feed salt, so links handed out before stop working.
+pub async fn rotate_feed_salt(store: &dyn MemoryStore, user_id: &str) -> anyhow::Result<()> {
+    let mut salt = [0u8; SALT_BYTES];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("no randomness available for a feed salt"))?;
+    store
+        .put_calendar_feed_salt(user_id, &URL_SAFE_NO_PAD.encode(salt))
+        .await
+}
+
+/// The user's calendar: one yearly all-day event per date fact.
+pub async fn user_calendar(
+    store: &dyn MemoryStore,
+    user_id: &str,
+    now: DateTime<Utc>,
+) -> anyhow::Result<String> {
+    let dates = store
+        .list_facts(user_id, FACT_SCAN_LIMIT)
+        .await?
+        .iter()
+        .filter_map(DateFact::from_fact)
+        .collect::<Vec<_>>();
+    Ok(render_calendar(user_id, &dates, now))
+}
+
+pub fn render_calendar(user_id: &str, dates: &[DateFact], now: DateTime<Utc>) -> String {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//CompanionPilot//Calendar Feed//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+        "METHOD:PUBLISH".to_owned(),
+        "X-WR-CALNAME:CompanionPilot".to_owned(),
+    ];
+    for date in dates {
+        // A leap year, so February 29 stays valid without a year.
+        let year = date.year.unwrap_or(2000);
+        // Like the greetings, February 29 falls on February 28 in other years.
+        let rule = if date.month == 2 && date.day == 29 {
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+        } else {
+            "FREQ=YEARLY"
+        };
+        let summary = match date.kind {
+            DateFactKind::Birthday => "Birthday".to_owned(),
+            DateFactKind::Anniversary => capitalize(&date.label()),
+        };
+        lines.extend([
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:{}-{user_id}@companionpilot", date.key),
+            format!("DTSTAMP:{stamp}"),
+            format!(
+                "DTSTART;VALUE=DATE:{year:04}{:02}{:02}",
+                date.month, date.day
+            ),
+            format!("RRULE:{rule}"),
+            format!("SUMMARY:{}", escape_text(&summary)),
+            "TRANSP:TRANSPARENT".to_owned(),
+            "END:VEVENT".to_owned(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_owned());
+    lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits a content line into lines of at most 75 octets, continuations
+/// starting with a space.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for ch in line.chars() {
+        if octets + ch.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(ch);
+        octets += ch.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::memory::InMemoryMemoryStore;
+
+    #[tokio::test]
+    async fn rotating_the_salt_revokes_only_that_users_link() {
+        let store = InMemoryMemoryStore::default();
+        let feeds = CalendarFeeds::new("calendar-secret-for-tests", None);
+        let old_token = feeds.token("42", &feed_salt(&store, "42").await.expect("salt"));
+        let other_token = feeds.token("43", &feed_salt(&store, "43").await.expect("salt"));
+
+        rotate_feed_salt(&store, "42").await.expect("rotated");
+        let salt = feed_salt(&store, "42").await.expect("salt");
+        assert!(!salt.is_empty());
+        assert!(!feeds.verify("42", &salt, &old_token));
+        assert!(feeds.verify("42", &salt, &feeds.token("42", &salt)));
+        let other_salt = feed_salt(&store, "43").await.expect("salt");
+        assert!(feeds.verify("43", &other_salt, &other_token));
+    }
+
+    #[test]
+    fn renders_yearly_events_behind_a_per_user_token() {
+        let feeds = CalendarFeeds::new("calendar-secret-for-tests", Some("https://cp.example/"));
+        let token = feeds.token("42", "");
+        assert!(feeds.verify("42", "", &token));
+        assert!(!feeds.verify("43", "", &token));
+        assert!(!feeds.verify("42", "", "not-a-token"));
+        assert!(!feeds.verify("42", "rotated", &token));
+        assert_eq!(
+            feeds.feed_url("42", ""),
+            Some(format!(
+                "https://cp.example/api/users/42/calendar.ics?token={token}"
+            ))
+        );
+
+        let dates = [
+            DateFact::parse("birthday", "29 February 2000").expect("date fact"),
+            DateFact::parse("wedding_anniversary", "06-20").expect("date fact"),
+        ];
+        let now = Utc
+            .with_ymd_and_hms(2026, 3, 1, 12, 0, 0)
+            .single()
+            .expect("valid timestamp");
+        let calendar = render_calendar("42", &dates, now);
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains(
+            "DTSTART;VALUE=DATE:20000229\r\nRRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1\r\n"
+        ));
+        assert!(calendar.contains("SUMMARY:Wedding anniversary\r\n"));
+        assert!(calendar.contains("UID:wedding_anniversary-42@companionpilot\r\n"));
+        assert!(calendar.contains("DTSTAMP:20260301T120000Z\r\n"));
+
+        let folded = fold_line(&"x".repeat(100));
+        assert_eq!(folded, format!("{}\r\n {}", "x".repeat(75), "x".repeat(25)));
+    }
+}
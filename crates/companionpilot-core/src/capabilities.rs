@@ -0,0 +1,118 @@
+use serde::Serialize;
+
+use crate::tools::ToolSpec;
+
+/// What the deployment runs on, set once at startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeploymentInfo {
+    /// The default model replies are generated with.
+    pub model: String,
+    /// `postgres` or `in_memory`.
+    pub memory_backend: String,
+    /// Whether the companion can join Discord voice channels.
+    pub voice: bool,
+}
+
+/// What the companion can do right now: served by `GET /api/capabilities`
+/// and given to the model when a user asks, so both describe the same
+/// deployment.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub model: String,
+    pub memory_backend: String,
+    pub voice: bool,
+    /// The configured tools, with their argument schemas.
+    pub tools: Vec<ToolSpec>,
+}
+
+impl Capabilities {
+    pub fn new(deployment: &DeploymentInfo, tools: Vec<ToolSpec>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            model: deployment.model.clone(),
+            memory_backend: deployment.memory_backend.clone(),
+            voice: deployment.voice,
+            tools,
+        }
+    }
+
+    /// System prompt section for answering "what can you do?".
+    pub fn prompt_block(&self) -> String {
+        let mut lines = vec![
+            "The user is asking what you can do. Answer from this list only and do not claim abilities that are not on it:".to_owned(),
+            "- Chat, and remember facts the user shares about themselves across conversations; they can ask what you remember or ask you to forget something.".to_owned(),
+        ];
+        lines.extend(
+            self.tools
+                .iter()
+                .map(|tool| format!("- Tool `{}`: {}", tool.tool_name, tool.when_to_use)),
+        );
+        lines.push(if self.voice {
+            "- Join Discord voice channels and talk out loud.".to_owned()
+        } else {
+            "- Voice chat is not available in this deployment.".to_owned()
+        });
+        lines.push(format!(
+            "You run on the model `{}` (CompanionPilot {}).",
+            self.model, self.version
+        ));
+        lines.join("\n")
+    }
+}
+
+/// Whether a message asks what the companion can do.
+pub fn is_capability_question(text: &str) -> bool {
+    const PHRASES: &[&str] = &[
+        "what can you do",
+        "what are you able to do",
+        "what are you capable of",
+        "what are your capabilities",
+        "what are your abilities",
+        "what can you help",
+        "what tools do you have",
+        "which tools do you have",
+        "what tools can you use",
+        "what features do you have",
+    ];
+    let normalized = text
+        .to_lowercase()
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    PHRASES.iter().any(|phrase| normalized.contains(phrase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::builtin_tool_specs;
+
+    #[test]
+    fn describes_the_configured_deployment() {
+        assert!(is_capability_question("Hey, what can you do?"));
+        assert!(is_capability_question("so... what tools do you have"));
+        assert!(!is_capability_question("what can we do this weekend?"));
+
+        let tools = builtin_tool_specs()
+            .into_iter()
+            .filter(|spec| spec.tool_name == "current_datetime")
+            .collect();
+        let capabilities = Capabilities::new(
+            &DeploymentInfo {
+                model: "openai/gpt-4o-mini".to_owned(),
+                memory_backend: "postgres".to_owned(),
+                voice: false,
+            },
+            tools,
+        );
+        let block = capabilities.prompt_block();
+        assert!(block.contains("Tool `current_datetime`"));
+        assert!(!block.contains("web_search"));
+        assert!(block.contains("Voice chat is not available"));
+        assert!(block.contains("`openai/gpt-4o-mini`"));
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, Semaphore, SemaphorePermit};
+
+use crate::{
+    coordination::ConversationLocked,
+    types::{MessageCtx, content_with_attachments, content_without_attachments},
+};
+
+/// Reply text surfaces show when an orchestration is rejected as busy.
+pub const BUSY_REPLY_TEXT: &str =
+    "I'm handling a lot of conversations right now. Please try again in a moment.";
+
+/// Returned (inside `anyhow::Error`) when both the running slots and the wait
+/// queue are full. Callers can `downcast_ref` it to answer with a busy message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrchestratorBusy {
+    pub max_concurrent: usize,
+    pub max_queued: usize,
+}
+
+impl fmt::Display for OrchestratorBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "orchestrator is busy ({} running, {} queued)",
+            self.max_concurrent, self.max_queued
+        )
+    }
+}
+
+impl std::error::Error for OrchestratorBusy {}
+
+/// Whether a turn failed because no capacity was free, here or on another
+/// instance holding the conversation; surfaces answer with [`BUSY_REPLY_TEXT`].
+pub fn is_busy(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<OrchestratorBusy>().is_some()
+        || error.downcast_ref::<ConversationLocked>().is_some()
+}
+
+/// Caps concurrent orchestrations and bounds how many requests may wait for a
+/// free slot.
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    semaphore: Semaphore,
+    waiting: AtomicUsize,
+    max_concurrent: usize,
+    max_queued: usize,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            semaphore: Semaphore::new(max_concurrent),
+            waiting: AtomicUsize::new(0),
+            max_concurrent,
+            max_queued,
+        }
+    }
+
+    /// Takes a running slot, waiting in the queue if needed. Fails immediately
+    /// when the queue is already full.
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>, OrchestratorBusy> {
+        if let Ok(permit) = self.semaphore.try_acquire() {
+            return Ok(permit);
+        }
+
+        let busy = OrchestratorBusy {
+            max_concurrent: self.max_concurrent,
+            max_queued: self.max_queued,
+        };
+        self.waiting
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |waiting| {
+                (waiting < self.max_queued).then_some(waiting + 1)
+            })
+            .map_err(|_| busy)?;
+
+        let _queued = QueuedSlot(&self.waiting);
+        self.semaphore.acquire().await.map_err(|_| busy)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.waiting.load(Ordering::Acquire)
+    }
+}
+
+/// Leaves the wait queue on drop, including when the waiting future is
+/// cancelled.
+struct QueuedSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// How messages in the same (user, channel) conversation are ordered while an
+/// earlier turn is still running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConversationSequencing {
+    /// Turns run concurrently (previous behavior).
+    Off,
+    /// Turns run one after another in arrival order.
+    #[default]
+    Queue,
+    /// Messages that arrive during a running turn are combined into a single
+    /// follow-up turn.
+    Merge,
+}
+
+impl ConversationSequencing {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Self::Off),
+            "queue" => Some(Self::Queue),
+            "merge" => Some(Self::Merge),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of entering a conversation.
+pub enum ConversationTurn<'a> {
+    /// Run this message (possibly with merged content) while holding the turn.
+    Run {
+        message: Box<MessageCtx>,
+        guard: Option<ConversationGuard<'a>>,
+    },
+    /// The message was folded into the turn started by `merged_into`.
+    Merged { merged_into: String },
+}
+
+#[derive(Default)]
+struct ConversationSlot {
+    turn: Arc<AsyncMutex<()>>,
+    pending: Mutex<Vec<MessageCtx>>,
+    merged: Mutex<HashMap<String, String>>,
+}
+
+type ConversationKey = (String, String);
+
+/// Serializes turns per (user, channel) so later messages see the replies to
+/// earlier ones.
+pub struct ConversationSequencer {
+    mode: ConversationSequencing,
+    slots: Mutex<HashMap<ConversationKey, Arc<ConversationSlot>>>,
+}
+
+impl ConversationSequencer {
+    pub fn new(mode: ConversationSequencing) -> Self {
+        Self {
+            mode,
+            slots: Mutex::default(),
+        }
+    }
+
+    pub async fn enter(&self, message: MessageCtx) -> ConversationTurn<'_> {
+        if self.mode == ConversationSequencing::Off {
+            return ConversationTurn::Run {
+                message: Box::new(message),
+                guard: None,
+            };
+        }
+        if self.mode == ConversationSequencing::Merge {
+            let waiter = self.queue_for_merge(message);
+            return self.take_merged_turn(waiter).await;
+        }
+
+        let key = (message.user_id.clone(), message.channel_id.clone());
+        let slot = self.slot(&key);
+        let turn = Arc::clone(&slot.turn).lock_owned().await;
+        ConversationTurn::Run {
+            message: Box::new(message),
+            guard: Some(ConversationGuard {
+                sequencer: self,
+                key,
+                slot,
+                _turn: turn,
+            }),
+        }
+    }
+
+    /// Adds the message to its conversation's pending batch.
+    fn queue_for_merge(&self, message: MessageCtx) -> MergeWaiter {
+        let key = (message.user_id.clone(), message.channel_id.clone());
+        let slot = self.slot(&key);
+        let message_id = message.message_id.clone();
+        lock(&slot.pending).push(message);
+        MergeWaiter {
+            key,
+            slot,
+            message_id,
+            waiting: true,
+        }
+    }
+
+    /// Waits for the turn, then runs everything pending as the waiter's own
+    /// message, unless an earlier turn already took it. Waiters can reach the
+    /// turn in another order than they queued, so the batch may include
+    /// messages queued before the waiter's own.
+    async fn take_merged_turn(&self, mut waiter: MergeWaiter) -> ConversationTurn<'_> {
+        let turn = Arc::clone(&waiter.slot.turn).lock_owned().await;
+        waiter.waiting = false;
+        let guard = ConversationGuard {
+            sequencer: self,
+            key: waiter.key.clone(),
+            slot: Arc::clone(&waiter.slot),
+            _turn: turn,
+        };
+        let message_id = std::mem::take(&mut waiter.message_id);
+        drop(waiter);
+
+        let slot = &guard.slot;
+        if let Some(merged_into) = lock(&slot.merged).remove(&message_id) {
+            return ConversationTurn::Merged { merged_into };
+        }
+
+        let mut pending = std::mem::take(&mut *lock(&slot.pending));
+        let Some(own_index) = pending
+            .iter()
+            .position(|message| message.message_id == message_id)
+        else {
+            // Every turn that takes a message records where it went, so this
+            // is not expected; put the batch back rather than lose it.
+            *lock(&slot.pending) = pending;
+            return ConversationTurn::Merged {
+                merged_into: message_id,
+            };
+        };
+        let text = pending
+            .iter()
+            .map(|message| content_without_attachments(&message.content, &message.attachments))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let attachments = pending
+            .iter()
+            .flat_map(|message| message.attachments.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut combined = pending.swap_remove(own_index);
+        let mut merged = lock(&slot.merged);
+        for message in pending {
+            merged.insert(message.message_id, message_id.clone());
+        }
+        drop(merged);
+        // Each message numbered its own images from 1; number them as one.
+        combined.content = content_with_attachments(&text, &attachments);
+        combined.attachments = attachments;
+
+        ConversationTurn::Run {
+            message: Box::new(combined),
+            guard: Some(guard),
+        }
+    }
+
+    fn slot(&self, key: &ConversationKey) -> Arc<ConversationSlot> {
+        Arc::clone(lock(&self.slots).entry(key.clone()).or_default())
+    }
+}
+
+/// A message queued for a merged turn. Dropped while still waiting (the
+/// caller gave up), it takes the message back out of the conversation.
+struct MergeWaiter {
+    key: ConversationKey,
+    slot: Arc<ConversationSlot>,
+    message_id: String,
+    waiting: bool,
+}
+
+impl Drop for MergeWaiter {
+    fn drop(&mut self) {
+        if !self.waiting {
+            return;
+        }
+        lock(&self.slot.pending).retain(|message| message.message_id != self.message_id);
+        lock(&self.slot.merged).remove(&self.message_id);
+    }
+}
+
+/// Holds a conversation's turn; dropping it lets the next message run and
+/// forgets idle conversations.
+pub struct ConversationGuard<'a> {
+    sequencer: &'a ConversationSequencer,
+    key: ConversationKey,
+    slot: Arc<ConversationSlot>,
+    _turn: OwnedMutexGuard<()>,
+}
+
+impl Drop for ConversationGuard<'_> {
+    fn drop(&mut self) {
+        let mut slots = lock(&self.sequencer.slots);
+        // One reference lives in the map and one in this guard; anything more
+        // is a message still waiting for the turn.
+        if Arc::strong_count(&self.slot) <= 2 {
+            slots.remove(&self.key);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use crate::types::{MessageAttachment, MessageCtx, content_with_attachments};
+
+    use super::{
+        ConcurrencyLimiter, ConversationSequencer, ConversationSequencing, ConversationTurn,
+    };
+
+    fn message(id: &str, content: &str) -> MessageCtx {
+        MessageCtx {
+            message_id: id.to_owned(),
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            content: content.to_owned(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn merge_mode_folds_waiting_messages_into_one_turn() {
+        let sequencer = ConversationSequencer::new(ConversationSequencing::Merge);
+        let first = sequencer.enter(message("m1", "hi")).await;
+
+        let second = sequencer.enter(message("m2", "I have a question"));
+        let third = sequencer.enter(message("m3", "about rust"));
+        tokio::pin!(second, third);
+        for waiting in [second.as_mut(), third.as_mut()] {
+            assert!(
+                tokio::time::timeout(Duration::from_millis(20), waiting)
+                    .await
+                    .is_err()
+            );
+        }
+
+        drop(first);
+        match second.await {
+            ConversationTurn::Run { message, guard } => {
+                assert_eq!(message.message_id, "m2");
+                assert_eq!(message.content, "I have a question\nabout rust");
+                drop(guard);
+            }
+            ConversationTurn::Merged { .. } => panic!("second message should run"),
+        }
+        match third.await {
+            ConversationTurn::Merged { merged_into } => assert_eq!(merged_into, "m2"),
+            ConversationTurn::Run { .. } => panic!("third message should be merged"),
+        }
+        assert!(sequencer.slots.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn merged_messages_number_their_images_as_one() {
+        let with_image = |id: &str, content: &str, filename: &str| {
+            let attachments = vec![MessageAttachment {
+                url: format!("https://cdn.discordapp.com/attachments/1/{id}/{filename}"),
+                filename: filename.to_owned(),
+                content_type: Some("image/png".to_owned()),
+                size: None,
+            }];
+            MessageCtx {
+                content: content_with_attachments(content, &attachments),
+                attachments,
+                ..message(id, content)
+            }
+        };
+        let sequencer = ConversationSequencer::new(ConversationSequencing::Merge);
+        let first = sequencer.enter(message("m1", "hi")).await;
+        let second = sequencer.enter(with_image("m2", "read this", "a.png"));
+        let third = sequencer.enter(with_image("m3", "and this", "b.png"));
+        tokio::pin!(second, third);
+        for waiting in [second.as_mut(), third.as_mut()] {
+            assert!(
+                tokio::time::timeout(Duration::from_millis(20), waiting)
+                    .await
+                    .is_err()
+            );
+        }
+
+        drop(first);
+        match second.await {
+            ConversationTurn::Run { message, guard } => {
+                assert_eq!(
+                    message.content,
+                    "read this\nand this\n[Attached image 1: a.png]\n[Attached image 2: b.png]"
+                );
+                assert_eq!(message.attachments.len(), 2);
+                drop(guard);
+            }
+            ConversationTurn::Merged { .. } => panic!("second message should run"),
+        }
+        assert!(matches!(third.await, ConversationTurn::Merged { .. }));
+    }
+
+    #[tokio::test]
+    async fn merged_turns_run_as_their_own_message_when_locks_come_in_reverse() {
+        let sequencer = ConversationSequencer::new(ConversationSequencing::Merge);
+        // m1 queues first, but m2 reaches the turn first.
+        let first = sequencer.queue_for_merge(message("m1", "hi"));
+        let second = sequencer.queue_for_merge(message("m2", "are you there"));
+        match sequencer.take_merged_turn(second).await {
+            ConversationTurn::Run { message, guard } => {
+                assert_eq!(message.message_id, "m2");
+                assert_eq!(message.content, "hi\nare you there");
+                drop(guard);
+            }
+            ConversationTurn::Merged { .. } => panic!("second message should run"),
+        }
+        match sequencer.take_merged_turn(first).await {
+            ConversationTurn::Merged { merged_into } => assert_eq!(merged_into, "m2"),
+            ConversationTurn::Run { .. } => panic!("first message should be merged"),
+        }
+        assert!(sequencer.slots.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_merge_waiters_leave_nothing_behind() {
+        let sequencer = ConversationSequencer::new(ConversationSequencing::Merge);
+        let first = sequencer.enter(message("m1", "hi")).await;
+        let mut second = Box::pin(sequencer.enter(message("m2", "never mind")));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), second.as_mut())
+                .await
+                .is_err()
+        );
+        let slot = sequencer.slot(&("u1".to_owned(), "c1".to_owned()));
+        assert_eq!(slot.pending.lock().unwrap().len(), 1);
+
+        // A turn that already took the message leaves an entry for it.
+        slot.merged
+            .lock()
+            .unwrap()
+            .insert("m2".to_owned(), "m9".to_owned());
+        drop(second);
+        assert!(slot.pending.lock().unwrap().is_empty());
+        assert!(slot.merged.lock().unwrap().is_empty());
+        drop(first);
+    }
+
+    #[tokio::test]
+    async fn rejects_when_running_slots_and_queue_are_full() {
+        let limiter = ConcurrencyLimiter::new(1, 1);
+        let running = limiter.acquire().await.expect("first slot should be free");
+
+        let queued = limiter.acquire();
+        tokio::pin!(queued);
+        let still_waiting = tokio::time::timeout(Duration::from_millis(20), queued.as_mut()).await;
+        assert!(still_waiting.is_err());
+        assert_eq!(limiter.queued(), 1);
+
+        let busy = limiter.acquire().await.expect_err("queue should be full");
+        assert_eq!(busy.max_queued, 1);
+
+        drop(running);
+        let _permit = queued
+            .await
+            .expect("queued request should get the freed slot");
+        assert_eq!(limiter.queued(), 0);
+    }
+}
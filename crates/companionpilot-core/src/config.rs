@@ -1,60 +1,1360 @@
-use std::{env, net::SocketAddr};
+use std::{env, fmt, net::SocketAddr, path::Path};
 
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ambient::parse_ambient_topics,
+    concurrency::ConversationSequencing,
+    context_window::ContextWindows,
+    guild_settings::CitationStyle,
+    in_flight::InFlightRecovery,
+    proxy::{TrustedProxies, split_list},
+    redaction::parse_redaction_kinds,
+    tool_output::DEFAULT_TOOL_OUTPUT_CHARS,
+    tools::{DEFAULT_EMBEDDING_MODEL, DEFAULT_OCR_MODEL, RerankMode, SearchMode},
+    widgets::WidgetRegistry,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     pub http_bind: SocketAddr,
-    pub discord_token: Option<String>,
-    pub model_provider: String,
+    pub http: HttpConfig,
+    pub model: ModelConfig,
+    pub memory: MemoryConfig,
+    pub voice: VoiceConfig,
+    pub tools: ToolsConfig,
+    pub discord: DiscordConfig,
+    pub alerts: AlertsConfig,
+    pub mqtt: MqttConfig,
+    /// Settings for guilds that have not stored their own.
+    pub guild_defaults: GuildDefaultsConfig,
+    pub personas_dir: Option<String>,
+    pub max_concurrent_orchestrations: u64,
+    pub max_queued_orchestrations: u64,
+    /// Messages each user may send per window; 0 disables the limit.
+    pub user_rate_limit: u64,
+    pub user_rate_limit_window_secs: u64,
+    pub conversation_sequencing: String,
+    /// `off`, `notify` or `retry`: what happens to replies a crash
+    /// interrupted.
+    pub in_flight_recovery: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Comma-separated origins allowed to call the API from a browser. `*`
+    /// lets any origin call `/chat` and `/chat/batch` only. Empty disables
+    /// CORS.
+    pub cors_allowed_origins: String,
+    /// Comma-separated request headers allowed on cross-origin calls, in
+    /// addition to `content-type` and the audit actor header.
+    pub cors_allowed_headers: String,
+    /// Comma-separated proxy addresses or CIDR ranges whose
+    /// `X-Forwarded-For` is trusted for client IPs.
+    pub trusted_proxies: String,
+    /// Prefix for every route, e.g. `/companion` behind a reverse proxy.
+    pub base_path: String,
+    /// PEM certificate chain and private key; with both set the server
+    /// speaks HTTPS itself.
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    /// How often the certificate files are checked for changes; 0 disables
+    /// reloading.
+    pub tls_reload_secs: u64,
+    /// Public chat widgets as comma-separated `name=token[:persona]`.
+    pub chat_widgets: String,
+    /// Signs users' iCal feed tokens; unset disables the feeds.
+    pub calendar_secret: Option<String>,
+    /// Where the API is reachable from outside, including any base path;
+    /// used for links handed to users, such as feed URLs.
+    pub public_url: Option<String>,
+}
+
+impl HttpConfig {
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig {
+    /// `auto`, `openrouter` or `mock`.
+    pub provider: String,
+    /// Makes the mock provider write deterministic templated replies from
+    /// this seed instead of echoing its prompt.
+    pub mock_seed: Option<u64>,
     pub openrouter_api_key: Option<String>,
     pub openrouter_model: String,
     pub openrouter_referer: Option<String>,
     pub openrouter_title: Option<String>,
-    pub openai_api_key: Option<String>,
-    pub openai_stt_model: String,
-    pub openai_tts_model: String,
-    pub openai_tts_voice: String,
-    pub tavily_api_key: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+    pub planner_json_retries: u64,
+    pub planner_few_shot_examples: u64,
+    pub small_talk_routing: bool,
+    pub speculative_synthesis: bool,
+    pub thoughts_log: bool,
+    /// Turns after a reply model switch that see a summary of the earlier
+    /// conversation; 0 disables handoff notes.
+    pub handoff_turns: u64,
+    /// Minutes between keep-warm latency probes; 0 disables them.
+    pub probe_interval_mins: u64,
+    /// Probe p95 latency that triggers a warning and admin alert.
+    pub probe_p95_alert_ms: u64,
+    /// Encrypts users' own model keys; unset disables bring-your-own-key.
+    pub user_key_secret: Option<String>,
+    /// Reply models for small talk, analysis, code and creative requests;
+    /// unset kinds use `openrouter_model`.
+    pub route_simple: Option<String>,
+    pub route_complex: Option<String>,
+    pub route_code: Option<String>,
+    pub route_creative: Option<String>,
+    /// Share of users (0-100) an updated persona prompt is tried on before
+    /// everyone gets it; 0 replaces it at once.
+    pub prompt_rollout_percent: u64,
+    pub prompt_rollout_window_mins: u64,
+    pub prompt_rollout_min_turns: u64,
+    pub prompt_rollout_max_fallback_increase: f32,
+    pub prompt_rollout_max_feedback_drop: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryConfig {
     pub database_url: Option<String>,
+    /// Coordinates replicas (locks, message dedupe) when set.
     pub redis_url: Option<String>,
-    pub voice_enabled: bool,
-    pub voice_allowlist: String,
-    pub voice_idle_timeout_sec: u64,
-    pub voice_chunk_gap_ms: u64,
-    pub voice_max_turn_ms: u64,
-    pub voice_listen_window_ms: u64,
+    pub redis_key_prefix: String,
+    pub conversation_lock_secs: u64,
+    pub fact_dedup_threshold: f32,
+    pub fact_decay_half_life_days: f32,
+    pub fact_approval_queue: bool,
+    pub cross_channel_context_turns: u64,
+    /// Comma-separated `surface=count` pairs (`guild`, `dm`, `dashboard`,
+    /// `voice`) for the recent turns loaded into context.
+    pub context_recent_messages: String,
+    /// The same for the facts loaded into context.
+    pub context_facts: String,
+    pub session_gap_minutes: u64,
+    pub session_titles: bool,
+    pub session_scoped_context: bool,
+    pub pinned_context_tokens: u64,
+    /// TOML file mapping external events (`POST /api/events`) to facts or
+    /// notices.
+    pub event_mappings_path: Option<String>,
+    /// Comma-separated kinds of personal data (`email`, `phone`, `address`)
+    /// masked in chat, tool and planner logs before they are stored.
+    pub redact_pii: String,
+    /// Comma-separated words masked the same way, e.g. profanity.
+    pub redact_words: String,
+    /// Comma-separated values, or `@domain`s, that are never masked.
+    pub redaction_allowlist: String,
+    pub review_interval_hours: u64,
+    pub review_stale_days: u64,
+    pub review_min_confidence: f32,
+    pub review_cooldown_days: u64,
+    pub review_max_facts: u64,
+    /// Nightly extraction of facts the planner missed in stored messages.
+    pub fact_backfill: bool,
+    pub fact_backfill_hour_utc: u64,
+    pub fact_backfill_max_messages: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceConfig {
+    pub enabled: bool,
+    pub openai_api_key: Option<String>,
+    pub stt_model: String,
+    pub tts_model: String,
+    pub tts_voice: String,
+    pub allowlist: String,
+    pub idle_timeout_sec: u64,
+    pub chunk_gap_ms: u64,
+    pub max_turn_ms: u64,
+    pub listen_window_ms: u64,
+    pub tts_cache_dir: Option<String>,
+    pub tts_cache_max_mb: u64,
+    pub barge_in: bool,
+    pub auto_join_users: String,
+}
+
+/// Web search backends accepted in `WEB_SEARCH_PROVIDERS`.
+pub const WEB_SEARCH_PROVIDERS: &[&str] = &["tavily", "brave", "serpapi", "searxng"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolsConfig {
+    pub tavily_api_key: Option<String>,
+    pub brave_search_api_key: Option<String>,
+    pub serpapi_api_key: Option<String>,
+    /// Base URL of a SearxNG instance with JSON output enabled.
+    pub searxng_url: Option<String>,
+    /// Comma-separated web search providers in the order they are asked;
+    /// empty uses every configured one.
+    pub web_search_providers: String,
+    /// `fallback` or `merge`.
+    pub web_search_mode: String,
+    /// `off`, `embedding` or `cross_encoder`.
+    pub web_search_rerank: String,
+    /// Embedding or `/rerank` endpoint; embeddings default to OpenAI's.
+    pub rerank_url: Option<String>,
+    pub rerank_api_key: Option<String>,
+    /// Embedding model for `embedding` reranking.
+    pub rerank_model: String,
+    /// `off`, `vision` or `tesseract`; how `ocr_image` reads attached images.
+    pub ocr_backend: String,
+    /// Chat completions endpoint for `vision`; defaults to OpenRouter.
+    pub ocr_url: Option<String>,
+    /// Falls back to `OPENROUTER_API_KEY` when `OCR_URL` is not set.
+    pub ocr_api_key: Option<String>,
+    pub ocr_model: String,
+    pub tesseract_path: String,
+    pub timeout_ms: u64,
+    pub timeout_overrides: String,
+    pub round_budget_ms: u64,
+    pub daily_quotas: String,
+    /// Characters of one tool output shown to the model; 0 is unlimited.
+    pub output_max_chars: u64,
+    /// Comma-separated `tool=chars` overrides of `output_max_chars`.
+    pub output_budgets: String,
+    pub stats_window: u64,
+    pub failure_alert_min_calls: u64,
+    pub failure_alert_threshold: f32,
+    /// Serve all tool calls from canned outputs instead of real APIs.
+    pub simulation: bool,
+    /// JSON file with the canned outputs per tool used in simulation mode.
+    pub simulation_script: Option<String>,
+    /// TOML file of named tool chains the planner may call as one tool.
+    pub macros_path: Option<String>,
+    /// Comma-separated tools that run but whose output is only logged.
+    pub shadow_tools: String,
+    /// Comma-separated long-running tools that run as background tasks,
+    /// answered with a follow-up message.
+    pub background_tools: String,
+    pub background_timeout_secs: u64,
+    /// Budget of one `deep_research` run.
+    pub deep_research_max_rounds: u64,
+    pub deep_research_max_searches: u64,
+    pub deep_research_pages_per_search: u64,
+    /// Comma-separated user ids whose Spotify playback is added to context.
+    pub spotify_context_users: String,
+    pub spotify_context_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordConfig {
+    pub token: Option<String>,
+    pub debounce_ms: u64,
+    pub admin_channel_id: Option<u64>,
+    /// Request the privileged message content intent; off leaves `/ask`.
+    pub message_content: bool,
+    /// Seconds between presence refreshes; 0 leaves the status alone.
+    pub presence_secs: u64,
+    /// Prefetch a user's memory context when they start typing.
+    pub typing_prefetch: bool,
+    /// Shard this instance runs as; both or neither must be set.
+    pub shard_id: Option<u32>,
+    pub shard_count: Option<u32>,
+    /// Greet users on their birthdays and anniversaries.
+    pub date_greetings: bool,
+    pub date_greetings_hour_utc: u64,
+    /// Channel greetings are posted in; unset sends DMs.
+    pub date_greetings_channel_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    pub webhook_url: Option<String>,
+    pub dedup_secs: u64,
+    pub max_per_hour: u64,
+}
+
+/// Home/IoT topics whose latest readings are shown to the model as ambient
+/// context.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// `mqtt://[user:password@]host[:port]`; unset disables the subscriber.
+    pub broker_url: Option<String>,
+    /// Prefix of the client id; each process adds its own suffix.
+    pub client_id: String,
+    /// Comma-separated `topic/filter=label` pairs.
+    pub topics: String,
+    /// Readings older than this are left out of context.
+    pub stale_secs: u64,
+    /// Comma-separated user ids who get the readings in context.
+    pub context_users: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildDefaultsConfig {
+    pub require_mention: bool,
+    pub persona: Option<String>,
+    pub memory_consent: bool,
+    pub reply_language: Option<String>,
+    pub citation_style: String,
+    pub replay_missed_mentions: bool,
+    pub cache_secs: u64,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            http_bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            http: HttpConfig::default(),
+            model: ModelConfig::default(),
+            memory: MemoryConfig::default(),
+            voice: VoiceConfig::default(),
+            tools: ToolsConfig::default(),
+            discord: DiscordConfig::default(),
+            alerts: AlertsConfig::default(),
+            mqtt: MqttConfig::default(),
+            guild_defaults: GuildDefaultsConfig::default(),
+            personas_dir: None,
+            max_concurrent_orchestrations: 8,
+            max_queued_orchestrations: 32,
+            user_rate_limit: 0,
+            user_rate_limit_window_secs: 60,
+            conversation_sequencing: "queue".to_owned(),
+            in_flight_recovery: "notify".to_owned(),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            cors_allowed_origins: String::new(),
+            cors_allowed_headers: String::new(),
+            trusted_proxies: String::new(),
+            base_path: String::new(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_reload_secs: 60,
+            chat_widgets: String::new(),
+            calendar_secret: None,
+            public_url: None,
+        }
+    }
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            provider: "auto".to_owned(),
+            mock_seed: None,
+            openrouter_api_key: None,
+            openrouter_model: "anthropic/claude-3.5-sonnet".to_owned(),
+            openrouter_referer: None,
+            openrouter_title: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            planner_json_retries: 1,
+            planner_few_shot_examples: 3,
+            small_talk_routing: true,
+            speculative_synthesis: true,
+            thoughts_log: false,
+            handoff_turns: 3,
+            probe_interval_mins: 0,
+            probe_p95_alert_ms: 10_000,
+            user_key_secret: None,
+            route_simple: None,
+            route_complex: None,
+            route_code: None,
+            route_creative: None,
+            prompt_rollout_percent: 0,
+            prompt_rollout_window_mins: 24 * 60,
+            prompt_rollout_min_turns: 20,
+            prompt_rollout_max_fallback_increase: 0.05,
+            prompt_rollout_max_feedback_drop: 0.2,
+        }
+    }
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            database_url: None,
+            redis_url: None,
+            redis_key_prefix: "companionpilot".to_owned(),
+            conversation_lock_secs: 300,
+            fact_dedup_threshold: 0.82,
+            fact_decay_half_life_days: 90.0,
+            fact_approval_queue: false,
+            cross_channel_context_turns: 0,
+            context_recent_messages: String::new(),
+            context_facts: String::new(),
+            session_gap_minutes: 120,
+            session_titles: true,
+            session_scoped_context: false,
+            pinned_context_tokens: 400,
+            event_mappings_path: None,
+            redact_pii: String::new(),
+            redact_words: String::new(),
+            redaction_allowlist: String::new(),
+            review_interval_hours: 0,
+            review_stale_days: 60,
+            review_min_confidence: 0.5,
+            review_cooldown_days: 7,
+            review_max_facts: 3,
+            fact_backfill: false,
+            fact_backfill_hour_utc: 3,
+            fact_backfill_max_messages: 200,
+        }
+    }
+}
+
+impl Default for VoiceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            openai_api_key: None,
+            stt_model: "gpt-4o-mini-transcribe".to_owned(),
+            tts_model: "gpt-4o-mini-tts".to_owned(),
+            tts_voice: "alloy".to_owned(),
+            allowlist: String::new(),
+            idle_timeout_sec: 300,
+            chunk_gap_ms: 700,
+            max_turn_ms: 12_000,
+            listen_window_ms: 12_000,
+            tts_cache_dir: None,
+            tts_cache_max_mb: 64,
+            barge_in: true,
+            auto_join_users: String::new(),
+        }
+    }
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            tavily_api_key: None,
+            brave_search_api_key: None,
+            serpapi_api_key: None,
+            searxng_url: None,
+            web_search_providers: String::new(),
+            web_search_mode: "fallback".to_owned(),
+            web_search_rerank: "off".to_owned(),
+            rerank_url: None,
+            rerank_api_key: None,
+            rerank_model: DEFAULT_EMBEDDING_MODEL.to_owned(),
+            ocr_backend: "off".to_owned(),
+            ocr_url: None,
+            ocr_api_key: None,
+            ocr_model: DEFAULT_OCR_MODEL.to_owned(),
+            tesseract_path: "tesseract".to_owned(),
+            timeout_ms: 10_000,
+            timeout_overrides: String::new(),
+            round_budget_ms: 0,
+            daily_quotas: String::new(),
+            output_max_chars: DEFAULT_TOOL_OUTPUT_CHARS as u64,
+            output_budgets: String::new(),
+            stats_window: 200,
+            failure_alert_min_calls: 10,
+            failure_alert_threshold: 0.5,
+            simulation: false,
+            simulation_script: None,
+            macros_path: None,
+            shadow_tools: String::new(),
+            background_tools: String::new(),
+            background_timeout_secs: 900,
+            deep_research_max_rounds: 2,
+            deep_research_max_searches: 8,
+            deep_research_pages_per_search: 2,
+            spotify_context_users: String::new(),
+            spotify_context_ttl_secs: 60,
+        }
+    }
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            debounce_ms: 1_500,
+            admin_channel_id: None,
+            message_content: true,
+            presence_secs: 5,
+            typing_prefetch: true,
+            shard_id: None,
+            shard_count: None,
+            date_greetings: false,
+            date_greetings_hour_utc: 9,
+            date_greetings_channel_id: None,
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            dedup_secs: 600,
+            max_per_hour: 20,
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker_url: None,
+            client_id: "companionpilot".to_owned(),
+            topics: String::new(),
+            stale_secs: 3600,
+            context_users: String::new(),
+        }
+    }
+}
+
+impl Default for GuildDefaultsConfig {
+    fn default() -> Self {
+        Self {
+            require_mention: false,
+            persona: None,
+            memory_consent: true,
+            reply_language: None,
+            citation_style: "off".to_owned(),
+            replay_missed_mentions: false,
+            cache_secs: 60,
+        }
+    }
 }
 
 impl AppConfig {
     pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
         let port = env::var("PORT").unwrap_or_else(|_| "8080".to_owned());
         let http_bind = env::var("HTTP_BIND").unwrap_or_else(|_| format!("0.0.0.0:{port}"));
         let http_bind = http_bind.parse()?;
 
         Ok(Self {
             http_bind,
-            discord_token: env::var("DISCORD_TOKEN").ok(),
-            model_provider: env::var("MODEL_PROVIDER").unwrap_or_else(|_| "auto".to_owned()),
+            model: ModelConfig::from_env(),
+            memory: MemoryConfig::from_env(),
+            voice: VoiceConfig::from_env(),
+            tools: ToolsConfig::from_env(),
+            discord: DiscordConfig {
+                token: env::var("DISCORD_TOKEN").ok(),
+                debounce_ms: env_u64("DISCORD_DEBOUNCE_MS", defaults.discord.debounce_ms),
+                admin_channel_id: env_parse("DISCORD_ADMIN_CHANNEL_ID"),
+                message_content: env_bool(
+                    "DISCORD_MESSAGE_CONTENT",
+                    defaults.discord.message_content,
+                ),
+                presence_secs: env_u64("DISCORD_PRESENCE_SECS", defaults.discord.presence_secs),
+                typing_prefetch: env_bool(
+                    "DISCORD_TYPING_PREFETCH",
+                    defaults.discord.typing_prefetch,
+                ),
+                shard_id: env_parse("DISCORD_SHARD_ID"),
+                shard_count: env_parse("DISCORD_SHARD_COUNT"),
+                date_greetings: env_bool("DATE_GREETINGS", defaults.discord.date_greetings),
+                date_greetings_hour_utc: env_u64(
+                    "DATE_GREETINGS_HOUR_UTC",
+                    defaults.discord.date_greetings_hour_utc,
+                ),
+                date_greetings_channel_id: env_parse("DATE_GREETINGS_CHANNEL_ID"),
+            },
+            alerts: AlertsConfig::from_env(),
+            mqtt: MqttConfig::from_env(),
+            http: HttpConfig {
+                cors_allowed_origins: env::var("CORS_ALLOWED_ORIGINS").unwrap_or_default(),
+                cors_allowed_headers: env::var("CORS_ALLOWED_HEADERS").unwrap_or_default(),
+                trusted_proxies: env::var("TRUSTED_PROXIES").unwrap_or_default(),
+                base_path: env::var("HTTP_BASE_PATH").unwrap_or_default(),
+                tls_cert_path: env_non_empty("TLS_CERT_PATH"),
+                tls_key_path: env_non_empty("TLS_KEY_PATH"),
+                tls_reload_secs: env_u64("TLS_RELOAD_SECS", defaults.http.tls_reload_secs),
+                chat_widgets: env::var("CHAT_WIDGETS").unwrap_or_default(),
+                calendar_secret: env_non_empty("CALENDAR_FEED_SECRET"),
+                public_url: env_non_empty("PUBLIC_URL"),
+            },
+            guild_defaults: GuildDefaultsConfig::from_env(),
+            personas_dir: env::var("PERSONAS_DIR").ok(),
+            max_concurrent_orchestrations: env_u64(
+                "MAX_CONCURRENT_ORCHESTRATIONS",
+                defaults.max_concurrent_orchestrations,
+            ),
+            max_queued_orchestrations: env_u64(
+                "MAX_QUEUED_ORCHESTRATIONS",
+                defaults.max_queued_orchestrations,
+            ),
+            user_rate_limit: env_u64("USER_RATE_LIMIT", defaults.user_rate_limit),
+            user_rate_limit_window_secs: env_u64(
+                "USER_RATE_LIMIT_WINDOW_SECS",
+                defaults.user_rate_limit_window_secs,
+            ),
+            conversation_sequencing: env::var("CONVERSATION_SEQUENCING")
+                .unwrap_or(defaults.conversation_sequencing),
+            in_flight_recovery: env::var("IN_FLIGHT_RECOVERY")
+                .unwrap_or(defaults.in_flight_recovery),
+        })
+    }
+
+    /// Checks the whole configuration and reports every problem found, such as
+    /// a feature enabled without its API key or a malformed URL.
+    pub fn validate(&self) -> Result<(), ConfigReport> {
+        let mut report = ConfigReport::default();
+
+        let model = &self.model;
+        match model.provider.to_ascii_lowercase().as_str() {
+            "auto" | "mock" => {}
+            "openrouter" => {
+                if model.openrouter_api_key.is_none() {
+                    report.push(
+                        "OPENROUTER_API_KEY",
+                        "MODEL_PROVIDER=openrouter requires an API key",
+                    );
+                }
+            }
+            other => report.push(
+                "MODEL_PROVIDER",
+                format!("unknown value `{other}`; expected auto, openrouter or mock"),
+            ),
+        }
+        if model
+            .user_key_secret
+            .as_ref()
+            .is_some_and(|secret| secret.len() < 16)
+        {
+            report.push(
+                "USER_KEY_SECRET",
+                "use at least 16 characters; the secret protects stored user keys",
+            );
+        }
+        check_url(
+            &mut report,
+            "OPENROUTER_REFERER",
+            model.openrouter_referer.as_deref(),
+            &["http", "https"],
+        );
+        check_range(
+            &mut report,
+            "MODEL_TEMPERATURE",
+            model.temperature,
+            0.0,
+            2.0,
+        );
+        check_range(&mut report, "MODEL_TOP_P", model.top_p, 0.0, 1.0);
+        if model.prompt_rollout_percent > 100 {
+            report.push("PROMPT_ROLLOUT_PERCENT", "must be between 0 and 100");
+        }
+        check_range(
+            &mut report,
+            "PROMPT_ROLLOUT_MAX_FALLBACK_INCREASE",
+            Some(model.prompt_rollout_max_fallback_increase),
+            0.0,
+            1.0,
+        );
+        check_range(
+            &mut report,
+            "PROMPT_ROLLOUT_MAX_FEEDBACK_DROP",
+            Some(model.prompt_rollout_max_feedback_drop),
+            0.0,
+            2.0,
+        );
+
+        let memory = &self.memory;
+        check_url(
+            &mut report,
+            "DATABASE_URL",
+            memory.database_url.as_deref(),
+            &["postgres", "postgresql"],
+        );
+        check_url(
+            &mut report,
+            "REDIS_URL",
+            memory.redis_url.as_deref(),
+            &["redis", "rediss"],
+        );
+        check_range(
+            &mut report,
+            "FACT_DEDUP_THRESHOLD",
+            Some(memory.fact_dedup_threshold),
+            0.0,
+            1.0,
+        );
+        check_range(
+            &mut report,
+            "MEMORY_REVIEW_MIN_CONFIDENCE",
+            Some(memory.review_min_confidence),
+            0.0,
+            1.0,
+        );
+        if memory.review_interval_hours > 0 && self.discord.token.is_none() {
+            report.push(
+                "MEMORY_REVIEW_INTERVAL_HOURS",
+                "memory reviews are sent over Discord but DISCORD_TOKEN is missing",
+            );
+        }
+        if memory.fact_backfill && memory.fact_backfill_hour_utc > 23 {
+            report.push("FACT_BACKFILL_HOUR_UTC", "must be an hour between 0 and 23");
+        }
+
+        let voice = &self.voice;
+        if voice.enabled {
+            if voice.openai_api_key.is_none() {
+                report.push(
+                    "OPENAI_API_KEY",
+                    "VOICE_ENABLED is true but no OpenAI key is set for speech",
+                );
+            }
+            if voice.allowlist.trim().is_empty() {
+                report.push(
+                    "VOICE_ALLOWLIST",
+                    "VOICE_ENABLED is true but no guild:channel pairs are allowlisted",
+                );
+            }
+            if self.discord.token.is_none() {
+                report.push(
+                    "DISCORD_TOKEN",
+                    "VOICE_ENABLED is true but voice runs through the Discord bot",
+                );
+            }
+        } else if !voice.auto_join_users.trim().is_empty() {
+            report.push(
+                "VOICE_AUTO_JOIN_USERS",
+                "auto-join users are set but VOICE_ENABLED is false",
+            );
+        }
+
+        check_range(
+            &mut report,
+            "TOOL_FAILURE_ALERT_THRESHOLD",
+            Some(self.tools.failure_alert_threshold),
+            0.0,
+            1.0,
+        );
+        if let Some(path) = &self.tools.simulation_script
+            && !Path::new(path).is_file()
+        {
+            report.push(
+                "TOOL_SIMULATION_SCRIPT",
+                format!("`{path}` does not exist or is not a file"),
+            );
+        }
+        if let Some(path) = &self.tools.macros_path
+            && !Path::new(path).is_file()
+        {
+            report.push(
+                "TOOL_MACROS_PATH",
+                format!("`{path}` does not exist or is not a file"),
+            );
+        }
+        if SearchMode::parse(&self.tools.web_search_mode).is_none() {
+            report.push(
+                "WEB_SEARCH_MODE",
+                format!(
+                    "unknown value `{}`; expected fallback or merge",
+                    self.tools.web_search_mode
+                ),
+            );
+        }
+        for provider in split_list(&self.tools.web_search_providers) {
+            if !WEB_SEARCH_PROVIDERS.contains(&provider) {
+                report.push(
+                    "WEB_SEARCH_PROVIDERS",
+                    format!(
+                        "unknown provider `{provider}`; expected {}",
+                        WEB_SEARCH_PROVIDERS.join(", ")
+                    ),
+                );
+            } else if !self.tools.web_search_provider_configured(provider) {
+                report.push(
+                    "WEB_SEARCH_PROVIDERS",
+                    format!("`{provider}` is listed but its API key or URL is not set"),
+                );
+            }
+        }
+        match RerankMode::parse(&self.tools.web_search_rerank) {
+            None => report.push(
+                "WEB_SEARCH_RERANK",
+                format!(
+                    "unknown value `{}`; expected off, embedding or cross_encoder",
+                    self.tools.web_search_rerank
+                ),
+            ),
+            Some(RerankMode::CrossEncoder) if self.tools.rerank_url.is_none() => report.push(
+                "RERANK_URL",
+                "WEB_SEARCH_RERANK=cross_encoder requires the /rerank endpoint URL",
+            ),
+            Some(RerankMode::Embedding)
+                if self.tools.rerank_url.is_none() && self.tools.rerank_api_key.is_none() =>
+            {
+                report.push(
+                    "RERANK_API_KEY",
+                    "WEB_SEARCH_RERANK=embedding uses OpenAI embeddings unless RERANK_URL is set, which needs a key",
+                );
+            }
+            Some(_) => {}
+        }
+        check_url(
+            &mut report,
+            "RERANK_URL",
+            self.tools.rerank_url.as_deref(),
+            &["http", "https"],
+        );
+        check_url(
+            &mut report,
+            "SEARXNG_URL",
+            self.tools.searxng_url.as_deref(),
+            &["http", "https"],
+        );
+        match self.tools.ocr_backend.trim().to_ascii_lowercase().as_str() {
+            "off" | "" | "tesseract" => {}
+            "vision" => {
+                if self.tools.ocr_url.is_none()
+                    && self.tools.ocr_api_key.is_none()
+                    && self.model.openrouter_api_key.is_none()
+                {
+                    report.push(
+                        "OCR_API_KEY",
+                        "OCR_BACKEND=vision uses OpenRouter unless OCR_URL is set, which needs OCR_API_KEY or OPENROUTER_API_KEY",
+                    );
+                }
+            }
+            other => report.push(
+                "OCR_BACKEND",
+                format!("unknown value `{other}`; expected off, vision or tesseract"),
+            ),
+        }
+        check_url(
+            &mut report,
+            "OCR_URL",
+            self.tools.ocr_url.as_deref(),
+            &["http", "https"],
+        );
+        if self.tools.deep_research_max_rounds == 0 {
+            report.push("DEEP_RESEARCH_MAX_ROUNDS", "must be at least 1");
+        }
+        if self.tools.deep_research_max_searches == 0 {
+            report.push("DEEP_RESEARCH_MAX_SEARCHES", "must be at least 1");
+        }
+        check_url(
+            &mut report,
+            "MQTT_BROKER_URL",
+            self.mqtt.broker_url.as_deref(),
+            &["mqtt", "tcp"],
+        );
+        if let Err(error) = parse_ambient_topics(&self.mqtt.topics) {
+            report.push("MQTT_TOPICS", error);
+        }
+        if self.mqtt.broker_url.is_some() && self.mqtt.topics.trim().is_empty() {
+            report.push("MQTT_TOPICS", "MQTT_BROKER_URL is set but no topics are");
+        }
+        if let Err(error) = parse_redaction_kinds(&self.memory.redact_pii) {
+            report.push("REDACT_PII", error);
+        }
+        if let Err(error) = ContextWindows::parse(&self.memory.context_recent_messages, "") {
+            report.push("CONTEXT_RECENT_MESSAGES", error);
+        }
+        if let Err(error) = ContextWindows::parse("", &self.memory.context_facts) {
+            report.push("CONTEXT_FACTS", error);
+        }
+        if let Some(path) = &self.memory.event_mappings_path
+            && !Path::new(path).is_file()
+        {
+            report.push(
+                "EVENT_MAPPINGS_PATH",
+                format!("`{path}` does not exist or is not a file"),
+            );
+        }
+
+        match (self.discord.shard_id, self.discord.shard_count) {
+            (None, None) => {}
+            (Some(id), Some(count)) if id < count => {}
+            (Some(_), Some(_)) => report.push(
+                "DISCORD_SHARD_ID",
+                "the shard id must be lower than DISCORD_SHARD_COUNT",
+            ),
+            _ => report.push(
+                "DISCORD_SHARD_COUNT",
+                "DISCORD_SHARD_ID and DISCORD_SHARD_COUNT must be set together",
+            ),
+        }
+
+        if self.discord.admin_channel_id.is_some() && self.discord.token.is_none() {
+            report.push(
+                "DISCORD_ADMIN_CHANNEL_ID",
+                "an admin channel is set but DISCORD_TOKEN is missing",
+            );
+        }
+        if self.discord.date_greetings {
+            if self.discord.token.is_none() {
+                report.push(
+                    "DATE_GREETINGS",
+                    "greetings are sent over Discord but DISCORD_TOKEN is missing",
+                );
+            }
+            if self.discord.date_greetings_hour_utc > 23 {
+                report.push(
+                    "DATE_GREETINGS_HOUR_UTC",
+                    "must be an hour between 0 and 23",
+                );
+            }
+        }
+        check_url(
+            &mut report,
+            "ADMIN_ALERT_WEBHOOK_URL",
+            self.alerts.webhook_url.as_deref(),
+            &["http", "https"],
+        );
+
+        for origin in split_list(&self.http.cors_allowed_origins).filter(|origin| *origin != "*") {
+            check_url(
+                &mut report,
+                "CORS_ALLOWED_ORIGINS",
+                Some(origin),
+                &["http", "https"],
+            );
+        }
+        if let Err(error) = TrustedProxies::parse(&self.http.trusted_proxies) {
+            report.push("TRUSTED_PROXIES", error);
+        }
+        if let Err(error) = WidgetRegistry::parse(&self.http.chat_widgets) {
+            report.push("CHAT_WIDGETS", error);
+        }
+        if self
+            .http
+            .calendar_secret
+            .as_ref()
+            .is_some_and(|secret| secret.len() < 16)
+        {
+            report.push(
+                "CALENDAR_FEED_SECRET",
+                "use at least 16 characters; the secret signs calendar feed tokens",
+            );
+        }
+        check_url(
+            &mut report,
+            "PUBLIC_URL",
+            self.http.public_url.as_deref(),
+            &["http", "https"],
+        );
+        match (&self.http.tls_cert_path, &self.http.tls_key_path) {
+            (Some(_), None) => report.push("TLS_KEY_PATH", "TLS_CERT_PATH is set without a key"),
+            (None, Some(_)) => {
+                report.push("TLS_CERT_PATH", "TLS_KEY_PATH is set without a certificate")
+            }
+            _ => {}
+        }
+        for (setting, path) in [
+            ("TLS_CERT_PATH", &self.http.tls_cert_path),
+            ("TLS_KEY_PATH", &self.http.tls_key_path),
+        ] {
+            if let Some(path) = path
+                && !Path::new(path).is_file()
+            {
+                report.push(setting, format!("`{path}` does not exist or is not a file"));
+            }
+        }
+
+        if ConversationSequencing::parse(&self.conversation_sequencing).is_none() {
+            report.push(
+                "CONVERSATION_SEQUENCING",
+                format!(
+                    "unknown value `{}`; expected off, queue or merge",
+                    self.conversation_sequencing
+                ),
+            );
+        }
+        if InFlightRecovery::parse(&self.in_flight_recovery).is_none() {
+            report.push(
+                "IN_FLIGHT_RECOVERY",
+                format!(
+                    "unknown value `{}`; expected off, notify or retry",
+                    self.in_flight_recovery
+                ),
+            );
+        }
+        if CitationStyle::parse(&self.guild_defaults.citation_style).is_none() {
+            report.push(
+                "CITATION_STYLE",
+                format!(
+                    "unknown value `{}`; expected off, footnotes or compact",
+                    self.guild_defaults.citation_style
+                ),
+            );
+        }
+
+        if report.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(report)
+        }
+    }
+}
+
+impl ModelConfig {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            provider: env::var("MODEL_PROVIDER").unwrap_or(defaults.provider),
+            mock_seed: env_parse("MOCK_MODEL_SEED"),
             openrouter_api_key: env::var("OPENROUTER_API_KEY").ok(),
-            openrouter_model: env::var("OPENROUTER_MODEL")
-                .unwrap_or_else(|_| "anthropic/claude-3.5-sonnet".to_owned()),
+            openrouter_model: env::var("OPENROUTER_MODEL").unwrap_or(defaults.openrouter_model),
             openrouter_referer: env::var("OPENROUTER_REFERER").ok(),
             openrouter_title: env::var("OPENROUTER_TITLE").ok(),
-            openai_api_key: env::var("OPENAI_API_KEY").ok(),
-            openai_stt_model: env::var("OPENAI_STT_MODEL")
-                .unwrap_or_else(|_| "gpt-4o-mini-transcribe".to_owned()),
-            openai_tts_model: env::var("OPENAI_TTS_MODEL")
-                .unwrap_or_else(|_| "gpt-4o-mini-tts".to_owned()),
-            openai_tts_voice: env::var("OPENAI_TTS_VOICE").unwrap_or_else(|_| "alloy".to_owned()),
-            tavily_api_key: env::var("TAVILY_API_KEY").ok(),
+            temperature: env_parse("MODEL_TEMPERATURE"),
+            top_p: env_parse("MODEL_TOP_P"),
+            max_tokens: env_parse("MODEL_MAX_TOKENS"),
+            stop: env::var("MODEL_STOP")
+                .unwrap_or_default()
+                .split('|')
+                .map(str::trim)
+                .filter(|sequence| !sequence.is_empty())
+                .map(ToOwned::to_owned)
+                .collect(),
+            planner_json_retries: env_u64("PLANNER_JSON_RETRIES", defaults.planner_json_retries),
+            planner_few_shot_examples: env_u64(
+                "PLANNER_FEW_SHOT_EXAMPLES",
+                defaults.planner_few_shot_examples,
+            ),
+            small_talk_routing: env_bool("SMALL_TALK_ROUTING", defaults.small_talk_routing),
+            speculative_synthesis: env_bool(
+                "SPECULATIVE_SYNTHESIS",
+                defaults.speculative_synthesis,
+            ),
+            thoughts_log: env_bool("THOUGHTS_LOG", defaults.thoughts_log),
+            handoff_turns: env_u64("MODEL_HANDOFF_TURNS", defaults.handoff_turns),
+            probe_interval_mins: env_u64("MODEL_PROBE_INTERVAL_MINS", defaults.probe_interval_mins),
+            probe_p95_alert_ms: env_u64("MODEL_PROBE_P95_ALERT_MS", defaults.probe_p95_alert_ms),
+            user_key_secret: env_non_empty("USER_KEY_SECRET"),
+            route_simple: env_non_empty("MODEL_ROUTE_SIMPLE"),
+            route_complex: env_non_empty("MODEL_ROUTE_COMPLEX"),
+            route_code: env_non_empty("MODEL_ROUTE_CODE"),
+            route_creative: env_non_empty("MODEL_ROUTE_CREATIVE"),
+            prompt_rollout_percent: env_u64(
+                "PROMPT_ROLLOUT_PERCENT",
+                defaults.prompt_rollout_percent,
+            ),
+            prompt_rollout_window_mins: env_u64(
+                "PROMPT_ROLLOUT_WINDOW_MINS",
+                defaults.prompt_rollout_window_mins,
+            ),
+            prompt_rollout_min_turns: env_u64(
+                "PROMPT_ROLLOUT_MIN_TURNS",
+                defaults.prompt_rollout_min_turns,
+            ),
+            prompt_rollout_max_fallback_increase: env_f32(
+                "PROMPT_ROLLOUT_MAX_FALLBACK_INCREASE",
+                defaults.prompt_rollout_max_fallback_increase,
+            ),
+            prompt_rollout_max_feedback_drop: env_f32(
+                "PROMPT_ROLLOUT_MAX_FEEDBACK_DROP",
+                defaults.prompt_rollout_max_feedback_drop,
+            ),
+        }
+    }
+}
+
+impl MemoryConfig {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
             database_url: env::var("DATABASE_URL").ok(),
             redis_url: env::var("REDIS_URL").ok(),
-            voice_enabled: env_bool("VOICE_ENABLED", false),
-            voice_allowlist: env::var("VOICE_ALLOWLIST").unwrap_or_default(),
-            voice_idle_timeout_sec: env_u64("VOICE_IDLE_TIMEOUT_SEC", 300),
-            voice_chunk_gap_ms: env_u64("VOICE_CHUNK_GAP_MS", 700),
-            voice_max_turn_ms: env_u64("VOICE_MAX_TURN_MS", 12_000),
-            voice_listen_window_ms: env_u64("VOICE_LISTEN_WINDOW_MS", 12_000),
-        })
+            redis_key_prefix: env_non_empty("REDIS_KEY_PREFIX")
+                .unwrap_or(defaults.redis_key_prefix),
+            conversation_lock_secs: env_u64(
+                "CONVERSATION_LOCK_SECS",
+                defaults.conversation_lock_secs,
+            ),
+            fact_dedup_threshold: env_f32("FACT_DEDUP_THRESHOLD", defaults.fact_dedup_threshold),
+            fact_decay_half_life_days: env_f32(
+                "FACT_DECAY_HALF_LIFE_DAYS",
+                defaults.fact_decay_half_life_days,
+            ),
+            fact_approval_queue: env_bool("FACT_APPROVAL_QUEUE", defaults.fact_approval_queue),
+            cross_channel_context_turns: env_u64(
+                "CROSS_CHANNEL_CONTEXT_TURNS",
+                defaults.cross_channel_context_turns,
+            ),
+            context_recent_messages: env::var("CONTEXT_RECENT_MESSAGES").unwrap_or_default(),
+            context_facts: env::var("CONTEXT_FACTS").unwrap_or_default(),
+            session_gap_minutes: env_u64("CHAT_SESSION_GAP_MINUTES", defaults.session_gap_minutes),
+            session_titles: env_bool("CHAT_SESSION_TITLES", defaults.session_titles),
+            session_scoped_context: env_bool(
+                "CHAT_SESSION_SCOPED_CONTEXT",
+                defaults.session_scoped_context,
+            ),
+            pinned_context_tokens: env_u64("PINNED_CONTEXT_TOKENS", defaults.pinned_context_tokens),
+            event_mappings_path: env_non_empty("EVENT_MAPPINGS_PATH"),
+            redact_pii: env::var("REDACT_PII").unwrap_or_default(),
+            redact_words: env::var("REDACT_WORDS").unwrap_or_default(),
+            redaction_allowlist: env::var("REDACTION_ALLOWLIST").unwrap_or_default(),
+            review_interval_hours: env_u64(
+                "MEMORY_REVIEW_INTERVAL_HOURS",
+                defaults.review_interval_hours,
+            ),
+            review_stale_days: env_u64("MEMORY_REVIEW_STALE_DAYS", defaults.review_stale_days),
+            review_min_confidence: env_f32(
+                "MEMORY_REVIEW_MIN_CONFIDENCE",
+                defaults.review_min_confidence,
+            ),
+            review_cooldown_days: env_u64(
+                "MEMORY_REVIEW_COOLDOWN_DAYS",
+                defaults.review_cooldown_days,
+            ),
+            review_max_facts: env_u64("MEMORY_REVIEW_MAX_FACTS", defaults.review_max_facts),
+            fact_backfill: env_bool("FACT_BACKFILL", defaults.fact_backfill),
+            fact_backfill_hour_utc: env_u64(
+                "FACT_BACKFILL_HOUR_UTC",
+                defaults.fact_backfill_hour_utc,
+            ),
+            fact_backfill_max_messages: env_u64(
+                "FACT_BACKFILL_MAX_MESSAGES",
+                defaults.fact_backfill_max_messages,
+            ),
+        }
+    }
+}
+
+impl VoiceConfig {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: env_bool("VOICE_ENABLED", defaults.enabled),
+            openai_api_key: env::var("OPENAI_API_KEY").ok(),
+            stt_model: env::var("OPENAI_STT_MODEL").unwrap_or(defaults.stt_model),
+            tts_model: env::var("OPENAI_TTS_MODEL").unwrap_or(defaults.tts_model),
+            tts_voice: env::var("OPENAI_TTS_VOICE").unwrap_or(defaults.tts_voice),
+            allowlist: env::var("VOICE_ALLOWLIST").unwrap_or_default(),
+            idle_timeout_sec: env_u64("VOICE_IDLE_TIMEOUT_SEC", defaults.idle_timeout_sec),
+            chunk_gap_ms: env_u64("VOICE_CHUNK_GAP_MS", defaults.chunk_gap_ms),
+            max_turn_ms: env_u64("VOICE_MAX_TURN_MS", defaults.max_turn_ms),
+            listen_window_ms: env_u64("VOICE_LISTEN_WINDOW_MS", defaults.listen_window_ms),
+            tts_cache_dir: env_non_empty("TTS_CACHE_DIR"),
+            tts_cache_max_mb: env_u64("TTS_CACHE_MAX_MB", defaults.tts_cache_max_mb),
+            barge_in: env_bool("VOICE_BARGE_IN", defaults.barge_in),
+            auto_join_users: env::var("VOICE_AUTO_JOIN_USERS").unwrap_or_default(),
+        }
+    }
+}
+
+impl ToolsConfig {
+    /// Web search providers in the order they are asked: the listed ones, or
+    /// every configured one.
+    pub fn web_search_provider_names(&self) -> Vec<&str> {
+        let listed = split_list(&self.web_search_providers).collect::<Vec<_>>();
+        if !listed.is_empty() {
+            return listed;
+        }
+        WEB_SEARCH_PROVIDERS
+            .iter()
+            .copied()
+            .filter(|provider| self.web_search_provider_configured(provider))
+            .collect()
+    }
+
+    fn web_search_provider_configured(&self, provider: &str) -> bool {
+        match provider {
+            "tavily" => self.tavily_api_key.is_some(),
+            "brave" => self.brave_search_api_key.is_some(),
+            "serpapi" => self.serpapi_api_key.is_some(),
+            "searxng" => self.searxng_url.is_some(),
+            _ => false,
+        }
+    }
+
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            tavily_api_key: env::var("TAVILY_API_KEY").ok(),
+            brave_search_api_key: env_non_empty("BRAVE_SEARCH_API_KEY"),
+            serpapi_api_key: env_non_empty("SERPAPI_API_KEY"),
+            searxng_url: env_non_empty("SEARXNG_URL"),
+            web_search_providers: env::var("WEB_SEARCH_PROVIDERS").unwrap_or_default(),
+            web_search_mode: env::var("WEB_SEARCH_MODE").unwrap_or(defaults.web_search_mode),
+            web_search_rerank: env::var("WEB_SEARCH_RERANK").unwrap_or(defaults.web_search_rerank),
+            rerank_url: env_non_empty("RERANK_URL"),
+            rerank_api_key: env_non_empty("RERANK_API_KEY"),
+            rerank_model: env_non_empty("RERANK_MODEL").unwrap_or(defaults.rerank_model),
+            ocr_backend: env::var("OCR_BACKEND").unwrap_or(defaults.ocr_backend),
+            ocr_url: env_non_empty("OCR_URL"),
+            ocr_api_key: env_non_empty("OCR_API_KEY"),
+            ocr_model: env_non_empty("OCR_MODEL").unwrap_or(defaults.ocr_model),
+            tesseract_path: env_non_empty("TESSERACT_PATH").unwrap_or(defaults.tesseract_path),
+            timeout_ms: env_u64("TOOL_TIMEOUT_MS", defaults.timeout_ms),
+            timeout_overrides: env::var("TOOL_TIMEOUT_OVERRIDES").unwrap_or_default(),
+            round_budget_ms: env_u64("TOOL_ROUND_BUDGET_MS", defaults.round_budget_ms),
+            daily_quotas: env::var("TOOL_DAILY_QUOTAS").unwrap_or_default(),
+            output_max_chars: env_u64("TOOL_OUTPUT_MAX_CHARS", defaults.output_max_chars),
+            output_budgets: env::var("TOOL_OUTPUT_BUDGETS").unwrap_or_default(),
+            stats_window: env_u64("TOOL_STATS_WINDOW", defaults.stats_window),
+            failure_alert_min_calls: env_u64(
+                "TOOL_FAILURE_ALERT_MIN_CALLS",
+                defaults.failure_alert_min_calls,
+            ),
+            failure_alert_threshold: env_f32(
+                "TOOL_FAILURE_ALERT_THRESHOLD",
+                defaults.failure_alert_threshold,
+            ),
+            simulation: env_bool("TOOL_SIMULATION", defaults.simulation),
+            simulation_script: env::var("TOOL_SIMULATION_SCRIPT")
+                .ok()
+                .filter(|path| !path.trim().is_empty()),
+            macros_path: env_non_empty("TOOL_MACROS_PATH"),
+            shadow_tools: env::var("TOOL_SHADOW_MODE").unwrap_or_default(),
+            background_tools: env::var("BACKGROUND_TOOLS").unwrap_or_default(),
+            background_timeout_secs: env_u64(
+                "BACKGROUND_TOOL_TIMEOUT_SECS",
+                defaults.background_timeout_secs,
+            ),
+            deep_research_max_rounds: env_u64(
+                "DEEP_RESEARCH_MAX_ROUNDS",
+                defaults.deep_research_max_rounds,
+            ),
+            deep_research_max_searches: env_u64(
+                "DEEP_RESEARCH_MAX_SEARCHES",
+                defaults.deep_research_max_searches,
+            ),
+            deep_research_pages_per_search: env_u64(
+                "DEEP_RESEARCH_PAGES_PER_SEARCH",
+                defaults.deep_research_pages_per_search,
+            ),
+            spotify_context_users: env::var("SPOTIFY_CONTEXT_USERS").unwrap_or_default(),
+            spotify_context_ttl_secs: env_u64(
+                "SPOTIFY_CONTEXT_TTL_SECS",
+                defaults.spotify_context_ttl_secs,
+            ),
+        }
+    }
+}
+
+impl AlertsConfig {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            webhook_url: env::var("ADMIN_ALERT_WEBHOOK_URL")
+                .ok()
+                .filter(|url| !url.trim().is_empty()),
+            dedup_secs: env_u64("ADMIN_ALERT_DEDUP_SECS", defaults.dedup_secs),
+            max_per_hour: env_u64("ADMIN_ALERT_MAX_PER_HOUR", defaults.max_per_hour),
+        }
+    }
+}
+
+impl MqttConfig {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            broker_url: env_non_empty("MQTT_BROKER_URL"),
+            client_id: env_non_empty("MQTT_CLIENT_ID").unwrap_or(defaults.client_id),
+            topics: env::var("MQTT_TOPICS").unwrap_or_default(),
+            stale_secs: env_u64("MQTT_STALE_SECS", defaults.stale_secs),
+            context_users: env::var("AMBIENT_CONTEXT_USERS").unwrap_or_default(),
+        }
+    }
+}
+
+impl GuildDefaultsConfig {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            require_mention: env_bool("DISCORD_REQUIRE_MENTION", defaults.require_mention),
+            persona: env_non_empty("DEFAULT_PERSONA"),
+            memory_consent: env_bool("MEMORY_CONSENT_DEFAULT", defaults.memory_consent),
+            reply_language: env_non_empty("REPLY_LANGUAGE"),
+            citation_style: env::var("CITATION_STYLE").unwrap_or(defaults.citation_style),
+            replay_missed_mentions: env_bool(
+                "REPLAY_MISSED_MENTIONS",
+                defaults.replay_missed_mentions,
+            ),
+            cache_secs: env_u64("GUILD_SETTINGS_CACHE_SECS", defaults.cache_secs),
+        }
+    }
+}
+
+/// One configuration problem, keyed by the environment variable to fix.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigProblem {
+    pub setting: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.setting, self.message)
+    }
+}
+
+/// Every problem found by [`AppConfig::validate`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigReport {
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl ConfigReport {
+    fn push(&mut self, setting: &'static str, message: impl Into<String>) {
+        self.problems.push(ConfigProblem {
+            setting,
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} configuration problem(s)", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n- {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigReport {}
+
+fn check_url(
+    report: &mut ConfigReport,
+    setting: &'static str,
+    raw: Option<&str>,
+    schemes: &[&str],
+) {
+    let Some(raw) = raw else {
+        return;
+    };
+    match reqwest::Url::parse(raw.trim()) {
+        Ok(url) if schemes.contains(&url.scheme()) => {}
+        Ok(url) => report.push(
+            setting,
+            format!(
+                "unsupported scheme `{}`; expected {}",
+                url.scheme(),
+                schemes.join(" or ")
+            ),
+        ),
+        Err(error) => report.push(setting, format!("invalid URL: {error}")),
+    }
+}
+
+fn check_range(
+    report: &mut ConfigReport,
+    setting: &'static str,
+    value: Option<f32>,
+    min: f32,
+    max: f32,
+) {
+    if let Some(value) = value
+        && !(min..=max).contains(&value)
+    {
+        report.push(
+            setting,
+            format!("{value} is outside the allowed range {min}-{max}"),
+        );
     }
 }
 
@@ -70,9 +1370,69 @@ fn env_bool(name: &str, default: bool) -> bool {
         .unwrap_or(default)
 }
 
+fn env_non_empty(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|raw| raw.trim().to_owned())
+        .filter(|raw| !raw.is_empty())
+}
+
 fn env_u64(name: &str, default: u64) -> u64 {
     env::var(name)
         .ok()
         .and_then(|raw| raw.trim().parse::<u64>().ok())
         .unwrap_or(default)
 }
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<T>().ok())
+}
+
+fn env_f32(name: &str, default: f32) -> f32 {
+    env::var(name)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<f32>().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppConfig;
+
+    #[test]
+    fn validate_reports_every_problem_at_once() {
+        assert!(AppConfig::default().validate().is_ok());
+
+        let mut config = AppConfig::default();
+        config.model.provider = "openrouter".to_owned();
+        config.memory.database_url = Some("mysql://localhost/db".to_owned());
+        config.voice.enabled = true;
+        config.alerts.webhook_url = Some("not a url".to_owned());
+
+        let report = config.validate().expect_err("config should be invalid");
+        let settings = report
+            .problems
+            .iter()
+            .map(|problem| problem.setting)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            settings,
+            vec![
+                "OPENROUTER_API_KEY",
+                "DATABASE_URL",
+                "OPENAI_API_KEY",
+                "VOICE_ALLOWLIST",
+                "DISCORD_TOKEN",
+                "ADMIN_ALERT_WEBHOOK_URL",
+            ]
+        );
+
+        let parsed: AppConfig =
+            serde_json::from_str(r#"{"model": {"provider": "mock"}, "voice": {"enabled": false}}"#)
+                .expect("partial config parses");
+        assert_eq!(parsed.model.openrouter_model, "anthropic/claude-3.5-sonnet");
+        assert_eq!(parsed.max_queued_orchestrations, 32);
+    }
+}
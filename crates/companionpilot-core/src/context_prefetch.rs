@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::types::MemoryContext;
+
+/// How long a prefetched context stays usable unless configured otherwise.
+pub const DEFAULT_PREFETCH_TTL: Duration = Duration::from_secs(15);
+
+type ConversationKey = (String, String, String);
+
+/// Memory contexts loaded ahead of a message, e.g. while the user is still
+/// typing. Each entry is used by at most one turn and only while fresh.
+/// Entries are dropped when a turn of the same user ends, so that turn's
+/// facts and messages are seen by the next one; writes made outside a turn
+/// (e.g. from the dashboard) can go unseen until the entry expires.
+#[derive(Debug)]
+pub struct ContextPrefetchCache {
+    ttl: Duration,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<ConversationKey, (Instant, MemoryContext)>,
+    /// When each user's entries were last invalidated, so a load that was
+    /// already running at that point is not cached.
+    invalidated_at: HashMap<String, Instant>,
+}
+
+impl ContextPrefetchCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Whether a fresh context is already cached, so repeated typing events
+    /// do not reload it.
+    pub fn is_fresh(&self, user_id: &str, guild_id: &str, channel_id: &str) -> bool {
+        self.lock()
+            .entries
+            .get(&key(user_id, guild_id, channel_id))
+            .is_some_and(|(loaded_at, _)| loaded_at.elapsed() < self.ttl)
+    }
+
+    /// Caches a context whose load started at `loaded_at`, unless the user's
+    /// entries were invalidated since.
+    pub fn insert(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+        loaded_at: Instant,
+        context: MemoryContext,
+    ) {
+        let mut state = self.lock();
+        let ttl = self.ttl;
+        state
+            .entries
+            .retain(|_, (loaded_at, _)| loaded_at.elapsed() < ttl);
+        state
+            .invalidated_at
+            .retain(|_, invalidated_at| invalidated_at.elapsed() < ttl);
+        if state
+            .invalidated_at
+            .get(user_id)
+            .is_some_and(|invalidated_at| *invalidated_at >= loaded_at)
+        {
+            return;
+        }
+        state
+            .entries
+            .insert(key(user_id, guild_id, channel_id), (loaded_at, context));
+    }
+
+    /// Removes and returns the cached context if it is still fresh.
+    pub fn take(&self, user_id: &str, guild_id: &str, channel_id: &str) -> Option<MemoryContext> {
+        let (loaded_at, context) = self
+            .lock()
+            .entries
+            .remove(&key(user_id, guild_id, channel_id))?;
+        (loaded_at.elapsed() < self.ttl).then_some(context)
+    }
+
+    /// Drops the user's cached contexts after something they include changed.
+    pub fn invalidate_user(&self, user_id: &str) {
+        let mut state = self.lock();
+        state
+            .entries
+            .retain(|(entry_user_id, _, _), _| entry_user_id != user_id);
+        state
+            .invalidated_at
+            .insert(user_id.to_owned(), Instant::now());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn key(user_id: &str, guild_id: &str, channel_id: &str) -> ConversationKey {
+    (
+        user_id.to_owned(),
+        guild_id.to_owned(),
+        channel_id.to_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefetched_context_is_used_once_while_fresh() {
+        let cache = ContextPrefetchCache::new(Duration::from_millis(50));
+        let context = MemoryContext {
+            summary: Some("likes chess".to_owned()),
+            ..MemoryContext::default()
+        };
+        cache.insert("u1", "g1", "c1", Instant::now(), context);
+        assert!(cache.is_fresh("u1", "g1", "c1"));
+        assert!(!cache.is_fresh("u1", "g1", "c2"));
+        assert_eq!(
+            cache
+                .take("u1", "g1", "c1")
+                .and_then(|context| context.summary),
+            Some("likes chess".to_owned())
+        );
+        assert!(cache.take("u1", "g1", "c1").is_none());
+
+        cache.insert("u1", "g1", "c1", Instant::now(), MemoryContext::default());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.take("u1", "g1", "c1").is_none());
+    }
+
+    #[test]
+    fn invalidation_drops_cached_and_in_flight_loads() {
+        let cache = ContextPrefetchCache::new(Duration::from_secs(60));
+        cache.insert("u1", "g1", "c1", Instant::now(), MemoryContext::default());
+        cache.insert("u2", "g1", "c1", Instant::now(), MemoryContext::default());
+        let in_flight_since = Instant::now();
+        cache.invalidate_user("u1");
+
+        assert!(!cache.is_fresh("u1", "g1", "c1"));
+        assert!(cache.is_fresh("u2", "g1", "c1"));
+        // A load that started before the invalidation may be stale.
+        cache.insert("u1", "g1", "c2", in_flight_since, MemoryContext::default());
+        assert!(!cache.is_fresh("u1", "g1", "c2"));
+        cache.insert("u1", "g1", "c2", Instant::now(), MemoryContext::default());
+        assert!(cache.is_fresh("u1", "g1", "c2"));
+    }
+}
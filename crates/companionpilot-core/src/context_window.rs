@@ -0,0 +1,131 @@
+use crate::types::{ContextLimits, DM_GUILD_ID, Modality};
+
+/// Where a turn happens, which decides how much context it gets: busy guild
+/// channels need more turns to follow along, spoken replies fewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextSurface {
+    GuildText,
+    Dm,
+    /// `/chat` and the website widgets.
+    Dashboard,
+    Voice,
+}
+
+impl ContextSurface {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::GuildText => "guild",
+            Self::Dm => "dm",
+            Self::Dashboard => "dashboard",
+            Self::Voice => "voice",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "guild" | "guild_text" => Some(Self::GuildText),
+            "dm" => Some(Self::Dm),
+            "dashboard" | "http" => Some(Self::Dashboard),
+            "voice" => Some(Self::Voice),
+            _ => None,
+        }
+    }
+
+    /// The surface of a Discord turn: voice by modality, DMs by guild.
+    pub fn of(guild_id: &str, modality: Modality) -> Self {
+        if modality == Modality::Voice {
+            Self::Voice
+        } else if guild_id == DM_GUILD_ID {
+            Self::Dm
+        } else {
+            Self::GuildText
+        }
+    }
+}
+
+/// The context limits of each surface.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextWindows {
+    pub guild_text: ContextLimits,
+    pub dm: ContextLimits,
+    pub dashboard: ContextLimits,
+    pub voice: ContextLimits,
+}
+
+impl ContextWindows {
+    /// Reads `surface=count` pairs separated by commas, e.g.
+    /// `guild=16,voice=4`, for recent messages and facts. Surfaces without an
+    /// entry keep the default limits.
+    pub fn parse(recent_messages: &str, facts: &str) -> Result<Self, String> {
+        let mut windows = Self::default();
+        for (surface, count) in parse_surface_counts(recent_messages)? {
+            windows.for_surface_mut(surface).recent_messages = count;
+        }
+        for (surface, count) in parse_surface_counts(facts)? {
+            windows.for_surface_mut(surface).facts = count;
+        }
+        Ok(windows)
+    }
+
+    pub fn for_surface(&self, surface: ContextSurface) -> ContextLimits {
+        match surface {
+            ContextSurface::GuildText => self.guild_text,
+            ContextSurface::Dm => self.dm,
+            ContextSurface::Dashboard => self.dashboard,
+            ContextSurface::Voice => self.voice,
+        }
+    }
+
+    fn for_surface_mut(&mut self, surface: ContextSurface) -> &mut ContextLimits {
+        match surface {
+            ContextSurface::GuildText => &mut self.guild_text,
+            ContextSurface::Dm => &mut self.dm,
+            ContextSurface::Dashboard => &mut self.dashboard,
+            ContextSurface::Voice => &mut self.voice,
+        }
+    }
+}
+
+fn parse_surface_counts(raw: &str) -> Result<Vec<(ContextSurface, usize)>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (surface, count) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected surface=count, got `{entry}`"))?;
+            let surface = ContextSurface::parse(surface).ok_or_else(|| {
+                format!(
+                    "unknown surface `{}`; expected guild, dm, dashboard or voice",
+                    surface.trim()
+                )
+            })?;
+            let count = count
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("`{}` is not a count", count.trim()))?;
+            Ok((surface, count))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_limits_per_surface() {
+        let windows = ContextWindows::parse("guild=16, voice=4", "voice=8").expect("valid windows");
+        assert_eq!(windows.guild_text.recent_messages, 16);
+        assert_eq!(
+            windows.for_surface(ContextSurface::Voice),
+            ContextLimits {
+                recent_messages: 4,
+                facts: 8
+            }
+        );
+        assert_eq!(windows.dm, ContextLimits::default());
+        assert!(ContextWindows::parse("thread=4", "").is_err());
+        assert!(ContextWindows::parse("dm=few", "").is_err());
+    }
+}
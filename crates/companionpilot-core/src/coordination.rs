@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tracing::warn;
+
+/// How long a Discord event id stays claimed; long enough to outlast any
+/// redelivery to another replica.
+pub const EVENT_DEDUPE_TTL: Duration = Duration::from_secs(10 * 60);
+/// First and longest pause between attempts to take a held lock.
+const LOCK_RETRY_MIN: Duration = Duration::from_millis(50);
+const LOCK_RETRY_MAX: Duration = Duration::from_millis(500);
+
+/// Shared locks and one-time claims that let several instances run behind one
+/// bot token and database without answering the same message twice.
+#[async_trait]
+pub trait InstanceCoordinator: Send + Sync {
+    /// Takes `key` for `ttl` unless someone else holds it; returns whether
+    /// this caller got it.
+    async fn try_acquire(&self, key: &str, token: &str, ttl: Duration) -> anyhow::Result<bool>;
+
+    /// Releases `key` if it is still held with `token`.
+    async fn release(&self, key: &str, token: &str) -> anyhow::Result<()>;
+}
+
+/// Claims `key` once across all instances, e.g. a Discord message id. Fails
+/// open: when the coordinator is unreachable every instance may proceed.
+pub async fn claim_once(coordinator: &dyn InstanceCoordinator, key: &str, ttl: Duration) -> bool {
+    match coordinator.try_acquire(key, &lock_token(), ttl).await {
+        Ok(claimed) => claimed,
+        Err(error) => {
+            warn!(
+                ?error,
+                key, "failed to claim key; proceeding without dedupe"
+            );
+            true
+        }
+    }
+}
+
+/// Returned (inside `anyhow::Error`) when another instance kept a
+/// conversation locked for longer than the caller was willing to wait.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversationLocked {
+    pub key: String,
+}
+
+impl fmt::Display for ConversationLocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "conversation `{}` is busy on another instance", self.key)
+    }
+}
+
+impl std::error::Error for ConversationLocked {}
+
+/// A held lock; released when dropped, or by expiry if the instance dies.
+pub struct DistributedLock {
+    coordinator: Arc<dyn InstanceCoordinator>,
+    key: String,
+    token: String,
+}
+
+impl Drop for DistributedLock {
+    fn drop(&mut self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let coordinator = self.coordinator.clone();
+        let key = std::mem::take(&mut self.key);
+        let token = std::mem::take(&mut self.token);
+        runtime.spawn(async move {
+            if let Err(error) = coordinator.release(&key, &token).await {
+                warn!(?error, key, "failed to release lock; it will expire");
+            }
+        });
+    }
+}
+
+/// Waits up to `ttl` for `key`, holding it for at most `ttl` once taken.
+/// Coordinator errors fail open and return `None` rather than blocking turns.
+pub async fn lock(
+    coordinator: &Arc<dyn InstanceCoordinator>,
+    key: &str,
+    ttl: Duration,
+) -> Result<Option<DistributedLock>, ConversationLocked> {
+    let token = lock_token();
+    let deadline = Instant::now() + ttl;
+    let mut pause = LOCK_RETRY_MIN;
+    loop {
+        match coordinator.try_acquire(key, &token, ttl).await {
+            Ok(true) => {
+                return Ok(Some(DistributedLock {
+                    coordinator: coordinator.clone(),
+                    key: key.to_owned(),
+                    token,
+                }));
+            }
+            Ok(false) if Instant::now() + pause < deadline => {
+                tokio::time::sleep(pause).await;
+                pause = (pause * 2).min(LOCK_RETRY_MAX);
+            }
+            Ok(false) => {
+                return Err(ConversationLocked {
+                    key: key.to_owned(),
+                });
+            }
+            Err(error) => {
+                warn!(?error, key, "failed to take lock; proceeding without it");
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// A value unique to this process and call, so only the holder releases a lock.
+fn lock_token() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    static STARTED: std::sync::OnceLock<u128> = std::sync::OnceLock::new();
+    let started = STARTED.get_or_init(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default()
+    });
+    format!(
+        "{}-{started:x}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Coordinates tasks within one process; for tests and single-instance
+/// embedding.
+#[derive(Debug, Default)]
+pub struct LocalCoordinator {
+    held: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+#[async_trait]
+impl InstanceCoordinator for LocalCoordinator {
+    async fn try_acquire(&self, key: &str, token: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let mut held = self
+            .held
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        if held.get(key).is_some_and(|(_, expires)| *expires > now) {
+            return Ok(false);
+        }
+        held.insert(key.to_owned(), (token.to_owned(), now + ttl));
+        Ok(true)
+    }
+
+    async fn release(&self, key: &str, token: &str) -> anyhow::Result<()> {
+        let mut held = self
+            .held
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if held.get(key).is_some_and(|(holder, _)| holder == token) {
+            held.remove(key);
+        }
+        Ok(())
+    }
+}
+
+/// Locks and claims stored in Redis, shared by every replica using the same
+/// server and key prefix.
+#[cfg(feature = "redis")]
+pub struct RedisCoordinator {
+    connection: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCoordinator {
+    pub async fn connect(redis_url: &str, prefix: &str) -> anyhow::Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let connection = client.get_connection_manager().await?;
+        Ok(Self {
+            connection,
+            prefix: prefix.trim_end_matches(':').to_owned(),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{key}", self.prefix)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl InstanceCoordinator for RedisCoordinator {
+    async fn try_acquire(&self, key: &str, token: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(reply.is_some())
+    }
+
+    async fn release(&self, key: &str, token: &str) -> anyhow::Result<()> {
+        // Compare-and-delete, so an expired lock retaken by another instance
+        // is left alone.
+        const RELEASE: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then \
+                               return redis.call('DEL', KEYS[1]) else return 0 end";
+        let _: i64 = redis::cmd("EVAL")
+            .arg(RELEASE)
+            .arg(1)
+            .arg(self.key(key))
+            .arg(token)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn locks_are_exclusive_until_released_or_expired() {
+        let coordinator: Arc<dyn InstanceCoordinator> = Arc::new(LocalCoordinator::default());
+        let ttl = Duration::from_millis(200);
+
+        let held = lock(&coordinator, "conversation:u1:c1", ttl)
+            .await
+            .expect("free lock")
+            .expect("local coordinator never fails");
+        assert!(!claim_once(coordinator.as_ref(), "conversation:u1:c1", ttl).await);
+        let waited = lock(
+            &coordinator,
+            "conversation:u1:c1",
+            Duration::from_millis(60),
+        )
+        .await;
+        assert!(waited.is_err());
+
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(claim_once(coordinator.as_ref(), "conversation:u1:c1", ttl).await);
+        assert!(!claim_once(coordinator.as_ref(), "conversation:u1:c1", ttl).await);
+
+        tokio::time::sleep(ttl).await;
+        assert!(claim_once(coordinator.as_ref(), "conversation:u1:c1", ttl).await);
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::memory::MemoryStore;
+
+/// Slash commands of the Discord adapter, which custom commands may not
+/// replace.
+pub const BUILTIN_COMMANDS: &[&str] = &[
+    "ask",
+    "retry",
+    "research",
+    "apikey",
+    "newchat",
+    "whatdoyouknow",
+    "greetings",
+    "notifications",
+    "calendar",
+    "stats",
+    "introduce",
+    "companion",
+    "voice",
+];
+/// Placeholders filled from the invocation instead of command options.
+const CONTEXT_PLACEHOLDERS: &[&str] = &["user", "date"];
+/// Discord allows 25 options per command.
+const MAX_OPTIONS: usize = 25;
+const MAX_DESCRIPTION_CHARS: usize = 100;
+const MAX_TEMPLATE_CHARS: usize = 4_000;
+
+static COMMAND_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z0-9_-]{1,32}$").expect("valid command name regex"));
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{([a-z0-9_]{1,32})\}").expect("valid placeholder regex"));
+
+/// An admin-defined slash command whose invocation sends a prompt template
+/// to the companion as the user's message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomCommand {
+    /// Slash command name, e.g. `summarize-week`.
+    pub name: String,
+    pub description: String,
+    /// The prompt, with `{placeholder}`s. `{user}` is the invoking user's
+    /// name and `{date}` today's date; every other placeholder becomes a
+    /// required text option of the command.
+    pub template: String,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CustomCommand {
+    pub fn validate(&self) -> Result<(), String> {
+        if !COMMAND_NAME.is_match(&self.name) {
+            return Err("name must be 1-32 lowercase letters, digits, `-` or `_`".to_owned());
+        }
+        if BUILTIN_COMMANDS.contains(&self.name.as_str()) {
+            return Err(format!("`/{}` is a built-in command", self.name));
+        }
+        let description = self.description.trim();
+        if description.is_empty() || description.chars().count() > MAX_DESCRIPTION_CHARS {
+            return Err(format!(
+                "description must be 1-{MAX_DESCRIPTION_CHARS} characters"
+            ));
+        }
+        if self.template.trim().is_empty() || self.template.chars().count() > MAX_TEMPLATE_CHARS {
+            return Err(format!(
+                "template must be 1-{MAX_TEMPLATE_CHARS} characters"
+            ));
+        }
+        if self.options().len() > MAX_OPTIONS {
+            return Err(format!("template has more than {MAX_OPTIONS} placeholders"));
+        }
+        Ok(())
+    }
+
+    /// Placeholders filled from command options, in template order.
+    pub fn options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        for captures in PLACEHOLDER.captures_iter(&self.template) {
+            let name = captures[1].to_owned();
+            if !CONTEXT_PLACEHOLDERS.contains(&name.as_str()) && !options.contains(&name) {
+                options.push(name);
+            }
+        }
+        options
+    }
+
+    /// The prompt for one invocation. Fails when an option is missing.
+    pub fn render(
+        &self,
+        args: &HashMap<String, String>,
+        user: &str,
+        today: NaiveDate,
+    ) -> Result<String, String> {
+        if let Some(missing) = self
+            .options()
+            .into_iter()
+            .find(|option| args.get(option).is_none_or(|value| value.trim().is_empty()))
+        {
+            return Err(format!("`{missing}` is required"));
+        }
+        Ok(PLACEHOLDER
+            .replace_all(&self.template, |captures: &Captures<'_>| {
+                match &captures[1] {
+                    "user" => user.to_owned(),
+                    "date" => today.format("%A %Y-%m-%d").to_string(),
+                    option => args
+                        .get(option)
+                        .map_or_else(String::new, |value| value.trim().to_owned()),
+                }
+            })
+            .trim()
+            .to_owned())
+    }
+}
+
+/// Custom commands kept in the memory store. Adapters that register them,
+/// such as Discord's slash commands, wait on [`CustomCommands::changed`]
+/// and also poll [`CustomCommands::list`], since the notification only
+/// covers changes made through this process.
+pub struct CustomCommands {
+    store: Arc<dyn MemoryStore>,
+    changed: Notify,
+}
+
+impl CustomCommands {
+    pub fn new(store: Arc<dyn MemoryStore>) -> Self {
+        Self {
+            store,
+            changed: Notify::new(),
+        }
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<CustomCommand>> {
+        self.store.list_custom_commands().await
+    }
+
+    pub async fn get(&self, name: &str) -> anyhow::Result<Option<CustomCommand>> {
+        Ok(self
+            .list()
+            .await?
+            .into_iter()
+            .find(|command| command.name == name))
+    }
+
+    /// Stores a validated command, replacing one with the same name.
+    pub async fn put(&self, mut command: CustomCommand) -> anyhow::Result<CustomCommand> {
+        command.description = command.description.trim().to_owned();
+        command.updated_at = Utc::now();
+        self.store.put_custom_command(command.clone()).await?;
+        self.changed.notify_one();
+        Ok(command)
+    }
+
+    pub async fn delete(&self, name: &str) -> anyhow::Result<bool> {
+        let deleted = self.store.delete_custom_command(name).await?;
+        if deleted {
+            self.changed.notify_one();
+        }
+        Ok(deleted)
+    }
+
+    /// Resolves after the next change made through this instance.
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_and_renders_templates() {
+        let command = CustomCommand {
+            name: "summarize-week".to_owned(),
+            description: "Summarize our week".to_owned(),
+            template: "Summarize what {user} and you talked about in the week before {date}, focusing on {topic}. Mention {topic} first.".to_owned(),
+            updated_at: Utc::now(),
+        };
+        assert_eq!(command.validate(), Ok(()));
+        assert_eq!(command.options(), vec!["topic".to_owned()]);
+
+        let today = NaiveDate::from_ymd_opt(2026, 3, 15).expect("valid date");
+        let args = HashMap::from([("topic".to_owned(), " books ".to_owned())]);
+        assert_eq!(
+            command.render(&args, "Petr", today).as_deref(),
+            Ok(
+                "Summarize what Petr and you talked about in the week before Sunday 2026-03-15, focusing on books. Mention books first."
+            )
+        );
+        assert!(command.render(&HashMap::new(), "Petr", today).is_err());
+
+        let builtin = CustomCommand {
+            name: "ask".to_owned(),
+            ..command.clone()
+        };
+        assert!(builtin.validate().is_err());
+        let uppercase = CustomCommand {
+            name: "Summarize".to_owned(),
+            ..command
+        };
+        assert!(uppercase.validate().is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use chrono::{Datelike, NaiveDate};
+
+use crate::types::MemoryFact;
+
+/// Fact key holding the user's birthday.
+pub const BIRTHDAY_FACT_KEY: &str = "birthday";
+/// Keys that are or end in this hold the user's anniversaries, e.g.
+/// `wedding_anniversary`.
+pub const ANNIVERSARY_KEY_SUFFIX: &str = "anniversary";
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFactKind {
+    Birthday,
+    Anniversary,
+}
+
+/// A yearly date from the user's facts, such as their birthday.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFact {
+    pub key: String,
+    pub kind: DateFactKind,
+    pub month: u32,
+    pub day: u32,
+    /// The year it first happened, when known.
+    pub year: Option<i32>,
+}
+
+impl DateFact {
+    /// The date fact stored under `fact`, if its key is a date key and its
+    /// value a date.
+    pub fn from_fact(fact: &MemoryFact) -> Option<Self> {
+        Self::parse(&fact.key, &fact.value)
+    }
+
+    pub fn parse(key: &str, value: &str) -> Option<Self> {
+        let kind = date_fact_kind(key)?;
+        let (month, day, year) = parse_yearly_date(value)?;
+        Some(Self {
+            key: key.to_owned(),
+            kind,
+            month,
+            day,
+            year,
+        })
+    }
+
+    /// What it is called, e.g. `birthday` or `wedding anniversary`.
+    pub fn label(&self) -> String {
+        self.key.replace('_', " ")
+    }
+
+    /// Stored form: `YYYY-MM-DD`, or `MM-DD` when the year is unknown.
+    pub fn value(&self) -> String {
+        match self.year {
+            Some(year) => format!("{year:04}-{:02}-{:02}", self.month, self.day),
+            None => format!("{:02}-{:02}", self.month, self.day),
+        }
+    }
+
+    /// Whether it falls on `date`. February 29 is celebrated on February 28
+    /// in other years.
+    pub fn falls_on(&self, date: NaiveDate) -> bool {
+        let day = if self.month == 2 && self.day == 29 && !date.leap_year() {
+            28
+        } else {
+            self.day
+        };
+        date.month() == self.month && date.day() == day
+    }
+
+    /// Years since it first happened, as of `date` (an age for birthdays).
+    pub fn years_on(&self, date: NaiveDate) -> Option<i32> {
+        self.year
+            .map(|year| date.year() - year)
+            .filter(|years| *years > 0)
+    }
+}
+
+/// Whether facts under `key` are yearly dates.
+pub fn is_date_fact_key(key: &str) -> bool {
+    date_fact_kind(key).is_some()
+}
+
+fn date_fact_kind(key: &str) -> Option<DateFactKind> {
+    if key == BIRTHDAY_FACT_KEY {
+        Some(DateFactKind::Birthday)
+    } else if key == ANNIVERSARY_KEY_SUFFIX
+        || key
+            .strip_suffix(ANNIVERSARY_KEY_SUFFIX)
+            .is_some_and(|prefix| prefix.ends_with('_'))
+    {
+        Some(DateFactKind::Anniversary)
+    } else {
+        None
+    }
+}
+
+/// Parses a yearly date as people write it: `1990-05-14`, `05-14`,
+/// `14.5.1990`, `May 14th`, `14 May 1990` or `the 14th of May`. Returns
+/// month, day and the year if one was given. Slashed dates are ambiguous
+/// between day and month first and are not accepted.
+pub fn parse_yearly_date(raw: &str) -> Option<(u32, u32, Option<i32>)> {
+    let raw = raw.trim().to_lowercase();
+    let numbers = |separator: char| {
+        raw.trim_end_matches(separator)
+            .split(separator)
+            .map(|part| part.trim().parse::<i32>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let (month, day, year) = if let Some(parts) = numbers('-') {
+        match parts[..] {
+            [year, month, day] if year > 31 => (month, day, Some(year)),
+            [month, day] => (month, day, None),
+            _ => return None,
+        }
+    } else if let Some(parts) = numbers('.') {
+        match parts[..] {
+            [day, month, year] => (month, day, Some(year)),
+            [day, month] => (month, day, None),
+            _ => return None,
+        }
+    } else {
+        let mut month = None;
+        let mut day = None;
+        let mut year = None;
+        for word in raw.split(|ch: char| ch.is_whitespace() || ch == ',') {
+            let word = word.trim_end_matches('.');
+            if word.is_empty() || word == "of" || word == "the" {
+                continue;
+            }
+            if word.len() >= 3
+                && let Some(index) = MONTHS.iter().position(|name| name.starts_with(word))
+            {
+                month = Some(index as i32 + 1);
+                continue;
+            }
+            let digits = ["st", "nd", "rd", "th"]
+                .iter()
+                .find_map(|suffix| word.strip_suffix(suffix))
+                .unwrap_or(word);
+            match digits.parse::<i32>().ok()? {
+                number if digits.len() == 4 => year = Some(number),
+                number => day = Some(number),
+            }
+        }
+        (month?, day?, year)
+    };
+    let month = u32::try_from(month).ok()?;
+    let day = u32::try_from(day).ok()?;
+    // A leap year, so February 29 is accepted without a year.
+    NaiveDate::from_ymd_opt(year.unwrap_or(2000), month, day)?;
+    Some((month, day, year.filter(|year| *year >= 1900)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn parses_written_dates_and_finds_their_day() {
+        assert_eq!(parse_yearly_date("1990-05-14"), Some((5, 14, Some(1990))));
+        assert_eq!(parse_yearly_date("05-14"), Some((5, 14, None)));
+        assert_eq!(parse_yearly_date("14.5.1990"), Some((5, 14, Some(1990))));
+        assert_eq!(parse_yearly_date("May 14th"), Some((5, 14, None)));
+        assert_eq!(
+            parse_yearly_date("the 14th of May, 1990"),
+            Some((5, 14, Some(1990)))
+        );
+        assert_eq!(parse_yearly_date("Feb 30"), None);
+        assert_eq!(parse_yearly_date("5/14/1990"), None);
+        assert_eq!(parse_yearly_date("next spring"), None);
+
+        assert!(is_date_fact_key("wedding_anniversary"));
+        assert!(!is_date_fact_key("mom_birthday"));
+        let leap = DateFact::from_fact(&MemoryFact {
+            key: "birthday".to_owned(),
+            value: "29 February 2000".to_owned(),
+            confidence: 0.9,
+            source: "user_message".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        })
+        .expect("date fact");
+        assert_eq!(leap.value(), "2000-02-29");
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).expect("valid date");
+        assert!(leap.falls_on(date(2027, 2, 28)));
+        assert!(!leap.falls_on(date(2028, 2, 28)));
+        assert!(leap.falls_on(date(2028, 2, 29)));
+        assert_eq!(leap.years_on(date(2027, 2, 28)), Some(27));
+    }
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{
+    date_facts::{DateFact, DateFactKind},
+    memory::MemoryStore,
+    timezone::user_timezone,
+};
+
+/// Users looked at per run.
+const USER_SCAN_LIMIT: usize = 1_000;
+const FACT_SCAN_LIMIT: usize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct DateGreetingConfig {
+    /// Hour of the day (UTC) greetings are sent at.
+    pub hour_utc: u32,
+    /// Channel greetings are posted in, mentioning the user; `None` sends
+    /// them as DMs. Users who are not in that channel's server get a DM.
+    pub channel_id: Option<u64>,
+}
+
+impl Default for DateGreetingConfig {
+    fn default() -> Self {
+        Self {
+            hour_utc: 9,
+            channel_id: None,
+        }
+    }
+}
+
+/// A user whose birthday or anniversary is today.
+#[derive(Debug, Clone)]
+pub struct DueGreeting {
+    pub user_id: String,
+    pub date_fact: DateFact,
+    /// Today in the user's time zone.
+    pub day: NaiveDate,
+}
+
+impl DueGreeting {
+    /// What the companion is asked to write. Greetings posted in a server
+    /// (`private` is false) leave out the age and anything personal.
+    pub fn instruction(&self, private: bool) -> String {
+        if !private {
+            return format!(
+                "Today is the user's {}. Write them a short, warm message for it in your own voice, as a friend who remembered. Others in the channel read it, so do not mention their age or anything personal about them. Do not ask them anything and do not say you were reminded.",
+                self.occasion_label()
+            );
+        }
+        let years = self.date_fact.years_on(self.day);
+        let occasion = match (self.date_fact.kind, years) {
+            (DateFactKind::Birthday, Some(age)) => format!("birthday; they turn {age}"),
+            (DateFactKind::Birthday, None) => "birthday".to_owned(),
+            (DateFactKind::Anniversary, Some(years)) => {
+                format!("{}, {years} years", self.date_fact.label())
+            }
+            (DateFactKind::Anniversary, None) => self.date_fact.label(),
+        };
+        format!(
+            "Today is the user's {occasion}. Write them a short, warm message for it in your own voice, as a friend who remembered. Mention something you know about them if it fits naturally. Do not ask them anything and do not say you were reminded."
+        )
+    }
+
+    fn occasion_label(&self) -> String {
+        match self.date_fact.kind {
+            DateFactKind::Birthday => "birthday".to_owned(),
+            DateFactKind::Anniversary => self.date_fact.label(),
+        }
+    }
+
+    /// Sent when the companion could not write the greeting.
+    pub fn fallback_text(&self) -> String {
+        match self.date_fact.kind {
+            DateFactKind::Birthday => "Happy birthday! 🎂 Hope it's a great one.".to_owned(),
+            DateFactKind::Anniversary => format!("Happy {}! 🎉", self.date_fact.label()),
+        }
+    }
+}
+
+/// Birthdays and anniversaries that fall on the users' current local date
+/// (UTC without a `timezone` fact). Greetings already sent and users'
+/// notification preferences are checked when they are sent.
+pub async fn due_greetings(
+    store: &dyn MemoryStore,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<DueGreeting>> {
+    let mut due = Vec::new();
+    for user in store.list_users(USER_SCAN_LIMIT).await? {
+        // Only Discord users (numeric ids) can be messaged.
+        if user.user_id.parse::<u64>().is_err() {
+            continue;
+        }
+        let facts = store.list_facts(&user.user_id, FACT_SCAN_LIMIT).await?;
+        let day = user_timezone(&facts)
+            .map_or(now.date_naive(), |tz| now.with_timezone(&tz).date_naive());
+        let today = facts
+            .iter()
+            .filter_map(DateFact::from_fact)
+            .filter(|date_fact| date_fact.falls_on(day))
+            .collect::<Vec<_>>();
+        if today.is_empty() {
+            continue;
+        }
+        due.extend(today.into_iter().map(|date_fact| DueGreeting {
+            user_id: user.user_id.clone(),
+            date_fact,
+            day,
+        }));
+    }
+    Ok(due)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{memory::InMemoryMemoryStore, types::MemoryFact};
+
+    #[tokio::test]
+    async fn finds_todays_dates_in_the_users_time_zone() {
+        let store = InMemoryMemoryStore::default();
+        let fact = |key: &str, value: &str| MemoryFact {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            confidence: 0.9,
+            source: "user_message".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        };
+        for (user_id, facts) in [
+            ("1", vec![fact("birthday", "1996-03-15")]),
+            (
+                "2",
+                vec![
+                    fact("wedding_anniversary", "2020-03-15"),
+                    fact("timezone", "Pacific/Auckland"),
+                ],
+            ),
+            ("web-user", vec![fact("birthday", "03-15")]),
+        ] {
+            for fact in facts {
+                store.upsert_fact(user_id, fact).await.expect("stored");
+            }
+        }
+
+        // Late on March 14 in UTC is already March 15 in Auckland.
+        let now = Utc
+            .with_ymd_and_hms(2026, 3, 14, 20, 0, 0)
+            .single()
+            .expect("valid timestamp");
+        let due = due_greetings(&store, now).await.expect("scan succeeds");
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].user_id, "2");
+        assert!(
+            due[0]
+                .instruction(true)
+                .contains("wedding anniversary, 6 years")
+        );
+
+        let next_day = now + chrono::Duration::hours(12);
+        let due = due_greetings(&store, next_day)
+            .await
+            .expect("scan succeeds");
+        let birthday = due
+            .iter()
+            .find(|greeting| greeting.user_id == "1")
+            .expect("birthday is due");
+        assert!(birthday.instruction(true).contains("they turn 30"));
+        let public = birthday.instruction(false);
+        assert!(public.contains("birthday") && !public.contains("30"));
+        assert!(due.iter().all(|greeting| greeting.user_id != "web-user"));
+        assert!(
+            !store
+                .has_date_greeting("1", "birthday", birthday.day)
+                .await
+                .expect("checked")
+        );
+        assert!(
+            store
+                .record_date_greeting("1", "birthday", birthday.day)
+                .await
+                .expect("recorded")
+        );
+        assert!(
+            store
+                .has_date_greeting("1", "birthday", birthday.day)
+                .await
+                .expect("checked")
+        );
+        assert!(
+            !store
+                .record_date_greeting("1", "birthday", birthday.day)
+                .await
+                .expect("recorded")
+        );
+    }
+}
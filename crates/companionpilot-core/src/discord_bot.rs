@@ -1,84 +1,2196 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use serde_json::json;
 use serenity::{
+    all::{
+        ActivityData, ButtonStyle, ChannelId, ChannelType, Command, CommandDataOptionValue,
+        CommandInteraction, CommandOptionType, ComponentInteraction, ComponentInteractionDataKind,
+        CreateActionRow, CreateButton, CreateCommand, CreateCommandOption,
+        CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditInteractionResponse,
+        Emoji, EmojiId, GetMessages, Guild, GuildId, Http, Interaction, MessageId,
+        MessageReference, Permissions, Reaction, ReactionType, Ready, UserId,
+    },
     async_trait,
-    model::{channel::Message, gateway::GatewayIntents, prelude::VoiceState},
+    model::{
+        channel::Message, event::TypingStartEvent, gateway::GatewayIntents, prelude::VoiceState,
+    },
     prelude::*,
 };
 use songbird::{SerenityInit, Songbird};
+use tokio::sync::mpsc;
 use tracing::{error, info, warn};
 
-use crate::{orchestrator::DefaultChatOrchestrator, types::MessageCtx, voice::VoiceManager};
+use crate::{
+    activity::Presence,
+    alerts::AdminAlert,
+    background_tasks::render_task_followup,
+    calendar::{feed_salt, rotate_feed_salt},
+    concurrency::{BUSY_REPLY_TEXT, is_busy},
+    coordination::{EVENT_DEDUPE_TTL, claim_once},
+    custom_commands::CustomCommand,
+    date_greetings::{DateGreetingConfig, DueGreeting, due_greetings},
+    fact_backfill::until_next_run,
+    feedback::feedback_score,
+    flags::TurnFlags,
+    guild_emoji::GuildEmoji,
+    guild_settings::{CitationStyle, GuildSettings, QuietMentionPolicy},
+    in_flight::{
+        IN_FLIGHT_HEARTBEAT, InFlightRecovery, claim_stale_in_flight, lost_reply_notice, retry_ctx,
+        should_retry,
+    },
+    memory::MemoryStore,
+    memory_review::{
+        MemoryReviewConfig, REVIEW_ID_PREFIX, ReviewBatch, ReviewVerdict, apply_review_verdict,
+        collect_review_batches, parse_review_button_id, review_button_id, review_message,
+    },
+    memory_summary::load_memory_summary,
+    model::{GenerationParams, KeyProvider},
+    notifications::{NotificationChannel, NotificationDecision, NotificationKind, QuietHours},
+    orchestrator::{ChatOrchestrator, TurnOptions},
+    outbox::{
+        OUTBOX_POLL_INTERVAL, PermanentFailure, claim_due_outbox, defer_outbox_message,
+        delayed_reply_text, finish_outbox_attempt, queue_outbox_message,
+    },
+    personas::{PersonaBundle, PersonaRegistry},
+    pins::{PIN_EMOJI, find_pin_target},
+    rate_limit::{RATE_LIMITED_REPLY_TEXT, RateLimited},
+    reply_filters::{
+        DISCORD_MESSAGE_LIMIT, MaxLength, ReplyFilter, ReplyFilterContext, append_within_limit,
+    },
+    tools::builtin_tool_specs,
+    types::{
+        BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord, ChatRole, DM_GUILD_ID,
+        MessageAttachment, MessageCtx, MessageFeedbackRecord, Modality, OrchestratorReply,
+        OutboxKind, OutboxRecord, TaskOrigin, TurnFailure, content_with_attachments,
+    },
+    voice::{VoiceManager, VoiceStateChange},
+};
+
+const SETUP_ID_PREFIX: &str = "companion_setup:";
+const ONBOARDING_ID_PREFIX: &str = "onboarding:";
+const NO_PERSONA_VALUE: &str = "__default__";
+/// Discord allows 25 options, and 25 selected values, per select menu.
+const SELECT_MENU_LIMIT: usize = 25;
+const DEFAULT_VOICE_ASK_SECS: u64 = 8;
+const MIN_VOICE_ASK_SECS: i64 = 2;
+const MAX_VOICE_ASK_SECS: i64 = 30;
+/// Latest stored messages searched for the one a pin or feedback reaction
+/// points at.
+const PIN_SEARCH_LIMIT: usize = 200;
+/// Discord's limit on a component `custom_id`.
+const MAX_CUSTOM_ID_LEN: usize = 100;
+/// Longest quote of a slash command's question above its reply.
+const MAX_QUOTE_CHARS: usize = 300;
+/// Name the bot's heartbeat is stored under.
+const HEARTBEAT_ADAPTER: &str = "discord";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the custom commands are reloaded, so changes made through
+/// another replica are registered too.
+const CUSTOM_COMMAND_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Mentions older than this are not replayed after downtime.
+const MAX_REPLAY_AGE: chrono::Duration = chrono::Duration::hours(24);
+const MAX_REPLAY_PER_CHANNEL: u8 = 50;
+/// Milliseconds from the Unix epoch to Discord's snowflake epoch (2015).
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+/// Footer of birthday and anniversary greetings.
+const GREETINGS_OPT_OUT_HINT: &str =
+    "-# `/greetings enabled:False` turns these messages off; `/notifications` sets quiet hours.";
+/// How long a greeting held back by the user's notification preferences is
+/// retried after the daily run.
+const GREETING_DEFERRAL: chrono::Duration = chrono::Duration::hours(12);
+/// Footer of replies to mentions during quiet hours, for guilds that mark them.
+const QUIET_HOURS_NOTE: &str = "-# 🌙 It's quiet hours right now.";
+
+struct Handler {
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    voice: Option<Arc<VoiceManager>>,
+    debouncer: MessageDebouncer,
+    admin_channel_id: Option<u64>,
+    admin_alerts: Mutex<Option<mpsc::UnboundedReceiver<AdminAlert>>>,
+    background_results: Mutex<Option<mpsc::UnboundedReceiver<BackgroundTaskRecord>>>,
+    in_flight_recovery: InFlightRecovery,
+    personas: Arc<PersonaRegistry>,
+    memory_review: Mutex<Option<MemoryReviewConfig>>,
+    date_greetings: Mutex<Option<DateGreetingConfig>>,
+    presence_interval: Mutex<Option<Duration>>,
+    started: AtomicBool,
+}
+
+/// Discord adapter settings beyond the token and shared services.
+#[derive(Default)]
+pub struct DiscordBotOptions {
+    /// Window for coalescing bursts of messages; zero disables debouncing.
+    pub debounce_window: Duration,
+    /// Channel that receives operational alerts.
+    pub admin_channel_id: Option<u64>,
+    pub admin_alerts: Option<mpsc::UnboundedReceiver<AdminAlert>>,
+    /// Personas offered by `/companion setup`.
+    pub personas: Arc<PersonaRegistry>,
+    /// Periodic DMs asking users to confirm stale facts; `None` disables them.
+    pub memory_review: Option<MemoryReviewConfig>,
+    /// Daily greetings on users' birthdays and anniversaries; `None`
+    /// disables them.
+    pub date_greetings: Option<DateGreetingConfig>,
+    /// Request the privileged message content intent. Without it the bot
+    /// only answers `/ask` and DMs.
+    pub message_content_intent: bool,
+    /// How often the bot's status is refreshed from live activity; `None`
+    /// leaves the status alone.
+    pub presence_interval: Option<Duration>,
+    /// `(shard_id, shard_count)` this instance connects as, so replicas split
+    /// guilds between them; `None` runs a single shard.
+    pub shard: Option<(u32, u32)>,
+    /// Subscribe to typing events and prefetch the typing user's memory
+    /// context before their message arrives.
+    pub typing_prefetch: bool,
+    /// Finished background tasks, posted to the channel they started in.
+    pub background_results: Option<mpsc::UnboundedReceiver<BackgroundTaskRecord>>,
+    /// What happens to replies that a crash interrupted.
+    pub in_flight_recovery: InFlightRecovery,
+}
+
+#[derive(Default)]
+struct PendingBurst {
+    generation: u64,
+    contents: Vec<String>,
+}
+
+/// Coalesces messages a user sends to one channel in quick succession, so a
+/// thought split across several Discord messages becomes one turn.
+struct MessageDebouncer {
+    window: Duration,
+    pending: Mutex<HashMap<(u64, u64), PendingBurst>>,
+}
+
+impl MessageDebouncer {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the combined content once no further message arrived within the
+    /// window, or `None` when a later message will carry this one.
+    async fn coalesce(&self, user_id: u64, channel_id: u64, content: String) -> Option<String> {
+        if self.window.is_zero() {
+            return Some(content);
+        }
+
+        let key = (user_id, channel_id);
+        let generation = {
+            let mut pending = self.pending.lock().await;
+            let burst = pending.entry(key).or_default();
+            burst.generation += 1;
+            burst.contents.push(content);
+            burst.generation
+        };
+
+        tokio::time::sleep(self.window).await;
+
+        let mut pending = self.pending.lock().await;
+        if pending.get(&key)?.generation != generation {
+            return None;
+        }
+        pending.remove(&key).map(|burst| burst.contents.join("\n"))
+    }
+}
+
+impl Handler {
+    async fn handle_command(&self, ctx: &Context, command: &CommandInteraction) {
+        if command.data.name == "companion" {
+            self.companion_command(ctx, command).await;
+            return;
+        }
+        if command.data.name == "whatdoyouknow" {
+            self.whatdoyouknow_command(ctx, command).await;
+            return;
+        }
+        if command.data.name == "introduce" {
+            self.introduce_command(ctx, command).await;
+            return;
+        }
+        let private_reply = match command.data.name.as_str() {
+            "apikey" => Some(self.apikey_command(command).await),
+            "greetings" => Some(self.greetings_command(command).await),
+            "notifications" => Some(self.notifications_command(command).await),
+            "calendar" => Some(self.calendar_command(command).await),
+            "stats" => Some(self.stats_command(command).await),
+            _ => None,
+        };
+        if let Some(content) = private_reply {
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            );
+            if let Err(error) = command.create_response(&ctx.http, response).await {
+                error!(?error, command = %command.data.name, "failed to answer slash command");
+            }
+            return;
+        }
+        if let Err(error) = command.defer(&ctx.http).await {
+            error!(?error, command = %command.data.name, "failed to defer slash command");
+            return;
+        }
+
+        let text = match command.data.name.as_str() {
+            "ask" => self.ask_command(command).await,
+            "retry" => self.retry_command(command).await,
+            "research" => self.research_command(command).await,
+            "newchat" => self.newchat_command(command).await,
+            "voice" => self.voice_command(command).await,
+            _ => self.custom_command(command).await,
+        };
+
+        let response = EditInteractionResponse::new().content(text);
+        if let Err(error) = command.edit_response(&ctx.http, response).await {
+            error!(?error, command = %command.data.name, "failed to answer slash command");
+        }
+    }
+
+    /// `/ask`: a chat turn that works without the message content intent.
+    /// The interaction is deferred first, so slow replies still arrive.
+    async fn ask_command(&self, command: &CommandInteraction) -> String {
+        let Some(question) = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "question")
+            .and_then(|option| option.value.as_str())
+            .map(str::trim)
+            .filter(|question| !question.is_empty())
+        else {
+            return "Ask me something with `/ask question:...`.".to_owned();
+        };
+        let raw_flags = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "flags")
+            .and_then(|option| option.value.as_str());
+        let can_manage = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.manage_guild());
+        let flags = match raw_flags {
+            None => TurnFlags::default(),
+            Some(_) if !can_manage => {
+                return "Only server managers can set flags.".to_owned();
+            }
+            Some(raw) => match TurnFlags::parse(raw) {
+                Ok(flags) => flags,
+                Err(error) => return format!("Invalid flags: {error}"),
+            },
+        };
+        let flags_note = if flags.is_empty() {
+            String::new()
+        } else {
+            format!("\n-# flags: {flags}")
+        };
+        let options = TurnOptions {
+            flags,
+            ..TurnOptions::default()
+        };
+        let text = self
+            .command_turn(command, question.to_owned(), options, question)
+            .await;
+        format!("{text}{flags_note}")
+    }
+
+    /// A custom command: its prompt template, filled from the command's
+    /// options, is sent as the user's message.
+    async fn custom_command(&self, command: &CommandInteraction) -> String {
+        let name = &command.data.name;
+        let custom = match self.orchestrator.custom_commands() {
+            Some(custom_commands) => custom_commands.get(name).await,
+            None => Ok(None),
+        };
+        let custom = match custom {
+            Ok(Some(custom)) => custom,
+            Ok(None) => return format!("Unknown command `/{name}`."),
+            Err(error) => {
+                error!(?error, command = %name, "failed to load custom command");
+                return "I couldn't load that command right now.".to_owned();
+            }
+        };
+        let args = command
+            .data
+            .options
+            .iter()
+            .filter_map(|option| {
+                let value = option.value.as_str()?;
+                Some((option.name.clone(), value.to_owned()))
+            })
+            .collect::<HashMap<_, _>>();
+        let user = command.member.as_ref().map_or_else(
+            || command.user.display_name(),
+            |member| member.display_name(),
+        );
+        let prompt = match custom.render(&args, user, Utc::now().date_naive()) {
+            Ok(prompt) => prompt,
+            Err(error) => return format!("Couldn't run `/{name}`: {error}."),
+        };
+        let invocation = std::iter::once(format!("/{name}"))
+            .chain(
+                custom
+                    .options()
+                    .into_iter()
+                    .filter_map(|option| Some(format!("{option}: {}", args.get(&option)?))),
+            )
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.command_turn(command, prompt, TurnOptions::default(), &invocation)
+            .await
+    }
+
+    /// Runs `content` as a chat turn for a slash command and renders the
+    /// reply under a quote of the invocation. Channel restrictions apply even
+    /// though slash commands always address the bot.
+    async fn command_turn(
+        &self,
+        command: &CommandInteraction,
+        content: String,
+        mut options: TurnOptions,
+        quote: &str,
+    ) -> String {
+        options.track_in_flight = true;
+        let guild_id = command
+            .guild_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| DM_GUILD_ID.to_owned());
+        let settings = self
+            .guild_settings(&guild_id)
+            .await
+            .unwrap_or_else(|error| {
+                warn!(?error, %guild_id, "failed to load guild settings; using defaults");
+                GuildSettings::new(&guild_id)
+            });
+        if command.guild_id.is_some()
+            && !settings
+                .activation
+                .should_respond(&command.channel_id.to_string(), true)
+        {
+            return "I'm not set up to answer in this channel.".to_owned();
+        }
+
+        let user_id = command.user.id.to_string();
+        let request = MessageCtx {
+            message_id: command.id.to_string(),
+            user_id: user_id.clone(),
+            guild_id,
+            channel_id: command.channel_id.to_string(),
+            content,
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        match self
+            .orchestrator
+            .handle_message_with_options(request, options)
+            .await
+        {
+            Ok(reply) if !reply.text.trim().is_empty() => {
+                let text = self.finish_reply(settings.citation_style, &settings.guild_id, &reply);
+                quoted_reply(quote, &text)
+            }
+            Ok(_) => quoted_reply(quote, "I don't have anything to add to that."),
+            Err(error) if is_busy(&error) => BUSY_REPLY_TEXT.to_owned(),
+            Err(error) if error.downcast_ref::<RateLimited>().is_some() => {
+                RATE_LIMITED_REPLY_TEXT.to_owned()
+            }
+            Err(error) => {
+                error!(?error, %user_id, command = %command.data.name, "failed to answer slash command");
+                failure_reply_text(&error)
+            }
+        }
+    }
+
+    async fn retry_command(&self, command: &CommandInteraction) -> String {
+        let temperature = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "temperature")
+            .and_then(|option| option.value.as_f64())
+            .map(|value| value as f32);
+        let user_id = command.user.id.to_string();
+        let channel_id = command.channel_id.to_string();
+
+        let regenerated = self
+            .orchestrator
+            .regenerate_last_reply(
+                &user_id,
+                Some(&channel_id),
+                GenerationParams {
+                    temperature,
+                    ..GenerationParams::default()
+                },
+            )
+            .await;
+        match regenerated {
+            Ok(Some(reply)) if !reply.text.trim().is_empty() => {
+                let guild_id = command
+                    .guild_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| DM_GUILD_ID.to_owned());
+                self.finish_reply(CitationStyle::Off, &guild_id, &reply)
+            }
+            Ok(Some(_)) => "I regenerated the reply, but it came back empty.".to_owned(),
+            Ok(None) => "There is no earlier message of yours here to retry.".to_owned(),
+            Err(error) if is_busy(&error) => BUSY_REPLY_TEXT.to_owned(),
+            Err(error) if error.downcast_ref::<RateLimited>().is_some() => {
+                RATE_LIMITED_REPLY_TEXT.to_owned()
+            }
+            Err(error) => {
+                error!(?error, %user_id, %channel_id, "failed to regenerate reply");
+                "Sorry, I couldn't regenerate that reply.".to_owned()
+            }
+        }
+    }
+
+    /// `/research`: starts a deep research task whose report is posted as a
+    /// follow-up, with progress notes while it runs.
+    async fn research_command(&self, command: &CommandInteraction) -> String {
+        let Some(question) = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "question")
+            .and_then(|option| option.value.as_str())
+            .map(str::trim)
+            .filter(|question| !question.is_empty())
+        else {
+            return "Tell me what to research with `/research question:...`.".to_owned();
+        };
+        let user_id = command.user.id.to_string();
+        let request = MessageCtx {
+            message_id: command.id.to_string(),
+            user_id: user_id.clone(),
+            guild_id: command
+                .guild_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| DM_GUILD_ID.to_owned()),
+            channel_id: command.channel_id.to_string(),
+            content: question.to_owned(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        match self
+            .orchestrator
+            .start_background_tool(
+                &request,
+                TaskOrigin::Discord,
+                "deep_research",
+                json!({ "question": question }),
+            )
+            .await
+        {
+            Ok(Some(_)) => quoted_reply(
+                question,
+                "I'm researching this now. It can take a few minutes; I'll post the report here when it's ready.",
+            ),
+            Ok(None) => "Research is not enabled for this bot.".to_owned(),
+            Err(error) if error.downcast_ref::<RateLimited>().is_some() => {
+                RATE_LIMITED_REPLY_TEXT.to_owned()
+            }
+            Err(error) => {
+                warn!(?error, %user_id, "failed to start research");
+                format!("Sorry, I couldn't start that research: {error}")
+            }
+        }
+    }
+
+    /// `/greetings`: turns birthday and anniversary greetings on or off for
+    /// the user.
+    async fn greetings_command(&self, command: &CommandInteraction) -> String {
+        let Some(enabled) = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "enabled")
+            .and_then(|option| option.value.as_bool())
+        else {
+            return "Use `/greetings enabled:True` or `/greetings enabled:False`.".to_owned();
+        };
+        let user_id = command.user.id.to_string();
+        let Some(notifications) = self.orchestrator.notifications() else {
+            return "Notification settings aren't available.".to_owned();
+        };
+        let saved = match notifications.get(&user_id).await {
+            Ok(mut prefs) => {
+                prefs.set_muted(NotificationKind::DateGreetings, !enabled);
+                notifications.put(prefs).await
+            }
+            Err(error) => Err(error),
+        };
+        match saved {
+            Ok(prefs) if enabled && !prefs.enabled => {
+                "Greetings are on, but all my proactive messages are off. Turn them on with `/notifications enabled:True`.".to_owned()
+            }
+            Ok(_) if enabled => {
+                "I'll wish you a happy birthday and anniversaries when I know them.".to_owned()
+            }
+            Ok(_) => "Okay, no more birthday or anniversary messages from me.".to_owned(),
+            Err(error) => {
+                error!(?error, %user_id, "failed to store greeting preference");
+                "Sorry, I couldn't save that.".to_owned()
+            }
+        }
+    }
+
+    /// `/calendar [new_link]`: hands the user the link to their birthdays
+    /// and anniversaries as a calendar subscription; `new_link` revokes the
+    /// old one first.
+    async fn calendar_command(&self, command: &CommandInteraction) -> String {
+        let Some(feeds) = self.orchestrator.calendar_feeds() else {
+            return "Calendar subscriptions aren't enabled here.".to_owned();
+        };
+        let user_id = command.user.id.to_string();
+        let memory = self.orchestrator.memory();
+        let new_link = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "new_link")
+            .and_then(|option| option.value.as_bool())
+            .unwrap_or(false);
+        if new_link && let Err(error) = rotate_feed_salt(memory.as_ref(), &user_id).await {
+            error!(?error, %user_id, "failed to rotate calendar link");
+            return "Sorry, I couldn't make a new link right now.".to_owned();
+        }
+        let salt = match feed_salt(memory.as_ref(), &user_id).await {
+            Ok(salt) => salt,
+            Err(error) => {
+                error!(?error, %user_id, "failed to load calendar link");
+                return "Sorry, I couldn't look up your link right now.".to_owned();
+            }
+        };
+        match feeds.feed_url(&user_id, &salt) {
+            Some(url) => format!(
+                "Subscribe to this link in your calendar app to see the birthdays and anniversaries I know about:\n<{url}>\nKeep it to yourself; anyone with the link can read your calendar."
+            ),
+            None => "Calendar subscriptions are on, but the server's public address isn't set, so I can't give you a link. Ask the admin for it.".to_owned(),
+        }
+    }
+
+    /// `/stats`: the user's own numbers with the companion.
+    async fn stats_command(&self, command: &CommandInteraction) -> String {
+        let user_id = command.user.id.to_string();
+        match self.orchestrator.memory().user_stats(&user_id).await {
+            Ok(stats) => format!("Your numbers with me:\n{}", stats.describe()),
+            Err(error) => {
+                error!(?error, %user_id, "failed to load user stats");
+                "Sorry, I couldn't count that right now.".to_owned()
+            }
+        }
+    }
+
+    /// `/notifications`: shows or changes the user's preferences for
+    /// proactive messages.
+    async fn notifications_command(&self, command: &CommandInteraction) -> String {
+        let Some(notifications) = self.orchestrator.notifications() else {
+            return "Notification settings aren't available.".to_owned();
+        };
+        let user_id = command.user.id.to_string();
+        let mut prefs = match notifications.get(&user_id).await {
+            Ok(prefs) => prefs,
+            Err(error) => {
+                error!(?error, %user_id, "failed to load notification preferences");
+                return "Sorry, I couldn't load your settings.".to_owned();
+            }
+        };
+        if command.data.options.is_empty() {
+            return format!("Your notification settings:\n{}", prefs.describe());
+        }
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|option| option.name == name)
+                .map(|option| &option.value)
+        };
+        if let Some(enabled) = option("enabled").and_then(CommandDataOptionValue::as_bool) {
+            prefs.enabled = enabled;
+        }
+        for (name, kind) in [
+            ("greetings", NotificationKind::DateGreetings),
+            ("memory_reviews", NotificationKind::MemoryReviews),
+        ] {
+            if let Some(on) = option(name).and_then(CommandDataOptionValue::as_bool) {
+                prefs.set_muted(kind, !on);
+            }
+        }
+        if let Some(channel) = option("delivery")
+            .and_then(CommandDataOptionValue::as_str)
+            .and_then(NotificationChannel::parse)
+        {
+            prefs.channel = channel;
+        }
+        let hour = |name: &str| {
+            option(name)
+                .and_then(CommandDataOptionValue::as_i64)
+                .map(|hour| hour.clamp(0, 23) as u32)
+        };
+        match (hour("quiet_start"), hour("quiet_end")) {
+            (None, None) => {}
+            (Some(start), Some(end)) if start == end => prefs.quiet_hours = None,
+            (Some(start_hour), Some(end_hour)) => {
+                prefs.quiet_hours = Some(QuietHours {
+                    start_hour,
+                    end_hour,
+                });
+            }
+            _ => {
+                return "Set both `quiet_start` and `quiet_end`; the same hour for both turns quiet hours off.".to_owned();
+            }
+        }
+        if let Some(cap) = option("max_per_day").and_then(CommandDataOptionValue::as_i64) {
+            prefs.max_per_day = (cap > 0).then(|| cap.min(i64::from(u32::MAX)) as u32);
+        }
+        if let Err(error) = prefs.validate() {
+            return format!("Invalid settings: {error}.");
+        }
+        match notifications.put(prefs).await {
+            Ok(prefs) => format!("Saved. Your notification settings:\n{}", prefs.describe()),
+            Err(error) => {
+                error!(?error, %user_id, "failed to store notification preferences");
+                "Sorry, I couldn't save that.".to_owned()
+            }
+        }
+    }
+
+    /// `/introduce`: starts the onboarding interview in the user's DMs.
+    async fn introduce_command(&self, ctx: &Context, command: &CommandInteraction) {
+        let user_id = command.user.id.to_string();
+        let started = match self.orchestrator.onboarding() {
+            Some(onboarding) => onboarding.start(&user_id).await,
+            None => Err(anyhow::anyhow!("onboarding is not supported")),
+        };
+        let message = match started {
+            Ok(first) if command.guild_id.is_none() => {
+                CreateInteractionResponseMessage::new().content(first)
+            }
+            Ok(first) => {
+                let sent = command
+                    .user
+                    .direct_message(&ctx.http, CreateMessage::new().content(first))
+                    .await;
+                let content = match sent {
+                    Ok(_) => "I sent you a DM with a few questions.",
+                    Err(error) => {
+                        warn!(?error, %user_id, "failed to DM onboarding interview");
+                        "I couldn't DM you. Allow DMs from server members, or run `/introduce` in a DM with me."
+                    }
+                };
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true)
+            }
+            Err(error) => {
+                error!(?error, %user_id, "failed to start onboarding");
+                CreateInteractionResponseMessage::new()
+                    .content("Sorry, I couldn't start that right now.")
+                    .ephemeral(true)
+            }
+        };
+        let response = CreateInteractionResponse::Message(message);
+        if let Err(error) = command.create_response(&ctx.http, response).await {
+            error!(?error, "failed to answer /introduce");
+        }
+    }
+
+    /// The first-DM offer's buttons: start the interview or dismiss it.
+    async fn handle_onboarding_component(&self, ctx: &Context, component: &ComponentInteraction) {
+        let user_id = component.user.id.to_string();
+        let start = component.data.custom_id == format!("{ONBOARDING_ID_PREFIX}start");
+        let content = match self.orchestrator.onboarding() {
+            Some(onboarding) if start => onboarding.start(&user_id).await.unwrap_or_else(|error| {
+                error!(?error, %user_id, "failed to start onboarding");
+                "Sorry, I couldn't start that right now. Try `/introduce`.".to_owned()
+            }),
+            _ => "No problem. Run `/introduce` whenever you like.".to_owned(),
+        };
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .components(Vec::new()),
+        );
+        if let Err(error) = component.create_response(&ctx.http, response).await {
+            error!(?error, "failed to answer onboarding component");
+        }
+    }
+
+    /// Answers the DM if the user is in the onboarding interview; returns
+    /// whether it was handled.
+    async fn onboarding_answer(&self, ctx: &Context, msg: &Message) -> bool {
+        let Some(onboarding) = self.orchestrator.onboarding() else {
+            return false;
+        };
+        let user_id = msg.author.id.to_string();
+        let reply = match onboarding.answer(&user_id, &msg.content).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return false,
+            Err(error) => {
+                error!(?error, %user_id, "failed to record onboarding answer");
+                "Sorry, I couldn't save that. Try again, or `stop` to stop.".to_owned()
+            }
+        };
+        if let Err(error) = msg.channel_id.say(&ctx.http, reply).await {
+            warn!(?error, %user_id, "failed to send onboarding reply");
+        }
+        true
+    }
+
+    /// Offers the onboarding interview after the user's first DM.
+    async fn offer_onboarding(&self, ctx: &Context, msg: &Message) {
+        let Some(onboarding) = self.orchestrator.onboarding() else {
+            return;
+        };
+        let user_id = msg.author.id.to_string();
+        match onboarding.offer(&user_id).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(error) => {
+                warn!(?error, %user_id, "failed to check onboarding offer");
+                return;
+            }
+        }
+        let offer = CreateMessage::new()
+            .content("Want to answer four quick questions so I remember the basics about you? You can also run `/introduce` later.")
+            .components(vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(format!("{ONBOARDING_ID_PREFIX}start"))
+                    .label("Sure")
+                    .style(ButtonStyle::Primary),
+                CreateButton::new(format!("{ONBOARDING_ID_PREFIX}dismiss"))
+                    .label("Not now")
+                    .style(ButtonStyle::Secondary),
+            ])]);
+        if let Err(error) = msg.channel_id.send_message(&ctx.http, offer).await {
+            warn!(?error, %user_id, "failed to offer onboarding");
+        }
+    }
+
+    /// `/newchat`: starts a fresh session so earlier turns stop shaping
+    /// session-scoped context.
+    async fn newchat_command(&self, command: &CommandInteraction) -> String {
+        let user_id = command.user.id.to_string();
+        match self
+            .orchestrator
+            .memory()
+            .start_chat_session(&user_id, Utc::now())
+            .await
+        {
+            Ok(_) => "Started a new chat. I still remember what you told me before.".to_owned(),
+            Err(error) => {
+                error!(?error, %user_id, "failed to start chat session");
+                "Sorry, I couldn't start a new chat.".to_owned()
+            }
+        }
+    }
+
+    /// A pin reaction on a user's own message or on a companion reply pins
+    /// the stored message for the reacting user; removing it unpins. 👍/👎 on
+    /// a companion reply store the user's feedback on it.
+    async fn stored_message_reaction(&self, ctx: &Context, reaction: &Reaction, added: bool) {
+        let ReactionType::Unicode(emoji) = &reaction.emoji else {
+            return;
+        };
+        let feedback = feedback_score(emoji);
+        if emoji != PIN_EMOJI && feedback.is_none() {
+            return;
+        }
+        let Some(reactor) = reaction.user_id else {
+            return;
+        };
+        let bot_id = ctx.cache.current_user().id;
+        if reactor == bot_id {
+            return;
+        }
+        let message = match reaction.message(&ctx.http).await {
+            Ok(message) => message,
+            Err(error) => {
+                warn!(?error, message_id = %reaction.message_id, "failed to fetch reacted message");
+                return;
+            }
+        };
+        let role = if message.author.id == bot_id {
+            ChatRole::Assistant
+        } else if message.author.id == reactor && feedback.is_none() {
+            ChatRole::User
+        } else {
+            return;
+        };
+
+        let user_id = reactor.to_string();
+        let memory = self.orchestrator.memory();
+        let history = match memory.list_chat_messages(&user_id, PIN_SEARCH_LIMIT).await {
+            Ok(history) => history,
+            Err(error) => {
+                warn!(?error, %user_id, "failed to load chat history for pin");
+                return;
+            }
+        };
+        let Some(target) = find_pin_target(&history, role, &message.id.to_string()) else {
+            info!(%user_id, message_id = %reaction.message_id, "no stored message for reaction");
+            return;
+        };
+        if let Some(score) = feedback {
+            let feedback = MessageFeedbackRecord {
+                user_id: user_id.clone(),
+                message_id: target.id.clone(),
+                score: if added { score } else { 0 },
+                updated_at: Utc::now(),
+            };
+            match memory.set_message_feedback(feedback).await {
+                Ok(()) => {
+                    info!(%user_id, stored_message_id = %target.id, score, added, "message feedback updated")
+                }
+                Err(error) => warn!(?error, %user_id, "failed to store message feedback"),
+            }
+            return;
+        }
+        let pinned = added;
+        match memory
+            .set_chat_message_pinned(&user_id, &target.id, pinned)
+            .await
+        {
+            Ok(_) => info!(%user_id, stored_message_id = %target.id, pinned, "message pin updated"),
+            Err(error) => warn!(?error, %user_id, "failed to update message pin"),
+        }
+    }
+
+    /// `/voice ask`: push-to-talk question answered in voice and text.
+    async fn voice_command(&self, command: &CommandInteraction) -> String {
+        let Some(voice) = &self.voice else {
+            return "Voice is not enabled for this bot.".to_owned();
+        };
+        let Some(guild_id) = command.guild_id else {
+            return "`/voice ask` only works in a server.".to_owned();
+        };
+        let Some(CommandDataOptionValue::SubCommand(options)) = command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "ask")
+            .map(|option| &option.value)
+        else {
+            return "Unknown `/voice` subcommand.".to_owned();
+        };
+        let question = options
+            .iter()
+            .find(|option| option.name == "question")
+            .and_then(|option| option.value.as_str());
+        let seconds = options
+            .iter()
+            .find(|option| option.name == "seconds")
+            .and_then(|option| option.value.as_i64())
+            .map_or(DEFAULT_VOICE_ASK_SECS, |seconds| {
+                seconds.clamp(MIN_VOICE_ASK_SECS, MAX_VOICE_ASK_SECS) as u64
+            });
+
+        let answer = voice
+            .ask_for_requester(
+                &guild_id.to_string(),
+                &command.user.id.to_string(),
+                question,
+                Duration::from_secs(seconds),
+            )
+            .await;
+        match answer {
+            Ok(answer) => quoted_reply(&answer.transcript, &answer.reply),
+            Err(error) if is_busy(&error) => BUSY_REPLY_TEXT.to_owned(),
+            Err(error) if error.downcast_ref::<RateLimited>().is_some() => {
+                RATE_LIMITED_REPLY_TEXT.to_owned()
+            }
+            Err(error) => {
+                warn!(?error, user_id = %command.user.id, "voice ask failed");
+                format!("Sorry, I couldn't answer that in voice: {error}")
+            }
+        }
+    }
+
+    /// Whether this instance handles a gateway event. Replicas connected to
+    /// the same shard all receive it; only the first to claim its id answers.
+    async fn claim_event(&self, key: String) -> bool {
+        match self.orchestrator.coordinator() {
+            Some(coordinator) => {
+                claim_once(
+                    coordinator.as_ref(),
+                    &format!("discord:{key}"),
+                    EVENT_DEDUPE_TTL,
+                )
+                .await
+            }
+            None => true,
+        }
+    }
+
+    async fn guild_settings(&self, guild_id: &str) -> anyhow::Result<GuildSettings> {
+        self.orchestrator.guild_settings().get(guild_id).await
+    }
+
+    /// Runs a reply through the Discord post-processing pipeline.
+    fn finish_reply(
+        &self,
+        citation_style: CitationStyle,
+        guild_id: &str,
+        reply: &OrchestratorReply,
+    ) -> String {
+        let guild_emoji = self
+            .orchestrator
+            .guild_emoji()
+            .map(|catalog| catalog.get(guild_id))
+            .unwrap_or_default();
+        self.orchestrator.reply_pipelines().discord.apply(
+            &reply.text,
+            &ReplyFilterContext {
+                citations: &reply.citations,
+                citation_style,
+                guild_emoji: &guild_emoji,
+            },
+        )
+    }
+
+    /// Records the custom emoji everyone in the guild can use. Emoji limited
+    /// to roles are left out, since the bot may not be able to post them.
+    fn set_guild_emoji<'a>(&self, guild_id: GuildId, emoji: impl Iterator<Item = &'a Emoji>) {
+        let Some(catalog) = self.orchestrator.guild_emoji() else {
+            return;
+        };
+        let emoji = emoji
+            .filter(|emoji| emoji.available && emoji.roles.is_empty())
+            .map(|emoji| GuildEmoji {
+                id: emoji.id.get(),
+                name: emoji.name.clone(),
+                animated: emoji.animated,
+            })
+            .collect::<Vec<_>>();
+        info!(%guild_id, emoji = emoji.len(), "loaded guild emoji");
+        catalog.set(&guild_id.to_string(), emoji);
+    }
+
+    /// `/companion setup`: replies with an ephemeral panel whose components
+    /// update the guild settings as the admin changes them.
+    async fn companion_command(&self, ctx: &Context, command: &CommandInteraction) {
+        let message = match command.guild_id {
+            Some(guild_id) => match self.guild_settings(&guild_id.to_string()).await {
+                Ok(settings) => setup_panel(&settings, &self.personas.list()),
+                Err(error) => {
+                    error!(?error, %guild_id, "failed to load guild settings");
+                    CreateInteractionResponseMessage::new()
+                        .content("Sorry, I couldn't load this server's settings.")
+                }
+            },
+            None => CreateInteractionResponseMessage::new()
+                .content("`/companion setup` only works in a server."),
+        };
+
+        let response = CreateInteractionResponse::Message(message.ephemeral(true));
+        if let Err(error) = command.create_response(&ctx.http, response).await {
+            error!(?error, "failed to answer /companion");
+        }
+    }
+
+    /// `/whatdoyouknow`: the user's stored memory, read from the store and
+    /// shown only to them.
+    async fn whatdoyouknow_command(&self, ctx: &Context, command: &CommandInteraction) {
+        let user_id = command.user.id.to_string();
+        let guild_id = command
+            .guild_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| DM_GUILD_ID.to_owned());
+        let content = match load_memory_summary(
+            self.orchestrator.memory().as_ref(),
+            &user_id,
+            &guild_id,
+            &command.channel_id.to_string(),
+        )
+        .await
+        {
+            Ok(summary) => MaxLength(DISCORD_MESSAGE_LIMIT)
+                .apply(summary.render(), &ReplyFilterContext::default()),
+            Err(error) => {
+                error!(?error, %user_id, "failed to load memory summary");
+                "Sorry, I couldn't look up what I remember right now.".to_owned()
+            }
+        };
+
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .ephemeral(true),
+        );
+        if let Err(error) = command.create_response(&ctx.http, response).await {
+            error!(?error, "failed to answer /whatdoyouknow");
+        }
+    }
+
+    /// `/apikey set|status|remove`: the user's own model key, used for their
+    /// turns instead of the server's. Keys are only accepted in DMs.
+    async fn apikey_command(&self, command: &CommandInteraction) -> String {
+        let Some(user_keys) = self.orchestrator.user_keys() else {
+            return "This bot does not accept personal API keys.".to_owned();
+        };
+        let Some(subcommand) = command.data.options.first() else {
+            return "Unknown `/apikey` subcommand.".to_owned();
+        };
+        let user_id = command.user.id.to_string();
+        let memory = self.orchestrator.memory();
+        match subcommand.name.as_str() {
+            "set" => {
+                if command.guild_id.is_some() {
+                    return "For your safety, send `/apikey set` in a DM with me.".to_owned();
+                }
+                let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+                    return "Unknown `/apikey` subcommand.".to_owned();
+                };
+                let option = |name: &str| {
+                    options
+                        .iter()
+                        .find(|option| option.name == name)
+                        .and_then(|option| option.value.as_str())
+                };
+                let Some(provider) = option("provider").and_then(KeyProvider::parse) else {
+                    return "Choose openrouter or openai as the provider.".to_owned();
+                };
+                let key = option("key").unwrap_or_default();
+                match user_keys
+                    .set(memory.as_ref(), &user_id, provider, key)
+                    .await
+                {
+                    Ok(record) => format!(
+                        "Saved your {} key ({}). Your messages now use it.",
+                        provider.as_str(),
+                        record.key_hint
+                    ),
+                    Err(error) => format!("I couldn't save that key: {error}"),
+                }
+            }
+            "status" => match memory.get_user_api_key(&user_id).await {
+                Ok(Some(record)) => format!(
+                    "Your messages use your {} key ({}), set <t:{}:R>.",
+                    record.provider.as_str(),
+                    record.key_hint,
+                    record.updated_at.timestamp()
+                ),
+                Ok(None) => "You have no key set; your messages use the server's.".to_owned(),
+                Err(error) => {
+                    error!(?error, %user_id, "failed to load user API key");
+                    "Sorry, I couldn't check your key right now.".to_owned()
+                }
+            },
+            "remove" => match memory.delete_user_api_key(&user_id).await {
+                Ok(true) => "Removed your key; your messages use the server's again.".to_owned(),
+                Ok(false) => "You have no key set.".to_owned(),
+                Err(error) => {
+                    error!(?error, %user_id, "failed to remove user API key");
+                    "Sorry, I couldn't remove your key right now.".to_owned()
+                }
+            },
+            _ => "Unknown `/apikey` subcommand.".to_owned(),
+        }
+    }
+
+    async fn handle_setup_component(&self, ctx: &Context, component: &ComponentInteraction) {
+        let Some(action) = component.data.custom_id.strip_prefix(SETUP_ID_PREFIX) else {
+            return;
+        };
+        let can_manage = component
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.manage_guild());
+
+        let mut greeting = None;
+        let message = match component.guild_id {
+            Some(guild_id) if can_manage => {
+                let guild_id = guild_id.to_string();
+                match self
+                    .update_guild_settings(&guild_id, action, &component.data.kind)
+                    .await
+                {
+                    Ok(_) if action == "done" => CreateInteractionResponseMessage::new()
+                        .content("Setup saved. Run `/companion setup` again to change it.")
+                        .components(Vec::new()),
+                    Ok((settings, previous_persona)) => {
+                        let personas = self.personas.list();
+                        greeting = activation_greeting(
+                            previous_persona.as_deref(),
+                            settings.persona.as_deref(),
+                            &personas,
+                        );
+                        setup_panel(&settings, &personas)
+                    }
+                    Err(error) => {
+                        error!(?error, %guild_id, "failed to save guild settings");
+                        CreateInteractionResponseMessage::new()
+                            .content("Sorry, I couldn't save that change.")
+                    }
+                }
+            }
+            _ => CreateInteractionResponseMessage::new()
+                .content("You need the Manage Server permission to change setup.")
+                .components(Vec::new()),
+        };
+
+        let response = CreateInteractionResponse::UpdateMessage(message);
+        if let Err(error) = component.create_response(&ctx.http, response).await {
+            error!(?error, "failed to answer setup component");
+        }
+        if let Some(greeting) = greeting
+            && let Err(error) = component.channel_id.say(&ctx.http, greeting).await
+        {
+            warn!(?error, "failed to post persona greeting");
+        }
+    }
+
+    async fn handle_review_component(&self, ctx: &Context, component: &ComponentInteraction) {
+        let Some((verdict, key)) = parse_review_button_id(&component.data.custom_id) else {
+            return;
+        };
+        let user_id = component.user.id.to_string();
+        let label = key.replace('_', " ");
+        let content = match apply_review_verdict(
+            self.orchestrator.memory().as_ref(),
+            &user_id,
+            key,
+            verdict,
+            Utc::now(),
+        )
+        .await
+        {
+            Ok(Some(fact)) => {
+                info!(%user_id, memory_key = key, ?verdict, "memory review answered");
+                match verdict {
+                    ReviewVerdict::Confirm => {
+                        format!(
+                            "Got it, I'll keep remembering your {label}: {}.",
+                            fact.value
+                        )
+                    }
+                    ReviewVerdict::Deny => format!("Okay, I've forgotten your {label}."),
+                }
+            }
+            Ok(None) => format!("I don't remember anything about your {label} anymore."),
+            Err(error) => {
+                error!(?error, %user_id, "failed to apply memory review answer");
+                "Sorry, I couldn't update that memory.".to_owned()
+            }
+        };
+
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new().content(content),
+        );
+        if let Err(error) = component.create_response(&ctx.http, response).await {
+            error!(?error, "failed to answer memory review component");
+        }
+    }
+
+    /// Applies a setup action and returns the new settings with the persona
+    /// that was active before.
+    async fn update_guild_settings(
+        &self,
+        guild_id: &str,
+        action: &str,
+        kind: &ComponentInteractionDataKind,
+    ) -> anyhow::Result<(GuildSettings, Option<String>)> {
+        let mut settings = self.guild_settings(guild_id).await?;
+        let previous_persona = settings.persona.clone();
+        if apply_setup_action(&mut settings, action, kind) {
+            settings = self.orchestrator.guild_settings().put(settings).await?;
+            info!(guild_id, action, "guild settings updated");
+        }
+        Ok((settings, previous_persona))
+    }
+
+    /// When the quiet hours a reply to `user_id` in this guild falls in end:
+    /// the guild's or the user's own, whichever ends later.
+    async fn quiet_until(&self, user_id: &str, settings: &GuildSettings) -> Option<DateTime<Utc>> {
+        let now = Utc::now();
+        let Some(notifications) = self.orchestrator.notifications() else {
+            return settings.quiet_until(now);
+        };
+        match notifications
+            .quiet_until(user_id, Some(settings), now)
+            .await
+        {
+            Ok(until) => until,
+            Err(error) => {
+                warn!(?error, user_id, "failed to check the user's quiet hours");
+                settings.quiet_until(now)
+            }
+        }
+    }
+
+    /// Runs one turn and posts the reply; `replayed` replies reference the
+    /// original message, which may be hours old.
+    async fn reply_to_message(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        settings: &GuildSettings,
+        request: MessageCtx,
+        replayed: bool,
+    ) {
+        let options = TurnOptions {
+            track_in_flight: true,
+            ..TurnOptions::default()
+        };
+        match self
+            .orchestrator
+            .handle_message_with_options(request, options)
+            .await
+        {
+            Ok(reply) => {
+                if reply.timings.total_ms >= 30_000 {
+                    warn!(
+                        user_id = %msg.author.id,
+                        channel_id = %msg.channel_id,
+                        message_id = %msg.id,
+                        total_ms = reply.timings.total_ms,
+                        planner_ms = reply.timings.planner_ms,
+                        tool_execution_ms = reply.timings.tool_execution_ms,
+                        final_model_ms = reply.timings.final_model_ms,
+                        "slow Discord reply detected"
+                    );
+                } else {
+                    info!(
+                        user_id = %msg.author.id,
+                        channel_id = %msg.channel_id,
+                        message_id = %msg.id,
+                        total_ms = reply.timings.total_ms,
+                        planner_ms = reply.timings.planner_ms,
+                        tool_execution_ms = reply.timings.tool_execution_ms,
+                        final_model_ms = reply.timings.final_model_ms,
+                        "Discord reply ready"
+                    );
+                }
+                let suppress_text_reply = reply
+                    .tool_calls
+                    .iter()
+                    .any(|call| call.tool_name == "discord_voice_listen_turn");
+
+                if suppress_text_reply || reply.text.trim().is_empty() {
+                    return;
+                }
+
+                let text = self.finish_reply(settings.citation_style, &settings.guild_id, &reply);
+                let reply_record = |text: String| ChatMessageRecord {
+                    id: format!("{}-assistant", msg.id),
+                    user_id: msg.author.id.to_string(),
+                    guild_id: settings.guild_id.clone(),
+                    channel_id: msg.channel_id.to_string(),
+                    role: ChatRole::Assistant,
+                    content: text,
+                    timestamp: Utc::now(),
+                    modality: Modality::Text,
+                    session_id: None,
+                    pinned: false,
+                    source_message_id: Some(msg.id.to_string()),
+                };
+                let bot_id = ctx.cache.current_user().id;
+                let quiet_until = if msg.guild_id.is_some() && msg.mentions_user_id(bot_id) {
+                    self.quiet_until(&msg.author.id.to_string(), settings).await
+                } else {
+                    None
+                };
+                let text = match quiet_mention_reply(settings.quiet_mentions, text, quiet_until) {
+                    QuietReply::Send(text) => text,
+                    QuietReply::Hold(until, text) => {
+                        match defer_outbox_message(
+                            self.orchestrator.memory().as_ref(),
+                            reply_record(text.clone()),
+                            &msg.id.to_string(),
+                            until,
+                        )
+                        .await
+                        {
+                            Ok(()) => return,
+                            Err(error) => {
+                                error!(?error, message_id = %msg.id, "failed to hold reply; sending it now");
+                                text
+                            }
+                        }
+                    }
+                };
+                let mut message = CreateMessage::new().content(text.clone());
+                if replayed {
+                    message = message.reference_message(msg);
+                }
+                match msg.channel_id.send_message(&ctx.http, message).await {
+                    // Reactions on the reply find it by the id it was sent as.
+                    Ok(sent) => {
+                        if let Err(error) = self
+                            .orchestrator
+                            .memory()
+                            .set_reply_source_message(
+                                &msg.author.id.to_string(),
+                                &msg.id.to_string(),
+                                &sent.id.to_string(),
+                            )
+                            .await
+                        {
+                            warn!(?error, message_id = %msg.id, "failed to link delivered reply");
+                        }
+                    }
+                    Err(error) => {
+                        error!(?error, "failed to send Discord message");
+                        if let Err(error) = queue_outbox_message(
+                            self.orchestrator.memory().as_ref(),
+                            OutboxKind::Deliver,
+                            reply_record(self.orchestrator.redact(text)),
+                            &msg.id.to_string(),
+                            &error.into(),
+                        )
+                        .await
+                        {
+                            error!(?error, message_id = %msg.id, "failed to queue undelivered reply");
+                        }
+                    }
+                }
+            }
+            Err(error) if is_busy(&error) => {
+                if let Err(error) = msg.channel_id.say(&ctx.http, BUSY_REPLY_TEXT).await {
+                    error!(?error, "failed to send Discord busy message");
+                }
+            }
+            Err(error) if error.downcast_ref::<RateLimited>().is_some() => {
+                if let Err(error) = msg.channel_id.say(&ctx.http, RATE_LIMITED_REPLY_TEXT).await {
+                    error!(?error, "failed to send Discord rate limit message");
+                }
+            }
+            Err(error) => {
+                error!(?error, "failed to process Discord message");
+                let text = failure_reply_text(&error);
+                if let Err(error) = msg.channel_id.say(&ctx.http, text).await {
+                    error!(?error, "failed to send Discord error message");
+                }
+            }
+        }
+    }
+
+    /// Answers mentions posted in the last [`MAX_REPLAY_AGE`] since
+    /// `last_seen`, oldest first, in guilds that enabled replay.
+    async fn replay_missed_mentions(&self, ctx: &Context, ready: &Ready, last_seen: DateTime<Utc>) {
+        let since = last_seen.max(Utc::now() - MAX_REPLAY_AGE);
+        let after = MessageId::new(snowflake_at(since));
+        for guild in &ready.guilds {
+            let guild_id = guild.id.to_string();
+            let settings = match self.guild_settings(&guild_id).await {
+                Ok(settings) if settings.replay_missed_mentions => settings,
+                Ok(_) => continue,
+                Err(error) => {
+                    warn!(?error, %guild_id, "failed to load guild settings for replay");
+                    continue;
+                }
+            };
+            let channel_ids = if settings.activation.channel_ids.is_empty() {
+                match guild.id.channels(&ctx.http).await {
+                    Ok(channels) => channels
+                        .into_values()
+                        .filter(|channel| channel.kind == ChannelType::Text)
+                        .map(|channel| channel.id)
+                        .collect(),
+                    Err(error) => {
+                        warn!(?error, %guild_id, "failed to list channels for replay");
+                        continue;
+                    }
+                }
+            } else {
+                settings
+                    .activation
+                    .channel_ids
+                    .iter()
+                    .filter_map(|id| id.parse::<u64>().ok())
+                    .map(ChannelId::new)
+                    .collect::<Vec<_>>()
+            };
+
+            for channel_id in channel_ids {
+                let mut missed = match channel_id
+                    .messages(
+                        &ctx.http,
+                        GetMessages::new()
+                            .after(after)
+                            .limit(MAX_REPLAY_PER_CHANNEL),
+                    )
+                    .await
+                {
+                    Ok(messages) => messages,
+                    Err(error) => {
+                        warn!(?error, %channel_id, "failed to fetch missed messages");
+                        continue;
+                    }
+                };
+                missed.retain(|msg| !msg.author.bot && msg.mentions_user_id(ready.user.id));
+                missed.sort_by_key(|msg| msg.id);
+                if !missed.is_empty() {
+                    info!(%guild_id, %channel_id, count = missed.len(), "replaying missed mentions");
+                }
+                for msg in missed {
+                    // `last_seen` lags the gateway, so the window can
+                    // include mentions answered before the restart.
+                    match self
+                        .orchestrator
+                        .memory()
+                        .has_recorded_turn(&msg.author.id.to_string(), &msg.id.to_string())
+                        .await
+                    {
+                        Ok(false) => {}
+                        Ok(true) => continue,
+                        Err(error) => {
+                            warn!(?error, message_id = %msg.id, "failed to check replayed mention");
+                            continue;
+                        }
+                    }
+                    if !self.claim_event(format!("message:{}", msg.id)).await {
+                        continue;
+                    }
+                    let attachments = message_attachments(&msg);
+                    let request = MessageCtx {
+                        message_id: msg.id.to_string(),
+                        user_id: msg.author.id.to_string(),
+                        guild_id: guild_id.clone(),
+                        channel_id: channel_id.to_string(),
+                        content: content_with_attachments(&msg.content, &attachments),
+                        timestamp: DateTime::from_timestamp(msg.timestamp.unix_timestamp(), 0)
+                            .unwrap_or_else(Utc::now),
+                        attachments,
+                    };
+                    self.reply_to_message(ctx, &msg, &settings, request, true)
+                        .await;
+                }
+            }
+        }
+    }
+}
+
+/// Applies one setup component interaction; returns whether anything changed.
+fn apply_setup_action(
+    settings: &mut GuildSettings,
+    action: &str,
+    kind: &ComponentInteractionDataKind,
+) -> bool {
+    match (action, kind) {
+        ("channels", ComponentInteractionDataKind::ChannelSelect { values }) => {
+            settings.activation.channel_ids = values.iter().map(|id| id.to_string()).collect();
+        }
+        ("persona", ComponentInteractionDataKind::StringSelect { values }) => {
+            settings.persona = values
+                .first()
+                .filter(|value| value.as_str() != NO_PERSONA_VALUE)
+                .cloned();
+        }
+        ("tools", ComponentInteractionDataKind::StringSelect { values }) => {
+            let all_tools = builtin_tool_specs()
+                .iter()
+                .map(|spec| spec.tool_name)
+                .collect::<Vec<_>>();
+            settings.enabled_tools = selected_tools(&all_tools, values, &settings.enabled_tools);
+        }
+        ("memory", ComponentInteractionDataKind::Button) => {
+            settings.memory_consent_default = !settings.memory_consent_default;
+        }
+        ("mention", ComponentInteractionDataKind::Button) => {
+            settings.activation.require_mention = !settings.activation.require_mention;
+        }
+        ("replay", ComponentInteractionDataKind::Button) => {
+            settings.replay_missed_mentions = !settings.replay_missed_mentions;
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// The greeting of a persona that setup just switched to, if it has one.
+fn activation_greeting(
+    previous: Option<&str>,
+    current: Option<&str>,
+    personas: &[PersonaBundle],
+) -> Option<String> {
+    let current = current.filter(|current| Some(*current) != previous)?;
+    personas
+        .iter()
+        .find(|bundle| bundle.name == current)?
+        .greeting
+        .as_deref()
+        .map(str::trim)
+        .filter(|greeting| !greeting.is_empty())
+        .map(str::to_owned)
+}
+
+/// The enabled tools after a setup tool selection. Tools past the menu's
+/// limit are not offered, so they keep their current state.
+fn selected_tools(
+    all_tools: &[&str],
+    values: &[String],
+    current: &Option<Vec<String>>,
+) -> Option<Vec<String>> {
+    let enabled = all_tools
+        .iter()
+        .enumerate()
+        .filter(|(index, tool)| {
+            if *index < SELECT_MENU_LIMIT {
+                values.iter().any(|value| value == *tool)
+            } else {
+                current
+                    .as_ref()
+                    .is_none_or(|tools| tools.iter().any(|enabled| enabled == *tool))
+            }
+        })
+        .map(|(_, tool)| (*tool).to_owned())
+        .collect::<Vec<_>>();
+    (enabled.len() < all_tools.len()).then_some(enabled)
+}
+
+/// The personas offered by the setup menu next to "Default": the first ones
+/// that fit, always including the guild's current persona.
+fn setup_personas<'a>(
+    personas: &'a [PersonaBundle],
+    selected: Option<&str>,
+) -> Vec<&'a PersonaBundle> {
+    let room = SELECT_MENU_LIMIT - 1;
+    let mut offered = personas.iter().take(room).collect::<Vec<_>>();
+    if let Some(current) = personas
+        .iter()
+        .skip(room)
+        .find(|bundle| Some(bundle.name.as_str()) == selected)
+    {
+        offered.pop();
+        offered.push(current);
+    }
+    offered
+}
+
+fn setup_panel(
+    settings: &GuildSettings,
+    personas: &[PersonaBundle],
+) -> CreateInteractionResponseMessage {
+    let channels = if settings.activation.channel_ids.is_empty() {
+        "all channels".to_owned()
+    } else {
+        settings
+            .activation
+            .channel_ids
+            .iter()
+            .map(|id| format!("<#{id}>"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let tools = match &settings.enabled_tools {
+        None => "all".to_owned(),
+        Some(tools) if tools.is_empty() => "none".to_owned(),
+        Some(tools) => tools.join(", "),
+    };
+    let content = format!(
+        "**CompanionPilot setup**\nActive in: {channels}{}\nPersona: {}\nRemember facts by default: {}\nTools: {tools}\nAnswer mentions missed while offline: {}",
+        if settings.activation.require_mention {
+            " (mentions only)"
+        } else {
+            ""
+        },
+        settings.persona.as_deref().unwrap_or("default"),
+        if settings.memory_consent_default {
+            "yes"
+        } else {
+            "no"
+        },
+        if settings.replay_missed_mentions {
+            "yes"
+        } else {
+            "no"
+        },
+    );
+
+    let channel_select = CreateSelectMenu::new(
+        format!("{SETUP_ID_PREFIX}channels"),
+        CreateSelectMenuKind::Channel {
+            channel_types: Some(vec![ChannelType::Text]),
+            default_channels: Some(
+                settings
+                    .activation
+                    .channel_ids
+                    .iter()
+                    .filter_map(|id| id.parse::<u64>().ok())
+                    .map(ChannelId::new)
+                    .take(SELECT_MENU_LIMIT)
+                    .collect(),
+            ),
+        },
+    )
+    .placeholder("Channels to answer in (none = all)")
+    .min_values(0)
+    .max_values(SELECT_MENU_LIMIT as u8);
+
+    let mut persona_options = vec![
+        CreateSelectMenuOption::new("Default", NO_PERSONA_VALUE)
+            .default_selection(settings.persona.is_none()),
+    ];
+    persona_options.extend(
+        setup_personas(personas, settings.persona.as_deref())
+            .into_iter()
+            .map(|bundle| {
+                let mut option = CreateSelectMenuOption::new(&bundle.name, &bundle.name)
+                    .default_selection(settings.persona.as_deref() == Some(bundle.name.as_str()));
+                if !bundle.description.is_empty() {
+                    option = option
+                        .description(bundle.description.chars().take(100).collect::<String>());
+                }
+                option
+            }),
+    );
+    let persona_select = CreateSelectMenu::new(
+        format!("{SETUP_ID_PREFIX}persona"),
+        CreateSelectMenuKind::String {
+            options: persona_options,
+        },
+    )
+    .placeholder("Persona");
+
+    let tool_specs = builtin_tool_specs();
+    let tool_options = tool_specs
+        .iter()
+        .take(SELECT_MENU_LIMIT)
+        .map(|spec| {
+            let enabled = settings
+                .enabled_tools
+                .as_ref()
+                .is_none_or(|tools| tools.iter().any(|tool| tool == spec.tool_name));
+            CreateSelectMenuOption::new(spec.tool_name, spec.tool_name).default_selection(enabled)
+        })
+        .collect::<Vec<_>>();
+    let tool_options_len = tool_options.len() as u8;
+    let tool_select = CreateSelectMenu::new(
+        format!("{SETUP_ID_PREFIX}tools"),
+        CreateSelectMenuKind::String {
+            options: tool_options,
+        },
+    )
+    .placeholder("Enabled tools")
+    .min_values(0)
+    .max_values(tool_options_len);
+
+    let buttons = vec![
+        CreateButton::new(format!("{SETUP_ID_PREFIX}memory"))
+            .label(if settings.memory_consent_default {
+                "Memory: on by default"
+            } else {
+                "Memory: off by default"
+            })
+            .style(if settings.memory_consent_default {
+                ButtonStyle::Success
+            } else {
+                ButtonStyle::Secondary
+            }),
+        CreateButton::new(format!("{SETUP_ID_PREFIX}mention"))
+            .label(if settings.activation.require_mention {
+                "Mentions only"
+            } else {
+                "All messages"
+            })
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(format!("{SETUP_ID_PREFIX}replay"))
+            .label(if settings.replay_missed_mentions {
+                "Missed mentions: answered"
+            } else {
+                "Missed mentions: ignored"
+            })
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(format!("{SETUP_ID_PREFIX}done"))
+            .label("Done")
+            .style(ButtonStyle::Primary),
+    ];
+
+    CreateInteractionResponseMessage::new()
+        .content(content)
+        .components(vec![
+            CreateActionRow::SelectMenu(channel_select),
+            CreateActionRow::SelectMenu(persona_select),
+            CreateActionRow::SelectMenu(tool_select),
+            CreateActionRow::Buttons(buttons),
+        ])
+}
+
+fn slash_commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("ask")
+            .description("Ask me anything")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "question", "Your message")
+                    .required(true),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "flags",
+                "Server managers: experimental flags, e.g. speculative=off,model=...",
+            )),
+        CreateCommand::new("retry")
+            .description("Regenerate my last reply to you in this channel")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Number,
+                    "temperature",
+                    "Sampling temperature for the new reply",
+                )
+                .min_number_value(0.0)
+                .max_number_value(2.0),
+            ),
+        CreateCommand::new("research")
+            .description("Research a question in depth and get a cited report")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "question", "What to research")
+                    .required(true),
+            ),
+        CreateCommand::new("apikey")
+            .description("Use your own OpenRouter or OpenAI key for your messages")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "set",
+                    "Save your key (DM only)",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "provider",
+                        "Who issued the key",
+                    )
+                    .add_string_choice("OpenRouter", "openrouter")
+                    .add_string_choice("OpenAI", "openai")
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "key", "The API key")
+                        .required(true),
+                ),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "status",
+                "Show whether your own key is in use",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "remove",
+                "Forget your key and use the server's again",
+            )),
+        CreateCommand::new("newchat").description("Start a new conversation with me"),
+        CreateCommand::new("whatdoyouknow").description("See what I remember about you"),
+        CreateCommand::new("greetings")
+            .description("Turn my birthday and anniversary messages on or off")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "enabled",
+                    "Whether I should greet you",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("notifications")
+            .description("See or change when and how I message you on my own")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "enabled",
+                "Any proactive messages at all",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "greetings",
+                "Birthday and anniversary greetings",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "memory_reviews",
+                "Check-ins about what I remember",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "delivery",
+                    "Where messages go",
+                )
+                .add_string_choice("Where each feature posts", "auto")
+                .add_string_choice("Always DM me", "dm"),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "quiet_start",
+                    "Hour (your time) quiet hours start",
+                )
+                .min_int_value(0)
+                .max_int_value(23),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "quiet_end",
+                    "Hour (your time) quiet hours end",
+                )
+                .min_int_value(0)
+                .max_int_value(23),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "max_per_day",
+                    "Most messages per day; 0 for no limit",
+                )
+                .min_int_value(0)
+                .max_int_value(20),
+            ),
+        CreateCommand::new("stats").description("See your numbers with me"),
+        CreateCommand::new("calendar")
+            .description("Get a link to subscribe to your birthdays and anniversaries")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "new_link",
+                "Make a new link; the old one stops working",
+            )),
+        CreateCommand::new("introduce")
+            .description("Answer a few questions so I remember the basics about you"),
+        CreateCommand::new("companion")
+            .description("Configure CompanionPilot for this server")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .dm_permission(false)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "setup",
+                "Choose channels, persona, memory default and tools",
+            )),
+        CreateCommand::new("voice")
+            .description("Talk to CompanionPilot in your voice channel")
+            .dm_permission(false)
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "ask",
+                    "Record what you say next and answer in voice and text",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "seconds",
+                        "How long to record you",
+                    )
+                    .min_int_value(MIN_VOICE_ASK_SECS as u64)
+                    .max_int_value(MAX_VOICE_ASK_SECS as u64),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "question",
+                    "Type the question instead of speaking it",
+                )),
+            ),
+    ]
+}
+
+/// `text` under a one-line quote of `quote`, both shortened so the message
+/// stays within Discord's limit.
+fn quoted_reply(quote: &str, text: &str) -> String {
+    let ctx = ReplyFilterContext::default();
+    let quote = MaxLength(MAX_QUOTE_CHARS)
+        .apply(quote.split_whitespace().collect::<Vec<_>>().join(" "), &ctx);
+    let quote = format!("> {quote}\n\n");
+    let room = DISCORD_MESSAGE_LIMIT.saturating_sub(quote.chars().count());
+    let text = MaxLength(room).apply(text.to_owned(), &ctx);
+    format!("{quote}{text}")
+}
 
-struct Handler {
-    orchestrator: Arc<DefaultChatOrchestrator>,
-    voice: Option<Arc<VoiceManager>>,
+/// A slash command running a custom command's prompt, with one required
+/// text option per placeholder.
+fn custom_slash_command(command: &CustomCommand) -> CreateCommand {
+    command.options().into_iter().fold(
+        CreateCommand::new(&command.name).description(&command.description),
+        |slash, option| {
+            let description = option.replace('_', " ");
+            slash.add_option(
+                CreateCommandOption::new(CommandOptionType::String, option, description)
+                    .required(true),
+            )
+        },
+    )
+}
+
+/// Registers the built-in slash commands and the custom commands, returning
+/// the custom commands that were included.
+async fn register_commands(http: &Http, orchestrator: &dyn ChatOrchestrator) -> Vec<CustomCommand> {
+    let mut commands = slash_commands();
+    let mut registered = Vec::new();
+    if let Some(custom_commands) = orchestrator.custom_commands() {
+        match custom_commands.list().await {
+            Ok(custom) => {
+                commands.extend(custom.iter().map(custom_slash_command));
+                registered = custom;
+            }
+            Err(error) => warn!(?error, "failed to load custom commands"),
+        }
+    }
+    if let Err(error) = Command::set_global_commands(http, commands).await {
+        error!(?error, "failed to register Discord slash commands");
+    }
+    registered
+}
+
+/// Re-registers the slash commands whenever custom commands change: right
+/// away for changes made through this process, and on the next poll for
+/// changes another replica wrote to the store.
+async fn reregister_custom_commands(http: Arc<Http>, orchestrator: Arc<dyn ChatOrchestrator>) {
+    let Some(custom_commands) = orchestrator.custom_commands() else {
+        return;
+    };
+    let mut registered = custom_commands.list().await.unwrap_or_default();
+    let mut ticker = tokio::time::interval(CUSTOM_COMMAND_POLL_INTERVAL);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = custom_commands.changed() => {}
+            _ = ticker.tick() => match custom_commands.list().await {
+                Ok(current) if current == registered => continue,
+                Ok(_) => {}
+                Err(error) => {
+                    warn!(?error, "failed to poll custom commands");
+                    continue;
+                }
+            },
+        }
+        info!("custom commands changed; re-registering Discord slash commands");
+        registered = register_commands(&http, orchestrator.as_ref()).await;
+    }
 }
 
 #[async_trait]
 impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!(user = %ready.user.name, "Discord gateway ready");
+        register_commands(&ctx.http, self.orchestrator.as_ref()).await;
+
+        if let Some(config) = self.memory_review.lock().await.take() {
+            tokio::spawn(run_memory_reviews(
+                ctx.http.clone(),
+                self.orchestrator.clone(),
+                config,
+            ));
+        }
+        if let Some(config) = self.date_greetings.lock().await.take() {
+            tokio::spawn(run_date_greetings(
+                ctx.http.clone(),
+                self.orchestrator.clone(),
+                config,
+            ));
+        }
+        if let Some(interval) = self.presence_interval.lock().await.take() {
+            tokio::spawn(run_presence_updates(
+                ctx.clone(),
+                self.orchestrator.clone(),
+                interval,
+            ));
+        }
+
+        if let Some(channel_id) = self.admin_channel_id
+            && let Some(mut alerts) = self.admin_alerts.lock().await.take()
+        {
+            let http = ctx.http.clone();
+            tokio::spawn(async move {
+                while let Some(alert) = alerts.recv().await {
+                    if let Err(error) = ChannelId::new(channel_id).say(&http, alert.render()).await
+                    {
+                        warn!(?error, kind = alert.kind, "failed to post admin alert");
+                    }
+                }
+            });
+        }
+
+        if let Some(results) = self.background_results.lock().await.take() {
+            tokio::spawn(deliver_background_results(
+                ctx.http.clone(),
+                self.orchestrator.clone(),
+                results,
+            ));
+        }
+
+        // Reconnects fire `ready` again; only the first one follows downtime.
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        tokio::spawn(reregister_custom_commands(
+            ctx.http.clone(),
+            self.orchestrator.clone(),
+        ));
+        let store = self.orchestrator.memory().clone();
+        let last_seen = store
+            .last_seen_at(HEARTBEAT_ADAPTER)
+            .await
+            .unwrap_or_else(|error| {
+                warn!(?error, "failed to read the last Discord heartbeat");
+                None
+            });
+        tokio::spawn(run_heartbeat(store));
+        tokio::spawn(run_outbox_delivery(
+            ctx.http.clone(),
+            self.orchestrator.clone(),
+        ));
+        if self.in_flight_recovery != InFlightRecovery::Off {
+            tokio::spawn(run_in_flight_recovery(
+                ctx.http.clone(),
+                self.orchestrator.clone(),
+                self.in_flight_recovery,
+            ));
+        }
+        if let Some(last_seen) = last_seen {
+            self.replay_missed_mentions(&ctx, &ready, last_seen).await;
+        }
+    }
+
+    async fn guild_create(&self, _ctx: Context, guild: Guild, _is_new: Option<bool>) {
+        self.set_guild_emoji(guild.id, guild.emojis.values());
+    }
+
+    async fn guild_emojis_update(
+        &self,
+        _ctx: Context,
+        guild_id: GuildId,
+        current_state: HashMap<EmojiId, Emoji>,
+    ) {
+        self.set_guild_emoji(guild_id, current_state.values());
+    }
+
+    async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
+        self.stored_message_reaction(&ctx, &add_reaction, true)
+            .await;
+    }
+
+    async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        self.stored_message_reaction(&ctx, &removed_reaction, false)
+            .await;
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if !self
+            .claim_event(format!("interaction:{}", interaction.id()))
+            .await
+        {
+            return;
+        }
+        match interaction {
+            Interaction::Command(command) => self.handle_command(&ctx, &command).await,
+            Interaction::Component(component)
+                if component.data.custom_id.starts_with(REVIEW_ID_PREFIX) =>
+            {
+                self.handle_review_component(&ctx, &component).await
+            }
+            Interaction::Component(component)
+                if component.data.custom_id.starts_with(ONBOARDING_ID_PREFIX) =>
+            {
+                self.handle_onboarding_component(&ctx, &component).await
+            }
+            Interaction::Component(component) => {
+                self.handle_setup_component(&ctx, &component).await
+            }
+            _ => {}
+        }
+    }
+
     async fn message(&self, ctx: Context, msg: Message) {
-        if msg.author.bot {
+        if msg.author.bot || !self.claim_event(format!("message:{}", msg.id)).await {
+            return;
+        }
+        if msg.guild_id.is_none() && self.onboarding_answer(&ctx, &msg).await {
             return;
         }
 
         let guild_id = msg
             .guild_id
             .map(|id| id.to_string())
-            .unwrap_or_else(|| "dm".to_owned());
+            .unwrap_or_else(|| DM_GUILD_ID.to_owned());
+        let settings = self
+            .guild_settings(&guild_id)
+            .await
+            .unwrap_or_else(|error| {
+                warn!(?error, %guild_id, "failed to load guild settings; using defaults");
+                GuildSettings::new(&guild_id)
+            });
+        if msg.guild_id.is_some() {
+            let mentioned = msg.mentions_user_id(ctx.cache.current_user().id);
+            if !settings
+                .activation
+                .should_respond(&msg.channel_id.to_string(), mentioned)
+            {
+                return;
+            }
+        }
+
+        // Messages with files are answered on their own, so the attachments
+        // match the message they came with.
+        let attachments = message_attachments(&msg);
+        let content = if attachments.is_empty() {
+            let coalesced = self
+                .debouncer
+                .coalesce(
+                    msg.author.id.get(),
+                    msg.channel_id.get(),
+                    msg.content.clone(),
+                )
+                .await;
+            let Some(content) = coalesced else {
+                return;
+            };
+            content
+        } else {
+            content_with_attachments(&msg.content, &attachments)
+        };
 
         let request = MessageCtx {
             message_id: msg.id.to_string(),
             user_id: msg.author.id.to_string(),
             guild_id,
             channel_id: msg.channel_id.to_string(),
-            content: msg.content.clone(),
+            content,
             timestamp: Utc::now(),
+            attachments,
         };
+        self.reply_to_message(&ctx, &msg, &settings, request, false)
+            .await;
+        if msg.guild_id.is_none() {
+            self.offer_onboarding(&ctx, &msg).await;
+        }
+    }
 
-        match self.orchestrator.handle_message(request).await {
-            Ok(reply) => {
-                if reply.timings.total_ms >= 30_000 {
-                    warn!(
-                        user_id = %msg.author.id,
-                        channel_id = %msg.channel_id,
-                        message_id = %msg.id,
-                        total_ms = reply.timings.total_ms,
-                        planner_ms = reply.timings.planner_ms,
-                        tool_execution_ms = reply.timings.tool_execution_ms,
-                        final_model_ms = reply.timings.final_model_ms,
-                        "slow Discord reply detected"
-                    );
-                } else {
-                    info!(
-                        user_id = %msg.author.id,
-                        channel_id = %msg.channel_id,
-                        message_id = %msg.id,
-                        total_ms = reply.timings.total_ms,
-                        planner_ms = reply.timings.planner_ms,
-                        tool_execution_ms = reply.timings.tool_execution_ms,
-                        final_model_ms = reply.timings.final_model_ms,
-                        "Discord reply ready"
-                    );
-                }
-                let suppress_text_reply = reply
-                    .tool_calls
-                    .iter()
-                    .any(|call| call.tool_name == "discord_voice_listen_turn");
-
-                if suppress_text_reply || reply.text.trim().is_empty() {
-                    return;
-                }
+    async fn typing_start(&self, ctx: Context, event: TypingStartEvent) {
+        let is_bot = event.member.as_ref().is_some_and(|member| member.user.bot);
+        if is_bot || event.user_id == ctx.cache.current_user().id {
+            return;
+        }
 
-                if let Err(error) = msg.channel_id.say(&ctx.http, reply.text).await {
-                    error!(?error, "failed to send Discord message");
-                }
-            }
-            Err(error) => {
-                error!(?error, "failed to process Discord message");
+        let guild_id = event
+            .guild_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| DM_GUILD_ID.to_owned());
+        let channel_id = event.channel_id.to_string();
+        if event.guild_id.is_some() {
+            // Mentions are not known yet, so prefetch wherever a mention
+            // would get an answer.
+            let Ok(settings) = self.guild_settings(&guild_id).await else {
+                return;
+            };
+            if !settings.activation.should_respond(&channel_id, true) {
+                return;
             }
         }
+        self.orchestrator
+            .prefetch_context(&event.user_id.to_string(), &guild_id, &channel_id)
+            .await;
     }
 
     async fn voice_state_update(&self, _ctx: Context, old: Option<VoiceState>, new: VoiceState) {
@@ -93,33 +2205,680 @@ impl EventHandler for Handler {
             return;
         };
 
-        let channel_id = new
-            .channel_id
-            .or_else(|| old.as_ref().and_then(|state| state.channel_id));
         voice
-            .update_user_voice_state(
-                guild_id.get(),
-                new.user_id.get(),
-                channel_id.map(|id| id.get()),
-            )
+            .update_user_voice_state(VoiceStateChange {
+                guild_id: guild_id.get(),
+                user_id: new.user_id.get(),
+                channel_id: new.channel_id.map(|id| id.get()),
+                display_name: new
+                    .member
+                    .as_ref()
+                    .map(|member| member.display_name().to_owned()),
+                is_bot: new.member.as_ref().is_some_and(|member| member.user.bot),
+            })
             .await;
     }
 }
 
+/// Posts finished background tasks to the channel they were started from,
+/// mentioning the user, and marks them delivered.
+async fn deliver_background_results(
+    http: Arc<Http>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    mut results: mpsc::UnboundedReceiver<BackgroundTaskRecord>,
+) {
+    while let Some(task) = results.recv().await {
+        // Channels of `/chat` tasks are whatever the caller sent.
+        if task.origin != TaskOrigin::Discord {
+            continue;
+        }
+        let Ok(channel_id) = task.channel_id.parse::<u64>() else {
+            continue;
+        };
+        // Progress notes go out quietly; only the result mentions the user.
+        let progress = task.status == BackgroundTaskStatus::Running;
+        let followup = render_task_followup(&task);
+        let text = orchestrator.reply_pipelines().discord.apply(
+            &if progress {
+                followup
+            } else {
+                format!("<@{}> {followup}", task.user_id)
+            },
+            &ReplyFilterContext::default(),
+        );
+        if let Err(error) = ChannelId::new(channel_id).say(&http, text).await {
+            warn!(?error, task_id = %task.id, "failed to post background task result");
+            continue;
+        }
+        if progress {
+            continue;
+        }
+        if let Err(error) = orchestrator
+            .memory()
+            .update_background_task(&task.id, BackgroundTaskStatus::Delivered, None, Utc::now())
+            .await
+        {
+            warn!(?error, task_id = %task.id, "failed to mark background task delivered");
+        }
+    }
+}
+
+/// Recovers replies whose turn died mid-flight, e.g. because the bot was
+/// killed, once their heartbeat has gone stale: the user is told or the
+/// message is answered again.
+async fn run_in_flight_recovery(
+    http: Arc<Http>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    recovery: InFlightRecovery,
+) {
+    let mut ticker = tokio::time::interval(IN_FLIGHT_HEARTBEAT);
+    loop {
+        ticker.tick().await;
+        let lost = match claim_stale_in_flight(orchestrator.memory().as_ref(), Utc::now()).await {
+            Ok(lost) => lost,
+            Err(error) => {
+                warn!(?error, "failed to check for lost replies");
+                continue;
+            }
+        };
+        for record in lost {
+            let Ok(channel_id) = record.channel_id.parse::<u64>() else {
+                info!(message_id = %record.message_id, "reply lost outside Discord");
+                continue;
+            };
+            warn!(
+                message_id = %record.message_id,
+                user_id = %record.user_id,
+                phase = record.phase.as_str(),
+                "reply lost mid-flight; recovering"
+            );
+            let http = http.clone();
+            let orchestrator = orchestrator.clone();
+            tokio::spawn(async move {
+                let text = if should_retry(recovery, &record) {
+                    let options = TurnOptions {
+                        track_in_flight: true,
+                        ..TurnOptions::default()
+                    };
+                    match orchestrator
+                        .handle_message_with_options(retry_ctx(&record), options)
+                        .await
+                    {
+                        Ok(reply) if reply.text.trim().is_empty() => return,
+                        Ok(reply) => reply.text,
+                        Err(error) => {
+                            warn!(?error, message_id = %record.message_id, "retrying lost reply failed");
+                            lost_reply_notice(&record)
+                        }
+                    }
+                } else {
+                    lost_reply_notice(&record)
+                };
+                let text = orchestrator.reply_pipelines().discord.apply(
+                    &format!("<@{}> {text}", record.user_id),
+                    &ReplyFilterContext::default(),
+                );
+                if let Err(error) = ChannelId::new(channel_id).say(&http, text).await {
+                    warn!(?error, message_id = %record.message_id, "failed to post recovered reply");
+                }
+            });
+        }
+    }
+}
+
+/// Retries replies Discord refused when they were first sent, and posts
+/// replies held for quiet hours once they end. A retry mentions the user, so
+/// they are notified once it finally lands; a held reply answers the message
+/// it was written for.
+async fn run_outbox_delivery(http: Arc<Http>, orchestrator: Arc<dyn ChatOrchestrator>) {
+    let mut ticker = tokio::time::interval(OUTBOX_POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let memory = orchestrator.memory();
+        for kind in [OutboxKind::Deliver, OutboxKind::Deferred] {
+            let due = match claim_due_outbox(memory.as_ref(), kind, Utc::now()).await {
+                Ok(due) => due,
+                Err(error) => {
+                    warn!(
+                        ?error,
+                        kind = kind.as_str(),
+                        "failed to check the reply outbox"
+                    );
+                    continue;
+                }
+            };
+            for record in due {
+                let outcome = match record.message.channel_id.parse::<u64>() {
+                    Ok(channel_id) => ChannelId::new(channel_id)
+                        .send_message(&http, outbox_message(&record))
+                        .await
+                        .map_err(delivery_error)
+                        .map(|sent| sent.id.to_string()),
+                    Err(error) => Err(anyhow::Error::from(error)
+                        .context("not a Discord channel")
+                        .context(PermanentFailure)),
+                };
+                if let Ok(sent_id) = &outcome
+                    && let Err(error) = memory
+                        .set_reply_source_message(
+                            &record.message.user_id,
+                            &record.reply_to,
+                            sent_id,
+                        )
+                        .await
+                {
+                    warn!(?error, outbox_id = %record.id, "failed to link delivered reply");
+                }
+                finish_outbox_attempt(memory.as_ref(), record, outcome.map(|_| ())).await;
+            }
+        }
+    }
+}
+
+/// Client errors other than rate limits, such as a deleted channel or
+/// missing permissions, are not retried.
+fn delivery_error(error: serenity::Error) -> anyhow::Error {
+    let permanent = match &error {
+        serenity::Error::Http(error) => error.status_code().is_some_and(|status| {
+            status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+        }),
+        _ => false,
+    };
+    if permanent {
+        anyhow::Error::from(error).context(PermanentFailure)
+    } else {
+        error.into()
+    }
+}
+
+/// The message an outbox entry is posted as.
+fn outbox_message(record: &OutboxRecord) -> CreateMessage {
+    if record.kind != OutboxKind::Deferred {
+        return CreateMessage::new().content(delayed_reply_text(record));
+    }
+    let message = CreateMessage::new().content(record.message.content.clone());
+    match (
+        record.message.channel_id.parse::<u64>(),
+        record.reply_to.parse::<u64>(),
+    ) {
+        (Ok(channel_id), Ok(message_id)) => message.reference_message(
+            MessageReference::from((ChannelId::new(channel_id), MessageId::new(message_id)))
+                .fail_if_not_exists(false),
+        ),
+        _ => message,
+    }
+}
+
+/// How a reply to a mention goes out during quiet hours ending at `until`.
+#[derive(Debug, PartialEq, Eq)]
+enum QuietReply {
+    Send(String),
+    /// Held until the time given.
+    Hold(DateTime<Utc>, String),
+}
+
+fn quiet_mention_reply(
+    policy: QuietMentionPolicy,
+    text: String,
+    until: Option<DateTime<Utc>>,
+) -> QuietReply {
+    let Some(until) = until else {
+        return QuietReply::Send(text);
+    };
+    match policy {
+        QuietMentionPolicy::Reply => QuietReply::Send(text),
+        QuietMentionPolicy::Mark => QuietReply::Send(append_within_limit(
+            &text,
+            QUIET_HOURS_NOTE,
+            DISCORD_MESSAGE_LIMIT,
+        )),
+        QuietMentionPolicy::Delay => QuietReply::Hold(until, text),
+    }
+}
+
+/// Records that the bot is running, so the next start knows how long it was
+/// offline.
+async fn run_heartbeat(store: Arc<dyn MemoryStore>) {
+    let mut ticker = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        ticker.tick().await;
+        if let Err(error) = store.record_last_seen(HEARTBEAT_ADAPTER, Utc::now()).await {
+            warn!(?error, "failed to record Discord heartbeat");
+        }
+    }
+}
+
+/// The apology for a failed turn, saying what was being done when the
+/// error carries a [`TurnFailure`].
+fn failure_reply_text(error: &anyhow::Error) -> String {
+    match error.downcast_ref::<TurnFailure>() {
+        Some(failure) => format!(
+            "Sorry, something went wrong {}. Please try again.",
+            failure.describe()
+        ),
+        None => "Sorry, something went wrong. Please try again.".to_owned(),
+    }
+}
+
+/// The files attached to a Discord message, as the orchestrator sees them.
+fn message_attachments(msg: &Message) -> Vec<MessageAttachment> {
+    msg.attachments
+        .iter()
+        .map(|attachment| MessageAttachment {
+            url: attachment.url.clone(),
+            filename: attachment.filename.clone(),
+            content_type: attachment.content_type.clone(),
+            size: Some(u64::from(attachment.size)),
+        })
+        .collect()
+}
+
+/// The smallest snowflake Discord could assign at `at`, for `after` queries.
+fn snowflake_at(at: DateTime<Utc>) -> u64 {
+    let since_epoch = (at.timestamp_millis() - DISCORD_EPOCH_MS).max(0) as u64;
+    since_epoch << 22
+}
+
+/// Mirrors orchestrator activity in the bot's status: "Thinking…" while a
+/// turn runs, "Listening to N users" after recent messages, and the default
+/// persona when idle. Discord is only told about changes.
+async fn run_presence_updates(
+    ctx: Context,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    interval: Duration,
+) {
+    let Some(activity) = orchestrator.activity() else {
+        return;
+    };
+    let persona = orchestrator.guild_settings().defaults().persona.clone();
+    let mut shown = None;
+    loop {
+        let presence = Presence::from_snapshot(activity.snapshot(), persona.as_deref());
+        if shown.as_ref() != Some(&presence) {
+            let text = presence.text();
+            let status = match presence {
+                Presence::Listening(_) => ActivityData::listening(text),
+                Presence::Thinking | Presence::Persona(_) => ActivityData::custom(text),
+            };
+            ctx.set_activity(Some(status));
+            shown = Some(presence);
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Periodically DMs users their stale or low-confidence facts to confirm.
+async fn run_memory_reviews(
+    http: Arc<Http>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    config: MemoryReviewConfig,
+) {
+    let memory = orchestrator.memory();
+    let coordinator = orchestrator.coordinator();
+    loop {
+        tokio::time::sleep(config.interval).await;
+        // With replicas, one instance per interval sends the reviews.
+        if let Some(coordinator) = &coordinator
+            && !claim_once(
+                coordinator.as_ref(),
+                "discord:memory-review",
+                config.interval,
+            )
+            .await
+        {
+            continue;
+        }
+        let batches = match collect_review_batches(memory.as_ref(), &config, Utc::now()).await {
+            Ok(batches) => batches,
+            Err(error) => {
+                warn!(?error, "memory review scan failed");
+                continue;
+            }
+        };
+        for batch in batches {
+            // Held back reviews are not recorded, so a later run sends them.
+            let kind = NotificationKind::MemoryReviews;
+            if !matches!(
+                notification_decision(orchestrator.as_ref(), &batch.user_id, kind, None).await,
+                NotificationDecision::Send { .. }
+            ) {
+                continue;
+            }
+            // A failed DM is recorded too, so users with closed DMs are
+            // retried after the cooldown rather than on every run.
+            let sent = send_memory_review(&http, &batch).await;
+            if let Err(error) = memory
+                .record_memory_review(&batch.user_id, Utc::now())
+                .await
+            {
+                warn!(?error, user_id = %batch.user_id, "failed to record memory review");
+            }
+            if let Err(error) = sent {
+                warn!(?error, user_id = %batch.user_id, "failed to send memory review");
+                continue;
+            }
+            record_notification(orchestrator.as_ref(), &batch.user_id, kind).await;
+            info!(user_id = %batch.user_id, facts = batch.facts.len(), "memory review sent");
+        }
+    }
+}
+
+/// The user's notification preferences and the quiet hours of `guild`, the
+/// server the message would be posted in, applied to a proactive message
+/// now. Without a preference service everything is sent; a failed lookup
+/// defers the message.
+async fn notification_decision(
+    orchestrator: &dyn ChatOrchestrator,
+    user_id: &str,
+    kind: NotificationKind,
+    guild: Option<&GuildSettings>,
+) -> NotificationDecision {
+    let Some(notifications) = orchestrator.notifications() else {
+        return NotificationDecision::Send { dm_only: false };
+    };
+    match notifications.decide(user_id, kind, guild, Utc::now()).await {
+        Ok(decision) => {
+            if decision != (NotificationDecision::Send { dm_only: false }) {
+                info!(
+                    user_id,
+                    kind = kind.as_str(),
+                    ?decision,
+                    "notification preferences applied"
+                );
+            }
+            decision
+        }
+        Err(error) => {
+            warn!(?error, user_id, "failed to check notification preferences");
+            NotificationDecision::Defer
+        }
+    }
+}
+
+/// Counts a sent proactive message against the user's daily cap.
+async fn record_notification(
+    orchestrator: &dyn ChatOrchestrator,
+    user_id: &str,
+    kind: NotificationKind,
+) {
+    if let Some(notifications) = orchestrator.notifications()
+        && let Err(error) = notifications.record_sent(user_id, kind, Utc::now()).await
+    {
+        warn!(?error, user_id, "failed to record notification");
+    }
+}
+
+/// Greets users on their birthdays and anniversaries once a day, in the
+/// configured channel or by DM. Greetings held back by quiet hours or a
+/// daily cap are retried hourly for [`GREETING_DEFERRAL`].
+async fn run_date_greetings(
+    http: Arc<Http>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    config: DateGreetingConfig,
+) {
+    let memory = orchestrator.memory();
+    let coordinator = orchestrator.coordinator();
+    let mut deferred = Vec::new();
+    let mut deferred_until = Utc::now();
+    loop {
+        let next_run = until_next_run(Utc::now(), config.hour_utc);
+        let retry = Duration::from_secs(60 * 60);
+        let daily = deferred.is_empty() || next_run <= retry;
+        tokio::time::sleep(if daily { next_run } else { retry }).await;
+
+        let due = if daily {
+            if let Some(coordinator) = &coordinator
+                && !claim_once(
+                    coordinator.as_ref(),
+                    "discord:date-greetings",
+                    Duration::from_secs(60 * 60),
+                )
+                .await
+            {
+                deferred.clear();
+                continue;
+            }
+            deferred_until = Utc::now() + GREETING_DEFERRAL;
+            match due_greetings(memory.as_ref(), Utc::now()).await {
+                Ok(due) => due,
+                Err(error) => {
+                    warn!(?error, "date greeting scan failed");
+                    continue;
+                }
+            }
+        } else if Utc::now() < deferred_until {
+            std::mem::take(&mut deferred)
+        } else {
+            deferred.clear();
+            continue;
+        };
+        deferred = send_date_greetings(&http, orchestrator.as_ref(), config.channel_id, due).await;
+    }
+}
+
+/// Sends the greetings the users' notification preferences allow now and
+/// returns the ones held back.
+async fn send_date_greetings(
+    http: &Arc<Http>,
+    orchestrator: &dyn ChatOrchestrator,
+    channel_id: Option<u64>,
+    due: Vec<DueGreeting>,
+) -> Vec<DueGreeting> {
+    let memory = orchestrator.memory();
+    let kind = NotificationKind::DateGreetings;
+    let mut deferred = Vec::new();
+    for greeting in due {
+        let user_id = &greeting.user_id;
+        let target = match channel_id {
+            Some(channel_id) => greeting_channel(http, channel_id, user_id)
+                .await
+                .unwrap_or_else(|error| {
+                    warn!(?error, %user_id, "failed to resolve date greeting channel");
+                    None
+                }),
+            None => None,
+        };
+        let guild = match target {
+            Some((guild_id, _)) => orchestrator
+                .guild_settings()
+                .get(&guild_id.to_string())
+                .await
+                .map_err(|error| warn!(?error, %guild_id, "failed to load guild settings"))
+                .ok(),
+            None => None,
+        };
+        match memory
+            .has_date_greeting(user_id, &greeting.date_fact.key, greeting.day)
+            .await
+        {
+            Ok(false) => {}
+            Ok(true) => continue,
+            Err(error) => {
+                warn!(?error, %user_id, "failed to check date greeting");
+                continue;
+            }
+        }
+        let decision = notification_decision(orchestrator, user_id, kind, guild.as_ref()).await;
+        let dm_only = match decision {
+            NotificationDecision::Send { dm_only } => dm_only,
+            NotificationDecision::Defer => {
+                deferred.push(greeting);
+                continue;
+            }
+            NotificationDecision::Suppress => continue,
+        };
+        let target = target.filter(|_| !dm_only);
+        if let Err(error) = send_date_greeting(http, orchestrator, target, &greeting).await {
+            // Unrecorded, so the next retry sends it.
+            warn!(?error, %user_id, "failed to send date greeting");
+            deferred.push(greeting);
+            continue;
+        }
+        if let Err(error) = memory
+            .record_date_greeting(user_id, &greeting.date_fact.key, greeting.day)
+            .await
+        {
+            warn!(?error, %user_id, "failed to record date greeting");
+        }
+        record_notification(orchestrator, user_id, kind).await;
+        info!(%user_id, key = %greeting.date_fact.key, "date greeting sent");
+    }
+    deferred
+}
+
+/// The configured greetings channel and its server, if the user is in that
+/// server; greetings to other users go out as DMs.
+async fn greeting_channel(
+    http: &Arc<Http>,
+    channel_id: u64,
+    user_id: &str,
+) -> anyhow::Result<Option<(GuildId, ChannelId)>> {
+    let user_id = UserId::new(user_id.parse::<u64>()?);
+    let channel = ChannelId::new(channel_id).to_channel(http).await?;
+    if let Some(channel) = channel.guild()
+        && channel.guild_id.member(http, user_id).await.is_ok()
+    {
+        return Ok(Some((channel.guild_id, channel.id)));
+    }
+    Ok(None)
+}
+
+/// Sends `greeting` in `target` with a mention, or as a DM without one.
+async fn send_date_greeting(
+    http: &Arc<Http>,
+    orchestrator: &dyn ChatOrchestrator,
+    target: Option<(GuildId, ChannelId)>,
+    greeting: &DueGreeting,
+) -> anyhow::Result<()> {
+    let user_id = UserId::new(greeting.user_id.parse::<u64>()?);
+    let (guild_id, channel_id, mention) = match target {
+        Some((guild_id, channel_id)) => {
+            (guild_id.to_string(), channel_id, format!("<@{user_id}> "))
+        }
+        None => {
+            let channel = user_id.create_dm_channel(http).await?;
+            (DM_GUILD_ID.to_owned(), channel.id, String::new())
+        }
+    };
+
+    let text = match orchestrator
+        .compose_proactive_message(
+            &greeting.user_id,
+            &guild_id,
+            &channel_id.to_string(),
+            &greeting.instruction(target.is_none()),
+        )
+        .await
+    {
+        Ok(Some(text)) => text,
+        Ok(None) => greeting.fallback_text(),
+        Err(error) => {
+            warn!(?error, user_id = %greeting.user_id, "failed to write date greeting");
+            greeting.fallback_text()
+        }
+    };
+    let guild_emoji = orchestrator
+        .guild_emoji()
+        .map(|catalog| catalog.get(&guild_id))
+        .unwrap_or_default();
+    let text = orchestrator.reply_pipelines().discord.apply(
+        &text,
+        &ReplyFilterContext {
+            guild_emoji: &guild_emoji,
+            ..ReplyFilterContext::default()
+        },
+    );
+    let text = append_within_limit(
+        &format!("{mention}{text}"),
+        GREETINGS_OPT_OUT_HINT,
+        DISCORD_MESSAGE_LIMIT,
+    );
+    channel_id
+        .send_message(http, CreateMessage::new().content(text))
+        .await?;
+    Ok(())
+}
+
+async fn send_memory_review(http: &Arc<Http>, batch: &ReviewBatch) -> anyhow::Result<()> {
+    let facts = batch
+        .facts
+        .iter()
+        .filter(|fact| {
+            review_button_id(ReviewVerdict::Confirm, &fact.key).len() <= MAX_CUSTOM_ID_LEN
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if facts.is_empty() {
+        return Ok(());
+    }
+    let rows = facts
+        .iter()
+        .map(|fact| {
+            let label = fact
+                .key
+                .replace('_', " ")
+                .chars()
+                .take(60)
+                .collect::<String>();
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(review_button_id(ReviewVerdict::Confirm, &fact.key))
+                    .label(format!("✅ {label}"))
+                    .style(ButtonStyle::Success),
+                CreateButton::new(review_button_id(ReviewVerdict::Deny, &fact.key))
+                    .label("❌")
+                    .style(ButtonStyle::Danger),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let user_id = batch.user_id.parse::<u64>()?;
+    let channel = UserId::new(user_id).create_dm_channel(http).await?;
+    channel
+        .send_message(
+            http,
+            CreateMessage::new()
+                .content(review_message(&facts))
+                .components(rows),
+        )
+        .await?;
+    Ok(())
+}
+
 pub async fn start_discord_bot(
     token: String,
-    orchestrator: Arc<DefaultChatOrchestrator>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
     voice: Option<Arc<VoiceManager>>,
+    options: DiscordBotOptions,
 ) -> anyhow::Result<()> {
-    let intents = GatewayIntents::GUILD_MESSAGES
+    let mut intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_EMOJIS_AND_STICKERS
         | GatewayIntents::GUILD_VOICE_STATES
         | GatewayIntents::DIRECT_MESSAGES
-        | GatewayIntents::MESSAGE_CONTENT;
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
+        | GatewayIntents::DIRECT_MESSAGE_REACTIONS;
+    if options.message_content_intent {
+        intents |= GatewayIntents::MESSAGE_CONTENT;
+    } else {
+        info!("message content intent disabled; answering /ask, DMs and mentions only");
+    }
+    if options.typing_prefetch {
+        intents |= GatewayIntents::GUILD_MESSAGE_TYPING | GatewayIntents::DIRECT_MESSAGE_TYPING;
+    }
 
     let handler = Handler {
         orchestrator,
         voice: voice.clone(),
+        debouncer: MessageDebouncer::new(options.debounce_window),
+        admin_channel_id: options.admin_channel_id,
+        admin_alerts: Mutex::new(options.admin_alerts),
+        background_results: Mutex::new(options.background_results),
+        in_flight_recovery: options.in_flight_recovery,
+        personas: options.personas,
+        memory_review: Mutex::new(options.memory_review),
+        date_greetings: Mutex::new(options.date_greetings),
+        presence_interval: Mutex::new(options.presence_interval),
+        started: AtomicBool::new(false),
     };
 
     let mut builder = Client::builder(token, intents).event_handler(handler);
@@ -132,7 +2891,264 @@ pub async fn start_discord_bot(
 
     let mut client = builder.await?;
 
-    info!("starting Discord gateway client");
-    client.start().await?;
+    match options.shard {
+        Some((shard_id, shard_count)) => {
+            info!(shard_id, shard_count, "starting Discord gateway shard");
+            client.start_shard(shard_id, shard_count).await?;
+        }
+        None => {
+            info!("starting Discord gateway client");
+            client.start().await?;
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+    use serenity::all::{ChannelId, ComponentInteractionDataKind, MessageId};
+
+    use super::{
+        MAX_QUOTE_CHARS, MessageDebouncer, QUIET_HOURS_NOTE, QuietReply, activation_greeting,
+        apply_setup_action, custom_slash_command, quiet_mention_reply, quoted_reply,
+        selected_tools, setup_panel, slash_commands, snowflake_at,
+    };
+    use crate::{
+        custom_commands::{BUILTIN_COMMANDS, CustomCommand},
+        guild_settings::{GuildSettings, QuietMentionPolicy},
+        personas::PersonaBundle,
+        reply_filters::DISCORD_MESSAGE_LIMIT,
+    };
+
+    #[test]
+    fn custom_commands_register_one_required_option_per_placeholder() {
+        let command = CustomCommand {
+            name: "summarize-week".to_owned(),
+            description: "Summarize our week".to_owned(),
+            template: "Summarize {user}'s week before {date}, focusing on {topic} in {time_zone}."
+                .to_owned(),
+            updated_at: Utc::now(),
+        };
+        let slash = serde_json::to_value(custom_slash_command(&command)).expect("serializable");
+
+        assert_eq!(slash["name"], "summarize-week");
+        assert_eq!(slash["description"], "Summarize our week");
+        let options = slash["options"].as_array().expect("options");
+        let names: Vec<_> = options.iter().map(|option| &option["name"]).collect();
+        assert_eq!(names, ["topic", "time_zone"]);
+        assert!(options.iter().all(|option| option["required"] == true));
+        assert_eq!(options[1]["description"], "time zone");
+    }
+
+    #[test]
+    fn builtin_command_list_matches_the_registered_commands() {
+        let mut registered: Vec<_> = slash_commands()
+            .into_iter()
+            .map(|command| {
+                serde_json::to_value(command).expect("serializable")["name"]
+                    .as_str()
+                    .expect("name")
+                    .to_owned()
+            })
+            .collect();
+        let mut builtin: Vec<_> = BUILTIN_COMMANDS
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        registered.sort();
+        builtin.sort();
+        assert_eq!(registered, builtin);
+    }
+
+    #[test]
+    fn mention_replies_follow_the_quiet_hours_policy() {
+        let until = Utc::now() + chrono::Duration::hours(3);
+        let reply = |policy, until| quiet_mention_reply(policy, "Hi!".to_owned(), until);
+        for policy in [
+            QuietMentionPolicy::Reply,
+            QuietMentionPolicy::Mark,
+            QuietMentionPolicy::Delay,
+        ] {
+            assert_eq!(reply(policy, None), QuietReply::Send("Hi!".to_owned()));
+        }
+        assert_eq!(
+            reply(QuietMentionPolicy::Reply, Some(until)),
+            QuietReply::Send("Hi!".to_owned())
+        );
+        assert_eq!(
+            reply(QuietMentionPolicy::Mark, Some(until)),
+            QuietReply::Send(format!("Hi!\n{QUIET_HOURS_NOTE}"))
+        );
+        assert_eq!(
+            reply(QuietMentionPolicy::Delay, Some(until)),
+            QuietReply::Hold(until, "Hi!".to_owned())
+        );
+        let QuietReply::Send(marked) =
+            quiet_mention_reply(QuietMentionPolicy::Mark, "word ".repeat(500), Some(until))
+        else {
+            panic!("marked replies are sent");
+        };
+        assert!(marked.chars().count() <= 2000);
+        assert!(marked.ends_with(QUIET_HOURS_NOTE));
+    }
+
+    #[test]
+    fn setup_actions_update_guild_settings() {
+        let mut settings = GuildSettings::new("1");
+        assert!(apply_setup_action(
+            &mut settings,
+            "channels",
+            &ComponentInteractionDataKind::ChannelSelect {
+                values: vec![ChannelId::new(42)],
+            },
+        ));
+        assert!(apply_setup_action(
+            &mut settings,
+            "tools",
+            &ComponentInteractionDataKind::StringSelect {
+                values: vec!["current_datetime".to_owned()],
+            },
+        ));
+        assert!(apply_setup_action(
+            &mut settings,
+            "memory",
+            &ComponentInteractionDataKind::Button,
+        ));
+        assert!(!apply_setup_action(
+            &mut settings,
+            "done",
+            &ComponentInteractionDataKind::Button,
+        ));
+
+        assert!(settings.activation.should_respond("42", false));
+        assert!(!settings.activation.should_respond("43", true));
+        assert_eq!(
+            settings.enabled_tools.as_deref(),
+            Some(&["current_datetime".to_owned()][..])
+        );
+        assert!(!settings.memory_consent_default);
+    }
+
+    #[test]
+    fn slash_command_replies_quote_the_question_within_the_limit() {
+        assert_eq!(
+            quoted_reply("what's  the\nweather?", "Sunny."),
+            "> what's the weather?\n\nSunny."
+        );
+
+        let reply = quoted_reply(&"why ".repeat(1_000), &"because ".repeat(500));
+        assert!(reply.chars().count() <= DISCORD_MESSAGE_LIMIT);
+        let (quote, text) = reply.split_once("\n\n").expect("quote and text");
+        assert!(quote.starts_with("> why") && quote.ends_with('…'));
+        assert!(quote.chars().count() <= MAX_QUOTE_CHARS + 2);
+        assert!(text.starts_with("because") && text.ends_with('…'));
+    }
+
+    #[test]
+    fn switching_persona_in_setup_posts_its_greeting() {
+        let personas = vec![
+            PersonaBundle {
+                name: "pirate".to_owned(),
+                greeting: Some("Ahoy, matey!".to_owned()),
+                ..PersonaBundle::default()
+            },
+            PersonaBundle {
+                name: "butler".to_owned(),
+                ..PersonaBundle::default()
+            },
+        ];
+        assert_eq!(
+            activation_greeting(None, Some("pirate"), &personas).as_deref(),
+            Some("Ahoy, matey!")
+        );
+        assert_eq!(
+            activation_greeting(Some("pirate"), Some("pirate"), &personas),
+            None
+        );
+        assert_eq!(
+            activation_greeting(Some("pirate"), Some("butler"), &personas),
+            None
+        );
+        assert_eq!(activation_greeting(Some("pirate"), None, &personas), None);
+    }
+
+    #[test]
+    fn setup_menus_stay_within_discords_option_limit() {
+        let personas = (0..40)
+            .map(|index| PersonaBundle {
+                name: format!("persona-{index}"),
+                ..PersonaBundle::default()
+            })
+            .collect::<Vec<_>>();
+        let mut settings = GuildSettings::new("1");
+        settings.persona = Some("persona-33".to_owned());
+        settings.activation.channel_ids = (1..=30).map(|id| id.to_string()).collect();
+
+        let panel = serde_json::to_value(setup_panel(&settings, &personas)).expect("serializable");
+        let menus = panel["components"]
+            .as_array()
+            .expect("rows")
+            .iter()
+            .map(|row| &row["components"][0])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            menus[0]["default_values"].as_array().map(Vec::len),
+            Some(25)
+        );
+        let persona_options = menus[1]["options"].as_array().expect("persona options");
+        assert_eq!(persona_options.len(), 25);
+        assert!(
+            persona_options
+                .iter()
+                .any(|option| option["value"] == "persona-33" && option["default"] == true)
+        );
+        assert!(menus[2]["options"].as_array().expect("tool options").len() <= 25);
+
+        let all_tools = (0..30)
+            .map(|index| format!("tool-{index}"))
+            .collect::<Vec<_>>();
+        let all_tools = all_tools.iter().map(String::as_str).collect::<Vec<_>>();
+        let current = Some(vec!["tool-0".to_owned(), "tool-27".to_owned()]);
+        assert_eq!(
+            selected_tools(&all_tools, &["tool-1".to_owned()], &current),
+            Some(vec!["tool-1".to_owned(), "tool-27".to_owned()])
+        );
+        let every_offered = all_tools[..25]
+            .iter()
+            .map(|tool| (*tool).to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(selected_tools(&all_tools, &every_offered, &None), None);
+    }
+
+    #[test]
+    fn replay_cursor_matches_discord_snowflake_time() {
+        let at = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).expect("valid time");
+        let cursor = MessageId::new(snowflake_at(at));
+        assert_eq!(cursor.created_at().unix_timestamp(), at.timestamp());
+    }
+
+    #[tokio::test]
+    async fn coalesces_burst_into_last_message() {
+        let debouncer = MessageDebouncer::new(Duration::from_millis(50));
+        let (first, second, other_channel) = tokio::join!(
+            debouncer.coalesce(1, 10, "so about tomorrow".to_owned()),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                debouncer
+                    .coalesce(1, 10, "can you remind me?".to_owned())
+                    .await
+            },
+            debouncer.coalesce(1, 11, "separate".to_owned()),
+        );
+
+        assert_eq!(first, None);
+        assert_eq!(
+            second.as_deref(),
+            Some("so about tomorrow\ncan you remind me?")
+        );
+        assert_eq!(other_channel.as_deref(), Some("separate"));
+    }
+}
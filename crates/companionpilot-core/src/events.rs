@@ -0,0 +1,302 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::types::{MemoryFact, SystemNoticeRecord};
+
+/// Longest an event-created notice waits for the user's next turn.
+pub const MAX_EVENT_NOTICE_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Something that happened outside chat, pushed by another service, e.g.
+/// `{"user_id": "123", "event": "workout.completed", "data": {"activity": "run"}}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalEvent {
+    pub user_id: String,
+    pub event: String,
+    /// Fields the mapping templates refer to as `{name}`.
+    #[serde(default)]
+    pub data: Map<String, Value>,
+    /// When it happened; defaults to when it was received.
+    #[serde(default)]
+    pub occurred_at: Option<DateTime<Utc>>,
+}
+
+/// How one event type is written to memory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventMapping {
+    pub event: String,
+    #[serde(flatten)]
+    pub action: EventAction,
+}
+
+/// Templates may use the event's `data` fields plus `{event}` and `{date}`
+/// (the day it occurred, `YYYY-MM-DD`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventAction {
+    /// Upserts a long-term fact, e.g. `last_workout = run, 5 km (2024-05-01)`.
+    Fact {
+        key: String,
+        value: String,
+        #[serde(default = "default_confidence")]
+        confidence: f32,
+    },
+    /// Queues a one-off note for the user's next turn, so the companion can
+    /// bring up what happened without remembering it forever.
+    Notice {
+        text: String,
+        #[serde(default = "default_notice_ttl_secs")]
+        ttl_secs: u64,
+    },
+}
+
+fn default_confidence() -> f32 {
+    0.9
+}
+
+fn default_notice_ttl_secs() -> u64 {
+    3 * 24 * 60 * 60
+}
+
+/// What one mapping produced for an event, ready to be stored.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventEffect {
+    Fact(MemoryFact),
+    Notice(SystemNoticeRecord),
+}
+
+/// Why an event could not be turned into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventError {
+    /// No mapping is configured for the event type.
+    Unmapped(String),
+    /// A template refers to a field the event does not carry.
+    MissingField { event: String, field: String },
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unmapped(event) => write!(f, "no mapping for event `{event}`"),
+            Self::MissingField { event, field } => {
+                write!(f, "event `{event}` has no `{field}` field in its data")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EventError {}
+
+#[derive(Debug, Deserialize)]
+struct MappingFile {
+    #[serde(default)]
+    mappings: Vec<EventMapping>,
+}
+
+/// Event mappings defined by the admin, keyed by event type.
+#[derive(Debug, Clone, Default)]
+pub struct EventMappings {
+    mappings: BTreeMap<String, Vec<EventAction>>,
+}
+
+impl EventMappings {
+    /// Parses a `[[mappings]]` TOML file; one event type may have several
+    /// mappings, e.g. a fact and a notice.
+    pub fn from_toml(raw: &str) -> anyhow::Result<Self> {
+        let file: MappingFile = toml::from_str(raw)?;
+        let mut errors = Vec::new();
+        let mut mappings = BTreeMap::<String, Vec<EventAction>>::new();
+        for mapping in file.mappings {
+            if mapping.event.trim().is_empty() {
+                errors.push("a mapping has an empty `event`".to_owned());
+                continue;
+            }
+            match &mapping.action {
+                EventAction::Fact {
+                    key, confidence, ..
+                } => {
+                    if key.trim().is_empty() {
+                        errors.push(format!("`{}`: fact key is empty", mapping.event));
+                    }
+                    if !(0.0..=1.0).contains(confidence) {
+                        errors.push(format!("`{}`: confidence must be 0-1", mapping.event));
+                    }
+                }
+                EventAction::Notice { ttl_secs, .. } => {
+                    if *ttl_secs == 0 || *ttl_secs > MAX_EVENT_NOTICE_TTL_SECS {
+                        errors.push(format!(
+                            "`{}`: ttl_secs must be between 1 and {MAX_EVENT_NOTICE_TTL_SECS}",
+                            mapping.event
+                        ));
+                    }
+                }
+            }
+            mappings
+                .entry(mapping.event)
+                .or_default()
+                .push(mapping.action);
+        }
+        anyhow::ensure!(
+            errors.is_empty(),
+            "invalid event mappings: {}",
+            errors.join("; ")
+        );
+        Ok(Self { mappings })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Renders every mapping for the event; nothing is produced unless all
+    /// of them render.
+    pub fn map(
+        &self,
+        event: &ExternalEvent,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<EventEffect>, EventError> {
+        let actions = self
+            .mappings
+            .get(&event.event)
+            .ok_or_else(|| EventError::Unmapped(event.event.clone()))?;
+        let occurred_at = event.occurred_at.unwrap_or(now);
+        let render = |template: &str| render_template(template, event, occurred_at);
+        actions
+            .iter()
+            .map(|action| {
+                Ok(match action {
+                    EventAction::Fact {
+                        key,
+                        value,
+                        confidence,
+                    } => EventEffect::Fact(MemoryFact {
+                        key: render(key)?,
+                        value: render(value)?,
+                        confidence: *confidence,
+                        source: format!("event:{}", event.event),
+                        updated_at: now,
+                        last_confirmed_at: None,
+                    }),
+                    EventAction::Notice { text, ttl_secs } => {
+                        EventEffect::Notice(SystemNoticeRecord {
+                            id: String::new(),
+                            user_id: event.user_id.clone(),
+                            text: render(text)?,
+                            created_at: now,
+                            expires_at: now + chrono::Duration::seconds(*ttl_secs as i64),
+                        })
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+fn render_template(
+    template: &str,
+    event: &ExternalEvent,
+    occurred_at: DateTime<Utc>,
+) -> Result<String, EventError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        let field = &rest[start + 1..start + len];
+        let value = match field {
+            "event" => event.event.clone(),
+            "date" => occurred_at.format("%Y-%m-%d").to_string(),
+            _ => match event.data.get(field) {
+                Some(Value::String(text)) => text.clone(),
+                Some(value) if !value.is_null() => value.to_string(),
+                _ => {
+                    return Err(EventError::MissingField {
+                        event: event.event.clone(),
+                        field: field.to_owned(),
+                    });
+                }
+            },
+        };
+        rendered.push_str(&value);
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn events_render_into_facts_and_notices() {
+        let mappings = EventMappings::from_toml(
+            r#"
+            [[mappings]]
+            event = "workout.completed"
+            kind = "fact"
+            key = "last_workout"
+            value = "{activity}, {distance_km} km ({date})"
+
+            [[mappings]]
+            event = "workout.completed"
+            kind = "notice"
+            text = "They just finished a {activity}."
+            ttl_secs = 3600
+            "#,
+        )
+        .expect("valid mappings");
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 18, 0, 0).unwrap();
+        let event: ExternalEvent = serde_json::from_value(json!({
+            "user_id": "u1",
+            "event": "workout.completed",
+            "data": {"activity": "run", "distance_km": 5}
+        }))
+        .expect("valid event");
+
+        let effects = mappings.map(&event, now).expect("mapped");
+        let EventEffect::Fact(fact) = &effects[0] else {
+            panic!("expected a fact, got {effects:?}");
+        };
+        assert_eq!(fact.key, "last_workout");
+        assert_eq!(fact.value, "run, 5 km (2024-05-01)");
+        assert_eq!(fact.source, "event:workout.completed");
+        let EventEffect::Notice(notice) = &effects[1] else {
+            panic!("expected a notice, got {effects:?}");
+        };
+        assert_eq!(notice.text, "They just finished a run.");
+        assert_eq!(notice.expires_at, now + chrono::Duration::hours(1));
+
+        let partial = ExternalEvent {
+            data: Map::new(),
+            ..event.clone()
+        };
+        assert_eq!(
+            mappings.map(&partial, now).unwrap_err(),
+            EventError::MissingField {
+                event: "workout.completed".to_owned(),
+                field: "activity".to_owned(),
+            }
+        );
+        let unknown = ExternalEvent {
+            event: "order.shipped".to_owned(),
+            ..event
+        };
+        assert!(matches!(
+            mappings.map(&unknown, now),
+            Err(EventError::Unmapped(_))
+        ));
+    }
+}
@@ -0,0 +1,551 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Timelike, Utc};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use crate::{
+    coordination::claim_once,
+    guild_settings::GuildSettingsCache,
+    memory::MemoryStore,
+    model::{ModelProvider, ModelRequest},
+    orchestrator::{ChatOrchestrator, parse_json_plan, proposed_fact},
+    types::{ChatMessageRecord, ChatRole, PendingFactRecord, PlannerDecisionRecord},
+};
+
+/// `source` of facts the backfill writes.
+pub const BACKFILL_SOURCE: &str = "backfill";
+/// Planner name of the decisions that record how far a user was backfilled.
+pub const BACKFILL_PLANNER: &str = "fact_backfill";
+const BACKFILL_DECISION: &str = "extract_facts";
+
+/// Claimed by the instance that runs the day's backfill.
+const BACKFILL_CLAIM_KEY: &str = "fact-backfill";
+const BACKFILL_CLAIM_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Users looked at per run, most recently active first.
+const USER_SCAN_LIMIT: usize = 500;
+/// Decisions of each kind read per user to find progress and already-planned
+/// messages.
+const DECISION_SCAN_LIMIT: usize = 2_000;
+/// Latest messages per user considered for backfill.
+const MESSAGE_SCAN_LIMIT: usize = 5_000;
+const FACT_SCAN_LIMIT: usize = 1_000;
+/// Messages newer than this may still be in a turn and are left for the next
+/// run.
+const SETTLE_TIME: chrono::Duration = chrono::Duration::minutes(10);
+
+#[derive(Debug, Clone)]
+pub struct FactBackfillConfig {
+    /// Hour of the day (UTC) the job runs at.
+    pub hour_utc: u32,
+    /// Messages of one user processed per run, oldest first.
+    pub max_messages_per_user: usize,
+    /// Messages sent to the model in one extraction prompt.
+    pub batch_size: usize,
+    /// Extracted facts below this confidence are dropped.
+    pub min_confidence: f32,
+    /// Queue extracted facts for admin approval instead of storing them.
+    pub approval_queue: bool,
+}
+
+impl Default for FactBackfillConfig {
+    fn default() -> Self {
+        Self {
+            hour_utc: 3,
+            max_messages_per_user: 200,
+            batch_size: 20,
+            min_confidence: 0.7,
+            approval_queue: false,
+        }
+    }
+}
+
+/// What one run did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackfillReport {
+    pub users: usize,
+    pub messages: usize,
+    pub facts: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ExtractedFacts {
+    #[serde(default)]
+    facts: Vec<ExtractedFact>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExtractedFact {
+    key: String,
+    value: String,
+    #[serde(default)]
+    confidence: f32,
+}
+
+/// Runs [`run_fact_backfill`] every day at the configured hour. With
+/// replicas, one instance runs it.
+pub fn start_fact_backfill_job(
+    model: Arc<dyn ModelProvider>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+    config: FactBackfillConfig,
+) {
+    tokio::spawn(async move {
+        let store = orchestrator.memory();
+        loop {
+            tokio::time::sleep(until_next_run(Utc::now(), config.hour_utc)).await;
+            if let Some(coordinator) = orchestrator.coordinator()
+                && !claim_once(coordinator.as_ref(), BACKFILL_CLAIM_KEY, BACKFILL_CLAIM_TTL).await
+            {
+                continue;
+            }
+            match run_fact_backfill(
+                model.as_ref(),
+                store.as_ref(),
+                orchestrator.guild_settings(),
+                &config,
+                Utc::now(),
+            )
+            .await
+            {
+                Ok(report) => info!(?report, "fact backfill finished"),
+                Err(error) => warn!(?error, "fact backfill failed"),
+            }
+        }
+    });
+}
+
+pub(crate) fn until_next_run(now: DateTime<Utc>, hour_utc: u32) -> Duration {
+    let today = now
+        .with_hour(hour_utc)
+        .and_then(|at| at.with_minute(0))
+        .and_then(|at| at.with_second(0))
+        .and_then(|at| at.with_nanosecond(0))
+        .unwrap_or(now);
+    let next = if today > now {
+        today
+    } else {
+        today + chrono::Duration::days(1)
+    };
+    (next - now).to_std().unwrap_or_default()
+}
+
+/// Extracts durable facts from user messages the planner never made a memory
+/// decision for: ones from before the planner existed or from turns where it
+/// fell back. Messages from guilds without memory consent are skipped. Only
+/// keys the user has no fact for yet, and did not just delete, are written,
+/// so planner-written facts always win. Each user's progress is recorded as
+/// a [`BACKFILL_PLANNER`] decision, so messages are processed once.
+pub async fn run_fact_backfill(
+    model: &dyn ModelProvider,
+    store: &dyn MemoryStore,
+    guild_settings: &GuildSettingsCache,
+    config: &FactBackfillConfig,
+    now: DateTime<Utc>,
+) -> anyhow::Result<BackfillReport> {
+    let mut report = BackfillReport::default();
+    let mut consent = HashMap::new();
+    for user in store.list_users(USER_SCAN_LIMIT).await? {
+        let mut pending = Vec::new();
+        for message in unplanned_messages(store, &user.user_id, config, now).await? {
+            if has_memory_consent(guild_settings, &mut consent, &message.guild_id).await {
+                pending.push(message);
+            }
+        }
+        if pending.is_empty() {
+            continue;
+        }
+        report.users += 1;
+        // Deleted facts count as known, so the backfill does not bring them
+        // back.
+        let mut known_keys = store
+            .list_facts(&user.user_id, FACT_SCAN_LIMIT)
+            .await?
+            .into_iter()
+            .map(|fact| fact.key)
+            .chain(store.list_deleted_fact_keys(&user.user_id).await?)
+            .collect::<HashSet<_>>();
+        for batch in pending.chunks(config.batch_size.max(1)) {
+            let written = match extract_facts(model, store, config, batch, &mut known_keys).await {
+                Ok(written) => written,
+                Err(error) => {
+                    // Progress stays where it was, so the batch is retried.
+                    warn!(?error, user_id = %user.user_id, "fact backfill batch failed");
+                    break;
+                }
+            };
+            report.messages += batch.len();
+            report.facts += written.len();
+            let through = batch.last().map_or(now, |message| message.timestamp);
+            store
+                .record_planner_decision(PlannerDecisionRecord {
+                    id: String::new(),
+                    user_id: user.user_id.clone(),
+                    guild_id: String::new(),
+                    channel_id: String::new(),
+                    planner: BACKFILL_PLANNER.to_owned(),
+                    decision: BACKFILL_DECISION.to_owned(),
+                    rationale: format!("{} facts from {} messages", written.len(), batch.len()),
+                    payload_json: json!({
+                        "through": through.to_rfc3339(),
+                        "messages": batch.len(),
+                        "facts": written,
+                    })
+                    .to_string(),
+                    success: true,
+                    error: None,
+                    timestamp: now,
+                })
+                .await?;
+        }
+    }
+    Ok(report)
+}
+
+/// Whether the guild lets the companion remember what is said in it; a failed
+/// lookup counts as no.
+async fn has_memory_consent(
+    guild_settings: &GuildSettingsCache,
+    consent: &mut HashMap<String, bool>,
+    guild_id: &str,
+) -> bool {
+    if let Some(allowed) = consent.get(guild_id) {
+        return *allowed;
+    }
+    let allowed = match guild_settings.get(guild_id).await {
+        Ok(settings) => settings.memory_consent_default,
+        Err(error) => {
+            warn!(
+                ?error,
+                guild_id, "failed to load guild settings for fact backfill"
+            );
+            false
+        }
+    };
+    consent.insert(guild_id.to_owned(), allowed);
+    allowed
+}
+
+/// The user's messages after their backfill progress that no unified planner
+/// decision applied a plan to, oldest first.
+async fn unplanned_messages(
+    store: &dyn MemoryStore,
+    user_id: &str,
+    config: &FactBackfillConfig,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<ChatMessageRecord>> {
+    let payload = |decision: &PlannerDecisionRecord| {
+        serde_json::from_str::<Value>(&decision.payload_json).unwrap_or_default()
+    };
+    let backfilled_through = store
+        .list_planner_decisions_of_kind(
+            user_id,
+            BACKFILL_PLANNER,
+            BACKFILL_DECISION,
+            DECISION_SCAN_LIMIT,
+        )
+        .await?
+        .iter()
+        .filter_map(|decision| {
+            payload(decision)["through"]
+                .as_str()
+                .and_then(|through| DateTime::parse_from_rfc3339(through).ok())
+                .map(|through| through.with_timezone(&Utc))
+        })
+        .max();
+    let planned = store
+        .list_planner_decisions_of_kind(user_id, "unified", "apply_plan", DECISION_SCAN_LIMIT)
+        .await?
+        .iter()
+        .filter_map(|decision| payload(decision)["user_input"].as_str().map(str::to_owned))
+        .collect::<HashSet<_>>();
+
+    let mut messages = store
+        .list_chat_messages(user_id, MESSAGE_SCAN_LIMIT)
+        .await?
+        .into_iter()
+        .filter(|message| {
+            message.role == ChatRole::User
+                && message.timestamp < now - SETTLE_TIME
+                && backfilled_through.is_none_or(|through| message.timestamp > through)
+                && !message.content.trim().is_empty()
+                && !message.content.trim_start().starts_with('/')
+                && !planned.contains(&message.content)
+        })
+        .collect::<Vec<_>>();
+    messages.sort_by_key(|message| message.timestamp);
+    messages.truncate(config.max_messages_per_user);
+    Ok(messages)
+}
+
+/// Asks the model for the durable facts in `batch` and writes the new ones.
+/// Returns the keys written.
+async fn extract_facts(
+    model: &dyn ModelProvider,
+    store: &dyn MemoryStore,
+    config: &FactBackfillConfig,
+    batch: &[ChatMessageRecord],
+    known_keys: &mut HashSet<String>,
+) -> anyhow::Result<Vec<String>> {
+    let Some(first) = batch.first() else {
+        return Ok(Vec::new());
+    };
+    let messages = batch
+        .iter()
+        .map(|message| {
+            format!(
+                "- [{}] {}",
+                message.timestamp.format("%Y-%m-%d"),
+                message.content.replace('\n', " ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut known = known_keys.iter().cloned().collect::<Vec<_>>();
+    known.sort();
+    let raw = model
+        .complete(ModelRequest {
+            system_prompt: BACKFILL_PROMPT.to_owned(),
+            user_prompt: format!(
+                "Keys already known (do not repeat them): {}\n\nMessages:\n{messages}",
+                if known.is_empty() {
+                    "none".to_owned()
+                } else {
+                    known.join(", ")
+                }
+            ),
+            ..ModelRequest::default()
+        })
+        .await?;
+    let extracted = parse_json_plan::<ExtractedFacts>(&raw)?;
+
+    let mut written = Vec::new();
+    for extracted in extracted.facts {
+        if extracted.confidence < config.min_confidence {
+            continue;
+        }
+        let Some(mut fact) = proposed_fact(&extracted.key, &extracted.value, extracted.confidence)
+        else {
+            continue;
+        };
+        if !known_keys.insert(fact.key.clone()) {
+            continue;
+        }
+        fact.source = BACKFILL_SOURCE.to_owned();
+        let key = fact.key.clone();
+        if config.approval_queue {
+            store
+                .queue_pending_fact(PendingFactRecord {
+                    id: String::new(),
+                    user_id: first.user_id.clone(),
+                    guild_id: first.guild_id.clone(),
+                    channel_id: first.channel_id.clone(),
+                    fact,
+                    rationale: "fact_backfill".to_owned(),
+                    created_at: Utc::now(),
+                })
+                .await?;
+        } else {
+            store.upsert_fact(&first.user_id, fact).await?;
+        }
+        written.push(key);
+    }
+    Ok(written)
+}
+
+const BACKFILL_PROMPT: &str = "You extract durable personal facts from a user's old chat messages for a companion's long-term memory. Only keep facts that stay true for months: name, pronouns, location, time zone, job, family, pets, long-term goals, strong preferences. Skip moods, plans for the day, questions and anything said about other people's private details. Reply with JSON only, shaped {\"facts\": [{\"key\": \"snake_case_key\", \"value\": \"short value\", \"confidence\": 0.0-1.0}]}, with an empty list when nothing qualifies. When messages disagree, use the latest one.";
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{
+        guild_settings::GuildSettings,
+        memory::{InMemoryMemoryStore, IntoDynMemoryStore},
+        types::Modality,
+    };
+
+    fn guild_settings(store: &Arc<InMemoryMemoryStore>) -> GuildSettingsCache {
+        GuildSettingsCache::new(
+            IntoDynMemoryStore::into_dyn(store.clone()),
+            GuildSettings::default(),
+            Duration::from_secs(60),
+        )
+    }
+
+    fn message(
+        user_id: &str,
+        guild_id: &str,
+        content: &str,
+        at: DateTime<Utc>,
+    ) -> ChatMessageRecord {
+        ChatMessageRecord {
+            id: String::new(),
+            user_id: user_id.to_owned(),
+            guild_id: guild_id.to_owned(),
+            channel_id: "c1".to_owned(),
+            role: ChatRole::User,
+            content: content.to_owned(),
+            timestamp: at,
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        }
+    }
+
+    /// Finds the user's dog in whatever it is given.
+    struct DogExtractor;
+
+    #[async_trait]
+    impl ModelProvider for DogExtractor {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            let facts = if request.user_prompt.contains("my dog Rex") {
+                json!([
+                    {"key": "Dog Name", "value": "Rex.", "confidence": 0.9},
+                    {"key": "mood", "value": "tired", "confidence": 0.3}
+                ])
+            } else {
+                json!([])
+            };
+            Ok(json!({ "facts": facts }).to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn backfills_unplanned_messages_once() {
+        let store = Arc::new(InMemoryMemoryStore::default());
+        let settings = guild_settings(&store);
+        let now = Utc::now();
+        let message = |content: &str, minutes_ago: i64| {
+            message(
+                "u1",
+                "g1",
+                content,
+                now - chrono::Duration::minutes(minutes_ago),
+            )
+        };
+        store
+            .record_chat_message(message("I walked my dog Rex today", 120))
+            .await
+            .expect("recorded");
+        store
+            .record_chat_message(message("planned already", 90))
+            .await
+            .expect("recorded");
+        store
+            .record_planner_decision(PlannerDecisionRecord {
+                id: String::new(),
+                user_id: "u1".to_owned(),
+                guild_id: "g1".to_owned(),
+                channel_id: "c1".to_owned(),
+                planner: "unified".to_owned(),
+                decision: "apply_plan".to_owned(),
+                rationale: String::new(),
+                payload_json: json!({ "user_input": "planned already" }).to_string(),
+                success: true,
+                error: None,
+                timestamp: now,
+            })
+            .await
+            .expect("recorded");
+        let config = FactBackfillConfig::default();
+
+        let report = run_fact_backfill(&DogExtractor, store.as_ref(), &settings, &config, now)
+            .await
+            .expect("backfill runs");
+        assert_eq!(
+            report,
+            BackfillReport {
+                users: 1,
+                messages: 1,
+                facts: 1
+            }
+        );
+        let facts = store.list_facts("u1", 10).await.expect("listed");
+        assert_eq!(facts[0].key, "dog_name");
+        assert_eq!(facts[0].value, "Rex");
+        assert_eq!(facts[0].source, BACKFILL_SOURCE);
+
+        let again = run_fact_backfill(&DogExtractor, store.as_ref(), &settings, &config, now)
+            .await
+            .expect("backfill runs");
+        assert_eq!(again, BackfillReport::default());
+
+        let at = |hour| {
+            Utc.with_ymd_and_hms(2026, 10, 1, hour, 30, 0)
+                .single()
+                .expect("valid timestamp")
+        };
+        assert_eq!(until_next_run(at(2), 3), Duration::from_secs(30 * 60));
+        assert_eq!(
+            until_next_run(at(4), 3),
+            Duration::from_secs(22 * 60 * 60 + 30 * 60)
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_guilds_without_consent_and_deleted_facts() {
+        let store = Arc::new(InMemoryMemoryStore::default());
+        let settings = guild_settings(&store);
+        let now = Utc::now();
+        let an_hour_ago = now - chrono::Duration::hours(1);
+        let mut private = GuildSettings::new("g2");
+        private.memory_consent_default = false;
+        store.put_guild_settings(private).await.expect("stored");
+        store
+            .record_chat_message(message(
+                "u1",
+                "g2",
+                "I walked my dog Rex today",
+                an_hour_ago,
+            ))
+            .await
+            .expect("recorded");
+
+        store
+            .upsert_fact(
+                "u2",
+                proposed_fact("dog_name", "Rex", 0.9).expect("valid fact"),
+            )
+            .await
+            .expect("stored");
+        store.delete_fact("u2", "dog_name").await.expect("deleted");
+        store
+            .record_chat_message(message(
+                "u2",
+                "g1",
+                "I walked my dog Rex today",
+                an_hour_ago,
+            ))
+            .await
+            .expect("recorded");
+
+        let report = run_fact_backfill(
+            &DogExtractor,
+            store.as_ref(),
+            &settings,
+            &FactBackfillConfig::default(),
+            now,
+        )
+        .await
+        .expect("backfill runs");
+        assert_eq!(
+            report,
+            BackfillReport {
+                users: 1,
+                messages: 1,
+                facts: 0
+            }
+        );
+        assert!(store.list_facts("u1", 10).await.expect("listed").is_empty());
+        assert!(store.list_facts("u2", 10).await.expect("listed").is_empty());
+    }
+}
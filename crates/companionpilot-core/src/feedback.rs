@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{Value, json};
+
+use crate::types::{ChatMessageRecord, ChatRole, MessageFeedbackRecord};
+
+/// Reaction that marks a companion reply as good.
+pub const FEEDBACK_UP_EMOJI: &str = "👍";
+/// Reaction that marks a companion reply as bad.
+pub const FEEDBACK_DOWN_EMOJI: &str = "👎";
+
+/// The feedback score a reaction gives, or `None` for other reactions.
+pub fn feedback_score(emoji: &str) -> Option<i32> {
+    match emoji {
+        FEEDBACK_UP_EMOJI => Some(1),
+        FEEDBACK_DOWN_EMOJI => Some(-1),
+        _ => None,
+    }
+}
+
+/// Renders conversations as OpenAI chat fine-tuning JSONL, one conversation
+/// per line. Messages are grouped by session (by channel when they have
+/// none). With `min_feedback`, only conversations whose replies' feedback
+/// adds up to at least that much are kept. Disliked replies stay in for
+/// context but get `"weight": 0`, so they are not learned from.
+pub fn render_finetune_jsonl(
+    messages: &[ChatMessageRecord],
+    feedback: &[MessageFeedbackRecord],
+    min_feedback: Option<i32>,
+    system_prompt: &str,
+) -> String {
+    let scores = feedback
+        .iter()
+        .map(|feedback| (feedback.message_id.as_str(), feedback.score))
+        .collect::<HashMap<_, _>>();
+    let mut conversations = BTreeMap::<String, Vec<&ChatMessageRecord>>::new();
+    for message in messages {
+        let key = match &message.session_id {
+            Some(session_id) => format!("session:{session_id}"),
+            None => format!("channel:{}", message.channel_id),
+        };
+        conversations.entry(key).or_default().push(message);
+    }
+
+    let mut lines = conversations
+        .into_values()
+        .filter_map(|mut conversation| {
+            conversation.sort_by_key(|message| message.timestamp);
+            // Fine-tuning examples open with the user and end on a reply.
+            let start = conversation
+                .iter()
+                .position(|message| message.role == ChatRole::User)?;
+            let end = conversation
+                .iter()
+                .rposition(|message| message.role == ChatRole::Assistant)?;
+            let conversation = conversation.get(start..=end)?;
+            let score = conversation
+                .iter()
+                .filter_map(|message| scores.get(message.id.as_str()))
+                .sum::<i32>();
+            if min_feedback.is_some_and(|min_feedback| score < min_feedback) {
+                return None;
+            }
+            let first_at = conversation.first()?.timestamp;
+            let mut chat = vec![json!({ "role": "system", "content": system_prompt })];
+            chat.extend(conversation.iter().map(|message| {
+                finetune_message(message, scores.get(message.id.as_str()).copied())
+            }));
+            Some((first_at, json!({ "messages": chat }).to_string()))
+        })
+        .collect::<Vec<_>>();
+    lines.sort_by_key(|(first_at, _)| *first_at);
+    lines.into_iter().map(|(_, line)| line + "\n").collect()
+}
+
+fn finetune_message(message: &ChatMessageRecord, score: Option<i32>) -> Value {
+    let mut entry = json!({ "role": message.role.as_str(), "content": message.content });
+    if message.role == ChatRole::Assistant && score.is_some_and(|score| score < 0) {
+        entry["weight"] = json!(0);
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::*;
+    use crate::types::Modality;
+
+    #[test]
+    fn exports_liked_conversations_as_chat_jsonl() {
+        let start = Utc
+            .with_ymd_and_hms(2026, 10, 1, 12, 0, 0)
+            .single()
+            .expect("valid timestamp");
+        let message = |id: &str, session: &str, role, content: &str| ChatMessageRecord {
+            id: id.to_owned(),
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            role,
+            content: content.to_owned(),
+            timestamp: start + Duration::minutes(id.parse().expect("numeric id")),
+            modality: Modality::Text,
+            session_id: Some(session.to_owned()),
+            pinned: false,
+            source_message_id: None,
+        };
+        let messages = vec![
+            message("1", "s1", ChatRole::User, "hi"),
+            message("2", "s1", ChatRole::Assistant, "hello!"),
+            message("3", "s1", ChatRole::User, "tell me a joke"),
+            message("4", "s1", ChatRole::Assistant, "no"),
+            message("5", "s2", ChatRole::User, "what is rust?"),
+            message("6", "s2", ChatRole::Assistant, "a language"),
+            message("7", "s2", ChatRole::User, "thanks"),
+        ];
+        let feedback = [("2", 1), ("4", -1), ("6", 1)]
+            .map(|(message_id, score)| MessageFeedbackRecord {
+                user_id: "u1".to_owned(),
+                message_id: message_id.to_owned(),
+                score,
+                updated_at: start,
+            })
+            .to_vec();
+
+        let all = render_finetune_jsonl(&messages, &feedback, None, "Be kind.");
+        let lines = all.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let first = serde_json::from_str::<Value>(lines[0]).expect("valid json");
+        assert_eq!(first["messages"][0]["content"], "Be kind.");
+        assert_eq!(first["messages"][4]["content"], "no");
+        assert_eq!(first["messages"][4]["weight"], 0);
+        let second = serde_json::from_str::<Value>(lines[1]).expect("valid json");
+        // The trailing user message has no reply to learn from.
+        assert_eq!(second["messages"].as_array().map(Vec::len), Some(3));
+
+        let liked = render_finetune_jsonl(&messages, &feedback, Some(1), "Be kind.");
+        assert_eq!(liked.lines().count(), 1);
+        assert!(liked.contains("what is rust?"));
+        assert_eq!(feedback_score("👍"), Some(1));
+        assert_eq!(feedback_score("📌"), None);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+use crate::prompt_rollout::PromptVariant;
+
+/// Header carrying per-request feature flags on `/chat`.
+pub const FLAGS_HEADER: &str = "x-cp-flags";
+
+/// Experimental behaviors toggled for a single turn, so they can be compared
+/// live without changing the deployment's configuration. Parsed from a
+/// comma-separated list such as `speculative=off, model=openai/gpt-4o-mini`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TurnFlags {
+    /// Overrides `speculative_synthesis`.
+    pub speculative_synthesis: Option<bool>,
+    /// Overrides whether the planner prompt includes few-shot examples.
+    pub planner_examples: Option<bool>,
+    /// Model for the planners and the reply instead of the configured one.
+    pub model: Option<String>,
+    /// Prompt of a persona under rollout, instead of the user's assigned one.
+    /// Set by the orchestrator for every turn in a rollout.
+    pub prompt: Option<PromptVariant>,
+}
+
+impl TurnFlags {
+    /// Parses `name=value` pairs; a bare `name` means `on`. Unknown flags and
+    /// invalid values are errors, so a typo does not silently test nothing.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut flags = Self::default();
+        for entry in raw
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, value) = entry
+                .split_once('=')
+                .map_or((entry, "on"), |(name, value)| (name.trim(), value.trim()));
+            match name.to_ascii_lowercase().as_str() {
+                "speculative" => flags.speculative_synthesis = Some(parse_switch(name, value)?),
+                "planner_examples" => flags.planner_examples = Some(parse_switch(name, value)?),
+                "model" if !value.is_empty() && value != "on" => {
+                    flags.model = Some(value.to_owned());
+                }
+                "model" => {
+                    return Err(
+                        "flag `model` needs a model id, e.g. model=openai/gpt-4o-mini".to_owned(),
+                    );
+                }
+                "prompt" => {
+                    flags.prompt = Some(PromptVariant::parse(value).ok_or_else(|| {
+                        format!("flag `prompt` expects stable or candidate, got `{value}`")
+                    })?);
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown flag `{name}`; expected speculative, planner_examples, model or prompt"
+                    ));
+                }
+            }
+        }
+        Ok(flags)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn parse_switch(name: &str, value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(format!("flag `{name}` expects on or off, got `{value}`")),
+    }
+}
+
+/// The canonical `name=value` list, as recorded in planner logs.
+impl fmt::Display for TurnFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let switch = |on: bool| if on { "on" } else { "off" };
+        let mut entries = Vec::new();
+        if let Some(on) = self.speculative_synthesis {
+            entries.push(format!("speculative={}", switch(on)));
+        }
+        if let Some(on) = self.planner_examples {
+            entries.push(format!("planner_examples={}", switch(on)));
+        }
+        if let Some(model) = &self.model {
+            entries.push(format!("model={model}"));
+        }
+        if let Some(prompt) = self.prompt {
+            entries.push(format!("prompt={}", prompt.as_str()));
+        }
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TurnFlags;
+
+    #[test]
+    fn parses_flags_and_rejects_unknown_ones() {
+        let flags = TurnFlags::parse("speculative=off, planner_examples, model=openai/gpt-4o-mini")
+            .expect("valid flags");
+        assert_eq!(flags.speculative_synthesis, Some(false));
+        assert_eq!(flags.planner_examples, Some(true));
+        assert_eq!(flags.model.as_deref(), Some("openai/gpt-4o-mini"));
+        assert_eq!(
+            flags.to_string(),
+            "speculative=off,planner_examples=on,model=openai/gpt-4o-mini"
+        );
+
+        assert!(TurnFlags::parse("").expect("empty").is_empty());
+        assert!(TurnFlags::parse("speculative=maybe").is_err());
+        assert!(TurnFlags::parse("turbo").is_err());
+        assert_eq!(
+            TurnFlags::parse("prompt=candidate")
+                .expect("valid flags")
+                .to_string(),
+            "prompt=candidate"
+        );
+        assert!(TurnFlags::parse("prompt").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+};
+
+use regex::{Captures, Regex};
+
+/// Custom emoji of a guild shown to the model per turn.
+pub const EMOJI_SAMPLE_SIZE: usize = 12;
+
+/// `<:name:id>` / `<a:name:id>` codes and bare `:name:` shortcodes, with
+/// the space before them so removing one leaves no double space.
+static EMOJI_CODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"( ?)(?:<a?:([A-Za-z0-9_]{2,32}):(\d+)>|:([A-Za-z0-9_]{2,32}):)")
+        .expect("valid emoji regex")
+});
+
+/// A custom emoji the bot can use in a guild.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuildEmoji {
+    pub id: u64,
+    pub name: String,
+    pub animated: bool,
+}
+
+impl GuildEmoji {
+    /// The code Discord renders as the emoji.
+    pub fn code(&self) -> String {
+        let prefix = if self.animated { "a" } else { "" };
+        format!("<{prefix}:{}:{}>", self.name, self.id)
+    }
+}
+
+/// Custom emoji per guild, filled by the Discord adapter as guilds become
+/// available and their emoji change.
+#[derive(Debug, Default)]
+pub struct GuildEmojiCatalog {
+    guilds: RwLock<HashMap<String, Vec<GuildEmoji>>>,
+}
+
+impl GuildEmojiCatalog {
+    pub fn set(&self, guild_id: &str, mut emoji: Vec<GuildEmoji>) {
+        emoji.sort_by(|left, right| left.name.cmp(&right.name));
+        self.guilds
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(guild_id.to_owned(), emoji);
+    }
+
+    pub fn get(&self, guild_id: &str) -> Vec<GuildEmoji> {
+        self.guilds
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// System prompt section offering a sample of the guild's emoji. The
+    /// sample rotates with `seed`, so different turns see different emoji.
+    pub fn prompt_block(&self, guild_id: &str, seed: &str) -> Option<String> {
+        let emoji = self.get(guild_id);
+        if emoji.is_empty() {
+            return None;
+        }
+        let start = seed.bytes().fold(0_usize, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(usize::from(byte))
+        }) % emoji.len();
+        let sample = emoji
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(EMOJI_SAMPLE_SIZE.min(emoji.len()))
+            .map(|emoji| format!(":{}:", emoji.name))
+            .collect::<Vec<_>>();
+        Some(format!(
+            "This server has custom emoji. You may use one or two where they fit the tone by writing their `:name:`; do not use others or make names up: {}",
+            sample.join(" ")
+        ))
+    }
+}
+
+/// Turns `:name:` shortcodes of the guild's emoji into codes Discord
+/// renders, and removes shortcodes and emoji codes that would show up as
+/// broken text. Code spans are left alone.
+pub fn render_guild_emoji(text: &str, emoji: &[GuildEmoji]) -> String {
+    let find = |name: &str| {
+        emoji.iter().find(|emoji| emoji.name == name).or_else(|| {
+            emoji
+                .iter()
+                .find(|emoji| emoji.name.eq_ignore_ascii_case(name))
+        })
+    };
+    text.split('`')
+        .enumerate()
+        .map(|(index, part)| {
+            if index % 2 == 1 {
+                return part.to_owned();
+            }
+            EMOJI_CODE
+                .replace_all(part, |captures: &Captures<'_>| {
+                    let space = &captures[1];
+                    if let Some(id) = captures.get(3) {
+                        let known = emoji
+                            .iter()
+                            .any(|emoji| emoji.id.to_string() == id.as_str());
+                        return match find(&captures[2]) {
+                            _ if known => captures[0].to_owned(),
+                            Some(emoji) => format!("{space}{}", emoji.code()),
+                            None => String::new(),
+                        };
+                    }
+                    let name = &captures[4];
+                    // Only a standalone `:name:` is a shortcode, not part of
+                    // a path like `std::fmt::Display` or of `x:word:y`.
+                    let whole = captures.get(0).expect("whole match");
+                    let joined = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':';
+                    if (space.is_empty() && part[..whole.start()].ends_with(joined))
+                        || part[whole.end()..].starts_with(joined)
+                    {
+                        return captures[0].to_owned();
+                    }
+                    match find(name) {
+                        Some(emoji) => format!("{space}{}", emoji.code()),
+                        // Digits only is a time like 12:30:45, not an emoji.
+                        None if name.bytes().all(|byte| byte.is_ascii_digit()) => {
+                            captures[0].to_owned()
+                        }
+                        None => String::new(),
+                    }
+                })
+                .into_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_known_emoji_and_drops_broken_codes() {
+        let catalog = GuildEmojiCatalog::default();
+        catalog.set(
+            "g1",
+            vec![
+                GuildEmoji {
+                    id: 11,
+                    name: "pog".to_owned(),
+                    animated: false,
+                },
+                GuildEmoji {
+                    id: 12,
+                    name: "catjam".to_owned(),
+                    animated: true,
+                },
+            ],
+        );
+        let block = catalog.prompt_block("g1", "m1").expect("guild has emoji");
+        assert!(block.contains(":pog:") && block.contains(":catjam:"));
+        assert!(catalog.prompt_block("g2", "m1").is_none());
+
+        let emoji = catalog.get("g1");
+        assert_eq!(
+            render_guild_emoji("That's huge :pog: :CatJam:", &emoji),
+            "That's huge <:pog:11> <a:catjam:12>"
+        );
+        assert_eq!(
+            render_guild_emoji("Nice :smile: see you at 12:30:45 <:gone:99>", &emoji),
+            "Nice see you at 12:30:45"
+        );
+        assert_eq!(
+            render_guild_emoji("Write `:pog:` for <:pog:11>", &emoji),
+            "Write `:pog:` for <:pog:11>"
+        );
+        for untouched in [
+            "Implement std::fmt::Display for it.",
+            "Keys like x:pog:y stay.",
+            "Ratio 1:pog:2 and a:b:c too.",
+        ] {
+            assert_eq!(render_guild_emoji(untouched, &emoji), untouched);
+        }
+        assert_eq!(
+            render_guild_emoji("(:pog:) and :pog:!", &emoji),
+            "(<:pog:11>) and <:pog:11>!"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    memory::MemoryStore, notifications::QuietHours, routing::ModelRoutes, timezone::parse_timezone,
+};
+
+/// When the Discord adapter answers messages in a guild. DMs always get a
+/// reply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivationRules {
+    /// Channels the bot answers in; empty means every channel.
+    #[serde(default, alias = "active_channel_ids")]
+    pub channel_ids: Vec<String>,
+    /// Only answer messages that mention the bot.
+    #[serde(default)]
+    pub require_mention: bool,
+}
+
+impl ActivationRules {
+    pub fn is_channel_active(&self, channel_id: &str) -> bool {
+        self.channel_ids.is_empty() || self.channel_ids.iter().any(|id| id == channel_id)
+    }
+
+    pub fn should_respond(&self, channel_id: &str, mentioned: bool) -> bool {
+        self.is_channel_active(channel_id) && (mentioned || !self.require_mention)
+    }
+}
+
+/// How tool citations are shown under a Discord reply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CitationStyle {
+    /// Citations are only kept in logs and the API response.
+    #[default]
+    Off,
+    /// Numbered source list under the reply.
+    Footnotes,
+    /// One line of links with embeds suppressed.
+    Compact,
+}
+
+impl CitationStyle {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Self::Off),
+            "footnotes" => Some(Self::Footnotes),
+            "compact" => Some(Self::Compact),
+            _ => None,
+        }
+    }
+
+    pub fn render(self, text: &str, citations: &[String]) -> String {
+        if citations.is_empty() {
+            return text.to_owned();
+        }
+        match self {
+            Self::Off => text.to_owned(),
+            Self::Footnotes => {
+                let sources = citations
+                    .iter()
+                    .enumerate()
+                    .map(|(index, url)| format!("[{}] <{url}>", index + 1))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{text}\n\nSources:\n{sources}")
+            }
+            Self::Compact => {
+                let links = citations
+                    .iter()
+                    .map(|url| format!("<{url}>"))
+                    .collect::<Vec<_>>()
+                    .join(" · ");
+                format!("{text}\n-# {links}")
+            }
+        }
+    }
+}
+
+/// What the Discord adapter does with messages that mention it during the
+/// guild's quiet hours.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietMentionPolicy {
+    /// Reply as usual.
+    #[default]
+    Reply,
+    /// Reply as usual, with a note that it is quiet hours.
+    Mark,
+    /// Hold the reply until the quiet hours end.
+    Delay,
+}
+
+impl QuietMentionPolicy {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "reply" => Some(Self::Reply),
+            "mark" => Some(Self::Mark),
+            "delay" => Some(Self::Delay),
+            _ => None,
+        }
+    }
+}
+
+/// Per-server behavior. Guilds without a stored record use the defaults
+/// configured through the environment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuildSettings {
+    pub guild_id: String,
+    #[serde(default)]
+    pub activation: ActivationRules,
+    /// Name of an imported persona bundle.
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// Whether members' facts are remembered unless they opt out.
+    #[serde(default = "default_memory_consent")]
+    pub memory_consent_default: bool,
+    /// Tools the planner may use in this server; `None` allows all.
+    #[serde(default)]
+    pub enabled_tools: Option<Vec<String>>,
+    /// Language replies are written in, e.g. `Czech`; the user's own when unset.
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub citation_style: CitationStyle,
+    /// Answer mentions posted while the bot was offline once it is back.
+    #[serde(default)]
+    pub replay_missed_mentions: bool,
+    /// Reply model routes overriding the global ones kind by kind.
+    #[serde(default, skip_serializing_if = "ModelRoutes::is_empty")]
+    pub model_routes: ModelRoutes,
+    /// Hours without proactive posts in this server's channels.
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    /// IANA time zone of `quiet_hours`; UTC when unset.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub quiet_mentions: QuietMentionPolicy,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            guild_id: String::new(),
+            activation: ActivationRules::default(),
+            persona: None,
+            memory_consent_default: true,
+            enabled_tools: None,
+            language: None,
+            citation_style: CitationStyle::default(),
+            replay_missed_mentions: false,
+            model_routes: ModelRoutes::default(),
+            quiet_hours: None,
+            timezone: None,
+            quiet_mentions: QuietMentionPolicy::default(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl GuildSettings {
+    pub fn new(guild_id: impl Into<String>) -> Self {
+        Self {
+            guild_id: guild_id.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(quiet_hours) = &self.quiet_hours {
+            quiet_hours.validate()?;
+        }
+        if let Some(timezone) = &self.timezone
+            && parse_timezone(timezone).is_none()
+        {
+            return Err(format!("unknown time zone `{timezone}`"));
+        }
+        Ok(())
+    }
+
+    /// Parses a stored record, including ones written before activation
+    /// rules existed, which kept the channels in `active_channel_ids`.
+    pub fn from_stored_json(raw: &str) -> serde_json::Result<Self> {
+        let mut record = serde_json::from_str::<serde_json::Value>(raw)?;
+        if let Some(fields) = record.as_object_mut()
+            && !fields.contains_key("activation")
+            && let Some(channel_ids) = fields.remove("active_channel_ids")
+        {
+            fields.insert(
+                "activation".to_owned(),
+                serde_json::json!({ "channel_ids": channel_ids }),
+            );
+        }
+        serde_json::from_value(record)
+    }
+
+    /// [`QuietHours::ends_after`] in the guild's time zone.
+    pub fn quiet_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = self.timezone.as_deref().and_then(parse_timezone);
+        self.quiet_hours?.ends_after(now, tz)
+    }
+}
+
+fn default_memory_consent() -> bool {
+    true
+}
+
+/// Read-through cache over the memory store's guild settings, so per-message
+/// lookups do not hit the database.
+pub struct GuildSettingsCache {
+    store: Arc<dyn MemoryStore>,
+    defaults: GuildSettings,
+    ttl: Duration,
+    entries: RwLock<HashMap<String, (Instant, GuildSettings)>>,
+}
+
+impl GuildSettingsCache {
+    pub fn new(store: Arc<dyn MemoryStore>, defaults: GuildSettings, ttl: Duration) -> Self {
+        Self {
+            store,
+            defaults,
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Settings used for guilds that have not stored their own.
+    pub fn defaults(&self) -> &GuildSettings {
+        &self.defaults
+    }
+
+    /// Stored settings for `guild_id`, or the defaults when none are stored.
+    pub async fn get(&self, guild_id: &str) -> anyhow::Result<GuildSettings> {
+        if let Some((cached_at, settings)) = self
+            .entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(guild_id)
+            && cached_at.elapsed() < self.ttl
+        {
+            return Ok(settings.clone());
+        }
+
+        let settings = self
+            .store
+            .get_guild_settings(guild_id)
+            .await?
+            .unwrap_or_else(|| GuildSettings {
+                guild_id: guild_id.to_owned(),
+                ..self.defaults.clone()
+            });
+        self.remember(settings.clone());
+        Ok(settings)
+    }
+
+    pub async fn put(&self, mut settings: GuildSettings) -> anyhow::Result<GuildSettings> {
+        settings.updated_at = Utc::now();
+        self.store.put_guild_settings(settings.clone()).await?;
+        self.remember(settings.clone());
+        Ok(settings)
+    }
+
+    fn remember(&self, settings: GuildSettings) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(settings.guild_id.clone(), (Instant::now(), settings));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{CitationStyle, GuildSettings, GuildSettingsCache};
+    use crate::memory::{InMemoryMemoryStore, MemoryStore};
+
+    #[tokio::test]
+    async fn falls_back_to_defaults_and_caches_writes() {
+        let store = Arc::new(InMemoryMemoryStore::default());
+        let defaults = GuildSettings {
+            language: Some("Czech".to_owned()),
+            ..GuildSettings::default()
+        };
+        let cache = GuildSettingsCache::new(store.clone(), defaults, Duration::from_secs(60));
+
+        let settings = cache.get("guild-1").await.expect("defaults");
+        assert_eq!(settings.guild_id, "guild-1");
+        assert_eq!(settings.language.as_deref(), Some("Czech"));
+
+        cache
+            .put(GuildSettings {
+                citation_style: CitationStyle::Footnotes,
+                ..settings
+            })
+            .await
+            .expect("stored");
+        assert_eq!(
+            cache.get("guild-1").await.expect("cached").citation_style,
+            CitationStyle::Footnotes
+        );
+        assert!(
+            store
+                .get_guild_settings("guild-1")
+                .await
+                .expect("store read")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn reads_channels_stored_before_activation_rules() {
+        let settings =
+            GuildSettings::from_stored_json(r#"{"guild_id":"g1","active_channel_ids":["42"]}"#)
+                .expect("legacy record");
+        assert_eq!(settings.activation.channel_ids, vec!["42".to_owned()]);
+        let settings = GuildSettings::from_stored_json(
+            r#"{"guild_id":"g1","activation":{"active_channel_ids":["43"]}}"#,
+        )
+        .expect("aliased record");
+        assert_eq!(settings.activation.channel_ids, vec!["43".to_owned()]);
+    }
+
+    #[test]
+    fn renders_citation_styles() {
+        let citations = vec!["https://a.example".to_owned()];
+        assert_eq!(CitationStyle::Off.render("Hi", &citations), "Hi");
+        assert_eq!(
+            CitationStyle::Footnotes.render("Hi", &citations),
+            "Hi\n\nSources:\n[1] <https://a.example>"
+        );
+    }
+}
@@ -1,28 +1,89 @@
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use axum::{
     Json, Router,
-    extract::{Path, Query, State},
-    http::header,
-    response::IntoResponse,
-    routing::{delete, get, post},
+    extract::{ConnectInfo, Extension, OriginalUri, Path, Query, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, Uri, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use tower_http::trace::TraceLayer;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    trace::TraceLayer,
+};
+use tracing::{Span, warn};
 
 use crate::{
-    memory::MemoryStore,
-    orchestrator::DefaultChatOrchestrator,
-    types::{MessageCtx, OrchestratorReply},
+    activity::ActivitySnapshot,
+    audit::{
+        ACTOR_HEADER, ANONYMOUS_ACTOR, AuditLogQuery, command_target, guild_target, persona_target,
+        user_target,
+    },
+    calendar::{CalendarFeeds, feed_salt, rotate_feed_salt, user_calendar},
+    capabilities::Capabilities,
+    concurrency::{BUSY_REPLY_TEXT, is_busy},
+    config::HttpConfig,
+    context_window::ContextSurface,
+    custom_commands::{CustomCommand, CustomCommands},
+    events::{EventEffect, EventError, EventMappings, ExternalEvent},
+    feedback::render_finetune_jsonl,
+    flags::{FLAGS_HEADER, TurnFlags},
+    guild_settings::GuildSettings,
+    memory::{FactEdit, FactEditError, FactEditSummary, MemoryStore, undo_cutoff},
+    model::{GenerationParams, KeyProvider},
+    model_probe::ModelProbeStats,
+    notifications::{NotificationPrefs, Notifications},
+    orchestrator::{ChatOrchestrator, TurnOptions, default_system_prompt_base},
+    personas::{PersonaBundle, PersonaRegistry},
+    planner_replay::{PlannerReplay, PlannerReplayError},
+    prompt_rollout::{PromptRollout, PromptRollouts},
+    proxy::{TrustedProxies, client_ip, normalize_base_path, split_list},
+    rate_limit::{RateLimited, retry_after_secs},
+    reply_filters::ReplyFilterContext,
+    response_format::{ResponseFormat, ResponseFormatError, check_response_schema},
+    tools::{ToolSpec, builtin_tool_specs},
+    transcript::{TranscriptFormat, render_transcript},
+    types::{
+        AuditLogRecord, BackgroundTaskRecord, ChatSession, MemoryFact, MessageAttachment,
+        MessageCtx, OrchestratorReply, PendingFactRecord, SystemNoticeRecord, TurnFailure,
+        UserApiKeyRecord, content_with_attachments,
+    },
+    widgets::{WIDGET_MAX_MESSAGE_CHARS, WIDGET_TOOLS, WidgetRegistry},
 };
 
 static DASHBOARD_HTML: &str = include_str!("dashboard.html");
+static WIDGET_HTML: &str = include_str!("widget.html");
+
+/// Header carrying a chat widget's token.
+const WIDGET_TOKEN_HEADER: &str = "x-widget-token";
+
+/// Seconds clients may reuse responses that only change with a deploy.
+const STATIC_INFO_MAX_AGE_SECS: u64 = 300;
+const HEALTH_MAX_AGE_SECS: u64 = 5;
+/// Largest response body [`etag_cache`] buffers to hash.
+const MAX_ETAG_BODY_BYTES: usize = 1024 * 1024;
+
+/// Most requests one `POST /chat/batch` call may carry.
+const MAX_BATCH_REQUESTS: usize = 32;
+/// How many batch items run through the orchestrator at once.
+const BATCH_CONCURRENCY: usize = 4;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub orchestrator: Arc<DefaultChatOrchestrator>,
+    pub orchestrator: Arc<dyn ChatOrchestrator>,
     pub memory: Arc<dyn MemoryStore>,
+    pub personas: Arc<PersonaRegistry>,
+    pub widgets: Arc<WidgetRegistry>,
+    pub event_mappings: Arc<EventMappings>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +94,204 @@ pub struct ChatRequest {
     #[serde(default = "default_channel")]
     pub channel_id: String,
     pub content: String,
+    /// Name of an imported persona bundle to answer as.
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// Per-request generation overrides (`model`, `temperature`, `top_p`,
+    /// `max_tokens`, `stop`).
+    #[serde(default, flatten)]
+    pub generation: GenerationParams,
+    /// `json` makes the reply machine-readable; see `response_schema`.
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// JSON Schema the reply must satisfy in `json` mode; any JSON if omitted.
+    #[serde(default)]
+    pub response_schema: Option<Value>,
+    /// Serve tool calls from the scripted simulation outputs.
+    #[serde(default)]
+    pub simulate_tools: bool,
+    /// Files the message refers to, e.g. screenshots for `ocr_image`.
+    #[serde(default)]
+    pub attachments: Vec<MessageAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WidgetChatRequest {
+    /// Random id the widget page keeps per browser.
+    pub visitor_id: String,
+    pub content: String,
+}
+
+/// What a public widget gets back: the reply without tool or timing details.
+#[derive(Debug, Serialize)]
+pub struct WidgetChatReply {
+    pub text: String,
+    pub citations: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatBatchRequest {
+    pub requests: Vec<ChatRequest>,
+}
+
+/// Outcome of one batch item; exactly one of `reply` and `error` is set.
+#[derive(Debug, Serialize)]
+pub struct ChatBatchItem {
+    pub index: usize,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply: Option<OrchestratorReply>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Where the turn failed, when it got that far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TurnFailure>,
+}
+
+/// A failed chat request. Turns that fail part-way carry the phase and
+/// timings, and are answered with a JSON body
+/// `{"error": ..., "failure": {"phase": ..., "tools": [...], "timings": {...}}}`;
+/// other errors are plain text.
+#[derive(Debug)]
+pub struct ChatError {
+    pub status: axum::http::StatusCode,
+    pub message: String,
+    pub failure: Option<TurnFailure>,
+}
+
+impl From<(axum::http::StatusCode, String)> for ChatError {
+    fn from((status, message): (axum::http::StatusCode, String)) -> Self {
+        Self {
+            status,
+            message,
+            failure: None,
+        }
+    }
+}
+
+impl ChatError {
+    fn from_orchestration(error: anyhow::Error) -> Self {
+        let failure = error.downcast_ref::<TurnFailure>().cloned();
+        let (status, message) = orchestration_error(error);
+        Self {
+            status,
+            message,
+            failure,
+        }
+    }
+}
+
+impl IntoResponse for ChatError {
+    fn into_response(self) -> Response {
+        match self.failure {
+            Some(failure) => (
+                self.status,
+                Json(json!({ "error": self.message, "failure": failure })),
+            )
+                .into_response(),
+            None => (self.status, self.message).into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RegenerateRequest {
+    /// Restrict to the latest reply in this channel; defaults to any channel.
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    #[serde(default, flatten)]
+    pub generation: GenerationParams,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: TranscriptFormat,
+    /// Export only this channel; all channels when omitted.
+    #[serde(default)]
+    pub channel_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FinetuneExportQuery {
+    pub user_id: String,
+    /// Keep only conversations whose replies' 👍/👎 feedback adds up to at
+    /// least this; all conversations when omitted.
+    #[serde(default)]
+    pub min_feedback: Option<i32>,
+    /// System message for every example; the default prompt when omitted.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+/// How many of a user's facts are searched for one being deleted.
+const FACT_LOOKUP_LIMIT: usize = 1_000;
+
+/// Upper bound on messages and tool calls included in one export.
+const EXPORT_LIMIT: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct SystemNoticeRequest {
+    pub text: String,
+    /// Seconds the notice waits for the user's next turn.
+    #[serde(default = "default_notice_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserApiKeyRequest {
+    /// `openrouter` or `openai`.
+    pub provider: String,
+    pub key: String,
+}
+
+fn default_notice_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// Longest a notice may wait for the user's next turn.
+const MAX_NOTICE_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize)]
+pub struct QuotaResetQuery {
+    /// Reset only this tool; all tools when omitted.
+    #[serde(default)]
+    pub tool: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FactEditRequest {
+    pub edits: Vec<FactEdit>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PendingFactQuery {
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomCommandRequest {
+    pub description: String,
+    /// Prompt with `{placeholder}`s; see [`CustomCommand::template`].
+    pub template: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PersonaImportQuery {
+    /// Share of users (0-100) that get an updated persona first; `0`
+    /// replaces it for everyone. Defaults to the configured rollout share.
+    #[serde(default)]
+    pub rollout_percent: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FactUndoQuery {
+    /// Restore only this fact; every recently deleted fact when omitted.
+    #[serde(default)]
+    pub key: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,12 +322,49 @@ struct DeletedBoolResponse {
     deleted: bool,
 }
 
+#[derive(Serialize)]
+struct PinnedResponse {
+    pinned: bool,
+}
+
+#[derive(Serialize)]
+struct EventResponse {
+    applied: Vec<EventEffect>,
+}
+
+#[derive(Serialize)]
+struct RestoredResponse {
+    restored: u64,
+}
+
 pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/", get(index))
-        .route("/health", get(health))
-        .route("/chat", post(chat))
+        .route(
+            "/health",
+            get(health).layer(middleware::from_fn(|request: Request, next: Next| {
+                etag_cache(request, next, HEALTH_MAX_AGE_SECS)
+            })),
+        )
+        .route(
+            "/chat",
+            post(chat).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit_headers,
+            )),
+        )
+        .route("/chat/batch", post(chat_batch))
+        .route("/api/events", post(api_ingest_event))
+        .route("/widget", get(widget_page))
+        .route(
+            "/widget/chat",
+            post(widget_chat).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit_headers,
+            )),
+        )
         .route("/dashboard", get(dashboard))
+        .route("/api/activity", get(api_activity))
         .route("/api/users", get(api_list_users))
         .route(
             "/api/users/{user_id}/messages",
@@ -78,19 +374,254 @@ pub fn router(state: AppState) -> Router {
             "/api/users/{user_id}/facts",
             get(api_list_facts).delete(api_clear_facts),
         )
+        .route(
+            "/api/users/{user_id}/messages/undo",
+            post(api_undo_clear_messages),
+        )
+        .route(
+            "/api/users/{user_id}/facts/undo",
+            post(api_undo_delete_facts),
+        )
         .route("/api/users/{user_id}/facts/{key}", delete(api_delete_fact))
+        .route("/api/users/{user_id}/calendar.ics", get(api_user_calendar))
+        .route(
+            "/api/dashboard/users/{user_id}/facts",
+            patch(api_edit_facts),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/chats/export",
+            get(api_export_chats),
+        )
+        .route("/api/dashboard/export/finetune", get(api_export_finetune))
         .route(
             "/api/users/{user_id}/tool-calls",
             get(api_list_tool_calls).delete(api_clear_tool_calls),
         )
+        .route(
+            "/api/users/{user_id}/thoughts",
+            get(api_list_thoughts).delete(api_clear_thoughts),
+        )
         .route(
             "/api/users/{user_id}/decisions",
             get(api_list_decisions).delete(api_clear_decisions),
         )
-        .layer(TraceLayer::new_for_http())
+        .route(
+            "/api/dashboard/users/{user_id}/messages/{message_id}/pin",
+            post(api_pin_message).delete(api_unpin_message),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/sessions",
+            get(api_list_sessions).post(api_start_session),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/sessions/{session_id}/messages",
+            get(api_list_session_messages),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/sessions/{session_id}/select",
+            post(api_select_session),
+        )
+        .route(
+            "/api/dashboard/prompts/default",
+            get(api_default_prompt).layer(middleware::from_fn(|request: Request, next: Next| {
+                etag_cache(request, next, STATIC_INFO_MAX_AGE_SECS)
+            })),
+        )
+        .route(
+            "/api/dashboard/tools",
+            get(api_list_tools).layer(middleware::from_fn(|request: Request, next: Next| {
+                etag_cache(request, next, STATIC_INFO_MAX_AGE_SECS)
+            })),
+        )
+        .route("/api/dashboard/tools/stats", get(api_tool_stats))
+        .route("/api/dashboard/model/latency", get(api_model_latency))
+        .route("/api/capabilities", get(api_capabilities))
+        .route(
+            "/api/dashboard/users/{user_id}/tasks",
+            get(api_list_background_tasks),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/notices",
+            get(api_list_notices).post(api_add_notice),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/api-key",
+            get(api_get_user_api_key)
+                .put(api_put_user_api_key)
+                .delete(api_delete_user_api_key),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/quotas",
+            get(api_list_quotas).delete(api_reset_quotas),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/regenerate",
+            post(api_regenerate_reply),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/notifications",
+            get(api_get_notification_prefs).put(api_put_notification_prefs),
+        )
+        .route(
+            "/api/dashboard/users/{user_id}/calendar/rotate",
+            post(api_rotate_calendar_link),
+        )
+        .route(
+            "/api/dashboard/planners/{decision_id}/replay",
+            post(api_replay_planner_decision),
+        )
+        .route(
+            "/api/admin/guilds/{guild_id}/settings",
+            get(api_get_guild_settings).put(api_put_guild_settings),
+        )
+        .route(
+            "/api/admin/personas",
+            get(api_list_personas).layer(middleware::from_fn(|request: Request, next: Next| {
+                etag_cache(request, next, 0)
+            })),
+        )
+        .route("/api/admin/personas/import", post(api_import_persona))
+        .route("/api/admin/audit", get(api_list_audit_logs))
+        .route("/api/admin/prompt-rollouts", get(api_list_prompt_rollouts))
+        .route(
+            "/api/admin/prompt-rollouts/{persona}/rollback",
+            post(api_roll_back_prompt),
+        )
+        .route("/api/admin/custom-commands", get(api_list_custom_commands))
+        .route(
+            "/api/admin/custom-commands/{name}",
+            put(api_put_custom_command).delete(api_delete_custom_command),
+        )
+        .route("/api/admin/pending-facts", get(api_list_pending_facts))
+        .route(
+            "/api/admin/pending-facts/{id}/approve",
+            post(api_approve_pending_fact),
+        )
+        .route(
+            "/api/admin/pending-facts/{id}/reject",
+            post(api_reject_pending_fact),
+        )
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
         .with_state(state)
 }
 
+/// Address of the client that sent a request, resolved through trusted
+/// proxies by [`apply_http_config`].
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// Prepares a router for deployment: mounts it under the configured base
+/// path, answers CORS requests from the allowed origins and resolves client
+/// IPs through trusted proxies. Serve the result with
+/// `into_make_service_with_connect_info::<SocketAddr>()` so the peer address
+/// is known.
+pub fn apply_http_config(router: Router, config: &HttpConfig) -> Router {
+    let trusted = TrustedProxies::parse(&config.trusted_proxies).unwrap_or_else(|error| {
+        warn!(%error, "ignoring TRUSTED_PROXIES");
+        TrustedProxies::default()
+    });
+    let mut router = match normalize_base_path(&config.base_path) {
+        Some(base_path) => Router::new().nest(&base_path, router),
+        None => router,
+    };
+    if let Some(cors) = cors_layer(config) {
+        router = router.layer(cors);
+    }
+    router.layer(middleware::from_fn_with_state(
+        Arc::new(trusted),
+        resolve_client_ip,
+    ))
+}
+
+/// Routes that a `*` in `CORS_ALLOWED_ORIGINS` opens to every origin; the
+/// rest stay limited to the listed origins.
+const PUBLIC_CORS_PATHS: &[&str] = &["/chat", "/chat/batch"];
+
+fn is_public_cors_path(path: &str, base_path: &str) -> bool {
+    path.strip_prefix(base_path)
+        .is_some_and(|path| PUBLIC_CORS_PATHS.contains(&path))
+}
+
+fn cors_layer(config: &HttpConfig) -> Option<CorsLayer> {
+    let origins = split_list(&config.cors_allowed_origins).collect::<Vec<_>>();
+    if origins.is_empty() {
+        return None;
+    }
+    let listed = origins
+        .iter()
+        .filter(|origin| **origin != "*")
+        .filter_map(|origin| HeaderValue::from_str(origin.trim_end_matches('/')).ok())
+        .collect::<Vec<_>>();
+    let allow_origin = if origins.contains(&"*") {
+        // The dashboard API has no authentication of its own; any other site
+        // a user visits must not be able to read or change it.
+        let base_path = normalize_base_path(&config.base_path).unwrap_or_default();
+        AllowOrigin::predicate(move |origin, request| {
+            listed.contains(origin) || is_public_cors_path(request.uri.path(), &base_path)
+        })
+    } else {
+        AllowOrigin::list(listed)
+    };
+    let allow_headers = [
+        header::CONTENT_TYPE,
+        HeaderName::from_static(ACTOR_HEADER),
+        HeaderName::from_static(FLAGS_HEADER),
+    ]
+    .into_iter()
+    .chain(
+        split_list(&config.cors_allowed_headers)
+            .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok()),
+    )
+    .collect::<Vec<_>>();
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_headers(allow_headers)
+            .expose_headers([
+                HeaderName::from_static("x-ratelimit-limit"),
+                HeaderName::from_static("x-ratelimit-remaining"),
+                HeaderName::from_static("x-ratelimit-reset"),
+                header::RETRY_AFTER,
+            ]),
+    )
+}
+
+async fn resolve_client_ip(
+    State(trusted): State<Arc<TrustedProxies>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let forwarded_for = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok());
+        let ip = client_ip(peer.ip(), forwarded_for, &trusted);
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(request).await
+}
+
+fn request_span(request: &Request) -> Span {
+    let client_ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| ip.to_string());
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        client_ip = client_ip.as_deref().unwrap_or("unknown"),
+    )
+}
+
 async fn index() -> &'static str {
     "CompanionPilot API"
 }
@@ -99,37 +630,462 @@ async fn health() -> &'static str {
     "ok"
 }
 
-async fn dashboard() -> impl IntoResponse {
+/// Serves the dashboard with its API calls pointed at the prefix the router
+/// is mounted under.
+async fn dashboard(uri: Uri, OriginalUri(original): OriginalUri) -> impl IntoResponse {
+    let base_path = original.path().strip_suffix(uri.path()).unwrap_or_default();
+    let html = DASHBOARD_HTML.replacen(
+        "const BASE_PATH = '';",
+        &format!("const BASE_PATH = {};", json!(base_path)),
+        1,
+    );
+    ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], html)
+}
+
+async fn chat(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ChatRequest>,
+) -> Response {
+    let flags = match request_flags(&headers) {
+        Ok(flags) => flags,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let message_id = format!("http-{}", Utc::now().timestamp_millis());
+    let user_id = RateLimitSubject(request.user_id.clone());
+    let mut response = run_chat(&state, request, message_id, flags)
+        .await
+        .map(Json)
+        .into_response();
+    response.extensions_mut().insert(user_id);
+    response
+}
+
+/// Writes an event from another service to the user's memory through the
+/// configured mappings.
+async fn api_ingest_event(
+    State(state): State<AppState>,
+    Json(event): Json<ExternalEvent>,
+) -> Result<Json<EventResponse>, (axum::http::StatusCode, String)> {
+    if event.user_id.trim().is_empty() {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "user_id must not be empty".to_owned(),
+        ));
+    }
+    let effects = state
+        .event_mappings
+        .map(&event, Utc::now())
+        .map_err(|error| match error {
+            EventError::Unmapped(_) => (axum::http::StatusCode::NOT_FOUND, error.to_string()),
+            EventError::MissingField { .. } => {
+                (axum::http::StatusCode::BAD_REQUEST, error.to_string())
+            }
+        })?;
+    let mut applied = Vec::with_capacity(effects.len());
+    for effect in effects {
+        match effect {
+            EventEffect::Fact(fact) => {
+                let written = state
+                    .orchestrator
+                    .write_external_fact(&event.user_id, fact, "external_event")
+                    .await
+                    .map_err(internal_error)?;
+                applied.extend(written.map(EventEffect::Fact));
+            }
+            EventEffect::Notice(mut notice) => {
+                notice.id = state
+                    .memory
+                    .add_system_notice(notice.clone())
+                    .await
+                    .map_err(internal_error)?;
+                applied.push(EventEffect::Notice(notice));
+            }
+        }
+    }
+    Ok(Json(EventResponse { applied }))
+}
+
+async fn widget_page() -> impl IntoResponse {
     (
         [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
-        DASHBOARD_HTML,
+        WIDGET_HTML,
     )
 }
 
-async fn chat(
+/// Answers a public widget visitor. The token picks the widget, which fixes
+/// the persona and the memory namespace; nothing else is configurable.
+async fn widget_chat(
     State(state): State<AppState>,
-    Json(request): Json<ChatRequest>,
-) -> Result<Json<OrchestratorReply>, (axum::http::StatusCode, String)> {
+    client_ip: Option<Extension<ClientIp>>,
+    headers: HeaderMap,
+    Json(request): Json<WidgetChatRequest>,
+) -> Response {
+    let Some(widget) = headers
+        .get(WIDGET_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|token| state.widgets.by_token(token))
+    else {
+        return (
+            axum::http::StatusCode::UNAUTHORIZED,
+            "invalid widget token".to_owned(),
+        )
+            .into_response();
+    };
+    let Some(user_id) = widget.visitor_user_id(&request.visitor_id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            "invalid visitor_id".to_owned(),
+        )
+            .into_response();
+    };
+    let content = request.content.trim();
+    if content.is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            "content must not be empty".to_owned(),
+        )
+            .into_response();
+    }
+    if content.chars().count() > WIDGET_MAX_MESSAGE_CHARS {
+        return (
+            axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            format!("messages are limited to {WIDGET_MAX_MESSAGE_CHARS} characters"),
+        )
+            .into_response();
+    }
+    let persona = match widget.persona.as_deref() {
+        Some(name) => match state.personas.get(name) {
+            Some(bundle) => Some(bundle.to_persona()),
+            None => {
+                warn!(widget = %widget.name, persona = %name, "widget persona is not loaded");
+                return (
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                    "this chat is not available right now".to_owned(),
+                )
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+    // Visitors pick their own ids, so the limit follows the client address.
+    let subject = match client_ip {
+        Some(Extension(ClientIp(ip))) => format!("{}:ip:{ip}", widget.guild_id()),
+        None => user_id.clone(),
+    };
+    if let Some(rate_limiter) = state.orchestrator.rate_limiter()
+        && let Err(limited) = rate_limiter.check(&subject)
+    {
+        warn!(widget = %widget.name, %subject, "widget message rejected: rate limit reached");
+        let mut response = orchestration_error(limited.into()).into_response();
+        response.extensions_mut().insert(RateLimitSubject(subject));
+        return response;
+    }
+
+    let message = MessageCtx {
+        message_id: format!("widget-{}", Utc::now().timestamp_millis()),
+        user_id: user_id.clone(),
+        guild_id: widget.guild_id(),
+        channel_id: "widget".to_owned(),
+        content: content.to_owned(),
+        timestamp: Utc::now(),
+        attachments: Vec::new(),
+    };
+    let options = TurnOptions {
+        persona,
+        enabled_tools: Some(WIDGET_TOOLS.iter().map(|tool| (*tool).to_owned()).collect()),
+        surface: Some(ContextSurface::Dashboard),
+        ..TurnOptions::default()
+    };
+    let mut response = state
+        .orchestrator
+        .handle_message_with_options(message, options)
+        .await
+        .map(|reply| {
+            Json(WidgetChatReply {
+                text: finish_reply(&state, &reply),
+                citations: reply.citations,
+            })
+        })
+        .map_err(orchestration_error)
+        .into_response();
+    response.extensions_mut().insert(RateLimitSubject(subject));
+    response
+}
+
+/// The user a response counted against, for [`rate_limit_headers`].
+#[derive(Debug, Clone)]
+struct RateLimitSubject(String);
+
+/// Tags successful responses with a content-hash `ETag` and a
+/// `Cache-Control` lifetime, and answers `304 Not Modified` when the client's
+/// `If-None-Match` already names the current body. With `max_age_secs` of
+/// zero clients revalidate on every request.
+async fn etag_cache(request: Request, next: Next, max_age_secs: u64) -> Response {
+    let if_none_match = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+    let response = next.run(request).await;
+    if response.status() != axum::http::StatusCode::OK {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_ETAG_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!(?error, "failed to buffer response for ETag");
+            return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    tag_with_etag(parts, bytes, if_none_match.as_deref(), max_age_secs)
+}
+
+/// Adds the caching headers to a buffered `200` response, or turns it into a
+/// bodiless `304` when `if_none_match` names its ETag.
+fn tag_with_etag(
+    mut parts: axum::http::response::Parts,
+    bytes: axum::body::Bytes,
+    if_none_match: Option<&str>,
+    max_age_secs: u64,
+) -> Response {
+    let digest = format!("{:x}", Sha256::digest(&bytes));
+    let etag = format!("\"{}\"", &digest[..32]);
+    let cache_control = if max_age_secs == 0 {
+        "private, no-cache".to_owned()
+    } else {
+        format!("private, max-age={max_age_secs}")
+    };
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&cache_control) {
+        parts.headers.insert(header::CACHE_CONTROL, value);
+    }
+
+    if if_none_match.is_some_and(|candidates| etag_matches(candidates, &etag)) {
+        parts.status = axum::http::StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.remove(header::CONTENT_TYPE);
+        return Response::from_parts(parts, axum::body::Body::empty());
+    }
+    Response::from_parts(parts, axum::body::Body::from(bytes))
+}
+
+/// Weak comparison of an `If-None-Match` list against an ETag.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Adds `X-RateLimit-*` headers for the user a chat response was for, plus
+/// `Retry-After` on `429`, read from the orchestrator's per-user limiter.
+async fn rate_limit_headers(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let Some(rate_limiter) = state.orchestrator.rate_limiter() else {
+        return response;
+    };
+    let Some(RateLimitSubject(user_id)) = response.extensions().get::<RateLimitSubject>() else {
+        return response;
+    };
+    let status = rate_limiter.status(user_id);
+    let reset_secs = retry_after_secs(status.reset_after);
+    let headers = response.headers_mut();
+    headers.insert("x-ratelimit-limit", HeaderValue::from(status.limit));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(status.remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(reset_secs));
+    if response.status() == axum::http::StatusCode::TOO_MANY_REQUESTS {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(reset_secs));
+    }
+    response
+}
+
+/// Runs independent chat requests with bounded concurrency. Item failures are
+/// reported per item; only a malformed batch fails the whole call.
+async fn chat_batch(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(batch): Json<ChatBatchRequest>,
+) -> Result<Json<Vec<ChatBatchItem>>, (axum::http::StatusCode, String)> {
+    let flags = request_flags(&headers)?;
+    if batch.requests.is_empty() {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "requests must not be empty".to_owned(),
+        ));
+    }
+    if batch.requests.len() > MAX_BATCH_REQUESTS {
+        return Err((
+            axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            format!("a batch may hold at most {MAX_BATCH_REQUESTS} requests"),
+        ));
+    }
+
+    let batch_id = Utc::now().timestamp_millis();
+    let permits = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+    let mut tasks = JoinSet::new();
+    for (index, request) in batch.requests.into_iter().enumerate() {
+        let state = state.clone();
+        let permits = permits.clone();
+        let flags = flags.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let message_id = format!("http-{batch_id}-{index}");
+            (index, run_chat(&state, request, message_id, flags).await)
+        });
+    }
+
+    let mut items = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined.map_err(|error| internal_error(error.into()))?;
+        items.push(match result {
+            Ok(reply) => ChatBatchItem {
+                index,
+                status: axum::http::StatusCode::OK.as_u16(),
+                reply: Some(reply),
+                error: None,
+                failure: None,
+            },
+            Err(error) => ChatBatchItem {
+                index,
+                status: error.status.as_u16(),
+                reply: None,
+                error: Some(error.message),
+                failure: error.failure,
+            },
+        });
+    }
+    items.sort_by_key(|item| item.index);
+    Ok(Json(items))
+}
+
+async fn run_chat(
+    state: &AppState,
+    request: ChatRequest,
+    message_id: String,
+    flags: TurnFlags,
+) -> Result<OrchestratorReply, ChatError> {
+    if let Some(attachment) = request
+        .attachments
+        .iter()
+        .find(|attachment| !attachment.is_discord_cdn())
+    {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!(
+                "attachment `{}` must be an https link on Discord's CDN",
+                attachment.filename
+            ),
+        )
+            .into());
+    }
     let message = MessageCtx {
-        message_id: format!("http-{}", Utc::now().timestamp_millis()),
+        message_id,
         user_id: request.user_id,
         guild_id: request.guild_id,
         channel_id: request.channel_id,
-        content: request.content,
+        content: content_with_attachments(&request.content, &request.attachments),
         timestamp: Utc::now(),
+        attachments: request.attachments,
     };
 
-    let reply = state
+    let response_schema = match request.response_format {
+        ResponseFormat::Text => None,
+        ResponseFormat::Json => {
+            let schema = request.response_schema.unwrap_or_else(|| json!({}));
+            check_response_schema(&schema).map_err(|error| {
+                (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    format!("invalid response_schema: {error}"),
+                )
+            })?;
+            Some(schema)
+        }
+    };
+    let persona = match request.persona.as_deref() {
+        Some(name) => Some(
+            state
+                .personas
+                .get(name)
+                .ok_or_else(|| {
+                    (
+                        axum::http::StatusCode::BAD_REQUEST,
+                        format!("unknown persona `{name}`"),
+                    )
+                })?
+                .to_persona(),
+        ),
+        None => None,
+    };
+    let options = TurnOptions {
+        persona,
+        generation: request.generation,
+        response_schema,
+        simulate_tools: request.simulate_tools,
+        flags,
+        surface: Some(ContextSurface::Dashboard),
+        ..TurnOptions::default()
+    };
+    let mut reply = state
         .orchestrator
-        .handle_message(message)
+        .handle_message_with_options(message, options)
         .await
-        .map_err(internal_error)?;
+        .map_err(ChatError::from_orchestration)?;
+    if reply.json.is_none() {
+        reply.text = finish_reply(state, &reply);
+    }
+    Ok(reply)
+}
+
+/// Per-request feature flags from the `X-CP-Flags` header.
+fn request_flags(headers: &HeaderMap) -> Result<TurnFlags, (axum::http::StatusCode, String)> {
+    let Some(value) = headers.get(FLAGS_HEADER) else {
+        return Ok(TurnFlags::default());
+    };
+    value
+        .to_str()
+        .map_err(|error| error.to_string())
+        .and_then(TurnFlags::parse)
+        .map_err(|error| {
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("invalid X-CP-Flags: {error}"),
+            )
+        })
+}
 
-    Ok(Json(reply))
+/// Runs a text reply through the HTTP post-processing pipeline; citations
+/// stay in their own field.
+fn finish_reply(state: &AppState, reply: &OrchestratorReply) -> String {
+    state.orchestrator.reply_pipelines().http.apply(
+        &reply.text,
+        &ReplyFilterContext {
+            citations: &reply.citations,
+            ..ReplyFilterContext::default()
+        },
+    )
 }
 
 // --- Dashboard API handlers ---
 
+async fn api_activity(State(state): State<AppState>) -> Json<Option<ActivitySnapshot>> {
+    Json(
+        state
+            .orchestrator
+            .activity()
+            .map(|activity| activity.snapshot()),
+    )
+}
+
 async fn api_list_users(
     State(state): State<AppState>,
     Query(query): Query<LimitQuery>,
@@ -155,77 +1111,434 @@ async fn api_list_messages(
     Ok(Json(messages))
 }
 
-async fn api_clear_messages(
+async fn api_pin_message(
     State(state): State<AppState>,
-    Path(user_id): Path<String>,
-) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
-    let deleted = state
+    Path((user_id, message_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<PinnedResponse>, (axum::http::StatusCode, String)> {
+    set_message_pin(&state, &headers, &user_id, &message_id, true).await
+}
+
+async fn api_unpin_message(
+    State(state): State<AppState>,
+    Path((user_id, message_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<PinnedResponse>, (axum::http::StatusCode, String)> {
+    set_message_pin(&state, &headers, &user_id, &message_id, false).await
+}
+
+async fn set_message_pin(
+    state: &AppState,
+    headers: &HeaderMap,
+    user_id: &str,
+    message_id: &str,
+    pinned: bool,
+) -> Result<Json<PinnedResponse>, (axum::http::StatusCode, String)> {
+    let found = state
         .memory
-        .clear_chat_messages(&user_id)
+        .set_chat_message_pinned(user_id, message_id, pinned)
         .await
         .map_err(internal_error)?;
-    Ok(Json(DeletedResponse { deleted }))
+    if !found {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            format!("no message `{message_id}` for `{user_id}`"),
+        ));
+    }
+    audit(
+        state,
+        headers,
+        if pinned {
+            "message.pin"
+        } else {
+            "message.unpin"
+        },
+        user_target(user_id),
+        None,
+        Some(json!({ "message_id": message_id, "pinned": pinned })),
+    )
+    .await;
+    Ok(Json(PinnedResponse { pinned }))
 }
 
-async fn api_list_facts(
+async fn api_list_sessions(
     State(state): State<AppState>,
     Path(user_id): Path<String>,
     Query(query): Query<LimitQuery>,
 ) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
-    let facts = state
+    let sessions = state
         .memory
-        .list_facts(&user_id, query.limit)
+        .list_chat_sessions(&user_id, query.limit)
         .await
         .map_err(internal_error)?;
-    Ok(Json(facts))
+    Ok(Json(sessions))
 }
 
-async fn api_clear_facts(
+/// Starts a new session, like `/newchat` in Discord.
+async fn api_start_session(
     State(state): State<AppState>,
     Path(user_id): Path<String>,
-) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
-    let deleted = state
+) -> Result<Json<ChatSession>, (axum::http::StatusCode, String)> {
+    let session = state
         .memory
-        .clear_facts(&user_id)
+        .start_chat_session(&user_id, Utc::now())
         .await
         .map_err(internal_error)?;
-    Ok(Json(DeletedResponse { deleted }))
+    Ok(Json(session))
 }
 
-async fn api_delete_fact(
+async fn api_list_session_messages(
     State(state): State<AppState>,
-    Path((user_id, key)): Path<(String, String)>,
-) -> Result<Json<DeletedBoolResponse>, (axum::http::StatusCode, String)> {
-    let deleted = state
+    Path((user_id, session_id)): Path<(String, String)>,
+    Query(query): Query<LimitQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let messages = state
         .memory
-        .delete_fact(&user_id, &key)
+        .list_session_messages(&user_id, &session_id, query.limit)
         .await
         .map_err(internal_error)?;
-    Ok(Json(DeletedBoolResponse { deleted }))
+    Ok(Json(messages))
 }
 
-async fn api_list_tool_calls(
+/// Makes an earlier session current, so the user's next message continues
+/// it.
+async fn api_select_session(
     State(state): State<AppState>,
-    Path(user_id): Path<String>,
-    Query(query): Query<LimitQuery>,
-) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
-    let calls = state
+    Path((user_id, session_id)): Path<(String, String)>,
+) -> Result<Json<ChatSession>, (axum::http::StatusCode, String)> {
+    let not_found = || {
+        (
+            axum::http::StatusCode::NOT_FOUND,
+            format!("no session `{session_id}` for `{user_id}`"),
+        )
+    };
+    let selected = state
         .memory
-        .list_tool_calls(&user_id, query.limit)
+        .touch_chat_session(&user_id, &session_id, Utc::now())
         .await
         .map_err(internal_error)?;
-    Ok(Json(calls))
+    if !selected {
+        return Err(not_found());
+    }
+    state
+        .memory
+        .current_chat_session(&user_id)
+        .await
+        .map_err(internal_error)?
+        .filter(|session| session.id == session_id)
+        .map(Json)
+        .ok_or_else(not_found)
 }
 
-async fn api_clear_tool_calls(
+async fn api_export_chats(
     State(state): State<AppState>,
     Path(user_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let mut messages = state
+        .memory
+        .list_chat_messages(&user_id, EXPORT_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    let mut tool_calls = state
+        .memory
+        .list_tool_calls(&user_id, EXPORT_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    if let Some(channel_id) = &query.channel_id {
+        messages.retain(|message| &message.channel_id == channel_id);
+        tool_calls.retain(|tool_call| &tool_call.channel_id == channel_id);
+    }
+
+    let document = render_transcript(&user_id, &messages, &tool_calls, query.format);
+    let file_stem = file_stem(&user_id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"conversation-{file_stem}.{}\"",
+                    query.format.extension()
+                ),
+            ),
+        ],
+        document,
+    ))
+}
+
+async fn api_export_finetune(
+    State(state): State<AppState>,
+    Query(query): Query<FinetuneExportQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let messages = state
+        .memory
+        .list_chat_messages(&query.user_id, EXPORT_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    let feedback = state
+        .memory
+        .list_message_feedback(&query.user_id)
+        .await
+        .map_err(internal_error)?;
+    let document = render_finetune_jsonl(
+        &messages,
+        &feedback,
+        query.min_feedback,
+        query
+            .system_prompt
+            .as_deref()
+            .unwrap_or_else(|| default_system_prompt_base()),
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/jsonl; charset=utf-8".to_owned(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"finetune-{}.jsonl\"",
+                    file_stem(&query.user_id)
+                ),
+            ),
+        ],
+        document,
+    ))
+}
+
+/// `id` with everything but ASCII letters, digits, `-` and `_` replaced, for
+/// use in a download file name.
+fn file_stem(id: &str) -> String {
+    id.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+async fn api_clear_messages(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
+    let deleted = state
+        .memory
+        .clear_chat_messages(&user_id)
+        .await
+        .map_err(internal_error)?;
+    audit_clear(&state, &headers, "messages.clear", &user_id, deleted).await;
+    Ok(Json(DeletedResponse { deleted }))
+}
+
+async fn api_undo_clear_messages(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<RestoredResponse>, (axum::http::StatusCode, String)> {
+    let restored = state
+        .memory
+        .restore_chat_messages(&user_id, undo_cutoff(Utc::now()))
+        .await
+        .map_err(internal_error)?;
+    if restored > 0 {
+        audit(
+            &state,
+            &headers,
+            "messages.restore",
+            user_target(&user_id),
+            None,
+            Some(json!({ "count": restored })),
+        )
+        .await;
+    }
+    Ok(Json(RestoredResponse { restored }))
+}
+
+async fn api_list_facts(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<LimitQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let facts = state
+        .memory
+        .list_facts(&user_id, query.limit)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(facts))
+}
+
+async fn api_clear_facts(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
+    let deleted = state
+        .memory
+        .clear_facts(&user_id)
+        .await
+        .map_err(internal_error)?;
+    audit_clear(&state, &headers, "facts.clear", &user_id, deleted).await;
+    Ok(Json(DeletedResponse { deleted }))
+}
+
+async fn api_delete_fact(
+    State(state): State<AppState>,
+    Path((user_id, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<DeletedBoolResponse>, (axum::http::StatusCode, String)> {
+    let before = state
+        .memory
+        .list_facts(&user_id, FACT_LOOKUP_LIMIT)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .find(|fact| fact.key == key);
+    let deleted = state
+        .memory
+        .delete_fact(&user_id, &key)
+        .await
+        .map_err(internal_error)?;
+    if deleted {
+        audit(
+            &state,
+            &headers,
+            "fact.delete",
+            user_target(&user_id),
+            Some(before.map_or_else(|| json!({ "key": key }), |fact| json!(fact))),
+            None,
+        )
+        .await;
+    }
+    Ok(Json(DeletedBoolResponse { deleted }))
+}
+
+async fn api_edit_facts(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<FactEditRequest>,
+) -> Result<Json<FactEditSummary>, (axum::http::StatusCode, String)> {
+    let touched_keys = request
+        .edits
+        .iter()
+        .flat_map(FactEdit::keys)
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    let touched_facts = |facts: Vec<MemoryFact>| {
+        facts
+            .into_iter()
+            .filter(|fact| touched_keys.contains(&fact.key))
+            .collect::<Vec<_>>()
+    };
+    let before = state
+        .memory
+        .list_facts(&user_id, FACT_LOOKUP_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    let summary = state
+        .memory
+        .apply_fact_edits(&user_id, &request.edits)
+        .await
+        .map_err(fact_edit_error)?;
+    let after = state
+        .memory
+        .list_facts(&user_id, FACT_LOOKUP_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "facts.edit",
+        user_target(&user_id),
+        Some(json!(touched_facts(before))),
+        Some(json!({ "edits": request.edits, "facts": touched_facts(after) })),
+    )
+    .await;
+    Ok(Json(summary))
+}
+
+async fn api_undo_delete_facts(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<FactUndoQuery>,
+    headers: HeaderMap,
+) -> Result<Json<RestoredResponse>, (axum::http::StatusCode, String)> {
+    let restored = state
+        .memory
+        .restore_facts(&user_id, query.key.as_deref(), undo_cutoff(Utc::now()))
+        .await
+        .map_err(internal_error)?;
+    if restored > 0 {
+        audit(
+            &state,
+            &headers,
+            "facts.restore",
+            user_target(&user_id),
+            None,
+            Some(json!({ "key": query.key, "count": restored })),
+        )
+        .await;
+    }
+    Ok(Json(RestoredResponse { restored }))
+}
+
+async fn api_list_tool_calls(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<LimitQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let calls = state
+        .memory
+        .list_tool_calls(&user_id, query.limit)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(calls))
+}
+
+async fn api_clear_tool_calls(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
     let deleted = state
         .memory
         .clear_tool_calls(&user_id)
         .await
         .map_err(internal_error)?;
+    audit_clear(&state, &headers, "tool_calls.clear", &user_id, deleted).await;
+    Ok(Json(DeletedResponse { deleted }))
+}
+
+async fn api_list_thoughts(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<LimitQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let thoughts = state
+        .memory
+        .list_thoughts(&user_id, query.limit)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(thoughts))
+}
+
+async fn api_clear_thoughts(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
+    let deleted = state
+        .memory
+        .clear_thoughts(&user_id)
+        .await
+        .map_err(internal_error)?;
+    audit_clear(&state, &headers, "thoughts.clear", &user_id, deleted).await;
     Ok(Json(DeletedResponse { deleted }))
 }
 
@@ -245,18 +1558,1097 @@ async fn api_list_decisions(
 async fn api_clear_decisions(
     State(state): State<AppState>,
     Path(user_id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Json<DeletedResponse>, (axum::http::StatusCode, String)> {
     let deleted = state
         .memory
         .clear_planner_decisions(&user_id)
         .await
         .map_err(internal_error)?;
+    audit_clear(&state, &headers, "decisions.clear", &user_id, deleted).await;
     Ok(Json(DeletedResponse { deleted }))
 }
 
+async fn api_tool_stats(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.orchestrator.tool_stats().snapshot())
+}
+
+async fn api_default_prompt() -> Json<Value> {
+    Json(json!({ "system_prompt": default_system_prompt_base() }))
+}
+
+async fn api_list_tools() -> Json<Vec<ToolSpec>> {
+    Json(builtin_tool_specs())
+}
+
+async fn api_model_latency(State(state): State<AppState>) -> Json<Option<ModelProbeStats>> {
+    Json(
+        state
+            .orchestrator
+            .model_probe()
+            .map(|probe| probe.snapshot()),
+    )
+}
+
+async fn api_capabilities(
+    State(state): State<AppState>,
+) -> Result<Json<Capabilities>, (axum::http::StatusCode, String)> {
+    state.orchestrator.capabilities().map(Json).ok_or((
+        axum::http::StatusCode::NOT_FOUND,
+        "capabilities are not reported by this orchestrator".to_owned(),
+    ))
+}
+
+async fn api_list_background_tasks(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<BackgroundTaskRecord>>, (axum::http::StatusCode, String)> {
+    let tasks = state
+        .memory
+        .list_background_tasks(Some(&user_id), &[], query.limit)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(tasks))
+}
+
+async fn api_list_notices(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Vec<SystemNoticeRecord>>, (axum::http::StatusCode, String)> {
+    let notices = state
+        .memory
+        .list_system_notices(&user_id, Utc::now())
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(notices))
+}
+
+async fn api_add_notice(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<SystemNoticeRequest>,
+) -> Result<Json<SystemNoticeRecord>, (axum::http::StatusCode, String)> {
+    let text = request.text.trim();
+    if text.is_empty() {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "notice text must not be empty".to_owned(),
+        ));
+    }
+    if request.ttl_secs == 0 || request.ttl_secs > MAX_NOTICE_TTL_SECS {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("ttl_secs must be between 1 and {MAX_NOTICE_TTL_SECS}"),
+        ));
+    }
+    let created_at = Utc::now();
+    let mut notice = SystemNoticeRecord {
+        id: String::new(),
+        user_id: user_id.clone(),
+        text: text.to_owned(),
+        created_at,
+        expires_at: created_at + chrono::Duration::seconds(request.ttl_secs as i64),
+    };
+    notice.id = state
+        .memory
+        .add_system_notice(notice.clone())
+        .await
+        .map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "notice.create",
+        user_target(&user_id),
+        None,
+        Some(json!(notice)),
+    )
+    .await;
+    Ok(Json(notice))
+}
+
+/// The user's own model key, without the key itself.
+async fn api_get_user_api_key(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Option<UserApiKeyRecord>>, (axum::http::StatusCode, String)> {
+    let record = state
+        .memory
+        .get_user_api_key(&user_id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(record))
+}
+
+async fn api_put_user_api_key(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<UserApiKeyRequest>,
+) -> Result<Json<UserApiKeyRecord>, (axum::http::StatusCode, String)> {
+    let Some(user_keys) = state.orchestrator.user_keys() else {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            "personal API keys are disabled; set USER_KEY_SECRET".to_owned(),
+        ));
+    };
+    let Some(provider) = KeyProvider::parse(&request.provider) else {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "provider must be openrouter or openai".to_owned(),
+        ));
+    };
+    let before = state
+        .memory
+        .get_user_api_key(&user_id)
+        .await
+        .map_err(internal_error)?;
+    let record = user_keys
+        .set(state.memory.as_ref(), &user_id, provider, &request.key)
+        .await
+        .map_err(|error| (axum::http::StatusCode::BAD_REQUEST, error.to_string()))?;
+    audit(
+        &state,
+        &headers,
+        "api_key.set",
+        user_target(&user_id),
+        before.map(|before| json!(before)),
+        Some(json!(record)),
+    )
+    .await;
+    Ok(Json(record))
+}
+
+async fn api_delete_user_api_key(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DeletedBoolResponse>, (axum::http::StatusCode, String)> {
+    let before = state
+        .memory
+        .get_user_api_key(&user_id)
+        .await
+        .map_err(internal_error)?;
+    let deleted = state
+        .memory
+        .delete_user_api_key(&user_id)
+        .await
+        .map_err(internal_error)?;
+    if deleted {
+        audit(
+            &state,
+            &headers,
+            "api_key.delete",
+            user_target(&user_id),
+            before.map(|before| json!(before)),
+            None,
+        )
+        .await;
+    }
+    Ok(Json(DeletedBoolResponse { deleted }))
+}
+
+async fn api_list_quotas(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let quotas = state
+        .orchestrator
+        .tool_quota_status(&user_id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(quotas))
+}
+
+async fn api_reset_quotas(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<QuotaResetQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let before = state
+        .orchestrator
+        .tool_quota_status(&user_id)
+        .await
+        .map_err(internal_error)?;
+    let deleted = state
+        .orchestrator
+        .reset_tool_quota(&user_id, query.tool.as_deref())
+        .await
+        .map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "quotas.reset",
+        user_target(&user_id),
+        Some(json!(before)),
+        Some(json!({ "tool": query.tool, "reset_counters": deleted })),
+    )
+    .await;
+    Ok(Json(DeletedResponse { deleted }))
+}
+
+async fn api_regenerate_reply(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    request: Option<Json<RegenerateRequest>>,
+) -> Result<Json<OrchestratorReply>, (axum::http::StatusCode, String)> {
+    let Json(request) = request.unwrap_or_default();
+    let reply = state
+        .orchestrator
+        .regenerate_last_reply(&user_id, request.channel_id.as_deref(), request.generation)
+        .await
+        .map_err(orchestration_error)?
+        .ok_or_else(|| {
+            (
+                axum::http::StatusCode::NOT_FOUND,
+                format!("no user message to regenerate for `{user_id}`"),
+            )
+        })?;
+    let text = finish_reply(&state, &reply);
+    Ok(Json(OrchestratorReply { text, ..reply }))
+}
+
+async fn api_replay_planner_decision(
+    State(state): State<AppState>,
+    Path(decision_id): Path<String>,
+) -> Result<Json<PlannerReplay>, (axum::http::StatusCode, String)> {
+    let replay = state
+        .orchestrator
+        .replay_planner_decision(&decision_id)
+        .await
+        .map_err(|error| match error.downcast_ref::<PlannerReplayError>() {
+            Some(_) => (axum::http::StatusCode::BAD_REQUEST, error.to_string()),
+            None => orchestration_error(error),
+        })?
+        .ok_or_else(|| {
+            (
+                axum::http::StatusCode::NOT_FOUND,
+                format!("no planner decision `{decision_id}`"),
+            )
+        })?;
+    Ok(Json(replay))
+}
+
+async fn api_get_guild_settings(
+    State(state): State<AppState>,
+    Path(guild_id): Path<String>,
+) -> Result<Json<GuildSettings>, (axum::http::StatusCode, String)> {
+    let settings = state
+        .orchestrator
+        .guild_settings()
+        .get(&guild_id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(settings))
+}
+
+async fn api_put_guild_settings(
+    State(state): State<AppState>,
+    Path(guild_id): Path<String>,
+    headers: HeaderMap,
+    Json(settings): Json<GuildSettings>,
+) -> Result<Json<GuildSettings>, (axum::http::StatusCode, String)> {
+    settings
+        .validate()
+        .map_err(|error| (axum::http::StatusCode::BAD_REQUEST, error))?;
+    let before = state
+        .orchestrator
+        .guild_settings()
+        .get(&guild_id)
+        .await
+        .map_err(internal_error)?;
+    let settings = state
+        .orchestrator
+        .guild_settings()
+        .put(GuildSettings {
+            guild_id: guild_id.clone(),
+            ..settings
+        })
+        .await
+        .map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "guild_settings.update",
+        guild_target(&guild_id),
+        Some(json!(before)),
+        Some(json!(settings)),
+    )
+    .await;
+    Ok(Json(settings))
+}
+
+async fn api_list_personas(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.personas.list())
+}
+
+/// Imports a persona bundle, sent as JSON (`Content-Type: application/json`)
+/// or TOML. With prompt rollouts enabled, an update to an existing persona
+/// starts a rollout instead of replacing it right away.
+async fn api_import_persona(
+    State(state): State<AppState>,
+    Query(query): Query<PersonaImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<PersonaBundle>, (axum::http::StatusCode, String)> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    let bundle = if is_json {
+        PersonaBundle::from_json(&body)
+    } else {
+        PersonaBundle::from_toml(&body)
+    }
+    .map_err(|error| {
+        (
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("could not parse persona bundle: {error}"),
+        )
+    })?;
+    let before = state.personas.get(&bundle.name);
+    if let Some(rollouts) = state.orchestrator.prompt_rollouts()
+        && let Some(stable) = before.as_ref().filter(|stable| **stable != bundle)
+    {
+        let percent = query
+            .rollout_percent
+            .unwrap_or(rollouts.policy().percent)
+            .min(100);
+        if percent > 0 {
+            bundle
+                .validate()
+                .map_err(|error| (axum::http::StatusCode::BAD_REQUEST, error.to_string()))?;
+            rollouts
+                .start(
+                    state.orchestrator.memory().as_ref(),
+                    bundle.clone(),
+                    percent,
+                    Utc::now(),
+                )
+                .await
+                .map_err(internal_error)?;
+            audit(
+                &state,
+                &headers,
+                "persona.rollout",
+                persona_target(&bundle.name),
+                Some(json!(stable)),
+                Some(json!({ "percent": percent, "candidate": bundle })),
+            )
+            .await;
+            return Ok(Json(bundle));
+        }
+    }
+    state
+        .personas
+        .import(bundle.clone())
+        .map_err(|error| (axum::http::StatusCode::BAD_REQUEST, error.to_string()))?;
+    audit(
+        &state,
+        &headers,
+        "persona.import",
+        persona_target(&bundle.name),
+        before.map(|before| json!(before)),
+        Some(json!(bundle)),
+    )
+    .await;
+    Ok(Json(bundle))
+}
+
+async fn api_list_prompt_rollouts(State(state): State<AppState>) -> Json<Vec<PromptRollout>> {
+    Json(
+        state
+            .orchestrator
+            .prompt_rollouts()
+            .map(PromptRollouts::list)
+            .unwrap_or_default(),
+    )
+}
+
+async fn api_roll_back_prompt(
+    State(state): State<AppState>,
+    Path(persona): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<PromptRollout>>, (axum::http::StatusCode, String)> {
+    let rolled_back = match state.orchestrator.prompt_rollouts() {
+        Some(rollouts) => rollouts
+            .roll_back(
+                state.orchestrator.memory().as_ref(),
+                &persona,
+                "rolled back by an admin",
+                Utc::now(),
+            )
+            .await
+            .map_err(internal_error)?,
+        None => false,
+    };
+    if !rolled_back {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            format!("persona `{persona}` has no running rollout"),
+        ));
+    }
+    audit(
+        &state,
+        &headers,
+        "persona.rollback",
+        persona_target(&persona),
+        None,
+        None,
+    )
+    .await;
+    Ok(api_list_prompt_rollouts(State(state)).await)
+}
+
+fn custom_commands(state: &AppState) -> Result<&CustomCommands, (axum::http::StatusCode, String)> {
+    state.orchestrator.custom_commands().ok_or_else(|| {
+        (
+            axum::http::StatusCode::NOT_FOUND,
+            "custom commands are not supported by this orchestrator".to_owned(),
+        )
+    })
+}
+
+async fn api_list_custom_commands(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CustomCommand>>, (axum::http::StatusCode, String)> {
+    let commands = custom_commands(&state)?
+        .list()
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(commands))
+}
+
+/// Creates or replaces a custom command; chat adapters pick up the change.
+async fn api_put_custom_command(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<CustomCommandRequest>,
+) -> Result<Json<CustomCommand>, (axum::http::StatusCode, String)> {
+    let commands = custom_commands(&state)?;
+    let command = CustomCommand {
+        name,
+        description: request.description,
+        template: request.template,
+        updated_at: Utc::now(),
+    };
+    command
+        .validate()
+        .map_err(|error| (axum::http::StatusCode::BAD_REQUEST, error))?;
+    let before = commands.get(&command.name).await.map_err(internal_error)?;
+    let command = commands.put(command).await.map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "custom_command.update",
+        command_target(&command.name),
+        before.map(|before| json!(before)),
+        Some(json!(command)),
+    )
+    .await;
+    Ok(Json(command))
+}
+
+async fn api_delete_custom_command(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<axum::http::StatusCode, (axum::http::StatusCode, String)> {
+    let commands = custom_commands(&state)?;
+    let Some(before) = commands.get(&name).await.map_err(internal_error)? else {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            format!("no custom command `/{name}`"),
+        ));
+    };
+    commands.delete(&name).await.map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "custom_command.delete",
+        command_target(&name),
+        Some(json!(before)),
+        None,
+    )
+    .await;
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+fn notifications(state: &AppState) -> Result<&Notifications, (axum::http::StatusCode, String)> {
+    state.orchestrator.notifications().ok_or_else(|| {
+        (
+            axum::http::StatusCode::NOT_FOUND,
+            "notification preferences are not supported by this orchestrator".to_owned(),
+        )
+    })
+}
+
+async fn api_get_notification_prefs(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<NotificationPrefs>, (axum::http::StatusCode, String)> {
+    let prefs = notifications(&state)?
+        .get(&user_id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(prefs))
+}
+
+/// Replaces the user's notification preferences; omitted fields get their
+/// defaults.
+async fn api_put_notification_prefs(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Json(mut prefs): Json<NotificationPrefs>,
+) -> Result<Json<NotificationPrefs>, (axum::http::StatusCode, String)> {
+    let notifications = notifications(&state)?;
+    prefs.user_id = user_id;
+    prefs
+        .validate()
+        .map_err(|error| (axum::http::StatusCode::BAD_REQUEST, error))?;
+    let before = notifications
+        .get(&prefs.user_id)
+        .await
+        .map_err(internal_error)?;
+    let prefs = notifications.put(prefs).await.map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "notifications.update",
+        user_target(&prefs.user_id),
+        Some(json!(before)),
+        Some(json!(prefs)),
+    )
+    .await;
+    Ok(Json(prefs))
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarQuery {
+    #[serde(default)]
+    token: String,
+}
+
+fn calendar_feeds(state: &AppState) -> Result<&CalendarFeeds, (axum::http::StatusCode, String)> {
+    state.orchestrator.calendar_feeds().ok_or_else(|| {
+        (
+            axum::http::StatusCode::NOT_FOUND,
+            "calendar feeds are not enabled".to_owned(),
+        )
+    })
+}
+
+/// The user's birthdays and anniversaries as an iCal feed. Calendar apps
+/// cannot send headers, so the token comes in the query string.
+async fn api_user_calendar(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<CalendarQuery>,
+) -> Result<impl IntoResponse, (axum::http::StatusCode, String)> {
+    let salt = feed_salt(state.memory.as_ref(), &user_id)
+        .await
+        .map_err(internal_error)?;
+    if !calendar_feeds(&state)?.verify(&user_id, &salt, &query.token) {
+        return Err((
+            axum::http::StatusCode::UNAUTHORIZED,
+            "invalid calendar token".to_owned(),
+        ));
+    }
+    let calendar = user_calendar(state.memory.as_ref(), &user_id, Utc::now())
+        .await
+        .map_err(internal_error)?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/calendar; charset=utf-8".to_owned(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"calendar-{}.ics\"", file_stem(&user_id)),
+            ),
+        ],
+        calendar,
+    ))
+}
+
+/// Revokes the user's feed links, e.g. one that leaked. The new link is only
+/// handed out to the user, by `/calendar`.
+async fn api_rotate_calendar_link(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<axum::http::StatusCode, (axum::http::StatusCode, String)> {
+    calendar_feeds(&state)?;
+    rotate_feed_salt(state.memory.as_ref(), &user_id)
+        .await
+        .map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "calendar.rotate",
+        user_target(&user_id),
+        None,
+        None,
+    )
+    .await;
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+async fn api_list_pending_facts(
+    State(state): State<AppState>,
+    Query(query): Query<PendingFactQuery>,
+) -> Result<Json<Vec<PendingFactRecord>>, (axum::http::StatusCode, String)> {
+    let pending = state
+        .memory
+        .list_pending_facts(query.user_id.as_deref(), query.limit)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(pending))
+}
+
+/// Stores a queued fact. A proposal whose key got a different value after it
+/// was queued is stale: it is dropped instead of overwriting the newer value.
+async fn api_approve_pending_fact(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<PendingFactRecord>, (axum::http::StatusCode, String)> {
+    let pending = take_pending_fact(&state, &id).await?;
+    let current = state
+        .memory
+        .list_facts(&pending.user_id, FACT_LOOKUP_LIMIT)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .find(|fact| fact.key == pending.fact.key);
+    if let Some(current) = current.filter(|current| {
+        current.updated_at > pending.created_at && current.value != pending.fact.value
+    }) {
+        audit(
+            &state,
+            &headers,
+            "pending_fact.stale",
+            user_target(&pending.user_id),
+            Some(json!(pending)),
+            Some(json!(current)),
+        )
+        .await;
+        return Err((
+            axum::http::StatusCode::CONFLICT,
+            format!(
+                "`{}` changed to `{}` after this proposal was queued; the proposal was dropped",
+                current.key, current.value
+            ),
+        ));
+    }
+    state
+        .memory
+        .upsert_fact(&pending.user_id, pending.fact.clone())
+        .await
+        .map_err(internal_error)?;
+    audit(
+        &state,
+        &headers,
+        "pending_fact.approve",
+        user_target(&pending.user_id),
+        None,
+        Some(json!(pending)),
+    )
+    .await;
+    Ok(Json(pending))
+}
+
+async fn api_reject_pending_fact(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<PendingFactRecord>, (axum::http::StatusCode, String)> {
+    let pending = take_pending_fact(&state, &id).await?;
+    audit(
+        &state,
+        &headers,
+        "pending_fact.reject",
+        user_target(&pending.user_id),
+        Some(json!(pending)),
+        None,
+    )
+    .await;
+    Ok(Json(pending))
+}
+
+async fn take_pending_fact(
+    state: &AppState,
+    id: &str,
+) -> Result<PendingFactRecord, (axum::http::StatusCode, String)> {
+    state
+        .memory
+        .take_pending_fact(id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                axum::http::StatusCode::NOT_FOUND,
+                format!("no pending fact `{id}`"),
+            )
+        })
+}
+
+async fn api_list_audit_logs(
+    State(state): State<AppState>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditLogRecord>>, (axum::http::StatusCode, String)> {
+    let records = state
+        .memory
+        .list_audit_logs(&query)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(records))
+}
+
+/// Records an administrative change. A failed write is logged rather than
+/// failing the change, which has already happened.
+async fn audit(
+    state: &AppState,
+    headers: &HeaderMap,
+    action: &str,
+    target: String,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    let record = AuditLogRecord {
+        actor: request_actor(headers),
+        action: action.to_owned(),
+        target,
+        before_json: before.map(|value| value.to_string()),
+        after_json: after.map(|value| value.to_string()),
+        timestamp: Utc::now(),
+    };
+    if let Err(error) = state.memory.record_audit_log(record).await {
+        warn!(?error, action, "failed to record audit log");
+    }
+}
+
+async fn audit_clear(
+    state: &AppState,
+    headers: &HeaderMap,
+    action: &str,
+    user_id: &str,
+    deleted: u64,
+) {
+    audit(
+        state,
+        headers,
+        action,
+        user_target(user_id),
+        Some(json!({ "count": deleted })),
+        Some(json!({ "count": 0 })),
+    )
+    .await;
+}
+
+fn request_actor(headers: &HeaderMap) -> String {
+    headers
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|actor| !actor.is_empty())
+        .unwrap_or(ANONYMOUS_ACTOR)
+        .to_owned()
+}
+
+fn orchestration_error(error: anyhow::Error) -> (axum::http::StatusCode, String) {
+    if is_busy(&error) {
+        return (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            BUSY_REPLY_TEXT.to_owned(),
+        );
+    }
+    if let Some(limited) = error.downcast_ref::<RateLimited>() {
+        return (
+            axum::http::StatusCode::TOO_MANY_REQUESTS,
+            limited.to_string(),
+        );
+    }
+    if let Some(error) = error.downcast_ref::<ResponseFormatError>() {
+        return (
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            error.to_string(),
+        );
+    }
+    if error.downcast_ref::<TurnFailure>().is_some() {
+        // The failure only names the phase; keep the cause in the message.
+        return (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("internal error: {error:#}"),
+        );
+    }
+    internal_error(error)
+}
+
+fn fact_edit_error(error: anyhow::Error) -> (axum::http::StatusCode, String) {
+    match error.downcast_ref::<FactEditError>() {
+        Some(FactEditError::EmptyKey) => (axum::http::StatusCode::BAD_REQUEST, error.to_string()),
+        Some(FactEditError::MissingFact(_)) => {
+            (axum::http::StatusCode::NOT_FOUND, error.to_string())
+        }
+        Some(FactEditError::KeyTaken(_)) => (axum::http::StatusCode::CONFLICT, error.to_string()),
+        None => internal_error(error),
+    }
+}
+
 fn internal_error(error: anyhow::Error) -> (axum::http::StatusCode, String) {
     (
         axum::http::StatusCode::INTERNAL_SERVER_ERROR,
         format!("internal error: {error}"),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::InMemoryMemoryStore, model::MockModelProvider,
+        orchestrator::GenericChatOrchestrator, safety::SafetyPolicy, tools::ToolRegistry,
+    };
+
+    fn test_state() -> AppState {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = GenericChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        AppState {
+            orchestrator: Arc::new(orchestrator),
+            memory,
+            personas: Arc::new(PersonaRegistry::default()),
+            widgets: Arc::new(WidgetRegistry::default()),
+            event_mappings: Arc::new(EventMappings::default()),
+        }
+    }
+
+    fn fact(value: &str, updated_at: chrono::DateTime<Utc>) -> MemoryFact {
+        MemoryFact {
+            key: "favorite_color".to_owned(),
+            value: value.to_owned(),
+            confidence: 0.9,
+            source: "user_message".to_owned(),
+            updated_at,
+            last_confirmed_at: None,
+        }
+    }
+
+    async fn queue(state: &AppState, value: &str, created_at: chrono::DateTime<Utc>) -> String {
+        state
+            .memory
+            .queue_pending_fact(PendingFactRecord {
+                id: String::new(),
+                user_id: "u1".to_owned(),
+                guild_id: "g1".to_owned(),
+                channel_id: "c1".to_owned(),
+                fact: fact(value, created_at),
+                rationale: "planner".to_owned(),
+                created_at,
+            })
+            .await
+            .expect("queued")
+    }
+
+    #[tokio::test]
+    async fn approving_a_stale_proposal_keeps_the_newer_fact() {
+        let state = test_state();
+        let proposed_at = Utc::now() - chrono::Duration::hours(1);
+        let stale = queue(&state, "teal", proposed_at).await;
+        state
+            .memory
+            .upsert_fact("u1", fact("orange", Utc::now()))
+            .await
+            .expect("stored");
+
+        let conflict =
+            api_approve_pending_fact(State(state.clone()), Path(stale), HeaderMap::new())
+                .await
+                .expect_err("stale proposals are refused");
+        assert_eq!(conflict.0, axum::http::StatusCode::CONFLICT);
+        let facts = state.memory.list_facts("u1", 10).await.expect("facts");
+        assert_eq!(facts[0].value, "orange");
+
+        let fresh = queue(&state, "teal", Utc::now()).await;
+        let Json(approved) =
+            api_approve_pending_fact(State(state.clone()), Path(fresh), HeaderMap::new())
+                .await
+                .expect("fresh proposals are stored");
+        assert_eq!(approved.fact.value, "teal");
+        let facts = state.memory.list_facts("u1", 10).await.expect("facts");
+        assert_eq!(facts[0].value, "teal");
+    }
+
+    fn chat_batch_request(items: Vec<Value>) -> Json<ChatBatchRequest> {
+        Json(serde_json::from_value(json!({ "requests": items })).expect("valid batch"))
+    }
+
+    #[tokio::test]
+    async fn chat_batch_reports_each_item_in_order() {
+        let state = test_state();
+        let Json(items) = chat_batch(
+            State(state.clone()),
+            HeaderMap::new(),
+            chat_batch_request(vec![
+                json!({"user_id": "u1", "content": "hello"}),
+                json!({"user_id": "u2", "content": "hi", "persona": "missing"}),
+                json!({"user_id": "u3", "content": "hey"}),
+            ]),
+        )
+        .await
+        .expect("batch runs");
+
+        let summary = items
+            .iter()
+            .map(|item| (item.index, item.status, item.reply.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(0, 200, true), (1, 400, false), (2, 200, true)]
+        );
+        assert_eq!(items[1].error.as_deref(), Some("unknown persona `missing`"));
+    }
+
+    #[tokio::test]
+    async fn chat_batch_rejects_empty_and_oversized_batches() {
+        let state = test_state();
+        let empty = chat_batch(
+            State(state.clone()),
+            HeaderMap::new(),
+            chat_batch_request(Vec::new()),
+        )
+        .await
+        .expect_err("empty batches are refused");
+        assert_eq!(empty.0, axum::http::StatusCode::BAD_REQUEST);
+
+        let oversized = chat_batch(
+            State(state),
+            HeaderMap::new(),
+            chat_batch_request(vec![
+                json!({"user_id": "u1", "content": "hello"});
+                MAX_BATCH_REQUESTS + 1
+            ]),
+        )
+        .await
+        .expect_err("oversized batches are refused");
+        assert_eq!(oversized.0, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    fn ok_parts() -> axum::http::response::Parts {
+        Response::new(()).into_parts().0
+    }
+
+    #[test]
+    fn etag_cache_answers_not_modified_for_a_matching_tag() {
+        let body = axum::body::Bytes::from_static(b"{\"status\":\"ok\"}");
+        let first = tag_with_etag(ok_parts(), body.clone(), None, HEALTH_MAX_AGE_SECS);
+        assert_eq!(first.status(), axum::http::StatusCode::OK);
+        assert_eq!(first.headers()[header::CACHE_CONTROL], "private, max-age=5");
+        let etag = first.headers()[header::ETAG]
+            .to_str()
+            .expect("ascii etag")
+            .to_owned();
+
+        let weak = format!("\"other\", W/{etag}");
+        let revalidated = tag_with_etag(ok_parts(), body.clone(), Some(&weak), 0);
+        assert_eq!(revalidated.status(), axum::http::StatusCode::NOT_MODIFIED);
+        assert_eq!(revalidated.headers()[header::ETAG], etag.as_str());
+        assert_eq!(
+            revalidated.headers()[header::CACHE_CONTROL],
+            "private, no-cache"
+        );
+
+        let changed = tag_with_etag(
+            ok_parts(),
+            axum::body::Bytes::from_static(b"{\"status\":\"degraded\"}"),
+            Some(&etag),
+            HEALTH_MAX_AGE_SECS,
+        );
+        assert_eq!(changed.status(), axum::http::StatusCode::OK);
+        assert_ne!(changed.headers()[header::ETAG], etag.as_str());
+    }
+
+    #[test]
+    fn any_origin_reaches_only_the_chat_routes() {
+        assert!(is_public_cors_path("/chat", ""));
+        assert!(is_public_cors_path("/chat/batch", ""));
+        assert!(is_public_cors_path("/companion/chat", "/companion"));
+        assert!(!is_public_cors_path("/chat", "/companion"));
+        assert!(!is_public_cors_path("/api/users/u1/facts", ""));
+        assert!(!is_public_cors_path(
+            "/companion/api/admin/audit",
+            "/companion"
+        ));
+        assert!(!is_public_cors_path("/widget/chat", ""));
+    }
+
+    fn command_request(template: &str) -> Json<CustomCommandRequest> {
+        Json(CustomCommandRequest {
+            description: "Summarize our week".to_owned(),
+            template: template.to_owned(),
+        })
+    }
+
+    #[tokio::test]
+    async fn custom_command_endpoints_validate_store_and_delete() {
+        let state = test_state();
+
+        let invalid = api_put_custom_command(
+            State(state.clone()),
+            Path("ask".to_owned()),
+            HeaderMap::new(),
+            command_request("Answer {question}"),
+        )
+        .await
+        .expect_err("built-in names are rejected");
+        assert_eq!(invalid.0, axum::http::StatusCode::BAD_REQUEST);
+
+        let Json(saved) = api_put_custom_command(
+            State(state.clone()),
+            Path("summarize-week".to_owned()),
+            HeaderMap::new(),
+            command_request("Summarize the week of {user}, focusing on {topic}."),
+        )
+        .await
+        .expect("valid command is stored");
+        assert_eq!(saved.options(), vec!["topic".to_owned()]);
+
+        let Json(listed) = api_list_custom_commands(State(state.clone()))
+            .await
+            .expect("commands are listed");
+        assert_eq!(listed, vec![saved]);
+
+        let status = api_delete_custom_command(
+            State(state.clone()),
+            Path("summarize-week".to_owned()),
+            HeaderMap::new(),
+        )
+        .await
+        .expect("stored command is deleted");
+        assert_eq!(status, axum::http::StatusCode::NO_CONTENT);
+
+        let missing = api_delete_custom_command(
+            State(state.clone()),
+            Path("summarize-week".to_owned()),
+            HeaderMap::new(),
+        )
+        .await
+        .expect_err("deleting twice is a 404");
+        assert_eq!(missing.0, axum::http::StatusCode::NOT_FOUND);
+
+        let Json(listed) = api_list_custom_commands(State(state))
+            .await
+            .expect("commands are listed");
+        assert!(listed.is_empty());
+    }
+}
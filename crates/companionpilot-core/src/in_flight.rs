@@ -0,0 +1,208 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::{
+    memory::MemoryStore,
+    types::{InFlightReplyRecord, MessageCtx, TurnPhase},
+};
+
+/// How often a running turn refreshes its in-flight marker.
+pub const IN_FLIGHT_HEARTBEAT: Duration = Duration::from_secs(30);
+/// Markers without a heartbeat for this long belong to a turn that died.
+pub const IN_FLIGHT_STALE_AFTER: Duration = Duration::from_secs(90);
+/// Message id prefix of turns retried after a crash; they are not retried
+/// again, so a message that crashes the bot cannot do it in a loop.
+pub const RECOVERED_ID_PREFIX: &str = "recovered-";
+
+/// What happens to a reply that died mid-flight.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InFlightRecovery {
+    /// Replies are not tracked.
+    Off,
+    /// Tell the user their message was lost and ask them to resend it.
+    #[default]
+    Notify,
+    /// Answer the message again; a retry that dies too falls back to
+    /// notifying.
+    Retry,
+}
+
+impl InFlightRecovery {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Self::Off),
+            "notify" => Some(Self::Notify),
+            "retry" => Some(Self::Retry),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps a turn's in-flight marker alive; the marker is removed when the
+/// turn ends, including when its future is dropped.
+pub struct InFlightGuard {
+    memory: Arc<dyn MemoryStore>,
+    message_id: String,
+    heartbeat: JoinHandle<()>,
+}
+
+impl InFlightGuard {
+    /// Stores the marker, with `content` as the message may be stored, and
+    /// starts its heartbeat. Store errors are logged rather than failing the
+    /// turn.
+    pub async fn start(memory: Arc<dyn MemoryStore>, ctx: &MessageCtx, content: String) -> Self {
+        let now = Utc::now();
+        let record = InFlightReplyRecord {
+            message_id: ctx.message_id.clone(),
+            user_id: ctx.user_id.clone(),
+            guild_id: ctx.guild_id.clone(),
+            channel_id: ctx.channel_id.clone(),
+            content,
+            phase: TurnPhase::LoadContext,
+            started_at: now,
+            updated_at: now,
+        };
+        if let Err(error) = memory.put_in_flight_reply(record).await {
+            warn!(?error, message_id = %ctx.message_id, "failed to mark reply in flight");
+        }
+        let heartbeat = tokio::spawn({
+            let memory = memory.clone();
+            let message_id = ctx.message_id.clone();
+            async move {
+                let mut ticker = tokio::time::interval(IN_FLIGHT_HEARTBEAT);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    if let Err(error) = memory
+                        .touch_in_flight_reply(&message_id, None, Utc::now())
+                        .await
+                    {
+                        warn!(?error, %message_id, "failed to refresh in-flight reply");
+                    }
+                }
+            }
+        });
+        Self {
+            memory,
+            message_id: ctx.message_id.clone(),
+            heartbeat,
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+        let memory = self.memory.clone();
+        let message_id = std::mem::take(&mut self.message_id);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(error) = memory.delete_in_flight_reply(&message_id).await {
+                    warn!(?error, %message_id, "failed to clear in-flight reply");
+                }
+            });
+        }
+    }
+}
+
+/// Claims the markers of turns that stopped sending heartbeats before `now`.
+/// Each marker is deleted as it is claimed, so only one instance recovers it.
+pub async fn claim_stale_in_flight(
+    memory: &dyn MemoryStore,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<InFlightReplyRecord>> {
+    let cutoff = now - chrono::Duration::from_std(IN_FLIGHT_STALE_AFTER)?;
+    let mut claimed = Vec::new();
+    for record in memory.list_stale_in_flight_replies(cutoff).await? {
+        if memory.delete_in_flight_reply(&record.message_id).await? {
+            claimed.push(record);
+        }
+    }
+    Ok(claimed)
+}
+
+/// Whether a lost reply should be answered again under `recovery`.
+pub fn should_retry(recovery: InFlightRecovery, record: &InFlightReplyRecord) -> bool {
+    recovery == InFlightRecovery::Retry && !record.message_id.starts_with(RECOVERED_ID_PREFIX)
+}
+
+/// The message for a user whose reply was lost.
+pub fn lost_reply_notice(record: &InFlightReplyRecord) -> String {
+    let quoted = record.content.chars().take(200).collect::<String>();
+    format!(
+        "Sorry, I restarted while answering your message and lost my reply:\n> {}\nCould you send it again?",
+        quoted.replace('\n', "\n> ")
+    )
+}
+
+/// The message context for answering a lost message again.
+pub fn retry_ctx(record: &InFlightReplyRecord) -> MessageCtx {
+    MessageCtx {
+        message_id: format!("{RECOVERED_ID_PREFIX}{}", record.message_id),
+        user_id: record.user_id.clone(),
+        guild_id: record.guild_id.clone(),
+        channel_id: record.channel_id.clone(),
+        content: record.content.clone(),
+        timestamp: Utc::now(),
+        attachments: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryMemoryStore;
+
+    #[tokio::test]
+    async fn stale_markers_are_claimed_once() {
+        let memory: Arc<dyn MemoryStore> = Arc::new(InMemoryMemoryStore::default());
+        let ctx = MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "what's up?".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let guard = InFlightGuard::start(memory.clone(), &ctx, ctx.content.clone()).await;
+        assert!(
+            memory
+                .touch_in_flight_reply("m1", Some(TurnPhase::Synthesis), Utc::now())
+                .await
+                .expect("touched")
+        );
+        assert!(
+            claim_stale_in_flight(memory.as_ref(), Utc::now())
+                .await
+                .expect("claimed")
+                .is_empty()
+        );
+        // A marker nobody refreshed for a while belongs to a dead turn.
+        std::mem::forget(guard);
+        let later = Utc::now() + chrono::Duration::minutes(5);
+        let claimed = claim_stale_in_flight(memory.as_ref(), later)
+            .await
+            .expect("claimed");
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].phase, TurnPhase::Synthesis);
+        assert!(
+            claim_stale_in_flight(memory.as_ref(), later)
+                .await
+                .expect("claimed")
+                .is_empty()
+        );
+
+        assert!(should_retry(InFlightRecovery::Retry, &claimed[0]));
+        let retried = InFlightReplyRecord {
+            message_id: retry_ctx(&claimed[0]).message_id,
+            ..claimed[0].clone()
+        };
+        assert!(!should_retry(InFlightRecovery::Retry, &retried));
+        assert!(lost_reply_notice(&claimed[0]).contains("> what's up?"));
+    }
+}
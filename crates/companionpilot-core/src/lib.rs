@@ -1,10 +1,71 @@
+pub mod activity;
+pub mod alerts;
+pub mod ambient;
+pub mod audit;
+pub mod background_tasks;
+pub mod builder;
+pub mod calendar;
+pub mod capabilities;
+pub mod concurrency;
 pub mod config;
+pub mod context_prefetch;
+pub mod context_window;
+pub mod coordination;
+pub mod custom_commands;
+pub mod date_facts;
+pub mod date_greetings;
+#[cfg(feature = "discord")]
 pub mod discord_bot;
+pub mod doctor;
+pub mod events;
+pub mod fact_backfill;
+pub mod feedback;
+pub mod flags;
+pub mod guild_emoji;
+pub mod guild_settings;
+#[cfg(feature = "http")]
 pub mod http;
+pub mod in_flight;
 pub mod memory;
+pub mod memory_review;
+pub mod memory_summary;
 pub mod model;
+pub mod model_handoff;
+pub mod model_probe;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod notifications;
+pub mod now_playing;
+pub mod onboarding;
 pub mod orchestrator;
+pub mod outbox;
+pub mod personas;
+pub mod pins;
+pub mod planner_examples;
+pub mod planner_replay;
+pub mod prompt_rollout;
+pub mod proxy;
+pub mod quotas;
+pub mod rate_limit;
+pub mod redaction;
+pub mod reply_filters;
+pub mod response_format;
+pub mod routing;
 pub mod safety;
+pub mod sessions;
+pub mod thoughts;
+pub mod timezone;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod tool_macros;
+pub mod tool_output;
+pub mod tool_stats;
 pub mod tools;
+pub mod transcript;
+pub mod tts_cache;
 pub mod types;
+pub mod user_keys;
+pub mod user_stats;
+#[cfg(feature = "voice")]
 pub mod voice;
+pub mod widgets;
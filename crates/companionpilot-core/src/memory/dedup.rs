@@ -0,0 +1,159 @@
+use crate::types::MemoryFact;
+
+/// Minimum key similarity required before two facts with equal values are
+/// considered the same memory stored under different wording.
+const VALUE_MATCH_MIN_KEY_SIMILARITY: f32 = 0.5;
+
+/// Finds an existing fact that restates `candidate` under a different key.
+///
+/// Facts are near-duplicates when their keys are similar above `threshold`, or
+/// when their values match above `threshold` and the keys are at least loosely
+/// related. Either way every word of one key must match a word of the other,
+/// so `mother_birthday` and `father_birthday` stay apart. Exact key matches
+/// are ignored because a regular upsert handles them.
+pub fn find_near_duplicate<'a>(
+    existing: &'a [MemoryFact],
+    candidate: &MemoryFact,
+    threshold: f32,
+) -> Option<&'a MemoryFact> {
+    existing
+        .iter()
+        .filter(|fact| fact.key != candidate.key)
+        .filter(|fact| key_words_match(&fact.key, &candidate.key, threshold))
+        .filter_map(|fact| {
+            let key_similarity = text_similarity(&fact.key, &candidate.key);
+            let value_similarity = text_similarity(&fact.value, &candidate.value);
+            let is_duplicate = key_similarity >= threshold
+                || (value_similarity >= threshold
+                    && key_similarity >= VALUE_MATCH_MIN_KEY_SIMILARITY);
+            is_duplicate.then_some((fact, key_similarity.max(value_similarity)))
+        })
+        .max_by(|left, right| left.1.total_cmp(&right.1))
+        .map(|(fact, _)| fact)
+}
+
+/// Sørensen–Dice coefficient over character bigrams of the normalized inputs.
+pub fn text_similarity(left: &str, right: &str) -> f32 {
+    let left = normalize(left);
+    let right = normalize(right);
+    if left == right {
+        return 1.0;
+    }
+
+    let left_bigrams = bigrams(&left);
+    let mut right_bigrams = bigrams(&right);
+    if left_bigrams.is_empty() || right_bigrams.is_empty() {
+        return 0.0;
+    }
+
+    let total = left_bigrams.len() + right_bigrams.len();
+    let mut shared = 0usize;
+    for bigram in &left_bigrams {
+        if let Some(index) = right_bigrams.iter().position(|other| other == bigram) {
+            right_bigrams.swap_remove(index);
+            shared += 1;
+        }
+    }
+
+    (2 * shared) as f32 / total as f32
+}
+
+/// Whether each word of either key has a counterpart in the other: the same
+/// word, an abbreviation of it (`fav` for `favorite`) or a spelling variant
+/// similar above `threshold`.
+fn key_words_match(left: &str, right: &str, threshold: f32) -> bool {
+    let left = key_words(left);
+    let right = key_words(right);
+    let matched = |word: &String, others: &[String]| {
+        others.iter().any(|other| {
+            other.starts_with(word.as_str())
+                || word.starts_with(other.as_str())
+                || text_similarity(word, other) >= threshold
+        })
+    };
+    left.iter().all(|word| matched(word, &right)) && right.iter().all(|word| matched(word, &left))
+}
+
+fn key_words(key: &str) -> Vec<String> {
+    key.split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn normalize(input: &str) -> String {
+    input
+        .chars()
+        .filter(|character| character.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn bigrams(input: &str) -> Vec<(char, char)> {
+    let characters = input.chars().collect::<Vec<_>>();
+    characters
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::types::MemoryFact;
+
+    use super::{find_near_duplicate, text_similarity};
+
+    fn fact(key: &str, value: &str) -> MemoryFact {
+        MemoryFact {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            confidence: 0.8,
+            source: "user_message".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        }
+    }
+
+    #[test]
+    fn similarity_ignores_case_and_separators() {
+        assert_eq!(text_similarity("Favorite Game", "favorite_game"), 1.0);
+        assert!(text_similarity("favourite_game", "favorite_game") > 0.8);
+        assert!(text_similarity("name", "favorite_color") < 0.3);
+    }
+
+    #[test]
+    fn finds_restated_preference_under_different_key() {
+        let existing = vec![fact("name", "Petr"), fact("favorite_game", "Factorio")];
+
+        let spelled_differently = fact("favourite_game", "Satisfactory");
+        let duplicate = find_near_duplicate(&existing, &spelled_differently, 0.8)
+            .expect("similar key should match");
+        assert_eq!(duplicate.key, "favorite_game");
+
+        let same_value = fact("fav_game", "factorio");
+        let duplicate =
+            find_near_duplicate(&existing, &same_value, 0.8).expect("same value should match");
+        assert_eq!(duplicate.key, "favorite_game");
+
+        assert!(find_near_duplicate(&existing, &fact("city", "Prague"), 0.8).is_none());
+    }
+
+    #[test]
+    fn keeps_keys_that_differ_in_a_word_apart() {
+        let existing = vec![
+            fact("mother_birthday", "12 May"),
+            fact("favorite_game", "Factorio"),
+        ];
+
+        assert!(find_near_duplicate(&existing, &fact("father_birthday", "12 May"), 0.8).is_none());
+        assert!(find_near_duplicate(&existing, &fact("birthday", "12 May"), 0.8).is_none());
+        assert!(
+            find_near_duplicate(&existing, &fact("favorite_board_game", "Factorio"), 0.8).is_none()
+        );
+        let duplicate = find_near_duplicate(&existing, &fact("Mother Birthday", "May 12"), 0.8)
+            .expect("same words should match");
+        assert_eq!(duplicate.key, "mother_birthday");
+    }
+}
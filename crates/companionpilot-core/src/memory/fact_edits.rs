@@ -0,0 +1,195 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::MemoryFact;
+
+/// Source recorded on facts written through the dashboard.
+pub const DASHBOARD_FACT_SOURCE: &str = "dashboard";
+
+/// One change in a bulk fact edit; edits apply in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FactEdit {
+    Upsert {
+        key: String,
+        value: String,
+        #[serde(default = "default_edit_confidence")]
+        confidence: f32,
+    },
+    Delete {
+        key: String,
+    },
+    Rename {
+        old_key: String,
+        new_key: String,
+    },
+}
+
+fn default_edit_confidence() -> f32 {
+    1.0
+}
+
+impl FactEdit {
+    /// Fact keys this edit reads or writes.
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Self::Upsert { key, .. } | Self::Delete { key } => vec![key],
+            Self::Rename { old_key, new_key } => vec![old_key, new_key],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FactEditSummary {
+    pub upserted: u64,
+    pub deleted: u64,
+    pub renamed: u64,
+}
+
+/// Why a bulk fact edit was rejected; nothing is applied in that case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FactEditError {
+    EmptyKey,
+    MissingFact(String),
+    KeyTaken(String),
+}
+
+impl fmt::Display for FactEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyKey => write!(f, "fact keys must not be empty"),
+            Self::MissingFact(key) => write!(f, "no fact with key `{key}`"),
+            Self::KeyTaken(key) => write!(f, "a fact with key `{key}` already exists"),
+        }
+    }
+}
+
+impl std::error::Error for FactEditError {}
+
+/// Fact written by an `upsert` edit.
+pub fn edited_fact(key: &str, value: &str, confidence: f32, now: DateTime<Utc>) -> MemoryFact {
+    MemoryFact {
+        key: key.to_owned(),
+        value: value.to_owned(),
+        confidence: confidence.clamp(0.0, 1.0),
+        source: DASHBOARD_FACT_SOURCE.to_owned(),
+        updated_at: now,
+        last_confirmed_at: Some(now),
+    }
+}
+
+pub fn check_fact_edits(edits: &[FactEdit]) -> Result<(), FactEditError> {
+    if edits
+        .iter()
+        .flat_map(FactEdit::keys)
+        .any(|key| key.trim().is_empty())
+    {
+        return Err(FactEditError::EmptyKey);
+    }
+    Ok(())
+}
+
+/// Applies `edits` to one user's live facts. On error `facts` is left
+/// untouched; on success the removed facts are returned alongside the summary.
+pub fn apply_fact_edits(
+    facts: &mut Vec<MemoryFact>,
+    edits: &[FactEdit],
+    now: DateTime<Utc>,
+) -> Result<(FactEditSummary, Vec<MemoryFact>), FactEditError> {
+    check_fact_edits(edits)?;
+    let mut edited = facts.clone();
+    let mut removed = Vec::new();
+    let mut summary = FactEditSummary::default();
+    for edit in edits {
+        match edit {
+            FactEdit::Upsert {
+                key,
+                value,
+                confidence,
+            } => {
+                let fact = edited_fact(key, value, *confidence, now);
+                match edited.iter_mut().find(|existing| existing.key == *key) {
+                    Some(existing) => *existing = fact,
+                    None => edited.push(fact),
+                }
+                summary.upserted += 1;
+            }
+            FactEdit::Delete { key } => {
+                if let Some(position) = edited.iter().position(|fact| fact.key == *key) {
+                    removed.push(edited.remove(position));
+                    summary.deleted += 1;
+                }
+            }
+            FactEdit::Rename { old_key, new_key } => {
+                if old_key == new_key {
+                    continue;
+                }
+                if edited.iter().any(|fact| fact.key == *new_key) {
+                    return Err(FactEditError::KeyTaken(new_key.clone()));
+                }
+                let fact = edited
+                    .iter_mut()
+                    .find(|fact| fact.key == *old_key)
+                    .ok_or_else(|| FactEditError::MissingFact(old_key.clone()))?;
+                fact.key = new_key.clone();
+                fact.updated_at = now;
+                summary.renamed += 1;
+            }
+        }
+    }
+    *facts = edited;
+    Ok((summary, removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{FactEdit, FactEditError, apply_fact_edits, edited_fact};
+
+    #[test]
+    fn applies_edits_in_order_or_not_at_all() {
+        let now = Utc::now();
+        let mut facts = vec![
+            edited_fact("favourite_colour", "blue", 0.8, now),
+            edited_fact("home town", "Brno", 0.8, now),
+        ];
+        let edits = serde_json::from_str::<Vec<FactEdit>>(
+            r#"[
+                {"op": "rename", "old_key": "home town", "new_key": "home_town"},
+                {"op": "upsert", "key": "home_town", "value": "Prague"},
+                {"op": "delete", "key": "favourite_colour"}
+            ]"#,
+        )
+        .expect("edits parse");
+
+        let (summary, removed) = apply_fact_edits(&mut facts, &edits, now).expect("edits apply");
+        assert_eq!(
+            (summary.upserted, summary.deleted, summary.renamed),
+            (1, 1, 1)
+        );
+        assert_eq!(removed[0].key, "favourite_colour");
+        assert_eq!(facts.len(), 1);
+        assert_eq!(
+            (facts[0].key.as_str(), facts[0].value.as_str()),
+            ("home_town", "Prague")
+        );
+
+        let conflicting = vec![
+            FactEdit::Delete {
+                key: "home_town".to_owned(),
+            },
+            FactEdit::Rename {
+                old_key: "pet".to_owned(),
+                new_key: "pet_name".to_owned(),
+            },
+        ];
+        assert_eq!(
+            apply_fact_edits(&mut facts, &conflicting, now).err(),
+            Some(FactEditError::MissingFact("pet".to_owned()))
+        );
+        assert_eq!(facts.len(), 1);
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{
         Arc,
         atomic::{AtomicU64, Ordering},
@@ -7,35 +7,116 @@ use std::{
 };
 
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, Utc};
 use tokio::sync::RwLock;
 
-use crate::types::{
-    ChatMessageRecord, MemoryContext, MemoryFact, PlannerDecisionRecord, ToolCallRecord,
-    UserDashboardSummary,
+use crate::{
+    audit::AuditLogQuery,
+    custom_commands::CustomCommand,
+    guild_settings::GuildSettings,
+    model_handoff::ModelHandoff,
+    notifications::NotificationPrefs,
+    onboarding::OnboardingState,
+    prompt_rollout::{PromptRollout, RolloutTurn},
+    types::{
+        AuditLogRecord, BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord, ChatRole,
+        ChatSearchQuery, ChatSession, ContextLimits, InFlightReplyRecord, MemoryContext,
+        MemoryFact, MessageFeedbackRecord, OutboxKind, OutboxRecord, PendingFactRecord,
+        PlannerDecisionRecord, SystemNoticeRecord, ThoughtRecord, ToolCallRecord, ToolUsageRecord,
+        TurnPhase, UserApiKeyRecord, UserDashboardSummary, context_speaker,
+    },
+    user_stats::{UserStats, average_reply_ms},
 };
 
-use super::MemoryStore;
+use super::{FactEdit, FactEditSummary, MemoryStore, apply_fact_edits};
+
+/// (user id, tool name, day)
+type ToolUsageKey = (String, String, NaiveDate);
+/// (user id, guild id, channel id)
+type ChannelKey = (String, String, String);
+
+/// Soft-deleted records per user, with their deletion time.
+type Trash<T> = Arc<RwLock<HashMap<String, Vec<(DateTime<Utc>, T)>>>>;
 
 #[derive(Debug)]
 pub struct InMemoryMemoryStore {
     facts: Arc<RwLock<HashMap<String, Vec<MemoryFact>>>>,
+    deleted_facts: Trash<MemoryFact>,
     summaries: Arc<RwLock<HashMap<String, String>>>,
     chats: Arc<RwLock<HashMap<String, Vec<ChatMessageRecord>>>>,
+    deleted_chats: Trash<ChatMessageRecord>,
+    chat_sessions: Arc<RwLock<HashMap<String, Vec<ChatSession>>>>,
     tool_calls: Arc<RwLock<HashMap<String, Vec<ToolCallRecord>>>>,
     planner_decisions: Arc<RwLock<HashMap<String, Vec<PlannerDecisionRecord>>>>,
+    thoughts: Arc<RwLock<HashMap<String, Vec<ThoughtRecord>>>>,
+    guild_settings: Arc<RwLock<HashMap<String, GuildSettings>>>,
+    custom_commands: Arc<RwLock<HashMap<String, CustomCommand>>>,
+    tool_usage: Arc<RwLock<HashMap<ToolUsageKey, u32>>>,
+    pending_facts: Arc<RwLock<Vec<PendingFactRecord>>>,
+    system_notices: Arc<RwLock<Vec<SystemNoticeRecord>>>,
+    background_tasks: Arc<RwLock<Vec<BackgroundTaskRecord>>>,
+    user_api_keys: Arc<RwLock<HashMap<String, UserApiKeyRecord>>>,
+    calendar_feed_salts: Arc<RwLock<HashMap<String, String>>>,
+    in_flight_replies: Arc<RwLock<HashMap<String, InFlightReplyRecord>>>,
+    outbox: Arc<RwLock<HashMap<String, OutboxRecord>>>,
+    message_feedback: Arc<RwLock<HashMap<String, HashMap<String, MessageFeedbackRecord>>>>,
+    memory_reviews: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+    notification_prefs: Arc<RwLock<HashMap<String, NotificationPrefs>>>,
+    notification_log: Arc<RwLock<HashMap<String, Vec<DateTime<Utc>>>>>,
+    date_greetings: Arc<RwLock<HashSet<(String, String, NaiveDate)>>>,
+    model_handoffs: Arc<RwLock<HashMap<ChannelKey, ModelHandoff>>>,
+    onboarding: Arc<RwLock<HashMap<String, OnboardingState>>>,
+    prompt_rollouts: Arc<RwLock<BTreeMap<String, PromptRollout>>>,
+    prompt_rollout_turns: Arc<RwLock<Vec<RolloutTurn>>>,
+    heartbeats: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+    audit_logs: Arc<RwLock<Vec<AuditLogRecord>>>,
     chat_seq: AtomicU64,
+    pending_seq: AtomicU64,
+    notice_seq: AtomicU64,
+    task_seq: AtomicU64,
+    decision_seq: AtomicU64,
+    session_seq: AtomicU64,
 }
 
 impl Default for InMemoryMemoryStore {
     fn default() -> Self {
         Self {
             facts: Arc::new(RwLock::new(HashMap::new())),
+            deleted_facts: Arc::new(RwLock::new(HashMap::new())),
             summaries: Arc::new(RwLock::new(HashMap::new())),
             chats: Arc::new(RwLock::new(HashMap::new())),
+            deleted_chats: Arc::new(RwLock::new(HashMap::new())),
+            chat_sessions: Arc::new(RwLock::new(HashMap::new())),
             tool_calls: Arc::new(RwLock::new(HashMap::new())),
             planner_decisions: Arc::new(RwLock::new(HashMap::new())),
+            thoughts: Arc::new(RwLock::new(HashMap::new())),
+            guild_settings: Arc::new(RwLock::new(HashMap::new())),
+            custom_commands: Arc::new(RwLock::new(HashMap::new())),
+            tool_usage: Arc::new(RwLock::new(HashMap::new())),
+            pending_facts: Arc::new(RwLock::new(Vec::new())),
+            system_notices: Arc::new(RwLock::new(Vec::new())),
+            background_tasks: Arc::new(RwLock::new(Vec::new())),
+            user_api_keys: Arc::new(RwLock::new(HashMap::new())),
+            calendar_feed_salts: Arc::new(RwLock::new(HashMap::new())),
+            in_flight_replies: Arc::new(RwLock::new(HashMap::new())),
+            outbox: Arc::new(RwLock::new(HashMap::new())),
+            message_feedback: Arc::new(RwLock::new(HashMap::new())),
+            memory_reviews: Arc::new(RwLock::new(HashMap::new())),
+            notification_prefs: Arc::new(RwLock::new(HashMap::new())),
+            notification_log: Arc::new(RwLock::new(HashMap::new())),
+            date_greetings: Arc::new(RwLock::new(HashSet::new())),
+            model_handoffs: Arc::new(RwLock::new(HashMap::new())),
+            onboarding: Arc::new(RwLock::new(HashMap::new())),
+            prompt_rollouts: Arc::new(RwLock::new(BTreeMap::new())),
+            prompt_rollout_turns: Arc::new(RwLock::new(Vec::new())),
+            heartbeats: Arc::new(RwLock::new(HashMap::new())),
+            audit_logs: Arc::new(RwLock::new(Vec::new())),
             chat_seq: AtomicU64::new(1),
+            pending_seq: AtomicU64::new(1),
+            notice_seq: AtomicU64::new(1),
+            task_seq: AtomicU64::new(1),
+            decision_seq: AtomicU64::new(1),
+            session_seq: AtomicU64::new(1),
         }
     }
 }
@@ -47,14 +128,19 @@ impl MemoryStore for InMemoryMemoryStore {
         user_id: &str,
         guild_id: &str,
         channel_id: &str,
+        limits: ContextLimits,
     ) -> anyhow::Result<MemoryContext> {
-        let facts = self
+        let mut facts = self
             .facts
             .read()
             .await
             .get(user_id)
             .cloned()
             .unwrap_or_default();
+        if facts.len() > limits.facts {
+            facts.sort_by_key(|fact| std::cmp::Reverse(fact.updated_at));
+            facts.truncate(limits.facts);
+        }
         let summary = self.summaries.read().await.get(user_id).cloned();
         let recent_messages = self
             .chats
@@ -66,8 +152,14 @@ impl MemoryStore for InMemoryMemoryStore {
             .into_iter()
             .filter(|message| message.guild_id == guild_id && message.channel_id == channel_id)
             .rev()
-            .take(8)
-            .map(|message| format!("{}: {}", message.role.as_str(), message.content))
+            .take(limits.recent_messages)
+            .map(|message| {
+                format!(
+                    "{}: {}",
+                    context_speaker(message.role, message.modality),
+                    message.content
+                )
+            })
             .collect::<Vec<_>>()
             .into_iter()
             .rev()
@@ -77,10 +169,21 @@ impl MemoryStore for InMemoryMemoryStore {
             summary,
             recent_messages,
             facts,
+            other_channel_messages: Vec::new(),
+            now_playing: None,
+            pinned_messages: Vec::new(),
+            ambient: Vec::new(),
+            system_notices: Vec::new(),
+            capabilities: None,
+            guild_emoji: None,
+            handoff: None,
         })
     }
 
     async fn upsert_fact(&self, user_id: &str, fact: MemoryFact) -> anyhow::Result<()> {
+        if let Some(deleted) = self.deleted_facts.write().await.get_mut(user_id) {
+            deleted.retain(|(_, deleted_fact)| deleted_fact.key != fact.key);
+        }
         let mut facts = self.facts.write().await;
         let user_facts = facts.entry(user_id.to_owned()).or_default();
 
@@ -94,13 +197,48 @@ impl MemoryStore for InMemoryMemoryStore {
     }
 
     async fn delete_fact(&self, user_id: &str, key: &str) -> anyhow::Result<bool> {
-        let mut facts = self.facts.write().await;
-        let Some(user_facts) = facts.get_mut(user_id) else {
-            return Ok(false);
+        let fact = {
+            let mut facts = self.facts.write().await;
+            let Some(user_facts) = facts.get_mut(user_id) else {
+                return Ok(false);
+            };
+            let Some(position) = user_facts.iter().position(|fact| fact.key == key) else {
+                return Ok(false);
+            };
+            user_facts.remove(position)
+        };
+        self.deleted_facts
+            .write()
+            .await
+            .entry(user_id.to_owned())
+            .or_default()
+            .push((Utc::now(), fact));
+        Ok(true)
+    }
+
+    async fn apply_fact_edits(
+        &self,
+        user_id: &str,
+        edits: &[FactEdit],
+    ) -> anyhow::Result<FactEditSummary> {
+        let now = Utc::now();
+        let (summary, removed) = {
+            let mut facts = self.facts.write().await;
+            let user_facts = facts.entry(user_id.to_owned()).or_default();
+            apply_fact_edits(user_facts, edits, now)?
         };
-        let initial_len = user_facts.len();
-        user_facts.retain(|fact| fact.key != key);
-        Ok(user_facts.len() != initial_len)
+        let mut deleted = self.deleted_facts.write().await;
+        let user_deleted = deleted.entry(user_id.to_owned()).or_default();
+        // Keys written by this batch must not be resurrected by an undo.
+        user_deleted.retain(|(_, fact)| {
+            !edits.iter().any(|edit| match edit {
+                FactEdit::Upsert { key, .. } => *key == fact.key,
+                FactEdit::Rename { new_key, .. } => *new_key == fact.key,
+                FactEdit::Delete { .. } => false,
+            })
+        });
+        user_deleted.extend(removed.into_iter().map(|fact| (now, fact)));
+        Ok(summary)
     }
 
     async fn search_relevant(
@@ -138,7 +276,7 @@ impl MemoryStore for InMemoryMemoryStore {
         Ok(facts)
     }
 
-    async fn record_chat_message(&self, message: ChatMessageRecord) -> anyhow::Result<()> {
+    async fn record_chat_message(&self, message: ChatMessageRecord) -> anyhow::Result<String> {
         let user_id = message.user_id.clone();
         let mut chats = self.chats.write().await;
         let mut message = message;
@@ -146,8 +284,9 @@ impl MemoryStore for InMemoryMemoryStore {
             let id = self.chat_seq.fetch_add(1, Ordering::Relaxed);
             message.id = format!("local-{id}");
         }
+        let id = message.id.clone();
         chats.entry(user_id).or_default().push(message);
-        Ok(())
+        Ok(id)
     }
 
     async fn list_chat_messages(
@@ -170,6 +309,73 @@ impl MemoryStore for InMemoryMemoryStore {
         Ok(messages)
     }
 
+    async fn list_channel_messages(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let mut messages = self
+            .chats
+            .read()
+            .await
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter(|message| message.guild_id == guild_id && message.channel_id == channel_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.timestamp);
+        if messages.len() > limit {
+            let start = messages.len().saturating_sub(limit);
+            messages = messages.split_off(start);
+        }
+        Ok(messages)
+    }
+
+    async fn search_chat_messages(
+        &self,
+        user_id: &str,
+        query: &ChatSearchQuery,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let terms = query
+            .text
+            .split(|ch: char| !ch.is_alphanumeric())
+            .filter(|term| !term.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut messages = self
+            .chats
+            .read()
+            .await
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|message| {
+                message.guild_id == query.guild_id
+                    && query.after.is_none_or(|after| message.timestamp >= after)
+                    && query.before.is_none_or(|before| message.timestamp < before)
+            })
+            .filter(|message| {
+                // Whole words, like Postgres' `simple` text search.
+                let words = message
+                    .content
+                    .split(|ch: char| !ch.is_alphanumeric())
+                    .map(str::to_lowercase)
+                    .collect::<HashSet<_>>();
+                terms.iter().all(|term| words.contains(term))
+            })
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| std::cmp::Reverse(message.timestamp));
+        messages.truncate(query.limit);
+        Ok(messages)
+    }
+
     async fn delete_chat_message(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool> {
         let mut chats = self.chats.write().await;
         let Some(user_chats) = chats.get_mut(user_id) else {
@@ -180,22 +386,322 @@ impl MemoryStore for InMemoryMemoryStore {
         Ok(user_chats.len() != initial_len)
     }
 
-    async fn clear_chat_messages(&self, user_id: &str) -> anyhow::Result<u64> {
+    async fn set_chat_message_pinned(
+        &self,
+        user_id: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> anyhow::Result<bool> {
         let mut chats = self.chats.write().await;
-        let removed = chats
-            .remove(user_id)
-            .map(|list| list.len() as u64)
-            .unwrap_or(0);
-        Ok(removed)
+        let Some(message) = chats
+            .get_mut(user_id)
+            .and_then(|messages| messages.iter_mut().find(|message| message.id == message_id))
+        else {
+            return Ok(false);
+        };
+        message.pinned = pinned;
+        Ok(true)
+    }
+
+    async fn list_pinned_messages(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let mut messages = self
+            .chats
+            .read()
+            .await
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|message| message.pinned && message.guild_id == guild_id)
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.timestamp);
+        if messages.len() > limit {
+            messages = messages.split_off(messages.len() - limit);
+        }
+        Ok(messages)
+    }
+
+    async fn set_reply_source_message(
+        &self,
+        user_id: &str,
+        answered_message_id: &str,
+        reply_message_id: &str,
+    ) -> anyhow::Result<bool> {
+        let mut chats = self.chats.write().await;
+        let Some(reply) = chats.get_mut(user_id).and_then(|messages| {
+            messages.iter_mut().rev().find(|message| {
+                message.role == ChatRole::Assistant
+                    && message.source_message_id.as_deref() == Some(answered_message_id)
+            })
+        }) else {
+            return Ok(false);
+        };
+        reply.source_message_id = Some(reply_message_id.to_owned());
+        Ok(true)
+    }
+
+    async fn has_recorded_turn(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool> {
+        Ok(self
+            .chats
+            .read()
+            .await
+            .get(user_id)
+            .is_some_and(|messages| {
+                messages.iter().any(|message| {
+                    message.role == ChatRole::User
+                        && message.source_message_id.as_deref() == Some(message_id)
+                })
+            }))
+    }
+
+    async fn set_message_feedback(&self, feedback: MessageFeedbackRecord) -> anyhow::Result<()> {
+        let mut all_feedback = self.message_feedback.write().await;
+        let user_feedback = all_feedback.entry(feedback.user_id.clone()).or_default();
+        if feedback.score == 0 {
+            user_feedback.remove(&feedback.message_id);
+        } else {
+            user_feedback.insert(feedback.message_id.clone(), feedback);
+        }
+        Ok(())
+    }
+
+    async fn list_message_feedback(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Vec<MessageFeedbackRecord>> {
+        Ok(self
+            .message_feedback
+            .read()
+            .await
+            .get(user_id)
+            .map(|feedback| feedback.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn start_chat_session(
+        &self,
+        user_id: &str,
+        started_at: DateTime<Utc>,
+    ) -> anyhow::Result<ChatSession> {
+        let session = ChatSession {
+            id: format!(
+                "session-{}",
+                self.session_seq.fetch_add(1, Ordering::Relaxed)
+            ),
+            user_id: user_id.to_owned(),
+            title: None,
+            started_at,
+            last_active_at: started_at,
+        };
+        self.chat_sessions
+            .write()
+            .await
+            .entry(user_id.to_owned())
+            .or_default()
+            .push(session.clone());
+        Ok(session)
+    }
+
+    async fn current_chat_session(&self, user_id: &str) -> anyhow::Result<Option<ChatSession>> {
+        Ok(self.list_chat_sessions(user_id, 1).await?.pop())
+    }
+
+    async fn touch_chat_session(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let mut sessions = self.chat_sessions.write().await;
+        let Some(session) = sessions
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.iter_mut().find(|session| session.id == session_id))
+        else {
+            return Ok(false);
+        };
+        session.last_active_at = session.last_active_at.max(at);
+        Ok(true)
+    }
+
+    async fn set_chat_session_title(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        title: &str,
+    ) -> anyhow::Result<bool> {
+        let mut sessions = self.chat_sessions.write().await;
+        let Some(session) = sessions
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.iter_mut().find(|session| session.id == session_id))
+        else {
+            return Ok(false);
+        };
+        session.title = Some(title.to_owned());
+        Ok(true)
+    }
+
+    async fn list_chat_sessions(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatSession>> {
+        let mut sessions = self
+            .chat_sessions
+            .read()
+            .await
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default();
+        // Newer sessions win ties, e.g. one started in the same instant.
+        sessions.reverse();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_active_at));
+        sessions.truncate(limit);
+        Ok(sessions)
+    }
+
+    async fn list_session_messages(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let mut messages = self
+            .chats
+            .read()
+            .await
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|message| message.session_id.as_deref() == Some(session_id))
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.timestamp);
+        if messages.len() > limit {
+            messages = messages.split_off(messages.len() - limit);
+        }
+        Ok(messages)
+    }
+
+    async fn clear_chat_sessions(&self, user_id: &str) -> anyhow::Result<u64> {
+        let removed = self.chat_sessions.write().await.remove(user_id);
+        Ok(removed.map_or(0, |sessions| sessions.len() as u64))
+    }
+
+    async fn clear_chat_messages(&self, user_id: &str) -> anyhow::Result<u64> {
+        let removed = self.chats.write().await.remove(user_id).unwrap_or_default();
+        let count = removed.len() as u64;
+        let now = Utc::now();
+        self.deleted_chats
+            .write()
+            .await
+            .entry(user_id.to_owned())
+            .or_default()
+            .extend(removed.into_iter().map(|message| (now, message)));
+        Ok(count)
     }
 
     async fn clear_facts(&self, user_id: &str) -> anyhow::Result<u64> {
+        let removed = self.facts.write().await.remove(user_id).unwrap_or_default();
+        let count = removed.len() as u64;
+        let now = Utc::now();
+        self.deleted_facts
+            .write()
+            .await
+            .entry(user_id.to_owned())
+            .or_default()
+            .extend(removed.into_iter().map(|fact| (now, fact)));
+        Ok(count)
+    }
+
+    async fn restore_chat_messages(
+        &self,
+        user_id: &str,
+        deleted_since: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let restored = {
+            let mut deleted = self.deleted_chats.write().await;
+            let Some(user_deleted) = deleted.get_mut(user_id) else {
+                return Ok(0);
+            };
+            let (restored, kept) = std::mem::take(user_deleted)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(deleted_at, _)| *deleted_at >= deleted_since);
+            *user_deleted = kept;
+            restored
+        };
+        let count = restored.len() as u64;
+        let mut chats = self.chats.write().await;
+        let user_chats = chats.entry(user_id.to_owned()).or_default();
+        user_chats.extend(restored.into_iter().map(|(_, message)| message));
+        user_chats.sort_by_key(|message| message.timestamp);
+        Ok(count)
+    }
+
+    async fn restore_facts(
+        &self,
+        user_id: &str,
+        key: Option<&str>,
+        deleted_since: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let mut deleted = self.deleted_facts.write().await;
+        let Some(user_deleted) = deleted.get_mut(user_id) else {
+            return Ok(0);
+        };
         let mut facts = self.facts.write().await;
-        let removed = facts
-            .remove(user_id)
-            .map(|list| list.len() as u64)
-            .unwrap_or(0);
-        Ok(removed)
+        let user_facts = facts.entry(user_id.to_owned()).or_default();
+        let mut restored = 0;
+        user_deleted.retain(|(deleted_at, fact)| {
+            let restorable = *deleted_at >= deleted_since
+                && key.is_none_or(|key| key == fact.key)
+                && !user_facts.iter().any(|live| live.key == fact.key);
+            if restorable {
+                user_facts.push(fact.clone());
+                restored += 1;
+            }
+            !restorable
+        });
+        Ok(restored)
+    }
+
+    async fn list_deleted_fact_keys(&self, user_id: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .deleted_facts
+            .read()
+            .await
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .map(|(_, fact)| fact.key.clone())
+            .collect())
+    }
+
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let mut purged = 0;
+        for user_deleted in self.deleted_facts.write().await.values_mut() {
+            let before = user_deleted.len();
+            user_deleted.retain(|(deleted_at, _)| *deleted_at >= deleted_before);
+            purged += (before - user_deleted.len()) as u64;
+        }
+        for user_deleted in self.deleted_chats.write().await.values_mut() {
+            let before = user_deleted.len();
+            user_deleted.retain(|(deleted_at, _)| *deleted_at >= deleted_before);
+            purged += (before - user_deleted.len()) as u64;
+        }
+        Ok(purged)
+    }
+
+    async fn purge_user_deleted(&self, user_id: &str) -> anyhow::Result<u64> {
+        let facts = self.deleted_facts.write().await.remove(user_id);
+        let messages = self.deleted_chats.write().await.remove(user_id);
+        Ok(
+            (facts.map_or(0, |facts| facts.len()) + messages.map_or(0, |messages| messages.len()))
+                as u64,
+        )
     }
 
     async fn clear_tool_calls(&self, user_id: &str) -> anyhow::Result<u64> {
@@ -261,6 +767,43 @@ impl MemoryStore for InMemoryMemoryStore {
         Ok(users)
     }
 
+    async fn user_stats(&self, user_id: &str) -> anyhow::Result<UserStats> {
+        let chats = self.chats.read().await;
+        let messages = chats.get(user_id).map(Vec::as_slice).unwrap_or_default();
+        let count = |role| {
+            messages
+                .iter()
+                .filter(|message| message.role == role)
+                .count() as i64
+        };
+        let mut tool_calls = HashMap::<String, i64>::new();
+        for call in self
+            .tool_calls
+            .read()
+            .await
+            .get(user_id)
+            .into_iter()
+            .flatten()
+        {
+            *tool_calls.entry(call.tool_name.clone()).or_default() += 1;
+        }
+        let mut tool_calls = tool_calls.into_iter().collect::<Vec<_>>();
+        tool_calls.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(UserStats {
+            messages_sent: count(ChatRole::User),
+            replies_received: count(ChatRole::Assistant),
+            fact_count: self
+                .facts
+                .read()
+                .await
+                .get(user_id)
+                .map_or(0, |facts| facts.len() as i64),
+            tool_calls,
+            avg_reply_ms: average_reply_ms(messages),
+            first_message_at: messages.iter().map(|message| message.timestamp).min(),
+        })
+    }
+
     async fn record_tool_call(&self, tool_call: ToolCallRecord) -> anyhow::Result<()> {
         let user_id = tool_call.user_id.clone();
         let mut tool_calls = self.tool_calls.write().await;
@@ -288,13 +831,72 @@ impl MemoryStore for InMemoryMemoryStore {
         Ok(calls)
     }
 
+    async fn record_thought(&self, thought: ThoughtRecord) -> anyhow::Result<()> {
+        self.thoughts
+            .write()
+            .await
+            .entry(thought.user_id.clone())
+            .or_default()
+            .push(thought);
+        Ok(())
+    }
+
+    async fn list_thoughts(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ThoughtRecord>> {
+        let mut thoughts = self
+            .thoughts
+            .read()
+            .await
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default();
+        thoughts.sort_by_key(|thought| thought.timestamp);
+        if thoughts.len() > limit {
+            thoughts = thoughts.split_off(thoughts.len() - limit);
+        }
+        Ok(thoughts)
+    }
+
+    async fn clear_thoughts(&self, user_id: &str) -> anyhow::Result<u64> {
+        Ok(self
+            .thoughts
+            .write()
+            .await
+            .remove(user_id)
+            .map_or(0, |thoughts| thoughts.len() as u64))
+    }
+
     async fn record_planner_decision(&self, decision: PlannerDecisionRecord) -> anyhow::Result<()> {
+        let id = self
+            .decision_seq
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
         let user_id = decision.user_id.clone();
         let mut decisions = self.planner_decisions.write().await;
-        decisions.entry(user_id).or_default().push(decision);
+        decisions
+            .entry(user_id)
+            .or_default()
+            .push(PlannerDecisionRecord { id, ..decision });
         Ok(())
     }
 
+    async fn get_planner_decision(
+        &self,
+        id: &str,
+    ) -> anyhow::Result<Option<PlannerDecisionRecord>> {
+        Ok(self
+            .planner_decisions
+            .read()
+            .await
+            .values()
+            .flatten()
+            .find(|decision| decision.id == id)
+            .cloned())
+    }
+
     async fn list_planner_decisions(
         &self,
         user_id: &str,
@@ -314,4 +916,590 @@ impl MemoryStore for InMemoryMemoryStore {
         }
         Ok(decisions)
     }
+
+    async fn list_planner_decisions_of_kind(
+        &self,
+        user_id: &str,
+        planner: &str,
+        decision: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PlannerDecisionRecord>> {
+        let mut decisions = self
+            .planner_decisions
+            .read()
+            .await
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter(|record| record.planner == planner && record.decision == decision)
+            .cloned()
+            .collect::<Vec<_>>();
+        decisions.sort_by_key(|decision| decision.timestamp);
+        if decisions.len() > limit {
+            let start = decisions.len().saturating_sub(limit);
+            decisions = decisions.split_off(start);
+        }
+        Ok(decisions)
+    }
+
+    async fn get_guild_settings(&self, guild_id: &str) -> anyhow::Result<Option<GuildSettings>> {
+        Ok(self.guild_settings.read().await.get(guild_id).cloned())
+    }
+
+    async fn put_guild_settings(&self, settings: GuildSettings) -> anyhow::Result<()> {
+        self.guild_settings
+            .write()
+            .await
+            .insert(settings.guild_id.clone(), settings);
+        Ok(())
+    }
+
+    async fn list_custom_commands(&self) -> anyhow::Result<Vec<CustomCommand>> {
+        let mut commands = self
+            .custom_commands
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        commands.sort_by(|left, right| left.name.cmp(&right.name));
+        Ok(commands)
+    }
+
+    async fn put_custom_command(&self, command: CustomCommand) -> anyhow::Result<()> {
+        self.custom_commands
+            .write()
+            .await
+            .insert(command.name.clone(), command);
+        Ok(())
+    }
+
+    async fn delete_custom_command(&self, name: &str) -> anyhow::Result<bool> {
+        Ok(self.custom_commands.write().await.remove(name).is_some())
+    }
+
+    async fn try_increment_tool_usage(
+        &self,
+        user_id: &str,
+        tool_name: &str,
+        day: NaiveDate,
+        limit: u32,
+    ) -> anyhow::Result<bool> {
+        let mut usage = self.tool_usage.write().await;
+        let count = usage
+            .entry((user_id.to_owned(), tool_name.to_owned(), day))
+            .or_default();
+        if *count >= limit {
+            return Ok(false);
+        }
+        *count += 1;
+        Ok(true)
+    }
+
+    async fn list_tool_usage(
+        &self,
+        user_id: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<Vec<ToolUsageRecord>> {
+        let mut records = self
+            .tool_usage
+            .read()
+            .await
+            .iter()
+            .filter(|((usage_user_id, _, usage_day), _)| {
+                usage_user_id == user_id && *usage_day == day
+            })
+            .map(|((user_id, tool_name, day), count)| ToolUsageRecord {
+                user_id: user_id.clone(),
+                tool_name: tool_name.clone(),
+                day: *day,
+                count: *count,
+            })
+            .collect::<Vec<_>>();
+        records.sort_by(|left, right| left.tool_name.cmp(&right.tool_name));
+        Ok(records)
+    }
+
+    async fn reset_tool_usage(
+        &self,
+        user_id: &str,
+        tool_name: Option<&str>,
+        day: NaiveDate,
+    ) -> anyhow::Result<u64> {
+        let mut usage = self.tool_usage.write().await;
+        let before = usage.len();
+        usage.retain(|(usage_user_id, usage_tool_name, usage_day), _| {
+            !(usage_user_id == user_id
+                && *usage_day == day
+                && tool_name.is_none_or(|tool_name| tool_name == usage_tool_name))
+        });
+        Ok((before - usage.len()) as u64)
+    }
+
+    async fn list_stale_facts(
+        &self,
+        stale_before: DateTime<Utc>,
+        below_confidence: f32,
+        reviewed_before: DateTime<Utc>,
+        after_user_id: Option<&str>,
+        max_users: usize,
+        per_user: usize,
+    ) -> anyhow::Result<Vec<(String, MemoryFact)>> {
+        let reviews = self.memory_reviews.read().await;
+        let facts = self.facts.read().await;
+        let mut pages = facts
+            .iter()
+            .filter(|(user_id, _)| after_user_id.is_none_or(|after| user_id.as_str() > after))
+            .filter(|(user_id, _)| {
+                reviews
+                    .get(*user_id)
+                    .is_none_or(|sent_at| *sent_at < reviewed_before)
+            })
+            .filter_map(|(user_id, facts)| {
+                let mut stale = facts
+                    .iter()
+                    .filter(|fact| {
+                        fact.last_confirmed_at.unwrap_or(fact.updated_at) < stale_before
+                            || fact.confidence < below_confidence
+                    })
+                    .collect::<Vec<_>>();
+                stale.sort_by_key(|fact| fact.last_confirmed_at.unwrap_or(fact.updated_at));
+                (!stale.is_empty()).then_some((user_id, stale))
+            })
+            .collect::<Vec<_>>();
+        pages.sort_by(|left, right| left.0.cmp(right.0));
+        Ok(pages
+            .into_iter()
+            .take(max_users)
+            .flat_map(|(user_id, stale)| {
+                stale
+                    .into_iter()
+                    .take(per_user)
+                    .map(|fact| (user_id.clone(), fact.clone()))
+            })
+            .collect())
+    }
+
+    async fn last_memory_review(&self, user_id: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        Ok(self.memory_reviews.read().await.get(user_id).copied())
+    }
+
+    async fn record_memory_review(
+        &self,
+        user_id: &str,
+        sent_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.memory_reviews
+            .write()
+            .await
+            .insert(user_id.to_owned(), sent_at);
+        Ok(())
+    }
+
+    async fn get_notification_prefs(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Option<NotificationPrefs>> {
+        Ok(self.notification_prefs.read().await.get(user_id).cloned())
+    }
+
+    async fn put_notification_prefs(&self, prefs: NotificationPrefs) -> anyhow::Result<()> {
+        self.notification_prefs
+            .write()
+            .await
+            .insert(prefs.user_id.clone(), prefs);
+        Ok(())
+    }
+
+    async fn record_notification(
+        &self,
+        user_id: &str,
+        _kind: &str,
+        sent_at: DateTime<Utc>,
+        keep_since: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let mut log = self.notification_log.write().await;
+        let sent = log.entry(user_id.to_owned()).or_default();
+        sent.retain(|earlier| *earlier >= keep_since);
+        sent.push(sent_at);
+        Ok(())
+    }
+
+    async fn count_notifications_since(
+        &self,
+        user_id: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<usize> {
+        Ok(self
+            .notification_log
+            .read()
+            .await
+            .get(user_id)
+            .map_or(0, |sent| {
+                sent.iter().filter(|sent_at| **sent_at >= since).count()
+            }))
+    }
+
+    async fn has_date_greeting(
+        &self,
+        user_id: &str,
+        fact_key: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<bool> {
+        Ok(self.date_greetings.read().await.contains(&(
+            user_id.to_owned(),
+            fact_key.to_owned(),
+            day,
+        )))
+    }
+
+    async fn record_date_greeting(
+        &self,
+        user_id: &str,
+        fact_key: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<bool> {
+        Ok(self
+            .date_greetings
+            .write()
+            .await
+            .insert((user_id.to_owned(), fact_key.to_owned(), day)))
+    }
+
+    async fn get_model_handoff(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+    ) -> anyhow::Result<Option<ModelHandoff>> {
+        let key = (
+            user_id.to_owned(),
+            guild_id.to_owned(),
+            channel_id.to_owned(),
+        );
+        Ok(self.model_handoffs.read().await.get(&key).cloned())
+    }
+
+    async fn put_model_handoff(&self, handoff: ModelHandoff) -> anyhow::Result<()> {
+        let key = (
+            handoff.user_id.clone(),
+            handoff.guild_id.clone(),
+            handoff.channel_id.clone(),
+        );
+        self.model_handoffs.write().await.insert(key, handoff);
+        Ok(())
+    }
+
+    async fn put_prompt_rollout(&self, rollout: PromptRollout) -> anyhow::Result<()> {
+        self.prompt_rollouts
+            .write()
+            .await
+            .insert(rollout.persona.clone(), rollout);
+        Ok(())
+    }
+
+    async fn list_prompt_rollouts(&self) -> anyhow::Result<Vec<PromptRollout>> {
+        Ok(self
+            .prompt_rollouts
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn record_prompt_rollout_turn(&self, turn: RolloutTurn) -> anyhow::Result<()> {
+        self.prompt_rollout_turns.write().await.push(turn);
+        Ok(())
+    }
+
+    async fn list_prompt_rollout_turns(
+        &self,
+        persona: &str,
+        started_at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<RolloutTurn>> {
+        Ok(self
+            .prompt_rollout_turns
+            .read()
+            .await
+            .iter()
+            .filter(|turn| turn.persona == persona && turn.rollout_started_at == started_at)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_onboarding(&self, user_id: &str) -> anyhow::Result<Option<OnboardingState>> {
+        Ok(self.onboarding.read().await.get(user_id).cloned())
+    }
+
+    async fn put_onboarding(&self, state: OnboardingState) -> anyhow::Result<()> {
+        self.onboarding
+            .write()
+            .await
+            .insert(state.user_id.clone(), state);
+        Ok(())
+    }
+
+    async fn last_seen_at(&self, adapter: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        Ok(self.heartbeats.read().await.get(adapter).copied())
+    }
+
+    async fn record_last_seen(&self, adapter: &str, seen_at: DateTime<Utc>) -> anyhow::Result<()> {
+        self.heartbeats
+            .write()
+            .await
+            .insert(adapter.to_owned(), seen_at);
+        Ok(())
+    }
+
+    async fn queue_pending_fact(&self, pending: PendingFactRecord) -> anyhow::Result<String> {
+        let id = format!(
+            "pending-{}",
+            self.pending_seq.fetch_add(1, Ordering::Relaxed)
+        );
+        self.pending_facts.write().await.push(PendingFactRecord {
+            id: id.clone(),
+            ..pending
+        });
+        Ok(id)
+    }
+
+    async fn list_pending_facts(
+        &self,
+        user_id: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PendingFactRecord>> {
+        Ok(self
+            .pending_facts
+            .read()
+            .await
+            .iter()
+            .filter(|pending| user_id.is_none_or(|user_id| pending.user_id == user_id))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn take_pending_fact(&self, id: &str) -> anyhow::Result<Option<PendingFactRecord>> {
+        let mut pending_facts = self.pending_facts.write().await;
+        Ok(pending_facts
+            .iter()
+            .position(|pending| pending.id == id)
+            .map(|position| pending_facts.remove(position)))
+    }
+
+    async fn create_background_task(&self, task: BackgroundTaskRecord) -> anyhow::Result<String> {
+        let id = format!("task-{}", self.task_seq.fetch_add(1, Ordering::Relaxed));
+        self.background_tasks
+            .write()
+            .await
+            .push(BackgroundTaskRecord {
+                id: id.clone(),
+                ..task
+            });
+        Ok(id)
+    }
+
+    async fn update_background_task(
+        &self,
+        id: &str,
+        status: BackgroundTaskStatus,
+        result_text: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let mut background_tasks = self.background_tasks.write().await;
+        let Some(task) = background_tasks.iter_mut().find(|task| task.id == id) else {
+            return Ok(false);
+        };
+        task.status = status;
+        if let Some(result_text) = result_text {
+            task.result_text = Some(result_text.to_owned());
+        }
+        task.updated_at = at;
+        Ok(true)
+    }
+
+    async fn list_background_tasks(
+        &self,
+        user_id: Option<&str>,
+        statuses: &[BackgroundTaskStatus],
+        limit: usize,
+    ) -> anyhow::Result<Vec<BackgroundTaskRecord>> {
+        Ok(self
+            .background_tasks
+            .read()
+            .await
+            .iter()
+            .rev()
+            .filter(|task| user_id.is_none_or(|user_id| task.user_id == user_id))
+            .filter(|task| statuses.is_empty() || statuses.contains(&task.status))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn add_system_notice(&self, notice: SystemNoticeRecord) -> anyhow::Result<String> {
+        let id = format!("notice-{}", self.notice_seq.fetch_add(1, Ordering::Relaxed));
+        self.system_notices.write().await.push(SystemNoticeRecord {
+            id: id.clone(),
+            ..notice
+        });
+        Ok(id)
+    }
+
+    async fn list_system_notices(
+        &self,
+        user_id: &str,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<SystemNoticeRecord>> {
+        Ok(self
+            .system_notices
+            .read()
+            .await
+            .iter()
+            .filter(|notice| notice.user_id == user_id && notice.expires_at > now)
+            .cloned()
+            .collect())
+    }
+
+    async fn consume_system_notices(
+        &self,
+        user_id: &str,
+        ids: &[String],
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.system_notices.write().await.retain(|notice| {
+            notice.user_id != user_id || (notice.expires_at > now && !ids.contains(&notice.id))
+        });
+        Ok(())
+    }
+
+    async fn put_in_flight_reply(&self, record: InFlightReplyRecord) -> anyhow::Result<()> {
+        self.in_flight_replies
+            .write()
+            .await
+            .insert(record.message_id.clone(), record);
+        Ok(())
+    }
+
+    async fn touch_in_flight_reply(
+        &self,
+        message_id: &str,
+        phase: Option<TurnPhase>,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let mut in_flight = self.in_flight_replies.write().await;
+        let Some(record) = in_flight.get_mut(message_id) else {
+            return Ok(false);
+        };
+        if let Some(phase) = phase {
+            record.phase = phase;
+        }
+        record.updated_at = now;
+        Ok(true)
+    }
+
+    async fn delete_in_flight_reply(&self, message_id: &str) -> anyhow::Result<bool> {
+        Ok(self
+            .in_flight_replies
+            .write()
+            .await
+            .remove(message_id)
+            .is_some())
+    }
+
+    async fn list_stale_in_flight_replies(
+        &self,
+        updated_before: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<InFlightReplyRecord>> {
+        let mut stale = self
+            .in_flight_replies
+            .read()
+            .await
+            .values()
+            .filter(|record| record.updated_at < updated_before)
+            .cloned()
+            .collect::<Vec<_>>();
+        stale.sort_by_key(|record| record.started_at);
+        Ok(stale)
+    }
+
+    async fn put_outbox_message(&self, record: OutboxRecord) -> anyhow::Result<()> {
+        self.outbox.write().await.insert(record.id.clone(), record);
+        Ok(())
+    }
+
+    async fn claim_due_outbox_messages(
+        &self,
+        kind: OutboxKind,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<OutboxRecord>> {
+        let mut outbox = self.outbox.write().await;
+        let mut due = outbox
+            .values()
+            .filter(|record| record.kind == kind && record.next_attempt_at <= now)
+            .cloned()
+            .collect::<Vec<_>>();
+        due.sort_by_key(|record| record.created_at);
+        due.truncate(limit);
+        for record in &due {
+            if let Some(stored) = outbox.get_mut(&record.id) {
+                stored.next_attempt_at = lease_until;
+            }
+        }
+        Ok(due)
+    }
+
+    async fn delete_outbox_message(&self, id: &str) -> anyhow::Result<bool> {
+        Ok(self.outbox.write().await.remove(id).is_some())
+    }
+
+    async fn put_user_api_key(&self, record: UserApiKeyRecord) -> anyhow::Result<()> {
+        self.user_api_keys
+            .write()
+            .await
+            .insert(record.user_id.clone(), record);
+        Ok(())
+    }
+
+    async fn get_user_api_key(&self, user_id: &str) -> anyhow::Result<Option<UserApiKeyRecord>> {
+        Ok(self.user_api_keys.read().await.get(user_id).cloned())
+    }
+
+    async fn delete_user_api_key(&self, user_id: &str) -> anyhow::Result<bool> {
+        Ok(self.user_api_keys.write().await.remove(user_id).is_some())
+    }
+
+    async fn get_calendar_feed_salt(&self, user_id: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.calendar_feed_salts.read().await.get(user_id).cloned())
+    }
+
+    async fn put_calendar_feed_salt(&self, user_id: &str, salt: &str) -> anyhow::Result<()> {
+        self.calendar_feed_salts
+            .write()
+            .await
+            .insert(user_id.to_owned(), salt.to_owned());
+        Ok(())
+    }
+
+    async fn record_audit_log(&self, record: AuditLogRecord) -> anyhow::Result<()> {
+        self.audit_logs.write().await.push(record);
+        Ok(())
+    }
+
+    async fn list_audit_logs(&self, query: &AuditLogQuery) -> anyhow::Result<Vec<AuditLogRecord>> {
+        let mut records = self
+            .audit_logs
+            .read()
+            .await
+            .iter()
+            .filter(|record| query.matches(record))
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by_key(|record| std::cmp::Reverse(record.timestamp));
+        records.truncate(query.limit);
+        Ok(records)
+    }
 }
@@ -1,23 +1,75 @@
+mod dedup;
+mod fact_edits;
 mod in_memory;
+#[cfg(feature = "postgres")]
 mod postgres;
+mod reinforcement;
+mod snapshot;
+mod trash;
+
+use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
 
-use crate::types::{
-    ChatMessageRecord, MemoryContext, MemoryFact, PlannerDecisionRecord, ToolCallRecord,
-    UserDashboardSummary,
+use crate::{
+    audit::AuditLogQuery,
+    custom_commands::CustomCommand,
+    guild_settings::GuildSettings,
+    model_handoff::ModelHandoff,
+    notifications::NotificationPrefs,
+    onboarding::OnboardingState,
+    prompt_rollout::{PromptRollout, RolloutTurn},
+    types::{
+        AuditLogRecord, BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord,
+        ChatSearchQuery, ChatSession, ContextLimits, InFlightReplyRecord, MemoryContext,
+        MemoryFact, MessageFeedbackRecord, OutboxKind, OutboxRecord, PendingFactRecord,
+        PlannerDecisionRecord, SystemNoticeRecord, ThoughtRecord, ToolCallRecord, ToolUsageRecord,
+        TurnPhase, UserApiKeyRecord, UserDashboardSummary,
+    },
+    user_stats::UserStats,
 };
 
+pub use dedup::{find_near_duplicate, text_similarity};
+pub use fact_edits::{
+    DASHBOARD_FACT_SOURCE, FactEdit, FactEditError, FactEditSummary, apply_fact_edits,
+    check_fact_edits, edited_fact,
+};
 pub use in_memory::InMemoryMemoryStore;
+#[cfg(feature = "postgres")]
 pub use postgres::PostgresMemoryStore;
+pub use reinforcement::{effective_confidence, rank_facts_by_confidence, reinforce_fact};
+pub use snapshot::{MemorySnapshot, RestoreSummary, SNAPSHOT_EXCLUDED_DATA, UserSnapshot};
+pub use trash::{UNDO_WINDOW_HOURS, start_hard_delete_job, undo_cutoff};
+
+/// Turns a store handle into the shared trait object, for concrete stores and
+/// `dyn MemoryStore` alike. Lets generic callers hand their store to
+/// components that only take `Arc<dyn MemoryStore>`.
+pub trait IntoDynMemoryStore: MemoryStore {
+    fn into_dyn(self: Arc<Self>) -> Arc<dyn MemoryStore>;
+}
+
+impl<S: MemoryStore + 'static> IntoDynMemoryStore for S {
+    fn into_dyn(self: Arc<Self>) -> Arc<dyn MemoryStore> {
+        self
+    }
+}
+
+impl IntoDynMemoryStore for dyn MemoryStore {
+    fn into_dyn(self: Arc<Self>) -> Arc<dyn MemoryStore> {
+        self
+    }
+}
 
 #[async_trait]
 pub trait MemoryStore: Send + Sync {
+    /// The user's memory for a turn in this channel, within `limits`.
     async fn load_context(
         &self,
         user_id: &str,
         guild_id: &str,
         channel_id: &str,
+        limits: ContextLimits,
     ) -> anyhow::Result<MemoryContext>;
 
     async fn upsert_fact(&self, user_id: &str, fact: MemoryFact) -> anyhow::Result<()>;
@@ -31,9 +83,33 @@ pub trait MemoryStore: Send + Sync {
 
     async fn list_facts(&self, user_id: &str, limit: usize) -> anyhow::Result<Vec<MemoryFact>>;
 
+    /// Soft-deletes a fact; see [`MemoryStore::restore_facts`].
     async fn delete_fact(&self, user_id: &str, key: &str) -> anyhow::Result<bool>;
 
-    async fn record_chat_message(&self, message: ChatMessageRecord) -> anyhow::Result<()>;
+    /// Applies all edits in one transaction, or none of them. Rejected
+    /// batches fail with a [`FactEditError`].
+    async fn apply_fact_edits(
+        &self,
+        user_id: &str,
+        edits: &[FactEdit],
+    ) -> anyhow::Result<FactEditSummary>;
+
+    /// Moves a fact to a new key, keeping its value and confidence.
+    async fn rename_fact(&self, user_id: &str, old_key: &str, new_key: &str) -> anyhow::Result<()> {
+        self.apply_fact_edits(
+            user_id,
+            &[FactEdit::Rename {
+                old_key: old_key.to_owned(),
+                new_key: new_key.to_owned(),
+            }],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Stores the message and returns the id it was stored under, which may
+    /// differ from `message.id`.
+    async fn record_chat_message(&self, message: ChatMessageRecord) -> anyhow::Result<String>;
 
     async fn list_chat_messages(
         &self,
@@ -41,18 +117,152 @@ pub trait MemoryStore: Send + Sync {
         limit: usize,
     ) -> anyhow::Result<Vec<ChatMessageRecord>>;
 
+    /// The user's latest messages in one channel, oldest first.
+    async fn list_channel_messages(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>>;
+
+    /// The user's messages matching `query`, best matches first.
+    async fn search_chat_messages(
+        &self,
+        user_id: &str,
+        query: &ChatSearchQuery,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>>;
+
     async fn delete_chat_message(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool>;
 
+    /// Pins or unpins one message. Returns `false` if the user has no such
+    /// message.
+    async fn set_chat_message_pinned(
+        &self,
+        user_id: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> anyhow::Result<bool>;
+
+    /// The user's latest pinned messages in a guild, oldest first.
+    async fn list_pinned_messages(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>>;
+
+    /// Points the reply to the platform message `answered_message_id` at
+    /// the message it was delivered as. Returns `false` if no such reply is
+    /// recorded.
+    async fn set_reply_source_message(
+        &self,
+        user_id: &str,
+        answered_message_id: &str,
+        reply_message_id: &str,
+    ) -> anyhow::Result<bool>;
+
+    /// Whether a turn for the platform message `message_id` is recorded,
+    /// i.e. the message was already handled.
+    async fn has_recorded_turn(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool>;
+
+    /// Stores the user's feedback on a message, replacing earlier feedback;
+    /// a score of 0 clears it.
+    async fn set_message_feedback(&self, feedback: MessageFeedbackRecord) -> anyhow::Result<()>;
+
+    async fn list_message_feedback(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Vec<MessageFeedbackRecord>>;
+
+    /// Starts a session for the user; it becomes the current one.
+    async fn start_chat_session(
+        &self,
+        user_id: &str,
+        started_at: DateTime<Utc>,
+    ) -> anyhow::Result<ChatSession>;
+
+    /// The user's most recently active session.
+    async fn current_chat_session(&self, user_id: &str) -> anyhow::Result<Option<ChatSession>>;
+
+    /// Marks a session active at `at`, which makes it the current one.
+    /// Returns `false` if the user has no such session.
+    async fn touch_chat_session(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+
+    async fn set_chat_session_title(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        title: &str,
+    ) -> anyhow::Result<bool>;
+
+    /// The user's sessions, most recently active first.
+    async fn list_chat_sessions(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatSession>>;
+
+    /// The latest messages of one session, oldest first.
+    async fn list_session_messages(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>>;
+
+    /// Deletes all of the user's sessions; their messages stay, outside any
+    /// session.
+    async fn clear_chat_sessions(&self, user_id: &str) -> anyhow::Result<u64>;
+
+    /// Soft-deletes the user's chat history; see
+    /// [`MemoryStore::restore_chat_messages`].
     async fn clear_chat_messages(&self, user_id: &str) -> anyhow::Result<u64>;
 
     async fn clear_tool_calls(&self, user_id: &str) -> anyhow::Result<u64>;
 
+    /// Soft-deletes all of the user's facts.
     async fn clear_facts(&self, user_id: &str) -> anyhow::Result<u64>;
 
+    /// Restores chat messages soft-deleted at or after `deleted_since`.
+    async fn restore_chat_messages(
+        &self,
+        user_id: &str,
+        deleted_since: DateTime<Utc>,
+    ) -> anyhow::Result<u64>;
+
+    /// Restores facts (one key, or all) soft-deleted at or after
+    /// `deleted_since`. A fact stored again since its deletion stays as is.
+    async fn restore_facts(
+        &self,
+        user_id: &str,
+        key: Option<&str>,
+        deleted_since: DateTime<Utc>,
+    ) -> anyhow::Result<u64>;
+
+    /// Keys of the user's facts that are soft-deleted and not yet purged.
+    async fn list_deleted_fact_keys(&self, user_id: &str) -> anyhow::Result<Vec<String>>;
+
+    /// Permanently removes facts and messages soft-deleted before
+    /// `deleted_before`.
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64>;
+
+    /// Permanently removes the user's soft-deleted facts and messages, however
+    /// recently they were deleted.
+    async fn purge_user_deleted(&self, user_id: &str) -> anyhow::Result<u64>;
+
     async fn clear_planner_decisions(&self, user_id: &str) -> anyhow::Result<u64>;
 
     async fn list_users(&self, limit: usize) -> anyhow::Result<Vec<UserDashboardSummary>>;
 
+    /// The user's own message, fact and tool counts and reply times.
+    async fn user_stats(&self, user_id: &str) -> anyhow::Result<UserStats>;
+
     async fn record_tool_call(&self, tool_call: ToolCallRecord) -> anyhow::Result<()>;
 
     async fn list_tool_calls(
@@ -63,9 +273,288 @@ pub trait MemoryStore: Send + Sync {
 
     async fn record_planner_decision(&self, decision: PlannerDecisionRecord) -> anyhow::Result<()>;
 
+    async fn record_thought(&self, thought: ThoughtRecord) -> anyhow::Result<()>;
+
+    /// The user's latest thoughts, oldest first.
+    async fn list_thoughts(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ThoughtRecord>>;
+
+    async fn clear_thoughts(&self, user_id: &str) -> anyhow::Result<u64>;
+
     async fn list_planner_decisions(
         &self,
         user_id: &str,
         limit: usize,
     ) -> anyhow::Result<Vec<PlannerDecisionRecord>>;
+
+    /// The user's latest decisions of one kind, oldest first.
+    async fn list_planner_decisions_of_kind(
+        &self,
+        user_id: &str,
+        planner: &str,
+        decision: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PlannerDecisionRecord>>;
+
+    async fn get_planner_decision(&self, id: &str)
+    -> anyhow::Result<Option<PlannerDecisionRecord>>;
+
+    async fn get_guild_settings(&self, guild_id: &str) -> anyhow::Result<Option<GuildSettings>>;
+
+    async fn put_guild_settings(&self, settings: GuildSettings) -> anyhow::Result<()>;
+
+    /// Admin-defined slash commands, by name.
+    async fn list_custom_commands(&self) -> anyhow::Result<Vec<CustomCommand>>;
+
+    /// Inserts or replaces the command with the same name.
+    async fn put_custom_command(&self, command: CustomCommand) -> anyhow::Result<()>;
+
+    async fn delete_custom_command(&self, name: &str) -> anyhow::Result<bool>;
+
+    /// Increments the user's usage of `tool_name` on `day` unless it already
+    /// reached `limit`; returns whether the call may proceed.
+    async fn try_increment_tool_usage(
+        &self,
+        user_id: &str,
+        tool_name: &str,
+        day: NaiveDate,
+        limit: u32,
+    ) -> anyhow::Result<bool>;
+
+    async fn list_tool_usage(
+        &self,
+        user_id: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<Vec<ToolUsageRecord>>;
+
+    /// Resets the user's counters for `day`, for one tool or all of them.
+    async fn reset_tool_usage(
+        &self,
+        user_id: &str,
+        tool_name: Option<&str>,
+        day: NaiveDate,
+    ) -> anyhow::Result<u64>;
+
+    /// Facts last written or confirmed before `stale_before`, or with
+    /// confidence below `below_confidence`, of users whose last memory review
+    /// is older than `reviewed_before`. Pages through users by id: up to
+    /// `max_users` users after `after_user_id`, each with their `per_user`
+    /// stalest facts, ordered by user and then stalest first.
+    async fn list_stale_facts(
+        &self,
+        stale_before: DateTime<Utc>,
+        below_confidence: f32,
+        reviewed_before: DateTime<Utc>,
+        after_user_id: Option<&str>,
+        max_users: usize,
+        per_user: usize,
+    ) -> anyhow::Result<Vec<(String, MemoryFact)>>;
+
+    async fn last_memory_review(&self, user_id: &str) -> anyhow::Result<Option<DateTime<Utc>>>;
+
+    async fn record_memory_review(
+        &self,
+        user_id: &str,
+        sent_at: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+
+    /// The user's proactive message settings; `None` if they kept the
+    /// defaults.
+    async fn get_notification_prefs(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Option<NotificationPrefs>>;
+
+    async fn put_notification_prefs(&self, prefs: NotificationPrefs) -> anyhow::Result<()>;
+
+    /// Logs a proactive message of `kind` sent to the user, and drops the
+    /// user's entries sent before `keep_since`, which no cap counts anymore.
+    async fn record_notification(
+        &self,
+        user_id: &str,
+        kind: &str,
+        sent_at: DateTime<Utc>,
+        keep_since: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+
+    async fn count_notifications_since(
+        &self,
+        user_id: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<usize>;
+
+    /// Whether the user was already greeted for `fact_key` on `day`.
+    async fn has_date_greeting(
+        &self,
+        user_id: &str,
+        fact_key: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<bool>;
+
+    /// Records that the user was greeted for the date fact `fact_key` on
+    /// `day`. Returns `false` if they already were.
+    async fn record_date_greeting(
+        &self,
+        user_id: &str,
+        fact_key: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<bool>;
+
+    /// The model the user's replies were last generated with, and the
+    /// handoff note after a switch.
+    async fn get_model_handoff(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+    ) -> anyhow::Result<Option<ModelHandoff>>;
+
+    async fn put_model_handoff(&self, handoff: ModelHandoff) -> anyhow::Result<()>;
+
+    /// Stores a prompt rollout, replacing the one of the same persona.
+    async fn put_prompt_rollout(&self, rollout: PromptRollout) -> anyhow::Result<()>;
+
+    /// Prompt rollouts, running and finished, one per persona.
+    async fn list_prompt_rollouts(&self) -> anyhow::Result<Vec<PromptRollout>>;
+
+    async fn record_prompt_rollout_turn(&self, turn: RolloutTurn) -> anyhow::Result<()>;
+
+    /// Turns counted for the rollout of `persona` that started at
+    /// `started_at`.
+    async fn list_prompt_rollout_turns(
+        &self,
+        persona: &str,
+        started_at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<RolloutTurn>>;
+
+    /// Where the user is in the onboarding interview; `None` if it was never
+    /// offered to them.
+    async fn get_onboarding(&self, user_id: &str) -> anyhow::Result<Option<OnboardingState>>;
+
+    async fn put_onboarding(&self, state: OnboardingState) -> anyhow::Result<()>;
+
+    /// When a chat adapter (e.g. `discord`) last reported itself running.
+    async fn last_seen_at(&self, adapter: &str) -> anyhow::Result<Option<DateTime<Utc>>>;
+
+    async fn record_last_seen(&self, adapter: &str, seen_at: DateTime<Utc>) -> anyhow::Result<()>;
+
+    /// Queues a fact for admin approval and returns its id; the record's own
+    /// `id` is ignored.
+    async fn queue_pending_fact(&self, pending: PendingFactRecord) -> anyhow::Result<String>;
+
+    /// Queued facts, oldest first, for one user or everyone.
+    async fn list_pending_facts(
+        &self,
+        user_id: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PendingFactRecord>>;
+
+    /// Removes a queued fact and returns it, e.g. to upsert it on approval.
+    async fn take_pending_fact(&self, id: &str) -> anyhow::Result<Option<PendingFactRecord>>;
+
+    /// Stores a background task and returns its id; the record's own `id` is
+    /// ignored.
+    async fn create_background_task(&self, task: BackgroundTaskRecord) -> anyhow::Result<String>;
+
+    /// Moves a task to `status`, replacing its result when one is given.
+    /// Returns `false` for unknown ids.
+    async fn update_background_task(
+        &self,
+        id: &str,
+        status: BackgroundTaskStatus,
+        result_text: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+
+    /// Tasks in any of `statuses` (all when empty), for one user or everyone,
+    /// newest first.
+    async fn list_background_tasks(
+        &self,
+        user_id: Option<&str>,
+        statuses: &[BackgroundTaskStatus],
+        limit: usize,
+    ) -> anyhow::Result<Vec<BackgroundTaskRecord>>;
+
+    /// Stores a notice for the user's next turn and returns its id; the
+    /// record's own `id` is ignored.
+    async fn add_system_notice(&self, notice: SystemNoticeRecord) -> anyhow::Result<String>;
+
+    /// The user's unexpired notices, oldest first.
+    async fn list_system_notices(
+        &self,
+        user_id: &str,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<SystemNoticeRecord>>;
+
+    /// Removes the notices with the given ids, which a turn has shown, and
+    /// the user's expired ones.
+    async fn consume_system_notices(
+        &self,
+        user_id: &str,
+        ids: &[String],
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+
+    /// Marks a reply as in flight, replacing an earlier marker for the same
+    /// message.
+    async fn put_in_flight_reply(&self, record: InFlightReplyRecord) -> anyhow::Result<()>;
+
+    /// Refreshes an in-flight marker's heartbeat, moving it to `phase` when
+    /// given. Returns `false` when the message has no marker.
+    async fn touch_in_flight_reply(
+        &self,
+        message_id: &str,
+        phase: Option<TurnPhase>,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+
+    /// Returns `false` when the marker was already gone, so concurrent
+    /// recoveries can claim a marker by deleting it.
+    async fn delete_in_flight_reply(&self, message_id: &str) -> anyhow::Result<bool>;
+
+    /// Markers whose last heartbeat is older than `updated_before`, oldest
+    /// first.
+    async fn list_stale_in_flight_replies(
+        &self,
+        updated_before: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<InFlightReplyRecord>>;
+
+    /// Stores an outbox entry, replacing the one with the same id.
+    async fn put_outbox_message(&self, record: OutboxRecord) -> anyhow::Result<()>;
+
+    /// Entries of `kind` whose next attempt is due by `now`, oldest first,
+    /// moved to `lease_until` in the same step so that no other instance
+    /// claims them too.
+    async fn claim_due_outbox_messages(
+        &self,
+        kind: OutboxKind,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<OutboxRecord>>;
+
+    async fn delete_outbox_message(&self, id: &str) -> anyhow::Result<bool>;
+
+    /// Stores the user's own model key, replacing an earlier one.
+    async fn put_user_api_key(&self, record: UserApiKeyRecord) -> anyhow::Result<()>;
+
+    async fn get_user_api_key(&self, user_id: &str) -> anyhow::Result<Option<UserApiKeyRecord>>;
+
+    /// Returns `false` when the user had no key stored.
+    async fn delete_user_api_key(&self, user_id: &str) -> anyhow::Result<bool>;
+
+    /// Salt in the user's calendar feed token; `None` until they first get a
+    /// new link.
+    async fn get_calendar_feed_salt(&self, user_id: &str) -> anyhow::Result<Option<String>>;
+
+    /// Replaces the user's calendar feed salt, revoking their earlier links.
+    async fn put_calendar_feed_salt(&self, user_id: &str, salt: &str) -> anyhow::Result<()>;
+
+    async fn record_audit_log(&self, record: AuditLogRecord) -> anyhow::Result<()>;
+
+    /// Matching audit entries, newest first.
+    async fn list_audit_logs(&self, query: &AuditLogQuery) -> anyhow::Result<Vec<AuditLogRecord>>;
 }
@@ -1,12 +1,128 @@
 use async_trait::async_trait;
 use sqlx::{PgPool, postgres::PgPoolOptions};
 
-use crate::types::{
-    ChatMessageRecord, ChatRole, MemoryContext, MemoryFact, PlannerDecisionRecord, ToolCallRecord,
-    UserDashboardSummary,
+use crate::{
+    audit::AuditLogQuery,
+    custom_commands::CustomCommand,
+    guild_settings::GuildSettings,
+    model::KeyProvider,
+    model_handoff::ModelHandoff,
+    notifications::NotificationPrefs,
+    onboarding::{OnboardingState, OnboardingStep},
+    prompt_rollout::{PromptRollout, PromptVariant, RolloutTurn},
+    types::{
+        AuditLogRecord, BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord, ChatRole,
+        ChatSearchQuery, ChatSession, ContextLimits, InFlightReplyRecord, MemoryContext,
+        MemoryFact, MessageFeedbackRecord, Modality, OutboxKind, OutboxRecord, PendingFactRecord,
+        PlannerDecisionRecord, SystemNoticeRecord, TaskOrigin, ThoughtRecord, ToolCallRecord,
+        ToolUsageRecord, TurnPhase, UserApiKeyRecord, UserDashboardSummary, context_speaker,
+    },
+    user_stats::{REPLY_TIME_WINDOW, UserStats},
 };
 
-use super::MemoryStore;
+use super::{FactEdit, FactEditError, FactEditSummary, MemoryStore, check_fact_edits, edited_fact};
+
+type PendingFactRow = (
+    i64,
+    String,
+    String,
+    String,
+    String,
+    String,
+    f32,
+    String,
+    Option<chrono::DateTime<chrono::Utc>>,
+    String,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type BackgroundTaskRow = (
+    i64,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    chrono::DateTime<chrono::Utc>,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type InFlightReplyRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    chrono::DateTime<chrono::Utc>,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type SystemNoticeRow = (
+    i64,
+    String,
+    String,
+    chrono::DateTime<chrono::Utc>,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type PlannerDecisionRow = (
+    i64,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    bool,
+    Option<String>,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type ChatMessageRow = (
+    i64,
+    String,
+    String,
+    String,
+    String,
+    String,
+    chrono::DateTime<chrono::Utc>,
+    String,
+    Option<i64>,
+    bool,
+    Option<String>,
+);
+
+type ChatSessionRow = (
+    i64,
+    String,
+    Option<String>,
+    chrono::DateTime<chrono::Utc>,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type ThoughtRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    chrono::DateTime<chrono::Utc>,
+);
+
+type FactRow = (
+    String,
+    String,
+    f32,
+    String,
+    chrono::DateTime<chrono::Utc>,
+    Option<chrono::DateTime<chrono::Utc>>,
+);
 
 #[derive(Debug, Clone)]
 pub struct PostgresMemoryStore {
@@ -30,27 +146,22 @@ impl MemoryStore for PostgresMemoryStore {
         user_id: &str,
         guild_id: &str,
         channel_id: &str,
+        limits: ContextLimits,
     ) -> anyhow::Result<MemoryContext> {
-        let facts =
-            sqlx::query_as::<_, (String, String, f32, String, chrono::DateTime<chrono::Utc>)>(
-                "SELECT key, value, confidence, source, updated_at
+        let facts = sqlx::query_as::<_, FactRow>(
+            "SELECT key, value, confidence, source, updated_at, last_confirmed_at
              FROM memory_facts
-             WHERE user_id = $1
+             WHERE user_id = $1 AND deleted_at IS NULL
              ORDER BY updated_at DESC
-             LIMIT 32",
-            )
-            .bind(user_id)
-            .fetch_all(&self.pool)
-            .await?
-            .into_iter()
-            .map(|(key, value, confidence, source, updated_at)| MemoryFact {
-                key,
-                value,
-                confidence,
-                source,
-                updated_at,
-            })
-            .collect::<Vec<_>>();
+             LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limits.facts as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(fact_from_row)
+        .collect::<Vec<_>>();
 
         let summary = sqlx::query_as::<_, (String,)>(
             "SELECT summary
@@ -66,55 +177,138 @@ impl MemoryStore for PostgresMemoryStore {
         .await?
         .map(|row| row.0);
 
-        let recent_messages = sqlx::query_as::<_, (String, String)>(
-            "SELECT role, content
+        let recent_messages = sqlx::query_as::<_, (String, String, String)>(
+            "SELECT role, content, modality
              FROM chat_messages
-             WHERE user_id = $1 AND guild_id = $2 AND channel_id = $3
+             WHERE user_id = $1 AND guild_id = $2 AND channel_id = $3 AND deleted_at IS NULL
              ORDER BY timestamp DESC
-             LIMIT 8",
+             LIMIT $4",
         )
         .bind(user_id)
         .bind(guild_id)
         .bind(channel_id)
+        .bind(limits.recent_messages as i64)
         .fetch_all(&self.pool)
         .await?
         .into_iter()
         .rev()
-        .map(|(role, content)| format!("{role}: {content}"))
+        .map(|(role, content, modality)| {
+            format!(
+                "{}: {content}",
+                context_speaker(parse_role(&role), Modality::parse(&modality))
+            )
+        })
         .collect::<Vec<_>>();
 
         Ok(MemoryContext {
             summary,
             recent_messages,
             facts,
+            other_channel_messages: Vec::new(),
+            now_playing: None,
+            pinned_messages: Vec::new(),
+            ambient: Vec::new(),
+            system_notices: Vec::new(),
+            capabilities: None,
+            guild_emoji: None,
+            handoff: None,
         })
     }
 
     async fn upsert_fact(&self, user_id: &str, fact: MemoryFact) -> anyhow::Result<()> {
-        sqlx::query(
-            "INSERT INTO memory_facts (user_id, key, value, confidence, source, updated_at)
-             VALUES ($1, $2, $3, $4, $5, $6)
-             ON CONFLICT (user_id, key)
-             DO UPDATE SET value = EXCLUDED.value, confidence = EXCLUDED.confidence, source = EXCLUDED.source, updated_at = EXCLUDED.updated_at",
-        )
-        .bind(user_id)
-        .bind(fact.key)
-        .bind(fact.value)
-        .bind(fact.confidence)
-        .bind(fact.source)
-        .bind(fact.updated_at)
-        .execute(&self.pool)
-        .await?;
+        let mut connection = self.pool.acquire().await?;
+        upsert_fact_row(&mut connection, user_id, fact).await
+    }
 
-        Ok(())
+    async fn apply_fact_edits(
+        &self,
+        user_id: &str,
+        edits: &[FactEdit],
+    ) -> anyhow::Result<FactEditSummary> {
+        check_fact_edits(edits)?;
+        let now = chrono::Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        let mut summary = FactEditSummary::default();
+        for edit in edits {
+            match edit {
+                FactEdit::Upsert {
+                    key,
+                    value,
+                    confidence,
+                } => {
+                    let fact = edited_fact(key, value, *confidence, now);
+                    upsert_fact_row(&mut transaction, user_id, fact).await?;
+                    summary.upserted += 1;
+                }
+                FactEdit::Delete { key } => {
+                    let result = sqlx::query(
+                        "UPDATE memory_facts SET deleted_at = $3
+                         WHERE user_id = $1 AND key = $2 AND deleted_at IS NULL",
+                    )
+                    .bind(user_id)
+                    .bind(key)
+                    .bind(now)
+                    .execute(&mut *transaction)
+                    .await?;
+                    summary.deleted += result.rows_affected();
+                }
+                FactEdit::Rename { old_key, new_key } => {
+                    if old_key == new_key {
+                        continue;
+                    }
+                    let (taken,) = sqlx::query_as::<_, (bool,)>(
+                        "SELECT EXISTS (
+                             SELECT 1 FROM memory_facts
+                             WHERE user_id = $1 AND key = $2 AND deleted_at IS NULL
+                         )",
+                    )
+                    .bind(user_id)
+                    .bind(new_key)
+                    .fetch_one(&mut *transaction)
+                    .await?;
+                    if taken {
+                        return Err(FactEditError::KeyTaken(new_key.clone()).into());
+                    }
+                    // A soft-deleted fact under the new key would block the rename.
+                    sqlx::query(
+                        "DELETE FROM memory_facts
+                         WHERE user_id = $1 AND key = $2 AND deleted_at IS NOT NULL",
+                    )
+                    .bind(user_id)
+                    .bind(new_key)
+                    .execute(&mut *transaction)
+                    .await?;
+                    let result = sqlx::query(
+                        "UPDATE memory_facts SET key = $3, updated_at = $4
+                         WHERE user_id = $1 AND key = $2 AND deleted_at IS NULL",
+                    )
+                    .bind(user_id)
+                    .bind(old_key)
+                    .bind(new_key)
+                    .bind(now)
+                    .execute(&mut *transaction)
+                    .await?;
+                    if result.rows_affected() == 0 {
+                        return Err(FactEditError::MissingFact(old_key.clone()).into());
+                    }
+                    summary.renamed += 1;
+                }
+            }
+        }
+        transaction.commit().await?;
+        Ok(summary)
     }
 
     async fn delete_fact(&self, user_id: &str, key: &str) -> anyhow::Result<bool> {
-        let result = sqlx::query("DELETE FROM memory_facts WHERE user_id = $1 AND key = $2")
-            .bind(user_id)
-            .bind(key)
-            .execute(&self.pool)
-            .await?;
+        let result = sqlx::query(
+            "UPDATE memory_facts SET deleted_at = $3
+             WHERE user_id = $1 AND key = $2 AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
         Ok(result.rows_affected() > 0)
     }
 
@@ -127,29 +321,23 @@ impl MemoryStore for PostgresMemoryStore {
         let query = format!("%{}%", query.to_lowercase());
         let limit = k as i64;
 
-        let facts =
-            sqlx::query_as::<_, (String, String, f32, String, chrono::DateTime<chrono::Utc>)>(
-                "SELECT key, value, confidence, source, updated_at
+        let facts = sqlx::query_as::<_, FactRow>(
+            "SELECT key, value, confidence, source, updated_at, last_confirmed_at
              FROM memory_facts
              WHERE user_id = $1
+               AND deleted_at IS NULL
                AND (LOWER(key) LIKE $2 OR LOWER(value) LIKE $2)
              ORDER BY updated_at DESC
              LIMIT $3",
-            )
-            .bind(user_id)
-            .bind(query)
-            .bind(limit)
-            .fetch_all(&self.pool)
-            .await?
-            .into_iter()
-            .map(|(key, value, confidence, source, updated_at)| MemoryFact {
-                key,
-                value,
-                confidence,
-                source,
-                updated_at,
-            })
-            .collect();
+        )
+        .bind(user_id)
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(fact_from_row)
+        .collect();
 
         Ok(facts)
     }
@@ -157,36 +345,31 @@ impl MemoryStore for PostgresMemoryStore {
     async fn list_facts(&self, user_id: &str, limit: usize) -> anyhow::Result<Vec<MemoryFact>> {
         let limit = limit as i64;
 
-        let facts =
-            sqlx::query_as::<_, (String, String, f32, String, chrono::DateTime<chrono::Utc>)>(
-                "SELECT key, value, confidence, source, updated_at
+        let facts = sqlx::query_as::<_, FactRow>(
+            "SELECT key, value, confidence, source, updated_at, last_confirmed_at
                  FROM memory_facts
-                 WHERE user_id = $1
+                 WHERE user_id = $1 AND deleted_at IS NULL
                  ORDER BY updated_at DESC
                  LIMIT $2",
-            )
-            .bind(user_id)
-            .bind(limit)
-            .fetch_all(&self.pool)
-            .await?
-            .into_iter()
-            .map(|(key, value, confidence, source, updated_at)| MemoryFact {
-                key,
-                value,
-                confidence,
-                source,
-                updated_at,
-            })
-            .collect::<Vec<_>>();
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(fact_from_row)
+        .collect::<Vec<_>>();
 
         Ok(facts)
     }
 
-    async fn record_chat_message(&self, message: ChatMessageRecord) -> anyhow::Result<()> {
-        sqlx::query(
+    async fn record_chat_message(&self, message: ChatMessageRecord) -> anyhow::Result<String> {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
             "INSERT INTO chat_messages
-             (user_id, guild_id, channel_id, role, content, timestamp)
-             VALUES ($1, $2, $3, $4, $5, $6)",
+             (user_id, guild_id, channel_id, role, content, timestamp, modality, session_id,
+              pinned, source_message_id)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING id",
         )
         .bind(message.user_id)
         .bind(message.guild_id)
@@ -194,10 +377,18 @@ impl MemoryStore for PostgresMemoryStore {
         .bind(message.role.as_str())
         .bind(message.content)
         .bind(message.timestamp)
-        .execute(&self.pool)
+        .bind(message.modality.as_str())
+        .bind(
+            message
+                .session_id
+                .and_then(|session_id| session_id.parse::<i64>().ok()),
+        )
+        .bind(message.pinned)
+        .bind(message.source_message_id)
+        .fetch_one(&self.pool)
         .await?;
 
-        Ok(())
+        Ok(id.to_string())
     }
 
     async fn list_chat_messages(
@@ -207,21 +398,11 @@ impl MemoryStore for PostgresMemoryStore {
     ) -> anyhow::Result<Vec<ChatMessageRecord>> {
         let limit = limit as i64;
 
-        let mut messages = sqlx::query_as::<
-            _,
-            (
-                i64,
-                String,
-                String,
-                String,
-                String,
-                String,
-                chrono::DateTime<chrono::Utc>,
-            ),
-        >(
-            "SELECT id, user_id, guild_id, channel_id, role, content, timestamp
+        let mut messages = sqlx::query_as::<_, ChatMessageRow>(
+            "SELECT id, user_id, guild_id, channel_id, role, content, timestamp, modality,
+                    session_id, pinned, source_message_id
              FROM chat_messages
-             WHERE user_id = $1
+             WHERE user_id = $1 AND deleted_at IS NULL
              ORDER BY timestamp DESC
              LIMIT $2",
         )
@@ -230,292 +411,2070 @@ impl MemoryStore for PostgresMemoryStore {
         .fetch_all(&self.pool)
         .await?
         .into_iter()
-        .map(
-            |(id, user_id, guild_id, channel_id, role, content, timestamp)| ChatMessageRecord {
-                id: id.to_string(),
-                user_id,
-                guild_id,
-                channel_id,
-                role: parse_role(&role),
-                content,
-                timestamp,
-            },
-        )
+        .map(chat_message_from_row)
         .collect::<Vec<_>>();
 
         messages.reverse();
         Ok(messages)
     }
 
-    async fn delete_chat_message(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool> {
-        let id = match message_id.parse::<i64>() {
-            Ok(value) => value,
-            Err(_) => return Ok(false),
-        };
-        let result = sqlx::query("DELETE FROM chat_messages WHERE user_id = $1 AND id = $2")
-            .bind(user_id)
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected() > 0)
-    }
-
-    async fn clear_chat_messages(&self, user_id: &str) -> anyhow::Result<u64> {
-        let result = sqlx::query("DELETE FROM chat_messages WHERE user_id = $1")
-            .bind(user_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected())
-    }
-
-    async fn clear_facts(&self, user_id: &str) -> anyhow::Result<u64> {
-        let result = sqlx::query("DELETE FROM memory_facts WHERE user_id = $1")
-            .bind(user_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected())
-    }
+    async fn list_channel_messages(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let mut messages = sqlx::query_as::<_, ChatMessageRow>(
+            "SELECT id, user_id, guild_id, channel_id, role, content, timestamp, modality,
+                    session_id, pinned, source_message_id
+             FROM chat_messages
+             WHERE user_id = $1 AND guild_id = $2 AND channel_id = $3 AND deleted_at IS NULL
+             ORDER BY timestamp DESC
+             LIMIT $4",
+        )
+        .bind(user_id)
+        .bind(guild_id)
+        .bind(channel_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(chat_message_from_row)
+        .collect::<Vec<_>>();
 
-    async fn clear_tool_calls(&self, user_id: &str) -> anyhow::Result<u64> {
-        let result = sqlx::query("DELETE FROM tool_call_logs WHERE user_id = $1")
-            .bind(user_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected())
+        messages.reverse();
+        Ok(messages)
     }
 
-    async fn clear_planner_decisions(&self, user_id: &str) -> anyhow::Result<u64> {
-        let result = sqlx::query("DELETE FROM planner_decision_logs WHERE user_id = $1")
-            .bind(user_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected())
+    async fn set_chat_message_pinned(
+        &self,
+        user_id: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> anyhow::Result<bool> {
+        let Ok(id) = message_id.parse::<i64>() else {
+            return Ok(false);
+        };
+        let result = sqlx::query(
+            "UPDATE chat_messages SET pinned = $3
+             WHERE user_id = $1 AND id = $2 AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(id)
+        .bind(pinned)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
     }
 
-    async fn list_users(&self, limit: usize) -> anyhow::Result<Vec<UserDashboardSummary>> {
-        let limit = limit as i64;
-
-        let users = sqlx::query_as::<_, (String, i64, i64, chrono::DateTime<chrono::Utc>)>(
-            "SELECT
-                     u.user_id AS user_id,
-                     COALESCE(f.fact_count, 0) AS fact_count,
-                     COALESCE(m.message_count, 0) AS message_count,
-                     u.last_activity AS last_activity
-                 FROM (
-                     SELECT user_id, MAX(last_activity) AS last_activity
-                     FROM (
-                         SELECT user_id, MAX(updated_at) AS last_activity
-                         FROM memory_facts
-                         GROUP BY user_id
-                         UNION ALL
-                         SELECT user_id, MAX(timestamp) AS last_activity
-                         FROM chat_messages
-                         GROUP BY user_id
-                     ) activity
-                     GROUP BY user_id
-                 ) u
-                 LEFT JOIN (
-                     SELECT user_id, COUNT(*)::bigint AS fact_count
-                     FROM memory_facts
-                     GROUP BY user_id
-                 ) f ON f.user_id = u.user_id
-                 LEFT JOIN (
-                     SELECT user_id, COUNT(*)::bigint AS message_count
-                     FROM chat_messages
-                     GROUP BY user_id
-                 ) m ON m.user_id = u.user_id
-                 ORDER BY u.last_activity DESC
-                 LIMIT $1",
+    async fn list_pinned_messages(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let mut messages = sqlx::query_as::<_, ChatMessageRow>(
+            "SELECT id, user_id, guild_id, channel_id, role, content, timestamp, modality,
+                    session_id, pinned, source_message_id
+             FROM chat_messages
+             WHERE user_id = $1 AND guild_id = $2 AND pinned AND deleted_at IS NULL
+             ORDER BY timestamp DESC
+             LIMIT $3",
         )
-        .bind(limit)
+        .bind(user_id)
+        .bind(guild_id)
+        .bind(limit as i64)
         .fetch_all(&self.pool)
         .await?
         .into_iter()
-        .map(
-            |(user_id, fact_count, message_count, last_activity)| UserDashboardSummary {
-                user_id,
-                fact_count,
-                message_count,
-                last_activity,
-            },
-        )
+        .map(chat_message_from_row)
         .collect::<Vec<_>>();
 
-        Ok(users)
+        messages.reverse();
+        Ok(messages)
     }
 
-    async fn record_tool_call(&self, tool_call: ToolCallRecord) -> anyhow::Result<()> {
+    async fn set_reply_source_message(
+        &self,
+        user_id: &str,
+        answered_message_id: &str,
+        reply_message_id: &str,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE chat_messages SET source_message_id = $3
+             WHERE user_id = $1 AND role = 'assistant' AND source_message_id = $2",
+        )
+        .bind(user_id)
+        .bind(answered_message_id)
+        .bind(reply_message_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn has_recorded_turn(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool> {
+        let recorded = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (
+                 SELECT 1 FROM chat_messages
+                 WHERE user_id = $1 AND role = 'user' AND source_message_id = $2
+             )",
+        )
+        .bind(user_id)
+        .bind(message_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(recorded)
+    }
+
+    async fn set_message_feedback(&self, feedback: MessageFeedbackRecord) -> anyhow::Result<()> {
+        if feedback.score == 0 {
+            sqlx::query("DELETE FROM message_feedback WHERE user_id = $1 AND message_id = $2")
+                .bind(&feedback.user_id)
+                .bind(&feedback.message_id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        }
         sqlx::query(
-            "INSERT INTO tool_call_logs
-             (user_id, guild_id, channel_id, tool_name, source, args_json, result_text, citations_text, success, error, timestamp)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            "INSERT INTO message_feedback (user_id, message_id, score, updated_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (user_id, message_id)
+             DO UPDATE SET score = EXCLUDED.score, updated_at = EXCLUDED.updated_at",
         )
-        .bind(tool_call.user_id)
-        .bind(tool_call.guild_id)
-        .bind(tool_call.channel_id)
-        .bind(tool_call.tool_name)
-        .bind(tool_call.source)
-        .bind(tool_call.args_json)
-        .bind(tool_call.result_text)
-        .bind(tool_call.citations.join("\n"))
-        .bind(tool_call.success)
-        .bind(tool_call.error)
-        .bind(tool_call.timestamp)
+        .bind(&feedback.user_id)
+        .bind(&feedback.message_id)
+        .bind(feedback.score)
+        .bind(feedback.updated_at)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    async fn list_tool_calls(
+    async fn list_message_feedback(
         &self,
         user_id: &str,
-        limit: usize,
-    ) -> anyhow::Result<Vec<ToolCallRecord>> {
-        let limit = limit as i64;
-        let mut calls = sqlx::query_as::<
-            _,
-            (
-                String,
-                String,
-                String,
-                String,
-                String,
+    ) -> anyhow::Result<Vec<MessageFeedbackRecord>> {
+        let rows = sqlx::query_as::<_, (String, i32, chrono::DateTime<chrono::Utc>)>(
+            "SELECT message_id, score, updated_at
+             FROM message_feedback
+             WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(message_id, score, updated_at)| MessageFeedbackRecord {
+                user_id: user_id.to_owned(),
+                message_id,
+                score,
+                updated_at,
+            })
+            .collect())
+    }
+
+    async fn start_chat_session(
+        &self,
+        user_id: &str,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<ChatSession> {
+        let row = sqlx::query_as::<_, ChatSessionRow>(
+            "INSERT INTO chat_sessions (user_id, started_at, last_active_at)
+             VALUES ($1, $2, $2)
+             RETURNING id, user_id, title, started_at, last_active_at",
+        )
+        .bind(user_id)
+        .bind(started_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(chat_session_from_row(row))
+    }
+
+    async fn current_chat_session(&self, user_id: &str) -> anyhow::Result<Option<ChatSession>> {
+        Ok(self.list_chat_sessions(user_id, 1).await?.pop())
+    }
+
+    async fn touch_chat_session(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<bool> {
+        let Ok(id) = session_id.parse::<i64>() else {
+            return Ok(false);
+        };
+        let result = sqlx::query(
+            "UPDATE chat_sessions SET last_active_at = GREATEST(last_active_at, $3)
+             WHERE user_id = $1 AND id = $2",
+        )
+        .bind(user_id)
+        .bind(id)
+        .bind(at)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_session_title(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        title: &str,
+    ) -> anyhow::Result<bool> {
+        let Ok(id) = session_id.parse::<i64>() else {
+            return Ok(false);
+        };
+        let result =
+            sqlx::query("UPDATE chat_sessions SET title = $3 WHERE user_id = $1 AND id = $2")
+                .bind(user_id)
+                .bind(id)
+                .bind(title)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_chat_sessions(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatSession>> {
+        let sessions = sqlx::query_as::<_, ChatSessionRow>(
+            "SELECT id, user_id, title, started_at, last_active_at
+             FROM chat_sessions
+             WHERE user_id = $1
+             ORDER BY last_active_at DESC, id DESC
+             LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(chat_session_from_row)
+        .collect();
+        Ok(sessions)
+    }
+
+    async fn list_session_messages(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        let Ok(session_id) = session_id.parse::<i64>() else {
+            return Ok(Vec::new());
+        };
+        let mut messages = sqlx::query_as::<_, ChatMessageRow>(
+            "SELECT id, user_id, guild_id, channel_id, role, content, timestamp, modality,
+                    session_id, pinned, source_message_id
+             FROM chat_messages
+             WHERE user_id = $1 AND session_id = $2 AND deleted_at IS NULL
+             ORDER BY timestamp DESC
+             LIMIT $3",
+        )
+        .bind(user_id)
+        .bind(session_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(chat_message_from_row)
+        .collect::<Vec<_>>();
+
+        messages.reverse();
+        Ok(messages)
+    }
+
+    async fn clear_chat_sessions(&self, user_id: &str) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM chat_sessions WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn search_chat_messages(
+        &self,
+        user_id: &str,
+        query: &ChatSearchQuery,
+    ) -> anyhow::Result<Vec<ChatMessageRecord>> {
+        // The expression matches the GIN index from migration 0022.
+        let messages = sqlx::query_as::<_, ChatMessageRow>(
+            "SELECT id, user_id, guild_id, channel_id, role, content, timestamp, modality,
+                    session_id, pinned, source_message_id
+             FROM chat_messages, plainto_tsquery('simple', $2) AS query
+             WHERE user_id = $1 AND guild_id = $6 AND deleted_at IS NULL
+               AND to_tsvector('simple', content) @@ query
+               AND ($3::timestamptz IS NULL OR timestamp >= $3)
+               AND ($4::timestamptz IS NULL OR timestamp < $4)
+             ORDER BY ts_rank(to_tsvector('simple', content), query) DESC, timestamp DESC
+             LIMIT $5",
+        )
+        .bind(user_id)
+        .bind(&query.text)
+        .bind(query.after)
+        .bind(query.before)
+        .bind(query.limit as i64)
+        .bind(&query.guild_id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(chat_message_from_row)
+        .collect();
+        Ok(messages)
+    }
+
+    async fn delete_chat_message(&self, user_id: &str, message_id: &str) -> anyhow::Result<bool> {
+        let id = match message_id.parse::<i64>() {
+            Ok(value) => value,
+            Err(_) => return Ok(false),
+        };
+        let result = sqlx::query("DELETE FROM chat_messages WHERE user_id = $1 AND id = $2")
+            .bind(user_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn clear_chat_messages(&self, user_id: &str) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE chat_messages SET deleted_at = $2
+             WHERE user_id = $1 AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn clear_facts(&self, user_id: &str) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE memory_facts SET deleted_at = $2
+             WHERE user_id = $1 AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn restore_chat_messages(
+        &self,
+        user_id: &str,
+        deleted_since: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE chat_messages SET deleted_at = NULL
+             WHERE user_id = $1 AND deleted_at >= $2",
+        )
+        .bind(user_id)
+        .bind(deleted_since)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn restore_facts(
+        &self,
+        user_id: &str,
+        key: Option<&str>,
+        deleted_since: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE memory_facts SET deleted_at = NULL
+             WHERE user_id = $1 AND deleted_at >= $2 AND ($3::TEXT IS NULL OR key = $3)",
+        )
+        .bind(user_id)
+        .bind(deleted_since)
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_deleted_fact_keys(&self, user_id: &str) -> anyhow::Result<Vec<String>> {
+        let keys = sqlx::query_scalar::<_, String>(
+            "SELECT DISTINCT key FROM memory_facts
+             WHERE user_id = $1 AND deleted_at IS NOT NULL",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    async fn purge_deleted(
+        &self,
+        deleted_before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<u64> {
+        let mut transaction = self.pool.begin().await?;
+        let facts = sqlx::query("DELETE FROM memory_facts WHERE deleted_at < $1")
+            .bind(deleted_before)
+            .execute(&mut *transaction)
+            .await?;
+        let messages = sqlx::query("DELETE FROM chat_messages WHERE deleted_at < $1")
+            .bind(deleted_before)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(facts.rows_affected() + messages.rows_affected())
+    }
+
+    async fn purge_user_deleted(&self, user_id: &str) -> anyhow::Result<u64> {
+        let mut transaction = self.pool.begin().await?;
+        let facts =
+            sqlx::query("DELETE FROM memory_facts WHERE user_id = $1 AND deleted_at IS NOT NULL")
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+        let messages =
+            sqlx::query("DELETE FROM chat_messages WHERE user_id = $1 AND deleted_at IS NOT NULL")
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+        transaction.commit().await?;
+        Ok(facts.rows_affected() + messages.rows_affected())
+    }
+
+    async fn clear_tool_calls(&self, user_id: &str) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM tool_call_logs WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn clear_planner_decisions(&self, user_id: &str) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM planner_decision_logs WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_users(&self, limit: usize) -> anyhow::Result<Vec<UserDashboardSummary>> {
+        let limit = limit as i64;
+
+        let users = sqlx::query_as::<_, (String, i64, i64, chrono::DateTime<chrono::Utc>)>(
+            "SELECT
+                     u.user_id AS user_id,
+                     COALESCE(f.fact_count, 0) AS fact_count,
+                     COALESCE(m.message_count, 0) AS message_count,
+                     u.last_activity AS last_activity
+                 FROM (
+                     SELECT user_id, MAX(last_activity) AS last_activity
+                     FROM (
+                         SELECT user_id, MAX(updated_at) AS last_activity
+                         FROM memory_facts
+                         WHERE deleted_at IS NULL
+                         GROUP BY user_id
+                         UNION ALL
+                         SELECT user_id, MAX(timestamp) AS last_activity
+                         FROM chat_messages
+                         WHERE deleted_at IS NULL
+                         GROUP BY user_id
+                     ) activity
+                     GROUP BY user_id
+                 ) u
+                 LEFT JOIN (
+                     SELECT user_id, COUNT(*)::bigint AS fact_count
+                     FROM memory_facts
+                     WHERE deleted_at IS NULL
+                     GROUP BY user_id
+                 ) f ON f.user_id = u.user_id
+                 LEFT JOIN (
+                     SELECT user_id, COUNT(*)::bigint AS message_count
+                     FROM chat_messages
+                     WHERE deleted_at IS NULL
+                     GROUP BY user_id
+                 ) m ON m.user_id = u.user_id
+                 ORDER BY u.last_activity DESC
+                 LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(
+            |(user_id, fact_count, message_count, last_activity)| UserDashboardSummary {
+                user_id,
+                fact_count,
+                message_count,
+                last_activity,
+            },
+        )
+        .collect::<Vec<_>>();
+
+        Ok(users)
+    }
+
+    async fn user_stats(&self, user_id: &str) -> anyhow::Result<UserStats> {
+        let (messages_sent, replies_received, first_message_at) =
+            sqlx::query_as::<_, (i64, i64, Option<chrono::DateTime<chrono::Utc>>)>(
+                "SELECT
+                     COUNT(*) FILTER (WHERE role = 'user')::bigint,
+                     COUNT(*) FILTER (WHERE role = 'assistant')::bigint,
+                     MIN(timestamp)
+                 FROM chat_messages
+                 WHERE user_id = $1 AND deleted_at IS NULL",
+            )
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        let fact_count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*)::bigint FROM memory_facts WHERE user_id = $1 AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let tool_calls = sqlx::query_as::<_, (String, i64)>(
+            "SELECT tool_name, COUNT(*)::bigint AS calls
+             FROM tool_call_logs
+             WHERE user_id = $1
+             GROUP BY tool_name
+             ORDER BY calls DESC, tool_name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        // Each reply is paired with the message right before it in the
+        // channel, as in `average_reply_ms`.
+        let avg_reply_ms = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT (AVG(EXTRACT(EPOCH FROM (timestamp - previous_at))) * 1000)::bigint
+             FROM (
+                 SELECT role, timestamp,
+                        LAG(role) OVER turns AS previous_role,
+                        LAG(timestamp) OVER turns AS previous_at
+                 FROM chat_messages
+                 WHERE user_id = $1 AND deleted_at IS NULL
+                 WINDOW turns AS (PARTITION BY channel_id ORDER BY timestamp)
+             ) pairs
+             WHERE role = 'assistant'
+               AND previous_role = 'user'
+               AND timestamp - previous_at <= make_interval(secs => $2)",
+        )
+        .bind(user_id)
+        .bind(REPLY_TIME_WINDOW.num_seconds() as f64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(UserStats {
+            messages_sent,
+            replies_received,
+            fact_count,
+            tool_calls,
+            avg_reply_ms,
+            first_message_at,
+        })
+    }
+
+    async fn record_tool_call(&self, tool_call: ToolCallRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO tool_call_logs
+             (user_id, guild_id, channel_id, tool_name, source, args_json, result_text, citations_text, success, error, timestamp)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(tool_call.user_id)
+        .bind(tool_call.guild_id)
+        .bind(tool_call.channel_id)
+        .bind(tool_call.tool_name)
+        .bind(tool_call.source)
+        .bind(tool_call.args_json)
+        .bind(tool_call.result_text)
+        .bind(tool_call.citations.join("\n"))
+        .bind(tool_call.success)
+        .bind(tool_call.error)
+        .bind(tool_call.timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_thought(&self, thought: ThoughtRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO message_thoughts
+             (user_id, guild_id, channel_id, message_id, source, text, timestamp)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(thought.user_id)
+        .bind(thought.guild_id)
+        .bind(thought.channel_id)
+        .bind(thought.message_id)
+        .bind(thought.source)
+        .bind(thought.text)
+        .bind(thought.timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_thoughts(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ThoughtRecord>> {
+        let mut thoughts = sqlx::query_as::<_, ThoughtRow>(
+            "SELECT user_id, guild_id, channel_id, message_id, source, text, timestamp
+             FROM message_thoughts
+             WHERE user_id = $1
+             ORDER BY timestamp DESC
+             LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(thought_from_row)
+        .collect::<Vec<_>>();
+
+        thoughts.reverse();
+        Ok(thoughts)
+    }
+
+    async fn clear_thoughts(&self, user_id: &str) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM message_thoughts WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_tool_calls(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ToolCallRecord>> {
+        let limit = limit as i64;
+        let mut calls = sqlx::query_as::<
+            _,
+            (
+                String,
+                String,
+                String,
+                String,
+                String,
+                String,
+                String,
+                String,
+                bool,
+                Option<String>,
+                chrono::DateTime<chrono::Utc>,
+            ),
+        >(
+            "SELECT user_id, guild_id, channel_id, tool_name, source, args_json, result_text, citations_text, success, error, timestamp
+             FROM tool_call_logs
+             WHERE user_id = $1
+             ORDER BY timestamp DESC
+             LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(
+            |(
+                user_id,
+                guild_id,
+                channel_id,
+                tool_name,
+                source,
+                args_json,
+                result_text,
+                citations_text,
+                success,
+                error,
+                timestamp,
+            )| ToolCallRecord {
+                user_id,
+                guild_id,
+                channel_id,
+                tool_name,
+                source,
+                args_json,
+                result_text,
+                citations: split_citations(&citations_text),
+                success,
+                error,
+                timestamp,
+            },
+        )
+        .collect::<Vec<_>>();
+
+        calls.reverse();
+        Ok(calls)
+    }
+
+    async fn record_planner_decision(&self, decision: PlannerDecisionRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO planner_decision_logs
+             (user_id, guild_id, channel_id, planner, decision, rationale, payload_json, success, error, timestamp)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(decision.user_id)
+        .bind(decision.guild_id)
+        .bind(decision.channel_id)
+        .bind(decision.planner)
+        .bind(decision.decision)
+        .bind(decision.rationale)
+        .bind(decision.payload_json)
+        .bind(decision.success)
+        .bind(decision.error)
+        .bind(decision.timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_planner_decisions(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PlannerDecisionRecord>> {
+        let limit = limit as i64;
+        let mut decisions = sqlx::query_as::<_, PlannerDecisionRow>(
+            "SELECT id, user_id, guild_id, channel_id, planner, decision, rationale, payload_json, success, error, timestamp
+             FROM planner_decision_logs
+             WHERE user_id = $1
+             ORDER BY timestamp DESC
+             LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(planner_decision_from_row)
+        .collect::<Vec<_>>();
+
+        decisions.reverse();
+        Ok(decisions)
+    }
+
+    async fn list_planner_decisions_of_kind(
+        &self,
+        user_id: &str,
+        planner: &str,
+        decision: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PlannerDecisionRecord>> {
+        let limit = limit as i64;
+        let mut decisions = sqlx::query_as::<_, PlannerDecisionRow>(
+            "SELECT id, user_id, guild_id, channel_id, planner, decision, rationale, payload_json, success, error, timestamp
+             FROM planner_decision_logs
+             WHERE user_id = $1 AND planner = $2 AND decision = $3
+             ORDER BY timestamp DESC
+             LIMIT $4",
+        )
+        .bind(user_id)
+        .bind(planner)
+        .bind(decision)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(planner_decision_from_row)
+        .collect::<Vec<_>>();
+
+        decisions.reverse();
+        Ok(decisions)
+    }
+
+    async fn get_planner_decision(
+        &self,
+        id: &str,
+    ) -> anyhow::Result<Option<PlannerDecisionRecord>> {
+        let Ok(id) = id.parse::<i64>() else {
+            return Ok(None);
+        };
+        let row = sqlx::query_as::<_, PlannerDecisionRow>(
+            "SELECT id, user_id, guild_id, channel_id, planner, decision, rationale, payload_json, success, error, timestamp
+             FROM planner_decision_logs
+             WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(planner_decision_from_row))
+    }
+
+    async fn get_guild_settings(&self, guild_id: &str) -> anyhow::Result<Option<GuildSettings>> {
+        let row = sqlx::query_as::<_, (String,)>(
+            "SELECT settings_json FROM guild_settings WHERE guild_id = $1",
+        )
+        .bind(guild_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(settings_json,)| Ok(GuildSettings::from_stored_json(&settings_json)?))
+            .transpose()
+    }
+
+    async fn put_guild_settings(&self, settings: GuildSettings) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO guild_settings (guild_id, settings_json, updated_at)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (guild_id)
+             DO UPDATE SET settings_json = EXCLUDED.settings_json, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&settings.guild_id)
+        .bind(serde_json::to_string(&settings)?)
+        .bind(settings.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_custom_commands(&self) -> anyhow::Result<Vec<CustomCommand>> {
+        let rows = sqlx::query_as::<_, (String, String, String, chrono::DateTime<chrono::Utc>)>(
+            "SELECT name, description, template, updated_at
+             FROM custom_commands
+             ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(name, description, template, updated_at)| CustomCommand {
+                name,
+                description,
+                template,
+                updated_at,
+            })
+            .collect())
+    }
+
+    async fn put_custom_command(&self, command: CustomCommand) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO custom_commands (name, description, template, updated_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (name)
+             DO UPDATE SET description = EXCLUDED.description,
+                           template = EXCLUDED.template,
+                           updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&command.name)
+        .bind(&command.description)
+        .bind(&command.template)
+        .bind(command.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_custom_command(&self, name: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM custom_commands WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn try_increment_tool_usage(
+        &self,
+        user_id: &str,
+        tool_name: &str,
+        day: chrono::NaiveDate,
+        limit: u32,
+    ) -> anyhow::Result<bool> {
+        if limit == 0 {
+            return Ok(false);
+        }
+        let row = sqlx::query_as::<_, (i32,)>(
+            "INSERT INTO tool_usage (user_id, tool_name, day, count)
+             VALUES ($1, $2, $3, 1)
+             ON CONFLICT (user_id, tool_name, day)
+             DO UPDATE SET count = tool_usage.count + 1
+             WHERE tool_usage.count < $4
+             RETURNING count",
+        )
+        .bind(user_id)
+        .bind(tool_name)
+        .bind(day)
+        .bind(limit as i32)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn list_tool_usage(
+        &self,
+        user_id: &str,
+        day: chrono::NaiveDate,
+    ) -> anyhow::Result<Vec<ToolUsageRecord>> {
+        let records = sqlx::query_as::<_, (String, i32)>(
+            "SELECT tool_name, count FROM tool_usage
+             WHERE user_id = $1 AND day = $2
+             ORDER BY tool_name",
+        )
+        .bind(user_id)
+        .bind(day)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(tool_name, count)| ToolUsageRecord {
+            user_id: user_id.to_owned(),
+            tool_name,
+            day,
+            count: count.max(0) as u32,
+        })
+        .collect();
+
+        Ok(records)
+    }
+
+    async fn reset_tool_usage(
+        &self,
+        user_id: &str,
+        tool_name: Option<&str>,
+        day: chrono::NaiveDate,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM tool_usage
+             WHERE user_id = $1 AND day = $2 AND ($3::TEXT IS NULL OR tool_name = $3)",
+        )
+        .bind(user_id)
+        .bind(day)
+        .bind(tool_name)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_stale_facts(
+        &self,
+        stale_before: chrono::DateTime<chrono::Utc>,
+        below_confidence: f32,
+        reviewed_before: chrono::DateTime<chrono::Utc>,
+        after_user_id: Option<&str>,
+        max_users: usize,
+        per_user: usize,
+    ) -> anyhow::Result<Vec<(String, MemoryFact)>> {
+        let stale = sqlx::query_as::<
+            _,
+            (
                 String,
                 String,
                 String,
-                bool,
-                Option<String>,
+                f32,
+                String,
                 chrono::DateTime<chrono::Utc>,
+                Option<chrono::DateTime<chrono::Utc>>,
             ),
         >(
-            "SELECT user_id, guild_id, channel_id, tool_name, source, args_json, result_text, citations_text, success, error, timestamp
-             FROM tool_call_logs
-             WHERE user_id = $1
-             ORDER BY timestamp DESC
+            "WITH stale AS (
+                 SELECT f.user_id, f.key, f.value, f.confidence, f.source, f.updated_at,
+                        f.last_confirmed_at,
+                        ROW_NUMBER() OVER (
+                            PARTITION BY f.user_id
+                            ORDER BY COALESCE(f.last_confirmed_at, f.updated_at) ASC
+                        ) AS position
+                 FROM memory_facts f
+                 LEFT JOIN memory_reviews r ON r.user_id = f.user_id
+                 WHERE f.deleted_at IS NULL
+                   AND (COALESCE(f.last_confirmed_at, f.updated_at) < $1 OR f.confidence < $2)
+                   AND (r.last_sent_at IS NULL OR r.last_sent_at < $3)
+                   AND ($4::TEXT IS NULL OR f.user_id > $4)
+             ),
+             page AS (
+                 SELECT DISTINCT user_id FROM stale ORDER BY user_id LIMIT $5
+             )
+             SELECT s.user_id, s.key, s.value, s.confidence, s.source, s.updated_at,
+                    s.last_confirmed_at
+             FROM stale s
+             JOIN page p ON p.user_id = s.user_id
+             WHERE s.position <= $6
+             ORDER BY s.user_id, s.position",
+        )
+        .bind(stale_before)
+        .bind(below_confidence)
+        .bind(reviewed_before)
+        .bind(after_user_id)
+        .bind(max_users as i64)
+        .bind(per_user as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(
+            |(user_id, key, value, confidence, source, updated_at, last_confirmed_at)| {
+                (
+                    user_id,
+                    fact_from_row((
+                        key,
+                        value,
+                        confidence,
+                        source,
+                        updated_at,
+                        last_confirmed_at,
+                    )),
+                )
+            },
+        )
+        .collect();
+
+        Ok(stale)
+    }
+
+    async fn last_memory_review(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let row = sqlx::query_as::<_, (chrono::DateTime<chrono::Utc>,)>(
+            "SELECT last_sent_at FROM memory_reviews WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(last_sent_at,)| last_sent_at))
+    }
+
+    async fn record_memory_review(
+        &self,
+        user_id: &str,
+        sent_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO memory_reviews (user_id, last_sent_at)
+             VALUES ($1, $2)
+             ON CONFLICT (user_id)
+             DO UPDATE SET last_sent_at = EXCLUDED.last_sent_at",
+        )
+        .bind(user_id)
+        .bind(sent_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_notification_prefs(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Option<NotificationPrefs>> {
+        let row = sqlx::query_as::<_, (String,)>(
+            "SELECT prefs_json FROM notification_prefs WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(prefs_json,)| {
+            let mut prefs: NotificationPrefs = serde_json::from_str(&prefs_json)?;
+            prefs.user_id = user_id.to_owned();
+            Ok(prefs)
+        })
+        .transpose()
+    }
+
+    async fn put_notification_prefs(&self, prefs: NotificationPrefs) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO notification_prefs (user_id, prefs_json, updated_at)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (user_id)
+             DO UPDATE SET prefs_json = EXCLUDED.prefs_json, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&prefs.user_id)
+        .bind(serde_json::to_string(&prefs)?)
+        .bind(prefs.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_notification(
+        &self,
+        user_id: &str,
+        kind: &str,
+        sent_at: chrono::DateTime<chrono::Utc>,
+        keep_since: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM notification_log WHERE user_id = $1 AND sent_at < $2")
+            .bind(user_id)
+            .bind(keep_since)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query(
+            "INSERT INTO notification_log (user_id, kind, sent_at)
+             VALUES ($1, $2, $3)",
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(sent_at)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn count_notifications_since(
+        &self,
+        user_id: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<usize> {
+        let (count,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT COUNT(*) FROM notification_log WHERE user_id = $1 AND sent_at >= $2",
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.max(0) as usize)
+    }
+
+    async fn has_date_greeting(
+        &self,
+        user_id: &str,
+        fact_key: &str,
+        day: chrono::NaiveDate,
+    ) -> anyhow::Result<bool> {
+        let greeted = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (
+                 SELECT 1 FROM date_greetings
+                 WHERE user_id = $1 AND fact_key = $2 AND greeted_on = $3
+             )",
+        )
+        .bind(user_id)
+        .bind(fact_key)
+        .bind(day)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(greeted)
+    }
+
+    async fn record_date_greeting(
+        &self,
+        user_id: &str,
+        fact_key: &str,
+        day: chrono::NaiveDate,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO date_greetings (user_id, fact_key, greeted_on, sent_at)
+             VALUES ($1, $2, $3, NOW())
+             ON CONFLICT (user_id, fact_key, greeted_on) DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(fact_key)
+        .bind(day)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_model_handoff(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+    ) -> anyhow::Result<Option<ModelHandoff>> {
+        let row =
+            sqlx::query_as::<_, (String, Option<String>, i32, chrono::DateTime<chrono::Utc>)>(
+                "SELECT model, summary, remaining_turns, updated_at
+             FROM model_handoffs
+             WHERE user_id = $1 AND guild_id = $2 AND channel_id = $3",
+            )
+            .bind(user_id)
+            .bind(guild_id)
+            .bind(channel_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(
+            |(model, summary, remaining_turns, updated_at)| ModelHandoff {
+                user_id: user_id.to_owned(),
+                guild_id: guild_id.to_owned(),
+                channel_id: channel_id.to_owned(),
+                model,
+                summary,
+                remaining_turns: remaining_turns.max(0) as u32,
+                updated_at,
+            },
+        ))
+    }
+
+    async fn put_model_handoff(&self, handoff: ModelHandoff) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO model_handoffs
+                (user_id, guild_id, channel_id, model, summary, remaining_turns, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (user_id, guild_id, channel_id)
+             DO UPDATE SET model = EXCLUDED.model,
+                           summary = EXCLUDED.summary,
+                           remaining_turns = EXCLUDED.remaining_turns,
+                           updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&handoff.user_id)
+        .bind(&handoff.guild_id)
+        .bind(&handoff.channel_id)
+        .bind(&handoff.model)
+        .bind(&handoff.summary)
+        .bind(handoff.remaining_turns.min(i32::MAX as u32) as i32)
+        .bind(handoff.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn put_prompt_rollout(&self, rollout: PromptRollout) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO prompt_rollouts (persona, record_json, started_at)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (persona)
+             DO UPDATE SET record_json = EXCLUDED.record_json,
+                           started_at = EXCLUDED.started_at",
+        )
+        .bind(&rollout.persona)
+        .bind(serde_json::to_string(&rollout)?)
+        .bind(rollout.started_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_prompt_rollouts(&self) -> anyhow::Result<Vec<PromptRollout>> {
+        let rows = sqlx::query_scalar::<_, String>(
+            "SELECT record_json FROM prompt_rollouts ORDER BY persona ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|record_json| Ok(serde_json::from_str(record_json)?))
+            .collect()
+    }
+
+    async fn record_prompt_rollout_turn(&self, turn: RolloutTurn) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO prompt_rollout_turns
+                (persona, rollout_started_at, variant, user_id, reply_message_id, planner_fallback)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&turn.persona)
+        .bind(turn.rollout_started_at)
+        .bind(turn.variant.as_str())
+        .bind(&turn.user_id)
+        .bind(&turn.reply_message_id)
+        .bind(turn.planner_fallback)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_prompt_rollout_turns(
+        &self,
+        persona: &str,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<RolloutTurn>> {
+        let rows = sqlx::query_as::<_, (String, String, String, Option<bool>)>(
+            "SELECT variant, user_id, reply_message_id, planner_fallback
+             FROM prompt_rollout_turns
+             WHERE persona = $1 AND rollout_started_at = $2
+             ORDER BY id ASC",
+        )
+        .bind(persona)
+        .bind(started_at)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(variant, user_id, reply_message_id, planner_fallback)| {
+                Ok(RolloutTurn {
+                    persona: persona.to_owned(),
+                    rollout_started_at: started_at,
+                    variant: PromptVariant::parse(&variant)
+                        .ok_or_else(|| anyhow::anyhow!("unknown prompt variant `{variant}`"))?,
+                    user_id,
+                    reply_message_id,
+                    planner_fallback,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_onboarding(&self, user_id: &str) -> anyhow::Result<Option<OnboardingState>> {
+        let row = sqlx::query_as::<_, (Option<String>, chrono::DateTime<chrono::Utc>)>(
+            "SELECT step, updated_at FROM onboarding WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(step, updated_at)| OnboardingState {
+            user_id: user_id.to_owned(),
+            step: step.as_deref().and_then(OnboardingStep::parse),
+            updated_at,
+        }))
+    }
+
+    async fn put_onboarding(&self, state: OnboardingState) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO onboarding (user_id, step, updated_at)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (user_id)
+             DO UPDATE SET step = EXCLUDED.step,
+                           updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&state.user_id)
+        .bind(state.step.map(OnboardingStep::as_str))
+        .bind(state.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn last_seen_at(
+        &self,
+        adapter: &str,
+    ) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let row = sqlx::query_as::<_, (chrono::DateTime<chrono::Utc>,)>(
+            "SELECT last_seen_at FROM adapter_heartbeats WHERE adapter = $1",
+        )
+        .bind(adapter)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(last_seen_at,)| last_seen_at))
+    }
+
+    async fn record_last_seen(
+        &self,
+        adapter: &str,
+        seen_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO adapter_heartbeats (adapter, last_seen_at)
+             VALUES ($1, $2)
+             ON CONFLICT (adapter)
+             DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at",
+        )
+        .bind(adapter)
+        .bind(seen_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn queue_pending_fact(&self, pending: PendingFactRecord) -> anyhow::Result<String> {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "INSERT INTO pending_facts
+             (user_id, guild_id, channel_id, key, value, confidence, source, last_confirmed_at, rationale, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING id",
+        )
+        .bind(pending.user_id)
+        .bind(pending.guild_id)
+        .bind(pending.channel_id)
+        .bind(pending.fact.key)
+        .bind(pending.fact.value)
+        .bind(pending.fact.confidence)
+        .bind(pending.fact.source)
+        .bind(pending.fact.last_confirmed_at)
+        .bind(pending.rationale)
+        .bind(pending.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id.to_string())
+    }
+
+    async fn list_pending_facts(
+        &self,
+        user_id: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<PendingFactRecord>> {
+        let pending = sqlx::query_as::<_, PendingFactRow>(
+            "SELECT id, user_id, guild_id, channel_id, key, value, confidence, source, last_confirmed_at, rationale, created_at
+             FROM pending_facts
+             WHERE ($1::TEXT IS NULL OR user_id = $1)
+             ORDER BY created_at ASC
              LIMIT $2",
         )
         .bind(user_id)
-        .bind(limit)
+        .bind(limit as i64)
         .fetch_all(&self.pool)
         .await?
         .into_iter()
-        .map(
-            |(
-                user_id,
-                guild_id,
-                channel_id,
-                tool_name,
-                source,
-                args_json,
-                result_text,
-                citations_text,
-                success,
-                error,
-                timestamp,
-            )| ToolCallRecord {
-                user_id,
-                guild_id,
-                channel_id,
-                tool_name,
-                source,
-                args_json,
-                result_text,
-                citations: split_citations(&citations_text),
-                success,
-                error,
-                timestamp,
-            },
+        .map(pending_fact_from_row)
+        .collect();
+
+        Ok(pending)
+    }
+
+    async fn take_pending_fact(&self, id: &str) -> anyhow::Result<Option<PendingFactRecord>> {
+        let Ok(id) = id.parse::<i64>() else {
+            return Ok(None);
+        };
+        let row = sqlx::query_as::<_, PendingFactRow>(
+            "DELETE FROM pending_facts
+             WHERE id = $1
+             RETURNING id, user_id, guild_id, channel_id, key, value, confidence, source, last_confirmed_at, rationale, created_at",
         )
-        .collect::<Vec<_>>();
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
 
-        calls.reverse();
-        Ok(calls)
+        Ok(row.map(pending_fact_from_row))
     }
 
-    async fn record_planner_decision(&self, decision: PlannerDecisionRecord) -> anyhow::Result<()> {
+    async fn create_background_task(&self, task: BackgroundTaskRecord) -> anyhow::Result<String> {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "INSERT INTO background_tasks
+             (user_id, guild_id, channel_id, tool_name, args_json, origin, status, result_text,
+              created_at, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING id",
+        )
+        .bind(task.user_id)
+        .bind(task.guild_id)
+        .bind(task.channel_id)
+        .bind(task.tool_name)
+        .bind(task.args.to_string())
+        .bind(task.origin.as_str())
+        .bind(task.status.as_str())
+        .bind(task.result_text)
+        .bind(task.created_at)
+        .bind(task.updated_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id.to_string())
+    }
+
+    async fn update_background_task(
+        &self,
+        id: &str,
+        status: BackgroundTaskStatus,
+        result_text: Option<&str>,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<bool> {
+        let Ok(id) = id.parse::<i64>() else {
+            return Ok(false);
+        };
+        let result = sqlx::query(
+            "UPDATE background_tasks
+             SET status = $2, result_text = COALESCE($3, result_text), updated_at = $4
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(result_text)
+        .bind(at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_background_tasks(
+        &self,
+        user_id: Option<&str>,
+        statuses: &[BackgroundTaskStatus],
+        limit: usize,
+    ) -> anyhow::Result<Vec<BackgroundTaskRecord>> {
+        let statuses = statuses
+            .iter()
+            .map(|status| status.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let tasks = sqlx::query_as::<_, BackgroundTaskRow>(
+            "SELECT id, user_id, guild_id, channel_id, tool_name, args_json, origin, status,
+                    result_text, created_at, updated_at
+             FROM background_tasks
+             WHERE ($1::TEXT IS NULL OR user_id = $1)
+               AND (cardinality($2::TEXT[]) = 0 OR status = ANY($2))
+             ORDER BY created_at DESC, id DESC
+             LIMIT $3",
+        )
+        .bind(user_id)
+        .bind(statuses)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(background_task_from_row)
+        .collect();
+
+        Ok(tasks)
+    }
+
+    async fn add_system_notice(&self, notice: SystemNoticeRecord) -> anyhow::Result<String> {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "INSERT INTO system_notices (user_id, text, created_at, expires_at)
+             VALUES ($1, $2, $3, $4)
+             RETURNING id",
+        )
+        .bind(notice.user_id)
+        .bind(notice.text)
+        .bind(notice.created_at)
+        .bind(notice.expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id.to_string())
+    }
+
+    async fn list_system_notices(
+        &self,
+        user_id: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<SystemNoticeRecord>> {
+        let notices = sqlx::query_as::<_, SystemNoticeRow>(
+            "SELECT id, user_id, text, created_at, expires_at
+             FROM system_notices
+             WHERE user_id = $1 AND expires_at > $2
+             ORDER BY created_at ASC, id ASC",
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(system_notice_from_row)
+        .collect();
+
+        Ok(notices)
+    }
+
+    async fn consume_system_notices(
+        &self,
+        user_id: &str,
+        ids: &[String],
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        let ids = ids
+            .iter()
+            .filter_map(|id| id.parse::<i64>().ok())
+            .collect::<Vec<_>>();
         sqlx::query(
-            "INSERT INTO planner_decision_logs
-             (user_id, guild_id, channel_id, planner, decision, rationale, payload_json, success, error, timestamp)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            "DELETE FROM system_notices
+             WHERE user_id = $1 AND (id = ANY($2) OR expires_at <= $3)",
         )
-        .bind(decision.user_id)
-        .bind(decision.guild_id)
-        .bind(decision.channel_id)
-        .bind(decision.planner)
-        .bind(decision.decision)
-        .bind(decision.rationale)
-        .bind(decision.payload_json)
-        .bind(decision.success)
-        .bind(decision.error)
-        .bind(decision.timestamp)
+        .bind(user_id)
+        .bind(&ids)
+        .bind(now)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    async fn list_planner_decisions(
+    async fn put_in_flight_reply(&self, record: InFlightReplyRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO in_flight_replies
+                 (message_id, user_id, guild_id, channel_id, content, phase, started_at, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (message_id)
+             DO UPDATE SET phase = EXCLUDED.phase, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&record.message_id)
+        .bind(&record.user_id)
+        .bind(&record.guild_id)
+        .bind(&record.channel_id)
+        .bind(&record.content)
+        .bind(record.phase.as_str())
+        .bind(record.started_at)
+        .bind(record.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn touch_in_flight_reply(
         &self,
-        user_id: &str,
+        message_id: &str,
+        phase: Option<TurnPhase>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE in_flight_replies
+             SET phase = COALESCE($2, phase), updated_at = $3
+             WHERE message_id = $1",
+        )
+        .bind(message_id)
+        .bind(phase.map(TurnPhase::as_str))
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_in_flight_reply(&self, message_id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM in_flight_replies WHERE message_id = $1")
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_stale_in_flight_replies(
+        &self,
+        updated_before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<InFlightReplyRecord>> {
+        let rows = sqlx::query_as::<_, InFlightReplyRow>(
+            "SELECT message_id, user_id, guild_id, channel_id, content, phase, started_at,
+                    updated_at
+             FROM in_flight_replies
+             WHERE updated_at < $1
+             ORDER BY started_at ASC",
+        )
+        .bind(updated_before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    message_id,
+                    user_id,
+                    guild_id,
+                    channel_id,
+                    content,
+                    phase,
+                    started_at,
+                    updated_at,
+                )| {
+                    Ok(InFlightReplyRecord {
+                        message_id,
+                        user_id,
+                        guild_id,
+                        channel_id,
+                        content,
+                        phase: TurnPhase::parse(&phase)
+                            .ok_or_else(|| anyhow::anyhow!("unknown turn phase `{phase}`"))?,
+                        started_at,
+                        updated_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    async fn put_outbox_message(&self, record: OutboxRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO reply_outbox (id, kind, record_json, next_attempt_at, created_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (id)
+             DO UPDATE SET record_json = EXCLUDED.record_json,
+                           next_attempt_at = EXCLUDED.next_attempt_at",
+        )
+        .bind(&record.id)
+        .bind(record.kind.as_str())
+        .bind(serde_json::to_string(&record)?)
+        .bind(record.next_attempt_at)
+        .bind(record.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn claim_due_outbox_messages(
+        &self,
+        kind: OutboxKind,
+        now: chrono::DateTime<chrono::Utc>,
+        lease_until: chrono::DateTime<chrono::Utc>,
         limit: usize,
-    ) -> anyhow::Result<Vec<PlannerDecisionRecord>> {
-        let limit = limit as i64;
-        let mut decisions = sqlx::query_as::<
+    ) -> anyhow::Result<Vec<OutboxRecord>> {
+        let rows = sqlx::query_scalar::<_, String>(
+            "UPDATE reply_outbox
+             SET next_attempt_at = $3
+             WHERE id IN (
+                 SELECT id
+                 FROM reply_outbox
+                 WHERE kind = $1 AND next_attempt_at <= $2
+                 ORDER BY created_at ASC
+                 LIMIT $4
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING record_json",
+        )
+        .bind(kind.as_str())
+        .bind(now)
+        .bind(lease_until)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut records = rows
+            .iter()
+            .map(|record_json| serde_json::from_str::<OutboxRecord>(record_json))
+            .collect::<Result<Vec<_>, _>>()?;
+        records.sort_by_key(|record| record.created_at);
+        Ok(records)
+    }
+
+    async fn delete_outbox_message(&self, id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM reply_outbox WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn put_user_api_key(&self, record: UserApiKeyRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO user_api_keys (user_id, provider, encrypted_key, key_hint, updated_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (user_id)
+             DO UPDATE SET provider = EXCLUDED.provider, encrypted_key = EXCLUDED.encrypted_key,
+                           key_hint = EXCLUDED.key_hint, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&record.user_id)
+        .bind(record.provider.as_str())
+        .bind(&record.encrypted_key)
+        .bind(&record.key_hint)
+        .bind(record.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_user_api_key(&self, user_id: &str) -> anyhow::Result<Option<UserApiKeyRecord>> {
+        let row = sqlx::query_as::<_, (String, String, String, chrono::DateTime<chrono::Utc>)>(
+            "SELECT provider, encrypted_key, key_hint, updated_at
+             FROM user_api_keys
+             WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(provider, encrypted_key, key_hint, updated_at)| {
+            Ok(UserApiKeyRecord {
+                user_id: user_id.to_owned(),
+                provider: KeyProvider::parse(&provider)
+                    .ok_or_else(|| anyhow::anyhow!("unknown key provider `{provider}`"))?,
+                encrypted_key,
+                key_hint,
+                updated_at,
+            })
+        })
+        .transpose()
+    }
+
+    async fn delete_user_api_key(&self, user_id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM user_api_keys WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_calendar_feed_salt(&self, user_id: &str) -> anyhow::Result<Option<String>> {
+        let salt = sqlx::query_scalar::<_, String>(
+            "SELECT salt FROM calendar_feed_salts WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(salt)
+    }
+
+    async fn put_calendar_feed_salt(&self, user_id: &str, salt: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO calendar_feed_salts (user_id, salt, rotated_at)
+             VALUES ($1, $2, NOW())
+             ON CONFLICT (user_id)
+             DO UPDATE SET salt = EXCLUDED.salt, rotated_at = EXCLUDED.rotated_at",
+        )
+        .bind(user_id)
+        .bind(salt)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_audit_log(&self, record: AuditLogRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO audit_logs (actor, action, target, before_json, after_json, timestamp)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(record.actor)
+        .bind(record.action)
+        .bind(record.target)
+        .bind(record.before_json)
+        .bind(record.after_json)
+        .bind(record.timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_audit_logs(&self, query: &AuditLogQuery) -> anyhow::Result<Vec<AuditLogRecord>> {
+        let records = sqlx::query_as::<
             _,
             (
                 String,
                 String,
                 String,
-                String,
-                String,
-                String,
-                String,
-                bool,
+                Option<String>,
                 Option<String>,
                 chrono::DateTime<chrono::Utc>,
             ),
         >(
-            "SELECT user_id, guild_id, channel_id, planner, decision, rationale, payload_json, success, error, timestamp
-             FROM planner_decision_logs
-             WHERE user_id = $1
+            "SELECT actor, action, target, before_json, after_json, timestamp
+             FROM audit_logs
+             WHERE ($1::TEXT IS NULL OR actor = $1)
+               AND ($2::TEXT IS NULL OR action = $2)
+               AND ($3::TEXT IS NULL OR target = $3)
+               AND ($4::TIMESTAMPTZ IS NULL OR timestamp >= $4)
              ORDER BY timestamp DESC
-             LIMIT $2",
+             LIMIT $5",
         )
-        .bind(user_id)
-        .bind(limit)
+        .bind(query.actor.as_deref())
+        .bind(query.action.as_deref())
+        .bind(query.target.as_deref())
+        .bind(query.since)
+        .bind(query.limit as i64)
         .fetch_all(&self.pool)
         .await?
         .into_iter()
         .map(
-            |(
-                user_id,
-                guild_id,
-                channel_id,
-                planner,
-                decision,
-                rationale,
-                payload_json,
-                success,
-                error,
-                timestamp,
-            )| PlannerDecisionRecord {
-                user_id,
-                guild_id,
-                channel_id,
-                planner,
-                decision,
-                rationale,
-                payload_json,
-                success,
-                error,
+            |(actor, action, target, before_json, after_json, timestamp)| AuditLogRecord {
+                actor,
+                action,
+                target,
+                before_json,
+                after_json,
                 timestamp,
             },
         )
-        .collect::<Vec<_>>();
+        .collect();
 
-        decisions.reverse();
-        Ok(decisions)
+        Ok(records)
+    }
+}
+
+async fn upsert_fact_row(
+    connection: &mut sqlx::PgConnection,
+    user_id: &str,
+    fact: MemoryFact,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO memory_facts (user_id, key, value, confidence, source, updated_at, last_confirmed_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (user_id, key)
+         DO UPDATE SET value = EXCLUDED.value, confidence = EXCLUDED.confidence, source = EXCLUDED.source, updated_at = EXCLUDED.updated_at, last_confirmed_at = EXCLUDED.last_confirmed_at, deleted_at = NULL",
+    )
+    .bind(user_id)
+    .bind(fact.key)
+    .bind(fact.value)
+    .bind(fact.confidence)
+    .bind(fact.source)
+    .bind(fact.updated_at)
+    .bind(fact.last_confirmed_at)
+    .execute(connection)
+    .await?;
+
+    Ok(())
+}
+
+fn chat_message_from_row(
+    (
+        id,
+        user_id,
+        guild_id,
+        channel_id,
+        role,
+        content,
+        timestamp,
+        modality,
+        session_id,
+        pinned,
+        source_message_id,
+    ): ChatMessageRow,
+) -> ChatMessageRecord {
+    ChatMessageRecord {
+        id: id.to_string(),
+        user_id,
+        guild_id,
+        channel_id,
+        role: parse_role(&role),
+        content,
+        timestamp,
+        modality: Modality::parse(&modality),
+        session_id: session_id.map(|id| id.to_string()),
+        pinned,
+        source_message_id,
+    }
+}
+
+fn chat_session_from_row(
+    (id, user_id, title, started_at, last_active_at): ChatSessionRow,
+) -> ChatSession {
+    ChatSession {
+        id: id.to_string(),
+        user_id,
+        title,
+        started_at,
+        last_active_at,
+    }
+}
+
+fn thought_from_row(
+    (user_id, guild_id, channel_id, message_id, source, text, timestamp): ThoughtRow,
+) -> ThoughtRecord {
+    ThoughtRecord {
+        user_id,
+        guild_id,
+        channel_id,
+        message_id,
+        source,
+        text,
+        timestamp,
+    }
+}
+
+fn fact_from_row(
+    (key, value, confidence, source, updated_at, last_confirmed_at): FactRow,
+) -> MemoryFact {
+    MemoryFact {
+        key,
+        value,
+        confidence,
+        source,
+        updated_at,
+        last_confirmed_at,
+    }
+}
+
+fn planner_decision_from_row(
+    (
+        id,
+        user_id,
+        guild_id,
+        channel_id,
+        planner,
+        decision,
+        rationale,
+        payload_json,
+        success,
+        error,
+        timestamp,
+    ): PlannerDecisionRow,
+) -> PlannerDecisionRecord {
+    PlannerDecisionRecord {
+        id: id.to_string(),
+        user_id,
+        guild_id,
+        channel_id,
+        planner,
+        decision,
+        rationale,
+        payload_json,
+        success,
+        error,
+        timestamp,
+    }
+}
+
+fn background_task_from_row(
+    (
+        id,
+        user_id,
+        guild_id,
+        channel_id,
+        tool_name,
+        args_json,
+        origin,
+        status,
+        result_text,
+        created_at,
+        updated_at,
+    ): BackgroundTaskRow,
+) -> BackgroundTaskRecord {
+    BackgroundTaskRecord {
+        id: id.to_string(),
+        user_id,
+        guild_id,
+        channel_id,
+        tool_name,
+        args: serde_json::from_str(&args_json).unwrap_or(serde_json::Value::Null),
+        origin: TaskOrigin::parse(&origin).unwrap_or_default(),
+        status: BackgroundTaskStatus::parse(&status).unwrap_or(BackgroundTaskStatus::Failed),
+        result_text,
+        created_at,
+        updated_at,
+    }
+}
+
+fn system_notice_from_row(
+    (id, user_id, text, created_at, expires_at): SystemNoticeRow,
+) -> SystemNoticeRecord {
+    SystemNoticeRecord {
+        id: id.to_string(),
+        user_id,
+        text,
+        created_at,
+        expires_at,
+    }
+}
+
+fn pending_fact_from_row(
+    (
+        id,
+        user_id,
+        guild_id,
+        channel_id,
+        key,
+        value,
+        confidence,
+        source,
+        last_confirmed_at,
+        rationale,
+        created_at,
+    ): PendingFactRow,
+) -> PendingFactRecord {
+    PendingFactRecord {
+        id: id.to_string(),
+        user_id,
+        guild_id,
+        channel_id,
+        fact: MemoryFact {
+            key,
+            value,
+            confidence,
+            source,
+            updated_at: created_at,
+            last_confirmed_at,
+        },
+        rationale,
+        created_at,
     }
 }
 
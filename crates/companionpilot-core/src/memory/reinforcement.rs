@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+
+use crate::types::MemoryFact;
+
+use super::text_similarity;
+
+/// Share of the remaining confidence headroom gained on each re-observation.
+const REINFORCEMENT_RATE: f32 = 0.3;
+/// Values at least this similar are treated as the same observation.
+const SAME_VALUE_SIMILARITY: f32 = 0.9;
+
+/// Combines a re-observed fact with the stored one.
+///
+/// When the values agree, the stored confidence moves towards 1.0 and the fact
+/// is marked as confirmed. A different value is a correction and replaces the
+/// stored fact as a fresh, unconfirmed observation.
+pub fn reinforce_fact(
+    existing: &MemoryFact,
+    observed: MemoryFact,
+    now: DateTime<Utc>,
+) -> MemoryFact {
+    if text_similarity(&existing.value, &observed.value) < SAME_VALUE_SIMILARITY {
+        return MemoryFact {
+            last_confirmed_at: None,
+            updated_at: now,
+            ..observed
+        };
+    }
+
+    let base = existing.confidence.max(observed.confidence).clamp(0.0, 1.0);
+    MemoryFact {
+        confidence: (base + (1.0 - base) * REINFORCEMENT_RATE).min(1.0),
+        last_confirmed_at: Some(now),
+        updated_at: now,
+        ..observed
+    }
+}
+
+/// Confidence after exponential decay since the fact was last confirmed (or
+/// written, if it never was).
+pub fn effective_confidence(fact: &MemoryFact, now: DateTime<Utc>, half_life_days: f32) -> f32 {
+    if half_life_days <= 0.0 {
+        return fact.confidence;
+    }
+
+    let anchor = fact.last_confirmed_at.unwrap_or(fact.updated_at);
+    let age_days = (now - anchor).num_seconds().max(0) as f32 / 86_400.0;
+    fact.confidence * 0.5_f32.powf(age_days / half_life_days)
+}
+
+/// Orders facts so well-established ones come first in prompt context.
+pub fn rank_facts_by_confidence(facts: &mut [MemoryFact], now: DateTime<Utc>, half_life_days: f32) {
+    facts.sort_by(|left, right| {
+        effective_confidence(right, now, half_life_days).total_cmp(&effective_confidence(
+            left,
+            now,
+            half_life_days,
+        ))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::types::MemoryFact;
+
+    use super::{effective_confidence, rank_facts_by_confidence, reinforce_fact};
+
+    fn fact(key: &str, value: &str, confidence: f32) -> MemoryFact {
+        MemoryFact {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            confidence,
+            source: "user_message".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        }
+    }
+
+    #[test]
+    fn reobserved_value_increases_confidence() {
+        let now = Utc::now();
+        let reinforced = reinforce_fact(&fact("name", "Petr", 0.8), fact("name", "petr", 0.7), now);
+        assert!(reinforced.confidence > 0.8);
+        assert_eq!(reinforced.last_confirmed_at, Some(now));
+    }
+
+    #[test]
+    fn changed_value_replaces_without_confirmation() {
+        let now = Utc::now();
+        let corrected =
+            reinforce_fact(&fact("name", "Petrr", 0.9), fact("name", "Karel", 0.6), now);
+        assert_eq!(corrected.value, "Karel");
+        assert_eq!(corrected.confidence, 0.6);
+        assert!(corrected.last_confirmed_at.is_none());
+    }
+
+    #[test]
+    fn stale_facts_decay_below_confirmed_ones() {
+        let now = Utc::now();
+        let mut stale = fact("old_hobby", "chess", 0.9);
+        stale.updated_at = now - Duration::days(180);
+        let mut confirmed = fact("hobby", "climbing", 0.8);
+        confirmed.last_confirmed_at = Some(now);
+
+        assert!(effective_confidence(&stale, now, 90.0) < 0.3);
+        let mut facts = vec![stale, confirmed];
+        rank_facts_by_confidence(&mut facts, now, 90.0);
+        assert_eq!(facts[0].key, "hobby");
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::types::{
+    ChatMessageRecord, ChatSession, MemoryFact, PlannerDecisionRecord, ToolCallRecord,
+};
+
+use super::MemoryStore;
+
+/// Version 2 added chat sessions; version 1 snapshots still restore, with
+/// their messages outside any session.
+const SNAPSHOT_FORMAT_VERSION: u32 = 2;
+const SNAPSHOT_LIST_LIMIT: usize = 1_000_000;
+
+/// Store contents a snapshot does not carry. Restoring leaves them as they
+/// are in the target store, so a snapshot is not a full backup.
+pub const SNAPSHOT_EXCLUDED_DATA: &[&str] = &[
+    "pending facts",
+    "system notices",
+    "background tasks",
+    "message feedback",
+    "user API keys",
+    "notification preferences",
+    "guild settings",
+    "custom commands",
+    "calendar feed salts",
+    "onboarding state",
+    "prompt rollouts",
+];
+
+/// Each user's facts, chat sessions and messages, tool calls and planner
+/// decisions; see [`SNAPSHOT_EXCLUDED_DATA`] for what is left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemorySnapshot {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub users: Vec<UserSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSnapshot {
+    pub user_id: String,
+    #[serde(default)]
+    pub facts: Vec<MemoryFact>,
+    #[serde(default)]
+    pub sessions: Vec<ChatSession>,
+    #[serde(default)]
+    pub messages: Vec<ChatMessageRecord>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallRecord>,
+    #[serde(default)]
+    pub planner_decisions: Vec<PlannerDecisionRecord>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestoreSummary {
+    pub users: usize,
+    pub facts: usize,
+    pub sessions: usize,
+    pub messages: usize,
+    pub tool_calls: usize,
+    pub planner_decisions: usize,
+}
+
+impl MemorySnapshot {
+    /// Reads every user known to the store together with their records,
+    /// except [`SNAPSHOT_EXCLUDED_DATA`].
+    pub async fn capture(store: &dyn MemoryStore) -> anyhow::Result<Self> {
+        warn!(
+            excluded = %SNAPSHOT_EXCLUDED_DATA.join(", "),
+            "memory snapshot leaves out data that is not per-user history"
+        );
+        let mut users = Vec::new();
+        for summary in store.list_users(SNAPSHOT_LIST_LIMIT).await? {
+            let user_id = summary.user_id;
+            users.push(UserSnapshot {
+                facts: store.list_facts(&user_id, SNAPSHOT_LIST_LIMIT).await?,
+                sessions: store
+                    .list_chat_sessions(&user_id, SNAPSHOT_LIST_LIMIT)
+                    .await?,
+                messages: store
+                    .list_chat_messages(&user_id, SNAPSHOT_LIST_LIMIT)
+                    .await?,
+                tool_calls: store.list_tool_calls(&user_id, SNAPSHOT_LIST_LIMIT).await?,
+                planner_decisions: store
+                    .list_planner_decisions(&user_id, SNAPSHOT_LIST_LIMIT)
+                    .await?,
+                user_id,
+            });
+        }
+
+        Ok(Self {
+            version: SNAPSHOT_FORMAT_VERSION,
+            created_at: Utc::now(),
+            users,
+        })
+    }
+
+    /// Writes the snapshot into `store`. Existing data of every user contained in
+    /// the snapshot is cleared first so a restore never duplicates history.
+    /// Sessions get new ids from `store`, and messages follow them.
+    /// [`SNAPSHOT_EXCLUDED_DATA`] in `store` is neither cleared nor restored.
+    pub async fn restore(&self, store: &dyn MemoryStore) -> anyhow::Result<RestoreSummary> {
+        if !(1..=SNAPSHOT_FORMAT_VERSION).contains(&self.version) {
+            anyhow::bail!(
+                "unsupported snapshot version {} (expected {SNAPSHOT_FORMAT_VERSION})",
+                self.version
+            );
+        }
+
+        warn!(
+            excluded = %SNAPSHOT_EXCLUDED_DATA.join(", "),
+            "memory snapshot restore keeps the target's data that snapshots leave out"
+        );
+        let mut summary = RestoreSummary::default();
+        for user in &self.users {
+            store.clear_facts(&user.user_id).await?;
+            store.clear_chat_messages(&user.user_id).await?;
+            store.clear_tool_calls(&user.user_id).await?;
+            store.clear_planner_decisions(&user.user_id).await?;
+            // Clearing only soft-deletes; the old rows must not come back
+            // next to the restored ones through undo.
+            store.purge_user_deleted(&user.user_id).await?;
+            store.clear_chat_sessions(&user.user_id).await?;
+
+            for fact in &user.facts {
+                store.upsert_fact(&user.user_id, fact.clone()).await?;
+            }
+            let mut session_ids = HashMap::new();
+            // Oldest first, so sessions active in the same instant keep their order.
+            for session in user.sessions.iter().rev() {
+                let restored = store
+                    .start_chat_session(&user.user_id, session.started_at)
+                    .await?;
+                if let Some(title) = &session.title {
+                    store
+                        .set_chat_session_title(&user.user_id, &restored.id, title)
+                        .await?;
+                }
+                store
+                    .touch_chat_session(&user.user_id, &restored.id, session.last_active_at)
+                    .await?;
+                session_ids.insert(session.id.as_str(), restored.id);
+            }
+            for message in &user.messages {
+                let mut message = message.clone();
+                message.session_id = message
+                    .session_id
+                    .and_then(|id| session_ids.get(id.as_str()).cloned());
+                store.record_chat_message(message).await?;
+            }
+            for call in &user.tool_calls {
+                store.record_tool_call(call.clone()).await?;
+            }
+            for decision in &user.planner_decisions {
+                store.record_planner_decision(decision.clone()).await?;
+            }
+
+            summary.users += 1;
+            summary.facts += user.facts.len();
+            summary.sessions += user.sessions.len();
+            summary.messages += user.messages.len();
+            summary.tool_calls += user.tool_calls.len();
+            summary.planner_decisions += user.planner_decisions.len();
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::{
+        memory::{InMemoryMemoryStore, MemoryStore},
+        types::{ChatMessageRecord, ChatRole, MemoryFact, Modality},
+    };
+
+    use super::MemorySnapshot;
+
+    #[tokio::test]
+    async fn snapshot_round_trips_between_stores() {
+        let source = InMemoryMemoryStore::default();
+        source
+            .upsert_fact(
+                "u1",
+                MemoryFact {
+                    key: "name".to_owned(),
+                    value: "Petr".to_owned(),
+                    confidence: 0.9,
+                    source: "user_message".to_owned(),
+                    updated_at: Utc::now(),
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("fact upsert should succeed");
+        let session = source
+            .start_chat_session("u1", Utc::now())
+            .await
+            .expect("session start should succeed");
+        source
+            .set_chat_session_title("u1", &session.id, "Greetings")
+            .await
+            .expect("session title should succeed");
+        source
+            .record_chat_message(ChatMessageRecord {
+                id: String::new(),
+                user_id: "u1".to_owned(),
+                guild_id: "g1".to_owned(),
+                channel_id: "c1".to_owned(),
+                role: ChatRole::User,
+                content: "hello".to_owned(),
+                timestamp: Utc::now(),
+                modality: Modality::Text,
+                session_id: Some(session.id.clone()),
+                pinned: false,
+                source_message_id: None,
+            })
+            .await
+            .expect("message record should succeed");
+
+        let snapshot = MemorySnapshot::capture(&source)
+            .await
+            .expect("capture should succeed");
+        let raw = serde_json::to_string(&snapshot).expect("snapshot should serialize");
+        let parsed: MemorySnapshot = serde_json::from_str(&raw).expect("snapshot should parse");
+
+        let target = InMemoryMemoryStore::default();
+        // u1 already has a newer, empty session in the target; it is
+        // replaced. Session ids come from the target store, so they do not
+        // line up with the source's.
+        target
+            .start_chat_session("u1", Utc::now() + chrono::Duration::hours(1))
+            .await
+            .expect("session start should succeed");
+        let summary = parsed
+            .restore(&target)
+            .await
+            .expect("restore should succeed");
+        assert_eq!(summary.users, 1);
+        assert_eq!(summary.facts, 1);
+        assert_eq!(summary.sessions, 1);
+        assert_eq!(summary.messages, 1);
+
+        let restored = target
+            .list_facts("u1", 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(restored[0].value, "Petr");
+
+        let restored_session = target
+            .current_chat_session("u1")
+            .await
+            .expect("session lookup should succeed")
+            .expect("session should be restored");
+        assert_ne!(restored_session.id, session.id);
+        let sessions = target
+            .list_chat_sessions("u1", 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(restored_session.title.as_deref(), Some("Greetings"));
+        let session_messages = target
+            .list_session_messages("u1", &restored_session.id, 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(session_messages.len(), 1);
+        assert_eq!(session_messages[0].content, "hello");
+    }
+
+    #[tokio::test]
+    async fn restored_users_cannot_undo_back_to_their_old_records() {
+        let target = InMemoryMemoryStore::default();
+        target
+            .upsert_fact(
+                "u1",
+                MemoryFact {
+                    key: "city".to_owned(),
+                    value: "Brno".to_owned(),
+                    confidence: 0.9,
+                    source: "user_message".to_owned(),
+                    updated_at: Utc::now(),
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("fact upsert should succeed");
+        target
+            .record_chat_message(ChatMessageRecord {
+                id: String::new(),
+                user_id: "u1".to_owned(),
+                guild_id: "g1".to_owned(),
+                channel_id: "c1".to_owned(),
+                role: ChatRole::User,
+                content: "before the restore".to_owned(),
+                timestamp: Utc::now(),
+                modality: Modality::Text,
+                session_id: None,
+                pinned: false,
+                source_message_id: None,
+            })
+            .await
+            .expect("message record should succeed");
+
+        let snapshot = MemorySnapshot::capture(&target)
+            .await
+            .expect("capture should succeed");
+        snapshot
+            .restore(&target)
+            .await
+            .expect("restore should succeed");
+
+        let epoch = chrono::DateTime::<Utc>::UNIX_EPOCH;
+        assert_eq!(
+            target
+                .restore_chat_messages("u1", epoch)
+                .await
+                .expect("undo should succeed"),
+            0
+        );
+        assert_eq!(
+            target
+                .restore_facts("u1", None, epoch)
+                .await
+                .expect("undo should succeed"),
+            0
+        );
+        let messages = target
+            .list_chat_messages("u1", 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(messages.len(), 1);
+    }
+}
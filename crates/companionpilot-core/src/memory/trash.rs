@@ -0,0 +1,132 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use super::MemoryStore;
+
+/// How long cleared messages and deleted facts can still be restored.
+pub const UNDO_WINDOW_HOURS: i64 = 24;
+
+const HARD_DELETE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Oldest deletion time that is still inside the undo window.
+pub fn undo_cutoff(now: DateTime<Utc>) -> DateTime<Utc> {
+    now - chrono::Duration::hours(UNDO_WINDOW_HOURS)
+}
+
+/// Periodically removes soft-deleted rows whose undo window has passed.
+pub fn start_hard_delete_job(store: Arc<dyn MemoryStore>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(HARD_DELETE_INTERVAL).await;
+            match store.purge_deleted(undo_cutoff(Utc::now())).await {
+                Ok(0) => {}
+                Ok(purged) => info!(purged, "hard-deleted expired soft-deleted records"),
+                Err(error) => warn!(?error, "hard delete of soft-deleted records failed"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::undo_cutoff;
+    use crate::{
+        memory::{InMemoryMemoryStore, MemoryStore},
+        types::{ChatMessageRecord, ChatRole, MemoryFact, Modality},
+    };
+
+    fn fact(key: &str, value: &str) -> MemoryFact {
+        MemoryFact {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            confidence: 0.9,
+            source: "test".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn cleared_records_can_be_restored_until_purged() {
+        let store = InMemoryMemoryStore::default();
+        store
+            .upsert_fact("u1", fact("city", "Prague"))
+            .await
+            .expect("upsert");
+        store
+            .upsert_fact("u1", fact("pet", "cat"))
+            .await
+            .expect("upsert");
+        store
+            .record_chat_message(ChatMessageRecord {
+                id: String::new(),
+                user_id: "u1".to_owned(),
+                guild_id: "g1".to_owned(),
+                channel_id: "c1".to_owned(),
+                role: ChatRole::User,
+                content: "hi".to_owned(),
+                timestamp: Utc::now(),
+                modality: Modality::Text,
+                session_id: None,
+                pinned: false,
+                source_message_id: None,
+            })
+            .await
+            .expect("record message");
+
+        assert!(store.delete_fact("u1", "pet").await.expect("store call"));
+        assert_eq!(
+            store.clear_chat_messages("u1").await.expect("store call"),
+            1
+        );
+        assert_eq!(
+            store.list_facts("u1", 10).await.expect("store call").len(),
+            1
+        );
+        assert!(
+            store
+                .list_chat_messages("u1", 10)
+                .await
+                .expect("store call")
+                .is_empty()
+        );
+
+        let since = undo_cutoff(Utc::now());
+        assert_eq!(
+            store
+                .restore_facts("u1", Some("pet"), since)
+                .await
+                .expect("store call"),
+            1
+        );
+        assert_eq!(
+            store
+                .restore_chat_messages("u1", since)
+                .await
+                .expect("store call"),
+            1
+        );
+        assert_eq!(
+            store.list_facts("u1", 10).await.expect("store call").len(),
+            2
+        );
+
+        assert_eq!(store.clear_facts("u1").await.expect("store call"), 2);
+        let purged = store
+            .purge_deleted(Utc::now() + Duration::seconds(1))
+            .await
+            .expect("purge");
+        assert_eq!(purged, 2);
+        assert_eq!(
+            store
+                .restore_facts("u1", None, since)
+                .await
+                .expect("store call"),
+            0
+        );
+    }
+}
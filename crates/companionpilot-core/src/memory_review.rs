@@ -0,0 +1,268 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    memory::{MemoryStore, reinforce_fact},
+    types::MemoryFact,
+};
+
+/// Prefix of the confirm/deny button ids on memory review DMs.
+pub const REVIEW_ID_PREFIX: &str = "memory_review:";
+
+/// Users whose stale facts are read per store query.
+const REVIEW_USERS_PER_PAGE: usize = 100;
+
+/// Facts listed when looking up the one a button refers to.
+const REVIEW_LOOKUP_LIMIT: usize = 1_000;
+
+/// Discord allows five rows of buttons per message, one row per fact.
+pub const MAX_REVIEW_FACTS: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct MemoryReviewConfig {
+    /// How often the scheduler looks for facts to review.
+    pub interval: Duration,
+    /// Facts not written or confirmed for this long are up for review.
+    pub stale_after: Duration,
+    /// Facts below this confidence are up for review regardless of age.
+    pub low_confidence: f32,
+    /// Minimum time between two review DMs to the same user.
+    pub cooldown: Duration,
+    pub max_facts: usize,
+}
+
+impl Default for MemoryReviewConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(6 * 60 * 60),
+            stale_after: Duration::from_secs(60 * 24 * 60 * 60),
+            low_confidence: 0.5,
+            cooldown: Duration::from_secs(7 * 24 * 60 * 60),
+            max_facts: 3,
+        }
+    }
+}
+
+/// Facts to ask one user about.
+#[derive(Debug, Clone)]
+pub struct ReviewBatch {
+    pub user_id: String,
+    pub facts: Vec<MemoryFact>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewVerdict {
+    Confirm,
+    Deny,
+}
+
+impl ReviewVerdict {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Confirm => "confirm",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+/// Users due for a review with their stalest facts. Only Discord users (numeric
+/// ids) can be messaged; users reviewed within the cooldown are skipped.
+pub async fn collect_review_batches(
+    store: &dyn MemoryStore,
+    config: &MemoryReviewConfig,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<ReviewBatch>> {
+    let stale_after = chrono::Duration::from_std(config.stale_after)?;
+    let cooldown = chrono::Duration::from_std(config.cooldown)?;
+    let max_facts = config.max_facts.clamp(1, MAX_REVIEW_FACTS);
+    let mut batches = Vec::new();
+    let mut after_user_id = None;
+    loop {
+        let stale = store
+            .list_stale_facts(
+                now - stale_after,
+                config.low_confidence,
+                now - cooldown,
+                after_user_id.as_deref(),
+                REVIEW_USERS_PER_PAGE,
+                max_facts,
+            )
+            .await?;
+        let page = group_review_batches(stale, max_facts);
+        let full_page = page.len() >= REVIEW_USERS_PER_PAGE;
+        after_user_id = page.last().map(|batch| batch.user_id.clone());
+        batches.extend(
+            page.into_iter()
+                .filter(|batch| batch.user_id.parse::<u64>().is_ok()),
+        );
+        if !full_page {
+            return Ok(batches);
+        }
+    }
+}
+
+/// Groups `(user id, fact)` pairs per user, keeping at most `max_facts` per
+/// user in the given order.
+pub fn group_review_batches(
+    stale: Vec<(String, MemoryFact)>,
+    max_facts: usize,
+) -> Vec<ReviewBatch> {
+    let mut batches: Vec<ReviewBatch> = Vec::new();
+    for (user_id, fact) in stale {
+        match batches.iter_mut().find(|batch| batch.user_id == user_id) {
+            Some(batch) if batch.facts.len() < max_facts => batch.facts.push(fact),
+            Some(_) => {}
+            None => batches.push(ReviewBatch {
+                user_id,
+                facts: vec![fact],
+            }),
+        }
+    }
+    batches
+}
+
+pub fn review_message(facts: &[MemoryFact]) -> String {
+    let lines = facts
+        .iter()
+        .map(|fact| format!("- **{}**: {}", fact.key.replace('_', " "), fact.value))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Quick memory check! Is this still right?\n{lines}\n\nTap ✅ to keep a fact or ❌ to make me forget it. If something changed, just tell me the new value."
+    )
+}
+
+pub fn review_button_id(verdict: ReviewVerdict, key: &str) -> String {
+    format!("{REVIEW_ID_PREFIX}{}:{key}", verdict.as_str())
+}
+
+pub fn parse_review_button_id(custom_id: &str) -> Option<(ReviewVerdict, &str)> {
+    let rest = custom_id.strip_prefix(REVIEW_ID_PREFIX)?;
+    let (verdict, key) = rest.split_once(':')?;
+    let verdict = match verdict {
+        "confirm" => ReviewVerdict::Confirm,
+        "deny" => ReviewVerdict::Deny,
+        _ => return None,
+    };
+    (!key.is_empty()).then_some((verdict, key))
+}
+
+/// Confirming reinforces the fact; denying deletes it. Returns the reviewed
+/// fact, or `None` if it no longer exists.
+pub async fn apply_review_verdict(
+    store: &dyn MemoryStore,
+    user_id: &str,
+    key: &str,
+    verdict: ReviewVerdict,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Option<MemoryFact>> {
+    let Some(fact) = store
+        .list_facts(user_id, REVIEW_LOOKUP_LIMIT)
+        .await?
+        .into_iter()
+        .find(|fact| fact.key == key)
+    else {
+        return Ok(None);
+    };
+    match verdict {
+        ReviewVerdict::Confirm => {
+            store
+                .upsert_fact(user_id, reinforce_fact(&fact, fact.clone(), now))
+                .await?;
+        }
+        ReviewVerdict::Deny => {
+            store.delete_fact(user_id, key).await?;
+        }
+    }
+    Ok(Some(fact))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{
+        MemoryReviewConfig, ReviewVerdict, collect_review_batches, group_review_batches,
+        parse_review_button_id, review_button_id,
+    };
+    use crate::{
+        memory::{InMemoryMemoryStore, MemoryStore},
+        types::MemoryFact,
+    };
+
+    #[test]
+    fn groups_stale_facts_per_user_and_round_trips_button_ids() {
+        let fact = |key: &str| MemoryFact {
+            key: key.to_owned(),
+            value: "x".to_owned(),
+            confidence: 0.3,
+            source: "planner".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        };
+        let stale = vec![
+            ("1".to_owned(), fact("city")),
+            ("2".to_owned(), fact("pet")),
+            ("1".to_owned(), fact("job")),
+            ("1".to_owned(), fact("team")),
+        ];
+
+        let batches = group_review_batches(stale, 2);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].user_id, "1");
+        let keys = batches[0]
+            .facts
+            .iter()
+            .map(|fact| fact.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["city", "job"]);
+
+        let id = review_button_id(ReviewVerdict::Deny, "favourite:food");
+        assert_eq!(
+            parse_review_button_id(&id),
+            Some((ReviewVerdict::Deny, "favourite:food"))
+        );
+        assert_eq!(parse_review_button_id("companion_setup:done"), None);
+    }
+
+    #[tokio::test]
+    async fn reviews_every_due_user_across_pages() {
+        let store = InMemoryMemoryStore::default();
+        let now = Utc::now();
+        let old = now - chrono::Duration::days(365);
+        for user in 0..250 {
+            for key in ["city", "pet", "job"] {
+                store
+                    .upsert_fact(
+                        &(1_000 + user).to_string(),
+                        MemoryFact {
+                            key: key.to_owned(),
+                            value: "x".to_owned(),
+                            confidence: 0.9,
+                            source: "planner".to_owned(),
+                            updated_at: old,
+                            last_confirmed_at: None,
+                        },
+                    )
+                    .await
+                    .expect("fact stored");
+            }
+        }
+        store
+            .record_memory_review("1000", now)
+            .await
+            .expect("review recorded");
+        let config = MemoryReviewConfig {
+            max_facts: 2,
+            ..MemoryReviewConfig::default()
+        };
+
+        let batches = collect_review_batches(&store, &config, now)
+            .await
+            .expect("batches");
+        assert_eq!(batches.len(), 249);
+        assert!(batches.iter().all(|batch| batch.facts.len() == 2));
+        assert!(batches.iter().all(|batch| batch.user_id != "1000"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{
+    memory::MemoryStore,
+    types::{ChatSession, ContextLimits, MemoryFact},
+};
+
+/// Facts listed in a summary, keeping it within one Discord message.
+const MAX_SUMMARY_FACTS: usize = 25;
+/// Most recent sessions listed under "Recent conversations".
+const MAX_SUMMARY_SESSIONS: usize = 5;
+/// Facts searched for the one a "forget my ..." request names.
+const FORGET_LOOKUP_LIMIT: usize = 1_000;
+
+const SUMMARY_PHRASES: &[&str] = &[
+    "/whatdoyouknow",
+    "what do you know about me",
+    "what do you remember about me",
+    "what have you remembered about me",
+    "what do you know of me",
+];
+
+/// A message asking about the user's stored memory, answered straight from
+/// the store instead of by the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryRequest {
+    /// "What do you know about me?" or `/whatdoyouknow`.
+    Summary,
+    /// "Forget my favorite game"; holds the topic as written.
+    Forget(String),
+}
+
+impl MemoryRequest {
+    pub fn detect(content: &str) -> Option<Self> {
+        let normalized = content
+            .trim()
+            .trim_end_matches(['?', '!', '.'])
+            .trim()
+            .to_lowercase();
+        if SUMMARY_PHRASES.contains(&normalized.as_str()) {
+            return Some(Self::Summary);
+        }
+        let request = normalized
+            .strip_prefix("please ")
+            .unwrap_or(&normalized)
+            .trim();
+        let topic = request
+            .strip_prefix("forget my ")
+            .or_else(|| request.strip_prefix("forget about my "))?
+            .trim();
+        (!topic.is_empty()).then(|| Self::Forget(topic.to_owned()))
+    }
+}
+
+/// What the store holds about one user.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySummary {
+    pub facts: Vec<MemoryFact>,
+    pub sessions: Vec<ChatSession>,
+    pub conversation_summary: Option<String>,
+}
+
+pub async fn load_memory_summary(
+    store: &dyn MemoryStore,
+    user_id: &str,
+    guild_id: &str,
+    channel_id: &str,
+) -> anyhow::Result<MemorySummary> {
+    let mut facts = store.list_facts(user_id, MAX_SUMMARY_FACTS + 1).await?;
+    facts.sort_by(|left, right| left.key.cmp(&right.key));
+    Ok(MemorySummary {
+        facts,
+        sessions: store
+            .list_chat_sessions(user_id, MAX_SUMMARY_SESSIONS)
+            .await?,
+        conversation_summary: store
+            .load_context(user_id, guild_id, channel_id, ContextLimits::default())
+            .await?
+            .summary,
+    })
+}
+
+impl MemorySummary {
+    /// A friendly overview grouped into facts, goals and recent conversations,
+    /// ending with how to correct or delete entries.
+    pub fn render(&self) -> String {
+        if self.facts.is_empty() && self.sessions.is_empty() {
+            return "I don't have anything saved about you yet. Tell me about yourself and I'll remember the important bits.".to_owned();
+        }
+
+        let (goals, facts): (Vec<_>, Vec<_>) = self
+            .facts
+            .iter()
+            .take(MAX_SUMMARY_FACTS)
+            .partition(|fact| fact.key.contains("goal"));
+        let mut sections = vec!["Here's what I know about you:".to_owned()];
+        if !facts.is_empty() {
+            sections.push(format!("**About you**\n{}", fact_lines(&facts)));
+        }
+        if !goals.is_empty() {
+            sections.push(format!("**Your goals**\n{}", fact_lines(&goals)));
+        }
+        if self.facts.len() > MAX_SUMMARY_FACTS {
+            sections.push("…and a few more things.".to_owned());
+        }
+
+        let mut conversations = self
+            .sessions
+            .iter()
+            .filter_map(|session| {
+                let title = session.title.as_deref()?.trim();
+                (!title.is_empty())
+                    .then(|| format!("- {}: {title}", session.started_at.format("%b %-d")))
+            })
+            .collect::<Vec<_>>();
+        if let Some(summary) = self
+            .conversation_summary
+            .as_deref()
+            .map(str::trim)
+            .filter(|summary| !summary.is_empty())
+        {
+            conversations.push(format!("- Lately: {summary}"));
+        }
+        if !conversations.is_empty() {
+            sections.push(format!(
+                "**Recent conversations**\n{}",
+                conversations.join("\n")
+            ));
+        }
+
+        let example = facts
+            .first()
+            .or(goals.first())
+            .map_or("favorite game".to_owned(), |fact| {
+                fact.key.replace('_', " ")
+            });
+        sections.push(format!(
+            "Something wrong? Just tell me the right value and I'll update it. To make me forget something, say \"forget my {example}\"."
+        ));
+        sections.join("\n\n")
+    }
+}
+
+fn fact_lines(facts: &[&MemoryFact]) -> String {
+    facts
+        .iter()
+        .map(|fact| format!("- {}: {}", fact.key.replace('_', " "), fact.value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Deletes the fact a "forget my ..." topic names, matching its key with
+/// spaces for underscores. Returns the deleted fact, if there was one.
+pub async fn forget_fact(
+    store: &dyn MemoryStore,
+    user_id: &str,
+    topic: &str,
+) -> anyhow::Result<Option<MemoryFact>> {
+    let wanted = topic.trim().to_lowercase().replace([' ', '-'], "_");
+    let Some(fact) = store
+        .list_facts(user_id, FORGET_LOOKUP_LIMIT)
+        .await?
+        .into_iter()
+        .find(|fact| fact.key == wanted)
+    else {
+        return Ok(None);
+    };
+    store.delete_fact(user_id, &fact.key).await?;
+    Ok(Some(fact))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn detects_memory_requests_and_renders_grouped_summary() {
+        assert_eq!(
+            MemoryRequest::detect("What do you know about me?"),
+            Some(MemoryRequest::Summary)
+        );
+        assert_eq!(
+            MemoryRequest::detect("please forget my favorite game."),
+            Some(MemoryRequest::Forget("favorite game".to_owned()))
+        );
+        assert_eq!(MemoryRequest::detect("what do you know about rust?"), None);
+
+        let fact = |key: &str, value: &str| MemoryFact {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            confidence: 0.9,
+            source: "planner".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        };
+        let summary = MemorySummary {
+            facts: vec![
+                fact("favorite_game", "Hades"),
+                fact("fitness_goal", "run 10k"),
+            ],
+            sessions: Vec::new(),
+            conversation_summary: Some("Training plans".to_owned()),
+        };
+        let text = summary.render();
+        assert!(text.contains("**About you**\n- favorite game: Hades"));
+        assert!(text.contains("**Your goals**\n- fitness goal: run 10k"));
+        assert!(text.contains("- Lately: Training plans"));
+        assert!(text.contains("\"forget my favorite game\""));
+    }
+}
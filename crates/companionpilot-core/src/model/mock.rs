@@ -9,75 +9,238 @@ pub struct MockModelProvider;
 #[async_trait]
 impl ModelProvider for MockModelProvider {
     async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+        if let Some(plan) = mock_unified_plan(&request) {
+            return Ok(plan);
+        }
+
+        Ok(format!(
+            "CompanionPilot mock reply.\n\nSystem: {}\n\nUser: {}",
+            request.system_prompt, request.user_prompt
+        ))
+    }
+}
+
+/// A reproducible stand-in for a real model in demos and integration tests.
+/// Plans like [`MockModelProvider`], then writes replies from templates that
+/// mention a known user fact and echo tool outputs. Template choices come from
+/// `seed` and the prompt, so the same seed and conversation give the same
+/// replies on every run.
+#[derive(Debug, Clone, Copy)]
+pub struct SeededMockModelProvider {
+    seed: u64,
+}
+
+const OPENERS: &[&str] = &[
+    "Sure thing!",
+    "Got it.",
+    "Happy to help.",
+    "Alright, here's what I have.",
+];
+const FACT_TEMPLATES: &[&str] = &[
+    "I remember your {key} is {value}.",
+    "Noting that your {key} is {value}.",
+    "(Your {key}: {value}.)",
+];
+const ECHO_TEMPLATES: &[&str] = &["You asked: \"{request}\"", "About \"{request}\":"];
+
+impl SeededMockModelProvider {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn reply(&self, request: &ModelRequest) -> String {
+        let mut rng = SplitMix64::new(
+            self.seed ^ fnv1a(&request.system_prompt) ^ fnv1a(&request.user_prompt).rotate_left(17),
+        );
+        let (user_request, tool_outputs) = split_tool_prompt(&request.user_prompt);
+        let mut lines = vec![rng.pick(OPENERS).to_owned()];
+        lines.push(
+            rng.pick(ECHO_TEMPLATES)
+                .replace("{request}", user_request.trim()),
+        );
+        for (tool_name, output) in tool_outputs {
+            lines.push(format!("From {tool_name}: {output}"));
+        }
+        let facts = known_facts(&request.system_prompt);
+        if !facts.is_empty() {
+            let (key, value) = &facts[rng.below(facts.len())];
+            lines.push(
+                rng.pick(FACT_TEMPLATES)
+                    .replace("{key}", &key.replace('_', " "))
+                    .replace("{value}", value),
+            );
+        }
+        lines.join("\n")
+    }
+}
+
+#[async_trait]
+impl ModelProvider for SeededMockModelProvider {
+    async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+        if let Some(plan) = mock_unified_plan(&request) {
+            return Ok(plan);
+        }
+        let reply = self.reply(&request);
         if request
             .system_prompt
-            .contains("You are the unified planner for CompanionPilot.")
+            .contains("You are the tool follow-up planner for CompanionPilot.")
         {
-            let memory = if let Some(name) = extract_name(&request.user_prompt) {
-                json!({
-                    "store": true,
-                    "key": "name",
-                    "value": name,
-                    "confidence": 0.96
-                })
-            } else if let Some(game) = extract_game(&request.user_prompt) {
-                json!({
-                    "store": true,
-                    "key": "favorite_game",
-                    "value": game,
-                    "confidence": 0.84
-                })
-            } else {
-                json!({
-                    "store": false,
-                    "key": "",
-                    "value": "",
-                    "confidence": 0.0
-                })
-            };
-
-            let mut tool_calls = Vec::new();
-            if let Some(query) = extract_search_query(&request.user_prompt) {
-                tool_calls.push(json!({
-                    "tool_name": "web_search",
-                    "args": {
-                        "query": query,
-                        "max_results": 5
-                    }
-                }));
-            }
-            if extract_join_voice(&request.user_prompt) {
-                tool_calls.push(json!({
-                    "tool_name": "discord_voice_join",
-                    "args": {}
-                }));
-            }
-            if extract_listen_voice_turn(&request.user_prompt) {
-                tool_calls.push(json!({
-                    "tool_name": "discord_voice_listen_turn",
-                    "args": {}
-                }));
-            }
-            if extract_leave_voice(&request.user_prompt) {
-                tool_calls.push(json!({
-                    "tool_name": "discord_voice_leave",
-                    "args": {}
-                }));
-            }
-
             return Ok(json!({
-                "tool_calls": tool_calls,
-                "memory": memory,
-                "rationale": "mock_unified_planner"
+                "action": "final",
+                "final_answer": reply,
+                "tool_calls": [],
+                "rationale": "mock_tool_followup"
             })
             .to_string());
         }
+        Ok(reply)
+    }
+}
 
-        Ok(format!(
-            "CompanionPilot mock reply.\n\nSystem: {}\n\nUser: {}",
-            request.system_prompt, request.user_prompt
-        ))
+/// SplitMix64: tiny, seedable and identical on every platform.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
     }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+        options[self.below(options.len())]
+    }
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across Rust releases.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// `key = value` pairs from the prompt's "Known user facts" line.
+fn known_facts(system_prompt: &str) -> Vec<(String, String)> {
+    system_prompt
+        .lines()
+        .find_map(|line| line.strip_prefix("Known user facts: "))
+        .map(|facts| {
+            facts
+                .split("; ")
+                .filter_map(|fact| fact.split_once('='))
+                .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Splits a synthesis prompt into the user's request and each tool's name and
+/// first output line. Prompts without tool outputs are all request.
+fn split_tool_prompt(user_prompt: &str) -> (&str, Vec<(&str, &str)>) {
+    let Some(rest) = user_prompt.strip_prefix("User request:\n") else {
+        return (user_prompt, Vec::new());
+    };
+    let Some((request, outputs)) = rest
+        .split_once("\n\nTool outputs:\n")
+        .or_else(|| rest.split_once("\n\nTool outputs so far:\n"))
+    else {
+        return (rest, Vec::new());
+    };
+    let mut echoed = Vec::new();
+    let mut lines = outputs.lines();
+    while let Some(line) = lines.next() {
+        let Some((_, tool_name)) = line.split_once(". Tool: ") else {
+            continue;
+        };
+        let output = lines
+            .by_ref()
+            .skip_while(|line| *line != "Output:" && *line != "Error:")
+            .nth(1)
+            .unwrap_or_default();
+        echoed.push((tool_name, output));
+    }
+    (request, echoed)
+}
+
+/// The canned unified plan, or `None` when `request` is not a planner call.
+fn mock_unified_plan(request: &ModelRequest) -> Option<String> {
+    if !request
+        .system_prompt
+        .contains("You are the unified planner for CompanionPilot.")
+    {
+        return None;
+    }
+
+    let memory = if let Some(name) = extract_name(&request.user_prompt) {
+        json!({
+            "store": true,
+            "key": "name",
+            "value": name,
+            "confidence": 0.96
+        })
+    } else if let Some(game) = extract_game(&request.user_prompt) {
+        json!({
+            "store": true,
+            "key": "favorite_game",
+            "value": game,
+            "confidence": 0.84
+        })
+    } else {
+        json!({
+            "store": false,
+            "key": "",
+            "value": "",
+            "confidence": 0.0
+        })
+    };
+
+    let mut tool_calls = Vec::new();
+    if let Some(query) = extract_search_query(&request.user_prompt) {
+        tool_calls.push(json!({
+            "tool_name": "web_search",
+            "args": {
+                "query": query,
+                "max_results": 5
+            }
+        }));
+    }
+    if extract_join_voice(&request.user_prompt) {
+        tool_calls.push(json!({
+            "tool_name": "discord_voice_join",
+            "args": {}
+        }));
+    }
+    if extract_listen_voice_turn(&request.user_prompt) {
+        tool_calls.push(json!({
+            "tool_name": "discord_voice_listen_turn",
+            "args": {}
+        }));
+    }
+    if extract_leave_voice(&request.user_prompt) {
+        tool_calls.push(json!({
+            "tool_name": "discord_voice_leave",
+            "args": {}
+        }));
+    }
+
+    Some(
+        json!({
+            "tool_calls": tool_calls,
+            "memory": memory,
+            "rationale": "mock_unified_planner"
+        })
+        .to_string(),
+    )
 }
 
 fn extract_name(input: &str) -> Option<String> {
@@ -142,3 +305,41 @@ fn extract_leave_voice(input: &str) -> bool {
     let lowered = input.to_lowercase();
     lowered.contains("leave voice") || lowered.contains("disconnect from voice")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(system_prompt: &str, user_prompt: &str) -> ModelRequest {
+        ModelRequest {
+            system_prompt: system_prompt.to_owned(),
+            user_prompt: user_prompt.to_owned(),
+            ..ModelRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn seeded_mock_replies_are_reproducible_and_use_memory_and_tools() {
+        let model = SeededMockModelProvider::new(7);
+        let chat = request("Known user facts: favorite_game = Hades", "hello there");
+        let first = model.complete(chat.clone()).await.expect("reply");
+        assert_eq!(first, model.complete(chat).await.expect("reply"));
+        assert!(first.contains("hello there"));
+        assert!(first.contains("favorite game") && first.contains("Hades"));
+
+        let synthesis = request(
+            "You are CompanionPilot. Use the provided tool outputs",
+            "User request:\nweather?\n\nTool outputs:\n1. Tool: web_search\nArgs: {}\nStatus: success\nOutput:\nSunny, 21 C\nmore detail",
+        );
+        let reply = model.complete(synthesis).await.expect("reply");
+        assert!(reply.contains("From web_search: Sunny, 21 C"));
+
+        let followup = request(
+            "You are the tool follow-up planner for CompanionPilot.",
+            "User request:\nweather?\n\nTool outputs so far:\n",
+        );
+        let plan: serde_json::Value =
+            serde_json::from_str(&model.complete(followup).await.expect("plan")).expect("json");
+        assert_eq!(plan["action"], "final");
+    }
+}
@@ -1,18 +1,179 @@
 mod mock;
 mod openrouter;
 
+use std::fmt;
+
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 
-pub use mock::MockModelProvider;
+pub use mock::{MockModelProvider, SeededMockModelProvider};
 pub use openrouter::OpenRouterProvider;
 
-#[derive(Debug, Clone)]
+/// Per-request generation settings. Unset fields use the provider defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    /// Provider-specific model id replacing the configured model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Stop sequences; empty means no override.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Key billed instead of the provider's own, e.g. the user's.
+    #[serde(skip)]
+    pub api_key: Option<ApiKey>,
+}
+
+/// The API a caller-supplied key belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyProvider {
+    #[serde(rename = "openrouter")]
+    OpenRouter,
+    #[serde(rename = "openai")]
+    OpenAi,
+}
+
+impl KeyProvider {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OpenRouter => "openrouter",
+            Self::OpenAi => "openai",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "openrouter" => Some(Self::OpenRouter),
+            "openai" => Some(Self::OpenAi),
+            _ => None,
+        }
+    }
+}
+
+/// A caller-supplied provider key. Masked in debug output and never
+/// serialized, so it cannot leak through logs or stored parameters.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub provider: KeyProvider,
+    secret: String,
+}
+
+impl ApiKey {
+    pub fn new(provider: KeyProvider, secret: impl Into<String>) -> Self {
+        Self {
+            provider,
+            secret: secret.into(),
+        }
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey({}, ***)", self.provider.as_str())
+    }
+}
+
+impl GenerationParams {
+    /// Fills every unset field from `fallback`.
+    pub fn or(self, fallback: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            model: self.model.or_else(|| fallback.model.clone()),
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            stop: if self.stop.is_empty() {
+                fallback.stop.clone()
+            } else {
+                self.stop
+            },
+            api_key: self.api_key.or_else(|| fallback.api_key.clone()),
+        }
+    }
+}
+
+/// Model used with an OpenAI key when the requested model belongs to another
+/// vendor.
+pub const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+
+/// The model a request for `model` actually runs on. OpenAI keys go straight
+/// to OpenAI, which names models without the vendor prefix and cannot serve
+/// other vendors' models.
+pub fn resolved_model<'a>(model: &'a str, api_key: Option<&ApiKey>) -> &'a str {
+    match api_key {
+        Some(key) if key.provider == KeyProvider::OpenAi => model
+            .strip_prefix("openai/")
+            .or((!model.contains('/')).then_some(model))
+            .unwrap_or(DEFAULT_OPENAI_MODEL),
+        _ => model,
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ModelRequest {
     pub system_prompt: String,
     pub user_prompt: String,
+    pub params: GenerationParams,
+}
+
+/// A completion and the reasoning the provider returned separately from it.
+#[derive(Debug, Clone, Default)]
+pub struct ModelCompletion {
+    pub text: String,
+    pub reasoning: Option<String>,
 }
 
 #[async_trait]
 pub trait ModelProvider: Send + Sync {
     async fn complete(&self, request: ModelRequest) -> anyhow::Result<String>;
+
+    /// Like [`ModelProvider::complete`], also returning the provider's
+    /// separate reasoning text. Providers without one keep the default.
+    async fn complete_with_reasoning(
+        &self,
+        request: ModelRequest,
+    ) -> anyhow::Result<ModelCompletion> {
+        Ok(ModelCompletion {
+            text: self.complete(request).await?,
+            reasoning: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GenerationParams;
+
+    #[test]
+    fn request_params_win_over_fallbacks() {
+        let global = GenerationParams {
+            temperature: Some(0.7),
+            max_tokens: Some(800),
+            stop: vec!["END".to_owned()],
+            ..GenerationParams::default()
+        };
+        let persona = GenerationParams {
+            temperature: Some(1.1),
+            top_p: Some(0.9),
+            ..GenerationParams::default()
+        };
+        let request = GenerationParams {
+            temperature: Some(0.2),
+            ..GenerationParams::default()
+        };
+
+        let merged = request.or(&persona.or(&global));
+        assert_eq!(merged.temperature, Some(0.2));
+        assert_eq!(merged.top_p, Some(0.9));
+        assert_eq!(merged.max_tokens, Some(800));
+        assert_eq!(merged.stop, vec!["END".to_owned()]);
+    }
 }
@@ -3,7 +3,10 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use super::{ModelProvider, ModelRequest};
+use super::{KeyProvider, ModelCompletion, ModelProvider, ModelRequest, resolved_model};
+
+const OPENROUTER_CHAT_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+const OPENAI_CHAT_URL: &str = "https://api.openai.com/v1/chat/completions";
 
 #[derive(Debug, Clone)]
 pub struct OpenRouterProvider {
@@ -35,6 +38,14 @@ impl OpenRouterProvider {
 struct ChatCompletionRequest<'a> {
     model: &'a str,
     messages: Vec<ChatMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
 }
 
 #[derive(Debug, Serialize)]
@@ -56,13 +67,33 @@ struct ChatChoice {
 #[derive(Debug, Deserialize)]
 struct ChatChoiceMessage {
     content: Value,
+    /// Reasoning tokens, for models that expose them.
+    #[serde(default)]
+    reasoning: Option<String>,
 }
 
 #[async_trait]
 impl ModelProvider for OpenRouterProvider {
     async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+        Ok(self.complete_with_reasoning(request).await?.text)
+    }
+
+    async fn complete_with_reasoning(
+        &self,
+        request: ModelRequest,
+    ) -> anyhow::Result<ModelCompletion> {
+        let model = resolved_model(
+            request.params.model.as_deref().unwrap_or(&self.model),
+            request.params.api_key.as_ref(),
+        );
+        // A request carrying its own key is billed to that key.
+        let (url, api_key) = match &request.params.api_key {
+            Some(key) if key.provider == KeyProvider::OpenAi => (OPENAI_CHAT_URL, key.secret()),
+            Some(key) => (OPENROUTER_CHAT_URL, key.secret()),
+            None => (OPENROUTER_CHAT_URL, self.api_key.as_str()),
+        };
         let payload = ChatCompletionRequest {
-            model: &self.model,
+            model,
             messages: vec![
                 ChatMessage {
                     role: "system",
@@ -73,19 +104,21 @@ impl ModelProvider for OpenRouterProvider {
                     content: &request.user_prompt,
                 },
             ],
+            temperature: request.params.temperature,
+            top_p: request.params.top_p,
+            max_tokens: request.params.max_tokens,
+            stop: &request.params.stop,
         };
 
-        let mut builder = self
-            .client
-            .post("https://openrouter.ai/api/v1/chat/completions")
-            .bearer_auth(&self.api_key)
-            .json(&payload);
+        let mut builder = self.client.post(url).bearer_auth(api_key).json(&payload);
 
-        if let Some(referer) = &self.referer {
-            builder = builder.header("HTTP-Referer", referer);
-        }
-        if let Some(title) = &self.title {
-            builder = builder.header("X-Title", title);
+        if url == OPENROUTER_CHAT_URL {
+            if let Some(referer) = &self.referer {
+                builder = builder.header("HTTP-Referer", referer);
+            }
+            if let Some(title) = &self.title {
+                builder = builder.header("X-Title", title);
+            }
         }
 
         let response = builder
@@ -95,13 +128,21 @@ impl ModelProvider for OpenRouterProvider {
             .json::<ChatCompletionResponse>()
             .await?;
 
-        let content = response
+        let message = &response
             .choices
             .first()
-            .and_then(|choice| extract_message_content(&choice.message.content))
+            .ok_or_else(|| anyhow::anyhow!("model returned no choices"))?
+            .message;
+        let text = extract_message_content(&message.content)
             .ok_or_else(|| anyhow::anyhow!("model returned no choices"))?;
 
-        Ok(content)
+        Ok(ModelCompletion {
+            text,
+            reasoning: message
+                .reasoning
+                .clone()
+                .filter(|reasoning| !reasoning.trim().is_empty()),
+        })
     }
 }
 
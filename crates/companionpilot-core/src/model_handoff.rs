@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::{GenerationParams, ModelRequest},
+    types::ChatMessageRecord,
+};
+
+/// Latest messages of the user in the channel the handoff summary is written
+/// from.
+pub const HANDOFF_SCAN_LIMIT: usize = 30;
+const HANDOFF_MAX_TOKENS: u32 = 250;
+const HANDOFF_SYSTEM_PROMPT: &str = "You write handoff notes for an AI companion whose underlying model was just switched. From the transcript, summarize in at most five short bullet points the conversation threads that are still open: what the user is in the middle of, questions not yet answered, promises the companion made, and the tone of the conversation. Write in the third person about the user. Reply with the bullet points only, or `none` if nothing is ongoing.";
+
+/// The model a user's replies in one channel were last generated with and,
+/// after a switch, the note handed to the new model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelHandoff {
+    pub user_id: String,
+    pub guild_id: String,
+    pub channel_id: String,
+    pub model: String,
+    /// Summary of the open threads from before the switch to `model`.
+    pub summary: Option<String>,
+    /// Turns the summary is still shown for.
+    pub remaining_turns: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ModelHandoff {
+    pub fn new(user_id: &str, guild_id: &str, channel_id: &str, model: &str) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            guild_id: guild_id.to_owned(),
+            channel_id: channel_id.to_owned(),
+            model: model.to_owned(),
+            summary: None,
+            remaining_turns: 0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// The summary for the current turn, counting the turn against it.
+    pub fn take_turn(&mut self) -> Option<String> {
+        if self.remaining_turns == 0 {
+            return None;
+        }
+        self.remaining_turns -= 1;
+        self.updated_at = Utc::now();
+        self.summary.clone()
+    }
+}
+
+/// Asks for a summary of the open threads in `history` (oldest first).
+pub fn handoff_request(history: &[ChatMessageRecord], params: GenerationParams) -> ModelRequest {
+    let transcript = history
+        .iter()
+        .map(|message| format!("{}: {}", message.role.as_str(), message.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ModelRequest {
+        system_prompt: HANDOFF_SYSTEM_PROMPT.to_owned(),
+        user_prompt: transcript,
+        params: GenerationParams {
+            max_tokens: Some(HANDOFF_MAX_TOKENS),
+            ..params
+        },
+    }
+}
+
+/// The model's summary, or `None` when it found nothing ongoing.
+pub fn parse_handoff_summary(raw: &str) -> Option<String> {
+    let summary = raw.trim();
+    let nothing = summary
+        .trim_matches(|character: char| !character.is_alphanumeric())
+        .eq_ignore_ascii_case("none");
+    (!summary.is_empty() && !nothing).then(|| summary.to_owned())
+}
+
+/// System prompt section carrying the summary to the new model.
+pub fn handoff_block(summary: &str) -> String {
+    format!(
+        "You recently took over this conversation from another model. Notes on what was going on, so you can continue naturally without mentioning the switch:\n{summary}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_the_summary_for_a_limited_number_of_turns() {
+        let mut handoff = ModelHandoff {
+            summary: parse_handoff_summary("- Planning a trip to Lisbon in May\n"),
+            remaining_turns: 2,
+            ..ModelHandoff::new("u1", "g1", "c1", "openai/gpt-4o")
+        };
+        assert_eq!(
+            handoff.take_turn().as_deref(),
+            Some("- Planning a trip to Lisbon in May")
+        );
+        assert!(handoff.take_turn().is_some());
+        assert_eq!(handoff.take_turn(), None);
+
+        assert_eq!(parse_handoff_summary("None."), None);
+        assert_eq!(parse_handoff_summary("  "), None);
+    }
+}
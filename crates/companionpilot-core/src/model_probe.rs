@@ -0,0 +1,226 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    alerts::AdminAlerts,
+    model::{GenerationParams, ModelProvider, ModelRequest},
+    tool_stats::percentile,
+};
+
+/// A probe taking longer than this counts as failed.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct ModelProbeConfig {
+    /// Time between probes; also keeps the provider's connection warm.
+    pub interval: Duration,
+    /// Most recent probes kept for percentiles.
+    pub window: usize,
+    /// Probes required in the window before degradation is reported.
+    pub min_samples: usize,
+    /// p95 latency at or above which the model is reported as degraded.
+    pub p95_alert: Duration,
+}
+
+impl Default for ModelProbeConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5 * 60),
+            window: 30,
+            min_samples: 5,
+            p95_alert: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelProbeStats {
+    pub window_probes: usize,
+    pub window_failures: usize,
+    pub last_ms: Option<u64>,
+    pub last_probe_at: Option<DateTime<Utc>>,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub degraded: bool,
+}
+
+/// A change in the model's health, reported once per crossing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeTransition {
+    Degraded { p95_ms: u64 },
+    Recovered { p95_ms: u64 },
+}
+
+#[derive(Default)]
+struct ProbeWindow {
+    samples: VecDeque<(bool, u64)>,
+    last_probe_at: Option<DateTime<Utc>>,
+    degraded: bool,
+}
+
+impl ProbeWindow {
+    fn sorted_durations(&self) -> Vec<u64> {
+        let mut durations = self
+            .samples
+            .iter()
+            .map(|(_, duration_ms)| *duration_ms)
+            .collect::<Vec<_>>();
+        durations.sort_unstable();
+        durations
+    }
+}
+
+/// Rolling latency of tiny scheduled model requests, so provider slowness
+/// shows up before users wait on it.
+pub struct ModelLatencyProbe {
+    config: ModelProbeConfig,
+    window: Mutex<ProbeWindow>,
+}
+
+impl ModelLatencyProbe {
+    pub fn new(config: ModelProbeConfig) -> Self {
+        Self {
+            config,
+            window: Mutex::default(),
+        }
+    }
+
+    /// Records one probe; failed probes count with the time they took.
+    pub fn record(
+        &self,
+        success: bool,
+        duration_ms: u64,
+        at: DateTime<Utc>,
+    ) -> Option<ProbeTransition> {
+        let mut window = self
+            .window
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        window.samples.push_back((success, duration_ms));
+        while window.samples.len() > self.config.window.max(1) {
+            window.samples.pop_front();
+        }
+        window.last_probe_at = Some(at);
+
+        let p95_ms = percentile(&window.sorted_durations(), 95);
+        let degraded = window.samples.len() >= self.config.min_samples
+            && u128::from(p95_ms) >= self.config.p95_alert.as_millis();
+        let transition = match (window.degraded, degraded) {
+            (false, true) => Some(ProbeTransition::Degraded { p95_ms }),
+            (true, false) => Some(ProbeTransition::Recovered { p95_ms }),
+            _ => None,
+        };
+        window.degraded = degraded;
+        transition
+    }
+
+    pub fn snapshot(&self) -> ModelProbeStats {
+        let window = self
+            .window
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let durations = window.sorted_durations();
+        ModelProbeStats {
+            window_probes: window.samples.len(),
+            window_failures: window
+                .samples
+                .iter()
+                .filter(|(success, _)| !success)
+                .count(),
+            last_ms: window.samples.back().map(|(_, duration_ms)| *duration_ms),
+            last_probe_at: window.last_probe_at,
+            p50_ms: percentile(&durations, 50),
+            p95_ms: percentile(&durations, 95),
+            degraded: window.degraded,
+        }
+    }
+}
+
+/// Probes `model` every `interval` for the life of the process, warning and
+/// alerting admins when the p95 latency crosses the threshold either way.
+pub async fn run_model_probe(
+    probe: Arc<ModelLatencyProbe>,
+    model: Arc<dyn ModelProvider>,
+    admin_alerts: Option<AdminAlerts>,
+) {
+    let mut ticker = tokio::time::interval(probe.config.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let started_at = Instant::now();
+        let result = tokio::time::timeout(PROBE_TIMEOUT, model.complete(probe_request())).await;
+        let duration_ms = started_at.elapsed().as_millis() as u64;
+        let success = matches!(result, Ok(Ok(_)));
+        if !success {
+            warn!(duration_ms, "model latency probe failed");
+        }
+        match probe.record(success, duration_ms, Utc::now()) {
+            Some(ProbeTransition::Degraded { p95_ms }) => {
+                let message = format!(
+                    "model p95 latency is {p95_ms} ms over the last {} probes (alert at {} ms)",
+                    probe.snapshot().window_probes,
+                    probe.config.p95_alert.as_millis()
+                );
+                warn!(p95_ms, "{message}");
+                if let Some(admin_alerts) = &admin_alerts {
+                    admin_alerts.send("model_latency", message);
+                }
+            }
+            Some(ProbeTransition::Recovered { p95_ms }) => {
+                info!(p95_ms, "model latency recovered");
+            }
+            None => {}
+        }
+    }
+}
+
+fn probe_request() -> ModelRequest {
+    ModelRequest {
+        system_prompt: "Reply with the single word OK.".to_owned(),
+        user_prompt: "ping".to_owned(),
+        params: GenerationParams {
+            max_tokens: Some(1),
+            ..GenerationParams::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_degradation_once_and_recovery() {
+        let probe = ModelLatencyProbe::new(ModelProbeConfig {
+            window: 4,
+            min_samples: 2,
+            p95_alert: Duration::from_millis(1_000),
+            ..ModelProbeConfig::default()
+        });
+        let now = Utc::now();
+        assert_eq!(probe.record(true, 200, now), None);
+        assert_eq!(
+            probe.record(true, 1_500, now),
+            Some(ProbeTransition::Degraded { p95_ms: 1_500 })
+        );
+        assert_eq!(probe.record(false, 1_200, now), None);
+        assert!(probe.snapshot().degraded);
+        assert_eq!(probe.snapshot().window_failures, 1);
+
+        for _ in 0..3 {
+            probe.record(true, 100, now);
+        }
+        assert_eq!(
+            probe.record(true, 100, now),
+            Some(ProbeTransition::Recovered { p95_ms: 100 })
+        );
+        assert_eq!(probe.snapshot().p50_ms, 100);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{sync::Arc, time::Duration};
+
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use tracing::{debug, info, warn};
+
+use crate::ambient::AmbientContext;
+
+/// Pause before reconnecting after the broker connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Broker connection options from an `mqtt://[user:password@]host[:port]`
+/// URL. `client_id` gets a per-process suffix: brokers drop a client when
+/// another connects with its id, so replicas sharing one would keep
+/// disconnecting each other.
+pub fn mqtt_options(broker_url: &str, client_id: &str) -> anyhow::Result<MqttOptions> {
+    let url = reqwest::Url::parse(broker_url.trim())?;
+    anyhow::ensure!(
+        matches!(url.scheme(), "mqtt" | "tcp"),
+        "MQTT broker URL must use mqtt://, got `{}`",
+        url.scheme()
+    );
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("MQTT broker URL has no host"))?;
+    let mut options = MqttOptions::new(
+        replica_client_id(client_id),
+        host,
+        url.port().unwrap_or(1883),
+    );
+    options.set_keep_alive(Duration::from_secs(30));
+    if !url.username().is_empty() {
+        options.set_credentials(url.username(), url.password().unwrap_or_default());
+    }
+    Ok(options)
+}
+
+/// `prefix` plus eight hex digits from the process id and start time, which
+/// keeps the default id within the 23 characters every broker accepts.
+fn replica_client_id(prefix: &str) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    format!(
+        "{prefix}-{:08x}",
+        nanos ^ std::process::id().rotate_left(16)
+    )
+}
+
+/// Subscribes to the ambient topics and feeds every message into the
+/// blackboard, reconnecting (and resubscribing) whenever the broker drops.
+pub async fn run_mqtt_subscriber(options: MqttOptions, ambient: Arc<AmbientContext>) {
+    let (client, mut event_loop) = AsyncClient::new(options, 32);
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!(
+                    topics = ambient.topics().len(),
+                    "connected to MQTT broker; subscribing"
+                );
+                for topic in ambient.topics() {
+                    if let Err(error) = client.subscribe(&topic.filter, QoS::AtMostOnce).await {
+                        warn!(?error, filter = %topic.filter, "failed to subscribe to MQTT topic");
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                if !ambient.record(&publish.topic, &publish.payload) {
+                    debug!(topic = %publish.topic, "ignoring MQTT message without a mapping");
+                }
+            }
+            Ok(_) => {}
+            Err(error) => {
+                warn!(?error, "MQTT connection failed; reconnecting");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::{guild_settings::GuildSettings, memory::MemoryStore, timezone::user_timezone};
+
+/// Facts scanned for the user's time zone.
+const FACT_SCAN_LIMIT: usize = 1_000;
+/// Window the `max_per_day` cap counts over.
+const CAP_WINDOW: Duration = Duration::hours(24);
+
+/// A kind of message the companion sends without being asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// Birthday and anniversary greetings.
+    DateGreetings,
+    /// DMs asking to confirm stale facts.
+    MemoryReviews,
+}
+
+impl NotificationKind {
+    pub const ALL: [Self; 2] = [Self::DateGreetings, Self::MemoryReviews];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::DateGreetings => "date_greetings",
+            Self::MemoryReviews => "memory_reviews",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::DateGreetings => "birthday and anniversary greetings",
+            Self::MemoryReviews => "memory check-ins",
+        }
+    }
+}
+
+/// Where proactive messages are delivered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    /// Wherever the behavior posts, e.g. the configured greetings channel.
+    #[default]
+    Auto,
+    /// Always as a DM.
+    Dm,
+}
+
+impl NotificationChannel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Dm => "dm",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "dm" => Some(Self::Dm),
+            _ => None,
+        }
+    }
+}
+
+/// Hours of the local day without proactive messages: the user's (UTC
+/// without a `timezone` fact) or the guild's. Wraps past midnight when
+/// `start_hour > end_hour`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl QuietHours {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.start_hour > 23 || self.end_hour > 23 {
+            return Err("quiet hours must be between 0 and 23".to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+
+    /// End of the quiet period containing `now`, or `None` outside quiet
+    /// hours. `tz` is the zone the hours are in; UTC when `None`.
+    pub fn ends_after(&self, now: DateTime<Utc>, tz: Option<Tz>) -> Option<DateTime<Utc>> {
+        let (hour, minute) = match tz {
+            Some(tz) => {
+                let local = now.with_timezone(&tz);
+                (local.hour(), local.minute())
+            }
+            None => (now.hour(), now.minute()),
+        };
+        if !self.contains(hour) {
+            return None;
+        }
+        let hours_left = (self.end_hour + 24 - hour) % 24;
+        Some(now + Duration::minutes(i64::from(hours_left * 60 - minute)))
+    }
+}
+
+/// A user's settings for proactive messages, honored by every scheduled
+/// behavior. Users without a stored record get the defaults: everything on,
+/// delivered where the behavior posts, no quiet hours and no cap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationPrefs {
+    #[serde(default)]
+    pub user_id: String,
+    /// Turns every proactive message off.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Kinds the user turned off.
+    #[serde(default)]
+    pub muted: Vec<NotificationKind>,
+    #[serde(default)]
+    pub channel: NotificationChannel,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    /// Proactive messages per 24 hours; `None` is unlimited.
+    #[serde(default)]
+    pub max_per_day: Option<u32>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl NotificationPrefs {
+    pub fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            enabled: true,
+            muted: Vec::new(),
+            channel: NotificationChannel::default(),
+            quiet_hours: None,
+            max_per_day: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.quiet_hours
+            .as_ref()
+            .map_or(Ok(()), QuietHours::validate)
+    }
+
+    pub fn allows(&self, kind: NotificationKind) -> bool {
+        self.enabled && !self.muted.contains(&kind)
+    }
+
+    pub fn set_muted(&mut self, kind: NotificationKind, muted: bool) {
+        self.muted.retain(|muted_kind| *muted_kind != kind);
+        if muted {
+            self.muted.push(kind);
+        }
+    }
+
+    /// Short description for the `/notifications` command.
+    pub fn describe(&self) -> String {
+        if !self.enabled {
+            return "Proactive messages are off.".to_owned();
+        }
+        let kinds = NotificationKind::ALL
+            .iter()
+            .map(|kind| {
+                let state = if self.allows(*kind) { "on" } else { "off" };
+                format!("- {}: {state}", kind.label())
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let quiet = self.quiet_hours.map_or_else(
+            || "none".to_owned(),
+            |quiet| format!("{:02}:00-{:02}:00", quiet.start_hour, quiet.end_hour),
+        );
+        let cap = self
+            .max_per_day
+            .map_or_else(|| "unlimited".to_owned(), |cap| format!("{cap} per day"));
+        format!(
+            "{kinds}\nDelivery: {}\nQuiet hours: {quiet}\nLimit: {cap}",
+            self.channel.as_str()
+        )
+    }
+}
+
+/// What a scheduler should do with a proactive message right now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationDecision {
+    /// Send it; `dm_only` when the user wants DMs.
+    Send { dm_only: bool },
+    /// Not now (quiet hours or the daily cap); try again on a later run.
+    Defer,
+    /// The user turned this kind off.
+    Suppress,
+}
+
+/// Notification preferences in the memory store, and the checks schedulers
+/// run before every proactive message.
+pub struct Notifications {
+    store: Arc<dyn MemoryStore>,
+}
+
+impl Notifications {
+    pub fn new(store: Arc<dyn MemoryStore>) -> Self {
+        Self { store }
+    }
+
+    /// The user's preferences, or the defaults.
+    pub async fn get(&self, user_id: &str) -> anyhow::Result<NotificationPrefs> {
+        Ok(self
+            .store
+            .get_notification_prefs(user_id)
+            .await?
+            .unwrap_or_else(|| NotificationPrefs::new(user_id)))
+    }
+
+    pub async fn put(&self, mut prefs: NotificationPrefs) -> anyhow::Result<NotificationPrefs> {
+        prefs.muted.sort_by_key(|kind| kind.as_str());
+        prefs.muted.dedup();
+        prefs.updated_at = Utc::now();
+        self.store.put_notification_prefs(prefs.clone()).await?;
+        Ok(prefs)
+    }
+
+    /// The delivery gate every proactive message passes. `guild` is the
+    /// server the message would be posted in; its quiet hours only apply
+    /// when the user does not want DMs.
+    pub async fn decide(
+        &self,
+        user_id: &str,
+        kind: NotificationKind,
+        guild: Option<&GuildSettings>,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<NotificationDecision> {
+        let prefs = self.get(user_id).await?;
+        if !prefs.allows(kind) {
+            return Ok(NotificationDecision::Suppress);
+        }
+        if let Some(quiet) = prefs.quiet_hours {
+            let facts = self.store.list_facts(user_id, FACT_SCAN_LIMIT).await?;
+            if quiet.ends_after(now, user_timezone(&facts)).is_some() {
+                return Ok(NotificationDecision::Defer);
+            }
+        }
+        let dm_only = prefs.channel == NotificationChannel::Dm;
+        if !dm_only && guild.is_some_and(|guild| guild.quiet_until(now).is_some()) {
+            return Ok(NotificationDecision::Defer);
+        }
+        if let Some(cap) = prefs.max_per_day {
+            let sent = self
+                .store
+                .count_notifications_since(user_id, now - CAP_WINDOW)
+                .await?;
+            if sent >= cap as usize {
+                return Ok(NotificationDecision::Defer);
+            }
+        }
+        Ok(NotificationDecision::Send { dm_only })
+    }
+
+    /// [`QuietHours::ends_after`] over both the user's own quiet hours, in
+    /// their time zone, and those of the guild the reply goes to; the later
+    /// end wins.
+    pub async fn quiet_until(
+        &self,
+        user_id: &str,
+        guild: Option<&GuildSettings>,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let mut until = guild.and_then(|guild| guild.quiet_until(now));
+        if let Some(quiet) = self.get(user_id).await?.quiet_hours {
+            let facts = self.store.list_facts(user_id, FACT_SCAN_LIMIT).await?;
+            until = until.max(quiet.ends_after(now, user_timezone(&facts)));
+        }
+        Ok(until)
+    }
+
+    /// Counts a delivered message against the user's cap. Entries older than
+    /// the cap's window are dropped on the way.
+    pub async fn record_sent(
+        &self,
+        user_id: &str,
+        kind: NotificationKind,
+        sent_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.store
+            .record_notification(user_id, kind.as_str(), sent_at, sent_at - CAP_WINDOW)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{
+        memory::{InMemoryMemoryStore, IntoDynMemoryStore},
+        types::MemoryFact,
+    };
+
+    #[tokio::test]
+    async fn decisions_follow_kinds_quiet_hours_and_caps() {
+        let store = Arc::new(InMemoryMemoryStore::default());
+        let notifications = Notifications::new(IntoDynMemoryStore::into_dyn(store.clone()));
+        store
+            .upsert_fact(
+                "u1",
+                MemoryFact {
+                    key: "timezone".to_owned(),
+                    value: "Europe/Prague".to_owned(),
+                    confidence: 0.9,
+                    source: "user_message".to_owned(),
+                    updated_at: Utc::now(),
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("stored");
+        let mut prefs = notifications.get("u1").await.expect("defaults");
+        prefs.set_muted(NotificationKind::MemoryReviews, true);
+        prefs.quiet_hours = Some(QuietHours {
+            start_hour: 22,
+            end_hour: 7,
+        });
+        prefs.max_per_day = Some(1);
+        notifications.put(prefs).await.expect("stored");
+
+        // 21:30 UTC is 23:30 in Prague in summer.
+        let night = Utc
+            .with_ymd_and_hms(2026, 7, 1, 21, 30, 0)
+            .single()
+            .expect("valid timestamp");
+        let decide = |kind, now| notifications.decide("u1", kind, None, now);
+        assert_eq!(
+            decide(NotificationKind::MemoryReviews, night)
+                .await
+                .expect("decided"),
+            NotificationDecision::Suppress
+        );
+        assert_eq!(
+            decide(NotificationKind::DateGreetings, night)
+                .await
+                .expect("decided"),
+            NotificationDecision::Defer
+        );
+        let morning = night + Duration::hours(10);
+        assert_eq!(
+            decide(NotificationKind::DateGreetings, morning)
+                .await
+                .expect("decided"),
+            NotificationDecision::Send { dm_only: false }
+        );
+        let guild = GuildSettings {
+            quiet_hours: Some(QuietHours {
+                start_hour: 6,
+                end_hour: 8,
+            }),
+            ..GuildSettings::new("g1")
+        };
+        assert_eq!(
+            notifications
+                .decide("u1", NotificationKind::DateGreetings, Some(&guild), morning)
+                .await
+                .expect("decided"),
+            NotificationDecision::Defer
+        );
+        assert_eq!(
+            guild.quiet_until(morning),
+            Some(morning + Duration::minutes(30))
+        );
+        // Replies to mentions wait for the user's own quiet hours as well.
+        let quiet_until = |guild, now| notifications.quiet_until("u1", guild, now);
+        assert_eq!(
+            quiet_until(None, night).await.expect("checked"),
+            Some(night + Duration::minutes(7 * 60 + 30))
+        );
+        assert_eq!(
+            quiet_until(Some(&guild), morning).await.expect("checked"),
+            Some(morning + Duration::minutes(30))
+        );
+        assert_eq!(quiet_until(None, morning).await.expect("checked"), None);
+        notifications
+            .record_sent("u1", NotificationKind::DateGreetings, morning)
+            .await
+            .expect("recorded");
+        assert_eq!(
+            decide(
+                NotificationKind::DateGreetings,
+                morning + Duration::hours(1)
+            )
+            .await
+            .expect("decided"),
+            NotificationDecision::Defer
+        );
+
+        // Entries outside the cap's window are pruned by the next one.
+        notifications
+            .record_sent(
+                "u1",
+                NotificationKind::DateGreetings,
+                morning + Duration::days(3),
+            )
+            .await
+            .expect("recorded");
+        assert_eq!(
+            store
+                .count_notifications_since("u1", morning - Duration::days(1))
+                .await
+                .expect("counted"),
+            1
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Per-user cache of what a user is listening to, so consecutive messages do
+/// not each query Spotify. `None` entries (nothing playing, or the lookup
+/// failed) are cached too.
+#[derive(Debug)]
+pub struct NowPlayingCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Option<String>)>>,
+}
+
+impl NowPlayingCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// The cached value for `user_id`, or `None` if missing or expired.
+    pub fn get(&self, user_id: &str) -> Option<Option<String>> {
+        let entries = self.lock();
+        let (fetched_at, now_playing) = entries.get(user_id)?;
+        (fetched_at.elapsed() < self.ttl).then(|| now_playing.clone())
+    }
+
+    pub fn insert(&self, user_id: &str, now_playing: Option<String>) {
+        let mut entries = self.lock();
+        entries.retain(|_, (fetched_at, _)| fetched_at.elapsed() < self.ttl);
+        entries.insert(user_id.to_owned(), (Instant::now(), now_playing));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, Option<String>)>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for NowPlayingCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+/// "Track by Artist" from `spotify_playing_status` output, or `None` when
+/// nothing is playing.
+pub fn now_playing_summary(status_text: &str) -> Option<String> {
+    if !status_text.contains("Playback status: currently playing") {
+        return None;
+    }
+    let field = |name: &str| {
+        status_text
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let track = field("Track:")?;
+    Some(match field("Artist:") {
+        Some(artist) => format!("{track} by {artist}"),
+        None => track.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{NowPlayingCache, now_playing_summary};
+
+    #[test]
+    fn summarizes_playing_tracks_and_expires_cached_entries() {
+        let playing = "Spotify user: Petr\nPlayback status: currently playing\nTrack: PHOENIX\nArtist: Elley Duhe\nAlbum: PHOENIX";
+        assert_eq!(
+            now_playing_summary(playing).as_deref(),
+            Some("PHOENIX by Elley Duhe")
+        );
+        assert_eq!(
+            now_playing_summary("Spotify user: Petr\nPlayback status: not currently playing"),
+            None
+        );
+
+        let cache = NowPlayingCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get("u1"), None);
+        cache.insert("u1", None);
+        assert_eq!(cache.get("u1"), Some(None));
+
+        let expired = NowPlayingCache::new(Duration::ZERO);
+        expired.insert("u1", Some("song".to_owned()));
+        assert_eq!(expired.get("u1"), None);
+    }
+}
@@ -0,0 +1,334 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    memory::MemoryStore,
+    timezone::{TIMEZONE_FACT_KEY, parse_timezone},
+    types::MemoryFact,
+};
+
+/// Source recorded on facts written by the interview.
+pub const ONBOARDING_FACT_SOURCE: &str = "onboarding";
+/// An interview left unanswered this long is over; the user's next message
+/// is a normal chat turn again.
+const ONBOARDING_TIMEOUT: Duration = Duration::hours(1);
+const MAX_NAME_CHARS: usize = 50;
+const MAX_INTERESTS: usize = 10;
+const MAX_INTEREST_CHARS: usize = 40;
+/// Reply tones the interview offers.
+pub const TONES: &[&str] = &["casual", "friendly", "playful", "formal", "concise"];
+
+const NAME_PREFIXES: &[&str] = &["my name is ", "call me ", "i'm ", "i am ", "it's "];
+const SKIP_ANSWERS: &[&str] = &["skip", "pass", "next"];
+const STOP_ANSWERS: &[&str] = &["stop", "cancel", "quit", "later"];
+
+/// One question of the onboarding interview, in the order asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnboardingStep {
+    Name,
+    Timezone,
+    Interests,
+    Tone,
+}
+
+impl OnboardingStep {
+    pub const FIRST: Self = Self::Name;
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Timezone => "timezone",
+            Self::Interests => "interests",
+            Self::Tone => "tone",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "name" => Some(Self::Name),
+            "timezone" => Some(Self::Timezone),
+            "interests" => Some(Self::Interests),
+            "tone" => Some(Self::Tone),
+            _ => None,
+        }
+    }
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Name => Some(Self::Timezone),
+            Self::Timezone => Some(Self::Interests),
+            Self::Interests => Some(Self::Tone),
+            Self::Tone => None,
+        }
+    }
+
+    /// Fact key the answer is stored under.
+    pub fn fact_key(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Timezone => TIMEZONE_FACT_KEY,
+            Self::Interests => "interests",
+            Self::Tone => "preferred_tone",
+        }
+    }
+
+    pub fn question(self) -> String {
+        match self {
+            Self::Name => "What should I call you?".to_owned(),
+            Self::Timezone => {
+                "Which city or time zone are you in? (e.g. `Prague` or `America/New_York`)"
+                    .to_owned()
+            }
+            Self::Interests => {
+                "What are you into? List a few interests, separated by commas.".to_owned()
+            }
+            Self::Tone => format!(
+                "Last one: how should I talk to you? One of {}.",
+                TONES
+                    .iter()
+                    .map(|tone| format!("`{tone}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// The fact value for an answer, or why it was not accepted.
+    pub fn validate(self, answer: &str) -> Result<String, String> {
+        let answer = answer.trim().trim_end_matches(['.', '!']).trim();
+        match self {
+            Self::Name => {
+                let name = NAME_PREFIXES
+                    .iter()
+                    .find_map(|prefix| {
+                        let head = answer.get(..prefix.len())?;
+                        head.eq_ignore_ascii_case(prefix)
+                            .then(|| answer[prefix.len()..].trim())
+                    })
+                    .unwrap_or(answer);
+                if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+                    return Err(format!(
+                        "Just the name, please (up to {MAX_NAME_CHARS} characters)."
+                    ));
+                }
+                Ok(name.to_owned())
+            }
+            Self::Timezone => timezone_for_answer(answer)
+                .ok_or_else(|| {
+                    "I don't know that one. Try a bigger city nearby or an IANA name like `Europe/Prague`.".to_owned()
+                }),
+            Self::Interests => {
+                let interests = answer
+                    .split([',', ';', '\n'])
+                    .map(str::trim)
+                    .filter(|interest| !interest.is_empty())
+                    .collect::<Vec<_>>();
+                if interests.is_empty()
+                    || interests.len() > MAX_INTERESTS
+                    || interests
+                        .iter()
+                        .any(|interest| interest.chars().count() > MAX_INTEREST_CHARS)
+                {
+                    return Err(format!(
+                        "List up to {MAX_INTERESTS} short interests separated by commas."
+                    ));
+                }
+                Ok(interests.join(", "))
+            }
+            Self::Tone => {
+                let answer = answer.to_lowercase();
+                TONES
+                    .iter()
+                    .find(|tone| answer.contains(**tone))
+                    .map(|tone| (*tone).to_owned())
+                    .ok_or_else(|| format!("Pick one of: {}.", TONES.join(", ")))
+            }
+        }
+    }
+}
+
+/// An IANA name, or a city that names a zone (`new york`).
+fn timezone_for_answer(answer: &str) -> Option<String> {
+    if let Some(tz) = parse_timezone(answer) {
+        return Some(tz.name().to_owned());
+    }
+    let city = answer.replace(' ', "_");
+    chrono_tz::TZ_VARIANTS
+        .iter()
+        .find(|tz| {
+            tz.name()
+                .rsplit('/')
+                .next()
+                .is_some_and(|last| last.eq_ignore_ascii_case(&city))
+        })
+        .map(|tz| tz.name().to_owned())
+}
+
+/// Where a user is in the interview. A stored state without a step means
+/// the interview was offered or finished.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnboardingState {
+    pub user_id: String,
+    pub step: Option<OnboardingStep>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The onboarding interview: a few questions whose answers are validated
+/// and stored as facts directly, without planner extraction.
+pub struct Onboarding {
+    store: Arc<dyn MemoryStore>,
+}
+
+impl Onboarding {
+    pub fn new(store: Arc<dyn MemoryStore>) -> Self {
+        Self { store }
+    }
+
+    /// Whether the interview should be offered to the user: once, and only
+    /// to users the companion knows nothing about yet. Records the offer.
+    pub async fn offer(&self, user_id: &str) -> anyhow::Result<bool> {
+        if self.store.get_onboarding(user_id).await?.is_some() {
+            return Ok(false);
+        }
+        let known = !self.store.list_facts(user_id, 1).await?.is_empty();
+        self.put_step(user_id, None).await?;
+        Ok(!known)
+    }
+
+    /// Starts (or restarts) the interview and returns its first message.
+    pub async fn start(&self, user_id: &str) -> anyhow::Result<String> {
+        self.put_step(user_id, Some(OnboardingStep::FIRST)).await?;
+        Ok(format!(
+            "Let's get to know each other! Four quick questions; answer `skip` to skip one or `stop` to stop.\n\n{}",
+            OnboardingStep::FIRST.question()
+        ))
+    }
+
+    /// Handles the user's message if they are in the interview, returning
+    /// the reply; `None` means the message is a normal chat turn.
+    pub async fn answer(&self, user_id: &str, text: &str) -> anyhow::Result<Option<String>> {
+        let Some(state) = self.store.get_onboarding(user_id).await? else {
+            return Ok(None);
+        };
+        let Some(step) = state.step else {
+            return Ok(None);
+        };
+        if Utc::now() - state.updated_at > ONBOARDING_TIMEOUT {
+            self.put_step(user_id, None).await?;
+            return Ok(None);
+        }
+
+        let command = text.trim().to_lowercase();
+        if STOP_ANSWERS.contains(&command.as_str()) {
+            self.put_step(user_id, None).await?;
+            return Ok(Some(
+                "Okay, we'll stop here. Run `/introduce` whenever you want to continue.".to_owned(),
+            ));
+        }
+        let mut reply = String::new();
+        if !SKIP_ANSWERS.contains(&command.as_str()) {
+            let value = match step.validate(text) {
+                Ok(value) => value,
+                Err(hint) => return Ok(Some(hint)),
+            };
+            let now = Utc::now();
+            self.store
+                .upsert_fact(
+                    user_id,
+                    MemoryFact {
+                        key: step.fact_key().to_owned(),
+                        value: value.clone(),
+                        confidence: 1.0,
+                        source: ONBOARDING_FACT_SOURCE.to_owned(),
+                        updated_at: now,
+                        last_confirmed_at: Some(now),
+                    },
+                )
+                .await?;
+            reply = acknowledgement(step, &value);
+        }
+
+        let next = step.next();
+        self.put_step(user_id, next).await?;
+        let follow_up = match next {
+            Some(next) => next.question(),
+            None => "That's everything, thanks! You can see what I remember with `/whatdoyouknow`."
+                .to_owned(),
+        };
+        Ok(Some(if reply.is_empty() {
+            follow_up
+        } else {
+            format!("{reply} {follow_up}")
+        }))
+    }
+
+    async fn put_step(&self, user_id: &str, step: Option<OnboardingStep>) -> anyhow::Result<()> {
+        self.store
+            .put_onboarding(OnboardingState {
+                user_id: user_id.to_owned(),
+                step,
+                updated_at: Utc::now(),
+            })
+            .await
+    }
+}
+
+fn acknowledgement(step: OnboardingStep, value: &str) -> String {
+    match step {
+        OnboardingStep::Name => format!("Nice to meet you, {value}!"),
+        OnboardingStep::Timezone => format!("Got it, {value}."),
+        OnboardingStep::Interests => "Noted!".to_owned(),
+        OnboardingStep::Tone => format!("{value} it is."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{InMemoryMemoryStore, IntoDynMemoryStore};
+
+    #[tokio::test]
+    async fn interview_validates_answers_and_stores_facts() {
+        let store = Arc::new(InMemoryMemoryStore::default());
+        let onboarding = Onboarding::new(IntoDynMemoryStore::into_dyn(store.clone()));
+        assert!(onboarding.offer("u1").await.expect("offered"));
+        assert!(!onboarding.offer("u1").await.expect("offered once"));
+        assert_eq!(
+            onboarding.answer("u1", "hello").await.expect("answer"),
+            None
+        );
+
+        onboarding.start("u1").await.expect("started");
+        for answer in [
+            "My name is Petr",
+            "Atlantis",
+            "new york",
+            "skip",
+            "somewhere between casual and friendly",
+        ] {
+            assert!(
+                onboarding
+                    .answer("u1", answer)
+                    .await
+                    .expect("answer")
+                    .is_some()
+            );
+        }
+        assert_eq!(onboarding.answer("u1", "hi").await.expect("answer"), None);
+
+        let facts = store.list_facts("u1", 10).await.expect("facts");
+        let fact = |key: &str| {
+            facts
+                .iter()
+                .find(|fact| fact.key == key)
+                .map(|fact| fact.value.as_str())
+        };
+        assert_eq!(fact("name"), Some("Petr"));
+        assert_eq!(fact("timezone"), Some("America/New_York"));
+        assert_eq!(fact("interests"), None);
+        assert_eq!(fact("preferred_tone"), Some("casual"));
+    }
+}
@@ -1,47 +1,643 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use chrono::Utc;
-use serde::{Deserialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Value, json};
 use tracing::{debug, info, warn};
 
 use crate::{
-    memory::MemoryStore,
-    model::{ModelProvider, ModelRequest},
+    activity::ActivityTracker,
+    alerts::AdminAlerts,
+    ambient::AmbientContext,
+    background_tasks::{BackgroundTasks, run_background_task, task_started_result},
+    calendar::CalendarFeeds,
+    capabilities::{Capabilities, DeploymentInfo, is_capability_question},
+    concurrency::{
+        ConcurrencyLimiter, ConversationSequencer, ConversationSequencing, ConversationTurn,
+    },
+    context_prefetch::{ContextPrefetchCache, DEFAULT_PREFETCH_TTL},
+    context_window::{ContextSurface, ContextWindows},
+    coordination::{self, InstanceCoordinator},
+    custom_commands::CustomCommands,
+    date_facts::{DateFact, is_date_fact_key},
+    flags::TurnFlags,
+    guild_emoji::GuildEmojiCatalog,
+    guild_settings::{GuildSettings, GuildSettingsCache},
+    in_flight::InFlightGuard,
+    memory::{
+        IntoDynMemoryStore, MemoryStore, find_near_duplicate, rank_facts_by_confidence,
+        reinforce_fact,
+    },
+    memory_summary::{MemoryRequest, forget_fact, load_memory_summary},
+    model::{
+        ApiKey, GenerationParams, ModelCompletion, ModelProvider, ModelRequest, resolved_model,
+    },
+    model_handoff::{
+        HANDOFF_SCAN_LIMIT, ModelHandoff, handoff_block, handoff_request, parse_handoff_summary,
+    },
+    model_probe::ModelLatencyProbe,
+    notifications::Notifications,
+    now_playing::{NowPlayingCache, now_playing_summary},
+    onboarding::Onboarding,
+    outbox::queue_outbox_message,
+    personas::PersonaRegistry,
+    pins::pinned_context,
+    planner_examples::{PlannerExample, format_planner_examples, select_planner_examples},
+    planner_replay::{PlannerOutcome, PlannerReplay, PlannerReplayError, diff_outcomes},
+    prompt_rollout::{PromptRollouts, PromptVariant},
+    quotas::{ToolQuotaExceeded, ToolQuotaStatus, quota_day, quota_resets_at},
+    rate_limit::UserRateLimiter,
+    redaction::Redactor,
+    reply_filters::{DEFAULT_REPLY_PIPELINES, ReplyPipelines},
+    response_format::{ResponseFormatError, response_format_instruction, validate_response},
+    routing::{ModelRoutes, TurnRoute, classify_complexity, classify_turn, likely_needs_tools},
     safety::SafetyPolicy,
-    tools::ToolExecutor,
+    sessions::{clean_session_title, session_expired, session_title_request},
+    thoughts::reply_and_thought,
+    timezone::{TIMEZONE_FACT_KEY, local_time_line, parse_timezone, user_timezone},
+    tool_macros::{ToolMacro, ToolMacroRegistry},
+    tool_output::{DEFAULT_TOOL_OUTPUT_CHARS, truncate_tool_output},
+    tool_stats::{ToolStatsAggregator, ToolStatsConfig},
+    tools::{
+        MockToolExecutor, ToolArgError, ToolExecutor, ToolResult, builtin_tool_specs,
+        find_tool_spec, validate_tool_args,
+    },
     types::{
-        ChatMessageRecord, ChatRole, MemoryFact, MessageCtx, OrchestratorReply,
-        PlannerDecisionRecord, ReplyTimings, ToolCall, ToolCallRecord, ToolCallTiming,
+        BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord, ChatRole, ChatSession,
+        DM_GUILD_ID, MemoryFact, MessageCtx, Modality, OrchestratorReply, OutboxKind,
+        PendingFactRecord, PlannerDecisionRecord, ReplyTimings, TaskOrigin, ThoughtRecord,
+        ToolCall, ToolCallRecord, ToolCallTiming, TurnFailure, TurnPhase, context_speaker,
     },
-    voice::VoiceReplyOrchestrator,
+    user_keys::UserKeyVault,
 };
 
 const MAX_PLANNED_TOOL_CALLS: usize = 6;
 const MAX_TOOL_DECISION_ROUNDS: usize = 3;
 const SLOW_REPLY_THRESHOLD_MS: u64 = 30_000;
+const FACT_WRITE_CANDIDATE_LIMIT: usize = 64;
+const REGENERATE_HISTORY_LIMIT: usize = 50;
+/// Slow replies within `SLOW_REPLY_SPIKE_WINDOW` that make up a spike worth an
+/// admin alert.
+const SLOW_REPLY_SPIKE_COUNT: usize = 3;
+const SLOW_REPLY_SPIKE_WINDOW: Duration = Duration::from_secs(600);
+const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest structured tool payload, as compact JSON, given to synthesis.
+const MAX_TOOL_DATA_CHARS: usize = 4_000;
+/// Voice turns wait for the user to speak and then play the reply back, so they
+/// need far more headroom than request/response tools.
+const VOICE_LISTEN_TURN_TIMEOUT: Duration = Duration::from_secs(90);
+/// Planner decisions scanned when picking few-shot examples.
+const PLANNER_EXAMPLE_SCAN_LIMIT: usize = 50;
+/// Few-shot examples shown when a request's flags turn them on while the
+/// configuration has them off.
+const FLAGGED_PLANNER_EXAMPLES: usize = 3;
+/// Session messages scanned for the current channel's turns when context is
+/// session-scoped.
+const SESSION_CONTEXT_SCAN_LIMIT: usize = 64;
+/// Pinned messages considered for the pinned-context budget.
+const PINNED_MESSAGE_SCAN_LIMIT: usize = 50;
+/// Chat history read when looking for turns in other channels.
+const CROSS_CHANNEL_SCAN_LIMIT: usize = 100;
+/// Most unfinished or undelivered background tasks picked up at startup.
+const RESUMED_TASK_LIMIT: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct OrchestratorConfig {
+    /// Similarity (0.0-1.0) above which a new fact is merged into an existing
+    /// fact stored under a different key. `None` disables deduplication.
+    pub fact_dedup_threshold: Option<f32>,
+    /// Days after which an unconfirmed fact's confidence halves when ranking
+    /// facts for prompt context. Zero disables decay.
+    pub fact_decay_half_life_days: f32,
+    /// Corrective round-trips sent to the model when planner output is not
+    /// valid JSON. Zero falls back immediately.
+    pub planner_json_retries: usize,
+    /// Upper bound for a single tool call; a timed-out call becomes a failed
+    /// tool output and orchestration continues.
+    pub tool_timeout: Duration,
+    /// Per-tool replacements for `tool_timeout`, keyed by tool name.
+    pub tool_timeout_overrides: HashMap<String, Duration>,
+    /// Total time all calls of one tool round may take. Calls that would start
+    /// after the budget is spent are skipped. `None` disables the budget.
+    pub tool_round_budget: Option<Duration>,
+    /// Calls each user may make per UTC day, keyed by tool name. Tools
+    /// without an entry are unlimited.
+    pub tool_quotas: HashMap<String, u32>,
+    /// Characters of one tool output the planner and synthesis see; longer
+    /// outputs are truncated. Zero disables the limit.
+    pub tool_output_max_chars: usize,
+    /// Per-tool replacements for `tool_output_max_chars`, keyed by tool name.
+    pub tool_output_budgets: HashMap<String, usize>,
+    /// Orchestrations allowed to run at once. `None` disables the limit.
+    pub max_concurrent_orchestrations: Option<usize>,
+    /// Orchestrations allowed to wait for a free slot before new ones are
+    /// rejected with `OrchestratorBusy`.
+    pub max_queued_orchestrations: usize,
+    /// Messages each user may send per `user_rate_window`; more are rejected
+    /// with `RateLimited`. `None` disables the limit.
+    pub user_rate_limit: Option<u32>,
+    pub user_rate_window: Duration,
+    /// Ordering of messages within one (user, channel) conversation.
+    pub conversation_sequencing: ConversationSequencing,
+    /// Longest a conversation stays locked across instances, and how long a
+    /// turn waits for another instance to release it.
+    pub conversation_lock_ttl: Duration,
+    /// Global generation settings for user-facing replies.
+    pub generation: GenerationParams,
+    /// Reply model per kind of message; a persona's or the caller's model
+    /// wins over them.
+    pub model_routes: ModelRoutes,
+    /// Keep a heartbeat-refreshed marker for each reply in flight, so replies
+    /// lost to a crash can be recovered after a restart.
+    pub in_flight_tracking: bool,
+    /// Rolling window and failure-alert threshold for per-tool statistics.
+    pub tool_stats: ToolStatsConfig,
+    /// Settings for guilds that have not stored their own.
+    pub guild_defaults: GuildSettings,
+    /// How long guild settings are served from memory before re-reading.
+    pub guild_settings_cache_ttl: Duration,
+    /// Past planner decisions from the same user or guild shown to the
+    /// unified planner as few-shot examples. Zero disables them.
+    pub planner_examples: usize,
+    /// Send messages the small-talk classifier recognizes straight to reply
+    /// synthesis, without a planner round.
+    pub small_talk_routing: bool,
+    /// Start the direct reply alongside the planner for messages unlikely to
+    /// need tools, and use it when the planner requests none.
+    pub speculative_synthesis: bool,
+    /// Queue planner-proposed facts for admin approval instead of storing
+    /// them right away.
+    pub fact_approval_queue: bool,
+    /// The user's latest turns from other channels and DMs added to prompt
+    /// context, labeled by where they happened. Zero disables carry-over.
+    pub cross_channel_turns: usize,
+    /// Recent turns and facts loaded into context, per surface.
+    pub context_windows: ContextWindows,
+    /// Serve every tool call from the simulated tool executor instead of the
+    /// real tools, for demos and prompt testing.
+    pub simulate_tools: bool,
+    /// Dark-launched tools: the planner may call them and they run, but their
+    /// output is only logged and recorded, never shown to synthesis. The
+    /// follow-up planner does not see it either, since it can answer
+    /// directly, so they only run in the first tool round.
+    pub shadow_tools: HashSet<String>,
+    /// Users whose Spotify playback is fetched before each turn and added to
+    /// context, so the companion can mention it without a tool call.
+    pub now_playing_users: Vec<String>,
+    /// How long a fetched playback status is reused for the same user.
+    pub now_playing_ttl: Duration,
+    /// Users who get the ambient (home/IoT) readings in context, in DMs
+    /// only so the readings are not shown to a server.
+    pub ambient_context_users: Vec<String>,
+    /// Idle time after which the user's next message starts a new chat
+    /// session. `None` leaves new sessions to `/newchat`.
+    pub session_gap: Option<Duration>,
+    /// Ask the model for a short title once a session's first turn is done.
+    pub session_titles: bool,
+    /// Limit recent-message context to the current session instead of the
+    /// channel's whole history.
+    pub session_scoped_context: bool,
+    /// Estimated tokens pinned messages may take up in prompt context; the
+    /// newest pins win. Zero leaves pins out of context.
+    pub pinned_context_tokens: usize,
+    /// Store planner rationales and synthesis reasoning per message for the
+    /// dashboard. Inline reasoning is stripped from replies either way.
+    pub thoughts_log: bool,
+    /// How long a memory context prefetched while the user is typing stays
+    /// usable for their next message. Zero disables prefetching.
+    pub context_prefetch_ttl: Duration,
+    /// Model, memory backend and voice support reported as capabilities.
+    pub deployment: DeploymentInfo,
+    /// Turns after a switch of the user's reply model (configuration change
+    /// or fallback model) that get a summary of the open threads from before
+    /// it. Zero disables handoff notes.
+    pub model_handoff_turns: u32,
+}
+
+impl OrchestratorConfig {
+    /// Parses `tool=ms` pairs separated by commas, e.g. `web_search=15000`.
+    /// Malformed entries are ignored.
+    pub fn parse_tool_timeout_overrides(raw: &str) -> HashMap<String, Duration> {
+        raw.split(',')
+            .filter_map(|entry| {
+                let (tool_name, timeout_ms) = entry.split_once('=')?;
+                let tool_name = tool_name.trim();
+                let timeout_ms = timeout_ms.trim().parse::<u64>().ok()?;
+                (!tool_name.is_empty() && timeout_ms > 0)
+                    .then(|| (tool_name.to_owned(), Duration::from_millis(timeout_ms)))
+            })
+            .collect()
+    }
+
+    fn timeout_for_tool(&self, tool_name: &str) -> Duration {
+        self.tool_timeout_overrides
+            .get(tool_name)
+            .copied()
+            .unwrap_or(self.tool_timeout)
+    }
+
+    fn output_budget_for_tool(&self, tool_name: &str) -> usize {
+        self.tool_output_budgets
+            .get(tool_name)
+            .copied()
+            .unwrap_or(self.tool_output_max_chars)
+    }
+}
+
+impl Default for OrchestratorConfig {
+    fn default() -> Self {
+        Self {
+            fact_dedup_threshold: Some(0.82),
+            fact_decay_half_life_days: 90.0,
+            planner_json_retries: 1,
+            tool_timeout: DEFAULT_TOOL_TIMEOUT,
+            tool_timeout_overrides: HashMap::from([(
+                "discord_voice_listen_turn".to_owned(),
+                VOICE_LISTEN_TURN_TIMEOUT,
+            )]),
+            tool_round_budget: None,
+            tool_quotas: HashMap::new(),
+            tool_output_max_chars: DEFAULT_TOOL_OUTPUT_CHARS,
+            tool_output_budgets: HashMap::new(),
+            max_concurrent_orchestrations: None,
+            max_queued_orchestrations: 0,
+            user_rate_limit: None,
+            user_rate_window: Duration::from_secs(60),
+            conversation_sequencing: ConversationSequencing::default(),
+            conversation_lock_ttl: Duration::from_secs(300),
+            generation: GenerationParams::default(),
+            model_routes: ModelRoutes::default(),
+            in_flight_tracking: false,
+            tool_stats: ToolStatsConfig::default(),
+            guild_defaults: GuildSettings::default(),
+            guild_settings_cache_ttl: Duration::from_secs(60),
+            planner_examples: 3,
+            small_talk_routing: true,
+            speculative_synthesis: true,
+            fact_approval_queue: false,
+            cross_channel_turns: 0,
+            context_windows: ContextWindows::default(),
+            simulate_tools: false,
+            shadow_tools: HashSet::new(),
+            now_playing_users: Vec::new(),
+            now_playing_ttl: Duration::from_secs(60),
+            ambient_context_users: Vec::new(),
+            session_gap: Some(Duration::from_secs(2 * 60 * 60)),
+            session_titles: true,
+            session_scoped_context: false,
+            pinned_context_tokens: 400,
+            thoughts_log: false,
+            context_prefetch_ttl: DEFAULT_PREFETCH_TTL,
+            deployment: DeploymentInfo::default(),
+            model_handoff_turns: 3,
+        }
+    }
+}
+
+/// A named companion voice: its system prompt plus preferred generation
+/// settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Persona {
+    pub name: String,
+    pub system_prompt: String,
+    #[serde(default)]
+    pub generation: GenerationParams,
+    /// Tools the planner may use for this persona; `None` allows all.
+    #[serde(default)]
+    pub enabled_tools: Option<Vec<String>>,
+}
+
+/// Per-turn adjustments supplied by the caller.
+#[derive(Debug, Clone, Default)]
+pub struct TurnOptions {
+    /// Replaces the default system prompt header for this turn.
+    pub persona: Option<Persona>,
+    /// Generation settings for the user-facing reply; they win over the
+    /// persona's and the global settings. Planners keep provider defaults.
+    pub generation: GenerationParams,
+    /// When set, the reply must be JSON valid against this schema; it is
+    /// checked server-side with one corrective retry.
+    pub response_schema: Option<Value>,
+    /// Tools allowed for this turn (e.g. by guild settings), on top of the
+    /// persona's own restriction; `None` allows all.
+    pub enabled_tools: Option<Vec<String>>,
+    /// Skips long-term fact writes, e.g. when memory consent is off.
+    pub skip_memory_write: bool,
+    /// Language the reply must be written in.
+    pub language: Option<String>,
+    /// How the message arrived and the reply is delivered; recorded with both
+    /// chat messages.
+    pub modality: Modality,
+    /// Serve this turn's tool calls from the simulated tool executor.
+    pub simulate_tools: bool,
+    /// Experimental behaviors toggled for this turn only; recorded with the
+    /// planner decision.
+    pub flags: TurnFlags,
+    /// Reply model routes (e.g. from guild settings) that win over the
+    /// global ones kind by kind.
+    pub model_routes: Option<ModelRoutes>,
+    /// Where the turn happens, which sizes its context; derived from the
+    /// modality and guild when unset.
+    pub surface: Option<ContextSurface>,
+    /// Answers the latest user message again: it is not recorded a second
+    /// time, and it and the replies after it are left out of the context.
+    pub regenerate: bool,
+    /// Keep an in-flight marker for the turn (when tracking is on), for
+    /// adapters that can deliver a recovered reply after a crash.
+    pub track_in_flight: bool,
+}
+
+/// Turns user messages into replies. The HTTP API, Discord bot and voice
+/// runtime only depend on this trait, so a custom orchestration strategy can
+/// replace [`GenericChatOrchestrator`]. Every operation must be implemented;
+/// optional subsystems are `Option` accessors that default to `None`, and the
+/// HTTP API answers their endpoints with 404 when they are missing.
+#[async_trait]
+pub trait ChatOrchestrator: Send + Sync {
+    async fn handle_message_with_options(
+        &self,
+        ctx: MessageCtx,
+        options: TurnOptions,
+    ) -> anyhow::Result<OrchestratorReply>;
+
+    async fn handle_message(&self, ctx: MessageCtx) -> anyhow::Result<OrchestratorReply> {
+        self.handle_message_with_system_prompt_override(ctx, None)
+            .await
+    }
+
+    async fn handle_message_with_system_prompt_override(
+        &self,
+        ctx: MessageCtx,
+        system_prompt_override: Option<String>,
+    ) -> anyhow::Result<OrchestratorReply> {
+        self.handle_message_with_options(
+            ctx,
+            TurnOptions {
+                persona: system_prompt_override.map(|system_prompt| Persona {
+                    name: "custom".to_owned(),
+                    system_prompt,
+                    generation: GenerationParams::default(),
+                    enabled_tools: None,
+                }),
+                ..TurnOptions::default()
+            },
+        )
+        .await
+    }
+
+    /// Answers a transcribed voice turn and returns the text to speak. It is
+    /// admitted like a text message: rate limit, conversation sequencing and
+    /// the concurrency limit.
+    async fn handle_voice_transcript(&self, message: MessageCtx) -> anyhow::Result<String> {
+        let options = TurnOptions {
+            modality: Modality::Voice,
+            ..TurnOptions::default()
+        };
+        let reply = self.handle_message_with_options(message, options).await?;
+        Ok(reply.text)
+    }
+
+    /// Answers a transcript heard by a voice tool during a turn that was
+    /// already admitted. It must run under that turn's admission; waiting for
+    /// a second permit could wait forever behind its own caller, so it cannot
+    /// go through [`ChatOrchestrator::handle_voice_transcript`].
+    async fn handle_nested_voice_transcript(&self, message: MessageCtx) -> anyhow::Result<String>;
+
+    /// Deletes the latest assistant reply of a user (optionally limited to one
+    /// channel) and answers the preceding user message again. Returns `None`
+    /// when there is no user message to regenerate from.
+    async fn regenerate_last_reply(
+        &self,
+        user_id: &str,
+        channel_id: Option<&str>,
+        generation: GenerationParams,
+    ) -> anyhow::Result<Option<OrchestratorReply>>;
+
+    /// Re-runs the unified planner on the message of a stored decision, with
+    /// the user's current memory context, and compares the two decisions.
+    /// Nothing is executed or recorded. Returns `None` for unknown ids.
+    async fn replay_planner_decision(
+        &self,
+        decision_id: &str,
+    ) -> anyhow::Result<Option<PlannerReplay>>;
+
+    /// Loads the user's memory context ahead of a message they are about to
+    /// send, e.g. on a typing event, so their turn can skip the load.
+    async fn prefetch_context(&self, user_id: &str, guild_id: &str, channel_id: &str);
+
+    /// Today's usage for every quota-limited tool.
+    async fn tool_quota_status(&self, user_id: &str) -> anyhow::Result<Vec<ToolQuotaStatus>>;
+
+    /// Resets today's usage of one tool, or of every tool, and returns how
+    /// many counters were cleared.
+    async fn reset_tool_quota(&self, user_id: &str, tool_name: Option<&str>)
+    -> anyhow::Result<u64>;
+
+    /// Starts a tool as a background task for the message's user, as when
+    /// the planner picks it, e.g. for `/research`. Like a message, it counts
+    /// against the user's rate limit and is refused when the guild's settings
+    /// do not enable the tool. Returns `None` when the tool does not run in
+    /// the background here.
+    async fn start_background_tool(
+        &self,
+        ctx: &MessageCtx,
+        origin: TaskOrigin,
+        tool_name: &str,
+        args: Value,
+    ) -> anyhow::Result<Option<BackgroundTaskRecord>>;
+
+    /// Writes a message the companion sends on its own, such as a birthday
+    /// greeting, following `instruction` in the channel's persona and with
+    /// the user's memory context; outside DMs the user's facts are left out.
+    /// The message is recorded as an assistant turn in that channel, so the
+    /// user's reply has it in context. Returns `None` when nothing was
+    /// written.
+    async fn compose_proactive_message(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+        instruction: &str,
+    ) -> anyhow::Result<Option<String>>;
+
+    /// Stores a fact that arrived outside a conversation, e.g. from a mapped
+    /// external event, the way a planner memory write is stored: cleaned up,
+    /// merged with near-duplicates and queued when facts need approval.
+    /// Returns the fact as written, or `None` when it is unusable.
+    async fn write_external_fact(
+        &self,
+        user_id: &str,
+        fact: MemoryFact,
+        rationale: &str,
+    ) -> anyhow::Result<Option<MemoryFact>>;
+
+    fn memory(&self) -> Arc<dyn MemoryStore>;
+
+    fn guild_settings(&self) -> &GuildSettingsCache;
+
+    fn tool_stats(&self) -> &ToolStatsAggregator;
+
+    /// `text` with the personal data the redactor removes, as adapters may
+    /// store it.
+    fn redact(&self, text: String) -> String;
+
+    /// The per-user message limit, if one is configured.
+    fn rate_limiter(&self) -> Option<&UserRateLimiter> {
+        None
+    }
 
-pub struct DefaultChatOrchestrator {
-    model: Arc<dyn ModelProvider>,
-    memory: Arc<dyn MemoryStore>,
-    tools: Arc<dyn ToolExecutor>,
+    /// Turns in flight and recently active users, for presence displays.
+    fn activity(&self) -> Option<&ActivityTracker> {
+        None
+    }
+
+    /// Locks and claims shared with other instances, when running replicas.
+    fn coordinator(&self) -> Option<Arc<dyn InstanceCoordinator>> {
+        None
+    }
+
+    /// Scheduled model latency probe, when enabled.
+    fn model_probe(&self) -> Option<&ModelLatencyProbe> {
+        None
+    }
+
+    /// Post-processing adapters apply to replies before delivering them.
+    fn reply_pipelines(&self) -> &ReplyPipelines {
+        &DEFAULT_REPLY_PIPELINES
+    }
+
+    /// Storage for users' own model keys, when bring-your-own-key is on.
+    fn user_keys(&self) -> Option<&UserKeyVault> {
+        None
+    }
+
+    /// Persona prompt rollouts, when rollouts are enabled.
+    fn prompt_rollouts(&self) -> Option<&PromptRollouts> {
+        None
+    }
+
+    /// What the companion can currently do, as told to users who ask.
+    fn capabilities(&self) -> Option<Capabilities> {
+        None
+    }
+
+    /// Custom emoji of the guilds the companion is in, when an adapter
+    /// reports them.
+    fn guild_emoji(&self) -> Option<&GuildEmojiCatalog> {
+        None
+    }
+
+    /// Admin-defined slash commands.
+    fn custom_commands(&self) -> Option<&CustomCommands> {
+        None
+    }
+
+    /// The onboarding interview that seeds a user's facts.
+    fn onboarding(&self) -> Option<&Onboarding> {
+        None
+    }
+
+    /// Users' preferences for proactive messages, which schedulers check
+    /// before sending one.
+    fn notifications(&self) -> Option<&Notifications> {
+        None
+    }
+
+    /// Tokens for users' iCal feeds, when the feeds are enabled.
+    fn calendar_feeds(&self) -> Option<&CalendarFeeds> {
+        None
+    }
+}
+
+/// The built-in chat orchestrator over a model, memory store and tool executor. The parts
+/// may be concrete types, avoiding dynamic dispatch, or trait objects as in
+/// [`DefaultChatOrchestrator`].
+pub struct GenericChatOrchestrator<M: ?Sized, S: ?Sized, T: ?Sized> {
+    model: Arc<M>,
+    memory: Arc<S>,
+    tools: Arc<T>,
     safety: SafetyPolicy,
+    config: OrchestratorConfig,
+    limiter: Option<ConcurrencyLimiter>,
+    rate_limiter: Option<UserRateLimiter>,
+    activity: ActivityTracker,
+    coordinator: Option<Arc<dyn InstanceCoordinator>>,
+    model_probe: Option<Arc<ModelLatencyProbe>>,
+    tool_stats: ToolStatsAggregator,
+    admin_alerts: Option<AdminAlerts>,
+    slow_replies: Mutex<VecDeque<Instant>>,
+    sequencer: ConversationSequencer,
+    guild_settings: GuildSettingsCache,
+    personas: Arc<PersonaRegistry>,
+    simulated_tools: Arc<MockToolExecutor>,
+    tool_macros: Arc<ToolMacroRegistry>,
+    now_playing: NowPlayingCache,
+    ambient: Option<Arc<AmbientContext>>,
+    background_tasks: Option<BackgroundTasks>,
+    redactor: Option<Arc<Redactor>>,
+    user_keys: Option<Arc<UserKeyVault>>,
+    prompt_rollouts: Option<Arc<PromptRollouts>>,
+    guild_emoji: GuildEmojiCatalog,
+    custom_commands: CustomCommands,
+    onboarding: Onboarding,
+    notifications: Notifications,
+    calendar_feeds: Option<Arc<CalendarFeeds>>,
+    context_prefetch: ContextPrefetchCache,
+    reply_pipelines: Arc<ReplyPipelines>,
 }
 
+/// The built-in orchestrator with trait-object parts, as wired by the binary.
+pub type DefaultChatOrchestrator =
+    GenericChatOrchestrator<dyn ModelProvider, dyn MemoryStore, dyn ToolExecutor>;
+
 enum UnifiedPlanDecision {
     UsePlan {
         tool_calls: Vec<ToolCall>,
+        rejected_tool_calls: Vec<RejectedToolCall>,
         memory: MemoryDecision,
         rationale: String,
         payload: Value,
     },
+    /// The router classified the message as small talk; no planner ran.
+    SmallTalk,
+    /// The message asks about stored memory and is answered from the store.
+    MemoryRequest,
     Fallback {
         reason: &'static str,
         error: Option<String>,
     },
 }
 
+/// A direct-reply model call started alongside the planner. Dropping it
+/// aborts the call, so a discarded or abandoned speculation stops early.
+struct SpeculativeReply {
+    handle: tokio::task::JoinHandle<anyhow::Result<ModelCompletion>>,
+}
+
+impl SpeculativeReply {
+    fn start<M: ModelProvider + ?Sized + 'static>(model: Arc<M>, request: ModelRequest) -> Self {
+        Self {
+            handle: tokio::spawn(async move { model.complete_with_reasoning(request).await }),
+        }
+    }
+
+    async fn finish(mut self) -> anyhow::Result<ModelCompletion> {
+        (&mut self.handle).await?
+    }
+}
+
+impl Drop for SpeculativeReply {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
 enum MemoryDecision {
     Store {
         fact: MemoryFact,
@@ -60,6 +656,7 @@ enum ToolFollowupDecision {
     },
     UseTools {
         tool_calls: Vec<ToolCall>,
+        rejected_tool_calls: Vec<RejectedToolCall>,
         rationale: String,
         payload: Value,
     },
@@ -110,176 +707,883 @@ struct ToolFollowupPlan {
     rationale: String,
 }
 
+struct SanitizedToolCalls {
+    calls: Vec<ToolCall>,
+    /// Steps of requested tool macros, run after `calls` in order.
+    macro_calls: Vec<ToolCall>,
+    rejected: Vec<RejectedToolCall>,
+}
+
+/// A planned call that failed schema validation; its errors are fed back to the
+/// follow-up planner instead of being executed.
+#[derive(Debug, Serialize)]
+struct RejectedToolCall {
+    tool_name: String,
+    args: Value,
+    errors: Vec<ToolArgError>,
+}
+
+impl RejectedToolCall {
+    fn error_text(&self) -> String {
+        let details = self
+            .errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ");
+        format!("invalid arguments, call was not executed: {details}")
+    }
+}
+
 struct ExecutedToolOutput {
     tool_name: String,
     args: Value,
     success: bool,
     text: String,
+    data: Option<Value>,
 }
 
-impl DefaultChatOrchestrator {
-    pub fn new(
-        model: Arc<dyn ModelProvider>,
-        memory: Arc<dyn MemoryStore>,
-        tools: Arc<dyn ToolExecutor>,
-        safety: SafetyPolicy,
-    ) -> Self {
+impl<M, S, T> GenericChatOrchestrator<M, S, T>
+where
+    M: ModelProvider + ?Sized + 'static,
+    S: IntoDynMemoryStore + ?Sized + 'static,
+    T: ToolExecutor + ?Sized + 'static,
+{
+    pub fn new(model: Arc<M>, memory: Arc<S>, tools: Arc<T>, safety: SafetyPolicy) -> Self {
+        let custom_commands = CustomCommands::new(IntoDynMemoryStore::into_dyn(memory.clone()));
+        let onboarding = Onboarding::new(IntoDynMemoryStore::into_dyn(memory.clone()));
+        let notifications = Notifications::new(IntoDynMemoryStore::into_dyn(memory.clone()));
         Self {
             model,
-            memory,
             tools,
             safety,
+            config: OrchestratorConfig::default(),
+            limiter: None,
+            rate_limiter: None,
+            activity: ActivityTracker::default(),
+            coordinator: None,
+            model_probe: None,
+            tool_stats: ToolStatsAggregator::default(),
+            admin_alerts: None,
+            slow_replies: Mutex::default(),
+            sequencer: ConversationSequencer::new(ConversationSequencing::default()),
+            guild_settings: GuildSettingsCache::new(
+                IntoDynMemoryStore::into_dyn(memory.clone()),
+                GuildSettings::default(),
+                Duration::from_secs(60),
+            ),
+            memory,
+            personas: Arc::default(),
+            simulated_tools: Arc::default(),
+            tool_macros: Arc::default(),
+            now_playing: NowPlayingCache::default(),
+            ambient: None,
+            background_tasks: None,
+            redactor: None,
+            user_keys: None,
+            calendar_feeds: None,
+            prompt_rollouts: None,
+            guild_emoji: GuildEmojiCatalog::default(),
+            custom_commands,
+            onboarding,
+            notifications,
+            context_prefetch: ContextPrefetchCache::new(DEFAULT_PREFETCH_TTL),
+            reply_pipelines: Arc::default(),
         }
     }
 
-    pub async fn handle_message(&self, ctx: MessageCtx) -> anyhow::Result<OrchestratorReply> {
-        self.handle_message_with_system_prompt_override(ctx, None)
-            .await
+    pub fn with_admin_alerts(mut self, admin_alerts: AdminAlerts) -> Self {
+        self.admin_alerts = Some(admin_alerts);
+        self
     }
 
-    pub async fn handle_message_with_system_prompt_override(
-        &self,
-        ctx: MessageCtx,
-        system_prompt_override: Option<String>,
-    ) -> anyhow::Result<OrchestratorReply> {
-        let request_started_at = Instant::now();
-        let system_prompt_override = system_prompt_override
-            .map(|prompt| prompt.trim().to_owned())
-            .filter(|prompt| !prompt.is_empty());
-        let safety_flags = self.safety.validate_user_message(&ctx.content);
+    /// Registry used to resolve the persona configured for a guild.
+    pub fn with_personas(mut self, personas: Arc<PersonaRegistry>) -> Self {
+        self.personas = personas;
+        self
+    }
 
-        let load_context_started_at = Instant::now();
-        let memory_context = self
-            .memory
-            .load_context(&ctx.user_id, &ctx.guild_id, &ctx.channel_id)
-            .await?;
-        let load_context_ms = elapsed_ms(load_context_started_at);
+    /// Scripted tool outputs served in simulation mode; without a script
+    /// every simulated call answers with a placeholder.
+    pub fn with_simulated_tools(mut self, simulated_tools: Arc<MockToolExecutor>) -> Self {
+        self.simulated_tools = simulated_tools;
+        self
+    }
 
-        let record_user_message_started_at = Instant::now();
-        self.memory
-            .record_chat_message(ChatMessageRecord {
-                id: ctx.message_id.clone(),
-                user_id: ctx.user_id.clone(),
-                guild_id: ctx.guild_id.clone(),
-                channel_id: ctx.channel_id.clone(),
-                role: ChatRole::User,
-                content: ctx.content.clone(),
-                timestamp: ctx.timestamp,
-            })
-            .await?;
-        let record_user_message_ms = elapsed_ms(record_user_message_started_at);
+    /// Named tool chains the planner may request as a single call.
+    pub fn with_tool_macros(mut self, tool_macros: Arc<ToolMacroRegistry>) -> Self {
+        self.tool_macros = tool_macros;
+        self
+    }
 
-        let planner_started_at = Instant::now();
-        let planner_decision = self
-            .decide_unified_plan(&ctx.content, &memory_context)
-            .await;
-        let mut planner_ms = elapsed_ms(planner_started_at);
-        self.record_unified_planner_decision(&ctx, &planner_decision)
-            .await;
+    /// Live home/IoT readings added to context for `ambient_context_users`.
+    pub fn with_ambient_context(mut self, ambient: Arc<AmbientContext>) -> Self {
+        self.ambient = Some(ambient);
+        self
+    }
 
-        let (mut pending_tool_calls, memory_decision) = match planner_decision {
-            UnifiedPlanDecision::UsePlan {
-                tool_calls, memory, ..
-            } => (tool_calls, memory),
-            UnifiedPlanDecision::Fallback { reason, .. } => {
-                debug!(
-                    user_id = %ctx.user_id,
-                    reason,
-                    "planner fallback: running without tools and without memory write"
-                );
-                (
-                    Vec::new(),
-                    MemoryDecision::Skip {
-                        reason: "planner_fallback",
-                    },
-                )
-            }
+    /// Runs calls to the long-running tools `background_tasks` handles
+    /// detached from the turn.
+    pub fn with_background_tasks(mut self, background_tasks: BackgroundTasks) -> Self {
+        self.background_tasks = Some(background_tasks);
+        self
+    }
+
+    /// Masks personal data in chat messages, tool results and planner
+    /// decisions before they are stored.
+    pub fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Lets users' own model keys pay for their turns.
+    pub fn with_user_keys(mut self, user_keys: Arc<UserKeyVault>) -> Self {
+        self.user_keys = Some(user_keys);
+        self
+    }
+
+    /// Serves users their birthdays and anniversaries as iCal feeds.
+    pub fn with_calendar_feeds(mut self, calendar_feeds: Arc<CalendarFeeds>) -> Self {
+        self.calendar_feeds = Some(calendar_feeds);
+        self
+    }
+
+    /// Answers part of the users of a persona under rollout with its
+    /// candidate prompt and counts how each variant does.
+    pub fn with_prompt_rollouts(mut self, prompt_rollouts: Arc<PromptRollouts>) -> Self {
+        self.prompt_rollouts = Some(prompt_rollouts);
+        self
+    }
+
+    /// Swaps in the candidate persona for users assigned to it while the
+    /// persona is under rollout, and records the variant in the turn's flags.
+    /// Also returns the variant the turn counts for in the rollout: `None`
+    /// when a `prompt` flag overrode the user's assignment.
+    fn apply_prompt_rollout(
+        &self,
+        user_id: &str,
+        mut options: TurnOptions,
+    ) -> (TurnOptions, Option<PromptVariant>) {
+        let Some(rollouts) = &self.prompt_rollouts else {
+            return (options, None);
+        };
+        let Some(persona) = &options.persona else {
+            return (options, None);
         };
+        let Some((assigned, candidate)) = rollouts.assign(&persona.name, user_id) else {
+            return (options, None);
+        };
+        let variant = options.flags.prompt.unwrap_or(assigned);
+        if variant == PromptVariant::Candidate {
+            options.persona = Some(candidate.to_persona());
+        }
+        options.flags.prompt = Some(variant);
+        (options, (variant == assigned).then_some(variant))
+    }
 
-        let mut executed_tool_calls = Vec::new();
-        let mut tool_outputs = Vec::new();
-        let mut citations = Vec::new();
-        let mut tool_timings = Vec::new();
-        let mut followup_reply_text: Option<String> = None;
-        let mut tool_round = 0usize;
+    /// The user's own model key, if they set one. Lookup failures fall back
+    /// to the server key rather than failing the turn.
+    async fn user_api_key(&self, user_id: &str) -> Option<ApiKey> {
+        let user_keys = self.user_keys.as_ref()?;
+        let memory = IntoDynMemoryStore::into_dyn(self.memory.clone());
+        match user_keys.resolve(memory.as_ref(), user_id).await {
+            Ok(api_key) => api_key,
+            Err(error) => {
+                warn!(?error, %user_id, "failed to load user's model key; using the server key");
+                None
+            }
+        }
+    }
 
-        loop {
-            if pending_tool_calls.is_empty() {
-                break;
+    /// `text` as it may be stored.
+    fn redacted(&self, text: String) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.redact_owned(text),
+            None => text,
+        }
+    }
+
+    /// Restarts tasks a previous process left running and re-sends finished
+    /// ones that were never delivered.
+    pub async fn resume_background_tasks(&self) -> anyhow::Result<()> {
+        let Some(background) = &self.background_tasks else {
+            return Ok(());
+        };
+        let tasks = self
+            .memory
+            .list_background_tasks(
+                None,
+                &[
+                    BackgroundTaskStatus::Running,
+                    BackgroundTaskStatus::Done,
+                    BackgroundTaskStatus::Failed,
+                ],
+                RESUMED_TASK_LIMIT,
+            )
+            .await?;
+        for task in tasks {
+            // Other replicas resume the same rows; only the claimant runs or
+            // delivers each task.
+            if let Some(coordinator) = &self.coordinator
+                && !coordination::claim_once(
+                    coordinator.as_ref(),
+                    &background_task_claim_key(&task.id),
+                    background.timeout(),
+                )
+                .await
+            {
+                continue;
+            }
+            if task.status == BackgroundTaskStatus::Running {
+                info!(task_id = %task.id, tool_name = %task.tool_name, "resuming background task");
+                tokio::spawn(run_background_task(
+                    self.tools.clone(),
+                    IntoDynMemoryStore::into_dyn(self.memory.clone()),
+                    self.redactor.clone(),
+                    background.clone(),
+                    task,
+                ));
+            } else if task.origin == TaskOrigin::Discord {
+                background.complete(task);
             }
+        }
+        Ok(())
+    }
 
-            tool_round += 1;
-            let planner_source = if tool_round == 1 {
-                "unified_planner"
-            } else {
-                "tool_followup"
-            };
-            self.execute_planned_tool_calls(
-                &ctx,
-                pending_tool_calls,
-                planner_source,
-                &mut executed_tool_calls,
-                &mut tool_outputs,
-                &mut citations,
-                &mut tool_timings,
+    /// Persists a background task for the call and starts it.
+    async fn start_background_task(
+        &self,
+        background: &BackgroundTasks,
+        ctx: &MessageCtx,
+        origin: TaskOrigin,
+        tool_name: &str,
+        args: Value,
+    ) -> anyhow::Result<BackgroundTaskRecord> {
+        let now = Utc::now();
+        let mut task = BackgroundTaskRecord {
+            id: String::new(),
+            user_id: ctx.user_id.clone(),
+            guild_id: ctx.guild_id.clone(),
+            channel_id: ctx.channel_id.clone(),
+            tool_name: tool_name.to_owned(),
+            args,
+            origin,
+            status: BackgroundTaskStatus::Running,
+            result_text: None,
+            created_at: now,
+            updated_at: now,
+        };
+        task.id = self.memory.create_background_task(task.clone()).await?;
+        // Keeps replicas starting meanwhile from resuming it as well.
+        if let Some(coordinator) = &self.coordinator {
+            coordination::claim_once(
+                coordinator.as_ref(),
+                &background_task_claim_key(&task.id),
+                background.timeout(),
             )
             .await;
+        }
+        tokio::spawn(run_background_task(
+            self.tools.clone(),
+            IntoDynMemoryStore::into_dyn(self.memory.clone()),
+            self.redactor.clone(),
+            background.clone(),
+            task.clone(),
+        ));
+        Ok(task)
+    }
 
-            if tool_round >= MAX_TOOL_DECISION_ROUNDS {
-                debug!(
-                    user_id = %ctx.user_id,
-                    tool_round,
-                    "tool planning rounds limit reached; forcing final synthesis"
-                );
-                break;
-            }
+    /// Reply post-processing per delivery surface, replacing the defaults.
+    pub fn with_reply_pipelines(mut self, reply_pipelines: Arc<ReplyPipelines>) -> Self {
+        self.reply_pipelines = reply_pipelines;
+        self
+    }
 
-            let followup_started_at = Instant::now();
-            let followup_decision = self
-                .decide_tool_followup(&ctx.content, &memory_context, &tool_outputs)
-                .await;
-            planner_ms = planner_ms.saturating_add(elapsed_ms(followup_started_at));
-            self.record_tool_followup_decision(&ctx, tool_round, &followup_decision)
-                .await;
+    /// Latency of the scheduled model probe, exposed for dashboards.
+    pub fn with_model_probe(mut self, model_probe: Arc<ModelLatencyProbe>) -> Self {
+        self.model_probe = Some(model_probe);
+        self
+    }
 
-            match followup_decision {
-                ToolFollowupDecision::Final { answer, .. } => {
-                    followup_reply_text = Some(answer);
-                    break;
-                }
-                ToolFollowupDecision::UseTools { tool_calls, .. } => {
-                    pending_tool_calls = tool_calls;
-                }
-                ToolFollowupDecision::Fallback { reason, .. } => {
-                    debug!(
-                        user_id = %ctx.user_id,
-                        reason,
-                        tool_round,
-                        "tool follow-up planner fallback; forcing final synthesis"
-                    );
-                    break;
-                }
-            }
-        }
+    /// Serializes conversations across instances sharing `coordinator`.
+    pub fn with_coordinator(mut self, coordinator: Arc<dyn InstanceCoordinator>) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
 
-        let tool_execution_ms = tool_timings.iter().fold(0u64, |total, timing| {
-            total.saturating_add(timing.duration_ms)
+    pub fn with_config(mut self, config: OrchestratorConfig) -> Self {
+        self.tool_stats = ToolStatsAggregator::new(config.tool_stats.clone());
+        self.limiter = config.max_concurrent_orchestrations.map(|max_concurrent| {
+            ConcurrencyLimiter::new(max_concurrent, config.max_queued_orchestrations)
         });
+        self.rate_limiter = config
+            .user_rate_limit
+            .map(|limit| UserRateLimiter::new(limit, config.user_rate_window));
+        self.sequencer = ConversationSequencer::new(config.conversation_sequencing);
+        self.now_playing = NowPlayingCache::new(config.now_playing_ttl);
+        self.context_prefetch = ContextPrefetchCache::new(config.context_prefetch_ttl);
+        self.guild_settings = GuildSettingsCache::new(
+            IntoDynMemoryStore::into_dyn(self.memory.clone()),
+            config.guild_defaults.clone(),
+            config.guild_settings_cache_ttl,
+        );
+        self.config = config;
+        self
+    }
+
+    /// Moves the turn to `phase`, for error reports and crash recovery.
+    async fn enter_phase(&self, ctx: &MessageCtx, progress: &mut TurnFailure, phase: TurnPhase) {
+        progress.phase = phase;
+        progress.tools.clear();
+        if !self.config.in_flight_tracking {
+            return;
+        }
+        if let Err(error) = self
+            .memory
+            .touch_in_flight_reply(&ctx.message_id, Some(phase), Utc::now())
+            .await
+        {
+            warn!(?error, message_id = %ctx.message_id, "failed to record turn phase");
+        }
+    }
+
+    /// The reply model the routes pick for the message's kind; a pick is
+    /// logged with the planner decisions, while staying on the default model
+    /// is not.
+    async fn route_model(
+        &self,
+        ctx: &MessageCtx,
+        guild_routes: Option<&ModelRoutes>,
+    ) -> Option<String> {
+        let (routes, source) = match guild_routes {
+            Some(routes) => (routes.or(&self.config.model_routes), "guild"),
+            None => (self.config.model_routes.clone(), "global"),
+        };
+        if routes.is_empty() {
+            return None;
+        }
+        let complexity = classify_complexity(&ctx.content);
+        // Turns that stay on the default model are not worth a decision row.
+        let model = routes.model_for(complexity)?.to_owned();
+        info!(
+            user_id = %ctx.user_id,
+            message_id = %ctx.message_id,
+            complexity = complexity.as_str(),
+            model = %model,
+            source,
+            "reply model routed"
+        );
+        self.record_planner_decision(
+            ctx,
+            "model_router",
+            complexity.as_str(),
+            format!("{} message routed to {model}", complexity.as_str()),
+            json!({ "complexity": complexity.as_str(), "model": model, "source": source }),
+            true,
+            None,
+        )
+        .await;
+        Some(model)
+    }
+
+    /// Fills options the caller left unset from the guild's settings; a
+    /// failed lookup leaves the options unchanged.
+    async fn apply_guild_settings(&self, guild_id: &str, mut options: TurnOptions) -> TurnOptions {
+        let settings = match self.guild_settings.get(guild_id).await {
+            Ok(settings) => settings,
+            Err(error) => {
+                warn!(?error, guild_id, "failed to load guild settings");
+                return options;
+            }
+        };
+
+        if options.persona.is_none()
+            && let Some(name) = &settings.persona
+        {
+            options.persona = self.personas.get(name).map(|bundle| bundle.to_persona());
+            if options.persona.is_none() {
+                warn!(guild_id, persona = %name, "guild persona is not loaded");
+            }
+        }
+        if options.enabled_tools.is_none() {
+            options.enabled_tools = settings.enabled_tools;
+        }
+        if options.language.is_none() {
+            options.language = settings.language;
+        }
+        if options.model_routes.is_none() && !settings.model_routes.is_empty() {
+            options.model_routes = Some(settings.model_routes);
+        }
+        options.skip_memory_write |= !settings.memory_consent_default;
+        options
+    }
+
+    /// Runs one orchestration without admission control. Used directly for
+    /// nested turns (voice transcripts) that already run inside an admitted one.
+    /// Errors carry a [`TurnFailure`] naming the phase that failed.
+    async fn run_turn(
+        &self,
+        ctx: MessageCtx,
+        options: TurnOptions,
+    ) -> anyhow::Result<OrchestratorReply> {
+        let started_at = Instant::now();
+        let mut progress = TurnFailure::default();
+        let mut shown_notices = Vec::new();
+        let user_id = ctx.user_id.clone();
+        let result = self
+            .run_turn_phases(ctx, options, &mut progress, &mut shown_notices)
+            .await;
+        // The turn may have written facts and history that a context
+        // prefetched meanwhile would miss.
+        self.context_prefetch.invalidate_user(&user_id);
+        match result {
+            Ok(reply) => {
+                // Notices are consumed only by a turn that answered, so a
+                // failed one leaves them for the retry.
+                if let Err(error) = self
+                    .memory
+                    .consume_system_notices(&user_id, &shown_notices, Utc::now())
+                    .await
+                {
+                    warn!(?error, %user_id, "failed to consume system notices");
+                }
+                Ok(reply)
+            }
+            Err(error) => {
+                progress.timings.total_ms = elapsed_ms(started_at);
+                warn!(phase = progress.phase.as_str(), tools = ?progress.tools, "turn failed");
+                Err(error.context(progress))
+            }
+        }
+    }
+
+    async fn run_turn_phases(
+        &self,
+        ctx: MessageCtx,
+        options: TurnOptions,
+        progress: &mut TurnFailure,
+        shown_notices: &mut Vec<String>,
+    ) -> anyhow::Result<OrchestratorReply> {
+        let request_started_at = Instant::now();
+        let options = self.apply_guild_settings(&ctx.guild_id, options).await;
+        let (options, rollout_variant) = self.apply_prompt_rollout(&ctx.user_id, options);
+        let system_prompt_override = options
+            .persona
+            .as_ref()
+            .map(|persona| persona.system_prompt.trim().to_owned())
+            .filter(|prompt| !prompt.is_empty());
+        let persona_generation = options
+            .persona
+            .as_ref()
+            .map(|persona| persona.generation.clone())
+            .unwrap_or_default();
+        let mut generation = options.generation.clone().or(&persona_generation);
+        // The model the user is on, before per-message routes and flags.
+        let conversation_model = generation
+            .model
+            .clone()
+            .or_else(|| self.config.generation.model.clone())
+            .unwrap_or_else(|| self.config.deployment.model.clone());
+        if generation.model.is_none() && options.flags.model.is_none() {
+            generation.model = self.route_model(&ctx, options.model_routes.as_ref()).await;
+        }
+        let mut generation = generation.or(&self.config.generation);
+        let mut planner_params = GenerationParams {
+            model: options.flags.model.clone(),
+            ..GenerationParams::default()
+        };
+        if let Some(model) = &options.flags.model {
+            generation.model = Some(model.clone());
+        }
+        if let Some(api_key) = self.user_api_key(&ctx.user_id).await {
+            planner_params.api_key = Some(api_key.clone());
+            generation.api_key = Some(api_key);
+        }
+        let safety_flags = self.safety.validate_user_message(&ctx.content);
+
+        let surface = options
+            .surface
+            .unwrap_or_else(|| ContextSurface::of(&ctx.guild_id, options.modality));
+        let limits = self.config.context_windows.for_surface(surface);
+        let load_context_started_at = Instant::now();
+        let mut memory_context =
+            match self
+                .context_prefetch
+                .take(&ctx.user_id, &ctx.guild_id, &ctx.channel_id)
+            {
+                Some(memory_context) => {
+                    debug!(user_id = %ctx.user_id, "using prefetched memory context");
+                    memory_context
+                }
+                None => {
+                    self.memory
+                        .load_context(&ctx.user_id, &ctx.guild_id, &ctx.channel_id, limits)
+                        .await?
+                }
+            };
+        rank_facts_by_confidence(
+            &mut memory_context.facts,
+            Utc::now(),
+            self.config.fact_decay_half_life_days,
+        );
+        if self.config.cross_channel_turns > 0 {
+            let history = self
+                .memory
+                .list_chat_messages(&ctx.user_id, CROSS_CHANNEL_SCAN_LIMIT)
+                .await?;
+            memory_context.other_channel_messages = cross_channel_turns(
+                &history,
+                &ctx.guild_id,
+                &ctx.channel_id,
+                self.config.cross_channel_turns,
+            );
+        }
+        memory_context.now_playing = self
+            .load_now_playing(&ctx, options.simulate_tools || self.config.simulate_tools)
+            .await;
+        if let Some(ambient) = &self.ambient
+            && ctx.guild_id == DM_GUILD_ID
+            && self.config.ambient_context_users.contains(&ctx.user_id)
+        {
+            memory_context.ambient = ambient.lines();
+        }
+        let session = self.resolve_session(&ctx.user_id, ctx.timestamp).await?;
+        if self.config.session_scoped_context {
+            memory_context.recent_messages = self
+                .session_context(&ctx, &session.id, limits.recent_messages)
+                .await?;
+        }
+        if self.config.pinned_context_tokens > 0 {
+            let pinned = self
+                .memory
+                .list_pinned_messages(&ctx.user_id, &ctx.guild_id, PINNED_MESSAGE_SCAN_LIMIT)
+                .await?;
+            memory_context.pinned_messages =
+                pinned_context(&pinned, self.config.pinned_context_tokens);
+        }
+        let notices = self
+            .memory
+            .list_system_notices(&ctx.user_id, Utc::now())
+            .await?;
+        shown_notices.extend(notices.iter().map(|notice| notice.id.clone()));
+        memory_context.system_notices = notices.into_iter().map(|notice| notice.text).collect();
+        if is_capability_question(&ctx.content) {
+            let mut capabilities =
+                Capabilities::new(&self.config.deployment, self.tools.enabled_tool_specs());
+            capabilities
+                .tools
+                .retain(|spec| tool_enabled(&options, spec.tool_name));
+            memory_context.capabilities = Some(capabilities.prompt_block());
+        }
+        let conversation_model =
+            resolved_model(&conversation_model, generation.api_key.as_ref()).to_owned();
+        memory_context.handoff = self
+            .model_handoff(&ctx, &conversation_model, planner_params.clone())
+            .await;
+        if options.modality == Modality::Text {
+            memory_context.guild_emoji = self
+                .guild_emoji
+                .prompt_block(&ctx.guild_id, &ctx.message_id);
+        }
+        if options.regenerate {
+            drop_last_exchange(&mut memory_context.recent_messages);
+        }
+        let load_context_ms = elapsed_ms(load_context_started_at);
+        progress.timings.load_context_ms = load_context_ms;
+
+        let record_user_message_started_at = Instant::now();
+        if !options.regenerate {
+            self.memory
+                .record_chat_message(ChatMessageRecord {
+                    id: ctx.message_id.clone(),
+                    user_id: ctx.user_id.clone(),
+                    guild_id: ctx.guild_id.clone(),
+                    channel_id: ctx.channel_id.clone(),
+                    role: ChatRole::User,
+                    content: self.redacted(ctx.content.clone()),
+                    timestamp: ctx.timestamp,
+                    modality: options.modality,
+                    session_id: Some(session.id.clone()),
+                    pinned: false,
+                    source_message_id: Some(ctx.message_id.clone()),
+                })
+                .await?;
+        }
+        let record_user_message_ms = elapsed_ms(record_user_message_started_at);
+        progress.timings.record_user_message_ms = record_user_message_ms;
+
+        let memory_request = MemoryRequest::detect(&ctx.content);
+        let route = if memory_request.is_some() {
+            TurnRoute::MemoryRequest
+        } else if self.config.small_talk_routing {
+            classify_turn(&ctx.content)
+        } else {
+            TurnRoute::Planner
+        };
+        debug!(
+            user_id = %ctx.user_id,
+            message_id = %ctx.message_id,
+            route = route.as_str(),
+            "message routed"
+        );
+
+        let direct_reply_request = ModelRequest {
+            system_prompt: with_response_format(
+                with_modality_style(
+                    with_reply_language(
+                        build_system_prompt(&memory_context, system_prompt_override.as_deref()),
+                        options.language.as_deref(),
+                    ),
+                    options.modality,
+                ),
+                options.response_schema.as_ref(),
+            ),
+            user_prompt: ctx.content.clone(),
+            params: generation.clone(),
+        };
+        let mut speculative_reply = (route == TurnRoute::Planner
+            && options
+                .flags
+                .speculative_synthesis
+                .unwrap_or(self.config.speculative_synthesis)
+            && !likely_needs_tools(&ctx.content))
+        .then(|| SpeculativeReply::start(self.model.clone(), direct_reply_request.clone()));
+
+        self.enter_phase(&ctx, progress, TurnPhase::Planner).await;
+        let planner_started_at = Instant::now();
+        let planner_decision = match route {
+            TurnRoute::SmallTalk => UnifiedPlanDecision::SmallTalk,
+            TurnRoute::MemoryRequest => UnifiedPlanDecision::MemoryRequest,
+            TurnRoute::Planner => {
+                let max_examples = match options.flags.planner_examples {
+                    Some(false) => 0,
+                    Some(true) => self.config.planner_examples.max(FLAGGED_PLANNER_EXAMPLES),
+                    None => self.config.planner_examples,
+                };
+                let planner_examples = self.load_planner_examples(&ctx, max_examples).await;
+                self.decide_unified_plan(
+                    &ctx.content,
+                    &memory_context,
+                    &planner_examples,
+                    &planner_params,
+                )
+                .await
+            }
+        };
+        let mut planner_ms = elapsed_ms(planner_started_at);
+        progress.timings.planner_ms = planner_ms;
+        self.record_unified_planner_decision(&ctx, &planner_decision, &options.flags)
+            .await;
+        let planner_fallback = (route == TurnRoute::Planner).then_some(matches!(
+            planner_decision,
+            UnifiedPlanDecision::Fallback { .. }
+        ));
+        let mut thoughts = Vec::new();
+        if let UnifiedPlanDecision::UsePlan { rationale, .. } = &planner_decision {
+            thoughts.push(("unified_planner", rationale.clone()));
+        }
+
+        let (mut pending_tool_calls, mut pending_rejections, memory_decision) =
+            match planner_decision {
+                UnifiedPlanDecision::UsePlan {
+                    tool_calls,
+                    rejected_tool_calls,
+                    memory,
+                    ..
+                } => {
+                    let (tool_calls, rejected_tool_calls) =
+                        restrict_to_enabled_tools(&options, tool_calls, rejected_tool_calls);
+                    (tool_calls, rejected_tool_calls, memory)
+                }
+                UnifiedPlanDecision::SmallTalk => (
+                    Vec::new(),
+                    Vec::new(),
+                    MemoryDecision::Skip {
+                        reason: "small_talk",
+                    },
+                ),
+                UnifiedPlanDecision::MemoryRequest => (
+                    Vec::new(),
+                    Vec::new(),
+                    MemoryDecision::Skip {
+                        reason: "memory_request",
+                    },
+                ),
+                UnifiedPlanDecision::Fallback { reason, .. } => {
+                    debug!(
+                        user_id = %ctx.user_id,
+                        reason,
+                        "planner fallback: running without tools and without memory write"
+                    );
+                    (
+                        Vec::new(),
+                        Vec::new(),
+                        MemoryDecision::Skip {
+                            reason: "planner_fallback",
+                        },
+                    )
+                }
+            };
+
+        if speculative_reply.is_some()
+            && !(pending_tool_calls.is_empty() && pending_rejections.is_empty())
+        {
+            speculative_reply = None;
+            debug!(
+                user_id = %ctx.user_id,
+                message_id = %ctx.message_id,
+                "speculative synthesis discarded; planner requested tools"
+            );
+        }
+
+        let mut executed_tool_calls = Vec::new();
+        let mut tool_outputs = Vec::new();
+        let mut citations = Vec::new();
+        let mut tool_timings = Vec::new();
+        let mut followup_reply_text: Option<String> = None;
+        let mut tool_round = 0usize;
+
+        loop {
+            if pending_tool_calls.is_empty() && pending_rejections.is_empty() {
+                break;
+            }
+
+            tool_round += 1;
+            self.enter_phase(&ctx, progress, TurnPhase::Tools).await;
+            progress.tools = pending_tool_calls
+                .iter()
+                .map(|call| call.tool_name.clone())
+                .collect();
+            let planner_source = if tool_round == 1 {
+                "unified_planner"
+            } else {
+                "tool_followup"
+            };
+            self.record_rejected_tool_calls(
+                &ctx,
+                std::mem::take(&mut pending_rejections),
+                planner_source,
+                &mut tool_outputs,
+            )
+            .await;
+            default_datetime_timezone(&mut pending_tool_calls, &memory_context.facts);
+            self.execute_planned_tool_calls(
+                &ctx,
+                task_origin(&options),
+                pending_tool_calls,
+                planner_source,
+                options.simulate_tools || self.config.simulate_tools,
+                &mut executed_tool_calls,
+                &mut tool_outputs,
+                &mut citations,
+                &mut tool_timings,
+            )
+            .await;
+            progress.timings.tool_calls = tool_timings.clone();
+
+            if tool_round >= MAX_TOOL_DECISION_ROUNDS {
+                debug!(
+                    user_id = %ctx.user_id,
+                    tool_round,
+                    "tool planning rounds limit reached; forcing final synthesis"
+                );
+                break;
+            }
+
+            self.enter_phase(&ctx, progress, TurnPhase::Planner).await;
+            let followup_started_at = Instant::now();
+            let budget = PlannerBudget {
+                rounds_left: MAX_TOOL_DECISION_ROUNDS - tool_round,
+                elapsed: request_started_at.elapsed(),
+                round_budget: self.config.tool_round_budget,
+                quotas: self.remaining_tool_quotas(&ctx.user_id).await,
+            };
+            let followup_decision = self
+                .decide_tool_followup(
+                    &ctx.content,
+                    &memory_context,
+                    &tool_outputs,
+                    &budget,
+                    &planner_params,
+                )
+                .await;
+            planner_ms = planner_ms.saturating_add(elapsed_ms(followup_started_at));
+            progress.timings.planner_ms = planner_ms;
+            self.record_tool_followup_decision(&ctx, tool_round, &followup_decision)
+                .await;
+            if let ToolFollowupDecision::Final { rationale, .. }
+            | ToolFollowupDecision::UseTools { rationale, .. } = &followup_decision
+            {
+                thoughts.push(("tool_followup", rationale.clone()));
+            }
+
+            match followup_decision {
+                ToolFollowupDecision::Final { answer, .. } => {
+                    followup_reply_text = Some(answer);
+                    break;
+                }
+                ToolFollowupDecision::UseTools {
+                    tool_calls,
+                    rejected_tool_calls,
+                    ..
+                } => {
+                    (pending_tool_calls, pending_rejections) =
+                        restrict_to_enabled_tools(&options, tool_calls, rejected_tool_calls);
+                    // Their output never reached this planner, which would
+                    // keep asking for them.
+                    pending_tool_calls
+                        .retain(|call| !self.config.shadow_tools.contains(&call.tool_name));
+                }
+                ToolFollowupDecision::Fallback { reason, .. } => {
+                    debug!(
+                        user_id = %ctx.user_id,
+                        reason,
+                        tool_round,
+                        "tool follow-up planner fallback; forcing final synthesis"
+                    );
+                    break;
+                }
+            }
+        }
+
+        let tool_execution_ms = tool_timings.iter().fold(0u64, |total, timing| {
+            total.saturating_add(timing.duration_ms)
+        });
+        progress.timings.tool_execution_ms = tool_execution_ms;
 
-        let (reply_text, final_model_ms) = if let Some(answer) = followup_reply_text {
+        let memory_reply = match memory_request {
+            Some(request) => Some(
+                self.answer_memory_request(&ctx, request, options.skip_memory_write)
+                    .await?,
+            ),
+            None => None,
+        };
+        let (reply_text, final_model_ms) = if let Some(answer) =
+            memory_reply.or(followup_reply_text)
+        {
             (answer, 0)
         } else {
+            self.enter_phase(&ctx, progress, TurnPhase::Synthesis).await;
             let final_model_started_at = Instant::now();
             let reply_text = if tool_outputs.is_empty() {
-                self.model
-                    .complete(ModelRequest {
-                        system_prompt: build_system_prompt(
-                            &memory_context,
-                            system_prompt_override.as_deref(),
-                        ),
-                        user_prompt: ctx.content.clone(),
-                    })
-                    .await?
+                let direct_reply = match speculative_reply.take() {
+                    Some(speculative_reply) => {
+                        debug!(
+                            user_id = %ctx.user_id,
+                            message_id = %ctx.message_id,
+                            "using speculative synthesis"
+                        );
+                        speculative_reply.finish().await
+                    }
+                    None => {
+                        self.model
+                            .complete_with_reasoning(direct_reply_request)
+                            .await
+                    }
+                };
+                direct_reply.inspect_err(|error| {
+                    self.alert("model_provider_error", "synthesis", error.to_string());
+                })?
             } else {
                 let tool_output_block = format_tool_outputs(&tool_outputs);
                 let custom_prompt_header = system_prompt_override
@@ -287,37 +1591,66 @@ impl DefaultChatOrchestrator {
                     .map(|prompt| format!("Custom system prompt override:\n{prompt}\n\n"))
                     .unwrap_or_default();
                 self.model
-                    .complete(ModelRequest {
-                        system_prompt: format!(
-                            "{}You are CompanionPilot. Use the provided tool outputs to answer the user's request precisely.\nNever say you cannot browse the web in this mode.\nNever output XML/JSON/pseudo tool-call markup.\nReturn only the final user-facing answer.\nIf citations are provided, keep your answer concise and factual.\n{}",
-                            custom_prompt_header,
-                            build_recent_context_block(&memory_context.recent_messages)
+                    .complete_with_reasoning(ModelRequest {
+                        system_prompt: with_response_format(
+                            with_modality_style(
+                                with_reply_language(
+                                    format!(
+                                        "{}You are CompanionPilot. Use the provided tool outputs to answer the user's request precisely.\nNever say you cannot browse the web in this mode.\nNever output XML/JSON/pseudo tool-call markup.\nReturn only the final user-facing answer.\nIf citations are provided, keep your answer concise and factual.\nA `Data:` line repeats a tool's output as JSON; take exact values (numbers, names, times) from it.\n{}",
+                                        custom_prompt_header,
+                                        build_recent_context_block(&memory_context.recent_messages)
+                                    ),
+                                    options.language.as_deref(),
+                                ),
+                                options.modality,
+                            ),
+                            options.response_schema.as_ref(),
                         ),
                         user_prompt: format!(
                             "User request:\n{}\n\nTool outputs:\n{}",
                             ctx.content, tool_output_block
                         ),
+                        params: generation.clone(),
                     })
                     .await
                     .unwrap_or_else(|error| {
                         warn!(?error, "failed to synthesize final answer from tool outputs");
-                        fallback_tool_output_text(&tool_outputs)
+                        self.alert("model_provider_error", "synthesis", error.to_string());
+                        ModelCompletion {
+                            text: fallback_tool_output_text(&tool_outputs),
+                            reasoning: None,
+                        }
                     })
             };
+            let (reply_text, thought) = reply_and_thought(reply_text);
+            if let Some(thought) = thought {
+                thoughts.push(("synthesis", thought));
+            }
             (reply_text, elapsed_ms(final_model_started_at))
         };
+        let (reply_text, reply_json) = match &options.response_schema {
+            Some(schema) => {
+                let (text, value) = self
+                    .shape_json_reply(schema, &ctx.content, reply_text, &generation)
+                    .await?;
+                (text, Some(value))
+            }
+            None => (reply_text, None),
+        };
 
+        progress.timings.final_model_ms = final_model_ms;
+        self.enter_phase(&ctx, progress, TurnPhase::MemoryWrite)
+            .await;
         let memory_write_started_at = Instant::now();
+        let memory_decision = match memory_decision {
+            MemoryDecision::Store { .. } if options.skip_memory_write => MemoryDecision::Skip {
+                reason: "memory_write_disabled",
+            },
+            decision => decision,
+        };
         match memory_decision {
             MemoryDecision::Store { fact, rationale } => {
-                info!(
-                    user_id = %ctx.user_id,
-                    memory_key = %fact.key,
-                    confidence = fact.confidence,
-                    rationale,
-                    "memory fact stored"
-                );
-                self.memory.upsert_fact(&ctx.user_id, fact).await?;
+                self.write_memory_fact(&ctx, fact, rationale).await?;
             }
             MemoryDecision::Skip { reason } => {
                 debug!(
@@ -329,19 +1662,69 @@ impl DefaultChatOrchestrator {
         }
         let memory_write_ms = elapsed_ms(memory_write_started_at);
 
+        if self.config.thoughts_log {
+            self.record_thoughts(&ctx, thoughts).await;
+        }
+
         let record_assistant_message_started_at = Instant::now();
-        self.memory
-            .record_chat_message(ChatMessageRecord {
-                id: format!("{}-assistant", ctx.message_id),
-                user_id: ctx.user_id.clone(),
-                guild_id: ctx.guild_id.clone(),
-                channel_id: ctx.channel_id.clone(),
-                role: ChatRole::Assistant,
-                content: reply_text.clone(),
-                timestamp: Utc::now(),
-            })
-            .await?;
+        let assistant_message = ChatMessageRecord {
+            id: format!("{}-assistant", ctx.message_id),
+            user_id: ctx.user_id.clone(),
+            guild_id: ctx.guild_id.clone(),
+            channel_id: ctx.channel_id.clone(),
+            role: ChatRole::Assistant,
+            content: self.redacted(reply_text.clone()),
+            timestamp: Utc::now(),
+            modality: options.modality,
+            session_id: Some(session.id.clone()),
+            pinned: false,
+            source_message_id: Some(ctx.message_id.clone()),
+        };
+        let stored_reply_id = match self
+            .memory
+            .record_chat_message(assistant_message.clone())
+            .await
+        {
+            Ok(id) => id,
+            Err(error) => {
+                // The reply is ready; keep it for the outbox job rather than
+                // dropping it over a failed history write.
+                let reply_id = assistant_message.id.clone();
+                let memory = IntoDynMemoryStore::into_dyn(self.memory.clone());
+                queue_outbox_message(
+                    memory.as_ref(),
+                    OutboxKind::Record,
+                    assistant_message,
+                    &ctx.message_id,
+                    &error,
+                )
+                .await
+                .map_err(|_| error)?;
+                reply_id
+            }
+        };
         let record_assistant_message_ms = elapsed_ms(record_assistant_message_started_at);
+        if let (Some(rollouts), Some(variant), Some(persona)) =
+            (&self.prompt_rollouts, rollout_variant, &options.persona)
+        {
+            let memory = IntoDynMemoryStore::into_dyn(self.memory.clone());
+            if let Err(error) = rollouts
+                .record_turn(
+                    memory.as_ref(),
+                    &persona.name,
+                    variant,
+                    &ctx.user_id,
+                    &stored_reply_id,
+                    planner_fallback,
+                )
+                .await
+            {
+                warn!(?error, persona = %persona.name, "failed to count prompt rollout turn");
+            }
+        }
+        if self.config.session_titles && session.title.is_none() {
+            self.spawn_session_title(&session, &ctx.content, &reply_text);
+        }
 
         let timings = ReplyTimings {
             total_ms: elapsed_ms(request_started_at),
@@ -368,6 +1751,7 @@ impl DefaultChatOrchestrator {
                 memory_write_ms = timings.memory_write_ms,
                 "slow reply detected"
             );
+            self.track_slow_reply();
         } else {
             info!(
                 user_id = %ctx.user_id,
@@ -389,29 +1773,99 @@ impl DefaultChatOrchestrator {
             tool_calls: executed_tool_calls,
             safety_flags,
             timings,
+            merged_into: None,
+            json: reply_json,
         };
 
         Ok(reply)
     }
 
-    async fn decide_unified_plan(
+    /// Answers "what do you know about me" and "forget my ..." from the store,
+    /// so the reply lists what is actually saved rather than what the model
+    /// recalls. Forgetting is a memory write, so it is refused when writes are
+    /// off for the turn.
+    async fn answer_memory_request(
         &self,
-        user_input: &str,
-        memory: &crate::types::MemoryContext,
-    ) -> UnifiedPlanDecision {
-        let planner_prompt = build_unified_planner_prompt(memory);
-        let planner_result = self
-            .model
-            .complete(ModelRequest {
-                system_prompt: planner_prompt,
-                user_prompt: user_input.to_owned(),
-            })
-            .await;
-
-        let planner_result = match planner_result {
+        ctx: &MessageCtx,
+        request: MemoryRequest,
+        skip_memory_write: bool,
+    ) -> anyhow::Result<String> {
+        let store = IntoDynMemoryStore::into_dyn(self.memory.clone());
+        match request {
+            MemoryRequest::Summary => Ok(load_memory_summary(
+                store.as_ref(),
+                &ctx.user_id,
+                &ctx.guild_id,
+                &ctx.channel_id,
+            )
+            .await?
+            .render()),
+            MemoryRequest::Forget(_) if skip_memory_write => Ok(
+                "Memory changes are turned off here, so I can't forget anything right now."
+                    .to_owned(),
+            ),
+            MemoryRequest::Forget(topic) => {
+                match forget_fact(store.as_ref(), &ctx.user_id, &topic).await? {
+                    Some(fact) => {
+                        info!(
+                            user_id = %ctx.user_id,
+                            memory_key = %fact.key,
+                            "memory fact forgotten on request"
+                        );
+                        Ok(format!(
+                            "Okay, I've forgotten your {}.",
+                            fact.key.replace('_', " ")
+                        ))
+                    }
+                    None => Ok(format!(
+                        "I don't have anything saved about your {topic}. Ask me \"what do you know about me?\" to see what I remember."
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Few-shot examples for the unified planner, from the user's own recent
+    /// decisions in this guild. Other members' decisions are never used: their
+    /// messages and tool arguments are theirs to see.
+    async fn load_planner_examples(&self, ctx: &MessageCtx, max: usize) -> Vec<PlannerExample> {
+        if max == 0 {
+            return Vec::new();
+        }
+        let mut decisions = match self
+            .memory
+            .list_planner_decisions(&ctx.user_id, PLANNER_EXAMPLE_SCAN_LIMIT)
+            .await
+        {
+            Ok(decisions) => decisions,
+            Err(error) => {
+                warn!(?error, "failed to load user planner decisions for examples");
+                Vec::new()
+            }
+        };
+        decisions.retain(|decision| decision.guild_id == ctx.guild_id);
+        select_planner_examples(&decisions, max)
+    }
+
+    async fn decide_unified_plan(
+        &self,
+        user_input: &str,
+        memory: &crate::types::MemoryContext,
+        examples: &[PlannerExample],
+        params: &GenerationParams,
+    ) -> UnifiedPlanDecision {
+        let planner_request = ModelRequest {
+            system_prompt: build_unified_planner_prompt(memory, examples, &self.tool_macros),
+            user_prompt: user_input.to_owned(),
+            params: params.clone(),
+        };
+        let planner_result = self.model.complete(planner_request.clone()).await;
+
+        let planner_result = match planner_result {
             Ok(content) => content,
             Err(error) => {
                 warn!(?error, "unified planner model call failed");
+                self.alert("model_provider_error", "planner", error.to_string());
                 return UnifiedPlanDecision::Fallback {
                     reason: "planner_model_error",
                     error: Some(error.to_string()),
@@ -419,11 +1873,23 @@ impl DefaultChatOrchestrator {
             }
         };
 
-        match parse_unified_plan(&planner_result) {
+        let parsed = self
+            .parse_planner_output(
+                "unified_planner",
+                &planner_request,
+                planner_result,
+                parse_unified_plan,
+            )
+            .await;
+        match parsed {
             Ok(plan) => {
-                let tool_calls = enforce_datetime_planning_boundary(sanitize_planned_tool_calls(
-                    plan.tool_calls,
-                ));
+                let SanitizedToolCalls {
+                    calls,
+                    macro_calls,
+                    rejected,
+                } = sanitize_planned_tool_calls(plan.tool_calls, &self.tool_macros);
+                let mut tool_calls = enforce_datetime_planning_boundary(calls);
+                tool_calls.extend(macro_calls);
                 let memory = memory_decision_from_plan(plan.memory);
                 let rationale = if plan.rationale.trim().is_empty() {
                     "model_planner".to_owned()
@@ -432,19 +1898,22 @@ impl DefaultChatOrchestrator {
                 };
 
                 let payload = json!({
+                    "user_input": user_input,
                     "tool_calls": tool_calls,
+                    "rejected_tool_calls": rejected,
                     "memory": memory_payload(&memory),
                     "rationale": rationale
                 });
 
                 UnifiedPlanDecision::UsePlan {
                     tool_calls,
+                    rejected_tool_calls: rejected,
                     memory,
                     rationale,
                     payload,
                 }
             }
-            Err(error) => {
+            Err((error, planner_result)) => {
                 warn!(
                     ?error,
                     planner_output = %truncate_for_log(&planner_result, 220),
@@ -463,24 +1932,25 @@ impl DefaultChatOrchestrator {
         user_input: &str,
         memory: &crate::types::MemoryContext,
         tool_outputs: &[ExecutedToolOutput],
+        budget: &PlannerBudget,
+        params: &GenerationParams,
     ) -> ToolFollowupDecision {
-        let planner_prompt = build_tool_followup_prompt(memory);
-        let planner_result = self
-            .model
-            .complete(ModelRequest {
-                system_prompt: planner_prompt,
-                user_prompt: format!(
-                    "User request:\n{}\n\nTool outputs so far:\n{}",
-                    user_input,
-                    format_tool_outputs(tool_outputs)
-                ),
-            })
-            .await;
+        let planner_request = ModelRequest {
+            system_prompt: build_tool_followup_prompt(memory, &self.tool_macros, budget),
+            user_prompt: format!(
+                "User request:\n{}\n\nTool outputs so far:\n{}",
+                user_input,
+                format_tool_outputs(tool_outputs)
+            ),
+            params: params.clone(),
+        };
+        let planner_result = self.model.complete(planner_request.clone()).await;
 
         let planner_result = match planner_result {
             Ok(content) => content,
             Err(error) => {
                 warn!(?error, "tool follow-up planner model call failed");
+                self.alert("model_provider_error", "planner", error.to_string());
                 return ToolFollowupDecision::Fallback {
                     reason: "followup_model_error",
                     error: Some(error.to_string()),
@@ -488,7 +1958,15 @@ impl DefaultChatOrchestrator {
             }
         };
 
-        match parse_tool_followup_plan(&planner_result) {
+        let parsed = self
+            .parse_planner_output(
+                "tool_followup",
+                &planner_request,
+                planner_result,
+                parse_tool_followup_plan,
+            )
+            .await;
+        match parsed {
             Ok(plan) => {
                 let rationale = if plan.rationale.trim().is_empty() {
                     "tool_followup_planner".to_owned()
@@ -520,10 +1998,14 @@ impl DefaultChatOrchestrator {
                         }
                     }
                     "tools" | "tool_calls" => {
-                        let tool_calls = enforce_datetime_planning_boundary(
-                            sanitize_planned_tool_calls(plan.tool_calls),
-                        );
-                        if tool_calls.is_empty() {
+                        let SanitizedToolCalls {
+                            calls,
+                            macro_calls,
+                            rejected,
+                        } = sanitize_planned_tool_calls(plan.tool_calls, &self.tool_macros);
+                        let mut tool_calls = enforce_datetime_planning_boundary(calls);
+                        tool_calls.extend(macro_calls);
+                        if tool_calls.is_empty() && rejected.is_empty() {
                             return ToolFollowupDecision::Fallback {
                                 reason: "followup_empty_tools",
                                 error: Some(
@@ -537,10 +2019,12 @@ impl DefaultChatOrchestrator {
                             payload: json!({
                                 "action": "tools",
                                 "tool_calls": &tool_calls,
+                                "rejected_tool_calls": &rejected,
                                 "rationale": rationale.clone()
                             }),
                             rationale,
                             tool_calls,
+                            rejected_tool_calls: rejected,
                         }
                     }
                     _ => ToolFollowupDecision::Fallback {
@@ -552,7 +2036,7 @@ impl DefaultChatOrchestrator {
                     },
                 }
             }
-            Err(error) => {
+            Err((error, planner_result)) => {
                 warn!(
                     ?error,
                     planner_output = %truncate_for_log(&planner_result, 220),
@@ -566,24 +2050,120 @@ impl DefaultChatOrchestrator {
         }
     }
 
+    /// Parses planner output, asking the model to repair invalid JSON up to
+    /// `planner_json_retries` times. On failure returns the last parse error and
+    /// the last raw output.
+    async fn parse_planner_output<P>(
+        &self,
+        planner: &'static str,
+        request: &ModelRequest,
+        mut output: String,
+        parse: fn(&str) -> Result<P, serde_json::Error>,
+    ) -> Result<P, (serde_json::Error, String)> {
+        let mut attempt = 0usize;
+        loop {
+            let error = match parse(&output) {
+                Ok(plan) => {
+                    if attempt > 0 {
+                        info!(planner, attempt, "planner output repaired after retry");
+                    }
+                    return Ok(plan);
+                }
+                Err(error) => error,
+            };
+            if attempt >= self.config.planner_json_retries {
+                return Err((error, output));
+            }
+
+            attempt += 1;
+            warn!(
+                planner,
+                attempt,
+                error = %error,
+                planner_output = %truncate_for_log(&output, 220),
+                "planner output is not valid JSON, requesting correction"
+            );
+            let correction = ModelRequest {
+                system_prompt: request.system_prompt.clone(),
+                user_prompt: build_json_correction_prompt(&request.user_prompt, &output, &error),
+                params: request.params.clone(),
+            };
+            output = match self.model.complete(correction).await {
+                Ok(content) => content,
+                Err(model_error) => {
+                    warn!(
+                        planner,
+                        attempt,
+                        error = ?model_error,
+                        "planner correction model call failed"
+                    );
+                    return Err((error, output));
+                }
+            };
+        }
+    }
+
+    /// Validates a reply against the caller's JSON schema, asking the model
+    /// once to fix it when it does not match.
+    async fn shape_json_reply(
+        &self,
+        schema: &Value,
+        user_input: &str,
+        reply_text: String,
+        generation: &GenerationParams,
+    ) -> anyhow::Result<(String, Value)> {
+        let errors = match validate_response(schema, &reply_text) {
+            Ok(value) => return Ok((value.to_string(), value)),
+            Err(errors) => errors,
+        };
+        warn!(
+            errors = %errors.join("; "),
+            reply = %truncate_for_log(&reply_text, 220),
+            "reply does not match requested JSON schema, requesting correction"
+        );
+
+        let corrected = self
+            .model
+            .complete(ModelRequest {
+                system_prompt: response_format_instruction(schema),
+                user_prompt: format!(
+                    "User request:\n{user_input}\n\nPrevious answer:\n{reply_text}\n\nValidation errors:\n{}\n\nReturn the corrected JSON only.",
+                    errors.join("\n")
+                ),
+                params: generation.clone(),
+            })
+            .await?;
+        match validate_response(schema, &corrected) {
+            Ok(value) => Ok((value.to_string(), value)),
+            Err(errors) => Err(ResponseFormatError { errors }.into()),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn execute_planned_tool_calls(
         &self,
         ctx: &MessageCtx,
+        origin: TaskOrigin,
         planned_tool_calls: Vec<ToolCall>,
         source: &'static str,
+        simulate: bool,
         executed_tool_calls: &mut Vec<ToolCall>,
         tool_outputs: &mut Vec<ExecutedToolOutput>,
         citations: &mut Vec<String>,
         tool_timings: &mut Vec<ToolCallTiming>,
     ) {
+        let round_started_at = Instant::now();
         for tool_call in planned_tool_calls {
             let tool_started_at = Instant::now();
             let tool_name = tool_call.tool_name;
             let args = tool_call.args.clone();
-            executed_tool_calls.push(ToolCall {
-                tool_name: tool_name.clone(),
-                args: args.clone(),
-            });
+            let shadow = !simulate && self.config.shadow_tools.contains(&tool_name);
+            if !shadow {
+                executed_tool_calls.push(ToolCall {
+                    tool_name: tool_name.clone(),
+                    args: args.clone(),
+                });
+            }
             info!(
                 user_id = %ctx.user_id,
                 guild_id = %ctx.guild_id,
@@ -591,10 +2171,54 @@ impl DefaultChatOrchestrator {
                 planner_source = source,
                 tool_name = %tool_name,
                 args_json = %args,
+                shadow,
                 "tool call selected by unified planner"
             );
 
-            let tool_result = match self.tools.execute(&tool_name, args.clone(), ctx).await {
+            // Simulated calls skip quotas and tool stats so demos neither use up
+            // users' allowances nor raise failure alerts. Shadow calls skip
+            // quotas too, since users never see their output.
+            let tool_result = if simulate {
+                self.simulated_tools
+                    .execute(&tool_name, args.clone(), ctx)
+                    .await
+            } else if shadow {
+                self.execute_tool_with_timeout(&tool_name, args.clone(), ctx, round_started_at)
+                    .await
+            } else {
+                match self.consume_tool_quota(&ctx.user_id, &tool_name).await {
+                    Ok(()) => match &self.background_tasks {
+                        Some(background) if background.handles(&tool_name) => self
+                            .start_background_task(
+                                background,
+                                ctx,
+                                origin,
+                                &tool_name,
+                                args.clone(),
+                            )
+                            .await
+                            .map(|task| task_started_result(&task)),
+                        _ => {
+                            self.execute_tool_with_timeout(
+                                &tool_name,
+                                args.clone(),
+                                ctx,
+                                round_started_at,
+                            )
+                            .await
+                        }
+                    },
+                    Err(error) => Err(error),
+                }
+            };
+            let record_source = if simulate {
+                format!("simulated_{source}")
+            } else if shadow {
+                format!("shadow_{source}")
+            } else {
+                source.to_owned()
+            };
+            let tool_result = match tool_result {
                 Ok(result) => result,
                 Err(error) => {
                     let error_text = error.to_string();
@@ -603,7 +2227,7 @@ impl DefaultChatOrchestrator {
                         guild_id: ctx.guild_id.clone(),
                         channel_id: ctx.channel_id.clone(),
                         tool_name: tool_name.clone(),
-                        source: source.to_owned(),
+                        source: record_source.clone(),
                         args_json: args.to_string(),
                         result_text: String::new(),
                         citations: Vec::new(),
@@ -618,6 +2242,9 @@ impl DefaultChatOrchestrator {
                         duration_ms,
                         success: false,
                     });
+                    if !simulate && error.downcast_ref::<ToolQuotaExceeded>().is_none() {
+                        self.record_tool_stats(&tool_name, false, duration_ms);
+                    }
                     warn!(
                         user_id = %ctx.user_id,
                         guild_id = %ctx.guild_id,
@@ -626,13 +2253,18 @@ impl DefaultChatOrchestrator {
                         tool_name = %tool_name,
                         duration_ms,
                         ?error,
+                        shadow,
                         "tool call failed; continuing orchestration"
                     );
+                    if shadow {
+                        continue;
+                    }
                     tool_outputs.push(ExecutedToolOutput {
                         tool_name,
                         args,
                         success: false,
                         text: error_text,
+                        data: None,
                     });
                     continue;
                 }
@@ -643,7 +2275,7 @@ impl DefaultChatOrchestrator {
                 guild_id: ctx.guild_id.clone(),
                 channel_id: ctx.channel_id.clone(),
                 tool_name: tool_name.clone(),
-                source: source.to_owned(),
+                source: record_source,
                 args_json: args.to_string(),
                 result_text: truncate_for_log(&tool_result.text, 1200),
                 citations: tool_result.citations.clone(),
@@ -659,858 +2291,4075 @@ impl DefaultChatOrchestrator {
                 duration_ms,
                 success: true,
             });
+            if !simulate {
+                self.record_tool_stats(&tool_name, true, duration_ms);
+            }
             info!(
                 user_id = %ctx.user_id,
                 planner_source = source,
                 tool_name = %tool_name,
                 duration_ms,
                 result_citations = tool_result.citations.len(),
+                shadow,
                 "tool call completed"
             );
+            if shadow {
+                info!(
+                    user_id = %ctx.user_id,
+                    tool_name = %tool_name,
+                    result_text = %truncate_for_log(&tool_result.text, 1200),
+                    "shadow tool output withheld from synthesis"
+                );
+                continue;
+            }
 
             citations.extend(tool_result.citations);
+            let budget = self.config.output_budget_for_tool(&tool_name);
+            let text = match truncate_tool_output(&tool_result.text, budget) {
+                Cow::Borrowed(_) => tool_result.text,
+                Cow::Owned(truncated) => {
+                    debug!(
+                        tool_name = %tool_name,
+                        chars = tool_result.text.chars().count(),
+                        budget,
+                        "tool output truncated"
+                    );
+                    truncated
+                }
+            };
             tool_outputs.push(ExecutedToolOutput {
                 tool_name,
                 args,
                 success: true,
-                text: tool_result.text,
+                text,
+                data: tool_result.data,
             });
         }
     }
 
-    async fn record_tool_call(&self, call: ToolCallRecord) {
-        if let Err(error) = self.memory.record_tool_call(call).await {
-            warn!(?error, "failed to persist tool call log");
+    /// The session this turn belongs to: the current one, unless it has been
+    /// idle longer than the configured gap.
+    async fn resolve_session(
+        &self,
+        user_id: &str,
+        at: chrono::DateTime<Utc>,
+    ) -> anyhow::Result<ChatSession> {
+        match self.memory.current_chat_session(user_id).await? {
+            Some(session) if !session_expired(&session, at, self.config.session_gap) => {
+                self.memory
+                    .touch_chat_session(user_id, &session.id, at)
+                    .await?;
+                Ok(session)
+            }
+            _ => self.memory.start_chat_session(user_id, at).await,
         }
     }
 
-    async fn record_unified_planner_decision(
+    /// The session's latest turns in this channel, formatted like
+    /// [`MemoryContext::recent_messages`](crate::types::MemoryContext).
+    async fn session_context(
         &self,
         ctx: &MessageCtx,
-        decision: &UnifiedPlanDecision,
-    ) {
-        let (decision_value, rationale, payload, success, error) = match decision {
-            UnifiedPlanDecision::UsePlan {
-                rationale, payload, ..
-            } => ("apply_plan", rationale.clone(), payload.clone(), true, None),
-            UnifiedPlanDecision::Fallback { reason, error } => (
-                "fallback_no_tools",
-                (*reason).to_owned(),
-                json!({}),
-                false,
-                error.clone(),
-            ),
-        };
+        session_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<String>> {
+        let messages = self
+            .memory
+            .list_session_messages(&ctx.user_id, session_id, SESSION_CONTEXT_SCAN_LIMIT)
+            .await?;
+        let mut recent = messages
+            .iter()
+            .rev()
+            .filter(|message| {
+                message.guild_id == ctx.guild_id && message.channel_id == ctx.channel_id
+            })
+            .take(limit)
+            .map(|message| {
+                format!(
+                    "{}: {}",
+                    context_speaker(message.role, message.modality),
+                    message.content
+                )
+            })
+            .collect::<Vec<_>>();
+        recent.reverse();
+        Ok(recent)
+    }
 
-        self.record_planner_decision(
-            ctx,
-            "unified",
-            decision_value,
-            rationale,
-            payload,
-            success,
-            error,
-        )
-        .await;
+    /// Titles an untitled session in the background from its first turn; a
+    /// failed attempt is retried after the session's next turn.
+    fn spawn_session_title(&self, session: &ChatSession, user_message: &str, reply: &str) {
+        let model = self.model.clone();
+        let memory = IntoDynMemoryStore::into_dyn(self.memory.clone());
+        let request = session_title_request(user_message, reply);
+        let user_id = session.user_id.clone();
+        let session_id = session.id.clone();
+        tokio::spawn(async move {
+            let title = match model.complete(request).await {
+                Ok(raw) => clean_session_title(&raw),
+                Err(error) => {
+                    debug!(?error, %user_id, %session_id, "session title generation failed");
+                    None
+                }
+            };
+            let Some(title) = title else {
+                return;
+            };
+            if let Err(error) = memory
+                .set_chat_session_title(&user_id, &session_id, &title)
+                .await
+            {
+                warn!(?error, %user_id, %session_id, "failed to store session title");
+            }
+        });
     }
 
-    async fn record_tool_followup_decision(
+    /// The handoff note for this turn. When the user's model differs from
+    /// the one their last reply in this channel used, the channel's open
+    /// threads are summarized first and the note is kept for the next
+    /// `model_handoff_turns` turns there. Failures only cost the note.
+    async fn model_handoff(
         &self,
         ctx: &MessageCtx,
-        round: usize,
-        decision: &ToolFollowupDecision,
-    ) {
-        let (decision_value, rationale, payload, success, error) = match decision {
-            ToolFollowupDecision::Final {
-                rationale, payload, ..
-            } => (
-                "final_answer",
-                rationale.clone(),
-                payload.clone(),
-                true,
-                None,
-            ),
-            ToolFollowupDecision::UseTools {
-                rationale, payload, ..
-            } => (
-                "request_tools",
-                rationale.clone(),
-                payload.clone(),
-                true,
-                None,
-            ),
-            ToolFollowupDecision::Fallback { reason, error } => (
-                "fallback_no_tools",
-                (*reason).to_owned(),
-                json!({}),
-                false,
-                error.clone(),
-            ),
+        model: &str,
+        params: GenerationParams,
+    ) -> Option<String> {
+        if self.config.model_handoff_turns == 0 {
+            return None;
+        }
+        let user_id = ctx.user_id.as_str();
+        let previous = match self
+            .memory
+            .get_model_handoff(user_id, &ctx.guild_id, &ctx.channel_id)
+            .await
+        {
+            Ok(previous) => previous,
+            Err(error) => {
+                warn!(?error, user_id, "failed to load model handoff");
+                return None;
+            }
         };
-
-        self.record_planner_decision(
-            ctx,
-            "tool_followup",
-            decision_value,
-            rationale,
-            json!({
-                "round": round,
-                "decision": payload
-            }),
-            success,
-            error,
-        )
-        .await;
+        let mut handoff = match previous {
+            Some(handoff) if handoff.model == model => {
+                if handoff.remaining_turns == 0 {
+                    return None;
+                }
+                handoff
+            }
+            Some(previous) => {
+                let summary = self.summarize_for_handoff(ctx, params).await;
+                info!(
+                    user_id,
+                    from = %previous.model,
+                    to = model,
+                    summarized = summary.is_some(),
+                    "reply model switched; handing off"
+                );
+                ModelHandoff {
+                    remaining_turns: if summary.is_some() {
+                        self.config.model_handoff_turns
+                    } else {
+                        0
+                    },
+                    summary,
+                    ..ModelHandoff::new(user_id, &ctx.guild_id, &ctx.channel_id, model)
+                }
+            }
+            // The user's first reply; there is nothing to hand off.
+            None => ModelHandoff::new(user_id, &ctx.guild_id, &ctx.channel_id, model),
+        };
+        let summary = handoff.take_turn();
+        if let Err(error) = self.memory.put_model_handoff(handoff).await {
+            warn!(?error, user_id, "failed to store model handoff");
+        }
+        summary
     }
 
-    async fn record_planner_decision(
+    async fn summarize_for_handoff(
         &self,
         ctx: &MessageCtx,
-        planner: &str,
-        decision: &str,
-        rationale: String,
-        payload: Value,
-        success: bool,
-        error: Option<String>,
-    ) {
-        let record = PlannerDecisionRecord {
-            user_id: ctx.user_id.clone(),
-            guild_id: ctx.guild_id.clone(),
-            channel_id: ctx.channel_id.clone(),
-            planner: planner.to_owned(),
-            decision: decision.to_owned(),
-            rationale,
-            payload_json: payload.to_string(),
-            success,
-            error,
-            timestamp: Utc::now(),
+        params: GenerationParams,
+    ) -> Option<String> {
+        let user_id = ctx.user_id.as_str();
+        let history = match self
+            .memory
+            .list_channel_messages(user_id, &ctx.guild_id, &ctx.channel_id, HANDOFF_SCAN_LIMIT)
+            .await
+        {
+            Ok(history) if !history.is_empty() => history,
+            Ok(_) => return None,
+            Err(error) => {
+                warn!(?error, user_id, "failed to load history for model handoff");
+                return None;
+            }
         };
-
-        if let Err(store_error) = self.memory.record_planner_decision(record).await {
-            warn!(
-                ?store_error,
-                planner, "failed to persist planner decision log"
-            );
+        match self.model.complete(handoff_request(&history, params)).await {
+            Ok(raw) => parse_handoff_summary(&raw),
+            Err(error) => {
+                warn!(?error, user_id, "failed to summarize for model handoff");
+                None
+            }
         }
     }
-}
 
-#[async_trait]
-impl VoiceReplyOrchestrator for DefaultChatOrchestrator {
-    async fn handle_voice_transcript(&self, message: MessageCtx) -> anyhow::Result<String> {
-        let reply = self.handle_message(message).await?;
-        Ok(reply.text)
+    /// What the user is listening to, for users with linked Spotify playback.
+    /// Lookups are cached per user; failures only drop the enrichment.
+    /// Simulated turns bypass the cache so scripted playback never leaks into
+    /// real turns.
+    async fn load_now_playing(&self, ctx: &MessageCtx, simulate: bool) -> Option<String> {
+        if !self
+            .config
+            .now_playing_users
+            .iter()
+            .any(|user_id| user_id == &ctx.user_id)
+        {
+            return None;
+        }
+        if !simulate && let Some(now_playing) = self.now_playing.get(&ctx.user_id) {
+            return now_playing;
+        }
+
+        const TOOL_NAME: &str = "spotify_playing_status";
+        let lookup = async {
+            if simulate {
+                self.simulated_tools
+                    .execute(TOOL_NAME, json!({}), ctx)
+                    .await
+            } else {
+                self.tools.execute(TOOL_NAME, json!({}), ctx).await
+            }
+        };
+        let now_playing =
+            match tokio::time::timeout(self.config.timeout_for_tool(TOOL_NAME), lookup).await {
+                Ok(Ok(result)) => now_playing_summary(&result.text),
+                Ok(Err(error)) => {
+                    debug!(?error, user_id = %ctx.user_id, "now playing lookup failed");
+                    None
+                }
+                Err(_) => {
+                    debug!(user_id = %ctx.user_id, "now playing lookup timed out");
+                    None
+                }
+            };
+        if !simulate {
+            self.now_playing.insert(&ctx.user_id, now_playing.clone());
+        }
+        now_playing
     }
-}
 
-fn build_unified_planner_prompt(memory: &crate::types::MemoryContext) -> String {
-    let context_block = build_planner_context_block(memory);
+    /// Today's quota usage shown to the follow-up planner; empty when no
+    /// quotas are configured or usage cannot be read.
+    async fn remaining_tool_quotas(&self, user_id: &str) -> Vec<ToolQuotaStatus> {
+        if self.config.tool_quotas.is_empty() {
+            return Vec::new();
+        }
+        self.tool_quota_status(user_id)
+            .await
+            .unwrap_or_else(|error| {
+                warn!(
+                    ?error,
+                    user_id, "failed to read tool quotas for the planner"
+                );
+                Vec::new()
+            })
+    }
 
-    format!(
-        "You are the unified planner for CompanionPilot.
-Decide both tool usage and memory write for one user message.
-Return strict JSON only (no markdown, no prose) with this exact schema:
-{{
-  \"tool_calls\": [{{\"tool_name\":\"...\",\"args\":{{...}}}}],
-  \"memory\": {{
-    \"store\": true|false,
-    \"key\": \"...\",
-    \"value\": \"...\",
-    \"confidence\": 0.0-1.0
-  }},
-  \"rationale\": \"short reason\"
-}}
-Tool calls are executed sequentially in listed order.
-There are no manual commands or manual overrides: all tool usage must come from this decision.
-If no tool is needed, return an empty tool_calls array.
-If memory should not be stored, set store=false and key/value to empty strings.
-Store only durable personal facts (identity, preferences, recurring goals, corrections).
-Do not store one-off requests or transient states.
-Use web search for latest/current/news/prices/weather or unknown factual claims.
-For time-sensitive requests, call current_datetime before web_search so queries and answers are anchored to real current time.
-If current_datetime is needed, request only current_datetime in this decision and wait for its output before planning web_search.
-Tool inventory:
-{}
-{}",
-        build_tool_inventory_for_planner(),
-        context_block
-    )
-}
-
-fn build_tool_followup_prompt(memory: &crate::types::MemoryContext) -> String {
-    let context_block = build_planner_context_block(memory);
-
-    format!(
-        "You are the tool follow-up planner for CompanionPilot.
-Decide whether the current evidence is enough for a final user-facing answer, or whether more tool calls are needed.
-Return strict JSON only (no markdown, no prose) with this exact schema:
-{{
-  \"action\": \"final\"|\"tools\",
-  \"final_answer\": \"non-empty only when action=final\",
-  \"tool_calls\": [{{\"tool_name\":\"...\",\"args\":{{...}}}}],
-  \"rationale\": \"short reason\"
-}}
-If action=final, provide the complete final answer and return an empty tool_calls array.
-If action=tools, final_answer must be empty and tool_calls must contain at least one valid call.
-Only request tools when the current outputs are insufficient or conflicting.
-For time-sensitive requests, prefer calling current_datetime before additional web_search calls.
-If current_datetime is needed, call it alone first, then plan web_search in a later tool round.
-Tool inventory:
-{}
-{}",
-        build_tool_inventory_for_planner(),
-        context_block
-    )
-}
-
-fn build_planner_context_block(memory: &crate::types::MemoryContext) -> String {
-    let mut context_lines = Vec::new();
-    if let Some(summary) = &memory.summary {
-        context_lines.push(format!("Conversation summary: {summary}"));
+    /// Counts one call against the user's daily quota for `tool_name`. Quota
+    /// storage failures let the call through.
+    async fn consume_tool_quota(&self, user_id: &str, tool_name: &str) -> anyhow::Result<()> {
+        let Some(&limit) = self.config.tool_quotas.get(tool_name) else {
+            return Ok(());
+        };
+        match self
+            .memory
+            .try_increment_tool_usage(user_id, tool_name, quota_day(Utc::now()), limit)
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ToolQuotaExceeded {
+                tool_name: tool_name.to_owned(),
+                limit,
+            }
+            .into()),
+            Err(error) => {
+                warn!(
+                    ?error,
+                    user_id, tool_name, "failed to count tool quota; allowing call"
+                );
+                Ok(())
+            }
+        }
     }
 
-    if !memory.facts.is_empty() {
-        let facts = memory
-            .facts
-            .iter()
-            .map(|fact| format!("{}={}", fact.key, fact.value))
-            .collect::<Vec<_>>()
-            .join("; ");
-        context_lines.push(format!("Known user facts: {facts}"));
+    fn alert(&self, kind: &'static str, key: &str, message: String) {
+        if let Some(admin_alerts) = &self.admin_alerts {
+            admin_alerts.send_keyed(kind, key, message);
+        }
     }
 
-    if !memory.recent_messages.is_empty() {
-        context_lines.push(build_recent_context_block(&memory.recent_messages));
+    fn track_slow_reply(&self) {
+        let now = Instant::now();
+        let mut slow_replies = self
+            .slow_replies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        slow_replies.retain(|at| now.duration_since(*at) < SLOW_REPLY_SPIKE_WINDOW);
+        slow_replies.push_back(now);
+        if slow_replies.len() >= SLOW_REPLY_SPIKE_COUNT {
+            self.alert(
+                "slow_reply_spike",
+                "replies",
+                format!(
+                    "{} replies took over {} s in the last {} minutes",
+                    slow_replies.len(),
+                    SLOW_REPLY_THRESHOLD_MS / 1000,
+                    SLOW_REPLY_SPIKE_WINDOW.as_secs() / 60
+                ),
+            );
+        }
     }
 
-    if context_lines.is_empty() {
-        String::new()
-    } else {
-        format!("Context:\n{}\n", context_lines.join("\n"))
+    fn record_tool_stats(&self, tool_name: &str, success: bool, duration_ms: u64) {
+        let Some(alert) = self.tool_stats.record(tool_name, success, duration_ms) else {
+            return;
+        };
+        warn!(
+            tool_name,
+            failure_rate = alert.failure_rate,
+            window_calls = alert.window_calls,
+            "tool failure rate crossed alert threshold"
+        );
+        self.alert("tool_failure_rate", tool_name, alert.to_string());
     }
-}
-
-fn build_tool_inventory_for_planner() -> &'static str {
-    r#"[
-  {
-    "tool_name": "current_datetime",
-    "args_schema": {},
-    "when_to_use": "Need the exact current date/time before time-sensitive lookups or answers.",
-    "when_not_to_use": "Question is timeless or explicitly historical."
-  },
-  {
-    "tool_name": "spotify_playing_status",
-    "args_schema": {},
-    "when_to_use": "Need the user's currently playing Spotify track/status.",
-    "when_not_to_use": "Question is unrelated to Spotify playback."
-  },
-  {
-    "tool_name": "web_search",
-    "args_schema": {
-      "query": "string (required, non-empty)",
-      "max_results": "integer 1-10 (optional, default 5)"
-    },
-    "when_to_use": "Need external factual information, latest/current info, or web-sourced recommendations.",
-    "when_not_to_use": "Casual chat, personal memory recall, or when the answer can be provided from context."
-  },
-  {
-    "tool_name": "discord_voice_join",
-    "args_schema": {
-      "channel_id": "string Discord channel id (optional; defaults to requester's current voice channel)"
-    },
-    "when_to_use": "User explicitly asks the assistant to join voice.",
-    "when_not_to_use": "User did not request voice channel participation."
-  },
-  {
-    "tool_name": "discord_voice_listen_turn",
-    "args_schema": {
-      "listen_window_ms": "integer 1000-60000 (optional, default 12000)",
-      "chunk_gap_ms": "integer 100-3000 (optional, default 700)",
-      "max_turn_ms": "integer >= chunk_gap_ms (optional, default 12000)"
-    },
-    "when_to_use": "Bot is already in voice and user requests a listen/respond voice turn.",
-    "when_not_to_use": "Bot is not in voice or user requested text-only response."
-  },
-  {
-    "tool_name": "discord_voice_leave",
-    "args_schema": {},
-    "when_to_use": "User explicitly asks assistant to leave voice or stop voice interaction.",
-    "when_not_to_use": "Bot is not connected to voice."
-  }
-]"#
-}
-
-fn parse_unified_plan(raw: &str) -> Result<UnifiedPlan, serde_json::Error> {
-    parse_json_plan(raw)
-}
-
-fn parse_tool_followup_plan(raw: &str) -> Result<ToolFollowupPlan, serde_json::Error> {
-    parse_json_plan(raw)
-}
-
-fn sanitize_planned_tool_calls(planned_calls: Vec<PlannedToolCall>) -> Vec<ToolCall> {
-    let mut sanitized_calls = Vec::new();
 
-    for planned_call in planned_calls {
-        if sanitized_calls.len() >= MAX_PLANNED_TOOL_CALLS {
-            break;
-        }
-        match planned_call.tool_name.as_str() {
-            "current_datetime" => {
-                sanitized_calls.push(ToolCall {
-                    tool_name: "current_datetime".to_owned(),
-                    args: json!({}),
-                });
-            }
-            "spotify_playing_status" => {
-                sanitized_calls.push(ToolCall {
-                    tool_name: "spotify_playing_status".to_owned(),
-                    args: json!({}),
-                });
+    async fn execute_tool_with_timeout(
+        &self,
+        tool_name: &str,
+        args: Value,
+        ctx: &MessageCtx,
+        round_started_at: Instant,
+    ) -> anyhow::Result<ToolResult> {
+        let mut timeout = self.config.timeout_for_tool(tool_name);
+        if let Some(budget) = self.config.tool_round_budget {
+            let remaining = budget.saturating_sub(round_started_at.elapsed());
+            if remaining.is_zero() {
+                return Err(anyhow::anyhow!(
+                    "skipped: tool round time budget of {} ms was exhausted",
+                    budget.as_millis()
+                ));
             }
-            "web_search" => {
-                let query = planned_call
-                    .args
-                    .get("query")
-                    .and_then(Value::as_str)
-                    .map(str::trim)
-                    .unwrap_or("");
-                if query.is_empty() {
-                    debug!("dropping planner web_search call with empty query");
-                    continue;
-                }
+            timeout = timeout.min(remaining);
+        }
 
-                let max_results = planned_call
-                    .args
-                    .get("max_results")
-                    .and_then(Value::as_u64)
-                    .unwrap_or(5)
-                    .clamp(1, 10);
-
-                sanitized_calls.push(ToolCall {
-                    tool_name: "web_search".to_owned(),
-                    args: json!({
-                        "query": query,
-                        "max_results": max_results
-                    }),
-                });
-            }
-            "discord_voice_join" => {
-                let channel_id = planned_call
-                    .args
-                    .get("channel_id")
-                    .and_then(Value::as_str)
-                    .map(str::trim)
-                    .filter(|value| !value.is_empty());
-                let args = match channel_id {
-                    Some(channel_id) => json!({ "channel_id": channel_id }),
-                    None => json!({}),
-                };
-                sanitized_calls.push(ToolCall {
-                    tool_name: "discord_voice_join".to_owned(),
-                    args,
-                });
-            }
-            "discord_voice_listen_turn" => {
-                let listen_window_ms = planned_call
-                    .args
-                    .get("listen_window_ms")
-                    .and_then(Value::as_u64)
-                    .unwrap_or(12_000)
-                    .clamp(1_000, 60_000);
-                let chunk_gap_ms = planned_call
-                    .args
-                    .get("chunk_gap_ms")
-                    .and_then(Value::as_u64)
-                    .unwrap_or(700)
-                    .clamp(100, 3_000);
-                let max_turn_ms = planned_call
-                    .args
-                    .get("max_turn_ms")
-                    .and_then(Value::as_u64)
-                    .unwrap_or(12_000)
-                    .max(chunk_gap_ms);
-
-                sanitized_calls.push(ToolCall {
-                    tool_name: "discord_voice_listen_turn".to_owned(),
-                    args: json!({
-                        "listen_window_ms": listen_window_ms,
-                        "chunk_gap_ms": chunk_gap_ms,
-                        "max_turn_ms": max_turn_ms
-                    }),
-                });
-            }
-            "discord_voice_leave" => {
-                sanitized_calls.push(ToolCall {
-                    tool_name: "discord_voice_leave".to_owned(),
-                    args: json!({}),
-                });
-            }
-            other => {
-                debug!(tool_name = other, "dropping unknown planner tool call");
-            }
+        match tokio::time::timeout(timeout, self.tools.execute(tool_name, args, ctx)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!(
+                "{tool_name} timed out after {} ms",
+                timeout.as_millis()
+            )),
         }
     }
 
-    sanitized_calls
-}
-
-fn enforce_datetime_planning_boundary(tool_calls: Vec<ToolCall>) -> Vec<ToolCall> {
-    let has_datetime = tool_calls
-        .iter()
-        .any(|call| call.tool_name == "current_datetime");
-    let has_non_datetime = tool_calls
-        .iter()
-        .any(|call| call.tool_name != "current_datetime");
-    if !has_datetime || !has_non_datetime {
-        return tool_calls;
+    /// Stores a fact the planner (or an outside source) decided to keep:
+    /// merged with what is already known, then queued for approval when the
+    /// queue is on. Returns the fact as written.
+    async fn write_memory_fact(
+        &self,
+        ctx: &MessageCtx,
+        fact: MemoryFact,
+        rationale: &str,
+    ) -> anyhow::Result<MemoryFact> {
+        let fact = self.resolve_fact_write(ctx, fact).await;
+        if self.config.fact_approval_queue {
+            let pending_id = self
+                .memory
+                .queue_pending_fact(PendingFactRecord {
+                    id: String::new(),
+                    user_id: ctx.user_id.clone(),
+                    guild_id: ctx.guild_id.clone(),
+                    channel_id: ctx.channel_id.clone(),
+                    fact: fact.clone(),
+                    rationale: rationale.to_owned(),
+                    created_at: Utc::now(),
+                })
+                .await?;
+            info!(
+                user_id = %ctx.user_id,
+                memory_key = %fact.key,
+                pending_id = %pending_id,
+                rationale,
+                "memory fact queued for approval"
+            );
+        } else {
+            info!(
+                user_id = %ctx.user_id,
+                memory_key = %fact.key,
+                confidence = fact.confidence,
+                rationale,
+                "memory fact stored"
+            );
+            self.memory.upsert_fact(&ctx.user_id, fact.clone()).await?;
+        }
+        Ok(fact)
     }
 
-    debug!(
-        total_calls = tool_calls.len(),
-        "deferring non-datetime tools to follow-up round because current_datetime was requested"
-    );
-    let datetime_call = tool_calls
-        .into_iter()
-        .find(|call| call.tool_name == "current_datetime")
-        .expect("checked current_datetime presence above");
-    vec![datetime_call]
-}
+    /// Folds a planner-proposed fact into the stored facts: near-duplicates are
+    /// merged into their canonical key and re-observed values are reinforced.
+    async fn resolve_fact_write(&self, ctx: &MessageCtx, fact: MemoryFact) -> MemoryFact {
+        let existing = match self
+            .memory
+            .list_facts(&ctx.user_id, FACT_WRITE_CANDIDATE_LIMIT)
+            .await
+        {
+            Ok(existing) => existing,
+            Err(error) => {
+                warn!(?error, "failed to load existing facts before memory write");
+                return fact;
+            }
+        };
 
-fn memory_decision_from_plan(plan: PlannedMemory) -> MemoryDecision {
-    if !plan.store {
-        return MemoryDecision::Skip {
-            reason: "planner_no_store",
+        let fact = match self.config.fact_dedup_threshold {
+            Some(threshold) => match find_near_duplicate(&existing, &fact, threshold) {
+                Some(canonical) => self.merge_near_duplicate_fact(ctx, canonical, fact).await,
+                None => fact,
+            },
+            None => fact,
         };
+
+        match existing.iter().find(|stored| stored.key == fact.key) {
+            Some(stored) => reinforce_fact(stored, fact, Utc::now()),
+            None => fact,
+        }
     }
 
-    let key = sanitize_memory_key(&plan.key);
-    let value = clean_memory_value(&plan.value);
-    if key.is_empty() || value.is_empty() {
-        return MemoryDecision::Skip {
-            reason: "planner_invalid_fact",
-        };
+    async fn merge_near_duplicate_fact(
+        &self,
+        ctx: &MessageCtx,
+        canonical: &MemoryFact,
+        fact: MemoryFact,
+    ) -> MemoryFact {
+        info!(
+            user_id = %ctx.user_id,
+            proposed_key = %fact.key,
+            canonical_key = %canonical.key,
+            "merging near-duplicate memory fact into canonical key"
+        );
+        self.record_planner_decision(
+            ctx,
+            "memory_dedup",
+            "merge_fact",
+            "near_duplicate_fact".to_owned(),
+            json!({
+                "proposed_key": fact.key,
+                "canonical_key": canonical.key,
+                "previous_value": canonical.value,
+                "value": fact.value
+            }),
+            true,
+            None,
+        )
+        .await;
+
+        MemoryFact {
+            key: canonical.key.clone(),
+            ..fact
+        }
     }
 
-    MemoryDecision::Store {
-        fact: MemoryFact {
-            key,
-            value,
-            confidence: plan.confidence.clamp(0.0, 1.0),
-            source: "user_message".to_owned(),
-            updated_at: Utc::now(),
-        },
-        rationale: "model_planner",
+    async fn record_rejected_tool_calls(
+        &self,
+        ctx: &MessageCtx,
+        rejected_tool_calls: Vec<RejectedToolCall>,
+        source: &'static str,
+        tool_outputs: &mut Vec<ExecutedToolOutput>,
+    ) {
+        for rejected in rejected_tool_calls {
+            let error_text = rejected.error_text();
+            warn!(
+                user_id = %ctx.user_id,
+                planner_source = source,
+                tool_name = %rejected.tool_name,
+                error = %error_text,
+                "planned tool call rejected by argument validation"
+            );
+            self.record_tool_call(ToolCallRecord {
+                user_id: ctx.user_id.clone(),
+                guild_id: ctx.guild_id.clone(),
+                channel_id: ctx.channel_id.clone(),
+                tool_name: rejected.tool_name.clone(),
+                source: source.to_owned(),
+                args_json: rejected.args.to_string(),
+                result_text: String::new(),
+                citations: Vec::new(),
+                success: false,
+                error: Some(error_text.clone()),
+                timestamp: Utc::now(),
+            })
+            .await;
+            tool_outputs.push(ExecutedToolOutput {
+                tool_name: rejected.tool_name,
+                args: rejected.args,
+                success: false,
+                text: error_text,
+                data: None,
+            });
+        }
     }
-}
 
-fn memory_payload(memory: &MemoryDecision) -> Value {
-    match memory {
-        MemoryDecision::Store { fact, .. } => json!({
-            "store": true,
-            "key": fact.key,
-            "value": fact.value,
-            "confidence": fact.confidence
-        }),
-        MemoryDecision::Skip { reason } => json!({
-            "store": false,
-            "reason": reason
-        }),
+    async fn record_tool_call(&self, mut call: ToolCallRecord) {
+        call.result_text = self.redacted(call.result_text);
+        if let Err(error) = self.memory.record_tool_call(call).await {
+            warn!(?error, "failed to persist tool call log");
+            self.alert("database_error", "tool_call_log", error.to_string());
+        }
     }
-}
 
-fn truncate_for_log(input: &str, max_len: usize) -> String {
-    let mut result = input.replace('\n', "\\n");
-    if result.len() > max_len {
-        result.truncate(max_len);
-        result.push_str("...");
+    async fn record_thoughts(&self, ctx: &MessageCtx, thoughts: Vec<(&'static str, String)>) {
+        for (source, text) in thoughts {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let thought = ThoughtRecord {
+                user_id: ctx.user_id.clone(),
+                guild_id: ctx.guild_id.clone(),
+                channel_id: ctx.channel_id.clone(),
+                message_id: ctx.message_id.clone(),
+                source: source.to_owned(),
+                text,
+                timestamp: Utc::now(),
+            };
+            if let Err(error) = self.memory.record_thought(thought).await {
+                warn!(?error, "failed to persist thought log");
+                self.alert("database_error", "thought_log", error.to_string());
+            }
+        }
     }
-    result
-}
 
-fn elapsed_ms(started_at: Instant) -> u64 {
-    started_at
-        .elapsed()
-        .as_millis()
-        .try_into()
-        .unwrap_or(u64::MAX)
-}
+    async fn record_unified_planner_decision(
+        &self,
+        ctx: &MessageCtx,
+        decision: &UnifiedPlanDecision,
+        flags: &TurnFlags,
+    ) {
+        let (decision_value, rationale, mut payload, success, error) =
+            unified_decision_fields(&ctx.content, decision);
+        if !flags.is_empty()
+            && let Some(payload) = payload.as_object_mut()
+        {
+            payload.insert("flags".to_owned(), json!(flags.to_string()));
+        }
 
-fn parse_json_plan<T: DeserializeOwned>(raw: &str) -> Result<T, serde_json::Error> {
-    let candidate = raw
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
+        self.record_planner_decision(
+            ctx,
+            "unified",
+            decision_value,
+            rationale,
+            payload,
+            success,
+            error,
+        )
+        .await;
+    }
 
-    match serde_json::from_str::<T>(candidate) {
-        Ok(plan) => Ok(plan),
-        Err(original_error) => {
-            if let Some(object_candidate) = extract_first_json_object(candidate) {
-                serde_json::from_str::<T>(object_candidate).map_err(|_| original_error)
-            } else {
-                Err(original_error)
-            }
-        }
+    async fn record_tool_followup_decision(
+        &self,
+        ctx: &MessageCtx,
+        round: usize,
+        decision: &ToolFollowupDecision,
+    ) {
+        let (decision_value, rationale, payload, success, error) = match decision {
+            ToolFollowupDecision::Final {
+                rationale, payload, ..
+            } => (
+                "final_answer",
+                rationale.clone(),
+                payload.clone(),
+                true,
+                None,
+            ),
+            ToolFollowupDecision::UseTools {
+                rationale, payload, ..
+            } => (
+                "request_tools",
+                rationale.clone(),
+                payload.clone(),
+                true,
+                None,
+            ),
+            ToolFollowupDecision::Fallback { reason, error } => (
+                "fallback_no_tools",
+                (*reason).to_owned(),
+                json!({}),
+                false,
+                error.clone(),
+            ),
+        };
+
+        self.record_planner_decision(
+            ctx,
+            "tool_followup",
+            decision_value,
+            rationale,
+            json!({
+                "round": round,
+                "decision": payload
+            }),
+            success,
+            error,
+        )
+        .await;
     }
-}
 
-fn extract_first_json_object(raw: &str) -> Option<&str> {
-    let mut start_index: Option<usize> = None;
-    let mut depth = 0usize;
-    let mut in_string = false;
-    let mut is_escaped = false;
+    #[allow(clippy::too_many_arguments)]
+    async fn record_planner_decision(
+        &self,
+        ctx: &MessageCtx,
+        planner: &str,
+        decision: &str,
+        rationale: String,
+        payload: Value,
+        success: bool,
+        error: Option<String>,
+    ) {
+        let record = PlannerDecisionRecord {
+            id: String::new(),
+            user_id: ctx.user_id.clone(),
+            guild_id: ctx.guild_id.clone(),
+            channel_id: ctx.channel_id.clone(),
+            planner: planner.to_owned(),
+            decision: decision.to_owned(),
+            rationale: self.redacted(rationale),
+            payload_json: self.redacted(payload.to_string()),
+            success,
+            error,
+            timestamp: Utc::now(),
+        };
 
-    for (index, character) in raw.char_indices() {
-        if start_index.is_none() {
-            if character == '{' {
-                start_index = Some(index);
-                depth = 1;
-            }
-            continue;
+        if let Err(store_error) = self.memory.record_planner_decision(record).await {
+            warn!(
+                ?store_error,
+                planner, "failed to persist planner decision log"
+            );
+            self.alert(
+                "database_error",
+                "planner_decision_log",
+                store_error.to_string(),
+            );
         }
+    }
+}
 
-        if in_string {
-            if is_escaped {
-                is_escaped = false;
-                continue;
-            }
-            match character {
-                '\\' => is_escaped = true,
-                '"' => in_string = false,
-                _ => {}
-            }
-            continue;
+#[async_trait]
+impl<M, S, T> ChatOrchestrator for GenericChatOrchestrator<M, S, T>
+where
+    M: ModelProvider + ?Sized + 'static,
+    S: IntoDynMemoryStore + ?Sized + 'static,
+    T: ToolExecutor + ?Sized + 'static,
+{
+    async fn handle_message_with_options(
+        &self,
+        ctx: MessageCtx,
+        options: TurnOptions,
+    ) -> anyhow::Result<OrchestratorReply> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.check(&ctx.user_id).inspect_err(|limited| {
+                warn!(
+                    user_id = %ctx.user_id,
+                    retry_after_ms = limited.retry_after.as_millis() as u64,
+                    "message rejected: user rate limit reached"
+                );
+            })?;
         }
-
-        match character {
-            '"' => in_string = true,
-            '{' => depth += 1,
-            '}' => {
-                depth = depth.saturating_sub(1);
-                if depth == 0 {
-                    let start = start_index?;
-                    return Some(&raw[start..=index]);
-                }
+        let _activity = self.activity.begin_turn(&ctx.user_id);
+        let (ctx, _turn) = match self.sequencer.enter(ctx).await {
+            ConversationTurn::Run { message, guard } => (*message, guard),
+            ConversationTurn::Merged { merged_into } => {
+                debug!(%merged_into, "message merged into a running conversation turn");
+                return Ok(OrchestratorReply {
+                    text: String::new(),
+                    citations: Vec::new(),
+                    tool_calls: Vec::new(),
+                    safety_flags: Vec::new(),
+                    timings: ReplyTimings::default(),
+                    merged_into: Some(merged_into),
+                    json: None,
+                });
             }
-            _ => {}
+        };
+        let _shared_turn = match &self.coordinator {
+            Some(coordinator) => {
+                let key = format!("conversation:{}:{}", ctx.user_id, ctx.channel_id);
+                coordination::lock(coordinator, &key, self.config.conversation_lock_ttl)
+                    .await
+                    .inspect_err(|_| {
+                        warn!(
+                            user_id = %ctx.user_id,
+                            channel_id = %ctx.channel_id,
+                            "orchestration rejected: conversation locked by another instance"
+                        );
+                    })?
+            }
+            None => None,
+        };
+        let _permit = match &self.limiter {
+            Some(limiter) => Some(limiter.acquire().await.inspect_err(|_| {
+                warn!(
+                    user_id = %ctx.user_id,
+                    channel_id = %ctx.channel_id,
+                    "orchestration rejected: concurrency limit and queue are full"
+                );
+            })?),
+            None => None,
+        };
+        let _in_flight = if self.config.in_flight_tracking && options.track_in_flight {
+            let memory = IntoDynMemoryStore::into_dyn(self.memory.clone());
+            let content = self.redacted(ctx.content.clone());
+            Some(InFlightGuard::start(memory, &ctx, content).await)
+        } else {
+            None
+        };
+        let reply = self.run_turn(ctx, options).await;
+        #[cfg(feature = "postgres")]
+        if let Err(error) = &reply
+            && let Some(db_error) = error.downcast_ref::<sqlx::Error>()
+        {
+            self.alert("database_error", "orchestration", db_error.to_string());
         }
+        reply
     }
 
-    None
-}
+    async fn replay_planner_decision(
+        &self,
+        decision_id: &str,
+    ) -> anyhow::Result<Option<PlannerReplay>> {
+        let Some(record) = self.memory.get_planner_decision(decision_id).await? else {
+            return Ok(None);
+        };
+        if record.planner != "unified" {
+            return Err(PlannerReplayError::UnsupportedPlanner(record.planner).into());
+        }
+        let payload = serde_json::from_str::<Value>(&record.payload_json).unwrap_or_default();
+        let Some(user_input) = payload["user_input"].as_str().map(ToOwned::to_owned) else {
+            return Err(PlannerReplayError::MissingInput.into());
+        };
 
-fn format_tool_outputs(outputs: &[ExecutedToolOutput]) -> String {
-    outputs
-        .iter()
-        .enumerate()
-        .map(|(index, output)| {
-            let (status, label) = if output.success {
-                ("success", "Output")
-            } else {
-                ("error", "Error")
-            };
-            format!(
-                "{}. Tool: {}\nArgs: {}\nStatus: {}\n{}:\n{}",
-                index + 1,
-                output.tool_name,
-                output.args,
-                status,
-                label,
-                output.text
+        let ctx = MessageCtx {
+            message_id: format!("replay-{decision_id}"),
+            user_id: record.user_id.clone(),
+            guild_id: record.guild_id.clone(),
+            channel_id: record.channel_id.clone(),
+            content: user_input.clone(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        let limits = self
+            .config
+            .context_windows
+            .for_surface(ContextSurface::of(&ctx.guild_id, Modality::Text));
+        let mut memory_context = self
+            .memory
+            .load_context(&ctx.user_id, &ctx.guild_id, &ctx.channel_id, limits)
+            .await?;
+        rank_facts_by_confidence(
+            &mut memory_context.facts,
+            Utc::now(),
+            self.config.fact_decay_half_life_days,
+        );
+        // The replayed message must not serve as its own few-shot example.
+        let mut examples = self
+            .load_planner_examples(&ctx, self.config.planner_examples)
+            .await;
+        examples.retain(|example| !example.user_input.eq_ignore_ascii_case(user_input.trim()));
+        let decision = self
+            .decide_unified_plan(
+                &user_input,
+                &memory_context,
+                &examples,
+                &GenerationParams::default(),
             )
-        })
-        .collect::<Vec<_>>()
-        .join("\n\n")
-}
+            .await;
 
-fn fallback_tool_output_text(outputs: &[ExecutedToolOutput]) -> String {
-    outputs
-        .iter()
-        .map(|output| {
-            if output.success {
-                format!("{} output:\n{}", output.tool_name, output.text)
-            } else {
-                format!("{} error:\n{}", output.tool_name, output.text)
-            }
-        })
-        .collect::<Vec<_>>()
-        .join("\n\n")
-}
+        let original = PlannerOutcome::from_payload(
+            &record.decision,
+            &record.rationale,
+            &payload,
+            record.error,
+        );
+        let (decision_value, rationale, replayed_payload, _, error) =
+            unified_decision_fields(&user_input, &decision);
+        let replayed =
+            PlannerOutcome::from_payload(decision_value, &rationale, &replayed_payload, error);
+        Ok(Some(PlannerReplay {
+            decision_id: record.id,
+            user_input,
+            diff: diff_outcomes(&original, &replayed),
+            original,
+            replayed,
+        }))
+    }
 
-fn dedupe_citations(citations: Vec<String>) -> Vec<String> {
-    let mut deduped = Vec::new();
-    for citation in citations {
-        if deduped.iter().any(|existing| existing == &citation) {
-            continue;
+    async fn regenerate_last_reply(
+        &self,
+        user_id: &str,
+        channel_id: Option<&str>,
+        generation: GenerationParams,
+    ) -> anyhow::Result<Option<OrchestratorReply>> {
+        let history = self
+            .memory
+            .list_chat_messages(user_id, REGENERATE_HISTORY_LIMIT)
+            .await?
+            .into_iter()
+            .filter(|message| channel_id.is_none_or(|channel_id| message.channel_id == channel_id))
+            .collect::<Vec<_>>();
+        let Some(last_user_index) = history
+            .iter()
+            .rposition(|message| message.role == ChatRole::User)
+        else {
+            return Ok(None);
+        };
+
+        let last_user_message = &history[last_user_index];
+        info!(
+            user_id,
+            channel_id = %last_user_message.channel_id,
+            model_override = ?generation.model,
+            temperature = ?generation.temperature,
+            "regenerating last reply"
+        );
+
+        let reply = self
+            .handle_message_with_options(
+                MessageCtx {
+                    message_id: format!("regenerate-{}", Utc::now().timestamp_millis()),
+                    user_id: user_id.to_owned(),
+                    guild_id: last_user_message.guild_id.clone(),
+                    channel_id: last_user_message.channel_id.clone(),
+                    content: last_user_message.content.clone(),
+                    timestamp: last_user_message.timestamp,
+                    attachments: Vec::new(),
+                },
+                TurnOptions {
+                    generation,
+                    regenerate: true,
+                    ..TurnOptions::default()
+                },
+            )
+            .await?;
+        // Only a successful turn replaces the old reply.
+        for message in history[last_user_index + 1..]
+            .iter()
+            .filter(|message| message.role == ChatRole::Assistant)
+        {
+            self.memory
+                .delete_chat_message(user_id, &message.id)
+                .await?;
         }
-        deduped.push(citation);
+        Ok(Some(reply))
     }
-    deduped
-}
 
-fn sanitize_memory_key(raw: &str) -> String {
-    let mut normalized = raw
-        .chars()
-        .map(|character| {
-            if character.is_ascii_alphanumeric() {
-                character.to_ascii_lowercase()
-            } else {
-                '_'
+    async fn prefetch_context(&self, user_id: &str, guild_id: &str, channel_id: &str) {
+        if self.config.context_prefetch_ttl.is_zero()
+            || self
+                .context_prefetch
+                .is_fresh(user_id, guild_id, channel_id)
+        {
+            return;
+        }
+        // Typing is only seen on Discord text channels and DMs.
+        let limits = self
+            .config
+            .context_windows
+            .for_surface(ContextSurface::of(guild_id, Modality::Text));
+        let loaded_at = Instant::now();
+        match self
+            .memory
+            .load_context(user_id, guild_id, channel_id, limits)
+            .await
+        {
+            Ok(memory_context) => {
+                self.context_prefetch.insert(
+                    user_id,
+                    guild_id,
+                    channel_id,
+                    loaded_at,
+                    memory_context,
+                );
             }
-        })
-        .collect::<String>();
+            Err(error) => debug!(%user_id, error = %error, "memory context prefetch failed"),
+        }
+    }
 
-    while normalized.contains("__") {
-        normalized = normalized.replace("__", "_");
+    async fn tool_quota_status(&self, user_id: &str) -> anyhow::Result<Vec<ToolQuotaStatus>> {
+        let day = quota_day(Utc::now());
+        let usage = self.memory.list_tool_usage(user_id, day).await?;
+        let mut statuses = self
+            .config
+            .tool_quotas
+            .iter()
+            .map(|(tool_name, limit)| ToolQuotaStatus {
+                tool_name: tool_name.clone(),
+                used: usage
+                    .iter()
+                    .find(|record| &record.tool_name == tool_name)
+                    .map_or(0, |record| record.count),
+                limit: *limit,
+                resets_at: quota_resets_at(day),
+            })
+            .collect::<Vec<_>>();
+        statuses.sort_by(|left, right| left.tool_name.cmp(&right.tool_name));
+        Ok(statuses)
     }
 
-    normalized.trim_matches('_').to_owned()
-}
+    async fn reset_tool_quota(
+        &self,
+        user_id: &str,
+        tool_name: Option<&str>,
+    ) -> anyhow::Result<u64> {
+        self.memory
+            .reset_tool_usage(user_id, tool_name, quota_day(Utc::now()))
+            .await
+    }
 
-const DEFAULT_SYSTEM_PROMPT_BASE: &str = "You are CompanionPilot, a helpful Discord AI companion.\nKeep replies concise and practical.\nNever emit XML/JSON/pseudo tool-call markup in normal replies.";
+    async fn start_background_tool(
+        &self,
+        ctx: &MessageCtx,
+        origin: TaskOrigin,
+        tool_name: &str,
+        args: Value,
+    ) -> anyhow::Result<Option<BackgroundTaskRecord>> {
+        let Some(background) = self
+            .background_tasks
+            .as_ref()
+            .filter(|background| background.handles(tool_name))
+        else {
+            return Ok(None);
+        };
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.check(&ctx.user_id).inspect_err(|limited| {
+                warn!(
+                    user_id = %ctx.user_id,
+                    retry_after_ms = limited.retry_after.as_millis() as u64,
+                    "background tool rejected: user rate limit reached"
+                );
+            })?;
+        }
+        let options = self
+            .apply_guild_settings(&ctx.guild_id, TurnOptions::default())
+            .await;
+        let call = ToolCall {
+            tool_name: tool_name.to_owned(),
+            args,
+        };
+        let (mut allowed, rejected) = restrict_to_enabled_tools(&options, vec![call], Vec::new());
+        let Some(ToolCall { args, .. }) = allowed.pop() else {
+            let reason = rejected
+                .first()
+                .and_then(|rejected| rejected.errors.first())
+                .map(|error| error.message.clone())
+                .unwrap_or_default();
+            anyhow::bail!("{reason}");
+        };
+        let args = match find_tool_spec(tool_name) {
+            Some(spec) => validate_tool_args(&spec, &args).map_err(|errors| {
+                anyhow::anyhow!(
+                    "invalid `{tool_name}` arguments: {}",
+                    errors
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                )
+            })?,
+            None => args,
+        };
+        self.consume_tool_quota(&ctx.user_id, tool_name).await?;
+        self.start_background_task(background, ctx, origin, tool_name, args)
+            .await
+            .map(Some)
+    }
 
-pub fn default_system_prompt_base() -> &'static str {
-    DEFAULT_SYSTEM_PROMPT_BASE
+    async fn compose_proactive_message(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+        channel_id: &str,
+        instruction: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let options = self
+            .apply_guild_settings(guild_id, TurnOptions::default())
+            .await;
+        let limits = self
+            .config
+            .context_windows
+            .for_surface(ContextSurface::of(guild_id, Modality::Text));
+        let mut memory_context = self
+            .memory
+            .load_context(user_id, guild_id, channel_id, limits)
+            .await?;
+        if guild_id == DM_GUILD_ID {
+            rank_facts_by_confidence(
+                &mut memory_context.facts,
+                Utc::now(),
+                self.config.fact_decay_half_life_days,
+            );
+        } else {
+            // Others read the message, so the user's facts stay out of it.
+            memory_context.facts.clear();
+        }
+        let persona_prompt = options
+            .persona
+            .as_ref()
+            .map(|persona| persona.system_prompt.trim())
+            .filter(|prompt| !prompt.is_empty());
+        let mut params = options
+            .persona
+            .as_ref()
+            .map(|persona| persona.generation.clone())
+            .unwrap_or_default()
+            .or(&self.config.generation);
+        if let Some(api_key) = self.user_api_key(user_id).await {
+            params.api_key = Some(api_key);
+        }
+        let text = self
+            .model
+            .complete(ModelRequest {
+                system_prompt: build_system_prompt(&memory_context, persona_prompt),
+                user_prompt: instruction.to_owned(),
+                params,
+            })
+            .await?;
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        self.memory
+            .record_chat_message(ChatMessageRecord {
+                id: String::new(),
+                user_id: user_id.to_owned(),
+                guild_id: guild_id.to_owned(),
+                channel_id: channel_id.to_owned(),
+                role: ChatRole::Assistant,
+                content: self.redacted(text.to_owned()),
+                timestamp: Utc::now(),
+                modality: Modality::Text,
+                session_id: None,
+                pinned: false,
+                source_message_id: None,
+            })
+            .await?;
+        Ok(Some(text.to_owned()))
+    }
+
+    async fn write_external_fact(
+        &self,
+        user_id: &str,
+        fact: MemoryFact,
+        rationale: &str,
+    ) -> anyhow::Result<Option<MemoryFact>> {
+        let Some(cleaned) = proposed_fact(&fact.key, &fact.value, fact.confidence) else {
+            return Ok(None);
+        };
+        let fact = MemoryFact {
+            source: fact.source,
+            ..cleaned
+        };
+        let ctx = MessageCtx {
+            message_id: String::new(),
+            user_id: user_id.to_owned(),
+            guild_id: DM_GUILD_ID.to_owned(),
+            channel_id: String::new(),
+            content: String::new(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        self.write_memory_fact(&ctx, fact, rationale)
+            .await
+            .map(Some)
+    }
+
+    async fn handle_nested_voice_transcript(&self, message: MessageCtx) -> anyhow::Result<String> {
+        let options = TurnOptions {
+            modality: Modality::Voice,
+            ..TurnOptions::default()
+        };
+        let reply = self.run_turn(message, options).await?;
+        Ok(reply.text)
+    }
+
+    fn memory(&self) -> Arc<dyn MemoryStore> {
+        IntoDynMemoryStore::into_dyn(self.memory.clone())
+    }
+
+    fn guild_settings(&self) -> &GuildSettingsCache {
+        &self.guild_settings
+    }
+
+    fn tool_stats(&self) -> &ToolStatsAggregator {
+        &self.tool_stats
+    }
+
+    fn redact(&self, text: String) -> String {
+        self.redacted(text)
+    }
+
+    fn rate_limiter(&self) -> Option<&UserRateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn activity(&self) -> Option<&ActivityTracker> {
+        Some(&self.activity)
+    }
+
+    fn coordinator(&self) -> Option<Arc<dyn InstanceCoordinator>> {
+        self.coordinator.clone()
+    }
+
+    fn model_probe(&self) -> Option<&ModelLatencyProbe> {
+        self.model_probe.as_deref()
+    }
+
+    fn capabilities(&self) -> Option<Capabilities> {
+        Some(Capabilities::new(
+            &self.config.deployment,
+            self.tools.enabled_tool_specs(),
+        ))
+    }
+
+    fn reply_pipelines(&self) -> &ReplyPipelines {
+        &self.reply_pipelines
+    }
+
+    fn user_keys(&self) -> Option<&UserKeyVault> {
+        self.user_keys.as_deref()
+    }
+
+    fn prompt_rollouts(&self) -> Option<&PromptRollouts> {
+        self.prompt_rollouts.as_deref()
+    }
+
+    fn guild_emoji(&self) -> Option<&GuildEmojiCatalog> {
+        Some(&self.guild_emoji)
+    }
+
+    fn custom_commands(&self) -> Option<&CustomCommands> {
+        Some(&self.custom_commands)
+    }
+
+    fn onboarding(&self) -> Option<&Onboarding> {
+        Some(&self.onboarding)
+    }
+
+    fn notifications(&self) -> Option<&Notifications> {
+        Some(&self.notifications)
+    }
+
+    fn calendar_feeds(&self) -> Option<&CalendarFeeds> {
+        self.calendar_feeds.as_deref()
+    }
 }
 
-fn build_system_prompt(
+/// Decision label, rationale, payload, success flag and error recorded for a
+/// unified planner decision.
+fn unified_decision_fields(
+    user_input: &str,
+    decision: &UnifiedPlanDecision,
+) -> (&'static str, String, Value, bool, Option<String>) {
+    match decision {
+        UnifiedPlanDecision::UsePlan {
+            rationale, payload, ..
+        } => ("apply_plan", rationale.clone(), payload.clone(), true, None),
+        UnifiedPlanDecision::SmallTalk => (
+            "skip_small_talk",
+            "small_talk_router".to_owned(),
+            json!({ "user_input": user_input }),
+            true,
+            None,
+        ),
+        UnifiedPlanDecision::MemoryRequest => (
+            "skip_memory_request",
+            "memory_request_router".to_owned(),
+            json!({ "user_input": user_input }),
+            true,
+            None,
+        ),
+        UnifiedPlanDecision::Fallback { reason, error } => (
+            "fallback_no_tools",
+            (*reason).to_owned(),
+            json!({ "user_input": user_input }),
+            false,
+            error.clone(),
+        ),
+    }
+}
+
+fn build_unified_planner_prompt(
     memory: &crate::types::MemoryContext,
-    override_prompt: Option<&str>,
+    examples: &[PlannerExample],
+    tool_macros: &ToolMacroRegistry,
 ) -> String {
-    let mut sections = if let Some(prompt) = override_prompt {
-        vec![prompt.to_owned()]
-    } else {
-        vec![DEFAULT_SYSTEM_PROMPT_BASE.to_owned()]
-    };
+    let context_block = build_planner_context_block(memory);
+
+    format!(
+        "You are the unified planner for CompanionPilot.
+Decide both tool usage and memory write for one user message.
+Return strict JSON only (no markdown, no prose) with this exact schema:
+{{
+  \"tool_calls\": [{{\"tool_name\":\"...\",\"args\":{{...}}}}],
+  \"memory\": {{
+    \"store\": true|false,
+    \"key\": \"...\",
+    \"value\": \"...\",
+    \"confidence\": 0.0-1.0
+  }},
+  \"rationale\": \"short reason\"
+}}
+Tool calls are executed sequentially in listed order.
+There are no manual commands or manual overrides: all tool usage must come from this decision.
+If no tool is needed, return an empty tool_calls array.
+If memory should not be stored, set store=false and key/value to empty strings.
+Store only durable personal facts (identity, preferences, recurring goals, corrections).
+Store the user's time zone under key \"timezone\" as an IANA name (e.g. Europe/Prague) when they reveal it or where they live.
+Store the user's birthday under key \"birthday\" and their anniversaries under keys ending in \"_anniversary\" (e.g. wedding_anniversary), as YYYY-MM-DD, or MM-DD when the year is unknown.
+Do not store one-off requests or transient states.
+Use web search for latest/current/news/prices/weather or unknown factual claims.
+For time-sensitive requests, call current_datetime before web_search so queries and answers are anchored to real current time.
+If current_datetime is needed, request only current_datetime in this decision and wait for its output before planning web_search.
+Tool args must satisfy the tool's args_schema (JSON Schema); invalid calls are rejected.
+Tool inventory:
+{}
+{}{}",
+        build_tool_inventory_for_planner(tool_macros),
+        format_planner_examples(examples),
+        context_block
+    )
+}
+
+fn build_tool_followup_prompt(
+    memory: &crate::types::MemoryContext,
+    tool_macros: &ToolMacroRegistry,
+    budget: &PlannerBudget,
+) -> String {
+    let context_block = build_planner_context_block(memory);
+
+    format!(
+        "You are the tool follow-up planner for CompanionPilot.
+Decide whether the current evidence is enough for a final user-facing answer, or whether more tool calls are needed.
+Return strict JSON only (no markdown, no prose) with this exact schema:
+{{
+  \"action\": \"final\"|\"tools\",
+  \"final_answer\": \"non-empty only when action=final\",
+  \"tool_calls\": [{{\"tool_name\":\"...\",\"args\":{{...}}}}],
+  \"rationale\": \"short reason\"
+}}
+If action=final, provide the complete final answer and return an empty tool_calls array.
+If action=tools, final_answer must be empty and tool_calls must contain at least one valid call.
+Only request tools when the current outputs are insufficient or conflicting.
+For time-sensitive requests, prefer calling current_datetime before additional web_search calls.
+If current_datetime is needed, call it alone first, then plan web_search in a later tool round.
+If an earlier call failed with invalid arguments, correct the arguments to match the tool's args_schema.
+Prefer action=final when the budget below will not allow another useful round or a needed tool has no quota left.
+{}
+Tool inventory:
+{}
+{}",
+        format_planner_budget(budget),
+        build_tool_inventory_for_planner(tool_macros),
+        context_block
+    )
+}
+
+/// What the follow-up planner has left to spend on this turn.
+struct PlannerBudget {
+    /// Tool rounds this planner may still request, counting the next one.
+    rounds_left: usize,
+    elapsed: Duration,
+    round_budget: Option<Duration>,
+    quotas: Vec<ToolQuotaStatus>,
+}
+
+fn format_planner_budget(budget: &PlannerBudget) -> String {
+    let mut lines = vec![
+        "Budget:".to_owned(),
+        match budget.rounds_left {
+            1 => "Tool rounds left: 1 (its results go straight into the answer; no further tools can be requested)".to_owned(),
+            rounds => format!("Tool rounds left: {rounds}"),
+        },
+        format!(
+            "Time elapsed this turn: {} ms",
+            budget.elapsed.as_millis()
+        ),
+    ];
+    if let Some(round_budget) = budget.round_budget {
+        lines.push(format!(
+            "Each tool round may take at most {} ms",
+            round_budget.as_millis()
+        ));
+    }
+    if !budget.quotas.is_empty() {
+        let quotas = budget
+            .quotas
+            .iter()
+            .map(|quota| {
+                format!(
+                    "{} {} of {}",
+                    quota.tool_name,
+                    quota.limit.saturating_sub(quota.used),
+                    quota.limit
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        lines.push(format!("Daily tool calls left for this user: {quotas}"));
+    }
+    lines.join("\n")
+}
 
+fn build_planner_context_block(memory: &crate::types::MemoryContext) -> String {
+    let mut context_lines = Vec::new();
     if let Some(summary) = &memory.summary {
-        sections.push(format!("Conversation summary: {summary}"));
+        context_lines.push(format!("Conversation summary: {summary}"));
     }
 
-    if !memory.recent_messages.is_empty() {
-        sections.push(build_recent_context_block(&memory.recent_messages));
+    if !memory.pinned_messages.is_empty() {
+        context_lines.push(build_pinned_block(&memory.pinned_messages));
+    }
+
+    if !memory.system_notices.is_empty() {
+        context_lines.push(build_system_notices_block(&memory.system_notices));
     }
 
     if !memory.facts.is_empty() {
-        let lines = memory
+        let facts = memory
             .facts
             .iter()
-            .map(|fact| format!("{} = {}", fact.key, fact.value))
+            .map(|fact| format!("{}={}", fact.key, fact.value))
             .collect::<Vec<_>>()
             .join("; ");
-        sections.push(format!("Known user facts: {lines}"));
+        context_lines.push(format!("Known user facts: {facts}"));
     }
 
-    sections.join("\n")
+    if !memory.recent_messages.is_empty() {
+        context_lines.push(build_recent_context_block(&memory.recent_messages));
+    }
+
+    if !memory.other_channel_messages.is_empty() {
+        context_lines.push(build_other_channels_block(&memory.other_channel_messages));
+    }
+
+    if let Some(now_playing) = &memory.now_playing {
+        context_lines.push(format!(
+            "Now playing on the user's Spotify (no spotify_playing_status call needed): {now_playing}"
+        ));
+    }
+
+    if context_lines.is_empty() {
+        String::new()
+    } else {
+        format!("Context:\n{}\n", context_lines.join("\n"))
+    }
 }
 
-fn build_recent_context_block(recent_messages: &[String]) -> String {
-    if recent_messages.is_empty() {
-        return String::new();
+fn build_tool_inventory_for_planner(tool_macros: &ToolMacroRegistry) -> String {
+    let tools =
+        serde_json::to_string_pretty(&builtin_tool_specs()).unwrap_or_else(|_| "[]".to_owned());
+    if tool_macros.is_empty() {
+        return tools;
     }
+    let macros = serde_json::to_string_pretty(&tool_macros.planner_inventory())
+        .unwrap_or_else(|_| "[]".to_owned());
+    format!(
+        "{tools}\nTool macros (request one like a tool, with tool_name set to macro_name; its steps run in order with your args merged into each step that accepts them):\n{macros}"
+    )
+}
 
-    let turns = recent_messages
-        .iter()
-        .rev()
-        .take(8)
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .map(|line| format!("- {line}"))
-        .collect::<Vec<_>>()
-        .join("\n");
-    format!("Recent conversation turns:\n{turns}")
+fn with_reply_language(system_prompt: String, language: Option<&str>) -> String {
+    match language
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+    {
+        Some(language) => format!(
+            "{system_prompt}\n\nWrite the reply in {language} unless the user explicitly asks for another language."
+        ),
+        None => system_prompt,
+    }
 }
 
-fn clean_memory_value(value: &str) -> String {
-    value
-        .trim()
-        .trim_matches(|character: char| character == '"' || character == '\'')
-        .trim_end_matches(|character: char| {
-            character == '.' || character == '!' || character == '?'
-        })
-        .trim()
-        .to_owned()
+/// Spoken replies are read out by text-to-speech, so they get prose-only
+/// instructions; text replies are unchanged.
+fn with_modality_style(system_prompt: String, modality: Modality) -> String {
+    match modality {
+        Modality::Voice => format!(
+            "{system_prompt}\n\nThis reply will be spoken aloud. Answer in a few short conversational sentences. Do not use markdown, bullet or numbered lists, tables, emoji or URLs; name a source instead of linking it."
+        ),
+        Modality::Text => system_prompt,
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::sync::Arc;
+fn with_response_format(system_prompt: String, response_schema: Option<&Value>) -> String {
+    match response_schema {
+        Some(schema) => format!("{system_prompt}\n\n{}", response_format_instruction(schema)),
+        None => system_prompt,
+    }
+}
 
-    use async_trait::async_trait;
-    use chrono::Utc;
-    use serde_json::{Value, json};
+fn build_json_correction_prompt(
+    original_prompt: &str,
+    invalid_output: &str,
+    error: &serde_json::Error,
+) -> String {
+    format!(
+        "{original_prompt}\n\nYour previous reply could not be parsed as JSON ({error}).\nPrevious reply:\n{invalid_output}\n\nRespond again with only the corrected JSON object matching the required schema. No markdown, no commentary."
+    )
+}
 
-    use crate::{
-        memory::{InMemoryMemoryStore, MemoryStore},
-        model::{MockModelProvider, ModelProvider, ModelRequest},
-        safety::SafetyPolicy,
-        tools::{ToolExecutor, ToolRegistry, ToolResult},
-        types::{MessageCtx, ToolCall},
-    };
+fn parse_unified_plan(raw: &str) -> Result<UnifiedPlan, serde_json::Error> {
+    parse_json_plan(raw)
+}
 
-    use super::{
-        DefaultChatOrchestrator, PlannedToolCall, clean_memory_value,
-        enforce_datetime_planning_boundary, parse_unified_plan, sanitize_memory_key,
-        sanitize_planned_tool_calls,
+fn parse_tool_followup_plan(raw: &str) -> Result<ToolFollowupPlan, serde_json::Error> {
+    parse_json_plan(raw)
+}
+
+/// Moves calls to tools the persona or the turn has not enabled into the
+/// rejected list so the planner is told why they did not run.
+fn restrict_to_enabled_tools(
+    options: &TurnOptions,
+    calls: Vec<ToolCall>,
+    mut rejected: Vec<RejectedToolCall>,
+) -> (Vec<ToolCall>, Vec<RejectedToolCall>) {
+    let (allowed, disabled): (Vec<_>, Vec<_>) = calls
+        .into_iter()
+        .partition(|call| tool_enabled(options, &call.tool_name));
+    rejected.extend(disabled.into_iter().map(|call| RejectedToolCall {
+        errors: vec![ToolArgError {
+            path: String::new(),
+            message: format!("tool `{}` is not enabled here", call.tool_name),
+        }],
+        tool_name: call.tool_name,
+        args: call.args,
+    }));
+    (allowed, rejected)
+}
+
+/// Where background tasks started by this turn report back. `/chat` and
+/// widget turns run on the dashboard surface with caller-chosen channels.
+fn task_origin(options: &TurnOptions) -> TaskOrigin {
+    if options.surface == Some(ContextSurface::Dashboard) {
+        TaskOrigin::Http
+    } else {
+        TaskOrigin::Discord
+    }
+}
+
+fn background_task_claim_key(task_id: &str) -> String {
+    format!("background-task:{task_id}")
+}
+
+/// Whether neither the persona nor the turn restricts `tool_name` away.
+fn tool_enabled(options: &TurnOptions, tool_name: &str) -> bool {
+    let allows = |tools: Option<&Vec<String>>| {
+        tools.is_none_or(|tools| tools.iter().any(|tool| tool == tool_name))
     };
+    allows(
+        options
+            .persona
+            .as_ref()
+            .and_then(|persona| persona.enabled_tools.as_ref()),
+    ) && allows(options.enabled_tools.as_ref())
+}
 
-    #[derive(Debug, Default)]
-    struct FollowupLoopModelProvider;
+fn sanitize_planned_tool_calls(
+    planned_calls: Vec<PlannedToolCall>,
+    tool_macros: &ToolMacroRegistry,
+) -> SanitizedToolCalls {
+    let mut calls = Vec::new();
+    let mut macro_calls = Vec::new();
+    let mut rejected = Vec::new();
 
-    #[async_trait]
-    impl ModelProvider for FollowupLoopModelProvider {
-        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
-            if request
-                .system_prompt
-                .contains("You are the unified planner for CompanionPilot.")
+    for planned_call in planned_calls {
+        if calls.len() + macro_calls.len() >= MAX_PLANNED_TOOL_CALLS {
+            break;
+        }
+        if let Some(tool_macro) = tool_macros.get(&planned_call.tool_name) {
+            // The cap counts expanded steps; a macro that does not fit is
+            // rejected whole rather than run in part.
+            let room = MAX_PLANNED_TOOL_CALLS - calls.len() - macro_calls.len();
+            match expand_tool_macro(tool_macro, &planned_call.args) {
+                Ok(steps) if steps.len() > room => rejected.push(RejectedToolCall {
+                    errors: vec![ToolArgError {
+                        path: String::new(),
+                        message: format!(
+                            "macro has {} steps but only {room} of {MAX_PLANNED_TOOL_CALLS} tool calls are left this round",
+                            steps.len()
+                        ),
+                    }],
+                    tool_name: planned_call.tool_name,
+                    args: planned_call.args,
+                }),
+                Ok(steps) => macro_calls.extend(steps),
+                Err(errors) => rejected.push(RejectedToolCall {
+                    tool_name: planned_call.tool_name,
+                    args: planned_call.args,
+                    errors,
+                }),
+            }
+            continue;
+        }
+        let Some(spec) = find_tool_spec(&planned_call.tool_name) else {
+            debug!(
+                tool_name = %planned_call.tool_name,
+                "rejecting unknown planner tool call"
+            );
+            rejected.push(RejectedToolCall {
+                errors: vec![ToolArgError {
+                    path: String::new(),
+                    message: format!("unknown tool `{}`", planned_call.tool_name),
+                }],
+                tool_name: planned_call.tool_name,
+                args: planned_call.args,
+            });
+            continue;
+        };
+
+        match validate_tool_args(&spec, &planned_call.args) {
+            Ok(args) => calls.push(ToolCall {
+                tool_name: planned_call.tool_name,
+                args,
+            }),
+            Err(errors) => {
+                debug!(
+                    tool_name = %planned_call.tool_name,
+                    error_count = errors.len(),
+                    "rejecting planner tool call with invalid args"
+                );
+                rejected.push(RejectedToolCall {
+                    tool_name: planned_call.tool_name,
+                    args: planned_call.args,
+                    errors,
+                });
+            }
+        }
+    }
+
+    SanitizedToolCalls {
+        calls,
+        macro_calls,
+        rejected,
+    }
+}
+
+/// Validates every step of a macro call; one invalid step rejects the whole
+/// macro, with errors prefixed by the step they belong to.
+fn expand_tool_macro(
+    tool_macro: &ToolMacro,
+    args: &Value,
+) -> Result<Vec<ToolCall>, Vec<ToolArgError>> {
+    let mut steps = Vec::new();
+    let mut errors = Vec::new();
+    for (index, (tool_name, step_args)) in tool_macro.step_args(args).into_iter().enumerate() {
+        let Some(spec) = find_tool_spec(&tool_name) else {
+            continue;
+        };
+        match validate_tool_args(&spec, &step_args) {
+            Ok(args) => steps.push(ToolCall { tool_name, args }),
+            Err(step_errors) => errors.extend(step_errors.into_iter().map(|error| ToolArgError {
+                path: format!("/steps/{index}{}", error.path),
+                message: format!("{tool_name}: {}", error.message),
+            })),
+        }
+    }
+    if errors.is_empty() {
+        Ok(steps)
+    } else {
+        Err(errors)
+    }
+}
+
+fn enforce_datetime_planning_boundary(tool_calls: Vec<ToolCall>) -> Vec<ToolCall> {
+    let has_datetime = tool_calls
+        .iter()
+        .any(|call| call.tool_name == "current_datetime");
+    let has_non_datetime = tool_calls
+        .iter()
+        .any(|call| call.tool_name != "current_datetime");
+    if !has_datetime || !has_non_datetime {
+        return tool_calls;
+    }
+
+    debug!(
+        total_calls = tool_calls.len(),
+        "deferring non-datetime tools to follow-up round because current_datetime was requested"
+    );
+    let datetime_call = tool_calls
+        .into_iter()
+        .find(|call| call.tool_name == "current_datetime")
+        .expect("checked current_datetime presence above");
+    vec![datetime_call]
+}
+
+/// Fills the user's stored time zone into `current_datetime` calls that do
+/// not name one, so the tool reports local time.
+fn default_datetime_timezone(tool_calls: &mut [ToolCall], facts: &[MemoryFact]) {
+    let Some(tz) = user_timezone(facts) else {
+        return;
+    };
+    for call in tool_calls
+        .iter_mut()
+        .filter(|call| call.tool_name == "current_datetime")
+    {
+        if call.args.is_null() {
+            call.args = json!({});
+        }
+        if let Some(args) = call.args.as_object_mut() {
+            args.entry("timezone")
+                .or_insert_with(|| Value::String(tz.name().to_owned()));
+        }
+    }
+}
+
+/// A fact proposed outside a turn (e.g. by the nightly backfill), cleaned up
+/// like a planner memory write; `None` when the key or value is unusable.
+pub(crate) fn proposed_fact(key: &str, value: &str, confidence: f32) -> Option<MemoryFact> {
+    match memory_decision_from_plan(PlannedMemory {
+        store: true,
+        key: key.to_owned(),
+        value: value.to_owned(),
+        confidence,
+    }) {
+        MemoryDecision::Store { fact, .. } => Some(fact),
+        MemoryDecision::Skip { .. } => None,
+    }
+}
+
+fn memory_decision_from_plan(plan: PlannedMemory) -> MemoryDecision {
+    if !plan.store {
+        return MemoryDecision::Skip {
+            reason: "planner_no_store",
+        };
+    }
+
+    let key = sanitize_memory_key(&plan.key);
+    let mut value = clean_memory_value(&plan.value);
+    if key.is_empty() || value.is_empty() {
+        return MemoryDecision::Skip {
+            reason: "planner_invalid_fact",
+        };
+    }
+    if key == TIMEZONE_FACT_KEY {
+        let Some(tz) = parse_timezone(&value) else {
+            return MemoryDecision::Skip {
+                reason: "planner_invalid_timezone",
+            };
+        };
+        value = tz.name().to_owned();
+    }
+    if is_date_fact_key(&key) {
+        let Some(date) = DateFact::parse(&key, &value) else {
+            return MemoryDecision::Skip {
+                reason: "planner_invalid_date",
+            };
+        };
+        value = date.value();
+    }
+
+    MemoryDecision::Store {
+        fact: MemoryFact {
+            key,
+            value,
+            confidence: plan.confidence.clamp(0.0, 1.0),
+            source: "user_message".to_owned(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        },
+        rationale: "model_planner",
+    }
+}
+
+fn memory_payload(memory: &MemoryDecision) -> Value {
+    match memory {
+        MemoryDecision::Store { fact, .. } => json!({
+            "store": true,
+            "key": fact.key,
+            "value": fact.value,
+            "confidence": fact.confidence
+        }),
+        MemoryDecision::Skip { reason } => json!({
+            "store": false,
+            "reason": reason
+        }),
+    }
+}
+
+fn truncate_for_log(input: &str, max_len: usize) -> String {
+    let mut result = input.replace('\n', "\\n");
+    if result.len() > max_len {
+        result.truncate(result.floor_char_boundary(max_len));
+        result.push_str("...");
+    }
+    result
+}
+
+fn elapsed_ms(started_at: Instant) -> u64 {
+    started_at
+        .elapsed()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+pub(crate) fn parse_json_plan<T: DeserializeOwned>(raw: &str) -> Result<T, serde_json::Error> {
+    let candidate = raw
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+
+    match serde_json::from_str::<T>(candidate) {
+        Ok(plan) => Ok(plan),
+        Err(original_error) => {
+            if let Some(object_candidate) = extract_first_json_object(candidate) {
+                serde_json::from_str::<T>(object_candidate).map_err(|_| original_error)
+            } else {
+                Err(original_error)
+            }
+        }
+    }
+}
+
+fn extract_first_json_object(raw: &str) -> Option<&str> {
+    let mut start_index: Option<usize> = None;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut is_escaped = false;
+
+    for (index, character) in raw.char_indices() {
+        if start_index.is_none() {
+            if character == '{' {
+                start_index = Some(index);
+                depth = 1;
+            }
+            continue;
+        }
+
+        if in_string {
+            if is_escaped {
+                is_escaped = false;
+                continue;
+            }
+            match character {
+                '\\' => is_escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match character {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    let start = start_index?;
+                    return Some(&raw[start..=index]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn format_tool_outputs(outputs: &[ExecutedToolOutput]) -> String {
+    outputs
+        .iter()
+        .enumerate()
+        .map(|(index, output)| {
+            let (status, label) = if output.success {
+                ("success", "Output")
+            } else {
+                ("error", "Error")
+            };
+            let mut block = format!(
+                "{}. Tool: {}\nArgs: {}\nStatus: {}\n{}:\n{}",
+                index + 1,
+                output.tool_name,
+                output.args,
+                status,
+                label,
+                output.text
+            );
+            // Oversized payloads are left out rather than cut into invalid JSON;
+            // the text above still carries the result.
+            if let Some(data) = output.data.as_ref().map(Value::to_string)
+                && data.len() <= MAX_TOOL_DATA_CHARS
             {
-                return Ok(json!({
-                    "tool_calls": [
-                        {
-                            "tool_name": "web_search",
-                            "args": {
-                                "query": "alpha",
-                                "max_results": 3
-                            }
-                        }
-                    ],
-                    "memory": {
-                        "store": false,
-                        "key": "",
-                        "value": "",
-                        "confidence": 0.0
-                    },
-                    "rationale": "need first lookup"
+                block.push_str("\nData: ");
+                block.push_str(&data);
+            }
+            block
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn fallback_tool_output_text(outputs: &[ExecutedToolOutput]) -> String {
+    outputs
+        .iter()
+        .map(|output| {
+            if output.success {
+                format!("{} output:\n{}", output.tool_name, output.text)
+            } else {
+                format!("{} error:\n{}", output.tool_name, output.text)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn dedupe_citations(citations: Vec<String>) -> Vec<String> {
+    let mut deduped = Vec::new();
+    for citation in citations {
+        if deduped.iter().any(|existing| existing == &citation) {
+            continue;
+        }
+        deduped.push(citation);
+    }
+    deduped
+}
+
+fn sanitize_memory_key(raw: &str) -> String {
+    let mut normalized = raw
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    while normalized.contains("__") {
+        normalized = normalized.replace("__", "_");
+    }
+
+    normalized.trim_matches('_').to_owned()
+}
+
+const DEFAULT_SYSTEM_PROMPT_BASE: &str = "You are CompanionPilot, a helpful Discord AI companion.\nKeep replies concise and practical.\nNever emit XML/JSON/pseudo tool-call markup in normal replies.";
+
+pub fn default_system_prompt_base() -> &'static str {
+    DEFAULT_SYSTEM_PROMPT_BASE
+}
+
+fn build_system_prompt(
+    memory: &crate::types::MemoryContext,
+    override_prompt: Option<&str>,
+) -> String {
+    let mut sections = if let Some(prompt) = override_prompt {
+        vec![prompt.to_owned()]
+    } else {
+        vec![DEFAULT_SYSTEM_PROMPT_BASE.to_owned()]
+    };
+
+    if let Some(summary) = &memory.summary {
+        sections.push(format!("Conversation summary: {summary}"));
+    }
+
+    if let Some(handoff) = &memory.handoff {
+        sections.push(handoff_block(handoff));
+    }
+
+    if !memory.pinned_messages.is_empty() {
+        sections.push(build_pinned_block(&memory.pinned_messages));
+    }
+
+    if !memory.system_notices.is_empty() {
+        sections.push(build_system_notices_block(&memory.system_notices));
+    }
+
+    if let Some(capabilities) = &memory.capabilities {
+        sections.push(capabilities.clone());
+    }
+
+    if let Some(guild_emoji) = &memory.guild_emoji {
+        sections.push(guild_emoji.clone());
+    }
+
+    if !memory.recent_messages.is_empty() {
+        sections.push(build_recent_context_block(&memory.recent_messages));
+    }
+
+    if !memory.other_channel_messages.is_empty() {
+        sections.push(build_other_channels_block(&memory.other_channel_messages));
+    }
+
+    match user_timezone(&memory.facts) {
+        Some(tz) => sections.push(local_time_line(tz, Utc::now())),
+        None => sections.push(
+            "The user's time zone is unknown. If an answer depends on their local time, ask which city or time zone they are in."
+                .to_owned(),
+        ),
+    }
+
+    if !memory.ambient.is_empty() {
+        sections.push(format!(
+            "Live readings from the user's home sensors (use when relevant, e.g. questions about the house):\n{}",
+            memory
+                .ambient
+                .iter()
+                .map(|line| format!("- {line}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+
+    if let Some(now_playing) = &memory.now_playing {
+        sections.push(format!(
+            "The user is currently listening to {now_playing} on Spotify; mention it only when it fits naturally."
+        ));
+    }
+
+    if !memory.facts.is_empty() {
+        let lines = memory
+            .facts
+            .iter()
+            .map(|fact| format!("{} = {}", fact.key, fact.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        sections.push(format!("Known user facts: {lines}"));
+    }
+
+    sections.join("\n")
+}
+
+/// Removes the latest user message and the replies after it from formatted
+/// recent context, for a turn that answers that message again.
+fn drop_last_exchange(recent_messages: &mut Vec<String>) {
+    let user = ChatRole::User.as_str();
+    if let Some(index) = recent_messages.iter().rposition(|line| {
+        line.strip_prefix(user)
+            .is_some_and(|rest| rest.starts_with(": ") || rest.starts_with(" ("))
+    }) {
+        recent_messages.truncate(index);
+    }
+}
+
+/// The recent turns as loaded; how many there are is decided by the
+/// surface's [`ContextLimits`](crate::types::ContextLimits) when the context
+/// is loaded.
+fn build_recent_context_block(recent_messages: &[String]) -> String {
+    if recent_messages.is_empty() {
+        return String::new();
+    }
+
+    let turns = recent_messages
+        .iter()
+        .map(|line| format!("- {line}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("Recent conversation turns:\n{turns}")
+}
+
+fn build_pinned_block(pinned_messages: &[String]) -> String {
+    let lines = pinned_messages
+        .iter()
+        .map(|line| format!("- {line}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("Pinned messages (the user asked you to keep these in mind):\n{lines}")
+}
+
+fn build_system_notices_block(system_notices: &[String]) -> String {
+    let lines = system_notices
+        .iter()
+        .map(|line| format!("- {line}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("Notices from the operator (mention them only when relevant):\n{lines}")
+}
+
+fn build_other_channels_block(other_channel_messages: &[String]) -> String {
+    let turns = other_channel_messages
+        .iter()
+        .map(|line| format!("- {line}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Earlier turns with this user in other channels (not part of this conversation; use only if they refer back to them):\n{turns}"
+    )
+}
+
+/// The latest `limit` turns of `history` (oldest first) outside the given
+/// channel, each labeled with where it happened. Only DMs see turns from
+/// everywhere; a server sees its own other channels, never DMs or other
+/// servers.
+fn cross_channel_turns(
+    history: &[ChatMessageRecord],
+    guild_id: &str,
+    channel_id: &str,
+    limit: usize,
+) -> Vec<String> {
+    let mut turns = history
+        .iter()
+        .rev()
+        .filter(|message| guild_id == DM_GUILD_ID || message.guild_id == guild_id)
+        .filter(|message| message.guild_id != guild_id || message.channel_id != channel_id)
+        .take(limit)
+        .map(|message| {
+            let place = if message.guild_id == DM_GUILD_ID {
+                "DMs".to_owned()
+            } else {
+                format!("channel {}", message.channel_id)
+            };
+            format!(
+                "{} in {place}: {}",
+                context_speaker(message.role, message.modality),
+                message.content
+            )
+        })
+        .collect::<Vec<_>>();
+    turns.reverse();
+    turns
+}
+
+fn clean_memory_value(value: &str) -> String {
+    value
+        .trim()
+        .trim_matches(|character: char| character == '"' || character == '\'')
+        .trim_end_matches(|character: char| {
+            character == '.' || character == '!' || character == '?'
+        })
+        .trim()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use serde_json::{Value, json};
+
+    use crate::{
+        background_tasks::{BackgroundTasks, DEFAULT_BACKGROUND_TIMEOUT},
+        context_window::ContextSurface,
+        coordination::{InstanceCoordinator, LocalCoordinator},
+        guild_settings::GuildSettings,
+        memory::{InMemoryMemoryStore, MemoryStore},
+        model::{GenerationParams, MockModelProvider, ModelProvider, ModelRequest},
+        rate_limit::RateLimited,
+        redaction::Redactor,
+        routing::ModelRoutes,
+        safety::SafetyPolicy,
+        tool_macros::ToolMacroRegistry,
+        tools::{MockToolExecutor, MockToolResponse, ToolExecutor, ToolRegistry, ToolResult},
+        types::{
+            BackgroundTaskRecord, BackgroundTaskStatus, ChatMessageRecord, ChatRole, ContextLimits,
+            DM_GUILD_ID, MemoryFact, MessageCtx, Modality, PlannerDecisionRecord,
+            SystemNoticeRecord, TaskOrigin, ToolCall, TurnFailure, TurnPhase,
+        },
+    };
+
+    use super::{
+        ChatOrchestrator, DefaultChatOrchestrator, GenericChatOrchestrator, OrchestratorConfig,
+        PlannedToolCall, PlannerBudget, TurnOptions, clean_memory_value, cross_channel_turns,
+        drop_last_exchange, enforce_datetime_planning_boundary, format_planner_budget,
+        parse_unified_plan, sanitize_memory_key, sanitize_planned_tool_calls, task_origin,
+        truncate_for_log,
+    };
+
+    #[derive(Debug, Default)]
+    struct FollowupLoopModelProvider;
+
+    #[async_trait]
+    impl ModelProvider for FollowupLoopModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            if request
+                .system_prompt
+                .contains("You are the unified planner for CompanionPilot.")
+            {
+                return Ok(json!({
+                    "tool_calls": [
+                        {
+                            "tool_name": "web_search",
+                            "args": {
+                                "query": "alpha",
+                                "max_results": 3
+                            }
+                        }
+                    ],
+                    "memory": {
+                        "store": false,
+                        "key": "",
+                        "value": "",
+                        "confidence": 0.0
+                    },
+                    "rationale": "need first lookup"
+                })
+                .to_string());
+            }
+
+            if request
+                .system_prompt
+                .contains("You are the tool follow-up planner for CompanionPilot.")
+            {
+                if request.user_prompt.contains("result:alpha")
+                    && !request.user_prompt.contains("result:beta")
+                {
+                    return Ok(json!({
+                        "action": "tools",
+                        "final_answer": "",
+                        "tool_calls": [
+                            {
+                                "tool_name": "web_search",
+                                "args": {
+                                    "query": "beta",
+                                    "max_results": 2
+                                }
+                            }
+                        ],
+                        "rationale": "need second lookup"
+                    })
+                    .to_string());
+                }
+
+                if request.user_prompt.contains("result:beta") {
+                    return Ok(json!({
+                        "action": "final",
+                        "final_answer": "Final answer from follow-up planner.",
+                        "tool_calls": [],
+                        "rationale": "have enough evidence"
+                    })
+                    .to_string());
+                }
+            }
+
+            Ok("fallback final synthesis".to_owned())
+        }
+    }
+
+    /// Asks for the same search in every planning round.
+    #[derive(Debug, Default)]
+    struct RepeatingSearchModelProvider;
+
+    #[async_trait]
+    impl ModelProvider for RepeatingSearchModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            let tool_calls = json!([{"tool_name": "web_search", "args": {"query": "alpha"}}]);
+            if request
+                .system_prompt
+                .contains("You are the unified planner for CompanionPilot.")
+            {
+                return Ok(json!({
+                    "tool_calls": tool_calls,
+                    "memory": {"store": false, "key": "", "value": "", "confidence": 0.0},
+                    "rationale": "look it up"
+                })
+                .to_string());
+            }
+            if request
+                .system_prompt
+                .contains("You are the tool follow-up planner for CompanionPilot.")
+            {
+                return Ok(json!({
+                    "action": "tools",
+                    "final_answer": "",
+                    "tool_calls": tool_calls,
+                    "rationale": "still nothing found"
+                })
+                .to_string());
+            }
+            Ok("Nothing found.".to_owned())
+        }
+    }
+
+    /// Plans no tools, then fails to write the reply.
+    #[derive(Debug, Default)]
+    struct FailingSynthesisModelProvider;
+
+    #[async_trait]
+    impl ModelProvider for FailingSynthesisModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            if request
+                .system_prompt
+                .contains("You are the unified planner for CompanionPilot.")
+            {
+                return Ok(json!({
+                    "tool_calls": [],
+                    "memory": {"store": false, "key": "", "value": "", "confidence": 0.0},
+                    "rationale": "just chat"
+                })
+                .to_string());
+            }
+            anyhow::bail!("upstream returned 502")
+        }
+    }
+
+    /// Answers every request with its system and user prompts.
+    struct EchoModelProvider;
+
+    #[async_trait]
+    impl ModelProvider for EchoModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            Ok(format!(
+                "{}\n{}",
+                request.system_prompt, request.user_prompt
+            ))
+        }
+    }
+
+    /// Records how many in-flight markers exist while it is answering.
+    struct InFlightProbeModelProvider {
+        memory: Arc<InMemoryMemoryStore>,
+        seen: std::sync::Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl ModelProvider for InFlightProbeModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            let markers = self
+                .memory
+                .list_stale_in_flight_replies(Utc::now() + chrono::Duration::hours(1))
+                .await?;
+            self.seen.lock().expect("lock").push(markers.len());
+            Ok(request.user_prompt)
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct MalformedPlanModelProvider;
+
+    #[async_trait]
+    impl ModelProvider for MalformedPlanModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            if request
+                .system_prompt
+                .contains("You are the unified planner for CompanionPilot.")
+            {
+                if !request.user_prompt.contains("could not be parsed as JSON") {
+                    if request.user_prompt.contains("vyhledej") {
+                        // Byte 220, where the log line is cut, falls inside a `ž`.
+                        return Ok(format!(
+                            "Hledám: {} {{tool_calls: web_search",
+                            "ž".repeat(300)
+                        ));
+                    }
+                    return Ok("Sure, I will search for alpha {tool_calls: web_search".to_owned());
+                }
+                return Ok(json!({
+                    "tool_calls": [
+                        {"tool_name": "web_search", "args": {"query": "alpha"}}
+                    ],
+                    "memory": {"store": false, "key": "", "value": "", "confidence": 0.0},
+                    "rationale": "corrected plan"
+                })
+                .to_string());
+            }
+
+            if request
+                .system_prompt
+                .contains("You are the tool follow-up planner for CompanionPilot.")
+            {
+                return Ok(json!({
+                    "action": "final",
+                    "final_answer": "Done after correction.",
+                    "tool_calls": [],
+                    "rationale": "have evidence"
+                })
+                .to_string());
+            }
+
+            Ok("fallback final synthesis".to_owned())
+        }
+    }
+
+    /// Formats every field of every event, as a real log subscriber does;
+    /// without one, `tracing` never evaluates the logged values.
+    struct FormattingSubscriber;
+
+    impl tracing::Subscriber for FormattingSubscriber {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Format;
+            impl tracing::field::Visit for Format {
+                fn record_debug(
+                    &mut self,
+                    _field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    let _ = format!("{value:?}");
+                }
+            }
+            event.record(&mut Format);
+        }
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    /// Answers in long Czech prose until asked to fix it into the JSON schema.
+    struct CzechProseModelProvider;
+
+    #[async_trait]
+    impl ModelProvider for CzechProseModelProvider {
+        async fn complete(&self, request: ModelRequest) -> anyhow::Result<String> {
+            if request
+                .system_prompt
+                .contains("You are the unified planner for CompanionPilot.")
+            {
+                return Ok(json!({
+                    "tool_calls": [],
+                    "memory": {"store": false, "key": "", "value": "", "confidence": 0.0},
+                    "rationale": "no tools needed"
+                })
+                .to_string());
+            }
+            if request.user_prompt.contains("Validation errors:") {
+                return Ok(json!({"answer": "Ano"}).to_string());
+            }
+            // Byte 220, where the log line is cut, falls inside a `ž`.
+            Ok(format!("Odpověď: {}", "ž".repeat(300)))
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct StubWebSearchToolExecutor;
+
+    #[async_trait]
+    impl ToolExecutor for StubWebSearchToolExecutor {
+        async fn execute(
+            &self,
+            tool_name: &str,
+            args: Value,
+            _message_ctx: &MessageCtx,
+        ) -> anyhow::Result<ToolResult> {
+            if tool_name != "web_search" {
+                return Err(anyhow::anyhow!("unknown tool: {tool_name}"));
+            }
+
+            let query = args
+                .get("query")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("missing query arg"))?;
+
+            Ok(ToolResult {
+                text: format!("result:{query}"),
+                citations: vec![format!("https://example.com/{query}")],
+                data: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn persists_simple_name_fact() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+
+        let _ = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "1".into(),
+                user_id: "u1".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "my name is petr".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("handle message should succeed");
+
+        let facts = memory
+            .search_relevant("u1", "name", 10)
+            .await
+            .expect("search should succeed");
+        assert_eq!(facts.len(), 1);
+        assert_eq!(facts[0].key, "name");
+    }
+
+    #[tokio::test]
+    async fn approval_queue_holds_proposed_facts() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            fact_approval_queue: true,
+            ..OrchestratorConfig::default()
+        });
+
+        let _ = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "1".into(),
+                user_id: "u1".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "my name is petr".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("handle message should succeed");
+
+        assert!(
+            memory
+                .list_facts("u1", 10)
+                .await
+                .expect("list should succeed")
+                .is_empty()
+        );
+        let pending = memory
+            .list_pending_facts(Some("u1"), 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].fact.key, "name");
+    }
+
+    #[tokio::test]
+    async fn only_routed_turns_record_a_model_router_decision() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            model_routes: ModelRoutes {
+                complex: Some("strong".to_owned()),
+                ..ModelRoutes::default()
+            },
+            ..OrchestratorConfig::default()
+        });
+
+        for (message_id, content) in [
+            ("route-1", "my plan for tonight is pizza"),
+            ("route-2", "compare living in Prague and Vienna"),
+        ] {
+            orchestrator
+                .handle_message(MessageCtx {
+                    message_id: message_id.into(),
+                    user_id: "u-route".into(),
+                    guild_id: "g1".into(),
+                    channel_id: "c1".into(),
+                    content: content.into(),
+                    timestamp: Utc::now(),
+                    attachments: Vec::new(),
+                })
+                .await
+                .expect("turn should succeed");
+        }
+
+        let routed = memory
+            .list_planner_decisions("u-route", 20)
+            .await
+            .expect("decisions load")
+            .into_iter()
+            .filter(|record| record.planner == "model_router")
+            .collect::<Vec<_>>();
+        assert_eq!(routed.len(), 1);
+        assert_eq!(routed[0].decision, "complex");
+        assert!(routed[0].rationale.contains("strong"));
+    }
+
+    #[tokio::test]
+    async fn only_turns_that_ask_for_it_are_tracked_in_flight() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let model = Arc::new(InFlightProbeModelProvider {
+            memory: memory.clone(),
+            seen: std::sync::Mutex::default(),
+        });
+        let orchestrator = DefaultChatOrchestrator::new(
+            model.clone(),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            in_flight_tracking: true,
+            ..OrchestratorConfig::default()
+        });
+
+        for (message_id, track_in_flight) in [("http-1", false), ("discord-1", true)] {
+            orchestrator
+                .handle_message_with_options(
+                    MessageCtx {
+                        message_id: message_id.into(),
+                        user_id: "u-flight".into(),
+                        guild_id: "g1".into(),
+                        channel_id: "c1".into(),
+                        content: "hello there".into(),
+                        timestamp: Utc::now(),
+                        attachments: Vec::new(),
+                    },
+                    TurnOptions {
+                        track_in_flight,
+                        ..TurnOptions::default()
+                    },
+                )
+                .await
+                .expect("turn should succeed");
+            let seen = std::mem::take(&mut *model.seen.lock().expect("lock"));
+            let expected = usize::from(track_in_flight);
+            assert!(!seen.is_empty());
+            assert!(
+                seen.iter().all(|count| *count == expected),
+                "{message_id}: {seen:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn external_facts_are_cleaned_and_queued_like_planner_writes() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            fact_approval_queue: true,
+            ..OrchestratorConfig::default()
+        });
+        let fact = |key: &str, value: &str| MemoryFact {
+            key: key.into(),
+            value: value.into(),
+            confidence: 1.5,
+            source: "event:workout.completed".into(),
+            updated_at: Utc::now(),
+            last_confirmed_at: None,
+        };
+
+        let written = orchestrator
+            .write_external_fact("u1", fact("Last Workout", "5 km run"), "external_event")
+            .await
+            .expect("write should succeed")
+            .expect("fact is usable");
+        assert_eq!(written.key, "last_workout");
+        assert_eq!(written.confidence, 1.0);
+        assert_eq!(written.source, "event:workout.completed");
+        assert!(
+            orchestrator
+                .write_external_fact("u1", fact("!!", "x"), "external_event")
+                .await
+                .expect("write should succeed")
+                .is_none()
+        );
+
+        assert!(
+            memory
+                .list_facts("u1", 10)
+                .await
+                .expect("facts load")
+                .is_empty()
+        );
+        let pending = memory
+            .list_pending_facts(Some("u1"), 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].rationale, "external_event");
+    }
+
+    #[tokio::test]
+    async fn failed_turns_report_their_phase() {
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(FailingSynthesisModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+
+        let error = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "1".into(),
+                user_id: "u1".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "how was your day?".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect_err("synthesis fails");
+
+        let failure = error
+            .downcast_ref::<TurnFailure>()
+            .expect("error carries the failed phase");
+        assert_eq!(failure.phase, TurnPhase::Synthesis);
+        assert_eq!(failure.describe(), "while writing my reply");
+        assert!(format!("{error:#}").contains("upstream returned 502"));
+    }
+
+    #[tokio::test]
+    async fn statically_typed_orchestrator_handles_messages() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator: GenericChatOrchestrator<
+            MockModelProvider,
+            InMemoryMemoryStore,
+            ToolRegistry,
+        > = GenericChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+
+        orchestrator
+            .handle_message(MessageCtx {
+                message_id: "1".into(),
+                user_id: "u1".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "my name is petr".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("handle message should succeed");
+
+        let facts = memory
+            .list_facts("u1", 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(facts[0].value, "petr");
+    }
+
+    #[test]
+    fn cross_channel_turns_skip_the_current_channel_and_keep_dms_private() {
+        let message = |guild_id: &str, channel_id: &str, content: &str| ChatMessageRecord {
+            id: String::new(),
+            user_id: "u1".to_owned(),
+            guild_id: guild_id.to_owned(),
+            channel_id: channel_id.to_owned(),
+            role: ChatRole::User,
+            content: content.to_owned(),
+            timestamp: Utc::now(),
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        };
+        let history = vec![
+            message("g1", "c2", "my exam is on friday"),
+            message("g1", "c3", "wish me luck"),
+            message("g1", "c1", "hello"),
+            message("dm", "d1", "I'm nervous about it"),
+            message("g2", "c9", "my other server"),
+            message("g1", "c1", "as I said earlier"),
+        ];
+
+        assert_eq!(
+            cross_channel_turns(&history, "g1", "c1", 5),
+            vec![
+                "user in channel c2: my exam is on friday",
+                "user in channel c3: wish me luck",
+            ]
+        );
+        assert_eq!(
+            cross_channel_turns(&history, "g1", "c1", 1),
+            vec!["user in channel c3: wish me luck"]
+        );
+        assert_eq!(
+            cross_channel_turns(&history, DM_GUILD_ID, "d1", 2),
+            vec![
+                "user in channel c9: my other server",
+                "user in channel c1: as I said earlier"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn search_command_is_not_a_manual_override() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory,
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+
+        let result = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "2".into(),
+                user_id: "u2".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "/search rust".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("planner should be allowed to skip tool usage");
+
+        assert!(result.tool_calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tool_failure_is_included_in_final_synthesis_context() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+
+        let result = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "3".into(),
+                user_id: "u3".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "search the web for rust async traits".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("tool failure should still synthesize a final answer");
+
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].tool_name, "web_search");
+        assert!(result.text.contains("Status: error"));
+        assert!(result.text.contains("web_search tool is not configured"));
+    }
+
+    #[tokio::test]
+    async fn simulated_turns_serve_scripted_tool_outputs() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let simulated_tools = Arc::new(MockToolExecutor::default().with_response(
+            "web_search",
+            MockToolResponse {
+                text: "scripted: async fn in traits is stable".into(),
+                citations: vec!["https://example.com/async".into()],
+                data: Some(json!({"stable_since": "1.75"})),
+                error: None,
+            },
+        ));
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_simulated_tools(simulated_tools.clone());
+
+        let result = orchestrator
+            .handle_message_with_options(
+                MessageCtx {
+                    message_id: "sim-1".into(),
+                    user_id: "u-sim".into(),
+                    guild_id: "g1".into(),
+                    channel_id: "c1".into(),
+                    content: "search the web for rust async traits".into(),
+                    timestamp: Utc::now(),
+                    attachments: Vec::new(),
+                },
+                TurnOptions {
+                    simulate_tools: true,
+                    ..TurnOptions::default()
+                },
+            )
+            .await
+            .expect("simulated turn should succeed");
+
+        assert!(
+            result
+                .text
+                .contains("scripted: async fn in traits is stable")
+        );
+        assert!(result.text.contains(r#"Data: {"stable_since":"1.75"}"#));
+        assert_eq!(result.citations, vec!["https://example.com/async"]);
+        assert_eq!(simulated_tools.call_count("web_search"), 1);
+        let records = memory
+            .list_tool_calls("u-sim", 10)
+            .await
+            .expect("tool calls should load");
+        assert_eq!(records[0].source, "simulated_unified_planner");
+    }
+
+    #[tokio::test]
+    async fn simulated_now_playing_stays_out_of_the_cache() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let simulated_tools = Arc::new(MockToolExecutor::default().with_response(
+            "spotify_playing_status",
+            MockToolResponse {
+                text: "Now playing: Scripted Song by Test Band".into(),
+                citations: Vec::new(),
+                data: None,
+                error: None,
+            },
+        ));
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory,
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            now_playing_users: vec!["u-sim".into()],
+            ..OrchestratorConfig::default()
+        })
+        .with_simulated_tools(simulated_tools.clone());
+
+        for message_id in ["sim-np-1", "sim-np-2"] {
+            orchestrator
+                .handle_message_with_options(
+                    MessageCtx {
+                        message_id: message_id.into(),
+                        user_id: "u-sim".into(),
+                        guild_id: "g1".into(),
+                        channel_id: "c1".into(),
+                        content: "hello".into(),
+                        timestamp: Utc::now(),
+                        attachments: Vec::new(),
+                    },
+                    TurnOptions {
+                        simulate_tools: true,
+                        ..TurnOptions::default()
+                    },
+                )
+                .await
+                .expect("simulated turn should succeed");
+        }
+
+        assert_eq!(simulated_tools.call_count("spotify_playing_status"), 2);
+        assert!(orchestrator.now_playing.get("u-sim").is_none());
+    }
+
+    #[tokio::test]
+    async fn followup_planner_can_run_multiple_tool_rounds_before_final_answer() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(FollowupLoopModelProvider),
+            memory,
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        );
+
+        let result = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "3b".into(),
+                user_id: "u3b".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "find a final answer using tools".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("follow-up planning loop should complete");
+
+        assert_eq!(result.tool_calls.len(), 2);
+        assert_eq!(result.tool_calls[0].tool_name, "web_search");
+        assert_eq!(result.tool_calls[0].args["query"], "alpha");
+        assert_eq!(result.tool_calls[1].tool_name, "web_search");
+        assert_eq!(result.tool_calls[1].args["query"], "beta");
+        assert_eq!(result.text, "Final answer from follow-up planner.");
+        assert_eq!(result.citations.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalid_planner_json_is_corrected_with_a_retry() {
+        let ctx = MessageCtx {
+            message_id: "json-retry".into(),
+            user_id: "u-json".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "look up alpha".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MalformedPlanModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        );
+        let result = orchestrator
+            .handle_message(ctx.clone())
+            .await
+            .expect("corrected plan should succeed");
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.text, "Done after correction.");
+
+        let _logging = tracing::subscriber::set_default(FormattingSubscriber);
+        let result = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "json-retry-czech".into(),
+                content: "vyhledej alpha".into(),
+                ..ctx.clone()
+            })
+            .await
+            .expect("corrected non-ASCII plan should succeed");
+        assert_eq!(result.tool_calls.len(), 1);
+
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MalformedPlanModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            planner_json_retries: 0,
+            ..OrchestratorConfig::default()
+        });
+        let result = orchestrator
+            .handle_message(ctx)
+            .await
+            .expect("planner fallback should still reply");
+        assert!(result.tool_calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn long_non_ascii_replies_are_corrected_into_the_json_schema() {
+        let _logging = tracing::subscriber::set_default(FormattingSubscriber);
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(CzechProseModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        let result = orchestrator
+            .handle_message_with_options(
+                MessageCtx {
+                    message_id: "json-czech".into(),
+                    user_id: "u-json".into(),
+                    guild_id: "g1".into(),
+                    channel_id: "c1".into(),
+                    content: "odpověz ano nebo ne".into(),
+                    timestamp: Utc::now(),
+                    attachments: Vec::new(),
+                },
+                TurnOptions {
+                    response_schema: Some(json!({
+                        "type": "object",
+                        "properties": {"answer": {"type": "string"}},
+                        "required": ["answer"]
+                    })),
+                    ..TurnOptions::default()
+                },
+            )
+            .await
+            .expect("corrected JSON reply");
+        assert_eq!(result.json, Some(json!({"answer": "Ano"})));
+    }
+
+    #[test]
+    fn truncate_for_log_cuts_at_a_char_boundary() {
+        assert_eq!(truncate_for_log("žžž", 3), "ž...");
+        assert_eq!(truncate_for_log("a\nb", 10), "a\\nb");
+    }
+
+    #[derive(Debug, Default)]
+    struct SlowToolExecutor;
+
+    #[async_trait]
+    impl ToolExecutor for SlowToolExecutor {
+        async fn execute(
+            &self,
+            _tool_name: &str,
+            _args: Value,
+            _message_ctx: &MessageCtx,
+        ) -> anyhow::Result<ToolResult> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(ToolResult {
+                text: "too late".to_owned(),
+                citations: Vec::new(),
+                data: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn timed_out_tool_becomes_failed_output() {
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(SlowToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            tool_timeout_overrides: OrchestratorConfig::parse_tool_timeout_overrides(
+                "web_search=50, broken, other=",
+            ),
+            ..OrchestratorConfig::default()
+        });
+
+        let result = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "timeout".into(),
+                user_id: "u-timeout".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "search the web for rust async traits".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("timed out tool should still synthesize a final answer");
+
+        assert_eq!(result.tool_calls.len(), 1);
+        assert!(result.text.contains("web_search timed out after 50 ms"));
+    }
+
+    #[tokio::test]
+    async fn tool_quota_refuses_calls_over_the_daily_limit() {
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            tool_quotas: crate::quotas::parse_tool_quotas("web_search=1"),
+            ..OrchestratorConfig::default()
+        });
+        let message = |id: &str| MessageCtx {
+            message_id: id.into(),
+            user_id: "u-quota".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "search the web for rust async traits".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let first = orchestrator
+            .handle_message(message("quota-1"))
+            .await
+            .expect("first search is within quota");
+        assert!(first.text.contains("result:rust async traits"));
+
+        let second = orchestrator
+            .handle_message(message("quota-2"))
+            .await
+            .expect("over-quota search still produces a reply");
+        assert!(second.text.contains("daily limit of 1 `web_search` calls"));
+
+        let status = orchestrator
+            .tool_quota_status("u-quota")
+            .await
+            .expect("quota status");
+        assert_eq!((status[0].used, status[0].limit), (1, 1));
+        assert_eq!(
+            orchestrator
+                .reset_tool_quota("u-quota", Some("web_search"))
+                .await
+                .expect("reset"),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn shadow_tools_run_but_stay_out_of_the_reply() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            shadow_tools: std::collections::HashSet::from(["web_search".to_owned()]),
+            ..OrchestratorConfig::default()
+        });
+
+        let reply = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "shadow-1".into(),
+                user_id: "u-shadow".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "search the web for rust async traits".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("message should succeed");
+        assert!(reply.tool_calls.is_empty());
+        assert!(reply.citations.is_empty());
+        assert!(!reply.text.contains("result:rust async traits"));
+
+        let calls = memory
+            .list_tool_calls("u-shadow", 10)
+            .await
+            .expect("tool calls");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].source, "shadow_unified_planner");
+        assert!(calls[0].result_text.contains("result:rust async traits"));
+
+        // The follow-up planner never sees the output, so it is not asked
+        // to run the tool again.
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(RepeatingSearchModelProvider),
+            memory.clone(),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            shadow_tools: std::collections::HashSet::from(["web_search".to_owned()]),
+            ..OrchestratorConfig::default()
+        });
+        orchestrator
+            .handle_message(MessageCtx {
+                message_id: "shadow-2".into(),
+                user_id: "u-shadow-2".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "look up alpha".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("message should succeed");
+        let calls = memory
+            .list_tool_calls("u-shadow-2", 10)
+            .await
+            .expect("tool calls");
+        assert_eq!(calls.len(), 1);
+
+        // Logging a long non-ASCII result must not break the user's turn.
+        let _logging = tracing::subscriber::set_default(FormattingSubscriber);
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            shadow_tools: std::collections::HashSet::from(["web_search".to_owned()]),
+            ..OrchestratorConfig::default()
+        });
+        orchestrator
+            .handle_message(MessageCtx {
+                message_id: "shadow-3".into(),
+                user_id: "u-shadow-3".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: format!("search the web for {}", "ž".repeat(700)),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("message should succeed");
+        let calls = memory
+            .list_tool_calls("u-shadow-3", 10)
+            .await
+            .expect("tool calls");
+        assert_eq!(calls.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn background_tools_reply_at_once_and_deliver_later() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let (background, mut results) = BackgroundTasks::channel(
+            std::collections::HashSet::from(["web_search".to_owned()]),
+            DEFAULT_BACKGROUND_TIMEOUT,
+        );
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_background_tasks(background)
+        .with_redactor(Arc::new(Redactor::new(&[], &["traits".to_owned()], &[])));
+
+        let reply = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "bg-1".into(),
+                user_id: "u-bg".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "search the web for rust async traits".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("message should succeed");
+        assert!(reply.text.contains("Started background task"));
+
+        let finished = tokio::time::timeout(Duration::from_secs(5), results.recv())
+            .await
+            .expect("task should finish")
+            .expect("task result");
+        assert_eq!(finished.status, BackgroundTaskStatus::Done);
+        assert!(
+            finished
+                .result_text
+                .as_deref()
+                .is_some_and(|text| text.contains("result:rust async traits"))
+        );
+        let stored = memory
+            .list_background_tasks(Some("u-bg"), &[BackgroundTaskStatus::Done], 10)
+            .await
+            .expect("tasks should load");
+        assert_eq!(stored.len(), 1);
+        // Only the delivered copy keeps what the redactor removes.
+        assert!(
+            stored[0]
+                .result_text
+                .as_deref()
+                .is_some_and(|text| text.contains("result:rust async [redacted]"))
+        );
+        let history = memory
+            .list_chat_messages("u-bg", 10)
+            .await
+            .expect("history should load");
+        assert!(
+            history
+                .last()
+                .is_some_and(|message| message.content.contains("result:rust async [redacted]"))
+        );
+    }
+
+    #[tokio::test]
+    async fn background_tools_follow_guild_settings_and_the_rate_limit() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let (background, _results) = BackgroundTasks::channel(
+            std::collections::HashSet::from(["web_search".to_owned()]),
+            DEFAULT_BACKGROUND_TIMEOUT,
+        );
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(StubWebSearchToolExecutor),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            user_rate_limit: Some(2),
+            ..OrchestratorConfig::default()
+        })
+        .with_background_tasks(background);
+        memory
+            .put_guild_settings(GuildSettings {
+                guild_id: "g-locked".into(),
+                enabled_tools: Some(vec!["current_datetime".into()]),
+                ..GuildSettings::default()
+            })
+            .await
+            .expect("settings stored");
+        let ctx = |guild_id: &str| MessageCtx {
+            message_id: "research-1".into(),
+            user_id: "u-bg".into(),
+            guild_id: guild_id.into(),
+            channel_id: "c1".into(),
+            content: "rust async traits".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        let start = |guild_id: &'static str| {
+            let orchestrator = &orchestrator;
+            async move {
+                orchestrator
+                    .start_background_tool(
+                        &ctx(guild_id),
+                        TaskOrigin::Discord,
+                        "web_search",
+                        json!({ "query": "rust async traits" }),
+                    )
+                    .await
+            }
+        };
+
+        let refused = start("g-locked").await.expect_err("tool is disabled");
+        assert!(refused.to_string().contains("not enabled here"));
+        assert!(start("g1").await.expect("started").is_some());
+        let limited = start("g1").await.expect_err("rate limited");
+        assert!(limited.downcast_ref::<RateLimited>().is_some());
+    }
+
+    #[tokio::test]
+    async fn resumed_tasks_are_claimed_once_and_only_discord_ones_delivered() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        for origin in [TaskOrigin::Discord, TaskOrigin::Http] {
+            memory
+                .create_background_task(BackgroundTaskRecord {
+                    id: String::new(),
+                    user_id: "u-bg".into(),
+                    guild_id: "g1".into(),
+                    channel_id: "123".into(),
+                    tool_name: "web_search".into(),
+                    args: json!({}),
+                    origin,
+                    status: BackgroundTaskStatus::Done,
+                    result_text: Some("done".into()),
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                })
+                .await
+                .expect("task stored");
+        }
+        let coordinator: Arc<dyn InstanceCoordinator> = Arc::new(LocalCoordinator::default());
+        let mut delivered = Vec::new();
+        for _replica in 0..2 {
+            let (background, mut results) = BackgroundTasks::channel(
+                std::collections::HashSet::from(["web_search".to_owned()]),
+                DEFAULT_BACKGROUND_TIMEOUT,
+            );
+            let orchestrator = DefaultChatOrchestrator::new(
+                Arc::new(MockModelProvider),
+                memory.clone(),
+                Arc::new(StubWebSearchToolExecutor),
+                SafetyPolicy::default(),
+            )
+            .with_background_tasks(background)
+            .with_coordinator(coordinator.clone());
+            orchestrator
+                .resume_background_tasks()
+                .await
+                .expect("tasks resume");
+            while let Ok(task) = results.try_recv() {
+                delivered.push(task.origin);
+            }
+        }
+        assert_eq!(delivered, [TaskOrigin::Discord]);
+        assert_eq!(
+            task_origin(&TurnOptions {
+                surface: Some(ContextSurface::Dashboard),
+                ..TurnOptions::default()
+            }),
+            TaskOrigin::Http
+        );
+    }
+
+    #[tokio::test]
+    async fn regenerate_replaces_last_assistant_reply() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        orchestrator
+            .handle_message(MessageCtx {
+                message_id: "regen-1".into(),
+                user_id: "u-regen".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "tell me a joke".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("message should succeed");
+
+        let reply = orchestrator
+            .regenerate_last_reply("u-regen", Some("c1"), GenerationParams::default())
+            .await
+            .expect("regenerate should succeed")
+            .expect("there is a message to regenerate");
+        assert!(!reply.text.is_empty());
+
+        let history = memory
+            .list_chat_messages("u-regen", 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].content, "tell me a joke");
+        assert_eq!(history[1].content, reply.text);
+
+        let nothing = orchestrator
+            .regenerate_last_reply("u-regen", Some("other"), GenerationParams::default())
+            .await
+            .expect("regenerate should succeed");
+        assert!(nothing.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_regenerate_keeps_the_exchange() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(FailingSynthesisModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        for (id, role, content) in [
+            ("regen-1", ChatRole::User, "tell me a joke"),
+            (
+                "regen-1-assistant",
+                ChatRole::Assistant,
+                "Why did the crab...",
+            ),
+        ] {
+            memory
+                .record_chat_message(ChatMessageRecord {
+                    id: id.to_owned(),
+                    user_id: "u-regen".to_owned(),
+                    guild_id: "g1".to_owned(),
+                    channel_id: "c1".to_owned(),
+                    role,
+                    content: content.to_owned(),
+                    timestamp: Utc::now(),
+                    modality: Modality::Text,
+                    session_id: None,
+                    pinned: false,
+                    source_message_id: None,
                 })
-                .to_string());
-            }
+                .await
+                .expect("recorded");
+        }
 
-            if request
-                .system_prompt
-                .contains("You are the tool follow-up planner for CompanionPilot.")
-            {
-                if request.user_prompt.contains("result:alpha")
-                    && !request.user_prompt.contains("result:beta")
-                {
-                    return Ok(json!({
-                        "action": "tools",
-                        "final_answer": "",
-                        "tool_calls": [
-                            {
-                                "tool_name": "web_search",
-                                "args": {
-                                    "query": "beta",
-                                    "max_results": 2
-                                }
-                            }
-                        ],
-                        "rationale": "need second lookup"
-                    })
-                    .to_string());
-                }
+        orchestrator
+            .regenerate_last_reply("u-regen", Some("c1"), GenerationParams::default())
+            .await
+            .expect_err("synthesis fails");
 
-                if request.user_prompt.contains("result:beta") {
-                    return Ok(json!({
-                        "action": "final",
-                        "final_answer": "Final answer from follow-up planner.",
-                        "tool_calls": [],
-                        "rationale": "have enough evidence"
+        let history = memory
+            .list_chat_messages("u-regen", 10)
+            .await
+            .expect("history should load");
+        let contents = history
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["tell me a joke", "Why did the crab..."]);
+    }
+
+    #[test]
+    fn regenerating_drops_the_last_exchange_from_context() {
+        let mut recent = vec![
+            "user: hi".to_owned(),
+            "assistant: hello".to_owned(),
+            "user (voice): tell me a joke".to_owned(),
+            "assistant: Why did the crab...".to_owned(),
+        ];
+        drop_last_exchange(&mut recent);
+        assert_eq!(recent, ["user: hi", "assistant: hello"]);
+    }
+
+    #[tokio::test]
+    async fn proactive_messages_in_servers_leave_out_facts() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(EchoModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        memory
+            .upsert_fact(
+                "u1",
+                MemoryFact {
+                    key: "favorite_color".into(),
+                    value: "teal".into(),
+                    confidence: 0.9,
+                    source: "user_message".into(),
+                    updated_at: Utc::now(),
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("stored");
+
+        let compose = |guild_id: &'static str| {
+            orchestrator.compose_proactive_message("u1", guild_id, "c1", "Say happy birthday.")
+        };
+        let in_dm = compose(DM_GUILD_ID)
+            .await
+            .expect("composed")
+            .expect("written");
+        assert!(in_dm.contains("teal"));
+        let in_server = compose("g1").await.expect("composed").expect("written");
+        assert!(!in_server.contains("teal"));
+    }
+
+    #[tokio::test]
+    async fn forget_requests_respect_skipped_memory_writes() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        memory
+            .upsert_fact(
+                "u-forget",
+                MemoryFact {
+                    key: "favorite_color".into(),
+                    value: "teal".into(),
+                    confidence: 0.9,
+                    source: "user_message".into(),
+                    updated_at: Utc::now(),
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("stored");
+
+        let forget = |message_id: &str, skip_memory_write: bool| {
+            orchestrator.handle_message_with_options(
+                MessageCtx {
+                    message_id: message_id.into(),
+                    user_id: "u-forget".into(),
+                    guild_id: "g1".into(),
+                    channel_id: "c1".into(),
+                    content: "forget my favorite color".into(),
+                    timestamp: Utc::now(),
+                    attachments: Vec::new(),
+                },
+                TurnOptions {
+                    skip_memory_write,
+                    ..TurnOptions::default()
+                },
+            )
+        };
+        let refused = forget("f-1", true).await.expect("turn should succeed");
+        assert!(refused.text.contains("turned off"));
+        let facts = memory.list_facts("u-forget", 10).await.expect("facts load");
+        assert_eq!(facts.len(), 1);
+
+        let forgotten = forget("f-2", false).await.expect("turn should succeed");
+        assert!(forgotten.text.contains("forgotten your favorite color"));
+        let facts = memory.list_facts("u-forget", 10).await.expect("facts load");
+        assert!(facts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn voice_turns_ask_for_spoken_replies() {
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(EchoModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        let message = |channel_id: &str| MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: channel_id.into(),
+            content: "hi there".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let spoken = orchestrator
+            .handle_voice_transcript(message("voice"))
+            .await
+            .expect("voice turn");
+        assert!(spoken.contains("This reply will be spoken aloud."));
+        let written = orchestrator
+            .handle_message(message("text"))
+            .await
+            .expect("text turn");
+        assert!(!written.text.contains("spoken aloud"));
+    }
+
+    #[tokio::test]
+    async fn adapters_drive_the_builtin_orchestrator_through_the_trait() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator: Arc<dyn ChatOrchestrator> = Arc::new(DefaultChatOrchestrator::new(
+            Arc::new(EchoModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        ));
+        let message = MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u-trait".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "hi there".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let reply = orchestrator
+            .handle_message_with_system_prompt_override(
+                message,
+                Some("You are a pirate named Flint.".to_owned()),
+            )
+            .await
+            .expect("turn through the trait");
+        assert!(reply.text.contains("You are a pirate named Flint."));
+
+        let regenerated = orchestrator
+            .regenerate_last_reply("u-trait", Some("c1"), GenerationParams::default())
+            .await
+            .expect("regenerated through the trait")
+            .expect("there is a message to answer again");
+        assert!(!regenerated.text.contains("pirate"));
+        assert!(
+            orchestrator
+                .regenerate_last_reply("u-nobody", None, GenerationParams::default())
+                .await
+                .expect("nothing to regenerate")
+                .is_none()
+        );
+        assert!(
+            orchestrator
+                .tool_quota_status("u-trait")
+                .await
+                .expect("quota status")
+                .iter()
+                .all(|status| status.used == 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn planner_examples_come_from_the_users_own_decisions() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(EchoModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        for (user_id, input) in [
+            ("u1", "weather in Prague?"),
+            ("u2", "my diagnosis explained"),
+        ] {
+            memory
+                .record_planner_decision(PlannerDecisionRecord {
+                    id: String::new(),
+                    user_id: user_id.into(),
+                    guild_id: "g1".into(),
+                    channel_id: "c1".into(),
+                    planner: "unified".into(),
+                    decision: "apply_plan".into(),
+                    rationale: String::new(),
+                    payload_json: json!({
+                        "user_input": input,
+                        "tool_calls": [{"tool_name": "web_search", "args": {"query": input}}],
+                        "rejected_tool_calls": [],
                     })
-                    .to_string());
-                }
-            }
+                    .to_string(),
+                    success: true,
+                    error: None,
+                    timestamp: Utc::now(),
+                })
+                .await
+                .expect("recorded");
+        }
+        let ctx = MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "hi".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
 
-            Ok("fallback final synthesis".to_owned())
+        let examples = orchestrator.load_planner_examples(&ctx, 3).await;
+        assert_eq!(examples.len(), 1);
+        assert_eq!(examples[0].user_input, "weather in Prague?");
+    }
+
+    #[tokio::test]
+    async fn model_handoffs_stay_in_their_channel() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(EchoModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        for (channel_id, content) in [("c1", "planning a trip to Lisbon"), ("c2", "my bank PIN")] {
+            memory
+                .record_chat_message(ChatMessageRecord {
+                    id: format!("{channel_id}-1"),
+                    user_id: "u1".into(),
+                    guild_id: "g1".into(),
+                    channel_id: channel_id.into(),
+                    role: ChatRole::User,
+                    content: content.into(),
+                    timestamp: Utc::now(),
+                    modality: Modality::Text,
+                    session_id: None,
+                    pinned: false,
+                    source_message_id: None,
+                })
+                .await
+                .expect("recorded");
         }
+        let ctx = |channel_id: &str| MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: channel_id.into(),
+            content: "hi".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        let params = GenerationParams::default();
+
+        assert_eq!(
+            orchestrator
+                .model_handoff(&ctx("c1"), "model-a", params.clone())
+                .await,
+            None
+        );
+        let summary = orchestrator
+            .model_handoff(&ctx("c1"), "model-b", params.clone())
+            .await
+            .expect("switch is handed off");
+        assert!(summary.contains("Lisbon"));
+        assert!(!summary.contains("PIN"));
+        // The other channel never saw a reply from model-a.
+        assert_eq!(
+            orchestrator
+                .model_handoff(&ctx("c2"), "model-b", params)
+                .await,
+            None
+        );
     }
 
-    #[derive(Debug, Default)]
-    struct StubWebSearchToolExecutor;
+    #[tokio::test]
+    async fn replaying_a_planner_decision_reports_an_unchanged_plan() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        orchestrator
+            .handle_message(MessageCtx {
+                message_id: "replay-1".into(),
+                user_id: "u-replay".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "search the web for rust async traits".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("handle message should succeed");
 
-    #[async_trait]
-    impl ToolExecutor for StubWebSearchToolExecutor {
-        async fn execute(
-            &self,
-            tool_name: &str,
-            args: Value,
-            _message_ctx: &MessageCtx,
-        ) -> anyhow::Result<ToolResult> {
-            if tool_name != "web_search" {
-                return Err(anyhow::anyhow!("unknown tool: {tool_name}"));
-            }
+        let decisions = memory
+            .list_planner_decisions("u-replay", 10)
+            .await
+            .expect("decisions should load");
+        let unified = decisions
+            .iter()
+            .find(|decision| decision.planner == "unified")
+            .expect("unified decision should be recorded");
+        let replay = orchestrator
+            .replay_planner_decision(&unified.id)
+            .await
+            .expect("replay should succeed")
+            .expect("decision should exist");
+        assert_eq!(replay.user_input, "search the web for rust async traits");
+        assert_eq!(replay.original.tool_calls.len(), 1);
+        assert!(!replay.diff.changed);
 
-            let query = args
-                .get("query")
-                .and_then(Value::as_str)
-                .ok_or_else(|| anyhow::anyhow!("missing query arg"))?;
+        let followup = decisions
+            .iter()
+            .find(|decision| decision.planner != "unified")
+            .expect("follow-up decision should be recorded");
+        assert!(
+            orchestrator
+                .replay_planner_decision(&followup.id)
+                .await
+                .is_err()
+        );
+        assert!(
+            orchestrator
+                .replay_planner_decision("missing")
+                .await
+                .expect("unknown ids are not errors")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn voice_turns_are_recorded_with_voice_modality() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        orchestrator
+            .handle_voice_transcript(MessageCtx {
+                message_id: "voice-1".into(),
+                user_id: "voice:g1:c1".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "tell me a joke".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("voice turn should succeed");
+
+        let history = memory
+            .list_chat_messages("voice:g1:c1", 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 2);
+        assert!(
+            history
+                .iter()
+                .all(|message| message.modality == Modality::Voice)
+        );
+        let context = memory
+            .load_context("voice:g1:c1", "g1", "c1", ContextLimits::default())
+            .await
+            .expect("context should load");
+        assert!(context.recent_messages[0].starts_with("user (voice): tell me a joke"));
+    }
+
+    #[tokio::test]
+    async fn voice_turns_are_admitted_unless_nested_in_an_admitted_turn() {
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            max_concurrent_orchestrations: Some(1),
+            user_rate_limit: Some(1),
+            ..OrchestratorConfig::default()
+        });
+        let transcript = |message_id: &str| MessageCtx {
+            message_id: message_id.into(),
+            user_id: "voice:g1:c1".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "tell me a joke".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        orchestrator
+            .handle_voice_transcript(transcript("voice-1"))
+            .await
+            .expect("voice turn should succeed");
+        let limited = orchestrator
+            .handle_voice_transcript(transcript("voice-2"))
+            .await
+            .expect_err("second turn is over the rate limit");
+        assert!(limited.downcast_ref::<RateLimited>().is_some());
+
+        // The calling turn holds the only permit.
+        let _permit = orchestrator
+            .limiter
+            .as_ref()
+            .expect("limiter configured")
+            .acquire()
+            .await
+            .expect("permit");
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            orchestrator.handle_nested_voice_transcript(transcript("voice-3")),
+        )
+        .await
+        .expect("nested turn does not wait for a permit")
+        .expect("nested voice turn should succeed");
+    }
+
+    #[tokio::test]
+    async fn name_correction_overwrites_previous_memory() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+
+        let _ = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "4".into(),
+                user_id: "u4".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "my name is Petrr".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("first message should succeed");
+
+        let _ = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "5".into(),
+                user_id: "u4".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "I misspelled my name, it's Petr.".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
+            .await
+            .expect("correction message should succeed");
+
+        let facts = memory
+            .search_relevant("u4", "name", 10)
+            .await
+            .expect("search should succeed");
+        assert_eq!(facts.len(), 1);
+        assert_eq!(facts[0].value, "Petr");
+    }
+
+    #[tokio::test]
+    async fn restated_fact_is_reinforced() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
 
-            Ok(ToolResult {
-                text: format!("result:{query}"),
-                citations: vec![format!("https://example.com/{query}")],
-            })
+        for message_id in ["4b", "4c"] {
+            let _ = orchestrator
+                .handle_message(MessageCtx {
+                    message_id: message_id.into(),
+                    user_id: "u4b".into(),
+                    guild_id: "g1".into(),
+                    channel_id: "c1".into(),
+                    content: "my name is Petr".into(),
+                    timestamp: Utc::now(),
+                    attachments: Vec::new(),
+                })
+                .await
+                .expect("message should succeed");
         }
+
+        let facts = memory
+            .list_facts("u4b", 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(facts.len(), 1);
+        assert!(facts[0].confidence > 0.96);
+        assert!(facts[0].last_confirmed_at.is_some());
     }
 
     #[tokio::test]
-    async fn persists_simple_name_fact() {
+    async fn short_term_memory_includes_recent_non_fact_turns() {
         let memory = Arc::new(InMemoryMemoryStore::default());
         let orchestrator = DefaultChatOrchestrator::new(
             Arc::new(MockModelProvider),
-            memory.clone(),
+            memory,
             Arc::new(ToolRegistry::default()),
             SafetyPolicy::default(),
         );
 
         let _ = orchestrator
             .handle_message(MessageCtx {
-                message_id: "1".into(),
-                user_id: "u1".into(),
+                message_id: "6".into(),
+                user_id: "u6".into(),
                 guild_id: "g1".into(),
                 channel_id: "c1".into(),
-                content: "my name is petr".into(),
+                content: "I am 24 years old.".into(),
                 timestamp: Utc::now(),
+                attachments: Vec::new(),
             })
             .await
-            .expect("handle message should succeed");
+            .expect("first message should succeed");
 
-        let facts = memory
-            .search_relevant("u1", "name", 10)
+        let second = orchestrator
+            .handle_message(MessageCtx {
+                message_id: "7".into(),
+                user_id: "u6".into(),
+                guild_id: "g1".into(),
+                channel_id: "c1".into(),
+                content: "What did I just tell you?".into(),
+                timestamp: Utc::now(),
+                attachments: Vec::new(),
+            })
             .await
-            .expect("search should succeed");
-        assert_eq!(facts.len(), 1);
-        assert_eq!(facts[0].key, "name");
+            .expect("second message should succeed");
+
+        assert!(second.text.contains("Recent conversation turns:"));
+        assert!(second.text.contains("user: I am 24 years old."));
     }
 
     #[tokio::test]
-    async fn search_command_is_not_a_manual_override() {
+    async fn idle_gap_starts_a_new_session_and_scopes_context_to_it() {
         let memory = Arc::new(InMemoryMemoryStore::default());
         let orchestrator = DefaultChatOrchestrator::new(
             Arc::new(MockModelProvider),
-            memory,
+            memory.clone(),
             Arc::new(ToolRegistry::default()),
             SafetyPolicy::default(),
-        );
+        )
+        .with_config(OrchestratorConfig {
+            session_scoped_context: true,
+            session_titles: false,
+            ..OrchestratorConfig::default()
+        });
+        let message = |id: &str, content: &str, timestamp| MessageCtx {
+            message_id: id.into(),
+            user_id: "u9".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: content.into(),
+            timestamp,
+            attachments: Vec::new(),
+        };
 
-        let result = orchestrator
-            .handle_message(MessageCtx {
-                message_id: "2".into(),
-                user_id: "u2".into(),
-                guild_id: "g1".into(),
-                channel_id: "c1".into(),
-                content: "/search rust".into(),
-                timestamp: Utc::now(),
-            })
+        orchestrator
+            .handle_message(message(
+                "1",
+                "My sister is visiting.",
+                Utc::now() - chrono::Duration::hours(3),
+            ))
             .await
-            .expect("planner should be allowed to skip tool usage");
+            .expect("first message should succeed");
+        orchestrator
+            .handle_message(message("2", "Any tips for Rust?", Utc::now()))
+            .await
+            .expect("second message should succeed");
+        let third = orchestrator
+            .handle_message(message("3", "And for async?", Utc::now()))
+            .await
+            .expect("third message should succeed");
 
-        assert!(result.tool_calls.is_empty());
+        assert!(third.text.contains("user: Any tips for Rust?"));
+        assert!(!third.text.contains("My sister is visiting."));
+
+        let sessions = memory
+            .list_chat_sessions("u9", 10)
+            .await
+            .expect("sessions should load");
+        assert_eq!(sessions.len(), 2);
+        let current = memory
+            .list_session_messages("u9", &sessions[0].id, 10)
+            .await
+            .expect("session messages should load");
+        assert_eq!(current.len(), 4);
+        assert_eq!(current[0].content, "Any tips for Rust?");
     }
 
     #[tokio::test]
-    async fn tool_failure_is_included_in_final_synthesis_context() {
+    async fn pinned_messages_stay_in_context_after_scrolling_out() {
         let memory = Arc::new(InMemoryMemoryStore::default());
         let orchestrator = DefaultChatOrchestrator::new(
             Arc::new(MockModelProvider),
@@ -1518,58 +6367,119 @@ mod tests {
             Arc::new(ToolRegistry::default()),
             SafetyPolicy::default(),
         );
+        let message = |id: usize, guild_id: &str, content: &str| MessageCtx {
+            message_id: id.to_string(),
+            user_id: "u10".into(),
+            guild_id: guild_id.into(),
+            channel_id: "c1".into(),
+            content: content.into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
 
-        let result = orchestrator
-            .handle_message(MessageCtx {
-                message_id: "3".into(),
-                user_id: "u3".into(),
-                guild_id: "g1".into(),
-                channel_id: "c1".into(),
-                content: "search the web for rust async traits".into(),
-                timestamp: Utc::now(),
-            })
+        orchestrator
+            .handle_message(message(1, "g1", "My cat is called Miso."))
             .await
-            .expect("tool failure should still synthesize a final answer");
+            .expect("first message should succeed");
+        orchestrator
+            .handle_message(message(2, "g2", "My dog is called Rex."))
+            .await
+            .expect("other server message should succeed");
+        for pinned in ["1", "2"] {
+            assert!(
+                memory
+                    .set_chat_message_pinned("u10", pinned, true)
+                    .await
+                    .expect("pin should succeed")
+            );
+        }
+        for id in 3..9 {
+            orchestrator
+                .handle_message(message(id, "g1", "Tell me something."))
+                .await
+                .expect("filler message should succeed");
+        }
+        let reply = orchestrator
+            .handle_message(message(9, "g1", "What is my cat called?"))
+            .await
+            .expect("last message should succeed");
 
-        assert_eq!(result.tool_calls.len(), 1);
-        assert_eq!(result.tool_calls[0].tool_name, "web_search");
-        assert!(result.text.contains("Status: error"));
-        assert!(result.text.contains("web_search tool is not configured"));
+        assert!(reply.text.contains("Pinned messages"));
+        assert!(reply.text.contains(": My cat is called Miso."));
+        assert!(!reply.text.contains("My dog is called Rex."));
     }
 
     #[tokio::test]
-    async fn followup_planner_can_run_multiple_tool_rounds_before_final_answer() {
+    async fn ambient_readings_reach_listed_users_in_dms_only() {
+        let ambient = Arc::new(crate::ambient::AmbientContext::new(
+            crate::ambient::parse_ambient_topics("home/temp=living room temperature")
+                .expect("valid topics"),
+            Duration::from_secs(60),
+        ));
+        assert!(ambient.record("home/temp", b"21.5"));
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            ambient_context_users: vec!["u-home".to_owned()],
+            ..OrchestratorConfig::default()
+        })
+        .with_ambient_context(ambient);
+        let message = |id: &str, user_id: &str, guild_id: &str| MessageCtx {
+            message_id: id.into(),
+            user_id: user_id.into(),
+            guild_id: guild_id.into(),
+            channel_id: "c1".into(),
+            content: "Is it warm at home?".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        for (ctx, shown) in [
+            (message("a1", "u-home", DM_GUILD_ID), true),
+            (message("a2", "u-home", "g1"), false),
+            (message("a3", "u-other", DM_GUILD_ID), false),
+        ] {
+            let reply = orchestrator
+                .handle_message(ctx)
+                .await
+                .expect("message should succeed");
+            assert_eq!(reply.text.contains("living room temperature"), shown);
+        }
+    }
+
+    #[tokio::test]
+    async fn handled_messages_are_recorded_as_turns() {
         let memory = Arc::new(InMemoryMemoryStore::default());
         let orchestrator = DefaultChatOrchestrator::new(
-            Arc::new(FollowupLoopModelProvider),
-            memory,
-            Arc::new(StubWebSearchToolExecutor),
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
             SafetyPolicy::default(),
         );
-
-        let result = orchestrator
+        orchestrator
             .handle_message(MessageCtx {
-                message_id: "3b".into(),
-                user_id: "u3b".into(),
+                message_id: "m1".into(),
+                user_id: "u11".into(),
                 guild_id: "g1".into(),
                 channel_id: "c1".into(),
-                content: "find a final answer using tools".into(),
+                content: "Hello there.".into(),
                 timestamp: Utc::now(),
+                attachments: Vec::new(),
             })
             .await
-            .expect("follow-up planning loop should complete");
+            .expect("message should succeed");
 
-        assert_eq!(result.tool_calls.len(), 2);
-        assert_eq!(result.tool_calls[0].tool_name, "web_search");
-        assert_eq!(result.tool_calls[0].args["query"], "alpha");
-        assert_eq!(result.tool_calls[1].tool_name, "web_search");
-        assert_eq!(result.tool_calls[1].args["query"], "beta");
-        assert_eq!(result.text, "Final answer from follow-up planner.");
-        assert_eq!(result.citations.len(), 2);
+        assert!(memory.has_recorded_turn("u11", "m1").await.unwrap());
+        assert!(!memory.has_recorded_turn("u11", "m2").await.unwrap());
+        assert!(!memory.has_recorded_turn("u12", "m1").await.unwrap());
     }
 
     #[tokio::test]
-    async fn name_correction_overwrites_previous_memory() {
+    async fn system_notices_reach_only_the_next_turn() {
         let memory = Arc::new(InMemoryMemoryStore::default());
         let orchestrator = DefaultChatOrchestrator::new(
             Arc::new(MockModelProvider),
@@ -1577,75 +6487,209 @@ mod tests {
             Arc::new(ToolRegistry::default()),
             SafetyPolicy::default(),
         );
+        let now = Utc::now();
+        for (text, expires_at) in [
+            (
+                "The weather tool is down today.",
+                now + chrono::Duration::hours(1),
+            ),
+            (
+                "Old maintenance window.",
+                now - chrono::Duration::minutes(1),
+            ),
+        ] {
+            memory
+                .add_system_notice(SystemNoticeRecord {
+                    id: String::new(),
+                    user_id: "u12".into(),
+                    text: text.into(),
+                    created_at: now,
+                    expires_at,
+                })
+                .await
+                .expect("notice should be stored");
+        }
+        let message = |id: &str, channel_id: &str| MessageCtx {
+            message_id: id.into(),
+            user_id: "u12".into(),
+            guild_id: "g1".into(),
+            channel_id: channel_id.into(),
+            content: "Hello there".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        // A turn that fails leaves the notice for the next one.
+        let failing = DefaultChatOrchestrator::new(
+            Arc::new(FailingSynthesisModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        failing
+            .handle_message(message("0", "c0"))
+            .await
+            .expect_err("synthesis fails");
+        assert_eq!(
+            memory
+                .list_system_notices("u12", Utc::now())
+                .await
+                .expect("list should succeed")
+                .len(),
+            1
+        );
 
-        let _ = orchestrator
+        let first = orchestrator
+            .handle_message(message("1", "c1"))
+            .await
+            .expect("first message should succeed");
+        assert!(first.text.contains("The weather tool is down today."));
+        assert!(!first.text.contains("Old maintenance window."));
+
+        let second = orchestrator
+            .handle_message(message("2", "c2"))
+            .await
+            .expect("second message should succeed");
+        assert!(!second.text.contains("Notices from the operator"));
+        assert!(
+            memory
+                .list_system_notices("u12", Utc::now())
+                .await
+                .expect("list should succeed")
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn capability_questions_are_answered_from_the_enabled_tools() {
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            Arc::new(InMemoryMemoryStore::default()),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        );
+        let reply = orchestrator
             .handle_message(MessageCtx {
-                message_id: "4".into(),
-                user_id: "u4".into(),
+                message_id: "1".into(),
+                user_id: "u13".into(),
                 guild_id: "g1".into(),
                 channel_id: "c1".into(),
-                content: "my name is Petrr".into(),
+                content: "What can you do?".into(),
                 timestamp: Utc::now(),
+                attachments: Vec::new(),
             })
             .await
-            .expect("first message should succeed");
+            .expect("message should succeed");
+        assert!(reply.text.contains("Tool `current_datetime`"));
+        // Not configured, so neither reported nor claimed.
+        assert!(!reply.text.contains("Tool `web_search`"));
+
+        let capabilities = orchestrator.capabilities().expect("reported");
+        assert_eq!(
+            capabilities
+                .tools
+                .iter()
+                .map(|spec| spec.tool_name)
+                .collect::<Vec<_>>(),
+            ["current_datetime", "spotify_playing_status"]
+        );
+    }
 
-        let _ = orchestrator
+    #[tokio::test]
+    async fn planner_rationale_is_logged_as_a_thought_when_enabled() {
+        let memory = Arc::new(InMemoryMemoryStore::default());
+        let orchestrator = DefaultChatOrchestrator::new(
+            Arc::new(MockModelProvider),
+            memory.clone(),
+            Arc::new(ToolRegistry::default()),
+            SafetyPolicy::default(),
+        )
+        .with_config(OrchestratorConfig {
+            thoughts_log: true,
+            ..OrchestratorConfig::default()
+        });
+
+        let reply = orchestrator
             .handle_message(MessageCtx {
-                message_id: "5".into(),
-                user_id: "u4".into(),
+                message_id: "m1".into(),
+                user_id: "u11".into(),
                 guild_id: "g1".into(),
                 channel_id: "c1".into(),
-                content: "I misspelled my name, it's Petr.".into(),
+                content: "How are you today?".into(),
                 timestamp: Utc::now(),
+                attachments: Vec::new(),
             })
             .await
-            .expect("correction message should succeed");
+            .expect("message should succeed");
 
-        let facts = memory
-            .search_relevant("u4", "name", 10)
+        let thoughts = memory
+            .list_thoughts("u11", 10)
             .await
-            .expect("search should succeed");
-        assert_eq!(facts.len(), 1);
-        assert_eq!(facts[0].value, "Petr");
+            .expect("thoughts should list");
+        assert!(thoughts.iter().any(|thought| {
+            thought.source == "unified_planner"
+                && thought.message_id == "m1"
+                && thought.text == "mock_unified_planner"
+        }));
+        assert!(!reply.text.contains("mock_unified_planner"));
     }
 
     #[tokio::test]
-    async fn short_term_memory_includes_recent_non_fact_turns() {
+    async fn near_duplicate_fact_is_merged_into_canonical_key() {
         let memory = Arc::new(InMemoryMemoryStore::default());
+        memory
+            .upsert_fact(
+                "u8",
+                MemoryFact {
+                    key: "fav_game".to_owned(),
+                    value: "Factorio".to_owned(),
+                    confidence: 0.8,
+                    source: "user_message".to_owned(),
+                    updated_at: Utc::now(),
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("seed fact should be stored");
         let orchestrator = DefaultChatOrchestrator::new(
             Arc::new(MockModelProvider),
-            memory,
+            memory.clone(),
             Arc::new(ToolRegistry::default()),
             SafetyPolicy::default(),
-        );
+        )
+        .with_config(OrchestratorConfig {
+            fact_dedup_threshold: Some(0.8),
+            ..OrchestratorConfig::default()
+        });
 
         let _ = orchestrator
             .handle_message(MessageCtx {
-                message_id: "6".into(),
-                user_id: "u6".into(),
+                message_id: "8".into(),
+                user_id: "u8".into(),
                 guild_id: "g1".into(),
                 channel_id: "c1".into(),
-                content: "I am 24 years old.".into(),
+                content: "i play factorio".into(),
                 timestamp: Utc::now(),
+                attachments: Vec::new(),
             })
             .await
-            .expect("first message should succeed");
+            .expect("handle message should succeed");
 
-        let second = orchestrator
-            .handle_message(MessageCtx {
-                message_id: "7".into(),
-                user_id: "u6".into(),
-                guild_id: "g1".into(),
-                channel_id: "c1".into(),
-                content: "What did I just tell you?".into(),
-                timestamp: Utc::now(),
-            })
+        let facts = memory
+            .list_facts("u8", 10)
             .await
-            .expect("second message should succeed");
-
-        assert!(second.text.contains("Recent conversation turns:"));
-        assert!(second.text.contains("user: I am 24 years old."));
+            .expect("list should succeed");
+        assert_eq!(facts.len(), 1);
+        assert_eq!(facts[0].key, "fav_game");
+        let decisions = memory
+            .list_planner_decisions("u8", 10)
+            .await
+            .expect("decisions should list");
+        assert!(
+            decisions
+                .iter()
+                .any(|decision| decision.planner == "memory_dedup")
+        );
     }
 
     #[test]
@@ -1684,7 +6728,10 @@ mod tests {
             });
         }
 
-        let sanitized = sanitize_planned_tool_calls(planned_calls);
+        let sanitized = sanitize_planned_tool_calls(planned_calls, &ToolMacroRegistry::default());
+        assert_eq!(sanitized.rejected.len(), 1);
+        assert_eq!(sanitized.rejected[0].tool_name, "unknown_tool");
+        let sanitized = sanitized.calls;
         assert_eq!(sanitized.len(), 6);
         assert_eq!(sanitized[0].tool_name, "web_search");
         assert_eq!(sanitized[5].tool_name, "web_search");
@@ -1697,7 +6744,8 @@ mod tests {
             args: json!({"ignored": true}),
         }];
 
-        let sanitized = sanitize_planned_tool_calls(planned_calls);
+        let sanitized =
+            sanitize_planned_tool_calls(planned_calls, &ToolMacroRegistry::default()).calls;
         assert_eq!(sanitized.len(), 1);
         assert_eq!(sanitized[0].tool_name, "current_datetime");
         assert_eq!(sanitized[0].args, json!({}));
@@ -1719,7 +6767,8 @@ mod tests {
             },
         ];
 
-        let sanitized = sanitize_planned_tool_calls(planned_calls);
+        let sanitized =
+            sanitize_planned_tool_calls(planned_calls, &ToolMacroRegistry::default()).calls;
         assert_eq!(sanitized.len(), 2);
         assert_eq!(sanitized[0].tool_name, "current_datetime");
         assert_eq!(sanitized[1].tool_name, "web_search");
@@ -1729,6 +6778,89 @@ mod tests {
         assert_eq!(query, "current weather in berlin");
     }
 
+    #[test]
+    fn planner_budget_lists_rounds_time_and_quota_left() {
+        let budget = PlannerBudget {
+            rounds_left: 1,
+            elapsed: Duration::from_millis(2400),
+            round_budget: Some(Duration::from_secs(8)),
+            quotas: vec![crate::quotas::ToolQuotaStatus {
+                tool_name: "web_search".to_owned(),
+                used: 18,
+                limit: 20,
+                resets_at: Utc::now(),
+            }],
+        };
+
+        let text = format_planner_budget(&budget);
+        assert!(text.contains("Tool rounds left: 1 (its results go straight into the answer"));
+        assert!(text.contains("Time elapsed this turn: 2400 ms"));
+        assert!(text.contains("at most 8000 ms"));
+        assert!(text.contains("Daily tool calls left for this user: web_search 2 of 20"));
+    }
+
+    #[test]
+    fn sanitize_planned_tool_calls_expands_macros_past_datetime_boundary() {
+        let macros = ToolMacroRegistry::from_toml(
+            r#"
+            [[macros]]
+            name = "morning_briefing"
+            steps = [{ tool = "current_datetime" }, { tool = "web_search" }]
+            "#,
+        )
+        .expect("valid macros");
+        let planned_calls = vec![PlannedToolCall {
+            tool_name: "morning_briefing".to_owned(),
+            args: json!({"query": "prague news today"}),
+        }];
+
+        let sanitized = sanitize_planned_tool_calls(planned_calls, &macros);
+        assert!(sanitized.calls.is_empty());
+        assert_eq!(sanitized.macro_calls.len(), 2);
+        assert_eq!(sanitized.macro_calls[1].args["query"], "prague news today");
+        assert!(enforce_datetime_planning_boundary(sanitized.calls).is_empty());
+
+        let missing_query = vec![PlannedToolCall {
+            tool_name: "morning_briefing".to_owned(),
+            args: json!({}),
+        }];
+        let sanitized = sanitize_planned_tool_calls(missing_query, &macros);
+        assert!(sanitized.macro_calls.is_empty());
+        assert!(sanitized.rejected[0].errors[0].path.starts_with("/steps/1"));
+    }
+
+    #[test]
+    fn sanitize_planned_tool_calls_caps_expanded_macro_steps() {
+        let macros = ToolMacroRegistry::from_toml(
+            r#"
+            [[macros]]
+            name = "morning_briefing"
+            steps = [{ tool = "current_datetime" }, { tool = "web_search" }]
+            "#,
+        )
+        .expect("valid macros");
+        let search = |index: usize| PlannedToolCall {
+            tool_name: "web_search".to_owned(),
+            args: json!({"query": format!("rust query {index}")}),
+        };
+        let briefing = || PlannedToolCall {
+            tool_name: "morning_briefing".to_owned(),
+            args: json!({"query": "prague news today"}),
+        };
+        let mut planned_calls = (0..5).map(search).collect::<Vec<_>>();
+        planned_calls.push(briefing());
+
+        let sanitized = sanitize_planned_tool_calls(planned_calls, &macros);
+        assert_eq!(sanitized.calls.len(), 5);
+        assert!(sanitized.macro_calls.is_empty());
+        assert_eq!(sanitized.rejected[0].tool_name, "morning_briefing");
+
+        let planned_calls = vec![briefing(), briefing(), briefing(), briefing()];
+        let sanitized = sanitize_planned_tool_calls(planned_calls, &macros);
+        assert_eq!(sanitized.macro_calls.len(), 6);
+        assert!(sanitized.rejected.is_empty());
+    }
+
     #[test]
     fn sanitize_planned_tool_calls_allows_spotify_playing_status() {
         let planned_calls = vec![PlannedToolCall {
@@ -1736,12 +6868,14 @@ mod tests {
             args: json!({"ignored": true}),
         }];
 
-        let sanitized = sanitize_planned_tool_calls(planned_calls);
+        let sanitized =
+            sanitize_planned_tool_calls(planned_calls, &ToolMacroRegistry::default()).calls;
         assert_eq!(sanitized.len(), 1);
         assert_eq!(sanitized[0].tool_name, "spotify_playing_status");
         assert_eq!(sanitized[0].args, json!({}));
     }
 
+    #[cfg(feature = "voice")]
     #[test]
     fn sanitize_planned_tool_calls_allows_discord_voice_tools() {
         let planned_calls = vec![
@@ -1763,7 +6897,8 @@ mod tests {
             },
         ];
 
-        let sanitized = sanitize_planned_tool_calls(planned_calls);
+        let sanitized =
+            sanitize_planned_tool_calls(planned_calls, &ToolMacroRegistry::default()).calls;
         assert_eq!(sanitized.len(), 3);
         assert_eq!(sanitized[0].tool_name, "discord_voice_join");
         assert_eq!(sanitized[0].args["channel_id"], "123");
@@ -1771,6 +6906,49 @@ mod tests {
         assert_eq!(sanitized[2].tool_name, "discord_voice_leave");
     }
 
+    #[cfg(not(feature = "voice"))]
+    #[test]
+    fn sanitize_planned_tool_calls_drops_voice_tools_without_the_voice_feature() {
+        let planned_calls = vec![
+            PlannedToolCall {
+                tool_name: "discord_voice_join".to_owned(),
+                args: json!({"channel_id":"123"}),
+            },
+            PlannedToolCall {
+                tool_name: "current_datetime".to_owned(),
+                args: json!({}),
+            },
+        ];
+
+        let sanitized =
+            sanitize_planned_tool_calls(planned_calls, &ToolMacroRegistry::default()).calls;
+        assert_eq!(sanitized.len(), 1);
+        assert_eq!(sanitized[0].tool_name, "current_datetime");
+    }
+
+    #[tokio::test]
+    async fn voice_tools_follow_the_voice_feature() {
+        let ctx = MessageCtx {
+            message_id: "feature-1".into(),
+            user_id: "u1".into(),
+            guild_id: "1".into(),
+            channel_id: "2".into(),
+            content: "join voice".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        let error = ToolRegistry::default()
+            .execute("discord_voice_join", json!({}), &ctx)
+            .await
+            .expect_err("voice join needs a voice manager");
+
+        if cfg!(feature = "voice") {
+            assert_eq!(error.to_string(), "voice tools are not configured");
+        } else {
+            assert_eq!(error.to_string(), "unknown tool: discord_voice_join");
+        }
+    }
+
     #[test]
     fn enforce_datetime_planning_boundary_runs_datetime_in_isolation() {
         let calls = vec![
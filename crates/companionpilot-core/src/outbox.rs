@@ -0,0 +1,373 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::{
+    memory::MemoryStore,
+    reply_filters::{DISCORD_MESSAGE_LIMIT, MaxLength, ReplyFilter, ReplyFilterContext},
+    types::{ChatMessageRecord, OutboxKind, OutboxRecord},
+};
+
+/// How often the outbox is checked for due retries.
+pub const OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Attempts after which a reply is given up on.
+pub const MAX_OUTBOX_ATTEMPTS: u32 = 10;
+/// Entries retried per check.
+const OUTBOX_BATCH: usize = 50;
+/// How long a claimed entry is hidden from other instances while its attempt
+/// runs.
+const CLAIM_LEASE: chrono::Duration = chrono::Duration::minutes(5);
+const FIRST_RETRY_DELAY_SECS: i64 = 30;
+const MAX_RETRY_DELAY_SECS: i64 = 60 * 60;
+
+/// Wait before the next attempt after `attempts` failed ones: 30 seconds,
+/// doubling up to an hour.
+pub fn retry_delay(attempts: u32) -> chrono::Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    chrono::Duration::seconds((FIRST_RETRY_DELAY_SECS << doublings).min(MAX_RETRY_DELAY_SECS))
+}
+
+/// Keeps a reply whose first attempt to be stored or delivered failed.
+pub async fn queue_outbox_message(
+    memory: &dyn MemoryStore,
+    kind: OutboxKind,
+    message: ChatMessageRecord,
+    reply_to: &str,
+    error: &anyhow::Error,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let record = OutboxRecord {
+        id: format!("{}:{}", message.id, kind.as_str()),
+        kind,
+        message,
+        reply_to: reply_to.to_owned(),
+        attempts: 1,
+        next_attempt_at: now + retry_delay(1),
+        last_error: Some(format!("{error:#}")),
+        created_at: now,
+    };
+    warn!(
+        outbox_id = %record.id,
+        user_id = %record.message.user_id,
+        "reply queued in the outbox"
+    );
+    memory.put_outbox_message(record).await
+}
+
+/// Holds a reply written during quiet hours until `until`.
+pub async fn defer_outbox_message(
+    memory: &dyn MemoryStore,
+    message: ChatMessageRecord,
+    reply_to: &str,
+    until: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let record = OutboxRecord {
+        id: format!("{}:{}", message.id, OutboxKind::Deferred.as_str()),
+        kind: OutboxKind::Deferred,
+        message,
+        reply_to: reply_to.to_owned(),
+        attempts: 0,
+        next_attempt_at: until,
+        last_error: None,
+        created_at: Utc::now(),
+    };
+    info!(
+        outbox_id = %record.id,
+        %until,
+        "reply held until quiet hours end"
+    );
+    memory.put_outbox_message(record).await
+}
+
+/// Marks a failed attempt that retrying cannot fix, such as a channel that
+/// no longer exists; the entry is dropped instead of retried.
+#[derive(Debug, Clone, Copy)]
+pub struct PermanentFailure;
+
+impl std::fmt::Display for PermanentFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("delivery failed for good")
+    }
+}
+
+/// Due entries of `kind`, claimed atomically so that replicas and slow
+/// retries never pick one up twice, and each moved to its next attempt.
+/// Entries out of attempts are dropped.
+pub async fn claim_due_outbox(
+    memory: &dyn MemoryStore,
+    kind: OutboxKind,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<OutboxRecord>> {
+    let mut claimed = Vec::new();
+    for mut record in memory
+        .claim_due_outbox_messages(kind, now, now + CLAIM_LEASE, OUTBOX_BATCH)
+        .await?
+    {
+        if record.attempts >= MAX_OUTBOX_ATTEMPTS {
+            warn!(
+                outbox_id = %record.id,
+                user_id = %record.message.user_id,
+                last_error = record.last_error.as_deref().unwrap_or_default(),
+                "giving up on outbox reply"
+            );
+            memory.delete_outbox_message(&record.id).await?;
+            continue;
+        }
+        record.attempts += 1;
+        record.next_attempt_at = now + retry_delay(record.attempts);
+        memory.put_outbox_message(record.clone()).await?;
+        claimed.push(record);
+    }
+    Ok(claimed)
+}
+
+/// Settles a claimed entry: removes it on success or a
+/// [`PermanentFailure`], otherwise keeps the error for the next attempt.
+pub async fn finish_outbox_attempt(
+    memory: &dyn MemoryStore,
+    mut record: OutboxRecord,
+    outcome: anyhow::Result<()>,
+) {
+    let stored = match outcome {
+        Ok(()) => {
+            info!(
+                outbox_id = %record.id,
+                attempts = record.attempts,
+                "outbox reply went through"
+            );
+            memory.delete_outbox_message(&record.id).await.map(|_| ())
+        }
+        Err(error) if error.downcast_ref::<PermanentFailure>().is_some() => {
+            warn!(?error, outbox_id = %record.id, attempts = record.attempts, "dropping outbox reply");
+            memory.delete_outbox_message(&record.id).await.map(|_| ())
+        }
+        Err(error) => {
+            warn!(?error, outbox_id = %record.id, attempts = record.attempts, "outbox retry failed");
+            record.last_error = Some(format!("{error:#}"));
+            memory.put_outbox_message(record.clone()).await
+        }
+    };
+    if let Err(error) = stored {
+        warn!(?error, outbox_id = %record.id, "failed to update outbox entry");
+    }
+}
+
+/// What the user gets once a stuck reply is finally delivered, shortened to
+/// fit in one Discord message.
+pub fn delayed_reply_text(record: &OutboxRecord) -> String {
+    let intro = format!(
+        "<@{}> Sorry, this reply got stuck on its way to you. It answers your message from {}:\n",
+        record.message.user_id,
+        record.created_at.format("%Y-%m-%d %H:%M UTC"),
+    );
+    let room = DISCORD_MESSAGE_LIMIT.saturating_sub(intro.chars().count());
+    let reply = MaxLength(room).apply(
+        record.message.content.clone(),
+        &ReplyFilterContext::default(),
+    );
+    format!("{intro}{reply}")
+}
+
+/// Retries writing replies that were sent but could not be added to the
+/// chat history. Deliveries are retried by the adapter that owns the
+/// channel.
+pub fn start_outbox_job(memory: Arc<dyn MemoryStore>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(OUTBOX_POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let due = match claim_due_outbox(memory.as_ref(), OutboxKind::Record, Utc::now()).await
+            {
+                Ok(due) => due,
+                Err(error) => {
+                    warn!(?error, "failed to check the reply outbox");
+                    continue;
+                }
+            };
+            for record in due {
+                let outcome = memory
+                    .record_chat_message(record.message.clone())
+                    .await
+                    .map(|_| ());
+                finish_outbox_attempt(memory.as_ref(), record, outcome).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::InMemoryMemoryStore,
+        types::{ChatRole, Modality},
+    };
+
+    #[tokio::test]
+    async fn retries_back_off_and_give_up() {
+        assert_eq!(retry_delay(1), chrono::Duration::seconds(30));
+        assert_eq!(retry_delay(3), chrono::Duration::seconds(120));
+        assert_eq!(retry_delay(12), chrono::Duration::hours(1));
+
+        let store = InMemoryMemoryStore::default();
+        let message = ChatMessageRecord {
+            id: "m1-assistant".to_owned(),
+            user_id: "42".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            role: ChatRole::Assistant,
+            content: "Here you go.".to_owned(),
+            timestamp: Utc::now(),
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        };
+        queue_outbox_message(
+            &store,
+            OutboxKind::Deliver,
+            message,
+            "m1",
+            &anyhow::anyhow!("gateway timeout"),
+        )
+        .await
+        .expect("queued");
+
+        let now = Utc::now();
+        assert!(
+            claim_due_outbox(&store, OutboxKind::Deliver, now)
+                .await
+                .expect("claimed")
+                .is_empty()
+        );
+        let later = now + retry_delay(1);
+        assert!(
+            claim_due_outbox(&store, OutboxKind::Record, later)
+                .await
+                .expect("claimed")
+                .is_empty()
+        );
+        let claimed = claim_due_outbox(&store, OutboxKind::Deliver, later)
+            .await
+            .expect("claimed");
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].attempts, 2);
+        assert!(delayed_reply_text(&claimed[0]).starts_with("<@42> Sorry"));
+        // Claimed entries wait for their next attempt.
+        assert!(
+            claim_due_outbox(&store, OutboxKind::Deliver, later)
+                .await
+                .expect("claimed")
+                .is_empty()
+        );
+
+        let mut record = claimed[0].clone();
+        record.attempts = MAX_OUTBOX_ATTEMPTS;
+        store.put_outbox_message(record).await.expect("stored");
+        assert!(
+            claim_due_outbox(
+                &store,
+                OutboxKind::Deliver,
+                later + chrono::Duration::days(1)
+            )
+            .await
+            .expect("claimed")
+            .is_empty()
+        );
+        assert!(
+            !store
+                .delete_outbox_message("m1-assistant:deliver")
+                .await
+                .expect("deleted")
+        );
+    }
+
+    #[tokio::test]
+    async fn claims_once_fits_discord_and_drops_permanent_failures() {
+        let store = InMemoryMemoryStore::default();
+        let message = ChatMessageRecord {
+            id: "m3-assistant".to_owned(),
+            user_id: "42".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            role: ChatRole::Assistant,
+            content: "word ".repeat(500),
+            timestamp: Utc::now(),
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        };
+        queue_outbox_message(
+            &store,
+            OutboxKind::Deliver,
+            message,
+            "m3",
+            &anyhow::anyhow!("gateway timeout"),
+        )
+        .await
+        .expect("queued");
+
+        let later = Utc::now() + retry_delay(1);
+        let (first, second) = tokio::join!(
+            claim_due_outbox(&store, OutboxKind::Deliver, later),
+            claim_due_outbox(&store, OutboxKind::Deliver, later)
+        );
+        let mut claimed = first.expect("claimed");
+        claimed.extend(second.expect("claimed"));
+        assert_eq!(claimed.len(), 1);
+        let text = delayed_reply_text(&claimed[0]);
+        assert!(text.chars().count() <= DISCORD_MESSAGE_LIMIT);
+        assert!(text.ends_with('…'));
+
+        finish_outbox_attempt(
+            &store,
+            claimed.remove(0),
+            Err(anyhow::anyhow!("Unknown Channel").context(PermanentFailure)),
+        )
+        .await;
+        assert!(
+            !store
+                .delete_outbox_message("m3-assistant:deliver")
+                .await
+                .expect("deleted")
+        );
+    }
+
+    #[tokio::test]
+    async fn deferred_replies_wait_for_the_end_of_quiet_hours() {
+        let store = InMemoryMemoryStore::default();
+        let now = Utc::now();
+        let until = now + chrono::Duration::hours(6);
+        let message = ChatMessageRecord {
+            id: "m2-assistant".to_owned(),
+            user_id: "42".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            role: ChatRole::Assistant,
+            content: "Good morning!".to_owned(),
+            timestamp: now,
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        };
+        defer_outbox_message(&store, message, "m2", until)
+            .await
+            .expect("deferred");
+
+        assert!(
+            claim_due_outbox(&store, OutboxKind::Deferred, now)
+                .await
+                .expect("claimed")
+                .is_empty()
+        );
+        let claimed = claim_due_outbox(&store, OutboxKind::Deferred, until)
+            .await
+            .expect("claimed");
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].attempts, 1);
+        assert_eq!(claimed[0].reply_to, "m2");
+    }
+}
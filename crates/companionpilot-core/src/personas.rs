@@ -0,0 +1,229 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{model::GenerationParams, orchestrator::Persona, tools::find_tool_spec};
+
+/// Shareable persona definition, stored as a `.toml` or `.json` file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PersonaBundle {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub system_prompt: String,
+    /// Short style rules appended to the prompt, e.g. "never use emoji".
+    #[serde(default)]
+    pub style: Vec<String>,
+    /// Tools the planner may call while this persona is active; all
+    /// tools when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_tools: Option<Vec<String>>,
+    /// Opening line posted when `/companion setup` switches a server to this
+    /// persona.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub greeting: Option<String>,
+    #[serde(default)]
+    pub generation: GenerationParams,
+}
+
+/// A bundle failed validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersonaBundleError {
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for PersonaBundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid persona bundle: {}", self.errors.join("; "))
+    }
+}
+
+impl std::error::Error for PersonaBundleError {}
+
+impl PersonaBundle {
+    pub fn from_toml(raw: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(raw)?)
+    }
+
+    pub fn from_json(raw: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(raw)?)
+    }
+
+    pub fn validate(&self) -> Result<(), PersonaBundleError> {
+        let mut errors = Vec::new();
+        if !is_valid_persona_name(&self.name) {
+            errors.push(format!(
+                "name `{}` must be 1-32 lowercase letters, digits, `-` or `_`",
+                self.name
+            ));
+        }
+        if self.system_prompt.trim().is_empty() {
+            errors.push("system_prompt must not be empty".to_owned());
+        }
+        for tool_name in self.enabled_tools.iter().flatten() {
+            if find_tool_spec(tool_name).is_none() {
+                errors.push(format!("unknown tool `{tool_name}`"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PersonaBundleError { errors })
+        }
+    }
+
+    pub fn to_persona(&self) -> Persona {
+        let mut system_prompt = self.system_prompt.trim().to_owned();
+        let style = self
+            .style
+            .iter()
+            .map(|rule| rule.trim())
+            .filter(|rule| !rule.is_empty())
+            .collect::<Vec<_>>();
+        if !style.is_empty() {
+            system_prompt.push_str("\n\nStyle constraints:");
+            for rule in style {
+                system_prompt.push_str("\n- ");
+                system_prompt.push_str(rule);
+            }
+        }
+
+        Persona {
+            name: self.name.clone(),
+            system_prompt,
+            generation: self.generation.clone(),
+            enabled_tools: self.enabled_tools.clone(),
+        }
+    }
+}
+
+fn is_valid_persona_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '-' || ch == '_')
+}
+
+/// Imported persona bundles, keyed by name.
+#[derive(Default)]
+pub struct PersonaRegistry {
+    bundles: RwLock<BTreeMap<String, PersonaBundle>>,
+}
+
+impl PersonaRegistry {
+    /// Validates and stores `bundle`, replacing any bundle with the same name.
+    pub fn import(&self, bundle: PersonaBundle) -> Result<(), PersonaBundleError> {
+        bundle.validate()?;
+        self.bundles
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(bundle.name.clone(), bundle);
+        Ok(())
+    }
+
+    /// Imports every `.toml` and `.json` file in `dir`. Invalid bundles are
+    /// logged and skipped; returns the number imported.
+    pub fn load_dir(&self, dir: &Path) -> anyhow::Result<usize> {
+        let mut paths = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| bundle_format(path).is_some())
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        let mut imported = 0;
+        for path in paths {
+            match load_bundle_file(&path).and_then(|bundle| Ok(self.import(bundle)?)) {
+                Ok(()) => imported += 1,
+                Err(error) => warn!(path = %path.display(), %error, "skipping persona bundle"),
+            }
+        }
+        info!(dir = %dir.display(), imported, "loaded persona bundles");
+        Ok(imported)
+    }
+
+    pub fn get(&self, name: &str) -> Option<PersonaBundle> {
+        self.bundles
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    pub fn list(&self) -> Vec<PersonaBundle> {
+        self.bundles
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BundleFormat {
+    Toml,
+    Json,
+}
+
+fn bundle_format(path: &Path) -> Option<BundleFormat> {
+    match path.extension()?.to_str()? {
+        "toml" => Some(BundleFormat::Toml),
+        "json" => Some(BundleFormat::Json),
+        _ => None,
+    }
+}
+
+fn load_bundle_file(path: &Path) -> anyhow::Result<PersonaBundle> {
+    let raw = std::fs::read_to_string(path)?;
+    match bundle_format(path) {
+        Some(BundleFormat::Toml) => PersonaBundle::from_toml(&raw),
+        Some(BundleFormat::Json) => PersonaBundle::from_json(&raw),
+        None => Err(anyhow::anyhow!("unsupported persona bundle extension")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PersonaBundle, PersonaRegistry};
+
+    #[test]
+    fn imports_toml_bundle_and_rejects_unknown_tools() {
+        let bundle = PersonaBundle::from_toml(
+            r#"
+name = "pirate"
+system_prompt = "You are a cheerful pirate companion."
+style = ["Speak like a pirate.", "Keep replies under three sentences."]
+enabled_tools = ["current_datetime"]
+greeting = "Ahoy!"
+
+[generation]
+temperature = 0.9
+"#,
+        )
+        .expect("bundle should parse");
+        let registry = PersonaRegistry::default();
+        registry.import(bundle).expect("bundle should be valid");
+
+        let persona = registry.get("pirate").expect("imported").to_persona();
+        assert!(persona.system_prompt.contains("- Speak like a pirate."));
+        assert_eq!(persona.generation.temperature, Some(0.9));
+
+        let error = registry
+            .import(PersonaBundle {
+                name: "hacker".to_owned(),
+                system_prompt: "You hack.".to_owned(),
+                enabled_tools: Some(vec!["shell_exec".to_owned()]),
+                ..PersonaBundle::default()
+            })
+            .expect_err("unknown tool");
+        assert_eq!(error.errors, vec!["unknown tool `shell_exec`"]);
+        assert!(registry.get("hacker").is_none());
+    }
+}
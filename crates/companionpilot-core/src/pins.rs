@@ -0,0 +1,103 @@
+use crate::types::{ChatMessageRecord, ChatRole, context_speaker};
+
+/// Reaction that pins a message in Discord; removing it unpins the message.
+pub const PIN_EMOJI: &str = "📌";
+
+/// Rough characters per token, for budgeting pinned context without a
+/// tokenizer.
+const CHARS_PER_TOKEN: usize = 4;
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Prompt lines for pinned messages (oldest first). The newest pins are kept
+/// first; a message that does not fit the remaining `token_budget` is left
+/// out.
+pub fn pinned_context(pinned: &[ChatMessageRecord], token_budget: usize) -> Vec<String> {
+    let mut remaining = token_budget;
+    let mut lines = pinned
+        .iter()
+        .rev()
+        .filter_map(|message| {
+            let line = format!(
+                "{}, {}: {}",
+                context_speaker(message.role, message.modality),
+                message.timestamp.format("%Y-%m-%d"),
+                message.content
+            );
+            let tokens = estimate_tokens(&line);
+            (tokens <= remaining).then(|| {
+                remaining -= tokens;
+                line
+            })
+        })
+        .collect::<Vec<_>>();
+    lines.reverse();
+    lines
+}
+
+/// The stored message of `role` that stands for the platform message
+/// `message_id`, newest first.
+pub fn find_pin_target<'a>(
+    history: &'a [ChatMessageRecord],
+    role: ChatRole,
+    message_id: &str,
+) -> Option<&'a ChatMessageRecord> {
+    history.iter().rev().find(|message| {
+        message.role == role && message.source_message_id.as_deref() == Some(message_id)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{find_pin_target, pinned_context};
+    use crate::types::{ChatMessageRecord, ChatRole, Modality};
+
+    #[test]
+    fn budgets_pinned_context_and_matches_discord_messages() {
+        let message = |id: &str, role, content: &str| ChatMessageRecord {
+            id: id.to_owned(),
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            role,
+            content: content.to_owned(),
+            timestamp: Utc
+                .with_ymd_and_hms(2026, 10, 1, 12, 0, 0)
+                .single()
+                .expect("valid timestamp"),
+            modality: Modality::Text,
+            session_id: None,
+            pinned: true,
+            source_message_id: Some(format!("discord-{id}")),
+        };
+        let history = vec![
+            message("1", ChatRole::User, &"old ".repeat(40)),
+            message("2", ChatRole::User, "I'm allergic to peanuts.\nAlso hi."),
+            message("3", ChatRole::Assistant, "Noted, no peanuts."),
+        ];
+
+        let lines = pinned_context(&history, 30);
+        assert_eq!(
+            lines,
+            vec![
+                "user, 2026-10-01: I'm allergic to peanuts.\nAlso hi.",
+                "assistant, 2026-10-01: Noted, no peanuts.",
+            ]
+        );
+        assert_eq!(pinned_context(&history, 0), Vec::<String>::new());
+
+        let target =
+            find_pin_target(&history, ChatRole::User, "discord-2").expect("message should match");
+        assert_eq!(target.id, "2");
+        let reply = find_pin_target(&history, ChatRole::Assistant, "discord-3")
+            .expect("reply should match");
+        assert_eq!(reply.id, "3");
+        // Similar text is not enough.
+        assert!(find_pin_target(&history, ChatRole::User, "discord-3").is_none());
+        assert!(find_pin_target(&history, ChatRole::User, "discord-9").is_none());
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::types::PlannerDecisionRecord;
+
+/// A past message and the tool calls the unified planner chose for it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannerExample {
+    pub user_input: String,
+    pub tool_calls: Value,
+}
+
+/// Picks up to `max` examples from unified planner decisions that ran tools
+/// without rejections, newest first, preferring examples that show a tool not
+/// already covered. `decisions` may contain duplicates and any order.
+pub fn select_planner_examples(
+    decisions: &[PlannerDecisionRecord],
+    max: usize,
+) -> Vec<PlannerExample> {
+    let mut candidates = decisions
+        .iter()
+        .filter(|decision| {
+            decision.planner == "unified" && decision.decision == "apply_plan" && decision.success
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|decision| std::cmp::Reverse(decision.timestamp));
+
+    let mut seen_inputs = HashSet::new();
+    let mut examples = Vec::new();
+    for decision in candidates {
+        let Ok(payload) = serde_json::from_str::<Value>(&decision.payload_json) else {
+            continue;
+        };
+        let Some(user_input) = payload["user_input"].as_str().map(str::trim) else {
+            continue;
+        };
+        let tool_calls = &payload["tool_calls"];
+        let has_calls = tool_calls.as_array().is_some_and(|calls| !calls.is_empty());
+        let has_rejections = payload["rejected_tool_calls"]
+            .as_array()
+            .is_some_and(|rejected| !rejected.is_empty());
+        if user_input.is_empty() || !has_calls || has_rejections {
+            continue;
+        }
+        if seen_inputs.insert(user_input.to_lowercase()) {
+            examples.push(PlannerExample {
+                user_input: user_input.to_owned(),
+                tool_calls: tool_calls.clone(),
+            });
+        }
+    }
+
+    // Greedy pass for tool variety, then fill with the remaining newest ones.
+    let mut selected = Vec::new();
+    let mut covered_tools = HashSet::new();
+    for example in &examples {
+        if selected.len() >= max {
+            break;
+        }
+        let tools = example_tool_names(example);
+        if tools.iter().any(|tool| !covered_tools.contains(tool)) {
+            covered_tools.extend(tools);
+            selected.push(example.clone());
+        }
+    }
+    for example in examples {
+        if selected.len() >= max {
+            break;
+        }
+        if !selected.contains(&example) {
+            selected.push(example);
+        }
+    }
+    selected
+}
+
+fn example_tool_names(example: &PlannerExample) -> Vec<String> {
+    example
+        .tool_calls
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|call| call["tool_name"].as_str().map(ToOwned::to_owned))
+        .collect()
+}
+
+/// Prompt section listing the examples; empty when there are none.
+pub fn format_planner_examples(examples: &[PlannerExample]) -> String {
+    if examples.is_empty() {
+        return String::new();
+    }
+    let lines = examples
+        .iter()
+        .map(|example| {
+            format!(
+                "User: {}\ntool_calls: {}",
+                example.user_input, example.tool_calls
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    format!(
+        "Examples of this user's earlier requests and the tools chosen for them (follow the pattern only when the new message is similar):\n{lines}\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use super::select_planner_examples;
+    use crate::types::PlannerDecisionRecord;
+
+    #[test]
+    fn prefers_recent_examples_covering_different_tools() {
+        let now = Utc::now();
+        let decision = |input: &str, tool: &str, age_minutes: i64| PlannerDecisionRecord {
+            id: String::new(),
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            planner: "unified".to_owned(),
+            decision: "apply_plan".to_owned(),
+            rationale: String::new(),
+            payload_json: json!({
+                "user_input": input,
+                "tool_calls": [{"tool_name": tool, "args": {}}],
+                "rejected_tool_calls": [],
+            })
+            .to_string(),
+            success: true,
+            error: None,
+            timestamp: now - Duration::minutes(age_minutes),
+        };
+        let decisions = vec![
+            decision("weather in Prague?", "web_search", 1),
+            decision("news about rust", "web_search", 2),
+            decision("what time is it", "current_datetime", 3),
+            decision("weather in Prague?", "web_search", 4),
+        ];
+
+        let examples = select_planner_examples(&decisions, 2);
+        let inputs = examples
+            .iter()
+            .map(|example| example.user_input.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, vec!["weather in Prague?", "what time is it"]);
+    }
+}
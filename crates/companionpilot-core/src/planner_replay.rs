@@ -0,0 +1,169 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// A stored unified planner decision re-run against the current prompt and
+/// model.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannerReplay {
+    pub decision_id: String,
+    pub user_input: String,
+    pub original: PlannerOutcome,
+    pub replayed: PlannerOutcome,
+    pub diff: PlannerDiff,
+}
+
+/// The comparable parts of one unified planner decision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PlannerOutcome {
+    /// `apply_plan`, `skip_small_talk` or `fallback_no_tools`.
+    pub decision: String,
+    pub tool_calls: Vec<Value>,
+    pub rejected_tool_calls: Vec<Value>,
+    pub memory: Value,
+    pub rationale: String,
+    pub error: Option<String>,
+}
+
+impl PlannerOutcome {
+    /// Builds an outcome from a decision label, rationale and the payload
+    /// recorded with it.
+    pub fn from_payload(
+        decision: &str,
+        rationale: &str,
+        payload: &Value,
+        error: Option<String>,
+    ) -> Self {
+        let list = |field: &str| payload[field].as_array().cloned().unwrap_or_default();
+        Self {
+            decision: decision.to_owned(),
+            tool_calls: list("tool_calls"),
+            rejected_tool_calls: list("rejected_tool_calls"),
+            memory: payload["memory"].clone(),
+            rationale: rationale.to_owned(),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PlannerDiff {
+    /// Whether the decision, tool calls or memory write differ. Rationale
+    /// wording alone does not count as a change.
+    pub changed: bool,
+    pub decision_changed: bool,
+    /// Tool calls (name and args) only the replay made.
+    pub tool_calls_added: Vec<Value>,
+    /// Tool calls only the original decision made.
+    pub tool_calls_removed: Vec<Value>,
+    pub memory_changed: bool,
+    pub rationale_changed: bool,
+}
+
+/// Compares two outcomes; tool calls are matched as a multiset, so order
+/// changes alone are not reported.
+pub fn diff_outcomes(original: &PlannerOutcome, replayed: &PlannerOutcome) -> PlannerDiff {
+    let tool_calls_added = multiset_difference(&replayed.tool_calls, &original.tool_calls);
+    let tool_calls_removed = multiset_difference(&original.tool_calls, &replayed.tool_calls);
+    let decision_changed = original.decision != replayed.decision;
+    let memory_changed = original.memory != replayed.memory;
+    PlannerDiff {
+        changed: decision_changed
+            || memory_changed
+            || !tool_calls_added.is_empty()
+            || !tool_calls_removed.is_empty(),
+        decision_changed,
+        tool_calls_added,
+        tool_calls_removed,
+        memory_changed,
+        rationale_changed: original.rationale != replayed.rationale,
+    }
+}
+
+fn multiset_difference(left: &[Value], right: &[Value]) -> Vec<Value> {
+    let mut remaining = right.iter().collect::<Vec<_>>();
+    left.iter()
+        .filter(
+            |value| match remaining.iter().position(|candidate| candidate == value) {
+                Some(index) => {
+                    remaining.swap_remove(index);
+                    false
+                }
+                None => true,
+            },
+        )
+        .cloned()
+        .collect()
+}
+
+/// Why a stored decision cannot be replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannerReplayError {
+    /// Only unified planner decisions are replayable; follow-up decisions
+    /// depend on tool outputs that are not stored in full.
+    UnsupportedPlanner(String),
+    /// The decision was recorded without the user message it planned for.
+    MissingInput,
+}
+
+impl fmt::Display for PlannerReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedPlanner(planner) => write!(
+                f,
+                "`{planner}` planner decisions cannot be replayed; only `unified` ones can"
+            ),
+            Self::MissingInput => write!(f, "the decision has no recorded user input"),
+        }
+    }
+}
+
+impl std::error::Error for PlannerReplayError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{PlannerOutcome, diff_outcomes};
+
+    #[test]
+    fn diff_reports_tool_call_and_memory_changes_but_not_reordering() {
+        let original = PlannerOutcome::from_payload(
+            "apply_plan",
+            "needs search",
+            &json!({
+                "tool_calls": [
+                    {"tool_name": "current_datetime", "args": {}},
+                    {"tool_name": "web_search", "args": {"query": "old"}}
+                ],
+                "memory": {"store": false}
+            }),
+            None,
+        );
+        let reordered = PlannerOutcome {
+            tool_calls: original.tool_calls.iter().rev().cloned().collect(),
+            rationale: "reworded".to_owned(),
+            ..original.clone()
+        };
+        let diff = diff_outcomes(&original, &reordered);
+        assert!(!diff.changed);
+        assert!(diff.rationale_changed);
+
+        let replayed = PlannerOutcome::from_payload(
+            "apply_plan",
+            "needs search",
+            &json!({
+                "tool_calls": [{"tool_name": "web_search", "args": {"query": "new"}}],
+                "memory": {"store": true, "key": "city", "value": "Prague"}
+            }),
+            None,
+        );
+        let diff = diff_outcomes(&original, &replayed);
+        assert!(diff.changed);
+        assert!(!diff.decision_changed);
+        assert!(diff.memory_changed);
+        assert_eq!(diff.tool_calls_added.len(), 1);
+        assert_eq!(diff.tool_calls_removed.len(), 2);
+    }
+}
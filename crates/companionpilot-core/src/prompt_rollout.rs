@@ -0,0 +1,696 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    coordination::claim_once,
+    memory::MemoryStore,
+    orchestrator::ChatOrchestrator,
+    personas::{PersonaBundle, PersonaRegistry},
+};
+
+/// How often running rollouts are checked against their thresholds.
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(60);
+/// Claimed for each evaluation, so that one replica evaluates per interval.
+const EVALUATION_CLAIM_KEY: &str = "prompt-rollouts";
+const EVALUATION_CLAIM_TTL: Duration = Duration::from_secs(50);
+/// Rated replies a variant needs before its feedback score is compared.
+const MIN_RATED_REPLIES: u64 = 5;
+
+/// Which prompt of a persona under rollout a turn uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptVariant {
+    Stable,
+    Candidate,
+}
+
+impl PromptVariant {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Candidate => "candidate",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "stable" | "blue" => Some(Self::Stable),
+            "candidate" | "green" => Some(Self::Candidate),
+            _ => None,
+        }
+    }
+}
+
+/// When a candidate prompt is promoted or rolled back.
+#[derive(Debug, Clone)]
+pub struct RolloutPolicy {
+    /// Share of users (0-100) answered with the candidate prompt.
+    pub percent: u8,
+    /// A candidate that does not degrade within this long is promoted.
+    pub window: Duration,
+    /// Planner turns each variant needs before fallback rates are compared.
+    pub min_turns: u64,
+    /// Rolls back when the candidate's planner fallback rate exceeds the
+    /// stable one by more than this (0-1).
+    pub max_fallback_increase: f64,
+    /// Rolls back when the candidate's mean feedback (-1 to 1) is lower than
+    /// the stable one by more than this.
+    pub max_feedback_drop: f64,
+}
+
+impl Default for RolloutPolicy {
+    fn default() -> Self {
+        Self {
+            percent: 0,
+            window: Duration::from_secs(24 * 60 * 60),
+            min_turns: 20,
+            max_fallback_increase: 0.05,
+            max_feedback_drop: 0.2,
+        }
+    }
+}
+
+/// Outcomes of one variant's turns.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VariantStats {
+    pub turns: u64,
+    /// Turns the unified planner ran for.
+    pub planned_turns: u64,
+    pub planner_fallbacks: u64,
+    /// 👍/👎 on this variant's replies, summed.
+    pub feedback: i64,
+    pub rated_replies: u64,
+}
+
+impl VariantStats {
+    pub fn fallback_rate(&self) -> Option<f64> {
+        (self.planned_turns > 0).then(|| self.planner_fallbacks as f64 / self.planned_turns as f64)
+    }
+
+    /// Mean feedback from -1 (all 👎) to 1 (all 👍).
+    pub fn feedback_score(&self) -> Option<f64> {
+        (self.rated_replies > 0).then(|| self.feedback as f64 / self.rated_replies as f64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RolloutState {
+    Running,
+    Promoted,
+    RolledBack { reason: String },
+}
+
+/// A turn answered with the variant its user is assigned to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RolloutTurn {
+    pub persona: String,
+    /// Start of the rollout the turn counts for.
+    pub rollout_started_at: DateTime<Utc>,
+    pub variant: PromptVariant,
+    pub user_id: String,
+    /// Id the reply was stored under, which feedback on it refers to.
+    pub reply_message_id: String,
+    /// `None` when the planner did not run.
+    pub planner_fallback: Option<bool>,
+}
+
+/// A persona update being tried on part of the traffic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptRollout {
+    pub persona: String,
+    pub percent: u8,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub state: RolloutState,
+    pub stable: VariantStats,
+    pub candidate: VariantStats,
+    pub candidate_bundle: PersonaBundle,
+}
+
+/// What evaluating a rollout decided.
+#[derive(Debug, Clone)]
+pub enum RolloutOutcome {
+    Promoted(PersonaBundle),
+    RolledBack { persona: String, reason: String },
+}
+
+/// Blue/green rollouts of persona prompts, keyed by persona name. The
+/// persona registry keeps serving the stable bundle; users in the rollout's
+/// percentage get the candidate until it is promoted into the registry or
+/// rolled back. Rollouts and their turns are kept in the memory store, so
+/// they survive restarts and are shared by replicas; each instance assigns
+/// variants from its own copy, refreshed on every evaluation.
+pub struct PromptRollouts {
+    policy: RolloutPolicy,
+    rollouts: Mutex<BTreeMap<String, PromptRollout>>,
+}
+
+impl PromptRollouts {
+    pub fn new(policy: RolloutPolicy) -> Self {
+        Self {
+            policy,
+            rollouts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &RolloutPolicy {
+        &self.policy
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, PromptRollout>> {
+        self.rollouts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Starts rolling `candidate` out to `percent` of users, replacing an
+    /// earlier rollout of the same persona.
+    pub async fn start(
+        &self,
+        memory: &dyn MemoryStore,
+        candidate: PersonaBundle,
+        percent: u8,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let persona = candidate.name.clone();
+        let rollout = PromptRollout {
+            persona: persona.clone(),
+            percent: percent.min(100),
+            // Stored timestamps keep microseconds; turns are matched on it.
+            started_at: now.trunc_subsecs(6),
+            ended_at: None,
+            state: RolloutState::Running,
+            stable: VariantStats::default(),
+            candidate: VariantStats::default(),
+            candidate_bundle: candidate,
+        };
+        memory.put_prompt_rollout(rollout.clone()).await?;
+        info!(%persona, percent, "prompt rollout started");
+        self.lock().insert(persona, rollout);
+        Ok(())
+    }
+
+    /// Rollouts, running and finished, by persona name.
+    pub fn list(&self) -> Vec<PromptRollout> {
+        self.lock().values().cloned().collect()
+    }
+
+    /// The candidate bundle of a running rollout and the variant `user_id`
+    /// is assigned to. Users keep their variant for the whole rollout.
+    pub fn assign(&self, persona: &str, user_id: &str) -> Option<(PromptVariant, PersonaBundle)> {
+        let rollouts = self.lock();
+        let rollout = rollouts
+            .get(persona)
+            .filter(|rollout| rollout.state == RolloutState::Running)?;
+        let variant = if user_bucket(persona, user_id) < rollout.percent {
+            PromptVariant::Candidate
+        } else {
+            PromptVariant::Stable
+        };
+        Some((variant, rollout.candidate_bundle.clone()))
+    }
+
+    /// Counts a turn answered with the variant `user_id` is assigned to
+    /// under a running rollout. `planner_fallback` is `None` when the
+    /// planner did not run.
+    pub async fn record_turn(
+        &self,
+        memory: &dyn MemoryStore,
+        persona: &str,
+        variant: PromptVariant,
+        user_id: &str,
+        reply_message_id: &str,
+        planner_fallback: Option<bool>,
+    ) -> anyhow::Result<()> {
+        let Some(rollout_started_at) = self
+            .lock()
+            .get(persona)
+            .filter(|rollout| rollout.state == RolloutState::Running)
+            .map(|rollout| rollout.started_at)
+        else {
+            return Ok(());
+        };
+        memory
+            .record_prompt_rollout_turn(RolloutTurn {
+                persona: persona.to_owned(),
+                rollout_started_at,
+                variant,
+                user_id: user_id.to_owned(),
+                reply_message_id: reply_message_id.to_owned(),
+                planner_fallback,
+            })
+            .await
+    }
+
+    /// Ends a running rollout without promoting it. Returns whether one was
+    /// running.
+    pub async fn roll_back(
+        &self,
+        memory: &dyn MemoryStore,
+        persona: &str,
+        reason: &str,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        self.refresh(memory).await?;
+        let Some(mut rollout) = self
+            .lock()
+            .get(persona)
+            .filter(|rollout| rollout.state == RolloutState::Running)
+            .cloned()
+        else {
+            return Ok(false);
+        };
+        rollout.state = RolloutState::RolledBack {
+            reason: reason.to_owned(),
+        };
+        rollout.ended_at = Some(now);
+        memory.put_prompt_rollout(rollout.clone()).await?;
+        self.lock().insert(rollout.persona.clone(), rollout);
+        Ok(true)
+    }
+
+    /// Reloads rollouts from the store. Returns the promotions another
+    /// instance made since the last refresh, for the local registry.
+    pub async fn refresh(&self, memory: &dyn MemoryStore) -> anyhow::Result<Vec<RolloutOutcome>> {
+        let stored = memory.list_prompt_rollouts().await?;
+        let mut rollouts = self.lock();
+        let mut outcomes = Vec::new();
+        for rollout in &stored {
+            let was_running = rollouts.get(&rollout.persona).is_some_and(|known| {
+                known.started_at == rollout.started_at && known.state == RolloutState::Running
+            });
+            if was_running && rollout.state == RolloutState::Promoted {
+                outcomes.push(RolloutOutcome::Promoted(rollout.candidate_bundle.clone()));
+            }
+        }
+        *rollouts = stored
+            .into_iter()
+            .map(|rollout| (rollout.persona.clone(), rollout))
+            .collect();
+        Ok(outcomes)
+    }
+
+    /// Recounts running rollouts from their stored turns and feedback and
+    /// rolls back candidates that degraded; candidates that made it through
+    /// their window are promoted. Includes promotions made elsewhere since
+    /// the last refresh.
+    pub async fn evaluate(
+        &self,
+        memory: &dyn MemoryStore,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<RolloutOutcome>> {
+        let mut outcomes = self.refresh(memory).await?;
+        let running = self
+            .list()
+            .into_iter()
+            .filter(|rollout| rollout.state == RolloutState::Running)
+            .collect::<Vec<_>>();
+        for mut rollout in running {
+            let turns = memory
+                .list_prompt_rollout_turns(&rollout.persona, rollout.started_at)
+                .await?;
+            rollout.stable = variant_stats(memory, &turns, PromptVariant::Stable).await?;
+            rollout.candidate = variant_stats(memory, &turns, PromptVariant::Candidate).await?;
+            if let Some(reason) = degradation(&self.policy, &rollout.stable, &rollout.candidate) {
+                warn!(persona = %rollout.persona, %reason, "prompt rollout rolled back");
+                rollout.state = RolloutState::RolledBack {
+                    reason: reason.clone(),
+                };
+                rollout.ended_at = Some(now);
+                outcomes.push(RolloutOutcome::RolledBack {
+                    persona: rollout.persona.clone(),
+                    reason,
+                });
+            } else if (now - rollout.started_at).to_std().unwrap_or_default() >= self.policy.window
+            {
+                info!(persona = %rollout.persona, "prompt rollout promoted");
+                rollout.state = RolloutState::Promoted;
+                rollout.ended_at = Some(now);
+                outcomes.push(RolloutOutcome::Promoted(rollout.candidate_bundle.clone()));
+            }
+            memory.put_prompt_rollout(rollout.clone()).await?;
+            self.lock().insert(rollout.persona.clone(), rollout);
+        }
+        Ok(outcomes)
+    }
+}
+
+/// Evaluates rollouts on a schedule and imports promoted candidates into
+/// the persona registry. With replicas, one instance evaluates each time and
+/// the others pick up its results.
+pub async fn run_prompt_rollouts(
+    rollouts: Arc<PromptRollouts>,
+    personas: Arc<PersonaRegistry>,
+    orchestrator: Arc<dyn ChatOrchestrator>,
+) {
+    let memory = orchestrator.memory();
+    let mut ticker = tokio::time::interval(EVALUATION_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let evaluates = match orchestrator.coordinator() {
+            Some(coordinator) => {
+                claim_once(
+                    coordinator.as_ref(),
+                    EVALUATION_CLAIM_KEY,
+                    EVALUATION_CLAIM_TTL,
+                )
+                .await
+            }
+            None => true,
+        };
+        let outcomes = if evaluates {
+            rollouts.evaluate(memory.as_ref(), Utc::now()).await
+        } else {
+            rollouts.refresh(memory.as_ref()).await
+        };
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(error) => {
+                warn!(?error, "failed to evaluate prompt rollouts");
+                continue;
+            }
+        };
+        for outcome in outcomes {
+            if let RolloutOutcome::Promoted(bundle) = outcome
+                && let Err(error) = personas.import(bundle)
+            {
+                warn!(%error, "failed to promote rolled-out persona");
+            }
+        }
+    }
+}
+
+/// The reason to roll back, if the candidate did worse than allowed.
+fn degradation(
+    policy: &RolloutPolicy,
+    stable: &VariantStats,
+    candidate: &VariantStats,
+) -> Option<String> {
+    if stable.planned_turns >= policy.min_turns
+        && candidate.planned_turns >= policy.min_turns
+        && let (Some(stable_rate), Some(candidate_rate)) =
+            (stable.fallback_rate(), candidate.fallback_rate())
+        && candidate_rate - stable_rate > policy.max_fallback_increase
+    {
+        return Some(format!(
+            "planner fallback rate rose from {:.1}% to {:.1}%",
+            stable_rate * 100.0,
+            candidate_rate * 100.0
+        ));
+    }
+    if stable.rated_replies >= MIN_RATED_REPLIES
+        && candidate.rated_replies >= MIN_RATED_REPLIES
+        && let (Some(stable_score), Some(candidate_score)) =
+            (stable.feedback_score(), candidate.feedback_score())
+        && stable_score - candidate_score > policy.max_feedback_drop
+    {
+        return Some(format!(
+            "feedback score fell from {stable_score:.2} to {candidate_score:.2}"
+        ));
+    }
+    None
+}
+
+/// Counts of `variant`'s turns, with the feedback on their replies.
+async fn variant_stats(
+    memory: &dyn MemoryStore,
+    turns: &[RolloutTurn],
+    variant: PromptVariant,
+) -> anyhow::Result<VariantStats> {
+    let mut stats = VariantStats::default();
+    let mut replies = HashMap::<&str, Vec<&str>>::new();
+    for turn in turns.iter().filter(|turn| turn.variant == variant) {
+        stats.turns += 1;
+        if let Some(fallback) = turn.planner_fallback {
+            stats.planned_turns += 1;
+            stats.planner_fallbacks += u64::from(fallback);
+        }
+        replies
+            .entry(turn.user_id.as_str())
+            .or_default()
+            .push(turn.reply_message_id.as_str());
+    }
+    for (user_id, message_ids) in replies {
+        for feedback in memory.list_message_feedback(user_id).await? {
+            if feedback.score != 0 && message_ids.contains(&feedback.message_id.as_str()) {
+                stats.feedback += i64::from(feedback.score);
+                stats.rated_replies += 1;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// A stable 0-99 bucket per persona and user.
+fn user_bucket(persona: &str, user_id: &str) -> u8 {
+    let digest = Sha256::digest(format!("{persona}\n{user_id}"));
+    (u16::from_be_bytes([digest[0], digest[1]]) % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::InMemoryMemoryStore,
+        types::{ChatMessageRecord, ChatRole, MessageFeedbackRecord, Modality},
+    };
+
+    fn bundle(prompt: &str) -> PersonaBundle {
+        PersonaBundle::from_toml(&format!("name = \"pirate\"\nsystem_prompt = \"{prompt}\""))
+            .expect("valid bundle")
+    }
+
+    #[tokio::test]
+    async fn degraded_candidates_roll_back_and_healthy_ones_are_promoted() {
+        let memory = InMemoryMemoryStore::default();
+        let rollouts = PromptRollouts::new(RolloutPolicy {
+            min_turns: 4,
+            ..RolloutPolicy::default()
+        });
+        let start = Utc::now();
+        rollouts
+            .start(&memory, bundle("Arr, terse."), 50, start)
+            .await
+            .expect("started");
+        let variant_of = |user_id: &str| {
+            rollouts
+                .assign("pirate", user_id)
+                .map(|(variant, _)| variant)
+        };
+        let users = (0..40).map(|n| format!("u{n}")).collect::<Vec<_>>();
+        let candidate_user = users
+            .iter()
+            .find(|user_id| variant_of(user_id) == Some(PromptVariant::Candidate))
+            .expect("some user gets the candidate");
+        let stable_user = users
+            .iter()
+            .find(|user_id| variant_of(user_id) == Some(PromptVariant::Stable))
+            .expect("some user keeps the stable prompt");
+        assert_eq!(variant_of(candidate_user), Some(PromptVariant::Candidate));
+        assert!(rollouts.assign("other", candidate_user).is_none());
+
+        for turn in 0..5 {
+            let id = format!("m{turn}");
+            rollouts
+                .record_turn(
+                    &memory,
+                    "pirate",
+                    PromptVariant::Stable,
+                    stable_user,
+                    &id,
+                    Some(false),
+                )
+                .await
+                .expect("recorded");
+            rollouts
+                .record_turn(
+                    &memory,
+                    "pirate",
+                    PromptVariant::Candidate,
+                    candidate_user,
+                    &id,
+                    Some(turn % 2 == 0),
+                )
+                .await
+                .expect("recorded");
+        }
+        let outcomes = rollouts.evaluate(&memory, start).await.expect("evaluated");
+        assert!(matches!(
+            &outcomes[..],
+            [RolloutOutcome::RolledBack { reason, .. }] if reason.contains("fallback rate")
+        ));
+        assert!(rollouts.assign("pirate", candidate_user).is_none());
+
+        // Turns of the rolled-back rollout do not count for the new one.
+        let restart = start + chrono::Duration::minutes(1);
+        rollouts
+            .start(&memory, bundle("Arr, kind."), 50, restart)
+            .await
+            .expect("started");
+        for turn in 0..5 {
+            let id = format!("k{turn}");
+            rollouts
+                .record_turn(
+                    &memory,
+                    "pirate",
+                    PromptVariant::Candidate,
+                    candidate_user,
+                    &id,
+                    None,
+                )
+                .await
+                .expect("recorded");
+            memory
+                .set_message_feedback(MessageFeedbackRecord {
+                    user_id: candidate_user.clone(),
+                    message_id: id,
+                    score: 1,
+                    updated_at: start,
+                })
+                .await
+                .expect("feedback stored");
+        }
+        assert!(
+            rollouts
+                .evaluate(&memory, restart)
+                .await
+                .expect("evaluated")
+                .is_empty()
+        );
+        let later = restart + chrono::Duration::days(2);
+        let outcomes = rollouts.evaluate(&memory, later).await.expect("evaluated");
+        assert!(matches!(
+            &outcomes[..],
+            [RolloutOutcome::Promoted(bundle)] if bundle.system_prompt == "Arr, kind."
+        ));
+        assert_eq!(rollouts.list()[0].candidate.rated_replies, 5);
+    }
+
+    #[tokio::test]
+    async fn feedback_counts_against_the_id_the_reply_was_stored_under() {
+        let memory = InMemoryMemoryStore::default();
+        let rollouts = PromptRollouts::new(RolloutPolicy::default());
+        let start = Utc::now();
+        rollouts
+            .start(&memory, bundle("Arr, rude."), 50, start)
+            .await
+            .expect("started");
+        let users = (0..40).map(|n| format!("u{n}")).collect::<Vec<_>>();
+        let user_with = |variant| {
+            users
+                .iter()
+                .find(|user_id| {
+                    rollouts
+                        .assign("pirate", user_id)
+                        .is_some_and(|(assigned, _)| assigned == variant)
+                })
+                .expect("some user gets the variant")
+        };
+
+        for (variant, score) in [(PromptVariant::Stable, 1), (PromptVariant::Candidate, -1)] {
+            let user_id = user_with(variant);
+            for turn in 0..5 {
+                // Like Postgres, the store picks the id; it is not derived
+                // from the answered message.
+                let stored_id = memory
+                    .record_chat_message(ChatMessageRecord {
+                        id: String::new(),
+                        user_id: user_id.clone(),
+                        guild_id: "g1".to_owned(),
+                        channel_id: "c1".to_owned(),
+                        role: ChatRole::Assistant,
+                        content: "Arr.".to_owned(),
+                        timestamp: start,
+                        modality: Modality::Text,
+                        session_id: None,
+                        pinned: false,
+                        source_message_id: Some(format!("m{turn}")),
+                    })
+                    .await
+                    .expect("recorded");
+                assert_ne!(stored_id, format!("m{turn}-assistant"));
+                rollouts
+                    .record_turn(&memory, "pirate", variant, user_id, &stored_id, None)
+                    .await
+                    .expect("recorded");
+                memory
+                    .set_message_feedback(MessageFeedbackRecord {
+                        user_id: user_id.clone(),
+                        message_id: stored_id,
+                        score,
+                        updated_at: start,
+                    })
+                    .await
+                    .expect("feedback stored");
+            }
+        }
+
+        let outcomes = rollouts.evaluate(&memory, start).await.expect("evaluated");
+        assert!(matches!(
+            &outcomes[..],
+            [RolloutOutcome::RolledBack { reason, .. }] if reason.contains("feedback score")
+        ));
+        assert_eq!(rollouts.list()[0].candidate.rated_replies, 5);
+    }
+
+    #[tokio::test]
+    async fn rollouts_are_shared_through_the_store() {
+        let memory = InMemoryMemoryStore::default();
+        let first = PromptRollouts::new(RolloutPolicy::default());
+        let replica = PromptRollouts::new(RolloutPolicy::default());
+        let start = Utc::now();
+        first
+            .start(&memory, bundle("Arr, kind."), 100, start)
+            .await
+            .expect("started");
+        assert!(replica.assign("pirate", "u1").is_none());
+        assert!(
+            replica
+                .refresh(&memory)
+                .await
+                .expect("refreshed")
+                .is_empty()
+        );
+        assert_eq!(
+            replica.assign("pirate", "u1").map(|(variant, _)| variant),
+            Some(PromptVariant::Candidate)
+        );
+
+        // Turns recorded by any instance count toward the evaluation.
+        replica
+            .record_turn(
+                &memory,
+                "pirate",
+                PromptVariant::Candidate,
+                "u1",
+                "m1",
+                Some(false),
+            )
+            .await
+            .expect("recorded");
+        let later = start + chrono::Duration::days(2);
+        let outcomes = first.evaluate(&memory, later).await.expect("evaluated");
+        assert!(matches!(&outcomes[..], [RolloutOutcome::Promoted(_)]));
+        assert_eq!(first.list()[0].candidate.planned_turns, 1);
+
+        // The other instance learns about the promotion on its next refresh.
+        let outcomes = replica.refresh(&memory).await.expect("refreshed");
+        assert!(matches!(&outcomes[..], [RolloutOutcome::Promoted(_)]));
+        assert!(replica.assign("pirate", "u1").is_none());
+
+        let stored = serde_json::to_string(&first.list()[0]).expect("serialized");
+        let restored = serde_json::from_str::<PromptRollout>(&stored).expect("deserialized");
+        assert_eq!(restored.state, RolloutState::Promoted);
+        assert_eq!(restored.started_at, first.list()[0].started_at);
+    }
+}
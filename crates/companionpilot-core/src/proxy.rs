@@ -0,0 +1,126 @@
+use std::net::IpAddr;
+
+/// Reverse proxies whose `X-Forwarded-For` entries are believed, as single
+/// addresses or CIDR ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parses comma-separated addresses and ranges, e.g.
+    /// `127.0.0.1, 10.0.0.0/8, ::1`. Fails on the first malformed entry.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let ranges = split_list(raw)
+            .map(|entry| parse_range(entry).ok_or_else(|| format!("invalid proxy `{entry}`")))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { ranges })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(network, prefix)| in_range(ip, network, prefix))
+    }
+}
+
+/// The address of the client behind any trusted proxies. `X-Forwarded-For`
+/// is only read when the direct peer is trusted, and is walked from the right
+/// so a client cannot spoof its address by prepending entries.
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &TrustedProxies) -> IpAddr {
+    if !trusted.contains(peer) {
+        return peer;
+    }
+    let Some(forwarded_for) = forwarded_for else {
+        return peer;
+    };
+    let mut client = peer;
+    for hop in forwarded_for.rsplit(',') {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !trusted.contains(ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// Normalizes a route prefix such as `companion/` to `/companion`; `None` for
+/// an empty prefix or `/`.
+pub fn normalize_base_path(raw: &str) -> Option<String> {
+    let trimmed = raw.trim().trim_matches('/');
+    (!trimmed.is_empty()).then(|| format!("/{trimmed}"))
+}
+
+/// Non-empty trimmed entries of a comma-separated setting.
+pub fn split_list(raw: &str) -> impl Iterator<Item = &str> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+fn parse_range(entry: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match entry.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (entry, None),
+    };
+    let address = address.trim().parse::<IpAddr>().ok()?;
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse::<u8>().ok()?,
+        None => max_prefix,
+    };
+    (prefix <= max_prefix).then_some((address, prefix))
+}
+
+fn in_range(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_for_is_only_trusted_through_known_proxies() {
+        let trusted = TrustedProxies::parse("127.0.0.1, 10.0.0.0/8").expect("valid proxies");
+        let ip = |raw: &str| raw.parse::<IpAddr>().expect("valid ip");
+
+        assert_eq!(
+            client_ip(
+                ip("10.1.2.3"),
+                Some("1.1.1.1, 203.0.113.7, 10.0.0.5"),
+                &trusted
+            ),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(ip("198.51.100.1"), Some("203.0.113.7"), &trusted),
+            ip("198.51.100.1")
+        );
+        assert_eq!(client_ip(ip("127.0.0.1"), None, &trusted), ip("127.0.0.1"));
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+
+        assert_eq!(
+            normalize_base_path("companion/").as_deref(),
+            Some("/companion")
+        );
+        assert_eq!(normalize_base_path("/"), None);
+    }
+}
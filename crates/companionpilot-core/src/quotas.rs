@@ -0,0 +1,58 @@
+use std::{collections::HashMap, fmt};
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Serialize;
+
+/// A tool call was refused because the user spent their daily allowance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolQuotaExceeded {
+    pub tool_name: String,
+    pub limit: u32,
+}
+
+impl fmt::Display for ToolQuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "daily limit of {} `{}` calls reached for this user (resets at 00:00 UTC); tell the user kindly and answer without it",
+            self.limit, self.tool_name
+        )
+    }
+}
+
+impl std::error::Error for ToolQuotaExceeded {}
+
+/// Today's usage of one quota-limited tool.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolQuotaStatus {
+    pub tool_name: String,
+    pub used: u32,
+    pub limit: u32,
+    pub resets_at: DateTime<Utc>,
+}
+
+/// Quotas are counted per UTC day.
+pub fn quota_day(now: DateTime<Utc>) -> NaiveDate {
+    now.date_naive()
+}
+
+pub fn quota_resets_at(day: NaiveDate) -> DateTime<Utc> {
+    day.checked_add_days(Days::new(1))
+        .unwrap_or(day)
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc()
+}
+
+/// Parses `tool=limit` pairs separated by commas, e.g. `web_search=20`.
+/// Malformed entries are ignored.
+pub fn parse_tool_quotas(raw: &str) -> HashMap<String, u32> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let (tool_name, limit) = entry.split_once('=')?;
+            let tool_name = tool_name.trim();
+            let limit = limit.trim().parse::<u32>().ok()?;
+            (!tool_name.is_empty()).then(|| (tool_name.to_owned(), limit))
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// Reply text surfaces show when a user sends more messages than allowed.
+pub const RATE_LIMITED_REPLY_TEXT: &str =
+    "You're sending messages faster than I can keep up with. Give me a moment and try again.";
+
+/// Returned (inside `anyhow::Error`) when a user has used up the messages of
+/// the current window. Callers can `downcast_ref` it to answer politely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    pub limit: u32,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rate limit of {} messages reached; retry in {}s",
+            self.limit,
+            retry_after_secs(self.retry_after)
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Where a user stands in the current window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the window resets and the full limit is available again.
+    pub reset_after: Duration,
+}
+
+/// Fixed-window message limit per user. The orchestrator enforces it; the
+/// HTTP layer reads it back for rate-limit headers.
+#[derive(Debug)]
+pub struct UserRateLimiter {
+    limit: u32,
+    window: Duration,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl UserRateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit: limit.max(1),
+            window: window.max(Duration::from_secs(1)),
+            windows: Mutex::default(),
+        }
+    }
+
+    /// Counts one message for `user_id`, or refuses it when the window's
+    /// allowance is spent.
+    pub fn check(&self, user_id: &str) -> Result<RateLimitStatus, RateLimited> {
+        self.check_at(user_id, Instant::now())
+    }
+
+    /// The user's standing without counting a message.
+    pub fn status(&self, user_id: &str) -> RateLimitStatus {
+        self.status_at(user_id, Instant::now())
+    }
+
+    fn check_at(&self, user_id: &str, now: Instant) -> Result<RateLimitStatus, RateLimited> {
+        let mut windows = lock(&self.windows);
+        windows.retain(|_, (started_at, _)| now.duration_since(*started_at) < self.window);
+        let (started_at, used) = windows.entry(user_id.to_owned()).or_insert((now, 0));
+        let reset_after = self.window.saturating_sub(now.duration_since(*started_at));
+        if *used >= self.limit {
+            return Err(RateLimited {
+                limit: self.limit,
+                retry_after: reset_after,
+            });
+        }
+        *used += 1;
+        Ok(RateLimitStatus {
+            limit: self.limit,
+            remaining: self.limit - *used,
+            reset_after,
+        })
+    }
+
+    fn status_at(&self, user_id: &str, now: Instant) -> RateLimitStatus {
+        let windows = lock(&self.windows);
+        match windows.get(user_id) {
+            Some((started_at, used)) if now.duration_since(*started_at) < self.window => {
+                RateLimitStatus {
+                    limit: self.limit,
+                    remaining: self.limit.saturating_sub(*used),
+                    reset_after: self.window - now.duration_since(*started_at),
+                }
+            }
+            _ => RateLimitStatus {
+                limit: self.limit,
+                remaining: self.limit,
+                reset_after: self.window,
+            },
+        }
+    }
+}
+
+/// Whole seconds for `Retry-After`-style values, rounded up so clients never
+/// retry early.
+pub fn retry_after_secs(duration: Duration) -> u64 {
+    duration.as_millis().div_ceil(1000) as u64
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_messages_over_the_limit_until_the_window_resets() {
+        let limiter = UserRateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at("u1", start).map(|s| s.remaining), Ok(1));
+        assert_eq!(limiter.check_at("u1", start).map(|s| s.remaining), Ok(0));
+        let refused = limiter
+            .check_at("u1", start + Duration::from_secs(15))
+            .expect_err("third message is over the limit");
+        assert_eq!(retry_after_secs(refused.retry_after), 45);
+        assert_eq!(limiter.check_at("u2", start).map(|s| s.remaining), Ok(1));
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.status_at("u1", later).remaining, 2);
+        assert!(limiter.check_at("u1", later).is_ok());
+    }
+}
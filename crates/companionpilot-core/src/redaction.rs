@@ -0,0 +1,167 @@
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+
+/// Kinds of personal data the redactor recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionKind {
+    Email,
+    Phone,
+    /// Street addresses such as `221 Baker Street`.
+    Address,
+}
+
+impl RedactionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Phone => "phone",
+            Self::Address => "address",
+        }
+    }
+
+    fn pattern(self) -> &'static str {
+        match self {
+            Self::Email => r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b",
+            Self::Phone => {
+                r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{1,4}\)[\s.-]?)?\d{2,4}(?:[\s.-]\d{2,4}){1,4}\b|\+\d{8,15}\b"
+            }
+            Self::Address => {
+                r"(?i)\b\d{1,5}[a-z]?\s+(?:[a-z][a-z'-]*\s+){1,3}(?:street|st|avenue|ave|road|rd|boulevard|blvd|lane|ln|drive|dr|court|ct|way|place|pl|square|sq)\b"
+            }
+        }
+    }
+}
+
+/// Parses a comma-separated list such as `email,phone,address`.
+pub fn parse_redaction_kinds(raw: &str) -> Result<Vec<RedactionKind>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .map(|kind| match kind.to_ascii_lowercase().as_str() {
+            "email" => Ok(RedactionKind::Email),
+            "phone" => Ok(RedactionKind::Phone),
+            "address" => Ok(RedactionKind::Address),
+            _ => Err(format!(
+                "unknown redaction kind `{kind}`; expected email, phone or address"
+            )),
+        })
+        .collect()
+}
+
+/// Masks personal data and blocked words in text before it is stored, so
+/// logs and the dashboard do not collect raw PII from web results and user
+/// messages. Matches on the allowlist (exact values, or `@domain` for every
+/// address at a domain) are kept.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    rules: Vec<(RedactionKind, Regex)>,
+    words: Option<Regex>,
+    allowlist: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(kinds: &[RedactionKind], words: &[String], allowlist: &[String]) -> Self {
+        let words = words
+            .iter()
+            .map(|word| word.trim())
+            .filter(|word| !word.is_empty())
+            .map(regex::escape)
+            .collect::<Vec<_>>();
+        Self {
+            rules: kinds
+                .iter()
+                .map(|kind| {
+                    let regex = Regex::new(kind.pattern()).expect("built-in pattern is valid");
+                    (*kind, regex)
+                })
+                .collect(),
+            words: (!words.is_empty()).then(|| {
+                Regex::new(&format!(r"(?i)\b(?:{})\b", words.join("|")))
+                    .expect("escaped words form a valid pattern")
+            }),
+            allowlist: allowlist
+                .iter()
+                .map(|entry| entry.trim().to_lowercase())
+                .filter(|entry| !entry.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.words.is_none()
+    }
+
+    /// `text` with every match replaced by its kind, e.g. `[email]`; blocked
+    /// words become `[redacted]`.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for (kind, regex) in &self.rules {
+            let replaced = regex.replace_all(&text, |captures: &Captures| {
+                let found = &captures[0];
+                if self.allowed(found) || !plausible(*kind, found) {
+                    found.to_owned()
+                } else {
+                    format!("[{}]", kind.as_str())
+                }
+            });
+            if let Cow::Owned(replaced) = replaced {
+                text = Cow::Owned(replaced);
+            }
+        }
+        if let Some(words) = &self.words
+            && let Cow::Owned(replaced) = words.replace_all(&text, "[redacted]")
+        {
+            text = Cow::Owned(replaced);
+        }
+        text
+    }
+
+    /// [`Redactor::redact`] for owned text.
+    pub fn redact_owned(&self, text: String) -> String {
+        match self.redact(&text) {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(redacted) => redacted,
+        }
+    }
+
+    fn allowed(&self, found: &str) -> bool {
+        let found = found.to_lowercase();
+        self.allowlist.iter().any(|entry| {
+            *entry == found || (entry.starts_with('@') && found.ends_with(entry.as_str()))
+        })
+    }
+}
+
+/// Phone matches need 9-15 digits, so dates, times and version numbers stay.
+fn plausible(kind: RedactionKind, found: &str) -> bool {
+    match kind {
+        RedactionKind::Phone => {
+            let digits = found.chars().filter(char::is_ascii_digit).count();
+            (9..=15).contains(&digits)
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_personal_data_outside_the_allowlist() {
+        let kinds = parse_redaction_kinds("email, phone, address").expect("valid kinds");
+        assert!(parse_redaction_kinds("ssn").is_err());
+        let redactor = Redactor::new(&kinds, &["darn".to_owned()], &["@example.org".to_owned()]);
+
+        assert_eq!(
+            redactor.redact(
+                "Mail jane.doe@gmail.com or help@example.org, call +420 601 234 567, visit 221B Baker Street. Darn!"
+            ),
+            "Mail [email] or help@example.org, call [phone], visit [address]. [redacted]!"
+        );
+        let untouched = "Released 2024-05-01 at 18:00, version 1.2.3.";
+        assert_eq!(redactor.redact(untouched), untouched);
+        assert!(Redactor::new(&[], &[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,461 @@
+use std::sync::LazyLock;
+
+use serde_json::Value;
+use tracing::debug;
+
+use crate::{
+    guild_emoji::{GuildEmoji, render_guild_emoji},
+    guild_settings::CitationStyle,
+};
+
+/// Longest message Discord accepts.
+pub const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+/// Tags some models use for tool calls, removed with everything between them.
+const TOOL_MARKUP_TAGS: &[(&str, &str)] = &[
+    ("<tool_call>", "</tool_call>"),
+    ("<tool_use>", "</tool_use>"),
+    ("<function_calls>", "</function_calls>"),
+    ("<function_call>", "</function_call>"),
+];
+
+pub(crate) static DEFAULT_REPLY_PIPELINES: LazyLock<ReplyPipelines> =
+    LazyLock::new(ReplyPipelines::default);
+
+/// Where a finished reply is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplySurface {
+    Discord,
+    Http,
+    /// Spoken through text-to-speech.
+    Voice,
+}
+
+/// What filters may use besides the reply text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplyFilterContext<'a> {
+    pub citations: &'a [String],
+    pub citation_style: CitationStyle,
+    /// Custom emoji of the guild the reply is posted in.
+    pub guild_emoji: &'a [GuildEmoji],
+}
+
+/// One step of reply post-processing.
+pub trait ReplyFilter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, text: String, ctx: &ReplyFilterContext<'_>) -> String;
+}
+
+/// Filters applied in order to a final reply before it is delivered.
+#[derive(Default)]
+pub struct ReplyPipeline {
+    filters: Vec<Box<dyn ReplyFilter>>,
+}
+
+impl ReplyPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, filter: impl ReplyFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn filter_names(&self) -> Vec<&'static str> {
+        self.filters.iter().map(|filter| filter.name()).collect()
+    }
+
+    pub fn apply(&self, text: &str, ctx: &ReplyFilterContext<'_>) -> String {
+        self.filters.iter().fold(text.to_owned(), |text, filter| {
+            let filtered = filter.apply(text.clone(), ctx);
+            if filtered != text {
+                debug!(filter = filter.name(), "reply filter changed the reply");
+            }
+            filtered
+        })
+    }
+}
+
+/// One pipeline per delivery surface.
+pub struct ReplyPipelines {
+    pub discord: ReplyPipeline,
+    pub http: ReplyPipeline,
+    pub voice: ReplyPipeline,
+}
+
+impl ReplyPipelines {
+    pub fn for_surface(&self, surface: ReplySurface) -> &ReplyPipeline {
+        match surface {
+            ReplySurface::Discord => &self.discord,
+            ReplySurface::Http => &self.http,
+            ReplySurface::Voice => &self.voice,
+        }
+    }
+}
+
+impl Default for ReplyPipelines {
+    fn default() -> Self {
+        Self {
+            discord: ReplyPipeline::new()
+                .then(StripToolMarkup)
+                .then(StyleRules)
+                .then(DiscordMarkdown)
+                .then(GuildEmojiCodes)
+                .then(AttachCitations)
+                .then(MaxLength(DISCORD_MESSAGE_LIMIT)),
+            http: ReplyPipeline::new().then(StripToolMarkup).then(StyleRules),
+            voice: ReplyPipeline::new()
+                .then(StripToolMarkup)
+                .then(StyleRules)
+                .then(SpeechText),
+        }
+    }
+}
+
+/// Removes tool-call tags and fenced planner JSON that leaked into a reply.
+pub struct StripToolMarkup;
+
+impl ReplyFilter for StripToolMarkup {
+    fn name(&self) -> &'static str {
+        "strip_tool_markup"
+    }
+
+    fn apply(&self, mut text: String, _ctx: &ReplyFilterContext<'_>) -> String {
+        for (open, close) in TOOL_MARKUP_TAGS {
+            while let Some(start) = text.find(open) {
+                let end = text[start..]
+                    .find(close)
+                    .map_or(text.len(), |offset| start + offset + close.len());
+                text.replace_range(start..end, "");
+            }
+        }
+
+        let mut kept = Vec::new();
+        let mut fence: Option<Vec<&str>> = None;
+        for line in text.lines() {
+            match fence.as_mut() {
+                None if line.trim_start().starts_with("```") => fence = Some(vec![line]),
+                None => kept.push(line),
+                Some(block) => {
+                    block.push(line);
+                    if line.trim() == "```" {
+                        let block = fence.take().unwrap_or_default();
+                        if !is_planner_json(&block[1..block.len() - 1].join("\n")) {
+                            kept.extend(block);
+                        }
+                    }
+                }
+            }
+        }
+        kept.extend(fence.unwrap_or_default());
+        kept.join("\n")
+    }
+}
+
+/// Matches only the planner's own output shapes, so JSON the user asked for
+/// (which may well have an `action` or `tool` key) survives.
+fn is_planner_json(body: &str) -> bool {
+    let Ok(Value::Object(object)) = serde_json::from_str::<Value>(body) else {
+        return false;
+    };
+    let has_tool_calls = object.get("tool_calls").is_some_and(Value::is_array);
+    let unified =
+        has_tool_calls && (object.contains_key("memory") || object.contains_key("rationale"));
+    let follow_up = matches!(
+        object.get("action").and_then(Value::as_str),
+        Some("final" | "tools")
+    ) && (has_tool_calls || object.contains_key("final_answer"));
+    unified || follow_up
+}
+
+/// House style: no speaker label, no trailing spaces, at most one blank
+/// line in a row.
+pub struct StyleRules;
+
+impl ReplyFilter for StyleRules {
+    fn name(&self) -> &'static str {
+        "style_rules"
+    }
+
+    fn apply(&self, text: String, _ctx: &ReplyFilterContext<'_>) -> String {
+        let trimmed = text.trim();
+        let text = ["assistant:", "companionpilot:"]
+            .iter()
+            .find_map(|label| {
+                trimmed
+                    .get(..label.len())
+                    .filter(|prefix| prefix.eq_ignore_ascii_case(label))
+                    .map(|_| trimmed[label.len()..].trim_start())
+            })
+            .unwrap_or(trimmed);
+
+        let mut lines: Vec<&str> = Vec::new();
+        for line in text.lines().map(str::trim_end) {
+            if line.is_empty() && lines.last().is_some_and(|last| last.is_empty()) {
+                continue;
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+/// Rewrites markdown Discord does not render: headings below `###`, tables,
+/// horizontal rules and images.
+pub struct DiscordMarkdown;
+
+impl ReplyFilter for DiscordMarkdown {
+    fn name(&self) -> &'static str {
+        "discord_markdown"
+    }
+
+    fn apply(&self, text: String, _ctx: &ReplyFilterContext<'_>) -> String {
+        let mut in_code = false;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") {
+                in_code = !in_code;
+            }
+            if in_code || trimmed.starts_with("```") {
+                lines.push(line.to_owned());
+                continue;
+            }
+            if trimmed.starts_with("####") {
+                let heading = trimmed.trim_start_matches('#').trim();
+                lines.push(format!("**{heading}**"));
+            } else if is_table_separator(trimmed) || is_horizontal_rule(trimmed) {
+                continue;
+            } else if trimmed.starts_with('|') && trimmed.ends_with('|') && trimmed.len() > 1 {
+                let cells = trimmed[1..trimmed.len() - 1]
+                    .split('|')
+                    .map(str::trim)
+                    .collect::<Vec<_>>();
+                lines.push(cells.join(" · "));
+            } else {
+                lines.push(unwrap_images(line));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn is_table_separator(line: &str) -> bool {
+    line.starts_with('|')
+        && line.contains('-')
+        && line.chars().all(|ch| matches!(ch, '|' | '-' | ':' | ' '))
+}
+
+fn is_horizontal_rule(line: &str) -> bool {
+    let marks = line.replace(' ', "");
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| marks.chars().all(|ch| ch == *mark))
+}
+
+/// `![alt](url)` becomes the bare URL, which Discord embeds.
+fn unwrap_images(line: &str) -> String {
+    let mut line = line.to_owned();
+    while let Some(start) = line.find("![") {
+        let Some(url_start) = line[start..].find("](").map(|offset| start + offset + 2) else {
+            break;
+        };
+        let Some(url_end) = line[url_start..].find(')').map(|offset| url_start + offset) else {
+            break;
+        };
+        let url = line[url_start..url_end].to_owned();
+        line.replace_range(start..=url_end, &url);
+    }
+    line
+}
+
+/// Turns a reply into plain sentences for text-to-speech: no markdown, list
+/// markers, code fences or URLs.
+pub struct SpeechText;
+
+impl ReplyFilter for SpeechText {
+    fn name(&self) -> &'static str {
+        "speech_text"
+    }
+
+    fn apply(&self, text: String, _ctx: &ReplyFilterContext<'_>) -> String {
+        let mut sentences = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with("```") || is_table_separator(line) || is_horizontal_rule(line) {
+                continue;
+            }
+            let line = line.trim_start_matches('#').trim_start();
+            let line = strip_list_marker(line);
+            let spoken = unwrap_links(line)
+                .replace("**", "")
+                .replace("__", "")
+                .replace("~~", "")
+                .replace(['`', '*', '|'], " ")
+                .split_whitespace()
+                .filter(|word| !is_url(word))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let spoken = spoken.trim_end_matches([',', ';', ':', '-']).trim_end();
+            if spoken.is_empty() {
+                continue;
+            }
+            if spoken.ends_with(['.', '!', '?']) {
+                sentences.push(spoken.to_owned());
+            } else {
+                sentences.push(format!("{spoken}."));
+            }
+        }
+        sentences.join(" ")
+    }
+}
+
+fn strip_list_marker(line: &str) -> &str {
+    if let Some(rest) = ["- ", "* ", "+ ", "• "]
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+    {
+        return rest;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    match line[digits..]
+        .strip_prefix(". ")
+        .or(line[digits..].strip_prefix(") "))
+    {
+        Some(rest) if digits > 0 => rest,
+        _ => line,
+    }
+}
+
+/// `[text](url)` becomes `text`.
+fn unwrap_links(line: &str) -> String {
+    let mut line = line.to_owned();
+    while let Some(start) = line.find('[') {
+        let Some(text_end) = line[start..].find("](").map(|offset| start + offset) else {
+            break;
+        };
+        let Some(url_end) = line[text_end..].find(')').map(|offset| text_end + offset) else {
+            break;
+        };
+        let text = line[start + 1..text_end].to_owned();
+        line.replace_range(start..=url_end, &text);
+    }
+    line
+}
+
+fn is_url(word: &str) -> bool {
+    let word = word.trim_start_matches(['<', '(']);
+    word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.")
+}
+
+/// Renders the guild's custom emoji and drops emoji codes Discord would show
+/// as broken text.
+pub struct GuildEmojiCodes;
+
+impl ReplyFilter for GuildEmojiCodes {
+    fn name(&self) -> &'static str {
+        "guild_emoji"
+    }
+
+    fn apply(&self, text: String, ctx: &ReplyFilterContext<'_>) -> String {
+        render_guild_emoji(&text, ctx.guild_emoji)
+    }
+}
+
+/// Adds the reply's citations in the guild's citation style.
+pub struct AttachCitations;
+
+impl ReplyFilter for AttachCitations {
+    fn name(&self) -> &'static str {
+        "attach_citations"
+    }
+
+    fn apply(&self, text: String, ctx: &ReplyFilterContext<'_>) -> String {
+        ctx.citation_style.render(&text, ctx.citations)
+    }
+}
+
+/// Cuts replies longer than the given number of characters at a word
+/// boundary and marks the cut with an ellipsis.
+pub struct MaxLength(pub usize);
+
+impl ReplyFilter for MaxLength {
+    fn name(&self) -> &'static str {
+        "max_length"
+    }
+
+    fn apply(&self, text: String, _ctx: &ReplyFilterContext<'_>) -> String {
+        if text.chars().count() <= self.0 {
+            return text;
+        }
+        let cut = text
+            .char_indices()
+            .nth(self.0.saturating_sub(1))
+            .map_or(text.len(), |(index, _)| index);
+        let head = &text[..cut];
+        let head = head
+            .rfind(char::is_whitespace)
+            .filter(|index| *index > cut / 2)
+            .map_or(head, |index| &head[..index]);
+        format!("{}…", head.trim_end())
+    }
+}
+
+/// Appends `footer` on its own line, shortening `text` so that both fit in
+/// `limit` characters.
+pub fn append_within_limit(text: &str, footer: &str, limit: usize) -> String {
+    let room = limit.saturating_sub(footer.chars().count() + 1);
+    let text = MaxLength(room).apply(text.to_owned(), &ReplyFilterContext::default());
+    format!("{text}\n{footer}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discord_pipeline_cleans_and_formats_reply() {
+        let citations = vec!["https://example.com/a".to_owned()];
+        let ctx = ReplyFilterContext {
+            citations: &citations,
+            citation_style: CitationStyle::Compact,
+            ..ReplyFilterContext::default()
+        };
+        let raw = "Assistant: #### Results\n\n\n| City | Temp |\n|---|---|\n| Prague | 12 |  \n<tool_call>{\"tool\":\"web_search\"}</tool_call>\n```json\n{\"action\":\"final\",\"final_answer\":\"Hi\",\"tool_calls\":[]}\n```\n```rust\nlet x = 1;\n```";
+        assert_eq!(
+            ReplyPipelines::default().discord.apply(raw, &ctx),
+            "**Results**\n\nCity · Temp\nPrague · 12\n\n```rust\nlet x = 1;\n```\n-# <https://example.com/a>"
+        );
+        assert_eq!(
+            ReplyPipelines::default().http.apply(raw, &ctx),
+            "#### Results\n\n| City | Temp |\n|---|---|\n| Prague | 12 |\n\n```rust\nlet x = 1;\n```"
+        );
+
+        assert_eq!(
+            ReplyPipelines::default().voice.apply(
+                "## Weather\n- **Prague**: 12°C <https://example.com/w>\n2. Bring a [jacket](https://example.com/j)\n\nHave fun!",
+                &ReplyFilterContext::default()
+            ),
+            "Weather. Prague: 12°C. Bring a jacket. Have fun!"
+        );
+
+        let user_json = "```json\n{\"action\":\"deploy\",\"tool\":\"x\"}\n```";
+        assert_eq!(
+            ReplyPipelines::default()
+                .http
+                .apply(user_json, &ReplyFilterContext::default()),
+            user_json
+        );
+
+        let long = "word ".repeat(10);
+        let ctx = ReplyFilterContext::default();
+        assert_eq!(MaxLength(12).apply(long, &ctx), "word word…");
+        let footed = append_within_limit(&"word ".repeat(500), "-# note", DISCORD_MESSAGE_LIMIT);
+        assert!(footed.ends_with("…\n-# note"));
+        assert!(footed.chars().count() <= DISCORD_MESSAGE_LIMIT);
+        assert_eq!(append_within_limit("hi", "-# note", 2000), "hi\n-# note");
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::orchestrator::parse_json_plan;
+
+/// Output format requested by an API caller.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// The reply could not be shaped into JSON matching the caller's schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseFormatError {
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for ResponseFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reply does not match the requested JSON schema: {}",
+            self.errors.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for ResponseFormatError {}
+
+/// Checks that `schema` is itself a valid JSON Schema.
+pub fn check_response_schema(schema: &Value) -> Result<(), String> {
+    jsonschema::validator_for(schema)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+/// Parses `raw` as JSON (tolerating code fences and surrounding prose) and
+/// validates it against `schema`.
+pub fn validate_response(schema: &Value, raw: &str) -> Result<Value, Vec<String>> {
+    let value = parse_json_plan::<Value>(raw).map_err(|error| vec![error.to_string()])?;
+    let validator = jsonschema::validator_for(schema).map_err(|error| vec![error.to_string()])?;
+    let errors = validator
+        .iter_errors(&value)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            if path.is_empty() {
+                error.to_string()
+            } else {
+                format!("{path}: {error}")
+            }
+        })
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+pub(crate) fn response_format_instruction(schema: &Value) -> String {
+    format!(
+        "Output format override: answer with a single JSON value and nothing else (no markdown, no prose). It must satisfy this JSON Schema:\n{schema}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::validate_response;
+
+    #[test]
+    fn validates_fenced_json_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"mood": {"type": "string"}},
+            "required": ["mood"]
+        });
+
+        let value = validate_response(&schema, "```json\n{\"mood\": \"happy\"}\n```")
+            .expect("fenced JSON should validate");
+        assert_eq!(value["mood"], "happy");
+
+        let errors = validate_response(&schema, "{\"mood\": 3}").expect_err("wrong type");
+        assert_eq!(errors.len(), 1);
+        assert!(validate_response(&schema, "I feel happy").is_err());
+    }
+}
@@ -0,0 +1,465 @@
+use serde::{Deserialize, Serialize};
+
+/// How a message is handled before any model call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnRoute {
+    /// Run the unified planner, which may call tools and store memory.
+    Planner,
+    /// Pure small talk; reply directly without a planner round.
+    SmallTalk,
+    /// A question about, or deletion of, the user's stored memory; answered
+    /// from the store without any model call.
+    MemoryRequest,
+}
+
+impl TurnRoute {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Planner => "planner",
+            Self::SmallTalk => "small_talk",
+            Self::MemoryRequest => "memory_request",
+        }
+    }
+}
+
+/// Longest message, in words, that can still count as small talk.
+const MAX_SMALL_TALK_WORDS: usize = 6;
+
+const SMALL_TALK_WORDS: &[&str] = &[
+    "hi",
+    "hello",
+    "hey",
+    "heya",
+    "hiya",
+    "yo",
+    "sup",
+    "howdy",
+    "greetings",
+    "gm",
+    "gn",
+    "good",
+    "morning",
+    "afternoon",
+    "evening",
+    "night",
+    "nite",
+    "thanks",
+    "thank",
+    "thx",
+    "ty",
+    "tysm",
+    "you",
+    "u",
+    "so",
+    "much",
+    "a",
+    "lot",
+    "ok",
+    "okay",
+    "k",
+    "kk",
+    "alright",
+    "cool",
+    "nice",
+    "great",
+    "awesome",
+    "sweet",
+    "lol",
+    "lmao",
+    "lmfao",
+    "rofl",
+    "xd",
+    "yes",
+    "yeah",
+    "yep",
+    "yup",
+    "no",
+    "nope",
+    "nah",
+    "sure",
+    "bye",
+    "goodbye",
+    "cya",
+    "later",
+    "see",
+    "ya",
+    "np",
+    "welcome",
+    "wow",
+    "omg",
+    "oh",
+    "ah",
+    "hmm",
+    "same",
+    "true",
+    "fair",
+    "enough",
+    "got",
+    "it",
+    "gotcha",
+    "brb",
+    "gg",
+    "nvm",
+    "oops",
+    "ahoj",
+    "cau",
+    "diky",
+    "dekuju",
+];
+
+/// Cheap heuristic pre-classifier. Only short messages made entirely of
+/// greetings, acknowledgements, laughter or emoji are small talk; anything
+/// with a question, number or link goes to the planner.
+pub fn classify_turn(content: &str) -> TurnRoute {
+    let content = content.trim();
+    if content.is_empty()
+        || content.contains('?')
+        || content.contains("://")
+        || content.starts_with('/')
+        || content.chars().any(|ch| ch.is_ascii_digit())
+    {
+        return TurnRoute::Planner;
+    }
+
+    let lowered = content.to_lowercase();
+    let words = lowered
+        .split(|ch: char| !ch.is_alphanumeric() && ch != '\'')
+        .map(|word| word.trim_matches('\''))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    if words.len() > MAX_SMALL_TALK_WORDS {
+        return TurnRoute::Planner;
+    }
+    // Emoji- or punctuation-only messages have no words at all.
+    if words.iter().all(|word| is_small_talk_word(word)) {
+        TurnRoute::SmallTalk
+    } else {
+        TurnRoute::Planner
+    }
+}
+
+fn is_small_talk_word(word: &str) -> bool {
+    SMALL_TALK_WORDS.contains(&word) || is_laughter(word) || is_stretched(word)
+}
+
+/// "haha", "hehehe", "ahah", "looool".
+fn is_laughter(word: &str) -> bool {
+    let letters = word.chars().collect::<Vec<_>>();
+    let alternating = letters.len() >= 4
+        && letters.iter().all(|ch| matches!(ch, 'h' | 'a' | 'e'))
+        && letters
+            .windows(2)
+            .all(|pair| (pair[0] == 'h') != (pair[1] == 'h'));
+    let lol = letters.len() >= 3
+        && letters.first() == Some(&'l')
+        && letters.last() == Some(&'l')
+        && letters[1..letters.len() - 1].iter().all(|ch| *ch == 'o');
+    alternating || lol
+}
+
+/// "heyyy", "thanksss", "okkk": a small-talk word with its last letter
+/// repeated.
+fn is_stretched(word: &str) -> bool {
+    let Some(last) = word.chars().last() else {
+        return false;
+    };
+    let trimmed = word.trim_end_matches(last);
+    trimmed.len() + 1 < word.len()
+        && SMALL_TALK_WORDS.contains(&format!("{trimmed}{last}").as_str())
+}
+
+/// Words that usually mean the planner will reach for a tool (search, time,
+/// voice). Messages without any of them get speculative synthesis.
+const TOOL_HINTS: &[&str] = &[
+    "search",
+    "google",
+    "look up",
+    "lookup",
+    "find",
+    "latest",
+    "current",
+    "today",
+    "tonight",
+    "tomorrow",
+    "yesterday",
+    "now",
+    "news",
+    "weather",
+    "forecast",
+    "price",
+    "stock",
+    "score",
+    "time",
+    "date",
+    "when",
+    "release",
+    "version",
+    "http",
+    "www.",
+    "voice",
+    "join",
+    "leave",
+    "listen",
+    "play",
+    "remind",
+];
+
+/// Whether the planner is likely to request tools for this message, in which
+/// case no speculative reply is started. Cheap and deliberately eager: a
+/// false positive only gives up the speculation's head start, while a false
+/// negative wastes a speculative model call.
+pub fn likely_needs_tools(content: &str) -> bool {
+    let lowered = content.to_lowercase();
+    TOOL_HINTS.iter().any(|hint| lowered.contains(hint))
+}
+
+/// What kind of work a message asks for, used to pick the reply model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageComplexity {
+    /// Small talk and short, plain questions.
+    Simple,
+    /// Long messages and requests for analysis, comparison or planning.
+    Complex,
+    /// Code, errors and programming questions.
+    Code,
+    /// Stories, poems, jokes and role-play.
+    Creative,
+}
+
+impl MessageComplexity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Simple => "simple",
+            Self::Complex => "complex",
+            Self::Code => "code",
+            Self::Creative => "creative",
+        }
+    }
+}
+
+/// Messages with more words than this count as complex.
+const MAX_SIMPLE_WORDS: usize = 60;
+
+// Phrases rather than single words: "rust", "code" or "function" alone show
+// up in plenty of everyday messages.
+const CODE_HINTS: &[&str] = &[
+    "```",
+    "my code",
+    "this code",
+    "the code",
+    "rust code",
+    "python code",
+    "python script",
+    "not compile",
+    "won't compile",
+    "doesn't compile",
+    "compile error",
+    "compiler",
+    "borrow checker",
+    "syntax error",
+    "stack trace",
+    "traceback",
+    "segfault",
+    "null pointer",
+    "unit test",
+    "regex",
+    "sql query",
+    "javascript",
+    "typescript",
+    "error[",
+    "#include",
+];
+
+const CREATIVE_HINTS: &[&str] = &[
+    "poem",
+    "haiku",
+    "limerick",
+    "lyrics",
+    "write me",
+    "write a story",
+    "write a song",
+    "tell me a story",
+    "short story",
+    "a joke",
+    "roleplay",
+    "role-play",
+    "fan fiction",
+    "fanfic",
+];
+
+const COMPLEX_HINTS: &[&str] = &[
+    "explain",
+    "analyze",
+    "analyse",
+    "compare",
+    "comparison",
+    "pros and cons",
+    "trade-off",
+    "tradeoff",
+    "step by step",
+    "in detail",
+    "in depth",
+    "break down",
+    "summarize",
+    "summarise",
+    "difference between",
+];
+
+/// Cheap heuristic, checked in order: code, then creative, then complex;
+/// anything else is simple. Misrouting only costs reply quality or money,
+/// never a failed turn.
+pub fn classify_complexity(content: &str) -> MessageComplexity {
+    let lowered = content.trim().to_lowercase();
+    let mentions = |hints: &[&str]| hints.iter().any(|hint| contains_hint(&lowered, hint));
+    if mentions(CODE_HINTS) {
+        MessageComplexity::Code
+    } else if mentions(CREATIVE_HINTS) {
+        MessageComplexity::Creative
+    } else if lowered.split_whitespace().count() > MAX_SIMPLE_WORDS
+        || lowered.matches('?').count() > 1
+        || mentions(COMPLEX_HINTS)
+    {
+        MessageComplexity::Complex
+    } else {
+        MessageComplexity::Simple
+    }
+}
+
+/// Hints made of words match whole words only, so `story` does not match
+/// `history`; others, like "```", match anywhere.
+fn contains_hint(text: &str, hint: &str) -> bool {
+    if !hint.starts_with(char::is_alphanumeric) || !hint.ends_with(char::is_alphanumeric) {
+        return text.contains(hint);
+    }
+    text.match_indices(hint).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + hint.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Reply model per kind of message, e.g. a cheap model for small talk and a
+/// strong one for analysis. Unset kinds keep the default model.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelRoutes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simple: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creative: Option<String>,
+}
+
+impl ModelRoutes {
+    pub fn is_empty(&self) -> bool {
+        self.simple.is_none()
+            && self.complex.is_none()
+            && self.code.is_none()
+            && self.creative.is_none()
+    }
+
+    pub fn model_for(&self, complexity: MessageComplexity) -> Option<&str> {
+        match complexity {
+            MessageComplexity::Simple => self.simple.as_deref(),
+            MessageComplexity::Complex => self.complex.as_deref(),
+            MessageComplexity::Code => self.code.as_deref(),
+            MessageComplexity::Creative => self.creative.as_deref(),
+        }
+    }
+
+    /// Routes set here, falling back to `fallback` for the rest.
+    pub fn or(&self, fallback: &Self) -> Self {
+        Self {
+            simple: self.simple.clone().or_else(|| fallback.simple.clone()),
+            complex: self.complex.clone().or_else(|| fallback.complex.clone()),
+            code: self.code.clone().or_else(|| fallback.code.clone()),
+            creative: self.creative.clone().or_else(|| fallback.creative.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MessageComplexity, ModelRoutes, TurnRoute, classify_complexity, classify_turn,
+        likely_needs_tools,
+    };
+
+    #[test]
+    fn routes_only_pure_small_talk_past_the_planner() {
+        for message in [
+            "lol",
+            "Good morning!",
+            "thanks a lot 🙏",
+            "hahaha",
+            "heyyy",
+            "👍",
+        ] {
+            assert_eq!(classify_turn(message), TurnRoute::SmallTalk, "{message}");
+        }
+        for message in [
+            "good morning, what's the weather in Prague",
+            "how are you?",
+            "my name is Petr",
+            "thanks, remind me at 5",
+            "",
+        ] {
+            assert_eq!(classify_turn(message), TurnRoute::Planner, "{message}");
+        }
+    }
+
+    #[test]
+    fn flags_messages_that_probably_need_tools() {
+        assert!(likely_needs_tools("What's the weather in Prague?"));
+        assert!(likely_needs_tools("search the web for rust async traits"));
+        assert!(!likely_needs_tools("tell me a joke"));
+        assert!(!likely_needs_tools("my name is Petr"));
+    }
+
+    #[test]
+    fn routes_messages_to_models_by_complexity() {
+        for (message, expected) in [
+            ("hey, how are you?", MessageComplexity::Simple),
+            ("why are you so nice to me", MessageComplexity::Simple),
+            ("my plan for tonight is pizza", MessageComplexity::Simple),
+            ("my bike is covered in rust", MessageComplexity::Simple),
+            (
+                "what's the dress code at your office",
+                MessageComplexity::Simple,
+            ),
+            ("what's your favorite song", MessageComplexity::Simple),
+            ("imagine that lol", MessageComplexity::Simple),
+            ("why does my rust code not compile", MessageComplexity::Code),
+            ("```let x = 1;```", MessageComplexity::Code),
+            ("tell me a joke", MessageComplexity::Creative),
+            ("write me a poem about autumn", MessageComplexity::Creative),
+            ("tell me the history of Prague", MessageComplexity::Simple),
+            (
+                "compare living in Prague and Vienna with kids",
+                MessageComplexity::Complex,
+            ),
+        ] {
+            assert_eq!(classify_complexity(message), expected, "{message}");
+        }
+
+        let global = ModelRoutes {
+            simple: Some("cheap".to_owned()),
+            complex: Some("strong".to_owned()),
+            ..ModelRoutes::default()
+        };
+        let guild = ModelRoutes {
+            complex: Some("guild-strong".to_owned()),
+            ..ModelRoutes::default()
+        };
+        let routes = guild.or(&global);
+        assert_eq!(routes.model_for(MessageComplexity::Simple), Some("cheap"));
+        assert_eq!(
+            routes.model_for(MessageComplexity::Complex),
+            Some("guild-strong")
+        );
+        assert_eq!(routes.model_for(MessageComplexity::Code), None);
+        assert!(ModelRoutes::default().is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    model::{GenerationParams, ModelRequest},
+    types::ChatSession,
+};
+
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Whether a message at `at` should start a new session instead of joining
+/// `session`. Without a gap, only `/newchat` starts sessions.
+pub fn session_expired(session: &ChatSession, at: DateTime<Utc>, gap: Option<Duration>) -> bool {
+    let Some(gap) = gap.and_then(|gap| chrono::Duration::from_std(gap).ok()) else {
+        return false;
+    };
+    at - session.last_active_at > gap
+}
+
+/// Model request asking for a short title for a session, based on its first
+/// turn.
+pub fn session_title_request(user_message: &str, reply: &str) -> ModelRequest {
+    ModelRequest {
+        system_prompt: "You title chat sessions for CompanionPilot. Reply with a title of at most six words describing what the conversation is about. No quotes, no trailing punctuation, nothing else.".to_owned(),
+        user_prompt: format!("User: {user_message}\nAssistant: {reply}"),
+        params: GenerationParams {
+            temperature: Some(0.2),
+            max_tokens: Some(24),
+            ..GenerationParams::default()
+        },
+    }
+}
+
+/// Normalizes model output into a one-line title, or `None` if nothing
+/// usable is left.
+pub fn clean_session_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line
+        .strip_prefix("Title:")
+        .unwrap_or(line)
+        .trim()
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '*' | '`'))
+        .trim_end_matches(['.', '!', ','])
+        .trim();
+    if line.is_empty() {
+        return None;
+    }
+    Some(match line.char_indices().nth(MAX_TITLE_CHARS) {
+        Some((end, _)) => format!("{}…", line[..end].trim_end()),
+        None => line.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::{clean_session_title, session_expired};
+    use crate::types::ChatSession;
+
+    #[test]
+    fn expires_idle_sessions_and_cleans_titles() {
+        let now = Utc::now();
+        let session = ChatSession {
+            id: "session-1".to_owned(),
+            user_id: "u1".to_owned(),
+            title: None,
+            started_at: now - chrono::Duration::hours(3),
+            last_active_at: now - chrono::Duration::minutes(90),
+        };
+        let hour = Some(Duration::from_secs(3600));
+        assert!(session_expired(&session, now, hour));
+        assert!(!session_expired(
+            &session,
+            now,
+            Some(Duration::from_secs(7200))
+        ));
+        assert!(!session_expired(&session, now, None));
+
+        assert_eq!(
+            clean_session_title("\"Planning a Prague trip.\"\n").as_deref(),
+            Some("Planning a Prague trip")
+        );
+        assert_eq!(
+            clean_session_title("Title: Rust async traits").as_deref(),
+            Some("Rust async traits")
+        );
+        assert_eq!(clean_session_title("  \n\"\""), None);
+    }
+}
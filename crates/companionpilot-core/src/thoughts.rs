@@ -0,0 +1,69 @@
+use crate::model::ModelCompletion;
+
+const THINK_TAGS: [(&str, &str); 2] = [("<think>", "</think>"), ("<thinking>", "</thinking>")];
+
+/// Removes `<think>`/`<thinking>` blocks some models put inline and returns
+/// the remaining reply with the extracted reasoning. An unclosed block runs
+/// to the end of the text.
+pub fn split_thinking(raw: &str) -> (String, Option<String>) {
+    let mut reply = raw.to_owned();
+    let mut thoughts = Vec::new();
+    for (open, close) in THINK_TAGS {
+        while let Some(start) = reply.find(open) {
+            let body_start = start + open.len();
+            let (body_end, block_end) = match reply[body_start..].find(close) {
+                Some(offset) => (body_start + offset, body_start + offset + close.len()),
+                None => (reply.len(), reply.len()),
+            };
+            let thought = reply[body_start..body_end].trim().to_owned();
+            if !thought.is_empty() {
+                thoughts.push(thought);
+            }
+            reply.replace_range(start..block_end, "");
+        }
+    }
+    if thoughts.is_empty() {
+        return (raw.to_owned(), None);
+    }
+    (reply.trim().to_owned(), Some(thoughts.join("\n\n")))
+}
+
+/// The user-facing reply of a synthesis completion and all of its
+/// reasoning, whether returned separately or inline.
+pub fn reply_and_thought(completion: ModelCompletion) -> (String, Option<String>) {
+    let (reply, inline) = split_thinking(&completion.text);
+    let thought = match (completion.reasoning, inline) {
+        (Some(reasoning), Some(inline)) => Some(format!("{}\n\n{inline}", reasoning.trim())),
+        (reasoning, inline) => reasoning.map(|text| text.trim().to_owned()).or(inline),
+    };
+    (reply, thought)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reply_and_thought, split_thinking};
+    use crate::model::ModelCompletion;
+
+    #[test]
+    fn strips_inline_thinking_and_merges_provider_reasoning() {
+        assert_eq!(split_thinking("Hello!"), ("Hello!".to_owned(), None));
+        assert_eq!(
+            split_thinking("<think>\nUser greets me.\n</think>\n\nHi there!"),
+            ("Hi there!".to_owned(), Some("User greets me.".to_owned()))
+        );
+        assert_eq!(
+            split_thinking("Answer first. <thinking>never closed"),
+            ("Answer first.".to_owned(), Some("never closed".to_owned()))
+        );
+
+        let (reply, thought) = reply_and_thought(ModelCompletion {
+            text: "<think>check units</think>It's 5 km.".to_owned(),
+            reasoning: Some("User asks a distance. ".to_owned()),
+        });
+        assert_eq!(reply, "It's 5 km.");
+        assert_eq!(
+            thought.as_deref(),
+            Some("User asks a distance.\n\ncheck units")
+        );
+    }
+}
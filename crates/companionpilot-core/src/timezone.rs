@@ -0,0 +1,71 @@
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+
+use crate::types::MemoryFact;
+
+/// Fact key holding the user's IANA time zone, e.g. `Europe/Prague`. It is
+/// written by the planner like any other fact and can be edited from the
+/// dashboard.
+pub const TIMEZONE_FACT_KEY: &str = "timezone";
+
+/// Parses an IANA zone name, ignoring case and surrounding whitespace.
+pub fn parse_timezone(raw: &str) -> Option<Tz> {
+    let raw = raw.trim();
+    raw.parse::<Tz>().ok().or_else(|| {
+        chrono_tz::TZ_VARIANTS
+            .iter()
+            .copied()
+            .find(|tz| tz.name().eq_ignore_ascii_case(raw))
+    })
+}
+
+/// The user's time zone from their stored facts, if known and valid.
+pub fn user_timezone(facts: &[MemoryFact]) -> Option<Tz> {
+    facts
+        .iter()
+        .find(|fact| fact.key == TIMEZONE_FACT_KEY)
+        .and_then(|fact| parse_timezone(&fact.value))
+}
+
+/// Part of the day for a local hour, as used in "this morning" or "tonight".
+pub fn day_period(hour: u32) -> &'static str {
+    match hour {
+        5..=11 => "morning",
+        12..=16 => "afternoon",
+        17..=21 => "evening",
+        _ => "night",
+    }
+}
+
+/// Prompt line describing the user's local time.
+pub fn local_time_line(tz: Tz, now: DateTime<Utc>) -> String {
+    let local = now.with_timezone(&tz);
+    format!(
+        "The user's local time is {} ({}, {}); use it for words like today, this morning or tonight.",
+        local.format("%A %Y-%m-%d %H:%M"),
+        day_period(local.hour()),
+        tz.name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{local_time_line, parse_timezone};
+
+    #[test]
+    fn parses_zone_names_and_describes_local_time() {
+        let prague = parse_timezone(" europe/prague ").expect("zone should parse");
+        assert_eq!(prague.name(), "Europe/Prague");
+        assert_eq!(parse_timezone("Mars/Olympus"), None);
+
+        let now = Utc
+            .with_ymd_and_hms(2026, 1, 15, 6, 30, 0)
+            .single()
+            .expect("valid timestamp");
+        let line = local_time_line(prague, now);
+        assert!(line.contains("Thursday 2026-01-15 07:30"));
+        assert!(line.contains("(morning, Europe/Prague)"));
+    }
+}
@@ -0,0 +1,239 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::tools::find_tool_spec;
+
+/// Most steps one macro may expand into.
+pub const MAX_MACRO_STEPS: usize = 6;
+
+/// A named chain of tool calls the planner can request as one call, e.g.
+/// `morning_briefing` = `current_datetime` then `web_search`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolMacro {
+    pub name: String,
+    /// Shown to the planner as when to use the macro.
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroStep {
+    pub tool: String,
+    /// Fixed arguments; arguments passed to the macro call override them.
+    #[serde(default = "empty_args")]
+    pub args: Value,
+}
+
+fn empty_args() -> Value {
+    Value::Object(Map::new())
+}
+
+impl ToolMacro {
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let valid_name = (1..=32).contains(&self.name.len())
+            && self
+                .name
+                .chars()
+                .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_');
+        if !valid_name {
+            errors.push(format!(
+                "macro name `{}` must be 1-32 lowercase letters, digits or `_`",
+                self.name
+            ));
+        }
+        if find_tool_spec(&self.name).is_some() {
+            errors.push(format!("macro `{}` shadows a tool", self.name));
+        }
+        if self.steps.is_empty() || self.steps.len() > MAX_MACRO_STEPS {
+            errors.push(format!(
+                "macro `{}` needs 1-{MAX_MACRO_STEPS} steps",
+                self.name
+            ));
+        }
+        for step in &self.steps {
+            if find_tool_spec(&step.tool).is_none() {
+                errors.push(format!(
+                    "macro `{}`: unknown tool `{}`",
+                    self.name, step.tool
+                ));
+            }
+            if !step.args.is_object() {
+                errors.push(format!(
+                    "macro `{}`: args of `{}` must be a table",
+                    self.name, step.tool
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Arguments for each step: its fixed args overlaid with those `call_args`
+    /// keys the step's tool accepts.
+    pub fn step_args(&self, call_args: &Value) -> Vec<(String, Value)> {
+        self.steps
+            .iter()
+            .map(|step| {
+                let mut args = step.args.as_object().cloned().unwrap_or_default();
+                let accepted = find_tool_spec(&step.tool)
+                    .and_then(|spec| spec.args_schema.get("properties").cloned())
+                    .and_then(|properties| properties.as_object().cloned())
+                    .unwrap_or_default();
+                if let Some(call_args) = call_args.as_object() {
+                    for (key, value) in call_args {
+                        if accepted.contains_key(key) {
+                            args.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+                (step.tool.clone(), Value::Object(args))
+            })
+            .collect()
+    }
+
+    /// Keys a macro call may pass, gathered from its steps' schemas.
+    fn accepted_args(&self) -> Vec<String> {
+        let mut keys = self
+            .steps
+            .iter()
+            .filter_map(|step| find_tool_spec(&step.tool))
+            .filter_map(|spec| spec.args_schema.get("properties").cloned())
+            .filter_map(|properties| properties.as_object().cloned())
+            .flat_map(|properties| properties.into_iter().map(|(key, _)| key))
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MacroFile {
+    #[serde(default)]
+    macros: Vec<ToolMacro>,
+}
+
+/// Tool macros defined by the admin, keyed by name.
+#[derive(Debug, Clone, Default)]
+pub struct ToolMacroRegistry {
+    macros: BTreeMap<String, ToolMacro>,
+}
+
+impl ToolMacroRegistry {
+    /// Parses a `[[macros]]` TOML file; fails listing every invalid macro.
+    pub fn from_toml(raw: &str) -> anyhow::Result<Self> {
+        let file: MacroFile = toml::from_str(raw)?;
+        let mut errors = Vec::new();
+        let mut macros = BTreeMap::new();
+        for tool_macro in file.macros {
+            if let Err(macro_errors) = tool_macro.validate() {
+                errors.extend(macro_errors);
+                continue;
+            }
+            if macros.contains_key(&tool_macro.name) {
+                errors.push(format!("macro `{}` is defined twice", tool_macro.name));
+                continue;
+            }
+            macros.insert(tool_macro.name.clone(), tool_macro);
+        }
+        anyhow::ensure!(
+            errors.is_empty(),
+            "invalid tool macros: {}",
+            errors.join("; ")
+        );
+        Ok(Self { macros })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolMacro> {
+        self.macros.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ToolMacro> {
+        self.macros.values()
+    }
+
+    /// Planner-facing list of macros, in the shape of the tool inventory.
+    pub fn planner_inventory(&self) -> Value {
+        Value::Array(
+            self.iter()
+                .map(|tool_macro| {
+                    json!({
+                        "macro_name": tool_macro.name,
+                        "when_to_use": tool_macro.description,
+                        "steps": tool_macro
+                            .steps
+                            .iter()
+                            .map(|step| step.tool.as_str())
+                            .collect::<Vec<_>>(),
+                        "accepted_args": tool_macro.accepted_args(),
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macros_parse_and_merge_call_args_into_steps() {
+        let registry = ToolMacroRegistry::from_toml(
+            r#"
+            [[macros]]
+            name = "morning_briefing"
+            description = "Start-of-day date and headlines"
+            steps = [
+                { tool = "current_datetime" },
+                { tool = "web_search", args = { query = "top news today", max_results = 3 } },
+            ]
+            "#,
+        )
+        .expect("valid macros");
+
+        let briefing = registry.get("morning_briefing").expect("macro");
+        let steps = briefing.step_args(&json!({"timezone": "Europe/Prague", "bogus": 1}));
+        assert_eq!(
+            steps[0],
+            (
+                "current_datetime".to_owned(),
+                json!({"timezone": "Europe/Prague"})
+            )
+        );
+        assert_eq!(
+            steps[1],
+            (
+                "web_search".to_owned(),
+                json!({"query": "top news today", "max_results": 3})
+            )
+        );
+        assert_eq!(
+            registry.planner_inventory()[0]["steps"],
+            json!(["current_datetime", "web_search"])
+        );
+
+        let error = ToolMacroRegistry::from_toml(
+            "[[macros]]\nname = \"web_search\"\nsteps = [{ tool = \"nope\" }]",
+        )
+        .expect_err("invalid macro");
+        let message = error.to_string();
+        assert!(message.contains("shadows a tool"));
+        assert!(message.contains("unknown tool `nope`"));
+    }
+}
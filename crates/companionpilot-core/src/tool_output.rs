@@ -0,0 +1,159 @@
+use std::{borrow::Cow, collections::HashMap};
+
+/// Characters of one tool output shown to the planner and synthesis when no
+/// per-tool budget is set.
+pub const DEFAULT_TOOL_OUTPUT_CHARS: usize = 8_000;
+
+/// Parses `tool=chars` pairs separated by commas, e.g. `web_search=3000`.
+/// Malformed entries are ignored.
+pub fn parse_tool_output_budgets(raw: &str) -> HashMap<String, usize> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let (tool_name, chars) = entry.split_once('=')?;
+            let tool_name = tool_name.trim();
+            let chars = chars.trim().parse::<usize>().ok()?;
+            (!tool_name.is_empty()).then(|| (tool_name.to_owned(), chars))
+        })
+        .collect()
+}
+
+/// Shortens a tool output to about `max_chars` characters. The beginning and
+/// end are kept whole sentence by sentence, and Markdown headings from the
+/// middle are kept as an outline of what was left out. A note telling the
+/// model the output was truncated is appended. Zero means no limit.
+pub fn truncate_tool_output(text: &str, max_chars: usize) -> Cow<'_, str> {
+    let total = text.chars().count();
+    if max_chars == 0 || total <= max_chars {
+        return Cow::Borrowed(text);
+    }
+    let segments = sentences(text);
+    let lengths = segments
+        .iter()
+        .map(|segment| segment.chars().count())
+        .collect::<Vec<_>>();
+    let head_budget = max_chars * 3 / 5;
+    let tail_budget = max_chars / 4;
+
+    let mut head_end = 0;
+    let mut head_chars = 0;
+    while head_end < segments.len() && head_chars + lengths[head_end] <= head_budget {
+        head_chars += lengths[head_end];
+        head_end += 1;
+    }
+    let mut tail_start = segments.len();
+    let mut tail_chars = 0;
+    while tail_start > head_end + 1 && tail_chars + lengths[tail_start - 1] <= tail_budget {
+        tail_start -= 1;
+        tail_chars += lengths[tail_start];
+    }
+
+    // A first or last sentence longer than its budget is cut at a word.
+    let head = if head_end == 0 {
+        let cut = cut_at_word(segments[0], head_budget);
+        head_end = 1;
+        cut
+    } else {
+        segments[..head_end].concat()
+    };
+    let tail = if tail_start == segments.len() && tail_start > head_end {
+        let last = segments[tail_start - 1];
+        tail_start -= 1;
+        last_words(last, tail_budget)
+    } else {
+        segments[tail_start..].concat()
+    };
+
+    let mut outline_budget = max_chars.saturating_sub(head_budget + tail_budget);
+    let mut outline = Vec::new();
+    for (segment, length) in segments[head_end..tail_start]
+        .iter()
+        .zip(&lengths[head_end..tail_start])
+    {
+        if segment.trim_start().starts_with('#') && *length <= outline_budget {
+            outline_budget -= length;
+            outline.push(segment.trim());
+        }
+    }
+
+    let mut truncated = head.trim_end().to_owned();
+    truncated.push_str("\n[…]\n");
+    if !outline.is_empty() {
+        truncated.push_str(&outline.join("\n"));
+        truncated.push_str("\n[…]\n");
+    }
+    truncated.push_str(tail.trim_start());
+    let kept = truncated.chars().count();
+    truncated.push_str(&format!(
+        "\n[Output truncated: kept {kept} of {total} characters; the parts marked […] were left out and may hold more detail.]"
+    ));
+    Cow::Owned(truncated)
+}
+
+/// `text` split after sentence ends and line breaks; the pieces concatenate
+/// back to `text`.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, ch)) = chars.next() {
+        let ends_sentence = matches!(ch, '.' | '!' | '?')
+            && chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+        if ch == '\n' || ends_sentence {
+            let end = index + ch.len_utf8();
+            segments.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        segments.push(&text[start..]);
+    }
+    segments
+}
+
+/// The first `max_chars` characters of `text`, ending at a word boundary.
+fn cut_at_word(text: &str, max_chars: usize) -> String {
+    let cut = text.chars().take(max_chars).collect::<String>();
+    match cut.rfind(char::is_whitespace) {
+        Some(end) if end > 0 => cut[..end].to_owned(),
+        _ => cut,
+    }
+}
+
+/// The last `max_chars` characters of `text`, starting at a word boundary.
+fn last_words(text: &str, max_chars: usize) -> String {
+    let skip = text.chars().count().saturating_sub(max_chars);
+    let cut = text.chars().skip(skip).collect::<String>();
+    match cut.find(char::is_whitespace) {
+        Some(start) if skip > 0 => cut[start..].to_owned(),
+        _ => cut,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_beginning_headings_and_end() {
+        let filler = "This sentence only pads the page out. ".repeat(40);
+        let text = format!(
+            "# Rust 1.80\nThe release adds LazyCell. {filler}\n## Cargo changes\n{filler}\nIn short, upgrade soon."
+        );
+
+        let truncated = truncate_tool_output(&text, 400);
+        assert!(truncated.starts_with("# Rust 1.80\nThe release adds LazyCell."));
+        assert!(truncated.contains("\n## Cargo changes\n"));
+        assert!(truncated.contains("In short, upgrade soon.\n[Output truncated: kept "));
+        assert!(truncated.chars().count() < 600);
+        // Sentences are kept whole.
+        assert!(!truncated.contains("pads the\n"));
+
+        assert_eq!(truncate_tool_output("short", 400), "short");
+        assert_eq!(truncate_tool_output(&text, 0), text);
+        assert!(truncate_tool_output(&"x".repeat(1_000), 100).starts_with(&"x".repeat(60)));
+        assert_eq!(
+            parse_tool_output_budgets("web_search=3000, bad, fetch_url=x"),
+            HashMap::from([("web_search".to_owned(), 3000)])
+        );
+    }
+}
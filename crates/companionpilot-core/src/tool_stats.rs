@@ -0,0 +1,205 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+pub struct ToolStatsConfig {
+    /// Most recent calls per tool kept for rates and percentiles.
+    pub window: usize,
+    /// Calls required in the window before failure alerts can fire.
+    pub min_samples: usize,
+    /// Failure rate (0.0-1.0) at or above which a tool is reported unhealthy.
+    pub failure_rate_threshold: f32,
+}
+
+impl Default for ToolStatsConfig {
+    fn default() -> Self {
+        Self {
+            window: 200,
+            min_samples: 10,
+            failure_rate_threshold: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolStats {
+    pub tool_name: String,
+    /// Calls since process start.
+    pub total_calls: u64,
+    /// Calls in the rolling window.
+    pub window_calls: usize,
+    pub window_failures: usize,
+    pub success_rate: f32,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub alerting: bool,
+}
+
+/// Raised once when a tool crosses the failure threshold; cleared when it
+/// recovers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolFailureAlert {
+    pub tool_name: String,
+    pub failure_rate: f32,
+    pub window_calls: usize,
+}
+
+impl fmt::Display for ToolFailureAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tool `{}` is failing: {:.0}% of the last {} calls failed",
+            self.tool_name,
+            self.failure_rate * 100.0,
+            self.window_calls
+        )
+    }
+}
+
+#[derive(Default)]
+struct ToolWindow {
+    total_calls: u64,
+    samples: VecDeque<(bool, u64)>,
+    alerting: bool,
+}
+
+impl ToolWindow {
+    fn failures(&self) -> usize {
+        self.samples.iter().filter(|(success, _)| !success).count()
+    }
+
+    fn failure_rate(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.failures() as f32 / self.samples.len() as f32
+    }
+}
+
+/// In-process rolling success rates and latency percentiles per tool.
+pub struct ToolStatsAggregator {
+    config: ToolStatsConfig,
+    tools: Mutex<HashMap<String, ToolWindow>>,
+}
+
+impl Default for ToolStatsAggregator {
+    fn default() -> Self {
+        Self::new(ToolStatsConfig::default())
+    }
+}
+
+impl ToolStatsAggregator {
+    pub fn new(config: ToolStatsConfig) -> Self {
+        Self {
+            config,
+            tools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records one call and returns an alert when the tool just crossed the
+    /// failure threshold.
+    pub fn record(
+        &self,
+        tool_name: &str,
+        success: bool,
+        duration_ms: u64,
+    ) -> Option<ToolFailureAlert> {
+        let mut tools = self
+            .tools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let window = tools.entry(tool_name.to_owned()).or_default();
+        window.total_calls += 1;
+        window.samples.push_back((success, duration_ms));
+        while window.samples.len() > self.config.window.max(1) {
+            window.samples.pop_front();
+        }
+
+        let failure_rate = window.failure_rate();
+        let unhealthy = window.samples.len() >= self.config.min_samples
+            && failure_rate >= self.config.failure_rate_threshold;
+        let crossed = unhealthy && !window.alerting;
+        window.alerting = unhealthy;
+
+        crossed.then(|| ToolFailureAlert {
+            tool_name: tool_name.to_owned(),
+            failure_rate,
+            window_calls: window.samples.len(),
+        })
+    }
+
+    pub fn snapshot(&self) -> Vec<ToolStats> {
+        let tools = self
+            .tools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut stats = tools
+            .iter()
+            .map(|(tool_name, window)| {
+                let mut durations = window
+                    .samples
+                    .iter()
+                    .map(|(_, duration_ms)| *duration_ms)
+                    .collect::<Vec<_>>();
+                durations.sort_unstable();
+                ToolStats {
+                    tool_name: tool_name.clone(),
+                    total_calls: window.total_calls,
+                    window_calls: window.samples.len(),
+                    window_failures: window.failures(),
+                    success_rate: 1.0 - window.failure_rate(),
+                    p50_ms: percentile(&durations, 50),
+                    p95_ms: percentile(&durations, 95),
+                    p99_ms: percentile(&durations, 99),
+                    alerting: window.alerting,
+                }
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by(|left, right| left.tool_name.cmp(&right.tool_name));
+        stats
+    }
+}
+
+/// Nearest-rank percentile of an ascending slice.
+pub(crate) fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ToolStatsAggregator, ToolStatsConfig};
+
+    #[test]
+    fn reports_percentiles_and_alerts_once_per_outage() {
+        let stats = ToolStatsAggregator::new(ToolStatsConfig {
+            window: 10,
+            min_samples: 4,
+            failure_rate_threshold: 0.5,
+        });
+        for duration_ms in 1..=10 {
+            assert!(stats.record("web_search", true, duration_ms * 10).is_none());
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot[0].p50_ms, 50);
+        assert_eq!(snapshot[0].p95_ms, 100);
+        assert_eq!(snapshot[0].success_rate, 1.0);
+
+        let alerts = (0..6)
+            .filter_map(|_| stats.record("web_search", false, 10))
+            .collect::<Vec<_>>();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].tool_name, "web_search");
+        assert!(stats.snapshot()[0].alerting);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{fmt, sync::Arc};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{Value, json};
+use tracing::info;
+
+use super::{ToolResult, ToolSpec};
+use crate::{
+    memory::MemoryStore,
+    types::{ChatSearchQuery, MessageCtx, context_speaker},
+    widgets::{is_widget_guild, is_widget_user},
+};
+
+const DEFAULT_RESULTS: u64 = 5;
+const MAX_RESULTS: u64 = 10;
+/// Longest message excerpt shown per hit.
+const MAX_HIT_CHARS: usize = 400;
+
+/// Full-text search over the user's own earlier conversations, for recalling
+/// what was said beyond the recent-turn window and the fact store. Only the
+/// current guild is searched, or only DMs from a DM, so nothing said in
+/// private is quoted into a server.
+#[derive(Clone)]
+pub struct ChatHistorySearchTool {
+    memory: Arc<dyn MemoryStore>,
+}
+
+impl fmt::Debug for ChatHistorySearchTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChatHistorySearchTool")
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChatHistorySearchTool {
+    pub fn spec() -> ToolSpec {
+        ToolSpec {
+            tool_name: "chat_history_search",
+            args_schema: json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "minLength": 1,
+                        "pattern": "\\S",
+                        "description": "A few keywords that must all appear in the message, e.g. `book reading`."
+                    },
+                    "after": {
+                        "type": "string",
+                        "pattern": "^\\d{4}-\\d{2}-\\d{2}$",
+                        "description": "Only messages on or after this date (YYYY-MM-DD)."
+                    },
+                    "before": {
+                        "type": "string",
+                        "pattern": "^\\d{4}-\\d{2}-\\d{2}$",
+                        "description": "Only messages before this date (YYYY-MM-DD)."
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_RESULTS,
+                        "default": DEFAULT_RESULTS
+                    }
+                },
+                "required": ["query"]
+            }),
+            when_to_use: "User asks about something they said or discussed with you earlier that is not in the recent turns or known facts.",
+            when_not_to_use: "The answer is in the recent conversation or the known user facts, or it is about the outside world.",
+        }
+    }
+
+    pub fn new(memory: Arc<dyn MemoryStore>) -> Self {
+        Self { memory }
+    }
+
+    pub async fn search(&self, args: Value, ctx: &MessageCtx) -> anyhow::Result<ToolResult> {
+        // Widget visitors choose their own ids, so anyone could read theirs.
+        if is_widget_guild(&ctx.guild_id) || is_widget_user(&ctx.user_id) {
+            anyhow::bail!("chat_history_search is not available in website widgets");
+        }
+        let text = args
+            .get("query")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|query| !query.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("chat_history_search requires string arg `query`"))?;
+        let after = date_arg(&args, "after")?;
+        // The current message is recorded before tools run; leave it out.
+        let before =
+            date_arg(&args, "before")?.map_or(ctx.timestamp, |before| before.min(ctx.timestamp));
+        let limit = args
+            .get("max_results")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_RESULTS)
+            .clamp(1, MAX_RESULTS) as usize;
+
+        let hits = self
+            .memory
+            .search_chat_messages(
+                &ctx.user_id,
+                &ChatSearchQuery {
+                    text: text.to_owned(),
+                    guild_id: ctx.guild_id.clone(),
+                    after,
+                    before: Some(before),
+                    limit,
+                },
+            )
+            .await?;
+        info!(hits = hits.len(), "chat history search");
+        if hits.is_empty() {
+            return Ok(ToolResult {
+                text: format!("No earlier messages mention `{text}`."),
+                citations: Vec::new(),
+                data: Some(json!({ "query": text, "hits": [] })),
+            });
+        }
+
+        let lines = hits
+            .iter()
+            .map(|message| {
+                let excerpt = message
+                    .content
+                    .chars()
+                    .take(MAX_HIT_CHARS)
+                    .collect::<String>();
+                format!(
+                    "- {} {}: {}",
+                    message.timestamp.format("%Y-%m-%d %H:%M UTC"),
+                    context_speaker(message.role, message.modality),
+                    excerpt.replace('\n', " ")
+                )
+            })
+            .collect::<Vec<_>>();
+        let data = hits
+            .iter()
+            .map(|message| {
+                json!({
+                    "timestamp": message.timestamp.to_rfc3339(),
+                    "role": message.role.as_str(),
+                    "channel_id": message.channel_id,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(ToolResult {
+            text: format!(
+                "Earlier messages mentioning `{text}`, best matches first:\n{}",
+                lines.join("\n")
+            ),
+            citations: Vec::new(),
+            data: Some(json!({ "query": text, "hits": data })),
+        })
+    }
+}
+
+/// Midnight UTC of a `YYYY-MM-DD` argument.
+fn date_arg(args: &Value, name: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let Some(raw) = args.get(name).and_then(Value::as_str) else {
+        return Ok(None);
+    };
+    let date = NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("`{name}` must be a date like 2026-01-31, got `{raw}`"))?;
+    Ok(Some(date.and_time(chrono::NaiveTime::MIN).and_utc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::{
+        memory::InMemoryMemoryStore,
+        types::{ChatMessageRecord, ChatRole, Modality},
+    };
+
+    #[tokio::test]
+    async fn finds_older_messages_by_keyword_and_date() {
+        let memory: Arc<dyn MemoryStore> = Arc::new(InMemoryMemoryStore::default());
+        let january = Utc
+            .with_ymd_and_hms(2026, 1, 12, 20, 0, 0)
+            .single()
+            .expect("valid timestamp");
+        let now = Utc::now();
+        for (guild_id, content, timestamp) in [
+            ("g1", "I'm reading Piranesi, such a strange book", january),
+            (
+                "g1",
+                "Finished the book, loved it",
+                january + Duration::days(60),
+            ),
+            ("g1", "Bookmarks are handy", january),
+            ("g2", "A book from another server", january),
+            ("dm", "A private book", january),
+            ("g1", "what book did I say I was reading?", now),
+        ] {
+            memory
+                .record_chat_message(ChatMessageRecord {
+                    id: String::new(),
+                    user_id: "u1".to_owned(),
+                    guild_id: guild_id.to_owned(),
+                    channel_id: "c1".to_owned(),
+                    role: ChatRole::User,
+                    content: content.to_owned(),
+                    timestamp,
+                    modality: Modality::Text,
+                    session_id: None,
+                    pinned: false,
+                    source_message_id: None,
+                })
+                .await
+                .expect("recorded");
+        }
+        let tool = ChatHistorySearchTool::new(memory);
+        let ctx = MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "what book did I say I was reading?".into(),
+            timestamp: now,
+            attachments: Vec::new(),
+        };
+
+        let result = tool
+            .search(json!({"query": "book"}), &ctx)
+            .await
+            .expect("search succeeds");
+        // The question itself, other guilds, DMs and partial words are not hits.
+        assert_eq!(
+            result.data.expect("hit data")["hits"]
+                .as_array()
+                .map(Vec::len),
+            Some(2)
+        );
+        let result = tool
+            .search(
+                json!({"query": "reading book", "after": "2026-01-01", "before": "2026-02-01"}),
+                &ctx,
+            )
+            .await
+            .expect("search succeeds");
+        assert!(result.text.contains("Piranesi"));
+        assert!(!result.text.contains("loved it"));
+        assert!(
+            tool.search(json!({"query": "book", "after": "January"}), &ctx)
+                .await
+                .is_err()
+        );
+
+        let dm = MessageCtx {
+            guild_id: "dm".into(),
+            ..ctx.clone()
+        };
+        let result = tool
+            .search(json!({"query": "book"}), &dm)
+            .await
+            .expect("search succeeds");
+        assert!(result.text.contains("A private book"));
+        assert!(!result.text.contains("Piranesi"));
+
+        let widget = MessageCtx {
+            user_id: "widget:site:u1".into(),
+            guild_id: "widget:site".into(),
+            ..ctx
+        };
+        assert!(
+            tool.search(json!({"query": "book"}), &widget)
+                .await
+                .is_err()
+        );
+    }
+}
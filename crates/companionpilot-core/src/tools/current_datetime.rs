@@ -1,33 +1,142 @@
-use chrono::Utc;
-use serde_json::Value;
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use chrono_tz::Tz;
+use serde_json::{Value, json};
 
-use super::ToolResult;
+use super::{ToolArgError, ToolResult, ToolSpec};
+use crate::timezone::{day_period, parse_timezone};
+
+/// Output forms `current_datetime` can return.
+const FORMATS: [&str; 4] = ["all", "iso", "human", "relative"];
 
 #[derive(Debug, Clone, Default)]
 pub struct CurrentDateTimeTool;
 
 impl CurrentDateTimeTool {
-    pub async fn get_now(&self, _args: Value) -> anyhow::Result<ToolResult> {
-        let now = Utc::now();
-        let text = format!(
-            "Current UTC datetime: {}\nCurrent UTC date: {}\nCurrent UTC year: {}",
-            now.to_rfc3339(),
-            now.format("%Y-%m-%d"),
-            now.format("%Y")
-        );
+    pub fn spec() -> ToolSpec {
+        ToolSpec {
+            tool_name: "current_datetime",
+            args_schema: json!({
+                "type": "object",
+                "properties": {
+                    "timezone": {
+                        "type": "string",
+                        "description": "IANA time zone for local time, e.g. Europe/Prague. Defaults to the user's stored time zone."
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": FORMATS,
+                        "description": "iso (machine timestamps), human (written-out date), relative (today, yesterday, tomorrow, this week) or all (default)."
+                    }
+                }
+            }),
+            when_to_use: "Need the exact current date/time before time-sensitive lookups or answers.",
+            when_not_to_use: "Question is timeless or explicitly historical.",
+        }
+    }
+
+    /// Checks the JSON Schema cannot express: the time zone must exist.
+    pub fn check_args(args: &Value) -> Vec<ToolArgError> {
+        match args.get("timezone").and_then(Value::as_str) {
+            Some(raw) if parse_timezone(raw).is_none() => vec![ToolArgError {
+                path: "/timezone".to_owned(),
+                message: format!(
+                    "unknown time zone `{raw}`; use an IANA name such as Europe/Prague"
+                ),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    pub async fn get_now(&self, args: Value) -> anyhow::Result<ToolResult> {
+        let tz = match args.get("timezone").and_then(Value::as_str) {
+            Some(raw) => Some(parse_timezone(raw).ok_or_else(|| {
+                anyhow::anyhow!("unknown time zone `{raw}`; use an IANA name such as Europe/Prague")
+            })?),
+            None => None,
+        };
+        let format = args.get("format").and_then(Value::as_str).unwrap_or("all");
 
+        let now = Utc::now();
         Ok(ToolResult {
-            text,
+            text: describe_datetime(now, tz, format),
             citations: Vec::new(),
+            data: Some(datetime_data(now, tz)),
         })
     }
 }
 
+fn datetime_data(now: DateTime<Utc>, tz: Option<Tz>) -> Value {
+    let local = now.with_timezone(&tz.unwrap_or(Tz::UTC));
+    json!({
+        "utc": now.to_rfc3339(),
+        "timezone": local.timezone().name(),
+        "local": local.to_rfc3339(),
+        "date": local.format("%Y-%m-%d").to_string(),
+        "weekday": local.format("%A").to_string(),
+        "part_of_day": day_period(local.hour()),
+    })
+}
+
+/// Text for `now` in the requested forms. Human and relative forms use the
+/// given time zone, or UTC when there is none.
+fn describe_datetime(now: DateTime<Utc>, tz: Option<Tz>, format: &str) -> String {
+    let all = !matches!(format, "iso" | "human" | "relative");
+    let local = now.with_timezone(&tz.unwrap_or(Tz::UTC));
+    let zone = local.timezone().name();
+    let mut sections = Vec::new();
+
+    if all || format == "iso" {
+        let mut lines = vec![
+            format!("Current UTC datetime: {}", now.to_rfc3339()),
+            format!("Current UTC date: {}", now.format("%Y-%m-%d")),
+            format!("Current UTC year: {}", now.format("%Y")),
+        ];
+        if tz.is_some() {
+            lines.push(format!("Local datetime ({zone}): {}", local.to_rfc3339()));
+            lines.push(format!("Local date: {}", local.format("%Y-%m-%d")));
+        }
+        sections.push(lines.join("\n"));
+    }
+
+    if all || format == "human" {
+        sections.push(format!(
+            "Human-readable ({zone}): {} at {} ({})",
+            local.format("%A, %-d %B %Y"),
+            local.format("%H:%M %Z"),
+            day_period(local.hour())
+        ));
+    }
+
+    if all || format == "relative" {
+        let today = local.date_naive();
+        let day = |date: chrono::NaiveDate| date.format("%A %Y-%m-%d").to_string();
+        let week_start = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+        sections.push(
+            [
+                format!("Today ({zone}): {}", day(today)),
+                format!("Yesterday: {}", day(today - Duration::days(1))),
+                format!("Tomorrow: {}", day(today + Duration::days(1))),
+                format!(
+                    "This week: {} to {}",
+                    day(week_start),
+                    day(week_start + Duration::days(6))
+                ),
+                format!("Part of day: {}", day_period(local.hour())),
+            ]
+            .join("\n"),
+        );
+    }
+
+    sections.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
+    use chrono::{TimeZone, Utc};
     use serde_json::json;
 
-    use super::CurrentDateTimeTool;
+    use super::{CurrentDateTimeTool, describe_datetime};
+    use crate::timezone::parse_timezone;
 
     #[tokio::test]
     async fn returns_utc_datetime_fields() {
@@ -42,4 +151,59 @@ mod tests {
         assert!(result.text.contains("Current UTC year:"));
         assert!(result.citations.is_empty());
     }
+
+    #[tokio::test]
+    async fn returns_local_time_for_a_timezone_arg() {
+        let tool = CurrentDateTimeTool;
+        let utc = tool
+            .get_now(json!({}))
+            .await
+            .expect("current_datetime should succeed");
+        assert!(!utc.text.contains("Local datetime"));
+
+        let local = tool
+            .get_now(json!({"timezone": "Asia/Tokyo"}))
+            .await
+            .expect("current_datetime with a time zone should succeed");
+        assert!(local.text.contains("Local datetime (Asia/Tokyo):"));
+        assert!(local.text.contains("+09:00"));
+        let data = local.data.expect("structured datetime");
+        assert_eq!(data["timezone"], "Asia/Tokyo");
+        assert!(
+            data["local"]
+                .as_str()
+                .is_some_and(|local| local.ends_with("+09:00"))
+        );
+
+        assert!(
+            tool.get_now(json!({"timezone": "Nowhere/City"}))
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            CurrentDateTimeTool::check_args(&json!({"timezone": "Nowhere/City"}))[0].path,
+            "/timezone"
+        );
+    }
+
+    #[test]
+    fn describes_human_and_relative_forms_in_local_time() {
+        let now = Utc
+            .with_ymd_and_hms(2026, 10, 15, 22, 30, 0)
+            .single()
+            .expect("valid timestamp");
+        let tokyo = parse_timezone("Asia/Tokyo");
+
+        let human = describe_datetime(now, tokyo, "human");
+        assert_eq!(
+            human,
+            "Human-readable (Asia/Tokyo): Friday, 16 October 2026 at 07:30 JST (morning)"
+        );
+
+        let relative = describe_datetime(now, tokyo, "relative");
+        assert!(relative.contains("Today (Asia/Tokyo): Friday 2026-10-16"));
+        assert!(relative.contains("Yesterday: Thursday 2026-10-15"));
+        assert!(relative.contains("This week: Monday 2026-10-12 to Sunday 2026-10-18"));
+        assert!(!relative.contains("Current UTC datetime"));
+    }
 }
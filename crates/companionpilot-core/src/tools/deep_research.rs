@@ -0,0 +1,407 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use super::{ToolExecutor, ToolResult, ToolSpec};
+use crate::{
+    background_tasks::report_progress,
+    model::{ModelProvider, ModelRequest},
+    orchestrator::parse_json_plan,
+    types::MessageCtx,
+};
+
+/// Longest page excerpt kept as a research note.
+const MAX_PAGE_CHARS: usize = 3_000;
+const PAGE_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const SEARCH_RESULTS_PER_QUERY: u64 = 5;
+
+/// How far one research run may go. Separate from the chat planner's round
+/// cap, since a report is expected to take minutes rather than seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResearchBudget {
+    /// Rounds of sub-questions; each round after the first follows up on
+    /// gaps in what was found.
+    pub max_rounds: usize,
+    /// Sub-questions asked per round.
+    pub queries_per_round: usize,
+    /// Web searches over the whole run.
+    pub max_searches: usize,
+    /// Result pages read per search; 0 works from search snippets only.
+    pub pages_per_search: usize,
+}
+
+impl Default for ResearchBudget {
+    fn default() -> Self {
+        Self {
+            max_rounds: 2,
+            queries_per_round: 4,
+            max_searches: 8,
+            pages_per_search: 2,
+        }
+    }
+}
+
+/// Iterative web research: splits a question into sub-questions, searches
+/// each, reads the top pages, follows up on gaps and writes a cited report.
+/// Takes minutes, so it is meant to run as a background task.
+#[derive(Clone)]
+pub struct DeepResearchTool {
+    model: Arc<dyn ModelProvider>,
+    search: Arc<dyn ToolExecutor>,
+    client: Client,
+    budget: ResearchBudget,
+}
+
+impl fmt::Debug for DeepResearchTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeepResearchTool")
+            .field("budget", &self.budget)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResearchQueries {
+    #[serde(default)]
+    queries: Vec<String>,
+}
+
+/// What one search turned up.
+struct ResearchNote {
+    query: String,
+    text: String,
+    sources: Vec<String>,
+}
+
+impl DeepResearchTool {
+    pub fn spec() -> ToolSpec {
+        ToolSpec {
+            tool_name: "deep_research",
+            args_schema: json!({
+                "type": "object",
+                "properties": {
+                    "question": {
+                        "type": "string",
+                        "minLength": 1,
+                        "pattern": "\\S",
+                        "description": "The research question, with any scope the user gave."
+                    }
+                },
+                "required": ["question"]
+            }),
+            when_to_use: "User explicitly asks for in-depth research, a thorough comparison or a report that needs many sources.",
+            when_not_to_use: "Quick factual lookups a single web_search answers, or casual chat.",
+        }
+    }
+
+    /// `search` runs the `web_search` calls; `model` plans the sub-questions
+    /// and writes the report.
+    pub fn new(
+        model: Arc<dyn ModelProvider>,
+        search: Arc<dyn ToolExecutor>,
+        budget: ResearchBudget,
+    ) -> Self {
+        Self {
+            model,
+            search,
+            client: Client::builder()
+                .timeout(PAGE_FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            budget,
+        }
+    }
+
+    pub async fn research(&self, args: Value, ctx: &MessageCtx) -> anyhow::Result<ToolResult> {
+        let question = args
+            .get("question")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|question| !question.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("deep_research requires string arg `question`"))?;
+        info!(budget = ?self.budget, "deep research start");
+
+        let mut asked = Vec::<String>::new();
+        let mut notes = Vec::<ResearchNote>::new();
+        let mut queries = self.plan_queries(question, &notes).await;
+        if queries.is_empty() {
+            queries.push(question.to_owned());
+        }
+        for round in 0..self.budget.max_rounds {
+            for query in queries {
+                if asked.len() >= self.budget.max_searches {
+                    break;
+                }
+                if asked
+                    .iter()
+                    .any(|earlier| earlier.eq_ignore_ascii_case(&query))
+                {
+                    continue;
+                }
+                asked.push(query.clone());
+                report_progress(&format!(
+                    "Researching ({}/{}): {query}",
+                    asked.len(),
+                    self.budget.max_searches
+                ))
+                .await;
+                if let Some(note) = self.investigate(&query, ctx).await {
+                    notes.push(note);
+                }
+            }
+            if round + 1 == self.budget.max_rounds || asked.len() >= self.budget.max_searches {
+                break;
+            }
+            queries = self.plan_queries(question, &notes).await;
+            if queries.is_empty() {
+                break;
+            }
+        }
+        anyhow::ensure!(
+            !notes.is_empty(),
+            "deep research found nothing for `{question}`"
+        );
+
+        report_progress(&format!("Writing the report from {} searches", asked.len())).await;
+        let mut citations = Vec::<String>::new();
+        for source in notes.iter().flat_map(|note| &note.sources) {
+            if !citations.contains(source) {
+                citations.push(source.clone());
+            }
+        }
+        let report = self
+            .model
+            .complete(ModelRequest {
+                system_prompt: REPORT_PROMPT.to_owned(),
+                user_prompt: format!(
+                    "Question: {question}\n\nSources:\n{}\n\nNotes:\n{}",
+                    numbered_sources(&citations),
+                    format_notes(&notes)
+                ),
+                ..ModelRequest::default()
+            })
+            .await?;
+        info!(
+            searches = asked.len(),
+            sources = citations.len(),
+            "deep research finished"
+        );
+        Ok(ToolResult {
+            text: report.trim().to_owned(),
+            citations,
+            data: Some(json!({ "question": question, "queries": asked })),
+        })
+    }
+
+    /// Sub-questions to search next: the first split of the question, then
+    /// follow-ups for what the notes leave open. Empty when the model is
+    /// done or its answer cannot be parsed.
+    async fn plan_queries(&self, question: &str, notes: &[ResearchNote]) -> Vec<String> {
+        let user_prompt = if notes.is_empty() {
+            format!("Question: {question}")
+        } else {
+            format!(
+                "Question: {question}\n\nNotes so far:\n{}",
+                format_notes(notes)
+            )
+        };
+        let raw = match self
+            .model
+            .complete(ModelRequest {
+                system_prompt: format!(
+                    "You plan web research. Reply with JSON only, shaped {{\"queries\": [<query>, ...]}}, holding at most {} short search queries that together answer the question. When notes are given, only list queries for what they leave open, or an empty list if they already answer it.",
+                    self.budget.queries_per_round
+                ),
+                user_prompt,
+                ..ModelRequest::default()
+            })
+            .await
+        {
+            Ok(raw) => raw,
+            Err(error) => {
+                warn!(?error, "deep research planning failed");
+                return Vec::new();
+            }
+        };
+        parse_json_plan::<ResearchQueries>(&raw)
+            .map(|plan| {
+                plan.queries
+                    .into_iter()
+                    .map(|query| query.trim().to_owned())
+                    .filter(|query| !query.is_empty())
+                    .take(self.budget.queries_per_round)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Searches one sub-question and reads its top pages.
+    async fn investigate(&self, query: &str, ctx: &MessageCtx) -> Option<ResearchNote> {
+        // Hits are reranked against the message; here that is the
+        // sub-question rather than the whole request.
+        let ctx = MessageCtx {
+            content: query.to_owned(),
+            ..ctx.clone()
+        };
+        let result = match self
+            .search
+            .execute(
+                "web_search",
+                json!({ "query": query, "max_results": SEARCH_RESULTS_PER_QUERY }),
+                &ctx,
+            )
+            .await
+        {
+            Ok(result) => result,
+            Err(error) => {
+                warn!(?error, "deep research search failed");
+                return None;
+            }
+        };
+        let mut text = result.text;
+        for url in result.citations.iter().take(self.budget.pages_per_search) {
+            if let Some(page) = self.fetch_page(url).await {
+                text.push_str(&format!("\n\nFrom {url}:\n{page}"));
+            }
+        }
+        Some(ResearchNote {
+            query: query.to_owned(),
+            text,
+            sources: result.citations,
+        })
+    }
+
+    async fn fetch_page(&self, url: &str) -> Option<String> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .inspect_err(|error| warn!(?error, %url, "deep research page fetch failed"))
+            .ok()?;
+        let is_text = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_none_or(|value| value.starts_with("text/"));
+        if !is_text {
+            return None;
+        }
+        let body = response.text().await.ok()?;
+        let text = page_text(&body);
+        (!text.is_empty()).then(|| text.chars().take(MAX_PAGE_CHARS).collect())
+    }
+}
+
+const REPORT_PROMPT: &str = "You write research reports from notes. Answer the question in a few short sections with the key findings first. Cite sources inline by their number, e.g. [2], and only state what the notes support. Say plainly where the sources disagree or leave the question open.";
+
+fn numbered_sources(citations: &[String]) -> String {
+    citations
+        .iter()
+        .enumerate()
+        .map(|(index, url)| format!("[{}] {url}", index + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_notes(notes: &[ResearchNote]) -> String {
+    notes
+        .iter()
+        .map(|note| format!("## {}\n{}", note.query, note.text))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Readable text of an HTML page: scripts, styles and tags dropped and
+/// whitespace collapsed. Plain text passes through unchanged.
+fn page_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        let tag = &rest[start..];
+        let skip_to = ["<script", "<style"]
+            .iter()
+            .find(|open| {
+                tag.get(..open.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(open))
+            })
+            .and_then(|open| {
+                let close = format!("</{}", &open[1..]);
+                tag.to_ascii_lowercase().find(&close)
+            })
+            .unwrap_or(0);
+        match tag[skip_to..].find('>') {
+            Some(end) => rest = &tag[skip_to + end + 1..],
+            None => {
+                rest = "";
+            }
+        }
+    }
+    text.push_str(rest);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        model::MockModelProvider,
+        tools::{MockToolExecutor, MockToolResponse},
+    };
+
+    #[tokio::test]
+    async fn research_searches_and_cites_every_source() {
+        let search = MockToolExecutor::default().with_response(
+            "web_search",
+            MockToolResponse {
+                text: "Tokio is an async runtime.".to_owned(),
+                citations: vec!["https://tokio.rs".to_owned()],
+                ..MockToolResponse::default()
+            },
+        );
+        let search = Arc::new(search);
+        let tool = DeepResearchTool::new(
+            Arc::new(MockModelProvider),
+            search.clone(),
+            ResearchBudget {
+                pages_per_search: 0,
+                ..ResearchBudget::default()
+            },
+        );
+        let ctx = MessageCtx {
+            message_id: "m1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: String::new(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let result = tool
+            .research(json!({"question": "which async runtime?"}), &ctx)
+            .await
+            .expect("research succeeds");
+        // The mock model plans nothing, so the question itself is searched once.
+        assert_eq!(search.call_count("web_search"), 1);
+        assert_eq!(result.citations, vec!["https://tokio.rs".to_owned()]);
+        assert!(result.text.contains("[1] https://tokio.rs"));
+        assert!(result.text.contains("Tokio is an async runtime."));
+        assert!(tool.research(json!({}), &ctx).await.is_err());
+
+        assert_eq!(
+            page_text(
+                "<html><style>p{}</style><p>Hello <b>world</b></p><script>x()</script></html>"
+            ),
+            "Hello world"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{ToolExecutor, ToolResult};
+use crate::types::MessageCtx;
+
+/// One canned tool output. A set `error` makes the call fail with it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockToolResponse {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub citations: Vec<String>,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Serves tool calls from a script of canned outputs instead of real APIs,
+/// for simulation mode. Each call takes the next scripted response of its
+/// tool and the last one repeats once the script runs out; tools without a
+/// script answer with a placeholder echoing their arguments.
+#[derive(Debug, Default)]
+pub struct MockToolExecutor {
+    script: HashMap<String, Vec<MockToolResponse>>,
+    calls: Mutex<HashMap<String, usize>>,
+}
+
+impl MockToolExecutor {
+    pub fn new(script: HashMap<String, Vec<MockToolResponse>>) -> Self {
+        Self {
+            script,
+            calls: Mutex::default(),
+        }
+    }
+
+    /// Parses a script of the form `{"web_search": [{"text": "...",
+    /// "citations": ["https://..."]}], "spotify_playing_status": [...]}`.
+    pub fn from_json(raw: &str) -> anyhow::Result<Self> {
+        Ok(Self::new(serde_json::from_str(raw)?))
+    }
+
+    /// Appends a response to `tool_name`'s script.
+    pub fn with_response(mut self, tool_name: &str, response: MockToolResponse) -> Self {
+        self.script
+            .entry(tool_name.to_owned())
+            .or_default()
+            .push(response);
+        self
+    }
+
+    /// Calls served for `tool_name` so far.
+    pub fn call_count(&self, tool_name: &str) -> usize {
+        self.calls
+            .lock()
+            .expect("mock tool call counter poisoned")
+            .get(tool_name)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for MockToolExecutor {
+    async fn execute(
+        &self,
+        tool_name: &str,
+        args: Value,
+        _message_ctx: &MessageCtx,
+    ) -> anyhow::Result<ToolResult> {
+        let call_index = {
+            let mut calls = self.calls.lock().expect("mock tool call counter poisoned");
+            let count = calls.entry(tool_name.to_owned()).or_default();
+            *count += 1;
+            *count - 1
+        };
+        let Some(response) = self
+            .script
+            .get(tool_name)
+            .and_then(|responses| responses.get(call_index).or(responses.last()))
+        else {
+            return Ok(ToolResult {
+                text: format!("[simulated {tool_name}] no canned output scripted; args: {args}"),
+                citations: Vec::new(),
+                data: None,
+            });
+        };
+        if let Some(error) = &response.error {
+            return Err(anyhow::anyhow!("{error}"));
+        }
+        Ok(ToolResult {
+            text: response.text.clone(),
+            citations: response.citations.clone(),
+            data: response.data.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::MockToolExecutor;
+    use crate::{tools::ToolExecutor, types::MessageCtx};
+
+    #[tokio::test]
+    async fn serves_scripted_responses_in_order_then_repeats_the_last() {
+        let tools = MockToolExecutor::from_json(
+            r#"{"web_search": [
+                {"text": "first", "citations": ["https://example.com/1"]},
+                {"error": "simulated outage"},
+                {"text": "last"}
+            ]}"#,
+        )
+        .expect("script should parse");
+        let ctx = MessageCtx {
+            message_id: "1".into(),
+            user_id: "u1".into(),
+            guild_id: "g1".into(),
+            channel_id: "c1".into(),
+            content: "hi".into(),
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+
+        let first = tools
+            .execute("web_search", json!({}), &ctx)
+            .await
+            .expect("first response should succeed");
+        assert_eq!(first.text, "first");
+        assert_eq!(first.citations, vec!["https://example.com/1"]);
+        let error = tools
+            .execute("web_search", json!({}), &ctx)
+            .await
+            .expect_err("second response is an error");
+        assert_eq!(error.to_string(), "simulated outage");
+        for _ in 0..2 {
+            let last = tools
+                .execute("web_search", json!({}), &ctx)
+                .await
+                .expect("last response should repeat");
+            assert_eq!(last.text, "last");
+        }
+        assert_eq!(tools.call_count("web_search"), 4);
+
+        let unscripted = tools
+            .execute("current_datetime", json!({"a": 1}), &ctx)
+            .await
+            .expect("unscripted tools answer with a placeholder");
+        assert!(unscripted.text.contains("[simulated current_datetime]"));
+    }
+}
@@ -1,22 +1,80 @@
+mod chat_history_search;
 mod current_datetime;
+mod deep_research;
+mod mock;
+mod ocr_image;
+mod rerank;
+mod search_providers;
 mod spotify_playing_status;
+mod validation;
 mod web_search;
 
+#[cfg(feature = "voice")]
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use serde::Serialize;
 use serde_json::Value;
 
-use crate::{types::MessageCtx, voice::VoiceManager};
+use crate::types::MessageCtx;
+#[cfg(feature = "voice")]
+use crate::voice::VoiceManager;
 
+pub use chat_history_search::ChatHistorySearchTool;
 pub use current_datetime::CurrentDateTimeTool;
+pub use deep_research::{DeepResearchTool, ResearchBudget};
+pub use mock::{MockToolExecutor, MockToolResponse};
+pub use ocr_image::{DEFAULT_OCR_MODEL, DEFAULT_OCR_URL, OcrBackend, OcrImageTool};
+pub use rerank::{
+    CrossEncoderReranker, DEFAULT_EMBEDDING_MODEL, DEFAULT_EMBEDDING_URL, EmbeddingReranker,
+    RerankMode, Reranker,
+};
+pub use search_providers::{
+    BraveSearchProvider, SearxngSearchProvider, SerpApiSearchProvider, TavilySearchProvider,
+};
 pub use spotify_playing_status::SpotifyPlayingStatusTool;
-pub use web_search::TavilyWebSearchTool;
+pub use validation::{ToolArgError, validate_tool_args};
+pub use web_search::{SearchHit, SearchMode, SearchResults, WebSearchProvider, WebSearchTool};
+
+/// Planner-facing description of a tool, including the JSON Schema its
+/// arguments are validated against.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSpec {
+    pub tool_name: &'static str,
+    pub args_schema: Value,
+    pub when_to_use: &'static str,
+    pub when_not_to_use: &'static str,
+}
+
+/// Specs of every built-in tool, in the order they are presented to the planner.
+pub fn builtin_tool_specs() -> Vec<ToolSpec> {
+    #[cfg_attr(not(feature = "voice"), allow(unused_mut))]
+    let mut specs = vec![
+        CurrentDateTimeTool::spec(),
+        SpotifyPlayingStatusTool::spec(),
+        WebSearchTool::spec(),
+        DeepResearchTool::spec(),
+        ChatHistorySearchTool::spec(),
+        OcrImageTool::spec(),
+    ];
+    #[cfg(feature = "voice")]
+    specs.extend(VoiceManager::tool_specs());
+    specs
+}
+
+pub fn find_tool_spec(tool_name: &str) -> Option<ToolSpec> {
+    builtin_tool_specs()
+        .into_iter()
+        .find(|spec| spec.tool_name == tool_name)
+}
 
 #[derive(Debug, Clone)]
 pub struct ToolResult {
     pub text: String,
     pub citations: Vec<String>,
+    /// Structured fields behind `text` (search hits, track metadata, ...),
+    /// shown to the final synthesis as compact JSON so it need not parse prose.
+    pub data: Option<Value>,
 }
 
 #[async_trait]
@@ -27,18 +85,28 @@ pub trait ToolExecutor: Send + Sync {
         args: Value,
         message_ctx: &MessageCtx,
     ) -> anyhow::Result<ToolResult>;
+
+    /// Specs of the tools this executor can actually run.
+    fn enabled_tool_specs(&self) -> Vec<ToolSpec> {
+        builtin_tool_specs()
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct ToolRegistry {
     pub current_datetime: CurrentDateTimeTool,
     pub spotify_playing_status: SpotifyPlayingStatusTool,
-    pub web_search: Option<TavilyWebSearchTool>,
+    pub web_search: Option<WebSearchTool>,
+    pub deep_research: Option<DeepResearchTool>,
+    pub chat_history: Option<ChatHistorySearchTool>,
+    pub ocr_image: Option<OcrImageTool>,
+    #[cfg(feature = "voice")]
     pub voice: Option<Arc<VoiceManager>>,
 }
 
 #[async_trait]
 impl ToolExecutor for ToolRegistry {
+    #[cfg_attr(not(feature = "voice"), allow(unused_variables))]
     async fn execute(
         &self,
         tool_name: &str,
@@ -53,52 +121,93 @@ impl ToolExecutor for ToolRegistry {
                     .web_search
                     .as_ref()
                     .ok_or_else(|| anyhow::anyhow!("web_search tool is not configured"))?;
-                tool.search(args).await
+                tool.search(args, &message_ctx.content).await
             }
-            "discord_voice_join" => {
-                let manager = self
-                    .voice
+            "deep_research" => {
+                let tool = self
+                    .deep_research
                     .as_ref()
-                    .ok_or_else(|| anyhow::anyhow!("voice tools are not configured"))?;
-                let text = manager
+                    .ok_or_else(|| anyhow::anyhow!("deep_research tool is not configured"))?;
+                tool.research(args, message_ctx).await
+            }
+            "chat_history_search" => {
+                let tool = self
+                    .chat_history
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("chat_history_search tool is not configured"))?;
+                tool.search(args, message_ctx).await
+            }
+            "ocr_image" => {
+                let tool = self
+                    .ocr_image
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("ocr_image tool is not configured"))?;
+                tool.extract(args, message_ctx).await
+            }
+            #[cfg(feature = "voice")]
+            "discord_voice_join" | "discord_voice_listen_turn" | "discord_voice_leave" => {
+                self.execute_voice_tool(tool_name, args, message_ctx).await
+            }
+            _ => Err(anyhow::anyhow!("unknown tool: {tool_name}")),
+        }
+    }
+
+    fn enabled_tool_specs(&self) -> Vec<ToolSpec> {
+        builtin_tool_specs()
+            .into_iter()
+            .filter(|spec| match spec.tool_name {
+                "web_search" => self.web_search.is_some(),
+                "deep_research" => self.deep_research.is_some(),
+                "chat_history_search" => self.chat_history.is_some(),
+                "ocr_image" => self.ocr_image.is_some(),
+                #[cfg(feature = "voice")]
+                "discord_voice_join" | "discord_voice_listen_turn" | "discord_voice_leave" => {
+                    self.voice.is_some()
+                }
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "voice")]
+impl ToolRegistry {
+    async fn execute_voice_tool(
+        &self,
+        tool_name: &str,
+        args: Value,
+        message_ctx: &MessageCtx,
+    ) -> anyhow::Result<ToolResult> {
+        let manager = self
+            .voice
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("voice tools are not configured"))?;
+        let text = match tool_name {
+            "discord_voice_join" => {
+                manager
                     .join_for_requester(&message_ctx.guild_id, &message_ctx.user_id, &args)
-                    .await?;
-                Ok(ToolResult {
-                    text,
-                    citations: Vec::new(),
-                })
+                    .await?
             }
             "discord_voice_listen_turn" => {
-                let manager = self
-                    .voice
-                    .as_ref()
-                    .ok_or_else(|| anyhow::anyhow!("voice tools are not configured"))?;
-                let text = manager
+                manager
                     .listen_and_respond_for_requester(
                         &message_ctx.guild_id,
                         &message_ctx.user_id,
                         &args,
                     )
-                    .await?;
-                Ok(ToolResult {
-                    text,
-                    citations: Vec::new(),
-                })
+                    .await?
             }
             "discord_voice_leave" => {
-                let manager = self
-                    .voice
-                    .as_ref()
-                    .ok_or_else(|| anyhow::anyhow!("voice tools are not configured"))?;
-                let text = manager
+                manager
                     .leave_for_requester(&message_ctx.guild_id, &message_ctx.user_id)
-                    .await?;
-                Ok(ToolResult {
-                    text,
-                    citations: Vec::new(),
-                })
+                    .await?
             }
-            _ => Err(anyhow::anyhow!("unknown tool: {tool_name}")),
-        }
+            _ => return Err(anyhow::anyhow!("unknown tool: {tool_name}")),
+        };
+        Ok(ToolResult {
+            text,
+            citations: Vec::new(),
+            data: None,
+        })
     }
 }
@@ -0,0 +1,382 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    process::Stdio,
+};
+
+use reqwest::{Client, Url, redirect};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{debug, info};
+
+use super::{ToolResult, ToolSpec};
+use crate::types::{MessageAttachment, MessageCtx};
+
+/// Chat completions endpoint used by the vision backend when `OCR_URL` is
+/// not set.
+pub const DEFAULT_OCR_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+pub const DEFAULT_OCR_MODEL: &str = "openai/gpt-4o-mini";
+/// Images read per call when the planner does not pick one.
+const MAX_IMAGES: usize = 3;
+/// Largest image downloaded for tesseract.
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+/// Longest text kept per image.
+const MAX_TEXT_CHARS: usize = 4_000;
+const VISION_PROMPT: &str = "Transcribe all text in this image exactly as written, keeping line breaks and code or error messages verbatim. Do not describe the image or add anything else. If there is no text, reply with an empty message.";
+
+/// How `ocr_image` reads images.
+#[derive(Clone)]
+pub enum OcrBackend {
+    /// A vision model behind an OpenAI-compatible chat completions endpoint.
+    Vision {
+        url: String,
+        api_key: Option<String>,
+        model: String,
+    },
+    /// The `tesseract` command-line tool.
+    Tesseract { path: String },
+}
+
+impl fmt::Debug for OcrBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vision { url, model, .. } => f
+                .debug_struct("Vision")
+                .field("url", url)
+                .field("model", model)
+                .finish_non_exhaustive(),
+            Self::Tesseract { path } => f.debug_struct("Tesseract").field("path", path).finish(),
+        }
+    }
+}
+
+/// Extracts the text of images attached to the user's message, such as
+/// screenshots of error messages or photos of documents.
+#[derive(Debug, Clone)]
+pub struct OcrImageTool {
+    client: Client,
+    backend: OcrBackend,
+}
+
+#[derive(Debug, Deserialize)]
+struct VisionResponse {
+    choices: Vec<VisionChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VisionChoice {
+    message: VisionMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct VisionMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+impl OcrImageTool {
+    pub fn spec() -> ToolSpec {
+        ToolSpec {
+            tool_name: "ocr_image",
+            args_schema: json!({
+                "type": "object",
+                "properties": {
+                    "image": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Number of the attached image to read, as in `[Attached image N: ...]`; all images when omitted."
+                    },
+                    "language": {
+                        "type": "string",
+                        "pattern": "^[a-z_+]{3,40}$",
+                        "description": "Tesseract language code(s) of the text, e.g. `eng` or `ces+eng`."
+                    }
+                }
+            }),
+            when_to_use: "The user attached an image (screenshot, photo of a document or error message) and asks about the text in it.",
+            when_not_to_use: "No image is attached, or the question is about what the image shows rather than its text.",
+        }
+    }
+
+    pub fn new(backend: OcrBackend) -> Self {
+        Self {
+            client: Client::new(),
+            backend,
+        }
+    }
+
+    pub async fn extract(&self, args: Value, ctx: &MessageCtx) -> anyhow::Result<ToolResult> {
+        let selected = select_images(&ctx.attachments, args.get("image").and_then(Value::as_u64))?;
+        let language = args
+            .get("language")
+            .and_then(Value::as_str)
+            .unwrap_or("eng");
+        if !valid_language(language) {
+            anyhow::bail!("ocr_image: invalid language `{language}`");
+        }
+
+        info!(images = selected.len(), "ocr start");
+        let mut lines = Vec::new();
+        let mut extracted = Vec::new();
+        for image in selected {
+            let text = self.read(image, language).await?;
+            let text = text.trim().chars().take(MAX_TEXT_CHARS).collect::<String>();
+            debug!(filename = %image.filename, chars = text.len(), "ocr image read");
+            if text.is_empty() {
+                lines.push(format!("No text found in {}.", image.filename));
+            } else {
+                lines.push(format!("Text in {}:\n{text}", image.filename));
+            }
+            extracted.push(json!({ "filename": image.filename, "text": text }));
+        }
+        Ok(ToolResult {
+            text: lines.join("\n\n"),
+            citations: Vec::new(),
+            data: Some(json!({ "images": extracted })),
+        })
+    }
+
+    async fn read(&self, image: &MessageAttachment, language: &str) -> anyhow::Result<String> {
+        match &self.backend {
+            OcrBackend::Vision {
+                url,
+                api_key,
+                model,
+            } => {
+                let payload = json!({
+                    "model": model,
+                    "temperature": 0,
+                    "messages": [{
+                        "role": "user",
+                        "content": [
+                            { "type": "text", "text": VISION_PROMPT },
+                            { "type": "image_url", "image_url": { "url": image.url } },
+                        ],
+                    }],
+                });
+                let mut request = self.client.post(url).json(&payload);
+                if let Some(api_key) = api_key {
+                    request = request.bearer_auth(api_key);
+                }
+                let response = request
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<VisionResponse>()
+                    .await?;
+                Ok(response
+                    .choices
+                    .into_iter()
+                    .next()
+                    .and_then(|choice| choice.message.content)
+                    .unwrap_or_default())
+            }
+            OcrBackend::Tesseract { path } => {
+                let bytes = self.download(image).await?;
+                let mut child = Command::new(path)
+                    .args(["stdin", "stdout", "-l", language])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    // A tool timeout drops this future; take tesseract with it.
+                    .kill_on_drop(true)
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&bytes).await?;
+                }
+                let output = child.wait_with_output().await?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "tesseract failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+        }
+    }
+
+    async fn download(&self, image: &MessageAttachment) -> anyhow::Result<Vec<u8>> {
+        let too_large = || anyhow::anyhow!("ocr_image: {} is too large", image.filename);
+        if image.size.is_some_and(|size| size > MAX_IMAGE_BYTES) {
+            return Err(too_large());
+        }
+        let url = Url::parse(&image.url)?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("ocr_image: {} has no host", image.filename))?;
+        // Pin the checked address so a second lookup cannot point elsewhere.
+        let address = tokio::net::lookup_host((host, 443))
+            .await?
+            .find(|address| is_public_ip(address.ip()))
+            .ok_or_else(|| anyhow::anyhow!("ocr_image: {host} is not a public address"))?;
+        let client = Client::builder()
+            .redirect(redirect::Policy::none())
+            .resolve(host, SocketAddr::new(address.ip(), 443))
+            .build()?;
+        let mut response = client.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_IMAGE_BYTES)
+        {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (bytes.len() + chunk.len()) as u64 > MAX_IMAGE_BYTES {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Whether an address is reachable on the public internet, as opposed to
+/// loopback, private, link-local or otherwise reserved ranges.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// The images to read: number `image` as listed in the message, or the first
+/// few. Only Discord attachments are ever fetched.
+fn select_images(
+    attachments: &[MessageAttachment],
+    image: Option<u64>,
+) -> anyhow::Result<Vec<&MessageAttachment>> {
+    let images = attachments
+        .iter()
+        .filter(|attachment| attachment.is_image())
+        .collect::<Vec<_>>();
+    if images.is_empty() {
+        anyhow::bail!("ocr_image: the message has no attached images");
+    }
+    let selected = match image {
+        Some(number) => {
+            let image = usize::try_from(number)
+                .ok()
+                .and_then(|number| number.checked_sub(1))
+                .and_then(|index| images.get(index))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "ocr_image: there is no image {number}; the message has {}",
+                        images.len()
+                    )
+                })?;
+            vec![*image]
+        }
+        None => images.into_iter().take(MAX_IMAGES).collect(),
+    };
+    if let Some(image) = selected.iter().find(|image| !image.is_discord_cdn()) {
+        anyhow::bail!("ocr_image: {} is not a Discord attachment", image.filename);
+    }
+    Ok(selected)
+}
+
+/// Tesseract language codes joined by `+`, e.g. `eng` or `ces+eng`.
+fn valid_language(language: &str) -> bool {
+    (3..=40).contains(&language.len())
+        && language
+            .split('+')
+            .all(|code| code.len() >= 3 && code.chars().all(|c| c.is_ascii_lowercase() || c == '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_tesseract_language_codes() {
+        assert!(valid_language("eng"));
+        assert!(valid_language("ces+eng"));
+        assert!(valid_language("chi_sim"));
+        assert!(!valid_language("en"));
+        assert!(!valid_language("eng+"));
+        assert!(!valid_language("-psm"));
+        assert!(!valid_language("eng --oem"));
+    }
+
+    #[test]
+    fn selects_images_by_their_number_in_the_message() {
+        let attachment = |name: &str, content_type: &str| MessageAttachment {
+            url: format!("https://cdn.discordapp.com/attachments/1/2/{name}"),
+            filename: name.to_owned(),
+            content_type: Some(content_type.to_owned()),
+            size: None,
+        };
+        let attachments = (1..=5)
+            .map(|n| attachment(&format!("{n}.png"), "image/png"))
+            .chain([attachment("log.txt", "text/plain")])
+            .collect::<Vec<_>>();
+        let names = |selected: Vec<&MessageAttachment>| {
+            selected
+                .iter()
+                .map(|image| image.filename.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(select_images(&attachments, None).expect("images")),
+            ["1.png", "2.png", "3.png"]
+        );
+        assert_eq!(
+            names(select_images(&attachments, Some(5)).expect("image 5")),
+            ["5.png"]
+        );
+        assert!(select_images(&attachments, Some(6)).is_err());
+        assert!(select_images(&attachments, Some(0)).is_err());
+        assert!(select_images(&attachments[5..], None).is_err());
+
+        let mut elsewhere = attachment("x.png", "image/png");
+        elsewhere.url = "http://10.0.0.1/x.png".to_owned();
+        assert!(select_images(&[elsewhere], None).is_err());
+    }
+
+    #[test]
+    fn fetches_only_public_addresses() {
+        for ip in ["162.159.135.233", "2606:4700::6810:84e5"] {
+            assert!(is_public_ip(ip.parse().expect("ip")), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.5",
+            "172.16.3.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().expect("ip")), "{ip}");
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::web_search::SearchHit;
+
+/// Embedding endpoint used when `RERANK_URL` is not set.
+pub const DEFAULT_EMBEDDING_URL: &str = "https://api.openai.com/v1/embeddings";
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// How web search results are reordered against the user's question.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RerankMode {
+    /// Keep the providers' order.
+    #[default]
+    Off,
+    /// Cosine similarity of embeddings from an OpenAI-compatible endpoint.
+    Embedding,
+    /// Scores from a cross-encoder `/rerank` endpoint.
+    CrossEncoder,
+}
+
+impl RerankMode {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "off" | "" => Some(Self::Off),
+            "embedding" => Some(Self::Embedding),
+            "cross_encoder" => Some(Self::CrossEncoder),
+            _ => None,
+        }
+    }
+}
+
+/// Scores search hits by relevance to a question.
+#[async_trait]
+pub trait Reranker: fmt::Debug + Send + Sync {
+    /// One score per document, higher is more relevant; `None` for documents
+    /// the reranker left unscored.
+    async fn scores(
+        &self,
+        question: &str,
+        documents: &[String],
+    ) -> anyhow::Result<Vec<Option<f64>>>;
+}
+
+/// The text of a hit that is scored.
+pub fn hit_document(hit: &SearchHit) -> String {
+    match &hit.snippet {
+        Some(snippet) => format!("{}\n{snippet}", hit.title),
+        None => hit.title.clone(),
+    }
+}
+
+/// Orders `hits` by `scores`, best first with unscored hits last, and
+/// records each hit's score. Returns `false`, leaving the hits as they were,
+/// when the scores do not match them one to one.
+pub fn apply_scores(hits: &mut [SearchHit], scores: &[Option<f64>]) -> bool {
+    if scores.len() != hits.len() {
+        return false;
+    }
+    for (hit, score) in hits.iter_mut().zip(scores) {
+        hit.rerank_score = *score;
+    }
+    hits.sort_by(|a, b| match (a.rerank_score, b.rerank_score) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    true
+}
+
+/// Reranks by cosine similarity between the question's and each
+/// document's embedding.
+#[derive(Debug, Clone)]
+pub struct EmbeddingReranker {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl EmbeddingReranker {
+    pub fn new(url: &str, api_key: Option<String>, model: &str) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim().to_owned(),
+            api_key,
+            model: model.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f64>,
+}
+
+#[async_trait]
+impl Reranker for EmbeddingReranker {
+    async fn scores(
+        &self,
+        question: &str,
+        documents: &[String],
+    ) -> anyhow::Result<Vec<Option<f64>>> {
+        let input = std::iter::once(question)
+            .chain(documents.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        let mut request = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "model": self.model, "input": input }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let mut response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<EmbeddingResponse>()
+            .await?;
+        response.data.sort_by_key(|data| data.index);
+        let Some((question, documents)) = response.data.split_first() else {
+            anyhow::bail!("embedding endpoint returned no embeddings");
+        };
+        Ok(documents
+            .iter()
+            .map(|document| Some(cosine_similarity(&question.embedding, &document.embedding)))
+            .collect())
+    }
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Reranks with a cross-encoder served behind a `/rerank` endpoint that
+/// takes `{"query", "texts"}`, such as Hugging Face text-embeddings-inference.
+/// Responses in the Cohere/Jina shape (`results` with `relevance_score`)
+/// are accepted too.
+#[derive(Debug, Clone)]
+pub struct CrossEncoderReranker {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl CrossEncoderReranker {
+    pub fn new(url: &str, api_key: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim().to_owned(),
+            api_key,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CrossEncoderRequest<'a> {
+    query: &'a str,
+    texts: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CrossEncoderResponse {
+    Scores(Vec<CrossEncoderScore>),
+    Results { results: Vec<CrossEncoderScore> },
+}
+
+#[derive(Debug, Deserialize)]
+struct CrossEncoderScore {
+    index: usize,
+    #[serde(alias = "relevance_score")]
+    score: f64,
+}
+
+#[async_trait]
+impl Reranker for CrossEncoderReranker {
+    async fn scores(
+        &self,
+        question: &str,
+        documents: &[String],
+    ) -> anyhow::Result<Vec<Option<f64>>> {
+        let mut request = self.client.post(&self.url).json(&CrossEncoderRequest {
+            query: question,
+            texts: documents,
+        });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CrossEncoderResponse>()
+            .await?;
+        let ranked = match response {
+            CrossEncoderResponse::Scores(scores) => scores,
+            CrossEncoderResponse::Results { results } => results,
+        };
+        let mut scores = vec![None; documents.len()];
+        for ranked in ranked {
+            if let Some(score) = scores.get_mut(ranked.index) {
+                *score = Some(ranked.score);
+            }
+        }
+        Ok(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_hits_by_score_and_records_it() {
+        let mut hits = [
+            "Cooking blog",
+            "Pasta recipes",
+            "Rust 1.85 release notes",
+            "Rust editions",
+        ]
+        .into_iter()
+        .map(|title| SearchHit::new("tavily", title, "https://a.example", None, None))
+        .collect::<Vec<_>>();
+        assert!(apply_scores(
+            &mut hits,
+            &[None, Some(-0.1), Some(0.9), Some(0.5)]
+        ));
+        let titles = hits
+            .iter()
+            .map(|hit| hit.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                "Rust 1.85 release notes",
+                "Rust editions",
+                "Pasta recipes",
+                "Cooking blog"
+            ]
+        );
+        assert_eq!(hits[0].rerank_score, Some(0.9));
+        assert_eq!(hits[3].rerank_score, None);
+        assert!(!apply_scores(&mut hits, &[Some(1.0)]));
+
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,421 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::warn;
+
+use super::web_search::{SearchHit, SearchResults, WebSearchProvider};
+
+/// Sends a provider request and decodes its JSON body, logging failures
+/// under the provider's name.
+async fn fetch<T: DeserializeOwned>(provider: &str, request: RequestBuilder) -> anyhow::Result<T> {
+    let response = request
+        .send()
+        .await
+        .map_err(|error| {
+            warn!(?error, %provider, "web search request failed");
+            error
+        })?
+        .error_for_status()
+        .map_err(|error| {
+            warn!(?error, %provider, "web search returned error status");
+            error
+        })?;
+    let body = response.json::<T>().await.map_err(|error| {
+        warn!(?error, %provider, "failed to deserialize web search response");
+        error
+    })?;
+    Ok(body)
+}
+
+/// [Tavily](https://tavily.com), which also writes a short answer.
+#[derive(Debug, Clone)]
+pub struct TavilySearchProvider {
+    client: Client,
+    api_key: String,
+}
+
+impl TavilySearchProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TavilyRequest<'a> {
+    api_key: &'a str,
+    query: &'a str,
+    max_results: usize,
+    include_answer: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TavilyResponse {
+    answer: Option<String>,
+    results: Vec<TavilyResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TavilyResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    score: Option<f64>,
+}
+
+impl TavilyResponse {
+    fn into_results(self, provider: &'static str) -> SearchResults {
+        SearchResults {
+            answer: self.answer,
+            hits: self
+                .results
+                .iter()
+                .map(|item| {
+                    SearchHit::new(
+                        provider,
+                        &item.title,
+                        &item.url,
+                        item.content.as_deref(),
+                        item.score,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl WebSearchProvider for TavilySearchProvider {
+    fn name(&self) -> &'static str {
+        "tavily"
+    }
+
+    async fn search(&self, query: &str, max_results: usize) -> anyhow::Result<SearchResults> {
+        let request = self
+            .client
+            .post("https://api.tavily.com/search")
+            .json(&TavilyRequest {
+                api_key: &self.api_key,
+                query,
+                max_results,
+                include_answer: true,
+            });
+        let response = fetch::<TavilyResponse>(self.name(), request).await?;
+        Ok(response.into_results(self.name()))
+    }
+}
+
+/// The [Brave Search API](https://brave.com/search/api/).
+#[derive(Debug, Clone)]
+pub struct BraveSearchProvider {
+    client: Client,
+    api_key: String,
+}
+
+impl BraveSearchProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BraveResponse {
+    #[serde(default)]
+    web: Option<BraveWeb>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BraveWeb {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BraveResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+impl BraveResponse {
+    fn into_results(self, provider: &'static str, max_results: usize) -> SearchResults {
+        SearchResults {
+            answer: None,
+            hits: self
+                .web
+                .map(|web| web.results)
+                .unwrap_or_default()
+                .iter()
+                .take(max_results)
+                .map(|item| {
+                    SearchHit::new(
+                        provider,
+                        &item.title,
+                        &item.url,
+                        item.description.as_deref(),
+                        None,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl WebSearchProvider for BraveSearchProvider {
+    fn name(&self) -> &'static str {
+        "brave"
+    }
+
+    async fn search(&self, query: &str, max_results: usize) -> anyhow::Result<SearchResults> {
+        let request = self
+            .client
+            .get("https://api.search.brave.com/res/v1/web/search")
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", &self.api_key)
+            .query(&[("q", query), ("count", &max_results.to_string())]);
+        let response = fetch::<BraveResponse>(self.name(), request).await?;
+        Ok(response.into_results(self.name(), max_results))
+    }
+}
+
+/// Google results through [SerpAPI](https://serpapi.com).
+#[derive(Debug, Clone)]
+pub struct SerpApiSearchProvider {
+    client: Client,
+    api_key: String,
+}
+
+impl SerpApiSearchProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SerpApiResponse {
+    #[serde(default)]
+    answer_box: Option<SerpApiAnswerBox>,
+    #[serde(default)]
+    organic_results: Vec<SerpApiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SerpApiAnswerBox {
+    #[serde(default)]
+    answer: Option<String>,
+    #[serde(default)]
+    snippet: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SerpApiResult {
+    title: String,
+    link: String,
+    #[serde(default)]
+    snippet: Option<String>,
+}
+
+impl SerpApiResponse {
+    fn into_results(self, provider: &'static str, max_results: usize) -> SearchResults {
+        SearchResults {
+            answer: self
+                .answer_box
+                .and_then(|answer_box| answer_box.answer.or(answer_box.snippet)),
+            hits: self
+                .organic_results
+                .iter()
+                .take(max_results)
+                .map(|item| {
+                    SearchHit::new(
+                        provider,
+                        &item.title,
+                        &item.link,
+                        item.snippet.as_deref(),
+                        None,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl WebSearchProvider for SerpApiSearchProvider {
+    fn name(&self) -> &'static str {
+        "serpapi"
+    }
+
+    async fn search(&self, query: &str, max_results: usize) -> anyhow::Result<SearchResults> {
+        let request = self.client.get("https://serpapi.com/search.json").query(&[
+            ("engine", "google"),
+            ("q", query),
+            ("num", &max_results.to_string()),
+            ("api_key", &self.api_key),
+        ]);
+        let response = fetch::<SerpApiResponse>(self.name(), request).await?;
+        Ok(response.into_results(self.name(), max_results))
+    }
+}
+
+/// A self-hosted [SearxNG](https://docs.searxng.org) instance with the JSON
+/// output format enabled.
+#[derive(Debug, Clone)]
+pub struct SearxngSearchProvider {
+    client: Client,
+    base_url: String,
+}
+
+impl SearxngSearchProvider {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim().trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    answers: Vec<String>,
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    score: Option<f64>,
+}
+
+impl SearxngResponse {
+    fn into_results(self, provider: &'static str, max_results: usize) -> SearchResults {
+        SearchResults {
+            answer: self.answers.into_iter().next(),
+            hits: self
+                .results
+                .iter()
+                .take(max_results)
+                .map(|item| {
+                    SearchHit::new(
+                        provider,
+                        &item.title,
+                        &item.url,
+                        item.content.as_deref(),
+                        item.score,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl WebSearchProvider for SearxngSearchProvider {
+    fn name(&self) -> &'static str {
+        "searxng"
+    }
+
+    async fn search(&self, query: &str, max_results: usize) -> anyhow::Result<SearchResults> {
+        let request = self
+            .client
+            .get(format!("{}/search", self.base_url))
+            .query(&[("q", query), ("format", "json")]);
+        let response = fetch::<SearxngResponse>(self.name(), request).await?;
+        Ok(response.into_results(self.name(), max_results))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse<T: DeserializeOwned>(fixture: &str) -> T {
+        serde_json::from_str(fixture).expect("fixture parses")
+    }
+
+    #[test]
+    fn brave_responses_without_web_results_have_no_hits() {
+        let results = parse::<BraveResponse>(r#"{"type": "search", "query": {"original": "x"}}"#)
+            .into_results("brave", 5);
+        assert_eq!(results, SearchResults::default());
+
+        let results = parse::<BraveResponse>(
+            r#"{"web": {"results": [
+                {"title": "<strong>Rust</strong> 1.85", "url": "https://a.example",
+                 "description": "The 2024 edition."},
+                {"title": "Second", "url": "https://b.example"}
+            ]}}"#,
+        )
+        .into_results("brave", 1);
+        assert_eq!(results.answer, None);
+        assert_eq!(results.hits.len(), 1);
+        assert_eq!(results.hits[0].title, "Rust 1.85");
+        assert_eq!(
+            results.hits[0].snippet.as_deref(),
+            Some("The 2024 edition.")
+        );
+        assert_eq!(results.hits[0].provider, "brave");
+    }
+
+    #[test]
+    fn serpapi_answers_prefer_the_answer_box_answer_over_its_snippet() {
+        let results = parse::<SerpApiResponse>(
+            r#"{"answer_box": {"answer": "42", "snippet": "The answer is 42."},
+                "organic_results": [
+                    {"title": "Deep Thought", "link": "https://a.example", "snippet": "Computed."}
+                ]}"#,
+        )
+        .into_results("serpapi", 5);
+        assert_eq!(results.answer.as_deref(), Some("42"));
+        assert_eq!(results.hits[0].url, "https://a.example");
+        assert_eq!(results.hits[0].snippet.as_deref(), Some("Computed."));
+
+        let results = parse::<SerpApiResponse>(
+            r#"{"answer_box": {"snippet": "The answer is 42."}, "organic_results": []}"#,
+        )
+        .into_results("serpapi", 5);
+        assert_eq!(results.answer.as_deref(), Some("The answer is 42."));
+        assert!(results.hits.is_empty());
+
+        let results =
+            parse::<SerpApiResponse>(r#"{"search_metadata": {}}"#).into_results("serpapi", 5);
+        assert_eq!(results, SearchResults::default());
+    }
+
+    #[test]
+    fn searxng_answers_take_the_first_of_its_answers() {
+        let results = parse::<SearxngResponse>(
+            r#"{"answers": ["Prague", "Praha"],
+                "results": [
+                    {"title": "Prague", "url": "https://a.example", "content": "Capital.",
+                     "score": 2.5},
+                    {"title": "Czechia", "url": "https://b.example"}
+                ]}"#,
+        )
+        .into_results("searxng", 5);
+        assert_eq!(results.answer.as_deref(), Some("Prague"));
+        assert_eq!(results.hits.len(), 2);
+        assert_eq!(results.hits[0].score, Some(2.5));
+        assert_eq!(results.hits[1].snippet, None);
+
+        let results = parse::<SearxngResponse>(r#"{"query": "x"}"#).into_results("searxng", 5);
+        assert_eq!(results, SearchResults::default());
+    }
+}
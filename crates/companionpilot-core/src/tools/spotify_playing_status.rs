@@ -1,9 +1,9 @@
 use anyhow::Context;
 use reqwest::Client;
-use serde_json::Value;
+use serde_json::{Value, json};
 use tracing::{info, warn};
 
-use super::ToolResult;
+use super::{ToolResult, ToolSpec};
 
 const DEFAULT_PLAYING_STATUS_URL: &str = "https://api.peterrock.dev/api/spotify/playing-status";
 
@@ -20,6 +20,18 @@ impl Default for SpotifyPlayingStatusTool {
 }
 
 impl SpotifyPlayingStatusTool {
+    pub fn spec() -> ToolSpec {
+        ToolSpec {
+            tool_name: "spotify_playing_status",
+            args_schema: json!({
+                "type": "object",
+                "properties": {}
+            }),
+            when_to_use: "Need the user's currently playing Spotify track/status.",
+            when_not_to_use: "Question is unrelated to Spotify playback.",
+        }
+    }
+
     pub fn new(endpoint_url: impl Into<String>) -> Self {
         Self {
             client: Client::new(),
@@ -60,10 +72,30 @@ impl SpotifyPlayingStatusTool {
         Ok(ToolResult {
             text,
             citations: vec![self.endpoint_url.clone()],
+            data: playing_status_data(&payload),
         })
     }
 }
 
+/// The user, playback state and track metadata as structured data.
+fn playing_status_data(payload: &Value) -> Option<Value> {
+    let (user, status) = extract_user_and_status(payload)?;
+    let track = status.get("track").filter(|track| track.is_object());
+    let field = |key: &str| track.and_then(|track| track.get(key)).cloned();
+    Some(json!({
+        "user": user.get("display_name"),
+        "is_playing": status.get("is_playing").and_then(Value::as_bool).unwrap_or(false),
+        "progress_ms": status.get("progress_ms"),
+        "track": track.map(|_| json!({
+            "name": field("name"),
+            "artist": field("artist"),
+            "album": field("album"),
+            "duration_ms": field("duration_ms"),
+            "uri": field("uri"),
+        })),
+    }))
+}
+
 fn format_playing_status(payload: &Value) -> Option<String> {
     let (user, status) = extract_user_and_status(payload)?;
 
@@ -153,11 +185,10 @@ fn format_millis(ms: u64) -> String {
 mod tests {
     use serde_json::json;
 
-    use super::format_playing_status;
+    use super::{format_playing_status, playing_status_data};
 
-    #[test]
-    fn formats_current_track_from_nested_array_payload() {
-        let payload = json!([
+    fn nested_array_payload() -> serde_json::Value {
+        json!([
             [
                 {
                     "display_name": "Petr Seifert",
@@ -177,7 +208,12 @@ mod tests {
                     "progress_ms": 53338
                 }
             ]
-        ]);
+        ])
+    }
+
+    #[test]
+    fn formats_current_track_from_nested_array_payload() {
+        let payload = nested_array_payload();
 
         let text = format_playing_status(&payload).expect("payload should parse");
         assert!(text.contains("Spotify user: Petr Seifert"));
@@ -186,6 +222,13 @@ mod tests {
         assert!(text.contains("Progress: 00:53 / 03:04"));
     }
 
+    #[test]
+    fn extracts_structured_data_from_nested_array_payload() {
+        let data = playing_status_data(&nested_array_payload()).expect("payload should parse");
+        assert_eq!(data["track"]["artist"], "Elley Duhe");
+        assert_eq!(data["progress_ms"], 53338);
+    }
+
     #[test]
     fn formats_not_playing_state() {
         let payload = json!([
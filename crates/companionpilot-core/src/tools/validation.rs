@@ -0,0 +1,131 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{CurrentDateTimeTool, ToolSpec};
+
+/// A single schema violation in planner-provided tool arguments.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolArgError {
+    /// JSON pointer to the offending argument (empty for the argument object).
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ToolArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Validates `args` against the tool's JSON Schema, plus tool-specific checks
+/// the schema cannot express, and returns normalized arguments: undeclared
+/// properties are dropped, strings are trimmed and schema defaults are filled
+/// in.
+pub fn validate_tool_args(spec: &ToolSpec, args: &Value) -> Result<Value, Vec<ToolArgError>> {
+    let args = if args.is_null() {
+        Value::Object(Map::new())
+    } else {
+        args.clone()
+    };
+
+    let validator = jsonschema::validator_for(&spec.args_schema).map_err(|error| {
+        vec![ToolArgError {
+            path: String::new(),
+            message: format!("tool `{}` has an invalid schema: {error}", spec.tool_name),
+        }]
+    })?;
+
+    let errors = validator
+        .iter_errors(&args)
+        .map(|error| ToolArgError {
+            path: error.instance_path.to_string(),
+            message: error.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let args = normalize_args(&spec.args_schema, args);
+    let errors = match spec.tool_name {
+        "current_datetime" => CurrentDateTimeTool::check_args(&args),
+        #[cfg(feature = "voice")]
+        "discord_voice_listen_turn" => crate::voice::VoiceManager::check_listen_args(&args),
+        _ => Vec::new(),
+    };
+    if errors.is_empty() {
+        Ok(args)
+    } else {
+        Err(errors)
+    }
+}
+
+fn normalize_args(schema: &Value, args: Value) -> Value {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return args;
+    };
+    let Value::Object(mut provided) = args else {
+        return args;
+    };
+
+    let mut normalized = Map::new();
+    for (name, property_schema) in properties {
+        let value = match provided.remove(name) {
+            Some(Value::String(text)) => Some(Value::String(text.trim().to_owned())),
+            Some(value) => Some(value),
+            None => property_schema.get("default").cloned(),
+        };
+        if let Some(value) = value {
+            normalized.insert(name.clone(), value);
+        }
+    }
+
+    Value::Object(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::tools::find_tool_spec;
+
+    use super::validate_tool_args;
+
+    #[test]
+    fn fills_defaults_and_drops_undeclared_args() {
+        let spec = find_tool_spec("web_search").expect("web_search spec should exist");
+        let args = validate_tool_args(&spec, &json!({"query": " rust ", "extra": 1}))
+            .expect("args should be valid");
+        assert_eq!(args, json!({"query": "rust", "max_results": 5}));
+    }
+
+    #[test]
+    fn reports_structured_errors_for_invalid_args() {
+        let spec = find_tool_spec("web_search").expect("web_search spec should exist");
+        let errors = validate_tool_args(&spec, &json!({"query": "   ", "max_results": 50}))
+            .expect_err("args should be rejected");
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|error| error.path == "/max_results"));
+        assert!(errors.iter().any(|error| error.path == "/query"));
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn rejects_voice_turns_shorter_than_their_chunk_gap() {
+        let spec = find_tool_spec("discord_voice_listen_turn").expect("listen spec should exist");
+        let errors = validate_tool_args(&spec, &json!({"chunk_gap_ms": 2000, "max_turn_ms": 500}))
+            .expect_err("args should be rejected");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/max_turn_ms");
+
+        let args = validate_tool_args(&spec, &json!({"chunk_gap_ms": 500}))
+            .expect("defaults satisfy the check");
+        assert_eq!(args["max_turn_ms"], 12_000);
+    }
+}
@@ -1,25 +1,176 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::task::JoinSet;
 use tracing::{debug, info, warn};
 
-use super::ToolResult;
+use super::{
+    ToolResult, ToolSpec,
+    rerank::{Reranker, apply_scores, hit_document},
+};
+use crate::redaction::Redactor;
 
-#[derive(Debug, Clone)]
-pub struct TavilyWebSearchTool {
-    client: Client,
-    api_key: String,
+/// Longest result snippet kept in the structured data.
+const MAX_SNIPPET_CHARS: usize = 300;
+/// Hits fetched per result asked for when reranking, so better matches
+/// further down the providers' lists can move up.
+const RERANK_CANDIDATE_FACTOR: usize = 2;
+const MAX_RERANK_CANDIDATES: usize = 20;
+/// HTML tags stripped from titles and snippets.
+const FORMATTING_TAGS: &[&str] = &[
+    "a", "b", "br", "code", "div", "em", "i", "mark", "p", "small", "span", "strong", "sub", "sup",
+    "u",
+];
+
+/// One search hit, normalized across providers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub title: String,
+    pub url: String,
+    pub snippet: Option<String>,
+    /// Relevance score, for providers that report one.
+    pub score: Option<f64>,
+    /// Name of the provider the hit came from.
+    pub provider: &'static str,
+    /// Relevance to the user's question, when results were reranked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerank_score: Option<f64>,
 }
 
-impl TavilyWebSearchTool {
-    pub fn new(api_key: String) -> Self {
+impl SearchHit {
+    /// Builds a hit from raw provider fields: markup is stripped from the
+    /// title and snippet, and the snippet shortened to [`MAX_SNIPPET_CHARS`].
+    pub fn new(
+        provider: &'static str,
+        title: &str,
+        url: &str,
+        snippet: Option<&str>,
+        score: Option<f64>,
+    ) -> Self {
+        let snippet = snippet
+            .map(strip_markup)
+            .filter(|snippet| !snippet.is_empty())
+            .map(|snippet| snippet.chars().take(MAX_SNIPPET_CHARS).collect());
         Self {
-            client: Client::new(),
-            api_key,
+            title: strip_markup(title),
+            url: url.trim().to_owned(),
+            snippet,
+            score,
+            provider,
+            rerank_score: None,
         }
     }
+}
+
+/// What a provider returned for one query.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SearchResults {
+    /// Direct answer, for providers that write one.
+    pub answer: Option<String>,
+    pub hits: Vec<SearchHit>,
+}
 
-    pub async fn search(&self, args: Value) -> anyhow::Result<ToolResult> {
+/// A web search backend behind the `web_search` tool.
+#[async_trait]
+pub trait WebSearchProvider: fmt::Debug + Send + Sync {
+    /// Short name used in logs and the tool's structured data.
+    fn name(&self) -> &'static str;
+
+    async fn search(&self, query: &str, max_results: usize) -> anyhow::Result<SearchResults>;
+}
+
+/// How the `web_search` tool uses several providers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Ask the providers in order until one returns results.
+    #[default]
+    Fallback,
+    /// Ask every provider at once and interleave their results.
+    Merge,
+}
+
+impl SearchMode {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "fallback" => Some(Self::Fallback),
+            "merge" => Some(Self::Merge),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebSearchTool {
+    providers: Vec<Arc<dyn WebSearchProvider>>,
+    mode: SearchMode,
+    reranker: Option<Arc<dyn Reranker>>,
+    redactor: Option<Arc<Redactor>>,
+}
+
+impl WebSearchTool {
+    pub fn spec() -> ToolSpec {
+        ToolSpec {
+            tool_name: "web_search",
+            args_schema: json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "minLength": 1,
+                        "pattern": "\\S",
+                        "description": "Search query."
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": 10,
+                        "default": 5
+                    }
+                },
+                "required": ["query"]
+            }),
+            when_to_use: "Need external factual information, latest/current info, or web-sourced recommendations.",
+            when_not_to_use: "Casual chat, personal memory recall, or when the answer can be provided from context.",
+        }
+    }
+
+    /// `None` without providers.
+    pub fn new(providers: Vec<Arc<dyn WebSearchProvider>>, mode: SearchMode) -> Option<Self> {
+        (!providers.is_empty()).then_some(Self {
+            providers,
+            mode,
+            reranker: None,
+            redactor: None,
+        })
+    }
+
+    /// Reorders the hits by relevance to the user's question before they
+    /// reach the model.
+    pub fn with_reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.reranker = Some(reranker);
+        self
+    }
+
+    /// Masks personal data in the user's question before it is sent to the
+    /// reranker.
+    pub fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Names of the providers, in the order they are asked.
+    pub fn provider_names(&self) -> Vec<&'static str> {
+        self.providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect()
+    }
+
+    /// Runs a search; `question` is the user's message the hits are
+    /// reranked against.
+    pub async fn search(&self, args: Value, question: &str) -> anyhow::Result<ToolResult> {
         let query = args
             .get("query")
             .and_then(Value::as_str)
@@ -28,84 +179,393 @@ impl TavilyWebSearchTool {
             .get("max_results")
             .and_then(Value::as_u64)
             .unwrap_or(5)
-            .clamp(1, 10);
-
-        info!(max_results, "tavily web search start");
-        debug!(query = %query, "tavily query");
+            .clamp(1, 10) as usize;
 
-        let payload = TavilyRequest {
-            api_key: &self.api_key,
-            query,
-            max_results: max_results as usize,
-            include_answer: true,
+        info!(max_results, mode = ?self.mode, "web search start");
+        debug!(query = %query, "web search query");
+        let candidates = if self.reranker.is_some() {
+            (max_results * RERANK_CANDIDATE_FACTOR).min(MAX_RERANK_CANDIDATES)
+        } else {
+            max_results
         };
-
-        let response = self
-            .client
-            .post("https://api.tavily.com/search")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|error| {
-                warn!(?error, "tavily request failed");
-                error
-            })?
-            .error_for_status()
-            .map_err(|error| {
-                warn!(?error, "tavily returned error status");
-                error
-            })?
-            .json::<TavilyResponse>()
-            .await
-            .map_err(|error| {
-                warn!(?error, "failed to deserialize tavily response");
-                error
-            })?;
-
+        let mut results = match self.mode {
+            SearchMode::Fallback => self.search_fallback(query, candidates).await?,
+            SearchMode::Merge => self.search_merged(query, candidates).await?,
+        };
+        if let Some(reranker) = &self.reranker {
+            let question = match &self.redactor {
+                _ if question.trim().is_empty() => query.into(),
+                Some(redactor) => redactor.redact(question),
+                None => question.into(),
+            };
+            rerank(reranker.as_ref(), &question, &mut results.hits).await;
+        }
+        results.hits.truncate(max_results);
         info!(
-            result_count = response.results.len(),
-            has_answer = response.answer.is_some(),
-            "tavily web search success"
+            result_count = results.hits.len(),
+            has_answer = results.answer.is_some(),
+            "web search success"
         );
+        Ok(search_result(results))
+    }
 
-        let mut citations = Vec::new();
-        let mut lines = Vec::new();
-        if let Some(answer) = response.answer {
-            lines.push(format!("Summary: {answer}"));
+    async fn search_fallback(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> anyhow::Result<SearchResults> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            match provider.search(query, max_results).await {
+                Ok(results) if !results.hits.is_empty() || results.answer.is_some() => {
+                    return Ok(results);
+                }
+                Ok(_) => debug!(provider = provider.name(), "no web search results"),
+                Err(error) => {
+                    warn!(
+                        ?error,
+                        provider = provider.name(),
+                        "web search provider failed"
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+        match last_error {
+            Some(error) if self.providers.len() == 1 => Err(error),
+            Some(error) => Err(error.context("every web search provider failed or was empty")),
+            None => Ok(SearchResults::default()),
         }
+    }
 
-        for item in response.results {
-            citations.push(item.url.clone());
-            lines.push(format!("- {} ({})", item.title, item.url));
+    async fn search_merged(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> anyhow::Result<SearchResults> {
+        let mut tasks = JoinSet::new();
+        for (index, provider) in self.providers.iter().enumerate() {
+            let provider = provider.clone();
+            let query = query.to_owned();
+            tasks.spawn(async move {
+                let results = provider.search(&query, max_results).await;
+                (index, provider.name(), results)
+            });
         }
+        let mut answered = Vec::new();
+        let mut last_error = None;
+        while let Some(joined) = tasks.join_next().await {
+            let (index, name, results) = joined?;
+            match results {
+                Ok(results) => answered.push((index, results)),
+                Err(error) => {
+                    warn!(?error, provider = name, "web search provider failed");
+                    last_error = Some(error);
+                }
+            }
+        }
+        if answered.is_empty()
+            && let Some(error) = last_error
+        {
+            return Err(error.context("every web search provider failed"));
+        }
+        answered.sort_by_key(|(index, _)| *index);
+        Ok(merge_results(
+            answered.into_iter().map(|(_, results)| results).collect(),
+            max_results,
+        ))
+    }
+}
 
-        if lines.is_empty() {
-            lines.push("No search results returned.".to_owned());
+/// Reorders `hits` by relevance to `question`; they keep the providers'
+/// order when the reranker fails.
+async fn rerank(reranker: &dyn Reranker, question: &str, hits: &mut [SearchHit]) {
+    if hits.len() < 2 {
+        return;
+    }
+    let documents = hits.iter().map(hit_document).collect::<Vec<_>>();
+    match reranker.scores(question, &documents).await {
+        Ok(scores) if apply_scores(hits, &scores) => {
+            debug!(count = hits.len(), "web search results reranked");
         }
+        Ok(scores) => warn!(
+            expected = hits.len(),
+            got = scores.len(),
+            "reranker returned the wrong number of scores; keeping provider order"
+        ),
+        Err(error) => warn!(?error, "web search rerank failed; keeping provider order"),
+    }
+}
 
-        Ok(ToolResult {
-            text: lines.join("\n"),
-            citations,
-        })
+/// Interleaves the providers' hits by rank, dropping URLs already listed,
+/// and keeps the first provider's answer.
+fn merge_results(results: Vec<SearchResults>, max_results: usize) -> SearchResults {
+    let answer = results.iter().find_map(|results| results.answer.clone());
+    let longest = results
+        .iter()
+        .map(|results| results.hits.len())
+        .max()
+        .unwrap_or(0);
+    let mut seen = Vec::new();
+    let mut hits = Vec::new();
+    for rank in 0..longest {
+        for hit in results.iter().filter_map(|results| results.hits.get(rank)) {
+            let key = url_key(&hit.url);
+            if hits.len() < max_results && !seen.contains(&key) {
+                seen.push(key);
+                hits.push(hit.clone());
+            }
+        }
     }
+    SearchResults { answer, hits }
 }
 
-#[derive(Debug, Serialize)]
-struct TavilyRequest<'a> {
-    api_key: &'a str,
-    query: &'a str,
-    max_results: usize,
-    include_answer: bool,
+/// The URL without scheme, `www.`, trailing slash or fragment, for spotting
+/// the same page from two providers.
+fn url_key(url: &str) -> String {
+    let url = url.trim().to_ascii_lowercase();
+    let url = url.split('#').next().unwrap_or_default();
+    let url = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    url.strip_prefix("www.")
+        .unwrap_or(url)
+        .trim_end_matches('/')
+        .to_owned()
 }
 
-#[derive(Debug, Deserialize)]
-struct TavilyResponse {
-    answer: Option<String>,
-    results: Vec<TavilyResult>,
+/// Drops the formatting tags providers put in titles and snippets (Brave
+/// highlights matches with `<strong>`) and decodes the common entities.
+/// Anything else in angle brackets, like `Vec<T>`, is text and stays.
+fn strip_markup(raw: &str) -> String {
+    let mut text = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match formatting_tag_len(rest) {
+            Some(len) => rest = &rest[len..],
+            None => {
+                text.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    // `&amp;` goes last, so an escaped entity like `&amp;lt;` stays `&lt;`.
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .trim()
+        .to_owned()
+}
+
+/// Length of the formatting tag `text` starts with, e.g. `<strong>`, `</b>`
+/// or `<span class="x">`.
+fn formatting_tag_len(text: &str) -> Option<usize> {
+    let end = text.find('>')?;
+    let inner = text[1..end].trim_start_matches('/').trim_end_matches('/');
+    let name = inner
+        .split(|character: char| character.is_whitespace())
+        .next()?;
+    FORMATTING_TAGS
+        .iter()
+        .any(|tag| tag.eq_ignore_ascii_case(name))
+        .then_some(end + 1)
+}
+
+/// The tool output: the answer and hits as text and structured data, with
+/// the hit URLs as citations.
+fn search_result(results: SearchResults) -> ToolResult {
+    let data = json!({ "answer": results.answer, "results": results.hits });
+    let mut citations = Vec::new();
+    let mut lines = Vec::new();
+    if let Some(answer) = results.answer {
+        lines.push(format!("Summary: {answer}"));
+    }
+    for hit in results.hits {
+        lines.push(format!("- {} ({})", hit.title, hit.url));
+        citations.push(hit.url);
+    }
+    if lines.is_empty() {
+        lines.push("No search results returned.".to_owned());
+    }
+    ToolResult {
+        text: lines.join("\n"),
+        citations,
+        data: Some(data),
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct TavilyResult {
-    title: String,
-    url: String,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_providers_by_rank_without_duplicate_pages() {
+        let tavily = SearchResults {
+            answer: Some("Rust 1.85 shipped the 2024 edition.".to_owned()),
+            hits: vec![
+                SearchHit::new(
+                    "tavily",
+                    "Announcing Rust 1.85",
+                    "https://blog.rust-lang.org/2025/02/20/Rust-1.85.0.html",
+                    None,
+                    Some(0.9),
+                ),
+                SearchHit::new(
+                    "tavily",
+                    "Rust editions",
+                    "https://doc.rust-lang.org/edition-guide/",
+                    None,
+                    Some(0.7),
+                ),
+            ],
+        };
+        let brave = SearchResults {
+            answer: None,
+            hits: vec![
+                SearchHit::new(
+                    "brave",
+                    "<strong>Rust</strong> 1.85 &amp; the 2024 edition",
+                    "https://www.blog.rust-lang.org/2025/02/20/Rust-1.85.0.html/",
+                    Some("The <strong>2024</strong> edition is stable."),
+                    None,
+                ),
+                SearchHit::new(
+                    "brave",
+                    "Rust 2024 on Reddit",
+                    "https://reddit.com/r/rust/2024",
+                    None,
+                    None,
+                ),
+            ],
+        };
+        let merged = merge_results(vec![tavily, brave], 3);
+        assert_eq!(
+            merged.answer.as_deref(),
+            Some("Rust 1.85 shipped the 2024 edition.")
+        );
+        let urls = merged
+            .hits
+            .iter()
+            .map(|hit| hit.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                "https://blog.rust-lang.org/2025/02/20/Rust-1.85.0.html",
+                "https://doc.rust-lang.org/edition-guide/",
+                "https://reddit.com/r/rust/2024",
+            ]
+        );
+
+        let hit = SearchHit::new(
+            "brave",
+            "<b>A</b> &amp; B",
+            "https://a.example",
+            Some("<p></p>"),
+            None,
+        );
+        assert_eq!(hit.title, "A & B");
+        assert_eq!(hit.snippet, None);
+
+        let hit = SearchHit::new(
+            "tavily",
+            "Vec<T> in Rust",
+            "https://a.example",
+            Some(
+                "Use <em>Option<&str></em> when a < b, or <span class=\"hl\">HashMap<K, V></span>",
+            ),
+            None,
+        );
+        assert_eq!(hit.title, "Vec<T> in Rust");
+        assert_eq!(
+            hit.snippet.as_deref(),
+            Some("Use Option<&str> when a < b, or HashMap<K, V>")
+        );
+    }
+
+    #[test]
+    fn escaped_entities_are_decoded_once() {
+        let hit = SearchHit::new(
+            "brave",
+            "Write &amp;lt;b&amp;gt; for &lt;b&gt;",
+            "https://a.example",
+            Some("&quot;Tom &amp; Jerry&quot;"),
+            None,
+        );
+        assert_eq!(hit.title, "Write &lt;b&gt; for <b>");
+        assert_eq!(hit.snippet.as_deref(), Some("\"Tom & Jerry\""));
+    }
+
+    #[derive(Debug)]
+    struct FixedProvider;
+
+    #[async_trait]
+    impl WebSearchProvider for FixedProvider {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn search(&self, _query: &str, _max_results: usize) -> anyhow::Result<SearchResults> {
+            Ok(SearchResults {
+                answer: None,
+                hits: ["Pasta recipes", "Rust editions"]
+                    .into_iter()
+                    .map(|title| SearchHit::new("fixed", title, "https://a.example", None, None))
+                    .collect(),
+            })
+        }
+    }
+
+    /// Scores titles mentioning "Rust" and remembers the question it got.
+    #[derive(Debug, Default)]
+    struct RecordingReranker {
+        questions: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Reranker for RecordingReranker {
+        async fn scores(
+            &self,
+            question: &str,
+            documents: &[String],
+        ) -> anyhow::Result<Vec<Option<f64>>> {
+            self.questions.lock().unwrap().push(question.to_owned());
+            Ok(documents
+                .iter()
+                .map(|document| document.contains("Rust").then_some(1.0))
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn reranks_against_the_redacted_question() {
+        let reranker = Arc::new(RecordingReranker::default());
+        let redactor = Redactor::new(&[crate::redaction::RedactionKind::Email], &[], &[]);
+        let tool = WebSearchTool::new(vec![Arc::new(FixedProvider)], SearchMode::Fallback)
+            .expect("one provider")
+            .with_reranker(reranker.clone())
+            .with_redactor(Arc::new(redactor));
+
+        let result = tool
+            .search(
+                json!({"query": "rust editions"}),
+                "I'm jane@gmail.com, what are rust editions?",
+            )
+            .await
+            .expect("search succeeds");
+        let hits = &result.data.expect("structured data")["results"];
+        assert_eq!(hits[0]["title"], "Rust editions");
+        assert_eq!(hits[0]["rerank_score"], 1.0);
+        assert!(hits[1].get("rerank_score").is_none());
+        assert_eq!(
+            *reranker.questions.lock().unwrap(),
+            vec!["I'm [email], what are rust editions?".to_owned()]
+        );
+    }
 }
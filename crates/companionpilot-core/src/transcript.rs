@@ -0,0 +1,256 @@
+use std::fmt::Write as _;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::types::{ChatMessageRecord, ChatRole, Modality, ToolCallRecord};
+
+/// Document format for conversation exports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    #[default]
+    Md,
+    Html,
+}
+
+impl TranscriptFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Md => "text/markdown; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Md => "md",
+            Self::Html => "html",
+        }
+    }
+}
+
+enum Entry<'a> {
+    Message(&'a ChatMessageRecord),
+    ToolCall(&'a ToolCallRecord),
+}
+
+impl Entry<'_> {
+    fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            Entry::Message(message) => message.timestamp,
+            Entry::ToolCall(tool_call) => tool_call.timestamp,
+        }
+    }
+}
+
+/// Renders messages and the tool calls made while answering them, in time
+/// order, as a standalone document.
+pub fn render_transcript(
+    user_id: &str,
+    messages: &[ChatMessageRecord],
+    tool_calls: &[ToolCallRecord],
+    format: TranscriptFormat,
+) -> String {
+    let mut entries = messages
+        .iter()
+        .map(Entry::Message)
+        .chain(tool_calls.iter().map(Entry::ToolCall))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(Entry::timestamp);
+
+    match format {
+        TranscriptFormat::Md => render_markdown(user_id, &entries),
+        TranscriptFormat::Html => render_html(user_id, &entries),
+    }
+}
+
+fn speaker(role: ChatRole) -> &'static str {
+    match role {
+        ChatRole::User => "User",
+        ChatRole::Assistant => "CompanionPilot",
+    }
+}
+
+/// Suffix marking spoken messages in the message header.
+fn modality_tag(message: &ChatMessageRecord) -> &'static str {
+    match message.modality {
+        Modality::Text => "",
+        Modality::Voice => " · voice",
+    }
+}
+
+fn tool_status(tool_call: &ToolCallRecord) -> String {
+    if tool_call.success {
+        "ok".to_owned()
+    } else {
+        format!(
+            "failed: {}",
+            tool_call.error.as_deref().unwrap_or("unknown error")
+        )
+    }
+}
+
+fn render_markdown(user_id: &str, entries: &[Entry<'_>]) -> String {
+    let mut out = format!("# Conversation with {user_id}\n");
+    for entry in entries {
+        match entry {
+            Entry::Message(message) => {
+                let _ = write!(
+                    out,
+                    "\n**{}** · {} · #{}{}\n\n{}\n",
+                    speaker(message.role),
+                    message.timestamp.format("%Y-%m-%d %H:%M UTC"),
+                    message.channel_id,
+                    modality_tag(message),
+                    message.content.trim()
+                );
+            }
+            Entry::ToolCall(tool_call) => {
+                let _ = write!(
+                    out,
+                    "\n> Tool `{}` `{}` ({})\n",
+                    tool_call.tool_name,
+                    tool_call.args_json,
+                    tool_status(tool_call)
+                );
+                for (index, url) in tool_call.citations.iter().enumerate() {
+                    if is_web_url(url) {
+                        let _ = writeln!(out, "> [{}]({url})", index + 1);
+                    } else {
+                        let _ = writeln!(out, "> [{}] `{url}`", index + 1);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn render_html(user_id: &str, entries: &[Entry<'_>]) -> String {
+    let user_id = escape_html(user_id);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Conversation with {user_id}</title>\n<style>body{{font-family:sans-serif;max-width:48rem;margin:2rem auto}}.msg{{margin:1rem 0}}.meta{{color:#666;font-size:.85em}}.tool{{border-left:3px solid #ccc;padding-left:.75rem;color:#444;font-size:.9em}}p{{white-space:pre-wrap}}</style>\n</head><body>\n<h1>Conversation with {user_id}</h1>\n"
+    );
+    for entry in entries {
+        match entry {
+            Entry::Message(message) => {
+                let _ = writeln!(
+                    out,
+                    "<div class=\"msg\"><div class=\"meta\"><strong>{}</strong> · {} · #{}{}</div><p>{}</p></div>",
+                    speaker(message.role),
+                    message.timestamp.format("%Y-%m-%d %H:%M UTC"),
+                    escape_html(&message.channel_id),
+                    modality_tag(message),
+                    escape_html(message.content.trim())
+                );
+            }
+            Entry::ToolCall(tool_call) => {
+                let links = tool_call
+                    .citations
+                    .iter()
+                    .enumerate()
+                    .map(|(index, url)| {
+                        let escaped = escape_html(url);
+                        if is_web_url(url) {
+                            format!("<a href=\"{escaped}\">[{}]</a>", index + 1)
+                        } else {
+                            format!("[{}] <code>{escaped}</code>", index + 1)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let _ = writeln!(
+                    out,
+                    "<div class=\"tool\">Tool <code>{}</code> <code>{}</code> ({}) {links}</div>",
+                    escape_html(&tool_call.tool_name),
+                    escape_html(&tool_call.args_json),
+                    escape_html(&tool_status(tool_call))
+                );
+            }
+        }
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+/// Citations come from tool output, so only http(s) URLs become links;
+/// anything else, e.g. `javascript:`, is shown as text.
+fn is_web_url(url: &str) -> bool {
+    reqwest::Url::parse(url.trim()).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+fn escape_html(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for ch in raw.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{TranscriptFormat, render_transcript};
+    use crate::types::{ChatMessageRecord, ChatRole, Modality, ToolCallRecord};
+
+    #[test]
+    fn interleaves_tool_calls_and_escapes_html() {
+        let start = Utc::now();
+        let message = |id: &str, role, content: &str, offset| ChatMessageRecord {
+            id: id.to_owned(),
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            role,
+            content: content.to_owned(),
+            timestamp: start + Duration::seconds(offset),
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        };
+        let messages = vec![
+            message("1-assistant", ChatRole::Assistant, "Rust 1.85 <stable>", 2),
+            message("1", ChatRole::User, "latest rust?", 0),
+        ];
+        let tool_calls = vec![ToolCallRecord {
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: "c1".to_owned(),
+            tool_name: "web_search".to_owned(),
+            source: "unified_planner".to_owned(),
+            args_json: r#"{"query":"rust"}"#.to_owned(),
+            result_text: String::new(),
+            citations: vec![
+                "https://blog.rust-lang.org".to_owned(),
+                "JavaScript:alert(1)".to_owned(),
+            ],
+            success: true,
+            error: None,
+            timestamp: start + Duration::seconds(1),
+        }];
+
+        let markdown = render_transcript("u1", &messages, &tool_calls, TranscriptFormat::Md);
+        let question = markdown.find("latest rust?").expect("question");
+        let tool = markdown.find("Tool `web_search`").expect("tool call");
+        let answer = markdown.find("Rust 1.85").expect("answer");
+        assert!(question < tool && tool < answer);
+        assert!(markdown.contains("[1](https://blog.rust-lang.org)"));
+
+        let html = render_transcript("u1", &messages, &tool_calls, TranscriptFormat::Html);
+        assert!(html.contains("Rust 1.85 &lt;stable&gt;"));
+        assert!(html.contains("<a href=\"https://blog.rust-lang.org\">[1]</a>"));
+        assert!(html.contains("[2] <code>JavaScript:alert(1)</code>"));
+        assert!(!html.contains("href=\"JavaScript"));
+        assert!(markdown.contains("> [2] `JavaScript:alert(1)`"));
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+/// Replies longer than this are rarely repeated, so they are not cached.
+pub const MAX_CACHED_TTS_CHARS: usize = 300;
+
+struct CacheEntry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+    total_bytes: u64,
+    clock: u64,
+}
+
+impl CacheIndex {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// Size-bounded, least-recently-used cache of synthesized speech on disk,
+/// keyed by TTS model, voice and text. Recency survives restarts through file
+/// modification times.
+pub struct TtsCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<CacheIndex>,
+}
+
+impl TtsCache {
+    /// Opens (creating if needed) the cache directory and indexes the audio
+    /// already in it.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut files = fs::read_dir(&dir)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                let key = path
+                    .extension()
+                    .is_some_and(|extension| extension == "wav")
+                    .then(|| path.file_stem()?.to_str().map(ToOwned::to_owned))??;
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((modified, key, metadata.len()))
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let mut index = CacheIndex::default();
+        for (_, key, size) in files {
+            let last_used = index.tick();
+            index.total_bytes += size;
+            index.entries.insert(key, CacheEntry { size, last_used });
+        }
+        let cache = Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        };
+        cache.evict();
+        Ok(cache)
+    }
+
+    pub fn key(model: &str, voice: &str, text: &str) -> String {
+        let digest = Sha256::digest(format!("{model}\n{voice}\n{}", text.trim()));
+        format!("{digest:x}")
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        {
+            let mut index = self.lock();
+            let tick = index.tick();
+            index.entries.get_mut(key)?.last_used = tick;
+        }
+        let path = self.path(key);
+        match tokio::fs::read(&path).await {
+            Ok(audio) => {
+                if let Ok(file) = fs::File::options().append(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                Some(audio)
+            }
+            Err(error) => {
+                warn!(
+                    ?error,
+                    key, "cached TTS audio is unreadable; dropping entry"
+                );
+                self.forget(key);
+                None
+            }
+        }
+    }
+
+    pub async fn put(&self, key: &str, audio: &[u8]) -> anyhow::Result<()> {
+        let size = audio.len() as u64;
+        if size > self.max_bytes {
+            return Ok(());
+        }
+        let path = self.path(key);
+        let partial = path.with_extension("part");
+        tokio::fs::write(&partial, audio).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        {
+            let mut index = self.lock();
+            let last_used = index.tick();
+            if let Some(previous) = index
+                .entries
+                .insert(key.to_owned(), CacheEntry { size, last_used })
+            {
+                index.total_bytes -= previous.size;
+            }
+            index.total_bytes += size;
+        }
+        self.evict();
+        Ok(())
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.lock().total_bytes
+    }
+
+    fn evict(&self) {
+        let mut evicted = Vec::new();
+        {
+            let mut index = self.lock();
+            while index.total_bytes > self.max_bytes {
+                let Some(oldest) = index
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                if let Some(entry) = index.entries.remove(&oldest) {
+                    index.total_bytes -= entry.size;
+                }
+                evicted.push(oldest);
+            }
+        }
+        for key in evicted {
+            remove_file(&self.path(&key));
+        }
+    }
+
+    fn forget(&self, key: &str) {
+        let mut index = self.lock();
+        if let Some(entry) = index.entries.remove(key) {
+            index.total_bytes -= entry.size;
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.wav"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheIndex> {
+        self.index
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn remove_file(path: &Path) {
+    if let Err(error) = fs::remove_file(path)
+        && error.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!(?error, path = %path.display(), "failed to evict cached TTS audio");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TtsCache;
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_audio_over_the_size_limit() {
+        let dir = std::env::temp_dir().join(format!("cp-tts-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = TtsCache::open(&dir, 10).expect("cache dir");
+        let listening = TtsCache::key("tts", "alloy", "I'm listening");
+        let done = TtsCache::key("tts", "alloy", "Done!");
+        let bye = TtsCache::key("tts", "alloy", "Bye");
+
+        cache.put(&listening, &[1; 4]).await.expect("put");
+        cache.put(&done, &[2; 4]).await.expect("put");
+        assert_eq!(cache.get(&listening).await, Some(vec![1; 4]));
+        cache.put(&bye, &[3; 4]).await.expect("put");
+
+        assert!(cache.get(&done).await.is_none());
+        assert_eq!(cache.total_bytes(), 8);
+        let reopened = TtsCache::open(&dir, 10).expect("reopen");
+        assert_eq!(reopened.get(&bye).await, Some(vec![3; 4]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,8 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::model::KeyProvider;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserIdentity {
     pub discord_user_id: String,
@@ -17,6 +19,88 @@ pub struct MessageCtx {
     pub channel_id: String,
     pub content: String,
     pub timestamp: DateTime<Utc>,
+    /// Files attached to the message, e.g. screenshots.
+    #[serde(default)]
+    pub attachments: Vec<MessageAttachment>,
+}
+
+/// A file attached to a user message, fetched from `url` by the tools that
+/// read it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageAttachment {
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Size in bytes, when known.
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+/// Hosts Discord serves attachments from.
+const DISCORD_CDN_HOSTS: &[&str] = &["cdn.discordapp.com", "media.discordapp.net"];
+
+impl MessageAttachment {
+    /// Whether the URL is an https link on Discord's attachment CDN, the only
+    /// place attachments are fetched from.
+    pub fn is_discord_cdn(&self) -> bool {
+        reqwest::Url::parse(&self.url).is_ok_and(|url| {
+            url.scheme() == "https"
+                && url.port().is_none()
+                && url
+                    .host_str()
+                    .is_some_and(|host| DISCORD_CDN_HOSTS.contains(&host))
+        })
+    }
+
+    pub fn is_image(&self) -> bool {
+        match &self.content_type {
+            Some(content_type) => content_type.starts_with("image/"),
+            None => {
+                let filename = self.filename.to_ascii_lowercase();
+                [".png", ".jpg", ".jpeg", ".webp", ".gif", ".bmp"]
+                    .iter()
+                    .any(|extension| filename.ends_with(extension))
+            }
+        }
+    }
+}
+
+/// The user's message with a line per attached image, so the planner knows
+/// there is something to read.
+pub fn content_with_attachments(content: &str, attachments: &[MessageAttachment]) -> String {
+    let notes = attachment_notes(attachments);
+    if notes.is_empty() {
+        return content.to_owned();
+    }
+    format!("{content}\n{notes}").trim().to_owned()
+}
+
+/// The message text without the lines [`content_with_attachments`] added,
+/// e.g. to number the images again once messages are merged.
+pub fn content_without_attachments<'a>(
+    content: &'a str,
+    attachments: &[MessageAttachment],
+) -> &'a str {
+    let notes = attachment_notes(attachments);
+    if notes.is_empty() {
+        return content;
+    }
+    content
+        .strip_suffix(notes.as_str())
+        .map_or(content, str::trim_end)
+}
+
+fn attachment_notes(attachments: &[MessageAttachment]) -> String {
+    attachments
+        .iter()
+        .filter(|attachment| attachment.is_image())
+        .enumerate()
+        .map(|(index, attachment)| {
+            format!("[Attached image {}: {}]", index + 1, attachment.filename)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +110,26 @@ pub struct MemoryFact {
     pub confidence: f32,
     pub source: String,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// How much of the user's memory [`MemoryContext`] holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextLimits {
+    /// Latest turns of the conversation.
+    pub recent_messages: usize,
+    /// Most recently updated facts.
+    pub facts: usize,
+}
+
+impl Default for ContextLimits {
+    fn default() -> Self {
+        Self {
+            recent_messages: 8,
+            facts: 32,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -33,6 +137,35 @@ pub struct MemoryContext {
     pub summary: Option<String>,
     pub recent_messages: Vec<String>,
     pub facts: Vec<MemoryFact>,
+    /// The user's latest turns from other channels, filled by the orchestrator
+    /// when cross-channel context is enabled.
+    #[serde(default)]
+    pub other_channel_messages: Vec<String>,
+    /// What the user is listening to on Spotify, when playback enrichment is
+    /// enabled for them.
+    #[serde(default)]
+    pub now_playing: Option<String>,
+    /// Live home/IoT readings, when ambient context is enabled for the user.
+    #[serde(default)]
+    pub ambient: Vec<String>,
+    /// The user's pinned messages, filled by the orchestrator.
+    #[serde(default)]
+    pub pinned_messages: Vec<String>,
+    /// Operator notices for the user's next turn, filled by the orchestrator.
+    #[serde(default)]
+    pub system_notices: Vec<String>,
+    /// What the companion can do, filled by the orchestrator when the user
+    /// asks about it.
+    #[serde(default)]
+    pub capabilities: Option<String>,
+    /// A sample of the guild's custom emoji, filled by the orchestrator for
+    /// text turns.
+    #[serde(default)]
+    pub guild_emoji: Option<String>,
+    /// Open threads from before the user's reply model was switched, filled
+    /// by the orchestrator for the first turns after the switch.
+    #[serde(default)]
+    pub handoff: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +202,13 @@ pub struct OrchestratorReply {
     pub safety_flags: Vec<String>,
     #[serde(default)]
     pub timings: ReplyTimings,
+    /// Set when this message was folded into the turn of another message
+    /// (conversation merge mode); the reply for both is produced there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged_into: Option<String>,
+    /// Parsed reply when the caller requested JSON output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -87,6 +227,44 @@ impl ChatRole {
     }
 }
 
+/// How a message was exchanged.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Modality {
+    #[default]
+    Text,
+    /// Spoken in a voice channel: a transcript or a TTS reply.
+    Voice,
+}
+
+impl Modality {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Modality::Text => "text",
+            Modality::Voice => "voice",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Self {
+        match raw {
+            "voice" => Modality::Voice,
+            _ => Modality::Text,
+        }
+    }
+}
+
+/// Guild id recorded for direct messages.
+pub const DM_GUILD_ID: &str = "dm";
+
+/// Speaker label used for recent messages in prompt context, e.g.
+/// `user (voice)`.
+pub fn context_speaker(role: ChatRole, modality: Modality) -> String {
+    match modality {
+        Modality::Text => role.as_str().to_owned(),
+        Modality::Voice => format!("{} (voice)", role.as_str()),
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessageRecord {
     pub id: String,
@@ -96,6 +274,200 @@ pub struct ChatMessageRecord {
     pub role: ChatRole,
     pub content: String,
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub modality: Modality,
+    /// Conversation session the message belongs to, if it was recorded
+    /// during one.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Pinned messages are always included in prompt context, within the
+    /// pinned-context token budget.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Chat platform id of the message this record stands for, e.g. the
+    /// Discord message: the user's own message, or the reply once it was
+    /// delivered. Until then a reply carries the id of the message it answers.
+    #[serde(default)]
+    pub source_message_id: Option<String>,
+}
+
+/// A full-text search over one user's chat history.
+#[derive(Debug, Clone, Default)]
+pub struct ChatSearchQuery {
+    /// Words that must all appear in a message as whole words, ignoring
+    /// case.
+    pub text: String,
+    /// Only messages in this guild; DMs are recorded under [`DM_GUILD_ID`].
+    pub guild_id: String,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+/// Stage of a turn, recorded while a reply is in flight.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnPhase {
+    #[default]
+    LoadContext,
+    Planner,
+    Tools,
+    Synthesis,
+    MemoryWrite,
+}
+
+impl TurnPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LoadContext => "load_context",
+            Self::Planner => "planner",
+            Self::Tools => "tools",
+            Self::Synthesis => "synthesis",
+            Self::MemoryWrite => "memory_write",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "load_context" => Some(Self::LoadContext),
+            "planner" => Some(Self::Planner),
+            "tools" => Some(Self::Tools),
+            "synthesis" => Some(Self::Synthesis),
+            "memory_write" => Some(Self::MemoryWrite),
+            _ => None,
+        }
+    }
+}
+
+/// Where a failed turn stopped, attached as context to its error so callers
+/// can say more than "internal error". Find it with
+/// `error.downcast_ref::<TurnFailure>()`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TurnFailure {
+    pub phase: TurnPhase,
+    /// Tools of the round that was running, when the phase is `tools`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// Timings of the phases that finished; `total_ms` is the time until the
+    /// failure.
+    pub timings: ReplyTimings,
+}
+
+impl TurnFailure {
+    /// What the companion was doing, for messages to users, e.g. "while
+    /// running web_search".
+    pub fn describe(&self) -> String {
+        match self.phase {
+            TurnPhase::LoadContext => "while loading our conversation".to_owned(),
+            TurnPhase::Planner => "while planning my answer".to_owned(),
+            TurnPhase::Tools if self.tools.is_empty() => "while running tools".to_owned(),
+            TurnPhase::Tools => format!("while running {}", self.tools.join(", ")),
+            TurnPhase::Synthesis => "while writing my reply".to_owned(),
+            TurnPhase::MemoryWrite => "while saving to memory".to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for TurnFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "turn failed in phase {} after {} ms",
+            self.phase.as_str(),
+            self.timings.total_ms
+        )?;
+        if !self.tools.is_empty() {
+            write!(f, " (tools: {})", self.tools.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A reply being worked on, kept alive by a heartbeat so a crash mid-turn
+/// can be detected and recovered after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InFlightReplyRecord {
+    pub message_id: String,
+    pub user_id: String,
+    pub guild_id: String,
+    pub channel_id: String,
+    pub content: String,
+    pub phase: TurnPhase,
+    pub started_at: DateTime<Utc>,
+    /// Last heartbeat or phase change.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a finished reply in the outbox is still missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxKind {
+    /// It was sent but is not in the chat history.
+    Record,
+    /// It never reached the user.
+    Deliver,
+    /// It is held until the quiet hours it was written in end.
+    Deferred,
+}
+
+impl OutboxKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Record => "record",
+            Self::Deliver => "deliver",
+            Self::Deferred => "deferred",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "record" => Some(Self::Record),
+            "deliver" => Some(Self::Deliver),
+            "deferred" => Some(Self::Deferred),
+            _ => None,
+        }
+    }
+}
+
+/// A reply written after synthesis succeeded that could not be stored or
+/// delivered, kept until a retry gets it through.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxRecord {
+    /// The assistant message id and kind, e.g. `123-assistant:deliver`.
+    pub id: String,
+    pub kind: OutboxKind,
+    /// The reply; for deliveries its content is the text as it is sent.
+    pub message: ChatMessageRecord,
+    /// The user message the reply answers.
+    pub reply_to: String,
+    /// Attempts so far, including the one that failed first; deferred
+    /// replies start at zero.
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user's reaction to one of the companion's replies: `1` for 👍, `-1`
+/// for 👎.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageFeedbackRecord {
+    pub user_id: String,
+    pub message_id: String,
+    pub score: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A run of one user's chat messages, started after an idle gap or with
+/// `/newchat`. The most recently active session is the current one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub user_id: String,
+    /// Short model-written title; `None` until the first turn finishes.
+    pub title: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -121,8 +493,26 @@ pub struct ToolCallRecord {
     pub timestamp: DateTime<Utc>,
 }
 
+/// Reasoning behind one reply, kept for debugging and never shown to the
+/// user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThoughtRecord {
+    pub user_id: String,
+    pub guild_id: String,
+    pub channel_id: String,
+    /// The user message the reply answered.
+    pub message_id: String,
+    /// `unified_planner`, `tool_followup` or `synthesis`.
+    pub source: String,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlannerDecisionRecord {
+    /// Assigned by the store when the decision is recorded.
+    #[serde(default)]
+    pub id: String,
     pub user_id: String,
     pub guild_id: String,
     pub channel_id: String,
@@ -134,3 +524,202 @@ pub struct PlannerDecisionRecord {
     pub error: Option<String>,
     pub timestamp: DateTime<Utc>,
 }
+
+/// A planner-proposed fact waiting for an admin to approve or reject it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingFactRecord {
+    pub id: String,
+    pub user_id: String,
+    pub guild_id: String,
+    pub channel_id: String,
+    pub fact: MemoryFact,
+    pub rationale: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Progress of a tool call that runs in the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundTaskStatus {
+    Running,
+    Done,
+    Failed,
+    /// The result was posted back to the user.
+    Delivered,
+}
+
+impl BackgroundTaskStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Done => "done",
+            Self::Failed => "failed",
+            Self::Delivered => "delivered",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "running" => Some(Self::Running),
+            "done" => Some(Self::Done),
+            "failed" => Some(Self::Failed),
+            "delivered" => Some(Self::Delivered),
+            _ => None,
+        }
+    }
+}
+
+/// Where a background task was started. Only Discord tasks are posted back
+/// to a channel; HTTP callers read the result from the history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskOrigin {
+    Discord,
+    /// `/chat` and the widgets, whose channel ids are chosen by the caller.
+    #[default]
+    Http,
+}
+
+impl TaskOrigin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Discord => "discord",
+            Self::Http => "http",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "discord" => Some(Self::Discord),
+            "http" => Some(Self::Http),
+            _ => None,
+        }
+    }
+}
+
+/// A long-running tool call the turn handed off, persisted so a restart
+/// resumes it instead of losing it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundTaskRecord {
+    pub id: String,
+    pub user_id: String,
+    pub guild_id: String,
+    pub channel_id: String,
+    pub tool_name: String,
+    pub args: serde_json::Value,
+    #[serde(default)]
+    pub origin: TaskOrigin,
+    pub status: BackgroundTaskStatus,
+    /// The tool's output once done, or the error once failed.
+    pub result_text: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A note an operator injects into a user's next turn, e.g. "the weather
+/// tool is down today". It is consumed by that turn or dropped at expiry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemNoticeRecord {
+    pub id: String,
+    pub user_id: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A user's own model provider key, encrypted at rest; see
+/// [`crate::user_keys::UserKeyVault`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserApiKeyRecord {
+    pub user_id: String,
+    pub provider: KeyProvider,
+    /// Nonce and ciphertext, base64-encoded. Never sent to clients.
+    #[serde(skip_serializing, default)]
+    pub encrypted_key: String,
+    /// The key's last characters, to tell keys apart without revealing them.
+    pub key_hint: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One administrative change made through the dashboard/API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogRecord {
+    pub actor: String,
+    /// Dotted action name, e.g. `fact.delete` or `guild_settings.update`.
+    pub action: String,
+    /// What was changed, e.g. `user:123`.
+    pub target: String,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolUsageRecord {
+    pub user_id: String,
+    pub tool_name: String,
+    pub day: NaiveDate,
+    pub count: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(url: &str, filename: &str, content_type: Option<&str>) -> MessageAttachment {
+        MessageAttachment {
+            url: url.to_owned(),
+            filename: filename.to_owned(),
+            content_type: content_type.map(ToOwned::to_owned),
+            size: None,
+        }
+    }
+
+    #[test]
+    fn lists_attached_images_under_the_message() {
+        let attachments = [
+            attachment("https://cdn.discordapp.com/a/1.png", "error.png", None),
+            attachment(
+                "https://cdn.discordapp.com/a/2",
+                "notes.txt",
+                Some("text/plain"),
+            ),
+            attachment(
+                "https://media.discordapp.net/a/3",
+                "photo",
+                Some("image/jpeg"),
+            ),
+        ];
+        assert!(attachments[0].is_image());
+        assert!(!attachments[1].is_image());
+        assert!(attachments[2].is_image());
+
+        let content = content_with_attachments("what does this say?", &attachments);
+        assert_eq!(
+            content,
+            "what does this say?\n[Attached image 1: error.png]\n[Attached image 2: photo]"
+        );
+        assert_eq!(
+            content_without_attachments(&content, &attachments),
+            "what does this say?"
+        );
+        assert_eq!(content_with_attachments("hi", &attachments[1..2]), "hi");
+    }
+
+    #[test]
+    fn only_discord_cdn_links_are_fetchable() {
+        let fetchable = |url| attachment(url, "a.png", None).is_discord_cdn();
+        assert!(fetchable(
+            "https://cdn.discordapp.com/attachments/1/2/a.png"
+        ));
+        assert!(fetchable(
+            "https://media.discordapp.net/attachments/1/2/a.png"
+        ));
+        assert!(!fetchable(
+            "http://cdn.discordapp.com/attachments/1/2/a.png"
+        ));
+        assert!(!fetchable("https://cdn.discordapp.com:8443/a.png"));
+        assert!(!fetchable("https://cdn.discordapp.com.evil.test/a.png"));
+        assert!(!fetchable("https://169.254.169.254/latest/meta-data"));
+    }
+}
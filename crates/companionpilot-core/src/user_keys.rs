@@ -0,0 +1,193 @@
+use std::fmt;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    memory::MemoryStore,
+    model::{ApiKey, KeyProvider},
+    types::UserApiKeyRecord,
+};
+
+/// Characters of a key shown back to its owner and admins.
+const KEY_HINT_CHARS: usize = 4;
+
+/// Encrypts users' own model keys (AES-256-GCM, keyed by a hash of the
+/// server secret) so the database never holds them in the clear, and
+/// resolves them for the user's turns. Changing the secret makes stored keys
+/// unreadable; their owners then fall back to the server key until they set
+/// them again.
+pub struct UserKeyVault {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl fmt::Debug for UserKeyVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserKeyVault").finish_non_exhaustive()
+    }
+}
+
+impl UserKeyVault {
+    pub fn from_secret(secret: &str) -> Self {
+        let digest = Sha256::digest(secret.as_bytes());
+        let key = UnboundKey::new(&AES_256_GCM, &digest).expect("SHA-256 output is a valid key");
+        Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Encrypts and stores the user's key, replacing an earlier one.
+    pub async fn set(
+        &self,
+        memory: &dyn MemoryStore,
+        user_id: &str,
+        provider: KeyProvider,
+        key: &str,
+    ) -> anyhow::Result<UserApiKeyRecord> {
+        let key = key.trim();
+        anyhow::ensure!(
+            key.starts_with("sk-") && !key.contains(char::is_whitespace),
+            "that does not look like an API key; {} keys start with `sk-`",
+            provider.as_str()
+        );
+        let record = UserApiKeyRecord {
+            user_id: user_id.to_owned(),
+            provider,
+            encrypted_key: self.seal(user_id, key)?,
+            key_hint: key_hint(key),
+            updated_at: Utc::now(),
+        };
+        memory.put_user_api_key(record.clone()).await?;
+        Ok(record)
+    }
+
+    /// The user's own key, or `None` when they have not set one.
+    pub async fn resolve(
+        &self,
+        memory: &dyn MemoryStore,
+        user_id: &str,
+    ) -> anyhow::Result<Option<ApiKey>> {
+        let Some(record) = memory.get_user_api_key(user_id).await? else {
+            return Ok(None);
+        };
+        Ok(Some(ApiKey::new(
+            record.provider,
+            self.open(user_id, &record.encrypted_key)?,
+        )))
+    }
+
+    /// Base64 of a random nonce followed by the ciphertext and tag. The user
+    /// id is authenticated with it, so a sealed key copied to another user's
+    /// row does not decrypt.
+    fn seal(&self, user_id: &str, plaintext: &str) -> anyhow::Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("no randomness available for a nonce"))?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(user_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow::anyhow!("failed to encrypt key"))?;
+        let mut out = nonce.to_vec();
+        out.extend(sealed);
+        Ok(STANDARD.encode(out))
+    }
+
+    fn open(&self, user_id: &str, sealed: &str) -> anyhow::Result<String> {
+        let bytes = STANDARD.decode(sealed)?;
+        anyhow::ensure!(bytes.len() > NONCE_LEN, "stored key is truncated");
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow::anyhow!("stored key has a bad nonce"))?;
+        let mut ciphertext = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut ciphertext)
+            .map_err(|_| {
+                anyhow::anyhow!("stored key cannot be decrypted; was USER_KEY_SECRET changed?")
+            })?;
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+}
+
+/// `…` and the key's last characters.
+fn key_hint(key: &str) -> String {
+    let chars = key.chars().collect::<Vec<_>>();
+    let tail = &chars[chars.len().saturating_sub(KEY_HINT_CHARS)..];
+    format!("…{}", tail.iter().collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryMemoryStore;
+
+    #[tokio::test]
+    async fn keys_are_stored_encrypted_and_resolved() {
+        let memory = InMemoryMemoryStore::default();
+        let vault = UserKeyVault::from_secret("correct horse battery staple");
+
+        assert!(
+            vault
+                .set(&memory, "u1", KeyProvider::OpenRouter, "not a key")
+                .await
+                .is_err()
+        );
+        let record = vault
+            .set(
+                &memory,
+                "u1",
+                KeyProvider::OpenRouter,
+                " sk-or-v1-abcdef1234 ",
+            )
+            .await
+            .expect("key stored");
+        assert_eq!(record.key_hint, "…1234");
+        assert!(!record.encrypted_key.contains("abcdef"));
+
+        let key = vault
+            .resolve(&memory, "u1")
+            .await
+            .expect("resolved")
+            .expect("key set");
+        assert_eq!(key.provider, KeyProvider::OpenRouter);
+        assert_eq!(key.secret(), "sk-or-v1-abcdef1234");
+        assert!(!format!("{key:?}").contains("abcdef"));
+        assert!(
+            vault
+                .resolve(&memory, "u2")
+                .await
+                .expect("resolved")
+                .is_none()
+        );
+
+        let other = UserKeyVault::from_secret("another secret");
+        assert!(other.resolve(&memory, "u1").await.is_err());
+
+        // A sealed key moved to another user's row does not decrypt there.
+        let stolen = memory
+            .get_user_api_key("u1")
+            .await
+            .expect("loaded")
+            .expect("key set");
+        memory
+            .put_user_api_key(UserApiKeyRecord {
+                user_id: "u2".into(),
+                ..stolen
+            })
+            .await
+            .expect("stored");
+        assert!(vault.resolve(&memory, "u2").await.is_err());
+    }
+}
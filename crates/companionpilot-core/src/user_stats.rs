@@ -0,0 +1,190 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::types::{ChatMessageRecord, ChatRole};
+
+/// Longest gap between a user's message and the next reply in the channel
+/// that counts as answering it; later replies were likely proactive.
+pub const REPLY_TIME_WINDOW: Duration = Duration::minutes(10);
+/// Tools named in `/stats`.
+const TOP_TOOLS: usize = 3;
+
+/// A user's own numbers, shown to them by `/stats`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UserStats {
+    pub messages_sent: i64,
+    pub replies_received: i64,
+    pub fact_count: i64,
+    /// Tool calls made on the user's behalf, per tool, most used first.
+    pub tool_calls: Vec<(String, i64)>,
+    /// Mean time from a user's message to the reply after it in the same
+    /// channel, within [`REPLY_TIME_WINDOW`].
+    pub avg_reply_ms: Option<i64>,
+    pub first_message_at: Option<DateTime<Utc>>,
+}
+
+impl UserStats {
+    pub fn total_tool_calls(&self) -> i64 {
+        self.tool_calls.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Short summary for the `/stats` command.
+    pub fn describe(&self) -> String {
+        if self.messages_sent == 0 && self.fact_count == 0 {
+            return "We haven't talked yet, so there's nothing to count.".to_owned();
+        }
+        let mut lines = vec![
+            format!(
+                "Messages: {} from you, {} from me",
+                self.messages_sent, self.replies_received
+            ),
+            format!("Facts I remember about you: {}", self.fact_count),
+        ];
+        let total = self.total_tool_calls();
+        if total > 0 {
+            let top = self
+                .tool_calls
+                .iter()
+                .take(TOP_TOOLS)
+                .map(|(tool, count)| format!("{tool} ×{count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!("Tools used for you: {total} ({top})"));
+        } else {
+            lines.push("Tools used for you: none yet".to_owned());
+        }
+        if let Some(avg_reply_ms) = self.avg_reply_ms {
+            lines.push(format!(
+                "Average reply time: {:.1}s",
+                avg_reply_ms as f64 / 1000.0
+            ));
+        }
+        if let Some(first) = self.first_message_at {
+            lines.push(format!("Talking since: {}", first.format("%Y-%m-%d")));
+        }
+        lines
+            .iter()
+            .map(|line| format!("- {line}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Mean reply time over one user's messages, pairing each reply with the
+/// user message right before it in the same channel.
+pub fn average_reply_ms(messages: &[ChatMessageRecord]) -> Option<i64> {
+    let mut ordered = messages.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|message| (message.channel_id.as_str(), message.timestamp));
+    let gaps = ordered
+        .windows(2)
+        .filter(|pair| {
+            pair[0].channel_id == pair[1].channel_id
+                && pair[0].role == ChatRole::User
+                && pair[1].role == ChatRole::Assistant
+        })
+        .map(|pair| pair[1].timestamp - pair[0].timestamp)
+        .filter(|gap| *gap <= REPLY_TIME_WINDOW)
+        .map(|gap| gap.num_milliseconds())
+        .collect::<Vec<_>>();
+    if gaps.is_empty() {
+        return None;
+    }
+    Some(gaps.iter().sum::<i64>() / gaps.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{
+        memory::{InMemoryMemoryStore, MemoryStore},
+        types::{MemoryFact, Modality, ToolCallRecord},
+    };
+
+    #[tokio::test]
+    async fn aggregates_a_users_messages_facts_and_tools() {
+        let store = InMemoryMemoryStore::default();
+        let start = Utc
+            .with_ymd_and_hms(2026, 5, 1, 12, 0, 0)
+            .single()
+            .expect("valid timestamp");
+        let message = |channel_id: &str, role, seconds| ChatMessageRecord {
+            id: String::new(),
+            user_id: "u1".to_owned(),
+            guild_id: "g1".to_owned(),
+            channel_id: channel_id.to_owned(),
+            role,
+            content: "hi".to_owned(),
+            timestamp: start + Duration::seconds(seconds),
+            modality: Modality::Text,
+            session_id: None,
+            pinned: false,
+            source_message_id: None,
+        };
+        for record in [
+            message("c1", ChatRole::User, 0),
+            message("c2", ChatRole::User, 1),
+            message("c1", ChatRole::Assistant, 2),
+            message("c2", ChatRole::Assistant, 5),
+            // A proactive message hours later is not a reply.
+            message("c1", ChatRole::Assistant, 4 * 3600),
+        ] {
+            store.record_chat_message(record).await.expect("recorded");
+        }
+        store
+            .upsert_fact(
+                "u1",
+                MemoryFact {
+                    key: "city".to_owned(),
+                    value: "Prague".to_owned(),
+                    confidence: 0.9,
+                    source: "user_message".to_owned(),
+                    updated_at: start,
+                    last_confirmed_at: None,
+                },
+            )
+            .await
+            .expect("stored");
+        for tool_name in ["web_search", "current_datetime", "web_search"] {
+            store
+                .record_tool_call(ToolCallRecord {
+                    user_id: "u1".to_owned(),
+                    guild_id: "g1".to_owned(),
+                    channel_id: "c1".to_owned(),
+                    tool_name: tool_name.to_owned(),
+                    source: "planner".to_owned(),
+                    args_json: "{}".to_owned(),
+                    result_text: String::new(),
+                    citations: Vec::new(),
+                    success: true,
+                    error: None,
+                    timestamp: start,
+                })
+                .await
+                .expect("recorded");
+        }
+
+        let stats = store.user_stats("u1").await.expect("stats");
+        assert_eq!(stats.messages_sent, 2);
+        assert_eq!(stats.replies_received, 3);
+        assert_eq!(stats.fact_count, 1);
+        assert_eq!(
+            stats.tool_calls,
+            vec![
+                ("web_search".to_owned(), 2),
+                ("current_datetime".to_owned(), 1)
+            ]
+        );
+        assert_eq!(stats.avg_reply_ms, Some(3_000));
+        assert_eq!(stats.first_message_at, Some(start));
+        let text = stats.describe();
+        assert!(text.contains("Tools used for you: 3 (web_search ×2, current_datetime ×1)"));
+        assert!(text.contains("Average reply time: 3.0s"));
+
+        assert_eq!(
+            store.user_stats("nobody").await.expect("stats").describe(),
+            "We haven't talked yet, so there's nothing to count."
+        );
+    }
+}
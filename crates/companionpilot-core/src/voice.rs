@@ -1,5 +1,6 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -18,11 +19,18 @@ use songbird::{
     Config as SongbirdConfig, Songbird,
     driver::DecodeMode,
     events::{CoreEvent, Event, EventContext, EventHandler as VoiceEventHandler},
+    tracks::TrackHandle,
 };
 use tokio::sync::{Mutex, Notify, RwLock};
 use tracing::{info, warn};
 
-use crate::types::MessageCtx;
+use crate::{
+    orchestrator::ChatOrchestrator,
+    reply_filters::ReplyFilterContext,
+    tools::{ToolArgError, ToolSpec},
+    tts_cache::{MAX_CACHED_TTS_CHARS, TtsCache},
+    types::MessageCtx,
+};
 
 const DEFAULT_LISTEN_WINDOW_MS: u64 = 12_000;
 const DEFAULT_CHUNK_GAP_MS: u64 = 700;
@@ -31,6 +39,10 @@ const MAX_LISTEN_WINDOW_MS: u64 = 60_000;
 const MIN_CHUNK_GAP_MS: u64 = 100;
 const MAX_CHUNK_GAP_MS: u64 = 3_000;
 const MAX_TTS_INPUT_CHARS: usize = 4_000;
+/// Speaker segments shorter than ~0.25s of 48kHz stereo audio are dropped
+/// before per-speaker transcription.
+const MIN_SEGMENT_SAMPLES: usize = 24_000;
+const MAX_TRANSCRIBED_SEGMENTS: usize = 12;
 
 #[derive(Debug, Clone)]
 pub struct VoiceRuntimeConfig {
@@ -43,9 +55,24 @@ pub struct VoiceRuntimeConfig {
     pub default_chunk_gap: Duration,
     pub default_listen_window: Duration,
     pub default_max_turn: Duration,
+    /// Directory for cached TTS audio; `None` disables the cache.
+    pub tts_cache_dir: Option<PathBuf>,
+    pub tts_cache_max_bytes: u64,
+    /// Stop TTS playback as soon as someone in the channel starts speaking.
+    pub barge_in: bool,
+    /// Users whose arrival in an allowlisted channel makes the bot join it;
+    /// the bot then leaves once the channel is empty.
+    pub auto_join_user_ids: HashSet<u64>,
 }
 
 impl VoiceRuntimeConfig {
+    /// Parses comma-separated Discord user ids; invalid entries are ignored.
+    pub fn parse_user_ids(raw: &str) -> HashSet<u64> {
+        raw.split(',')
+            .filter_map(|entry| entry.trim().parse::<u64>().ok())
+            .collect()
+    }
+
     pub fn parse_allowlist(raw: &str) -> HashSet<(u64, u64)> {
         let mut entries = HashSet::new();
         for pair in raw.split(',') {
@@ -80,14 +107,59 @@ impl VoiceRuntimeConfig {
 
 #[derive(Debug, Clone)]
 struct AudioChunk {
-    speaker_label: String,
+    ssrc: u32,
+    pcm_samples: Vec<i16>,
+    received_at: Instant,
+}
+
+/// Continuous speech of one speaker within a captured turn.
+#[derive(Debug, Clone, PartialEq)]
+struct SpeakerSegment {
+    ssrc: u32,
     pcm_samples: Vec<i16>,
 }
 
 #[derive(Debug)]
 struct CapturedTurn {
-    speakers: Vec<String>,
-    pcm_samples: Vec<i16>,
+    /// In order of when each segment started.
+    segments: Vec<SpeakerSegment>,
+}
+
+impl CapturedTurn {
+    fn ssrcs(&self) -> Vec<u32> {
+        let mut ssrcs = self
+            .segments
+            .iter()
+            .map(|segment| segment.ssrc)
+            .collect::<Vec<_>>();
+        ssrcs.sort_unstable();
+        ssrcs.dedup();
+        ssrcs
+    }
+}
+
+/// Splits interleaved per-speaker chunks into segments. A speaker's segment
+/// continues until they are silent for longer than `gap`, so overlapping
+/// speech keeps one segment per speaker.
+fn group_into_segments(chunks: Vec<AudioChunk>, gap: Duration) -> Vec<SpeakerSegment> {
+    let mut segments: Vec<SpeakerSegment> = Vec::new();
+    let mut open: HashMap<u32, (usize, Instant)> = HashMap::new();
+    for chunk in chunks {
+        match open.get_mut(&chunk.ssrc) {
+            Some((index, last_seen)) if chunk.received_at.duration_since(*last_seen) <= gap => {
+                segments[*index].pcm_samples.extend(chunk.pcm_samples);
+                *last_seen = chunk.received_at;
+            }
+            _ => {
+                open.insert(chunk.ssrc, (segments.len(), chunk.received_at));
+                segments.push(SpeakerSegment {
+                    ssrc: chunk.ssrc,
+                    pcm_samples: chunk.pcm_samples,
+                });
+            }
+        }
+    }
+    segments
 }
 
 #[derive(Debug)]
@@ -97,6 +169,10 @@ struct VoiceSession {
     queue_notify: Notify,
     listen_lock: Mutex<()>,
     last_activity: Mutex<Instant>,
+    /// TTS reply currently being played, if any.
+    playing_track: Mutex<Option<TrackHandle>>,
+    /// Discord user behind each RTP source, from speaking-state updates.
+    ssrc_users: Mutex<HashMap<u32, u64>>,
 }
 
 impl VoiceSession {
@@ -107,9 +183,35 @@ impl VoiceSession {
             queue_notify: Notify::new(),
             listen_lock: Mutex::new(()),
             last_activity: Mutex::new(Instant::now()),
+            playing_track: Mutex::new(None),
+            ssrc_users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn map_ssrc(&self, ssrc: u32, user_id: u64) {
+        self.ssrc_users.lock().await.insert(ssrc, user_id);
+    }
+
+    async fn user_for_ssrc(&self, ssrc: u32) -> Option<u64> {
+        self.ssrc_users.lock().await.get(&ssrc).copied()
+    }
+
+    async fn set_playing_track(&self, track: TrackHandle) {
+        if let Some(previous) = self.playing_track.lock().await.replace(track) {
+            let _ = previous.stop();
         }
     }
 
+    /// Stops the TTS reply if it is still playing. Returns whether anything
+    /// was interrupted. A finished track has dropped its command channel, so
+    /// `stop` fails without waiting on the mixer.
+    async fn interrupt_playback(&self) -> bool {
+        let Some(track) = self.playing_track.lock().await.take() else {
+            return false;
+        };
+        track.stop().is_ok()
+    }
+
     async fn touch(&self) {
         *self.last_activity.lock().await = Instant::now();
     }
@@ -137,6 +239,34 @@ impl VoiceSession {
         }
     }
 
+    /// Collects audio for exactly `duration` and keeps only `user_id`'s
+    /// speech.
+    async fn record_user(&self, user_id: u64, duration: Duration) -> CapturedTurn {
+        let deadline = Instant::now() + duration;
+        let mut chunks = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match tokio::time::timeout(remaining, self.next_chunk()).await {
+                Ok(chunk) => chunks.push(chunk),
+                Err(_) => break,
+            }
+        }
+
+        let mut own_chunks = Vec::new();
+        for chunk in chunks {
+            if self.user_for_ssrc(chunk.ssrc).await == Some(user_id) {
+                own_chunks.push(chunk);
+            }
+        }
+        self.touch().await;
+        CapturedTurn {
+            segments: group_into_segments(own_chunks, duration),
+        }
+    }
+
     async fn capture_turn(
         &self,
         listen_window: Duration,
@@ -148,11 +278,7 @@ impl VoiceSession {
             .context("timed out waiting for next speaking event")?;
 
         let turn_started_at = Instant::now();
-        let mut speakers = HashSet::new();
-        let mut pcm_samples = Vec::new();
-
-        speakers.insert(first_chunk.speaker_label);
-        pcm_samples.extend(first_chunk.pcm_samples);
+        let mut chunks = vec![first_chunk];
 
         loop {
             let elapsed = turn_started_at.elapsed();
@@ -166,19 +292,15 @@ impl VoiceSession {
                 break;
             };
 
-            speakers.insert(next_chunk.speaker_label);
-            pcm_samples.extend(next_chunk.pcm_samples);
+            chunks.push(next_chunk);
         }
 
-        if pcm_samples.is_empty() {
+        if chunks.iter().all(|chunk| chunk.pcm_samples.is_empty()) {
             anyhow::bail!("captured speaking turn had no PCM audio");
         }
 
-        let mut speaker_labels = speakers.into_iter().collect::<Vec<_>>();
-        speaker_labels.sort();
         Ok(CapturedTurn {
-            speakers: speaker_labels,
-            pcm_samples,
+            segments: group_into_segments(chunks, chunk_gap),
         })
     }
 }
@@ -186,11 +308,17 @@ impl VoiceSession {
 #[derive(Clone)]
 struct VoiceReceiveHandler {
     session: Arc<VoiceSession>,
+    barge_in: bool,
 }
 
 #[async_trait]
 impl VoiceEventHandler for VoiceReceiveHandler {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::SpeakingStateUpdate(speaking) = ctx
+            && let Some(user_id) = speaking.user_id
+        {
+            self.session.map_ssrc(speaking.ssrc, user_id.0).await;
+        }
         if let EventContext::VoiceTick(tick) = ctx {
             for (ssrc, voice_data) in &tick.speaking {
                 let Some(decoded) = &voice_data.decoded_voice else {
@@ -199,11 +327,18 @@ impl VoiceEventHandler for VoiceReceiveHandler {
                 if decoded.is_empty() {
                     continue;
                 }
+                if self.barge_in && self.session.interrupt_playback().await {
+                    info!(
+                        channel_id = self.session.channel_id,
+                        ssrc, "barge-in: stopped TTS playback"
+                    );
+                }
 
                 self.session
                     .push_chunk(AudioChunk {
-                        speaker_label: format!("ssrc:{ssrc}"),
+                        ssrc: *ssrc,
                         pcm_samples: decoded.clone(),
+                        received_at: Instant::now(),
                     })
                     .await;
             }
@@ -213,18 +348,34 @@ impl VoiceEventHandler for VoiceReceiveHandler {
     }
 }
 
-#[async_trait]
-pub trait VoiceReplyOrchestrator: Send + Sync {
-    async fn handle_voice_transcript(&self, message: MessageCtx) -> anyhow::Result<String>;
+/// A member's voice state as reported by the gateway; `channel_id` is `None`
+/// once they disconnect.
+#[derive(Debug, Clone)]
+pub struct VoiceStateChange {
+    pub guild_id: u64,
+    pub user_id: u64,
+    pub channel_id: Option<u64>,
+    pub display_name: Option<String>,
+    pub is_bot: bool,
+}
+
+/// What was asked in a `/voice ask` turn and what the companion said back.
+#[derive(Debug, Clone)]
+pub struct VoiceAnswer {
+    pub transcript: String,
+    pub reply: String,
 }
 
 pub struct VoiceManager {
     config: VoiceRuntimeConfig,
     sessions: RwLock<HashMap<u64, Arc<VoiceSession>>>,
     user_voice_channels: RwLock<HashMap<(u64, u64), u64>>,
+    /// Guild display names keyed by (guild id, user id), from voice states.
+    display_names: RwLock<HashMap<(u64, u64), String>>,
     songbird: RwLock<Option<Arc<Songbird>>>,
-    orchestrator: RwLock<Option<Arc<dyn VoiceReplyOrchestrator>>>,
+    orchestrator: RwLock<Option<Arc<dyn ChatOrchestrator>>>,
     openai: OpenAiAudioClient,
+    tts_cache: Option<TtsCache>,
 }
 
 impl std::fmt::Debug for VoiceManager {
@@ -238,6 +389,13 @@ impl std::fmt::Debug for VoiceManager {
 
 impl VoiceManager {
     pub fn new(config: VoiceRuntimeConfig) -> Arc<Self> {
+        let tts_cache = config.tts_cache_dir.as_ref().and_then(|dir| {
+            TtsCache::open(dir, config.tts_cache_max_bytes)
+                .inspect_err(|error| {
+                    warn!(?error, dir = %dir.display(), "TTS cache disabled: directory is unusable");
+                })
+                .ok()
+        });
         Arc::new(Self {
             openai: OpenAiAudioClient::new(
                 config.openai_api_key.clone(),
@@ -248,11 +406,88 @@ impl VoiceManager {
             config,
             sessions: RwLock::new(HashMap::new()),
             user_voice_channels: RwLock::new(HashMap::new()),
+            display_names: RwLock::new(HashMap::new()),
             songbird: RwLock::new(None),
             orchestrator: RwLock::new(None),
+            tts_cache,
         })
     }
 
+    pub fn tool_specs() -> Vec<ToolSpec> {
+        vec![
+            ToolSpec {
+                tool_name: "discord_voice_join",
+                args_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "channel_id": {
+                            "type": "string",
+                            "pattern": "^[0-9]+$",
+                            "description": "Discord channel id; defaults to the requester's current voice channel."
+                        }
+                    }
+                }),
+                when_to_use: "User explicitly asks the assistant to join voice.",
+                when_not_to_use: "User did not request voice channel participation.",
+            },
+            ToolSpec {
+                tool_name: "discord_voice_listen_turn",
+                args_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "listen_window_ms": {
+                            "type": "integer",
+                            "minimum": MIN_LISTEN_WINDOW_MS,
+                            "maximum": MAX_LISTEN_WINDOW_MS,
+                            "default": DEFAULT_LISTEN_WINDOW_MS
+                        },
+                        "chunk_gap_ms": {
+                            "type": "integer",
+                            "minimum": MIN_CHUNK_GAP_MS,
+                            "maximum": MAX_CHUNK_GAP_MS,
+                            "default": DEFAULT_CHUNK_GAP_MS
+                        },
+                        "max_turn_ms": {
+                            "type": "integer",
+                            "minimum": MIN_CHUNK_GAP_MS,
+                            "default": DEFAULT_LISTEN_WINDOW_MS,
+                            "description": "Must be >= chunk_gap_ms."
+                        }
+                    }
+                }),
+                when_to_use: "Bot is already in voice and user requests a listen/respond voice turn.",
+                when_not_to_use: "Bot is not in voice or user requested text-only response.",
+            },
+            ToolSpec {
+                tool_name: "discord_voice_leave",
+                args_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {}
+                }),
+                when_to_use: "User explicitly asks assistant to leave voice or stop voice interaction.",
+                when_not_to_use: "Bot is not connected to voice.",
+            },
+        ]
+    }
+
+    /// Checks the listen-turn arguments the schema cannot relate:
+    /// `max_turn_ms` must not be shorter than `chunk_gap_ms`.
+    pub fn check_listen_args(args: &Value) -> Vec<ToolArgError> {
+        let chunk_gap_ms = args.get("chunk_gap_ms").and_then(Value::as_u64);
+        let max_turn_ms = args.get("max_turn_ms").and_then(Value::as_u64);
+        match (chunk_gap_ms, max_turn_ms) {
+            (Some(chunk_gap_ms), Some(max_turn_ms)) if max_turn_ms < chunk_gap_ms => {
+                vec![ToolArgError {
+                    path: "/max_turn_ms".to_owned(),
+                    message: format!(
+                        "max_turn_ms ({max_turn_ms}) must be at least chunk_gap_ms ({chunk_gap_ms})"
+                    ),
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
     pub fn songbird_config() -> SongbirdConfig {
         SongbirdConfig::default().decode_mode(DecodeMode::Decode)
     }
@@ -261,7 +496,7 @@ impl VoiceManager {
         *self.songbird.write().await = Some(manager);
     }
 
-    pub async fn set_orchestrator(&self, orchestrator: Arc<dyn VoiceReplyOrchestrator>) {
+    pub async fn set_orchestrator(&self, orchestrator: Arc<dyn ChatOrchestrator>) {
         *self.orchestrator.write().await = Some(orchestrator);
     }
 
@@ -277,20 +512,40 @@ impl VoiceManager {
         });
     }
 
-    pub async fn update_user_voice_state(
-        &self,
-        guild_id: u64,
-        user_id: u64,
-        channel_id: Option<u64>,
-    ) {
-        let mut states = self.user_voice_channels.write().await;
-        match channel_id {
-            Some(channel_id) => {
-                states.insert((guild_id, user_id), channel_id);
-            }
-            None => {
-                states.remove(&(guild_id, user_id));
+    pub async fn update_user_voice_state(&self, change: VoiceStateChange) {
+        let VoiceStateChange {
+            guild_id,
+            user_id,
+            channel_id,
+            display_name,
+            is_bot,
+        } = change;
+        if is_bot {
+            return;
+        }
+        if let Some(display_name) = display_name {
+            self.display_names
+                .write()
+                .await
+                .insert((guild_id, user_id), display_name);
+        }
+        let previous_channel_id = {
+            let mut states = self.user_voice_channels.write().await;
+            match channel_id {
+                Some(channel_id) => states.insert((guild_id, user_id), channel_id),
+                None => states.remove(&(guild_id, user_id)),
             }
+        };
+
+        if previous_channel_id == channel_id {
+            return;
+        }
+        if let Some(channel_id) = channel_id {
+            self.auto_join(guild_id, user_id, channel_id).await;
+        }
+        if let Some(previous_channel_id) = previous_channel_id {
+            self.auto_leave_if_empty(guild_id, previous_channel_id)
+                .await;
         }
     }
 
@@ -316,7 +571,11 @@ impl VoiceManager {
             };
 
         self.ensure_allowlisted(guild_id, channel_id)?;
+        self.join_channel(guild_id, channel_id).await?;
+        Ok(format!("Joined voice channel {channel_id}"))
+    }
 
+    async fn join_channel(&self, guild_id: u64, channel_id: u64) -> anyhow::Result<()> {
         let songbird = self.songbird().await?;
         let guild_id_key = GuildId::new(guild_id);
         let channel_id_key = ChannelId::new(channel_id);
@@ -332,19 +591,19 @@ impl VoiceManager {
         {
             let mut call = call_lock.lock().await;
             call.remove_all_global_events();
-            call.add_global_event(
-                Event::Core(CoreEvent::VoiceTick),
-                VoiceReceiveHandler {
-                    session: Arc::clone(&session),
-                },
-            );
+            let handler = VoiceReceiveHandler {
+                session: Arc::clone(&session),
+                barge_in: self.config.barge_in,
+            };
+            call.add_global_event(Event::Core(CoreEvent::VoiceTick), handler.clone());
+            call.add_global_event(Event::Core(CoreEvent::SpeakingStateUpdate), handler);
         }
 
         session.touch().await;
         self.sessions.write().await.insert(guild_id, session);
 
         info!(guild_id, channel_id, "voice join succeeded");
-        Ok(format!("Joined voice channel {channel_id}"))
+        Ok(())
     }
 
     pub async fn leave_for_requester(
@@ -364,7 +623,11 @@ impl VoiceManager {
             .context("no active voice session for this guild")?;
         self.ensure_requester_in_channel(guild_id, requester_user_id, session.channel_id)
             .await?;
+        self.leave_channel(guild_id).await?;
+        Ok("Left the voice channel.".to_owned())
+    }
 
+    async fn leave_channel(&self, guild_id: u64) -> anyhow::Result<()> {
         let songbird = self.songbird().await?;
         songbird
             .remove(GuildId::new(guild_id))
@@ -373,7 +636,47 @@ impl VoiceManager {
 
         self.sessions.write().await.remove(&guild_id);
         info!(guild_id, "voice session removed");
-        Ok("Left the voice channel.".to_owned())
+        Ok(())
+    }
+
+    /// Joins when an auto-join user enters an allowlisted channel while the
+    /// bot is not in voice in that guild.
+    async fn auto_join(&self, guild_id: u64, user_id: u64, channel_id: u64) {
+        if !self.config.auto_join_user_ids.contains(&user_id)
+            || !self.config.allowlist.contains(&(guild_id, channel_id))
+            || self.sessions.read().await.contains_key(&guild_id)
+        {
+            return;
+        }
+        match self.join_channel(guild_id, channel_id).await {
+            Ok(()) => info!(guild_id, channel_id, user_id, "voice auto-join"),
+            Err(error) => warn!(?error, guild_id, channel_id, "voice auto-join failed"),
+        }
+    }
+
+    /// Leaves an auto-join session once nobody is left in its channel.
+    async fn auto_leave_if_empty(&self, guild_id: u64, channel_id: u64) {
+        if self.config.auto_join_user_ids.is_empty() {
+            return;
+        }
+        let in_session_channel = self
+            .sessions
+            .read()
+            .await
+            .get(&guild_id)
+            .is_some_and(|session| session.channel_id == channel_id);
+        let occupied = self.user_voice_channels.read().await.iter().any(
+            |((state_guild_id, _), state_channel_id)| {
+                *state_guild_id == guild_id && *state_channel_id == channel_id
+            },
+        );
+        if !in_session_channel || occupied {
+            return;
+        }
+        match self.leave_channel(guild_id).await {
+            Ok(()) => info!(guild_id, channel_id, "voice auto-leave: channel is empty"),
+            Err(error) => warn!(?error, guild_id, channel_id, "voice auto-leave failed"),
+        }
     }
 
     pub async fn listen_and_respond_for_requester(
@@ -426,65 +729,239 @@ impl VoiceManager {
         };
         session.touch().await;
 
-        let wav_payload = pcm_i16_to_wav_bytes(&captured_turn.pcm_samples, 2, 48_000);
         let transcript = self
-            .openai
-            .transcribe_wav(&wav_payload)
+            .transcribe_turn(guild_id, &session, captured_turn)
+            .await?;
+
+        let synthetic_user_id = format!("voice:{guild_id}:{}", session.channel_id);
+        self.reply_in_voice(
+            guild_id,
+            &session,
+            synthetic_user_id,
+            transcript.clone(),
+            true,
+        )
+        .await?;
+
+        let truncated_transcript = truncate_for_tool_result(&transcript, 220);
+        Ok(format!(
+            "Processed voice turn and replied in voice. Transcript: {truncated_transcript}"
+        ))
+    }
+
+    /// Push-to-talk turn for `/voice ask`: records only the requester for
+    /// `record_for` (or takes the typed `question`), then answers in voice.
+    pub async fn ask_for_requester(
+        &self,
+        guild_id_raw: &str,
+        requester_user_id_raw: &str,
+        question: Option<&str>,
+        record_for: Duration,
+    ) -> anyhow::Result<VoiceAnswer> {
+        let guild_id = parse_discord_id(guild_id_raw, "guild_id")?;
+        let requester_user_id = parse_discord_id(requester_user_id_raw, "requester_user_id")?;
+        let session = self
+            .sessions
+            .read()
             .await
-            .context("STT transcription failed")?;
-        let transcript = transcript.trim();
-        if transcript.is_empty() {
-            anyhow::bail!("transcription returned empty text");
-        }
+            .get(&guild_id)
+            .cloned()
+            .context("bot is not connected to voice in this guild")?;
+        self.ensure_requester_in_channel(guild_id, requester_user_id, session.channel_id)
+            .await?;
+        self.ensure_allowlisted(guild_id, session.channel_id)?;
 
-        let speaker_prefix = if captured_turn.speakers.is_empty() {
-            String::new()
-        } else {
-            format!("[speakers:{}] ", captured_turn.speakers.join(","))
+        let transcript = match question.map(str::trim).filter(|text| !text.is_empty()) {
+            Some(question) => question.to_owned(),
+            None => {
+                let captured_turn = {
+                    let _listen_guard = session.listen_lock.lock().await;
+                    session.clear_chunks().await;
+                    session.record_user(requester_user_id, record_for).await
+                };
+                if captured_turn.segments.is_empty() {
+                    anyhow::bail!(
+                        "did not hear you speak in the last {}s",
+                        record_for.as_secs()
+                    );
+                }
+                self.transcribe_turn(guild_id, &session, captured_turn)
+                    .await?
+            }
         };
-        let transcript_for_orchestrator = format!("{speaker_prefix}{transcript}");
 
-        let synthetic_user_id = format!("voice:{guild_id}:{}", session.channel_id);
+        let reply = self
+            .reply_in_voice(
+                guild_id,
+                &session,
+                requester_user_id.to_string(),
+                transcript.clone(),
+                false,
+            )
+            .await?;
+        Ok(VoiceAnswer { transcript, reply })
+    }
+
+    /// Runs the transcript through the orchestrator and plays the reply.
+    /// `nested` is set when a voice tool heard it during a running turn.
+    async fn reply_in_voice(
+        &self,
+        guild_id: u64,
+        session: &VoiceSession,
+        user_id: String,
+        transcript: String,
+        nested: bool,
+    ) -> anyhow::Result<String> {
         let orchestrator = self
             .orchestrator
             .read()
             .await
             .clone()
             .context("voice orchestrator is not configured")?;
+        let message = MessageCtx {
+            message_id: format!("voice-turn-{}", Utc::now().timestamp_millis()),
+            user_id,
+            guild_id: guild_id.to_string(),
+            channel_id: session.channel_id.to_string(),
+            content: transcript,
+            timestamp: Utc::now(),
+            attachments: Vec::new(),
+        };
+        let reply_text = if nested {
+            orchestrator.handle_nested_voice_transcript(message).await
+        } else {
+            orchestrator.handle_voice_transcript(message).await
+        }
+        .context("failed to generate assistant reply for voice turn")?;
         let reply_text = orchestrator
-            .handle_voice_transcript(MessageCtx {
-                message_id: format!("voice-turn-{}", Utc::now().timestamp_millis()),
-                user_id: synthetic_user_id,
-                guild_id: guild_id.to_string(),
-                channel_id: session.channel_id.to_string(),
-                content: transcript_for_orchestrator,
-                timestamp: Utc::now(),
-            })
-            .await
-            .context("failed to generate assistant reply for voice turn")?;
+            .reply_pipelines()
+            .voice
+            .apply(&reply_text, &ReplyFilterContext::default());
 
         let reply_for_tts = clamp_tts_input(&reply_text);
         let tts_audio = self
-            .openai
-            .synthesize_wav(&reply_for_tts)
+            .synthesize_speech(&reply_for_tts)
             .await
             .context("TTS synthesis failed")?;
-        self.play_tts_audio(guild_id, tts_audio).await?;
+        self.play_tts_audio(guild_id, session, tts_audio).await?;
         session.touch().await;
+        Ok(reply_text)
+    }
 
-        let truncated_transcript = truncate_for_tool_result(transcript, 220);
-        Ok(format!(
-            "Processed voice turn and replied in voice. Transcript: {truncated_transcript}"
-        ))
+    /// Transcribes a captured turn, labelled with speakers' display names.
+    /// With several speakers each segment is transcribed on its own, giving
+    /// one `Name: text` line per segment.
+    async fn transcribe_turn(
+        &self,
+        guild_id: u64,
+        session: &VoiceSession,
+        captured_turn: CapturedTurn,
+    ) -> anyhow::Result<String> {
+        let mut names = HashMap::new();
+        for ssrc in captured_turn.ssrcs() {
+            names.insert(ssrc, self.speaker_name(guild_id, session, ssrc).await);
+        }
+        let mut speaker_names = names.values().cloned().collect::<Vec<_>>();
+        speaker_names.sort();
+        speaker_names.dedup();
+        let header = format!("[speakers:{}]", speaker_names.join(","));
+
+        if names.len() <= 1 {
+            let pcm_samples = captured_turn
+                .segments
+                .into_iter()
+                .flat_map(|segment| segment.pcm_samples)
+                .collect::<Vec<_>>();
+            let transcript = self
+                .openai
+                .transcribe_wav(&pcm_i16_to_wav_bytes(&pcm_samples, 2, 48_000))
+                .await
+                .context("STT transcription failed")?;
+            let transcript = transcript.trim();
+            if transcript.is_empty() {
+                anyhow::bail!("transcription returned empty text");
+            }
+            return Ok(format!("{header} {transcript}"));
+        }
+
+        let segments = captured_turn
+            .segments
+            .into_iter()
+            .filter(|segment| segment.pcm_samples.len() >= MIN_SEGMENT_SAMPLES)
+            .take(MAX_TRANSCRIBED_SEGMENTS)
+            .collect::<Vec<_>>();
+        let transcriptions = segments
+            .iter()
+            .map(|segment| {
+                let openai = self.openai.clone();
+                let wav_payload = pcm_i16_to_wav_bytes(&segment.pcm_samples, 2, 48_000);
+                tokio::spawn(async move { openai.transcribe_wav(&wav_payload).await })
+            })
+            .collect::<Vec<_>>();
+
+        let mut lines = Vec::new();
+        for (segment, transcription) in segments.iter().zip(transcriptions) {
+            match transcription.await {
+                Ok(Ok(text)) if !text.trim().is_empty() => {
+                    lines.push(format!("{}: {}", names[&segment.ssrc], text.trim()));
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(error)) => warn!(?error, "STT failed for a speaker segment"),
+                Err(error) => warn!(?error, "STT task for a speaker segment failed"),
+            }
+        }
+        if lines.is_empty() {
+            anyhow::bail!("transcription returned empty text");
+        }
+        Ok(format!("{header}\n{}", lines.join("\n")))
+    }
+
+    async fn speaker_name(&self, guild_id: u64, session: &VoiceSession, ssrc: u32) -> String {
+        let Some(user_id) = session.user_for_ssrc(ssrc).await else {
+            return format!("ssrc:{ssrc}");
+        };
+        self.display_names
+            .read()
+            .await
+            .get(&(guild_id, user_id))
+            .cloned()
+            .unwrap_or_else(|| format!("user:{user_id}"))
+    }
+
+    /// Synthesizes `text`, serving short phrases from the TTS cache when
+    /// possible.
+    async fn synthesize_speech(&self, text: &str) -> anyhow::Result<Vec<u8>> {
+        let Some(cache) = self
+            .tts_cache
+            .as_ref()
+            .filter(|_| text.chars().count() <= MAX_CACHED_TTS_CHARS)
+        else {
+            return self.openai.synthesize_wav(text).await;
+        };
+
+        let key = TtsCache::key(&self.config.tts_model, &self.config.tts_voice, text);
+        if let Some(audio) = cache.get(&key).await {
+            return Ok(audio);
+        }
+        let audio = self.openai.synthesize_wav(text).await?;
+        if let Err(error) = cache.put(&key, &audio).await {
+            warn!(?error, "failed to cache TTS audio");
+        }
+        Ok(audio)
     }
 
-    async fn play_tts_audio(&self, guild_id: u64, wav_audio: Vec<u8>) -> anyhow::Result<()> {
+    async fn play_tts_audio(
+        &self,
+        guild_id: u64,
+        session: &VoiceSession,
+        wav_audio: Vec<u8>,
+    ) -> anyhow::Result<()> {
         let songbird = self.songbird().await?;
         let handler_lock = songbird
             .get(GuildId::new(guild_id))
             .context("bot is no longer connected to voice")?;
-        let mut handler = handler_lock.lock().await;
-        let _track = handler.play_input(wav_audio.into());
+        let track = handler_lock.lock().await.play_input(wav_audio.into());
+        session.set_playing_track(track).await;
         Ok(())
     }
 
@@ -694,7 +1171,13 @@ pub fn default_chunk_gap() -> Duration {
 
 #[cfg(test)]
 mod tests {
-    use super::{VoiceRuntimeConfig, pcm_i16_to_wav_bytes};
+    use std::time::{Duration, Instant};
+
+    use songbird::{Config as SongbirdConfig, Driver};
+
+    use super::{
+        AudioChunk, VoiceRuntimeConfig, VoiceSession, group_into_segments, pcm_i16_to_wav_bytes,
+    };
 
     #[test]
     fn allowlist_parser_reads_pairs() {
@@ -704,6 +1187,77 @@ mod tests {
         assert!(parsed.contains(&(3, 4)));
     }
 
+    #[test]
+    fn auto_join_user_parser_skips_invalid_ids() {
+        let parsed = VoiceRuntimeConfig::parse_user_ids(" 11, 22,,abc");
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.contains(&11));
+        assert!(parsed.contains(&22));
+    }
+
+    #[test]
+    fn overlapping_speakers_keep_one_segment_each_until_they_pause() {
+        let start = Instant::now();
+        let chunk = |ssrc, sample, offset_ms| AudioChunk {
+            ssrc,
+            pcm_samples: vec![sample],
+            received_at: start + Duration::from_millis(offset_ms),
+        };
+        let segments = group_into_segments(
+            vec![
+                chunk(1, 1, 0),
+                chunk(2, 2, 20),
+                chunk(1, 1, 40),
+                chunk(2, 2, 60),
+                chunk(1, 3, 2_000),
+            ],
+            Duration::from_millis(700),
+        );
+
+        let summary = segments
+            .iter()
+            .map(|segment| (segment.ssrc, segment.pcm_samples.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(1, vec![1, 1]), (2, vec![2, 2]), (1, vec![3])]
+        );
+    }
+
+    #[tokio::test]
+    async fn barge_in_stops_the_playing_reply_once() {
+        let mut driver = Driver::new(SongbirdConfig::default());
+        let wav = pcm_i16_to_wav_bytes(&vec![0_i16; 48_000 * 2 * 5], 2, 48_000);
+        let track = driver.play_input(wav.into());
+        let session = VoiceSession::new(1);
+        session.set_playing_track(track).await;
+
+        assert!(session.interrupt_playback().await);
+        assert!(!session.interrupt_playback().await);
+    }
+
+    #[tokio::test]
+    async fn push_to_talk_keeps_only_the_requesters_speech() {
+        let session = VoiceSession::new(1);
+        session.map_ssrc(1, 100).await;
+        session.map_ssrc(2, 200).await;
+        let start = Instant::now();
+        for (ssrc, sample) in [(1, 1), (2, 2), (3, 3), (1, 4)] {
+            session
+                .push_chunk(AudioChunk {
+                    ssrc,
+                    pcm_samples: vec![sample],
+                    received_at: start,
+                })
+                .await;
+        }
+
+        let turn = session.record_user(100, Duration::from_millis(50)).await;
+
+        assert_eq!(turn.ssrcs(), vec![1]);
+        assert_eq!(turn.segments[0].pcm_samples, vec![1, 4]);
+    }
+
     #[test]
     fn wav_header_size_matches_payload() {
         let samples = vec![0_i16; 480];
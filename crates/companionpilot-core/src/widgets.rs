@@ -0,0 +1,127 @@
+/// Longest message a widget visitor may send.
+pub const WIDGET_MAX_MESSAGE_CHARS: usize = 2000;
+/// Longest visitor id a widget page may send.
+const MAX_VISITOR_ID_CHARS: usize = 64;
+/// Tools anonymous visitors may use. Anything reading stored conversations,
+/// running long jobs or touching voice stays off.
+pub const WIDGET_TOOLS: &[&str] = &["current_datetime", "web_search"];
+const WIDGET_ID_PREFIX: &str = "widget:";
+
+/// Whether a guild id groups a widget's visitors, who share it without
+/// knowing each other.
+pub fn is_widget_guild(guild_id: &str) -> bool {
+    guild_id.starts_with(WIDGET_ID_PREFIX)
+}
+
+/// Whether a user id belongs to a widget visitor.
+pub fn is_widget_user(user_id: &str) -> bool {
+    user_id.starts_with(WIDGET_ID_PREFIX)
+}
+
+/// A public chat box embedded on a website. Visitors authenticate with the
+/// widget's token only, always talk to its persona, and keep their memory
+/// under the widget's own user and guild ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatWidget {
+    pub name: String,
+    pub token: String,
+    /// Persona bundle every reply is written as; the default voice if unset.
+    pub persona: Option<String>,
+}
+
+impl ChatWidget {
+    /// Guild id grouping the widget's conversations.
+    pub fn guild_id(&self) -> String {
+        format!("{WIDGET_ID_PREFIX}{}", self.name)
+    }
+
+    /// User id for one visitor, or `None` when the visitor id is empty, too
+    /// long or contains anything but ASCII letters, digits, `-` and `_`.
+    pub fn visitor_user_id(&self, visitor_id: &str) -> Option<String> {
+        let valid = !visitor_id.is_empty()
+            && visitor_id.len() <= MAX_VISITOR_ID_CHARS
+            && visitor_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| format!("{WIDGET_ID_PREFIX}{}:{visitor_id}", self.name))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WidgetRegistry {
+    widgets: Vec<ChatWidget>,
+}
+
+impl WidgetRegistry {
+    /// Parses comma-separated `name=token` or `name=token:persona` entries,
+    /// e.g. `site=s3cret:pirate`. Names and tokens must be unique.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut widgets = Vec::<ChatWidget>::new();
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((name, rest)) = entry.split_once('=') else {
+                return Err(format!("widget `{entry}` is not `name=token[:persona]`"));
+            };
+            let (token, persona) = match rest.split_once(':') {
+                Some((token, persona)) => (token.trim(), Some(persona.trim())),
+                None => (rest.trim(), None),
+            };
+            let name = name.trim();
+            if name.is_empty() || token.is_empty() {
+                return Err(format!("widget `{entry}` needs a name and a token"));
+            }
+            if widgets
+                .iter()
+                .any(|widget| widget.name == name || widget.token == token)
+            {
+                return Err(format!("widget `{name}` repeats a name or token"));
+            }
+            widgets.push(ChatWidget {
+                name: name.to_owned(),
+                token: token.to_owned(),
+                persona: persona.filter(|p| !p.is_empty()).map(ToOwned::to_owned),
+            });
+        }
+        Ok(Self { widgets })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.widgets.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ChatWidget> {
+        self.widgets.iter()
+    }
+
+    /// The widget a token belongs to. Tokens are compared in constant time.
+    pub fn by_token(&self, token: &str) -> Option<&ChatWidget> {
+        self.widgets
+            .iter()
+            .find(|widget| constant_time_eq(widget.token.as_bytes(), token.as_bytes()))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widgets_resolve_by_token_into_their_own_namespace() {
+        let widgets = WidgetRegistry::parse("site=abc123:pirate, blog=xyz").expect("valid widgets");
+        let site = widgets.by_token("abc123").expect("site token");
+        assert_eq!(site.persona.as_deref(), Some("pirate"));
+        assert_eq!(site.guild_id(), "widget:site");
+        assert!(is_widget_guild(&site.guild_id()));
+        assert!(!is_widget_guild("123456"));
+        assert_eq!(
+            site.visitor_user_id("v-42").as_deref(),
+            Some("widget:site:v-42")
+        );
+        assert_eq!(site.visitor_user_id("../admin"), None);
+        assert!(widgets.by_token("abc12").is_none());
+        assert!(WidgetRegistry::parse("a=t,b=t").is_err());
+    }
+}
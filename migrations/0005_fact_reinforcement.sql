@@ -0,0 +1,2 @@
+ALTER TABLE memory_facts
+    ADD COLUMN IF NOT EXISTS last_confirmed_at TIMESTAMPTZ;
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS guild_settings (
+    guild_id TEXT PRIMARY KEY,
+    settings_json TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
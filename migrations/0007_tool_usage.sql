@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS tool_usage (
+    user_id TEXT NOT NULL,
+    tool_name TEXT NOT NULL,
+    day DATE NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (user_id, tool_name, day)
+);
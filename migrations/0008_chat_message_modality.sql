@@ -0,0 +1,3 @@
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS modality TEXT NOT NULL DEFAULT 'text'
+    CHECK (modality IN ('text', 'voice'));
@@ -0,0 +1,10 @@
+ALTER TABLE memory_facts ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+ALTER TABLE chat_messages ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_memory_facts_deleted_at
+    ON memory_facts (deleted_at)
+    WHERE deleted_at IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_chat_messages_deleted_at
+    ON chat_messages (deleted_at)
+    WHERE deleted_at IS NOT NULL;
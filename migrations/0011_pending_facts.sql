@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS pending_facts (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    guild_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    confidence REAL NOT NULL,
+    source TEXT NOT NULL,
+    last_confirmed_at TIMESTAMPTZ,
+    rationale TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_facts_user_created
+    ON pending_facts (user_id, created_at);
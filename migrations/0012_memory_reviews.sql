@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS memory_reviews (
+    user_id TEXT PRIMARY KEY,
+    last_sent_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_memory_facts_confirmed
+    ON memory_facts ((COALESCE(last_confirmed_at, updated_at)))
+    WHERE deleted_at IS NULL;
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS chat_sessions (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    title TEXT,
+    started_at TIMESTAMPTZ NOT NULL,
+    last_active_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_chat_sessions_user_active
+    ON chat_sessions (user_id, last_active_at DESC);
+
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS session_id BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_chat_messages_session
+    ON chat_messages (session_id, timestamp);
@@ -0,0 +1,12 @@
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_chat_messages_pinned
+    ON chat_messages (user_id, timestamp)
+    WHERE pinned;
+
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS source_message_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_chat_messages_source
+    ON chat_messages (user_id, source_message_id);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS adapter_heartbeats (
+    adapter TEXT PRIMARY KEY,
+    last_seen_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS system_notices (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_system_notices_user
+    ON system_notices (user_id, created_at);
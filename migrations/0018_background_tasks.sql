@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS background_tasks (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    guild_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    tool_name TEXT NOT NULL,
+    args_json TEXT NOT NULL,
+    origin TEXT NOT NULL DEFAULT 'http',
+    status TEXT NOT NULL,
+    result_text TEXT,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+ALTER TABLE background_tasks ADD COLUMN IF NOT EXISTS origin TEXT NOT NULL DEFAULT 'http';
+
+CREATE INDEX IF NOT EXISTS idx_background_tasks_status
+    ON background_tasks (status, created_at);
+
+CREATE INDEX IF NOT EXISTS idx_background_tasks_user
+    ON background_tasks (user_id, created_at);
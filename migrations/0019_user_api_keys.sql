@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS user_api_keys (
+    user_id TEXT PRIMARY KEY,
+    provider TEXT NOT NULL,
+    encrypted_key TEXT NOT NULL,
+    key_hint TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS message_feedback (
+    user_id TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    score INTEGER NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, message_id)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS in_flight_replies (
+    message_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    guild_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    phase TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS in_flight_replies_updated_at_idx ON in_flight_replies (updated_at);
@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS chat_messages_content_fts_idx
+    ON chat_messages USING GIN (to_tsvector('simple', content));
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS date_greetings (
+    user_id TEXT NOT NULL,
+    fact_key TEXT NOT NULL,
+    greeted_on DATE NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, fact_key, greeted_on)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS custom_commands (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL,
+    template TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS model_handoffs (
+    user_id TEXT NOT NULL,
+    guild_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    model TEXT NOT NULL,
+    summary TEXT,
+    remaining_turns INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, guild_id, channel_id)
+);
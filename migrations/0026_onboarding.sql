@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS onboarding (
+    user_id TEXT PRIMARY KEY,
+    -- Current interview question; NULL once offered or finished.
+    step TEXT,
+    updated_at TIMESTAMPTZ NOT NULL
+);
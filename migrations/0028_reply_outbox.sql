@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS reply_outbox (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    record_json TEXT NOT NULL,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS reply_outbox_due_idx ON reply_outbox (kind, next_attempt_at);
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS prompt_rollouts (
+    persona TEXT PRIMARY KEY,
+    record_json TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS prompt_rollout_turns (
+    id BIGSERIAL PRIMARY KEY,
+    persona TEXT NOT NULL,
+    rollout_started_at TIMESTAMPTZ NOT NULL,
+    variant TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    reply_message_id TEXT NOT NULL,
+    planner_fallback BOOLEAN
+);
+
+CREATE INDEX IF NOT EXISTS prompt_rollout_turns_rollout_idx
+    ON prompt_rollout_turns (persona, rollout_started_at);
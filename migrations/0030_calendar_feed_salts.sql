@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS calendar_feed_salts (
+    user_id TEXT PRIMARY KEY,
+    salt TEXT NOT NULL,
+    rotated_at TIMESTAMPTZ NOT NULL
+);